@@ -1,7 +1,7 @@
 #[macro_use]
 extern crate criterion;
 
-use libcnab::Bundle;
+use libcnab::{Bundle, BundleRef};
 
 use criterion::{black_box, Benchmark, Criterion, Throughput};
 use std::fs::File;
@@ -43,5 +43,22 @@ fn deserialize(c: &mut Criterion) {
     );
 }
 
-criterion_group!(benches, serialize, deserialize);
+fn deserialize_borrowed(c: &mut Criterion) {
+    let mut json = String::new();
+    File::open("./testdata/bundle.json")
+        .unwrap()
+        .read_to_string(&mut json)
+        .unwrap();
+    let size = Throughput::Bytes(json.len() as u32);
+
+    c.bench(
+        "BundleRef",
+        Benchmark::new("deserialize", move |b| {
+            b.iter(|| BundleRef::parse(black_box(&json)).unwrap().name.len())
+        })
+        .throughput(size),
+    );
+}
+
+criterion_group!(benches, serialize, deserialize, deserialize_borrowed);
 criterion_main!(benches);
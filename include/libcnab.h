@@ -0,0 +1,37 @@
+/*
+ * C interface to libcnab, built with the `ffi` feature. See the `ffi` module
+ * documentation for ownership rules: every returned string is freed with
+ * cnab_string_free, and every bundle handle with cnab_bundle_free.
+ */
+#ifndef LIBCNAB_H
+#define LIBCNAB_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A parsed bundle descriptor */
+typedef struct CnabBundle CnabBundle;
+
+/* Parse a bundle descriptor. Returns NULL, and a message in *error if error is
+ * not NULL, when json is not a bundle. */
+CnabBundle *cnab_bundle_parse(const char *json, char **error);
+
+/* The problems with the bundle and its extensions, as a JSON array of strings. */
+char *cnab_bundle_validate(const CnabBundle *bundle);
+
+/* The sha256:<hex> digest of the bundle's canonical JSON. */
+char *cnab_bundle_digest(const CnabBundle *bundle);
+
+/* The bundle as canonical JSON. */
+char *cnab_bundle_to_json(const CnabBundle *bundle);
+
+void cnab_bundle_free(CnabBundle *bundle);
+
+void cnab_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
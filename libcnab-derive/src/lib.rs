@@ -0,0 +1,124 @@
+//! Attribute macros for writing CNAB invocation images with libcnab.
+//!
+//! These are re-exported by `libcnab` when its `derive` feature is enabled:
+//!
+//! ```ignore
+//! use libcnab::runtime::CnabContext;
+//! use libcnab::{cnab_action, cnab_main};
+//!
+//! #[cnab_action("install")]
+//! fn install(ctx: &CnabContext) -> Result<(), std::io::Error> {
+//!     println!("installing {}", ctx.installation_name());
+//!     Ok(())
+//! }
+//!
+//! #[cnab_action]
+//! fn uninstall(_: &CnabContext) -> Result<(), std::io::Error> {
+//!     Ok(())
+//! }
+//!
+//! #[cnab_main(install, uninstall)]
+//! fn main() {}
+//! ```
+#![warn(rust_2018_idioms)]
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, Error, Ident, ItemFn, LitStr, Path, ReturnType, Token};
+
+/// Mark a function as the handler for a bundle action.
+///
+/// The action name defaults to the function name; pass a string to override it, as
+/// in `#[cnab_action("io.cnab.status")]`. The function must take a `&CnabContext`
+/// and return a `Result<(), E>` whose error converts into `ActionError`. Handlers
+/// are registered with the runtime by listing them in `#[cnab_main]`.
+#[proc_macro_attribute]
+pub fn cnab_action(args: TokenStream, input: TokenStream) -> TokenStream {
+    let function = parse_macro_input!(input as ItemFn);
+    let action = if args.is_empty() {
+        LitStr::new(&function.sig.ident.to_string(), function.sig.ident.span())
+    } else {
+        parse_macro_input!(args as LitStr)
+    };
+    if function.sig.inputs.len() != 1 {
+        return Error::new_spanned(
+            &function.sig,
+            "an action handler takes exactly one argument, the `&CnabContext`",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let name = &function.sig.ident;
+    let vis = &function.vis;
+    let register = register_ident(name);
+    let expanded = quote! {
+        #function
+
+        #[doc(hidden)]
+        #vis fn #register(runtime: ::libcnab::runtime::Runtime) -> ::libcnab::runtime::Runtime {
+            runtime.action(#action, |ctx: &::libcnab::runtime::CnabContext| {
+                #name(ctx).map_err(::std::convert::Into::<::libcnab::runtime::ActionError>::into)
+            })
+        }
+    };
+    expanded.into()
+}
+
+/// Generate the entrypoint of an invocation image.
+///
+/// Takes the paths of the `#[cnab_action]` handlers to register. The body of the
+/// annotated function runs first, for any setup, and the requested action is then
+/// dispatched; the process exits with the runtime's exit code.
+#[proc_macro_attribute]
+pub fn cnab_main(args: TokenStream, input: TokenStream) -> TokenStream {
+    let function = parse_macro_input!(input as ItemFn);
+    let handlers = match Punctuated::<Path, Token![,]>::parse_terminated.parse(args) {
+        Ok(handlers) => handlers,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    if handlers.is_empty() {
+        return Error::new(
+            Span::call_site(),
+            "cnab_main needs at least one action handler",
+        )
+        .to_compile_error()
+        .into();
+    }
+    if let ReturnType::Type(..) = function.sig.output {
+        return Error::new_spanned(
+            &function.sig.output,
+            "cnab_main functions cannot return a value",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let registrations = handlers.iter().map(|handler| {
+        let mut path = handler.clone();
+        let last = path.segments.last_mut().expect("paths have a segment");
+        last.ident = register_ident(&last.ident);
+        quote! { let runtime = #path(runtime); }
+    });
+    let attrs = &function.attrs;
+    let vis = &function.vis;
+    let sig = &function.sig;
+    let body = &function.block;
+    let expanded = quote! {
+        #(#attrs)*
+        #vis #sig {
+            #body
+            let runtime = ::libcnab::runtime::Runtime::new();
+            #(#registrations)*
+            ::std::process::exit(runtime.run());
+        }
+    };
+    expanded.into()
+}
+
+fn register_ident(handler: &Ident) -> Ident {
+    format_ident!("__cnab_register_{}", handler)
+}
@@ -0,0 +1,53 @@
+use libcnab::runtime::{CnabContext, Runtime, RuntimeError};
+use libcnab::{cnab_action, cnab_main};
+use std::path::Path;
+
+#[cnab_action]
+fn install(ctx: &CnabContext) -> Result<(), std::io::Error> {
+    assert_eq!(ctx.action(), "install");
+    Ok(())
+}
+
+#[cnab_action("io.cnab.status")]
+fn status(_: &CnabContext) -> Result<(), String> {
+    Err("not deployed".to_string())
+}
+
+#[allow(dead_code)]
+#[cnab_main(install, status)]
+fn entrypoint() {}
+
+fn context(action: &str) -> CnabContext {
+    let bundle = Path::new(env!("CARGO_MANIFEST_DIR")).join("../testdata/bundle.json");
+    CnabContext::from_lookup(
+        |name| match name {
+            "CNAB_ACTION" => Some(action.to_string()),
+            "CNAB_INSTALLATION_NAME" => Some("my-install".to_string()),
+            "CNAB_BUNDLE_NAME" => Some("helloworld".to_string()),
+            "CNAB_BUNDLE_VERSION" => Some("0.1.2".to_string()),
+            "CNAB_REVISION" => Some("01CP6XM0KVB9V1BQDZ9NK8VP29".to_string()),
+            _ => None,
+        },
+        bundle,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_cnab_action_registration() {
+    let runtime = __cnab_register_status(__cnab_register_install(Runtime::new()));
+    assert_eq!(
+        runtime.actions().collect::<Vec<_>>(),
+        vec!["install", "io.cnab.status"]
+    );
+
+    runtime
+        .dispatch(&context("install"))
+        .expect("install handled");
+    match runtime.dispatch(&context("io.cnab.status")) {
+        Err(RuntimeError::ActionFailed { source, .. }) => {
+            assert_eq!(source.to_string(), "not deployed")
+        }
+        other => panic!("expected failed action, got {:?}", other),
+    }
+}
@@ -0,0 +1,309 @@
+//! Python bindings for libcnab, exposed as the `cnab` module.
+//!
+//! Values with structure, such as `Bundle.describe()` and claim parameters, cross
+//! into Python as dicts and lists, and errors are raised as `cnab.CnabError`.
+use libcnab::claimstore::{ClaimStore as _, FileClaimStore};
+use libcnab::{Claim, ExtensionRegistry, Response, Status};
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+create_exception!(cnab, CnabError, PyException);
+
+fn error<E: std::fmt::Display>(e: E) -> PyErr {
+    CnabError::new_err(e.to_string())
+}
+
+/// Convert a JSON value into the Python value `json.loads` would give.
+fn to_python(py: Python<'_>, value: &serde_json::Value) -> PyResult<PyObject> {
+    let json = serde_json::to_string(value).map_err(error)?;
+    Ok(py.import("json")?.call_method1("loads", (json,))?.unbind())
+}
+
+/// A CNAB bundle descriptor.
+#[pyclass(name = "Bundle", module = "cnab")]
+#[derive(Clone)]
+struct PyBundle {
+    bundle: libcnab::Bundle,
+}
+
+#[pymethods]
+impl PyBundle {
+    /// Parse a bundle descriptor from a string of JSON.
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        let bundle = json.parse().map_err(error)?;
+        Ok(PyBundle { bundle })
+    }
+
+    /// Read a bundle descriptor from a `bundle.json` file.
+    #[staticmethod]
+    fn from_file(path: PathBuf) -> PyResult<Self> {
+        let bundle = libcnab::Bundle::from_file(path).map_err(error)?;
+        Ok(PyBundle { bundle })
+    }
+
+    #[getter]
+    fn name(&self) -> &str {
+        &self.bundle.name
+    }
+
+    #[getter]
+    fn version(&self) -> String {
+        self.bundle.version.to_string()
+    }
+
+    #[getter]
+    fn schema_version(&self) -> &str {
+        &self.bundle.schema_version
+    }
+
+    /// The problems with the bundle and its extensions, empty when there are none.
+    fn validate(&self) -> Vec<String> {
+        match self.bundle.validate(&ExtensionRegistry::default()) {
+            Ok(()) => Vec::new(),
+            Err(e) => e.problems.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    /// A summary of the bundle: its name, version and description, and the names of
+    /// its images, parameters, credentials, outputs and custom actions.
+    fn describe(&self, py: Python<'_>) -> PyResult<PyObject> {
+        fn names<V>(map: &Option<BTreeMap<String, V>>) -> Vec<&str> {
+            map.iter().flatten().map(|(k, _)| k.as_str()).collect()
+        }
+        let bundle = &self.bundle;
+        let description = serde_json::json!({
+            "name": bundle.name,
+            "version": bundle.version.to_string(),
+            "description": bundle.description,
+            "invocationImages": bundle
+                .invocation_images
+                .iter()
+                .map(|i| i.image.as_str())
+                .collect::<Vec<_>>(),
+            "images": names(&bundle.images),
+            "parameters": names(&bundle.parameters),
+            "credentials": names(&bundle.credentials),
+            "outputs": names(&bundle.outputs),
+            "actions": names(&bundle.actions),
+            "requiredExtensions": bundle.required_extensions.clone().unwrap_or_default(),
+        });
+        to_python(py, &description)
+    }
+
+    /// The bundle as canonical JSON.
+    fn to_json(&self) -> String {
+        String::from_utf8(self.bundle.to_canonical_json()).expect("JSON is UTF-8")
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Bundle({:?}, {:?})", self.bundle.name, self.version())
+    }
+}
+
+/// The record of the last action performed on an installation.
+#[pyclass(name = "Claim", module = "cnab")]
+#[derive(Clone)]
+struct PyClaim {
+    claim: Claim,
+}
+
+fn status(status: &str) -> PyResult<Status> {
+    serde_json::from_value(serde_json::Value::String(status.to_string()))
+        .map_err(|_| CnabError::new_err(format!("unknown status {}", status)))
+}
+
+#[pymethods]
+impl PyClaim {
+    /// A new claim for performing `action` on the installation `name` of `bundle`.
+    #[new]
+    #[pyo3(signature = (name, bundle, action, parameters = None))]
+    fn new(
+        name: &str,
+        bundle: &PyBundle,
+        action: &str,
+        parameters: Option<BTreeMap<String, String>>,
+    ) -> Self {
+        let now = chrono::Utc::now();
+        PyClaim {
+            claim: Claim {
+                bundle: bundle.bundle.clone(),
+                created: now,
+                custom: None,
+                modified: now,
+                name: name.to_string(),
+                outputs: None,
+                parameters,
+                result: Response::new(action, Status::Pending, None),
+                revision: ulid::Ulid::new().to_string(),
+                bundle_reference: None,
+            },
+        }
+    }
+
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        let claim = serde_json::from_str(json).map_err(error)?;
+        Ok(PyClaim { claim })
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string(&self.claim).map_err(error)
+    }
+
+    /// Record the result of an action, as a new revision.
+    ///
+    /// `status` is one of `success`, `failure`, `pending` or `canceled`.
+    #[pyo3(signature = (action, status, message = None))]
+    fn update(&mut self, action: &str, status: &str, message: Option<String>) -> PyResult<()> {
+        self.claim.result = Response::new(action, self::status(status)?, message);
+        self.claim.revision = ulid::Ulid::new().to_string();
+        self.claim.modified = chrono::Utc::now();
+        Ok(())
+    }
+
+    #[getter]
+    fn name(&self) -> &str {
+        &self.claim.name
+    }
+
+    #[getter]
+    fn revision(&self) -> &str {
+        &self.claim.revision
+    }
+
+    #[getter]
+    fn bundle(&self) -> PyBundle {
+        PyBundle {
+            bundle: self.claim.bundle.clone(),
+        }
+    }
+
+    #[getter]
+    fn action(&self) -> &str {
+        self.claim.result.action()
+    }
+
+    #[getter]
+    fn status(&self) -> String {
+        serde_json::to_value(self.claim.result.status())
+            .ok()
+            .and_then(|s| s.as_str().map(String::from))
+            .expect("statuses serialize as strings")
+    }
+
+    #[getter]
+    fn message(&self) -> Option<&str> {
+        self.claim.result.message()
+    }
+
+    #[getter]
+    fn created(&self) -> String {
+        self.claim.created.to_rfc3339()
+    }
+
+    #[getter]
+    fn modified(&self) -> String {
+        self.claim.modified.to_rfc3339()
+    }
+
+    #[getter]
+    fn parameters(&self) -> BTreeMap<String, String> {
+        self.claim.parameters.clone().unwrap_or_default()
+    }
+
+    #[setter]
+    fn set_parameters(&mut self, parameters: BTreeMap<String, String>) {
+        self.claim.parameters = Some(parameters);
+    }
+
+    #[getter]
+    fn outputs(&self) -> BTreeMap<String, String> {
+        self.claim.outputs.clone().unwrap_or_default()
+    }
+
+    #[setter]
+    fn set_outputs(&mut self, outputs: BTreeMap<String, String>) {
+        self.claim.outputs = Some(outputs);
+    }
+
+    #[getter]
+    fn bundle_reference(&self) -> Option<&str> {
+        self.claim.bundle_reference.as_deref()
+    }
+
+    #[setter]
+    fn set_bundle_reference(&mut self, reference: Option<String>) {
+        self.claim.bundle_reference = reference;
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Claim({:?}, {:?}, {:?})",
+            self.claim.name,
+            self.action(),
+            self.status()
+        )
+    }
+}
+
+/// Claims kept as JSON files in a directory, one per installation of a namespace.
+#[pyclass(name = "ClaimStore", module = "cnab")]
+struct PyClaimStore {
+    store: FileClaimStore,
+}
+
+#[pymethods]
+impl PyClaimStore {
+    #[new]
+    #[pyo3(signature = (dir, namespace = None))]
+    fn new(dir: PathBuf, namespace: Option<&str>) -> PyResult<Self> {
+        let store = FileClaimStore::new(dir);
+        let store = match namespace {
+            Some(namespace) => store.namespaced(namespace).map_err(error)?,
+            None => store,
+        };
+        Ok(PyClaimStore { store })
+    }
+
+    #[getter]
+    fn namespace(&self) -> &str {
+        self.store.namespace()
+    }
+
+    /// The claim of `installation`, or None if there is none.
+    fn read(&self, installation: &str) -> PyResult<Option<PyClaim>> {
+        let claim = self.store.read(installation).map_err(error)?;
+        Ok(claim.map(|claim| PyClaim { claim }))
+    }
+
+    fn store(&self, claim: &PyClaim) -> PyResult<()> {
+        self.store.store(&claim.claim).map_err(error)
+    }
+
+    fn delete(&self, installation: &str) -> PyResult<()> {
+        self.store.delete(installation).map_err(error)
+    }
+
+    /// The names of the installations with claims.
+    fn list(&self) -> PyResult<Vec<String>> {
+        self.store.list().map_err(error)
+    }
+
+    /// The namespace and name of the installations with claims in any namespace.
+    fn list_all(&self) -> PyResult<Vec<(String, String)>> {
+        self.store.list_all().map_err(error)
+    }
+}
+
+#[pymodule]
+fn cnab(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("CnabError", m.py().get_type::<CnabError>())?;
+    m.add_class::<PyBundle>()?;
+    m.add_class::<PyClaim>()?;
+    m.add_class::<PyClaimStore>()?;
+    Ok(())
+}
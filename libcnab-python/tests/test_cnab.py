@@ -0,0 +1,63 @@
+import os
+
+import pytest
+
+import cnab
+
+TESTDATA = os.path.join(os.path.dirname(__file__), "..", "..", "testdata")
+
+
+def bundle():
+    return cnab.Bundle.from_file(os.path.join(TESTDATA, "bundle.json"))
+
+
+def test_bundle():
+    b = bundle()
+    assert (b.name, b.version, b.schema_version) == ("helloworld", "0.1.2", "v1.0.0")
+    assert b.validate() == []
+    description = b.describe()
+    assert description["parameters"] == ["backend_port"]
+    assert description["invocationImages"] == ["technosophos/helloworld:0.1.0"]
+    assert cnab.Bundle.from_json(b.to_json()).to_json() == b.to_json()
+
+    with pytest.raises(cnab.CnabError):
+        cnab.Bundle.from_json('{"name": "incomplete"}')
+    newer = b.to_json().replace('"v1.0.0"', '"v2.0.0"')
+    with pytest.raises(cnab.CnabError, match="newer than the supported"):
+        cnab.Bundle.from_json(newer)
+
+
+def test_claims(tmp_path):
+    store = cnab.ClaimStore(str(tmp_path))
+    claim = cnab.Claim("blog", bundle(), "install", {"backend_port": "80"})
+    assert claim.status == "pending"
+    revision = claim.revision
+    claim.update("install", "success", "installed")
+    claim.outputs = {"address": "10.0.0.1"}
+    assert claim.revision != revision
+    store.store(claim)
+
+    read = store.read("blog")
+    assert (read.action, read.status, read.message) == ("install", "success", "installed")
+    assert read.parameters == {"backend_port": "80"}
+    assert read.outputs == {"address": "10.0.0.1"}
+    assert read.bundle.name == "helloworld"
+    assert store.list() == ["blog"]
+
+    with pytest.raises(cnab.CnabError):
+        claim.update("upgrade", "done")
+    store.delete("blog")
+    assert store.read("blog") is None
+
+
+def test_claim_namespaces(tmp_path):
+    store = cnab.ClaimStore(str(tmp_path))
+    acme = cnab.ClaimStore(str(tmp_path), namespace="acme")
+    assert (store.namespace, acme.namespace) == ("default", "acme")
+    acme.store(cnab.Claim("blog", bundle(), "install"))
+    assert store.list() == []
+    assert acme.list() == ["blog"]
+    assert store.list_all() == [("acme", "blog")]
+
+    with pytest.raises(cnab.CnabError):
+        cnab.ClaimStore(str(tmp_path), namespace="..")
@@ -0,0 +1,49 @@
+use crate::cnab::{Bundle, BundleParseError};
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+
+/// The future returned by the methods of the async traits, such as `AsyncDriver`
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+impl Bundle {
+    /// Deserialize a `Bundle` from a file, without blocking the runtime.
+    pub async fn from_file_async<P: AsRef<Path>>(path: P) -> Result<Self, BundleParseError> {
+        let json = tokio::fs::read(path).await?;
+        Self::from_json_slice(&json, &crate::ValidationProfile::Strict1_0)
+    }
+}
+
+/// Run blocking work on tokio's blocking threads, passing on its panics.
+#[cfg(any(feature = "claims", feature = "drivers", feature = "registry"))]
+pub(crate) async fn blocking<T, F>(work: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    match tokio::task::spawn_blocking(work).await {
+        Ok(value) => value,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(e) => panic!("blocking work did not finish: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_file_async() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let bundle = runtime
+            .block_on(Bundle::from_file_async("testdata/bundle.json"))
+            .unwrap();
+        assert_eq!(bundle.name, "helloworld");
+        assert!(matches!(
+            runtime.block_on(Bundle::from_file_async("no/such/bundle.json")),
+            Err(BundleParseError::IoError(_))
+        ));
+    }
+}
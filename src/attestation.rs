@@ -0,0 +1,803 @@
+//! in-toto attestations about bundles.
+//!
+//! A `Statement` says something about a bundle, such as how it was built, in a
+//! predicate of a given type. It names the bundle by the digest of its canonical JSON,
+//! leaving out the attestations it carries. Statements travel in signed DSSE
+//! `Envelope`s, either inside the bundle under the `io.cnab.attestations` custom key,
+//! or attached to the bundle in a registry as OCI referrers.
+//!
+//! `Bundle::add_provenance` and `Bundle::provenance` carry how a bundle was built
+//! along with it, and a `ProvenancePolicy` keeps the engine, with the `drivers`
+//! feature, from running bundles that were not built by a trusted builder, from a
+//! trusted repository.
+//!
+//! ```
+//! use libcnab::attestation::{self, Envelope, Provenance, Statement};
+//! use libcnab::signing::SigningKey;
+//! use libcnab::Bundle;
+//!
+//! let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+//! let provenance = Provenance::new("https://ci.example.com/builders/release")
+//!     .source("https://github.com/example/bundles", "0a1b2c3d")
+//!     .images(&bundle);
+//! let key = SigningKey::generate();
+//! let envelope = Envelope::new(&Statement::provenance(&bundle, &provenance)).sign(&key);
+//! bundle.add_attestation(&envelope);
+//!
+//! let trusted = [key.public_key()];
+//! let envelope = &bundle.attestations().unwrap()[0];
+//! let statement = attestation::verify(&bundle, envelope, &trusted).unwrap();
+//! assert_eq!(statement.predicate::<Provenance>().unwrap(), provenance);
+//! ```
+use crate::cnab::Bundle;
+#[cfg(feature = "drivers")]
+use crate::engine::{ExecutionPolicy, PolicyInput};
+use crate::error_code::{Coded, ErrorCode};
+use crate::signing::{PublicKey, Signature, SignatureError, SigningKey};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// The custom key under which a bundle carries its attestations
+pub const ATTESTATIONS_KEY: &str = "io.cnab.attestations";
+/// The `_type` of in-toto v1 statements
+pub const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+/// The `predicateType` of SLSA v1 provenance
+pub const SLSA_PROVENANCE_PREDICATE_TYPE: &str = "https://slsa.dev/provenance/v1";
+/// The `payloadType` of envelopes that hold in-toto statements
+pub const IN_TOTO_PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+/// The media type of DSSE envelopes, as they are stored in registries
+pub const DSSE_ENVELOPE_MEDIA_TYPE: &str = "application/vnd.dsse.envelope.v1+json";
+/// The build type of the provenance `Provenance::new` starts
+pub const CNAB_BUILD_TYPE: &str = "https://cnab.io/attestation/build/v1";
+
+/// Statement is an in-toto v1 statement: a predicate about its subjects.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Statement {
+    #[serde(rename = "_type")]
+    pub statement_type: String,
+    pub subject: Vec<ResourceDescriptor>,
+    pub predicate_type: String,
+    #[serde(default)]
+    pub predicate: serde_json::Value,
+}
+
+/// ResourceDescriptor names an artifact by its digests.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResourceDescriptor {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    /// Digests keyed by algorithm, such as `sha256` or `gitCommit`
+    #[serde(default)]
+    pub digest: BTreeMap<String, String>,
+}
+
+impl ResourceDescriptor {
+    fn digest(algorithm: &str, digest: &str) -> BTreeMap<String, String> {
+        vec![(algorithm.to_string(), digest.to_string())]
+            .into_iter()
+            .collect()
+    }
+}
+
+impl Statement {
+    /// A statement of `predicate` about `bundle`.
+    pub fn about<P: Serialize>(bundle: &Bundle, predicate_type: &str, predicate: &P) -> Self {
+        Statement {
+            statement_type: STATEMENT_TYPE.to_string(),
+            subject: vec![ResourceDescriptor {
+                name: Some(bundle.name.clone()),
+                uri: None,
+                digest: ResourceDescriptor::digest("sha256", &subject_digest(bundle)),
+            }],
+            predicate_type: predicate_type.to_string(),
+            predicate: serde_json::to_value(predicate).expect("predicates serialize"),
+        }
+    }
+
+    /// A statement of how `bundle` was built.
+    pub fn provenance(bundle: &Bundle, provenance: &Provenance) -> Self {
+        Self::about(bundle, SLSA_PROVENANCE_PREDICATE_TYPE, provenance)
+    }
+
+    /// Whether `bundle`, as it is now, is a subject of the statement.
+    pub fn is_about(&self, bundle: &Bundle) -> bool {
+        let digest = subject_digest(bundle);
+        self.subject
+            .iter()
+            .any(|s| s.digest.get("sha256") == Some(&digest))
+    }
+
+    /// The predicate, as a `P`.
+    pub fn predicate<P: DeserializeOwned>(&self) -> Result<P, serde_json::Error> {
+        serde_json::from_value(self.predicate.clone())
+    }
+}
+
+/// The hex sha256 digest statements name a bundle by: that of its canonical JSON,
+/// without the attestations under `ATTESTATIONS_KEY`.
+pub fn subject_digest(bundle: &Bundle) -> String {
+    let mut bundle = bundle.clone();
+    if let Some(custom) = &mut bundle.custom {
+        custom.remove(ATTESTATIONS_KEY);
+        if custom.is_empty() {
+            bundle.custom = None;
+        }
+    }
+    hex::encode(Sha256::digest(bundle.to_canonical_json()))
+}
+
+/// Provenance is a SLSA v1 provenance predicate: who built a bundle, from what.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Provenance {
+    pub build_definition: BuildDefinition,
+    pub run_details: RunDetails,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildDefinition {
+    pub build_type: String,
+    #[serde(default)]
+    pub external_parameters: serde_json::Value,
+    /// The sources and images the build used
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resolved_dependencies: Vec<ResourceDescriptor>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunDetails {
+    pub builder: Builder,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<BuildMetadata>,
+}
+
+/// Builder is what ran the build, named by a URI.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Builder {
+    pub id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildMetadata {
+    /// The ID of the build, such as a CI run's URL
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invocation_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_on: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_on: Option<DateTime<Utc>>,
+}
+
+impl Provenance {
+    /// The provenance of a build by the builder `builder_id`.
+    pub fn new(builder_id: &str) -> Self {
+        Provenance {
+            build_definition: BuildDefinition {
+                build_type: CNAB_BUILD_TYPE.to_string(),
+                external_parameters: serde_json::Value::Object(Default::default()),
+                resolved_dependencies: Vec::new(),
+            },
+            run_details: RunDetails {
+                builder: Builder {
+                    id: builder_id.to_string(),
+                },
+                metadata: None,
+            },
+        }
+    }
+
+    /// Record that the build was of the git repository `uri` at `commit`.
+    pub fn source(mut self, uri: &str, commit: &str) -> Self {
+        self.build_definition
+            .resolved_dependencies
+            .push(ResourceDescriptor {
+                name: None,
+                uri: Some(format!("git+{}", uri.trim_start_matches("git+"))),
+                digest: ResourceDescriptor::digest("gitCommit", commit),
+            });
+        self
+    }
+
+    /// Record the images of `bundle` that are pinned by `contentDigest` as materials
+    /// of the build.
+    pub fn images(mut self, bundle: &Bundle) -> Self {
+        let invocation_images = bundle
+            .invocation_images
+            .iter()
+            .map(|i| (&i.image, &i.content_digest));
+        let images = bundle
+            .images
+            .iter()
+            .flatten()
+            .map(|(_, i)| (&i.image, &i.content_digest));
+        for (image, digest) in invocation_images.chain(images) {
+            let (algorithm, hex) = match digest.as_deref().and_then(|d| d.split_once(':')) {
+                Some(digest) => digest,
+                None => continue,
+            };
+            self.build_definition
+                .resolved_dependencies
+                .push(ResourceDescriptor {
+                    name: None,
+                    uri: Some(format!("oci://{}", image)),
+                    digest: ResourceDescriptor::digest(algorithm, hex),
+                });
+        }
+        self
+    }
+
+    /// Record the ID of the build, such as a CI run's URL.
+    pub fn invocation(mut self, invocation_id: &str) -> Self {
+        self.run_details
+            .metadata
+            .get_or_insert_with(BuildMetadata::default)
+            .invocation_id = Some(invocation_id.to_string());
+        self
+    }
+
+    pub fn builder_id(&self) -> &str {
+        &self.run_details.builder.id
+    }
+
+    pub fn invocation_id(&self) -> Option<&str> {
+        self.run_details.metadata.as_ref()?.invocation_id.as_deref()
+    }
+
+    /// The git repository the bundle was built from, and the commit, as `source`
+    /// recorded them.
+    pub fn repository(&self) -> Option<(&str, &str)> {
+        self.build_definition
+            .resolved_dependencies
+            .iter()
+            .find_map(|d| {
+                let uri = d.uri.as_deref()?.strip_prefix("git+")?;
+                Some((uri, d.digest.get("gitCommit")?.as_str()))
+            })
+    }
+}
+
+/// Envelope is a DSSE envelope: a statement, with the signatures of it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope {
+    pub payload_type: String,
+    /// The statement's JSON, in base64
+    pub payload: String,
+    #[serde(default)]
+    pub signatures: Vec<EnvelopeSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvelopeSignature {
+    /// The ID of the key that made the signature
+    #[serde(default)]
+    pub keyid: String,
+    /// The signature, in base64
+    pub sig: String,
+}
+
+impl Envelope {
+    /// An unsigned envelope of `statement`.
+    pub fn new(statement: &Statement) -> Self {
+        let payload = serde_json::to_vec(statement).expect("statements serialize");
+        Envelope {
+            payload_type: IN_TOTO_PAYLOAD_TYPE.to_string(),
+            payload: STANDARD.encode(payload),
+            signatures: Vec::new(),
+        }
+    }
+
+    /// Add a signature by `key`.
+    pub fn sign(mut self, key: &SigningKey) -> Self {
+        let signature = key.sign(&self.signed_bytes().unwrap_or_default());
+        self.signatures.push(EnvelopeSignature {
+            keyid: signature.key_id(),
+            sig: STANDARD.encode(signature.to_bytes()),
+        });
+        self
+    }
+
+    /// The statement, without checking the signatures.
+    pub fn statement(&self) -> Result<Statement, AttestationError> {
+        if self.payload_type != IN_TOTO_PAYLOAD_TYPE {
+            return Err(AttestationError::PayloadType(self.payload_type.clone()));
+        }
+        let payload = STANDARD
+            .decode(&self.payload)
+            .map_err(|e| AttestationError::InvalidEnvelope(e.to_string()))?;
+        serde_json::from_slice(&payload)
+            .map_err(|e| AttestationError::InvalidEnvelope(e.to_string()))
+    }
+
+    /// The statement, if one of `trusted_keys` signed it, with the key that did.
+    pub fn verify<'a>(
+        &self,
+        trusted_keys: &'a [PublicKey],
+    ) -> Result<(Statement, &'a PublicKey), AttestationError> {
+        let signed = self.signed_bytes()?;
+        let mut error = AttestationError::Unsigned;
+        for signature in &self.signatures {
+            let bytes = STANDARD
+                .decode(&signature.sig)
+                .ok()
+                .and_then(|b| <[u8; 64]>::try_from(b.as_slice()).ok())
+                .ok_or_else(|| {
+                    AttestationError::InvalidEnvelope("invalid signature".to_string())
+                })?;
+            let signature = Signature::from_bytes(&signature.keyid, &bytes)?;
+            let key = match trusted_keys
+                .iter()
+                .find(|k| k.key_id() == signature.key_id())
+            {
+                Some(key) => key,
+                None => {
+                    error = SignatureError::UntrustedKey(signature.key_id()).into();
+                    continue;
+                }
+            };
+            key.verify(&signed, &signature)?;
+            return Ok((self.statement()?, key));
+        }
+        Err(error)
+    }
+
+    /// What the signatures sign: the DSSE pre-authentication encoding of the payload.
+    fn signed_bytes(&self) -> Result<Vec<u8>, AttestationError> {
+        let payload = STANDARD
+            .decode(&self.payload)
+            .map_err(|e| AttestationError::InvalidEnvelope(e.to_string()))?;
+        let mut signed = format!(
+            "DSSEv1 {} {} {} ",
+            self.payload_type.len(),
+            self.payload_type,
+            payload.len()
+        )
+        .into_bytes();
+        signed.extend_from_slice(&payload);
+        Ok(signed)
+    }
+}
+
+/// Check that `envelope` is signed by one of `trusted_keys`, and that its statement
+/// is about `bundle`, returning the statement.
+pub fn verify(
+    bundle: &Bundle,
+    envelope: &Envelope,
+    trusted_keys: &[PublicKey],
+) -> Result<Statement, AttestationError> {
+    let (statement, _) = envelope.verify(trusted_keys)?;
+    if !statement.is_about(bundle) {
+        return Err(AttestationError::WrongSubject(subject_digest(bundle)));
+    }
+    Ok(statement)
+}
+
+impl Bundle {
+    /// The attestations the bundle carries under `ATTESTATIONS_KEY`.
+    pub fn attestations(&self) -> Result<Vec<Envelope>, serde_json::Error> {
+        match self.custom.as_ref().and_then(|c| c.get(ATTESTATIONS_KEY)) {
+            Some(value) => serde_json::from_value(value.clone()),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Carry `envelope` with the bundle. Statements about the bundle stay valid,
+    /// since attestations are left out of the digest they name it by.
+    pub fn add_attestation(&mut self, envelope: &Envelope) {
+        let custom = self.custom.get_or_insert_with(BTreeMap::new);
+        let attestations = custom
+            .entry(ATTESTATIONS_KEY.to_string())
+            .or_insert_with(|| serde_json::Value::Array(Vec::new()));
+        if !attestations.is_array() {
+            *attestations = serde_json::Value::Array(Vec::new());
+        }
+        if let serde_json::Value::Array(attestations) = attestations {
+            attestations.push(serde_json::to_value(envelope).expect("envelopes serialize"));
+        }
+    }
+
+    /// Carry the provenance of the bundle, in a statement signed by `key`.
+    pub fn add_provenance(&mut self, provenance: &Provenance, key: &SigningKey) {
+        let envelope = Envelope::new(&Statement::provenance(self, provenance)).sign(key);
+        self.add_attestation(&envelope);
+    }
+
+    /// The provenance the bundle carries, from the first statement of it about the
+    /// bundle that one of `trusted_keys` signed. Fails with why the last provenance
+    /// could not be verified when none could, and is `None` when there is none.
+    pub fn provenance(
+        &self,
+        trusted_keys: &[PublicKey],
+    ) -> Result<Option<Provenance>, AttestationError> {
+        let attestations = self
+            .attestations()
+            .map_err(|e| AttestationError::InvalidEnvelope(e.to_string()))?;
+        let mut error = None;
+        for envelope in &attestations {
+            match envelope.statement() {
+                Ok(s) if s.predicate_type == SLSA_PROVENANCE_PREDICATE_TYPE => {}
+                _ => continue,
+            }
+            match verify(self, envelope, trusted_keys).and_then(|s| {
+                s.predicate()
+                    .map_err(|e| AttestationError::InvalidEnvelope(e.to_string()))
+            }) {
+                Ok(provenance) => return Ok(Some(provenance)),
+                Err(e) => error = Some(e),
+            }
+        }
+        error.map_or(Ok(None), Err)
+    }
+}
+
+/// ProvenancePolicy only allows bundles carrying provenance signed by a trusted key,
+/// optionally from given builders and source repositories. With the `drivers`
+/// feature it is an `ExecutionPolicy` for the engine.
+///
+/// ```
+/// use libcnab::attestation::ProvenancePolicy;
+/// use libcnab::signing::SigningKey;
+///
+/// let key = SigningKey::generate();
+/// let policy = ProvenancePolicy::new(&[key.public_key()])
+///     .builder("https://ci.example.com/builders/release")
+///     .repository("https://github.com/example/bundles");
+/// # #[cfg(feature = "drivers")]
+/// # let _: &dyn libcnab::engine::ExecutionPolicy = &policy;
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProvenancePolicy {
+    trusted_keys: Vec<PublicKey>,
+    builders: Vec<String>,
+    repositories: Vec<String>,
+}
+
+impl ProvenancePolicy {
+    pub fn new(trusted_keys: &[PublicKey]) -> Self {
+        ProvenancePolicy {
+            trusted_keys: trusted_keys.to_vec(),
+            builders: Vec::new(),
+            repositories: Vec::new(),
+        }
+    }
+
+    /// Allow bundles built by `builder_id`. Any builder is allowed when none is given.
+    pub fn builder(mut self, builder_id: &str) -> Self {
+        self.builders.push(builder_id.to_string());
+        self
+    }
+
+    /// Allow bundles built from the git repository `uri`. Any repository is allowed
+    /// when none is given.
+    pub fn repository(mut self, uri: &str) -> Self {
+        self.repositories
+            .push(uri.trim_start_matches("git+").to_string());
+        self
+    }
+
+    /// Check the provenance `bundle` carries.
+    pub fn check(&self, bundle: &Bundle) -> Result<Provenance, String> {
+        let provenance = match bundle.provenance(&self.trusted_keys) {
+            Ok(Some(provenance)) => provenance,
+            Ok(None) => return Err(format!("bundle {} has no provenance", bundle.name)),
+            Err(e) => {
+                return Err(format!(
+                    "the provenance of bundle {} is invalid: {}",
+                    bundle.name, e
+                ))
+            }
+        };
+        if !self.builders.is_empty() && !self.builders.iter().any(|b| b == provenance.builder_id())
+        {
+            return Err(format!(
+                "bundle {} was built by {}, which is not trusted",
+                bundle.name,
+                provenance.builder_id()
+            ));
+        }
+        if !self.repositories.is_empty() {
+            match provenance.repository() {
+                Some((uri, _)) if self.repositories.iter().any(|r| r == uri) => {}
+                Some((uri, _)) => {
+                    return Err(format!(
+                        "bundle {} was built from {}, which is not trusted",
+                        bundle.name, uri
+                    ))
+                }
+                None => {
+                    return Err(format!(
+                        "the provenance of bundle {} names no source repository",
+                        bundle.name
+                    ))
+                }
+            }
+        }
+        Ok(provenance)
+    }
+}
+
+#[cfg(feature = "drivers")]
+impl ExecutionPolicy for ProvenancePolicy {
+    fn evaluate(&self, input: &PolicyInput<'_>) -> Result<(), String> {
+        self.check(input.bundle).map(|_| ())
+    }
+}
+
+#[cfg(feature = "registry")]
+impl crate::registry::Client {
+    /// Attach `envelope` to the manifest `reference` points at, such as a published
+    /// bundle, as an in-toto referrer.
+    pub fn push_attestation<R: crate::reference::AsReference + ?Sized>(
+        &self,
+        reference: &R,
+        envelope: &Envelope,
+    ) -> Result<crate::registry::Referrer, crate::registry::RegistryError> {
+        let content = serde_json::to_vec(envelope).expect("envelopes serialize");
+        self.attach(
+            reference,
+            crate::registry::IN_TOTO_ARTIFACT_TYPE,
+            DSSE_ENVELOPE_MEDIA_TYPE,
+            &content,
+        )
+    }
+
+    /// The envelopes attached to the manifest `reference` points at. Attestations of
+    /// other formats are passed over.
+    pub fn attestations<R: crate::reference::AsReference + ?Sized>(
+        &self,
+        reference: &R,
+    ) -> Result<Vec<Envelope>, crate::registry::RegistryError> {
+        let mut envelopes = Vec::new();
+        for referrer in self.referrers(reference)? {
+            if referrer.kind != crate::registry::ReferrerKind::Attestation {
+                continue;
+            }
+            for (layer, content) in self.artifact_layers(&referrer)? {
+                if layer.media_type != DSSE_ENVELOPE_MEDIA_TYPE {
+                    continue;
+                }
+                if let Ok(envelope) = serde_json::from_slice(&content) {
+                    envelopes.push(envelope);
+                }
+            }
+        }
+        Ok(envelopes)
+    }
+}
+
+/// AttestationError describes a failure to read or verify an attestation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttestationError {
+    /// The envelope or its statement could not be parsed
+    InvalidEnvelope(String),
+    /// The envelope holds something other than an in-toto statement
+    PayloadType(String),
+    /// The envelope has no signature
+    Unsigned,
+    Signature(SignatureError),
+    /// The statement is not about the bundle, whose subject digest is given
+    WrongSubject(String),
+}
+
+impl fmt::Display for AttestationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttestationError::InvalidEnvelope(e) => write!(f, "invalid envelope: {}", e),
+            AttestationError::PayloadType(t) => write!(f, "unexpected payload type {}", t),
+            AttestationError::Unsigned => write!(f, "the envelope is not signed"),
+            AttestationError::Signature(e) => write!(f, "{}", e),
+            AttestationError::WrongSubject(digest) => {
+                write!(f, "the statement is not about bundle sha256:{}", digest)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AttestationError {}
+
+impl Coded for AttestationError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            AttestationError::InvalidEnvelope(_) => ErrorCode::AttestationInvalidEnvelope,
+            AttestationError::PayloadType(_) => ErrorCode::AttestationPayloadType,
+            AttestationError::Unsigned => ErrorCode::AttestationUnsigned,
+            AttestationError::Signature(e) => e.code(),
+            AttestationError::WrongSubject(_) => ErrorCode::AttestationWrongSubject,
+        }
+    }
+}
+
+impl From<SignatureError> for AttestationError {
+    fn from(error: SignatureError) -> Self {
+        AttestationError::Signature(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bundle() -> Bundle {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.invocation_images[0].content_digest = Some(
+            "sha256:bca460afa270d4c527981ef9ca4989346c56cf9b20217dcea37df1ece8120685".to_string(),
+        );
+        bundle
+    }
+
+    #[test]
+    fn test_provenance() {
+        let mut bundle = bundle();
+        let provenance = Provenance::new("https://ci.example.com/builder")
+            .source("git+https://github.com/example/bundles", "0a1b2c3d")
+            .images(&bundle)
+            .invocation("https://ci.example.com/runs/42");
+        let dependencies = &provenance.build_definition.resolved_dependencies;
+        assert_eq!(dependencies.len(), 2);
+        assert_eq!(
+            dependencies[0].uri.as_deref(),
+            Some("git+https://github.com/example/bundles")
+        );
+        assert_eq!(dependencies[0].digest["gitCommit"], "0a1b2c3d");
+        assert_eq!(
+            dependencies[1].uri,
+            Some(format!("oci://{}", bundle.invocation_images[0].image))
+        );
+        assert_eq!(
+            dependencies[1].digest["sha256"],
+            "bca460afa270d4c527981ef9ca4989346c56cf9b20217dcea37df1ece8120685"
+        );
+
+        let statement = Statement::provenance(&bundle, &provenance);
+        assert_eq!(statement.predicate_type, SLSA_PROVENANCE_PREDICATE_TYPE);
+        let json = serde_json::to_value(&statement).unwrap();
+        assert_eq!(json["_type"], STATEMENT_TYPE);
+        assert_eq!(
+            json["predicate"]["runDetails"]["metadata"]["invocationId"],
+            "https://ci.example.com/runs/42"
+        );
+
+        let key = SigningKey::from_bytes(&[9; 32]);
+        let envelope = Envelope::new(&statement).sign(&key);
+        bundle.add_attestation(&envelope);
+        bundle.add_attestation(&Envelope::new(&statement));
+        assert!(statement.is_about(&bundle));
+        let attestations = bundle.attestations().unwrap();
+        assert_eq!(attestations.len(), 2);
+        assert_eq!(attestations[0], envelope);
+
+        let trusted = [key.public_key()];
+        let verified = verify(&bundle, &envelope, &trusted).unwrap();
+        assert_eq!(verified.predicate::<Provenance>().unwrap(), provenance);
+        assert_eq!(
+            verify(&bundle, &attestations[1], &trusted),
+            Err(AttestationError::Unsigned)
+        );
+        let stranger = [SigningKey::from_bytes(&[1; 32]).public_key()];
+        match verify(&bundle, &envelope, &stranger) {
+            Err(AttestationError::Signature(SignatureError::UntrustedKey(_))) => {}
+            other => panic!("expected an untrusted key, got {:?}", other),
+        }
+
+        // DSSE signs the payload type along with the payload.
+        let mut retyped = envelope.clone();
+        retyped.payload_type = "text/plain".to_string();
+        match retyped.verify(&trusted) {
+            Err(AttestationError::Signature(SignatureError::BadSignature(_))) => {}
+            other => panic!("expected a bad signature, got {:?}", other),
+        }
+
+        bundle.description = Some("changed".to_string());
+        assert_eq!(
+            verify(&bundle, &envelope, &trusted),
+            Err(AttestationError::WrongSubject(subject_digest(&bundle)))
+        );
+    }
+
+    #[cfg(feature = "drivers")]
+    #[test]
+    fn test_provenance_policy() {
+        use crate::claimstore::MemoryClaimStore;
+        use crate::driver::DebugDriver;
+        use crate::engine::{Engine, EngineError};
+        use crate::secrets::SecretResolver;
+
+        let mut bundle = bundle();
+        let key = SigningKey::from_bytes(&[9; 32]);
+        let trusted = [key.public_key()];
+        assert_eq!(bundle.provenance(&trusted), Ok(None));
+        let provenance = Provenance::new("https://ci.example.com/builder")
+            .source("https://github.com/example/bundles", "0a1b2c3d")
+            .invocation("https://ci.example.com/runs/42");
+        bundle.add_provenance(&provenance, &SigningKey::from_bytes(&[1; 32]));
+        match bundle.provenance(&trusted) {
+            Err(AttestationError::Signature(SignatureError::UntrustedKey(_))) => {}
+            other => panic!("expected an untrusted key, got {:?}", other),
+        }
+        bundle.add_provenance(&provenance, &key);
+        let embedded = bundle.provenance(&trusted).unwrap().unwrap();
+        assert_eq!(embedded.builder_id(), "https://ci.example.com/builder");
+        assert_eq!(
+            embedded.invocation_id(),
+            Some("https://ci.example.com/runs/42")
+        );
+        assert_eq!(
+            embedded.repository(),
+            Some(("https://github.com/example/bundles", "0a1b2c3d"))
+        );
+
+        let policy = ProvenancePolicy::new(&trusted)
+            .builder("https://ci.example.com/builder")
+            .repository("git+https://github.com/example/bundles");
+        assert_eq!(policy.check(&bundle), Ok(provenance));
+        assert_eq!(
+            ProvenancePolicy::new(&trusted)
+                .builder("https://ci.example.com/other")
+                .check(&bundle),
+            Err(format!(
+                "bundle {} was built by https://ci.example.com/builder, which is not trusted",
+                bundle.name
+            ))
+        );
+        assert_eq!(
+            ProvenancePolicy::new(&trusted)
+                .repository("https://github.com/example/other")
+                .check(&bundle),
+            Err(format!(
+                "bundle {} was built from https://github.com/example/bundles, which is not trusted",
+                bundle.name
+            ))
+        );
+
+        let credentials: Vec<crate::CredentialSet> = vec![serde_json::from_str(
+            r#"{"name": "dev", "credentials": [{"name": "hostkey", "source": {"value": "key"}}]}"#,
+        )
+        .unwrap()];
+        let (driver, claims) = (DebugDriver::new(), MemoryClaimStore::new());
+        let engine = Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .policy(&policy);
+        match engine.install("unproven", &self::bundle(), &[], &credentials) {
+            Err(EngineError::Denied(reason)) => assert!(reason.contains("has no provenance")),
+            other => panic!("expected a denial, got {:?}", other),
+        }
+        engine
+            .install("proven", &bundle, &[], &credentials)
+            .expect("bundles with trusted provenance run");
+    }
+
+    #[cfg(feature = "registry")]
+    #[test]
+    fn test_push_attestation() {
+        use crate::registry::testing::FakeRegistry;
+        use crate::registry::{Client, OCI_MANIFEST};
+
+        let registry = FakeRegistry::start();
+        registry.put_manifest("bundles/hello", Some("0.1.0"), OCI_MANIFEST, b"{}");
+        let reference = format!("{}/bundles/hello:0.1.0", registry.host);
+        let bundle = bundle();
+        let key = SigningKey::from_bytes(&[9; 32]);
+        let statement = Statement::provenance(&bundle, &Provenance::new("https://ci"));
+        let envelope = Envelope::new(&statement).sign(&key);
+
+        let client = Client::new();
+        let referrer = client.push_attestation(&reference, &envelope).unwrap();
+        assert_eq!(referrer.kind, crate::registry::ReferrerKind::Attestation);
+        let attestations = client.attestations(&reference).unwrap();
+        assert_eq!(attestations, vec![envelope]);
+        verify(&bundle, &attestations[0], &[key.public_key()]).unwrap();
+    }
+}
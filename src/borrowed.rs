@@ -0,0 +1,299 @@
+use crate::cnab::{Action, Bundle, Credential, Output, Parameter};
+use serde::de::{DeserializeOwned, Error as _, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::value::RawValue;
+use std::borrow::{Borrow, Cow};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Deref;
+
+/// StrRef is a string of a `BundleRef`: borrowed from the JSON it was parsed from,
+/// unless the JSON escapes characters in it, which must then be unescaped into a
+/// string of its own.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StrRef<'a>(Cow<'a, str>);
+
+impl<'a> StrRef<'a> {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether the string is borrowed from the JSON, rather than unescaped.
+    pub fn is_borrowed(&self) -> bool {
+        matches!(self.0, Cow::Borrowed(_))
+    }
+}
+
+impl Deref for StrRef<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for StrRef<'_> {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for StrRef<'_> {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for StrRef<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl fmt::Display for StrRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for StrRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for StrRef<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StrRefVisitor;
+
+        impl<'de> Visitor<'de> for StrRefVisitor {
+            type Value = StrRef<'de>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a string")
+            }
+
+            fn visit_borrowed_str<E>(self, value: &'de str) -> Result<Self::Value, E> {
+                Ok(StrRef(Cow::Borrowed(value)))
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(StrRef(Cow::Owned(value.to_string())))
+            }
+
+            fn visit_string<E>(self, value: String) -> Result<Self::Value, E> {
+                Ok(StrRef(Cow::Owned(value)))
+            }
+        }
+
+        deserializer.deserialize_str(StrRefVisitor)
+    }
+}
+
+/// Sections of a `BundleRef` left as the JSON they were parsed from, keyed by name
+pub type RawSections<'a> = BTreeMap<StrRef<'a>, &'a RawValue>;
+
+/// BundleRef is a view of a bundle descriptor that borrows its strings from the JSON
+/// it was parsed from, for services that read many bundles and need only some of
+/// each.
+///
+/// The sections describing how a bundle is run, its actions, credentials,
+/// parameters, outputs, definitions and custom extensions, are not parsed at all:
+/// they are kept as the JSON they were in, to be parsed one at a time, such as with
+/// `BundleRef::parameter`, or all at once with `BundleRef::to_bundle`.
+///
+/// The view checks that the descriptor is JSON of the shape of a bundle, but not
+/// that its version is semantic, see `BundleRef::version`.
+///
+/// ```
+/// use libcnab::BundleRef;
+///
+/// let json = std::fs::read_to_string("testdata/bundle.json").unwrap();
+/// let bundle = BundleRef::parse(&json).unwrap();
+/// assert_eq!(bundle.name, "helloworld");
+/// assert!(bundle.name.is_borrowed());
+/// let hostkey = bundle.credential("hostkey").unwrap().unwrap();
+/// assert_eq!(hostkey.env.as_deref(), Some("HOST_KEY"));
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleRef<'a> {
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub actions: Option<RawSections<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<RawSections<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub custom: Option<RawSections<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub definitions: Option<RawSections<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<StrRef<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<BTreeMap<StrRef<'a>, ImageRef<'a>>>,
+    #[serde(borrow)]
+    pub invocation_images: Vec<ImageRef<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub keywords: Option<Vec<StrRef<'a>>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<StrRef<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub maintainers: Option<Vec<MaintainerRef<'a>>>,
+    #[serde(borrow)]
+    pub name: StrRef<'a>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub outputs: Option<RawSections<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<RawSections<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub required_extensions: Option<Vec<StrRef<'a>>>,
+    #[serde(borrow)]
+    pub schema_version: StrRef<'a>,
+    /// The version of the bundle, as it was written
+    #[serde(borrow)]
+    pub version: StrRef<'a>,
+}
+
+/// MaintainerRef is a `Maintainer` of a `BundleRef`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintainerRef<'a> {
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<StrRef<'a>>,
+    #[serde(borrow)]
+    pub name: StrRef<'a>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<StrRef<'a>>,
+}
+
+/// ImageRef is an `Image` or `InvocationImage` of a `BundleRef`.
+///
+/// Invocation images have neither a description nor a platform.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageRef<'a> {
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<StrRef<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub content_digest: Option<StrRef<'a>>,
+    #[serde(borrow)]
+    pub image: StrRef<'a>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub image_type: Option<StrRef<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<StrRef<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<PlatformRef<'a>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<i64>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<BTreeMap<StrRef<'a>, StrRef<'a>>>,
+}
+
+/// PlatformRef is a `Platform` of an `ImageRef`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlatformRef<'a> {
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<StrRef<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<StrRef<'a>>,
+}
+
+impl<'a> BundleRef<'a> {
+    /// Parse a view of the bundle descriptor `json`.
+    pub fn parse(json: &'a str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Parse a view of the bundle descriptor `json`, which must be UTF-8.
+    pub fn from_slice(json: &'a [u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(json)
+    }
+
+    /// The version of the bundle, which must be a semantic version.
+    pub fn version(&self) -> Result<semver::Version, semver::SemVerError> {
+        semver::Version::parse(&self.version)
+    }
+
+    /// Parse the action `name`, if the bundle declares it.
+    pub fn action(&self, name: &str) -> Option<Result<Action, serde_json::Error>> {
+        section(&self.actions, name)
+    }
+
+    /// Parse the credential `name`, if the bundle declares it.
+    pub fn credential(&self, name: &str) -> Option<Result<Credential, serde_json::Error>> {
+        section(&self.credentials, name)
+    }
+
+    /// Parse the parameter `name`, if the bundle declares it.
+    pub fn parameter(&self, name: &str) -> Option<Result<Parameter, serde_json::Error>> {
+        section(&self.parameters, name)
+    }
+
+    /// Parse the output `name`, if the bundle declares it.
+    pub fn output(&self, name: &str) -> Option<Result<Output, serde_json::Error>> {
+        section(&self.outputs, name)
+    }
+
+    /// Parse the custom extension `key` into `T`, if the bundle has it.
+    pub fn custom_extension<T: DeserializeOwned>(
+        &self,
+        key: &str,
+    ) -> Option<Result<T, serde_json::Error>> {
+        section(&self.custom, key)
+    }
+
+    /// Parse the whole bundle into the owned model, checking every field as it is
+    /// converted.
+    pub fn to_bundle(&self) -> Result<Bundle, serde_json::Error> {
+        let json = serde_json::to_string(self)?;
+        serde_json::from_str(&json)
+    }
+}
+
+fn section<T: DeserializeOwned>(
+    sections: &Option<RawSections<'_>>,
+    name: &str,
+) -> Option<Result<T, serde_json::Error>> {
+    let raw = sections.as_ref()?.get(name)?;
+    Some(serde_json::from_str(raw.get()).map_err(serde_json::Error::custom))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_bundle_ref() {
+        let json = std::fs::read_to_string("testdata/bundle.json").unwrap();
+        let bundle = BundleRef::parse(&json).unwrap();
+        let owned = Bundle::from_str(&json).unwrap();
+        assert_eq!(bundle.name, owned.name.as_str());
+        assert!(bundle.name.is_borrowed());
+        assert_eq!(bundle.version().unwrap(), owned.version);
+        assert_eq!(
+            bundle.invocation_images[0].image,
+            owned.invocation_images[0].image.as_str()
+        );
+        assert_eq!(
+            bundle.credential("hostkey").unwrap().unwrap().env,
+            owned.credentials.as_ref().unwrap()["hostkey"].env
+        );
+        assert!(bundle.parameter("missing").is_none());
+        assert_eq!(
+            bundle.to_bundle().unwrap().to_canonical_json(),
+            owned.to_canonical_json()
+        );
+
+        let escaped = r#"{
+            "schemaVersion": "v1.0.0",
+            "name": "hello\u0020world",
+            "version": "not semver",
+            "invocationImages": [{ "image": "example/hello:1.0" }]
+        }"#;
+        let bundle = BundleRef::from_slice(escaped.as_bytes()).unwrap();
+        assert_eq!(bundle.name, "hello world");
+        assert!(!bundle.name.is_borrowed());
+        assert!(bundle.version().is_err());
+        assert!(bundle.to_bundle().is_err());
+    }
+}
@@ -0,0 +1,280 @@
+//! Index and search a collection of bundle files.
+//!
+//! A `Catalog` reads only the name, version, keywords and description of each
+//! bundle up front, and parses a bundle in full the first time it is asked for.
+//!
+//! ```
+//! use libcnab::catalog::{Catalog, Query};
+//!
+//! let catalog = Catalog::from_paths(vec!["testdata/bundle.json"]);
+//! let query = Query::new()
+//!     .name("hello")
+//!     .version(semver::VersionReq::parse("^0.1").unwrap());
+//! let entry = catalog.search(&query).next().unwrap();
+//! assert_eq!(entry.name(), "helloworld");
+//! assert_eq!(entry.bundle().unwrap().invocation_images.len(), 1);
+//! ```
+use crate::cnab::{Bundle, BundleParseError};
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Catalog is a collection of bundle files, ordered by name and then version.
+#[derive(Debug, Default)]
+pub struct Catalog {
+    entries: Vec<Entry>,
+    invalid: Vec<(PathBuf, BundleParseError)>,
+}
+
+/// Entry is a bundle file of a catalog.
+#[derive(Debug)]
+pub struct Entry {
+    path: PathBuf,
+    summary: Summary,
+    bundle: OnceLock<Bundle>,
+}
+
+/// The fields of a bundle a catalog is indexed by
+#[derive(Debug, Deserialize)]
+struct Summary {
+    name: String,
+    version: Version,
+    description: Option<String>,
+    keywords: Option<Vec<String>>,
+}
+
+/// Query selects the entries of a catalog that match all of its criteria.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    name: Option<String>,
+    keywords: Vec<String>,
+    version: Option<VersionReq>,
+}
+
+impl Catalog {
+    /// Index the `.json` files of `dir`, without descending into subdirectories.
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> std::io::Result<Catalog> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "json") && path.is_file() {
+                paths.push(path);
+            }
+        }
+        Ok(Catalog::from_paths(paths))
+    }
+
+    /// Index the bundle files at `paths`.
+    pub fn from_paths<I, P>(paths: I) -> Catalog
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        let mut catalog = Catalog::default();
+        for path in paths {
+            let path = path.into();
+            match summarize(&path) {
+                Ok(summary) => catalog.entries.push(Entry {
+                    path,
+                    summary,
+                    bundle: OnceLock::new(),
+                }),
+                Err(e) => catalog.invalid.push((path, e)),
+            }
+        }
+        catalog.entries.sort_by(|a, b| {
+            (a.name(), a.version(), &a.path).cmp(&(b.name(), b.version(), &b.path))
+        });
+        catalog
+    }
+
+    /// The entries, by name and then version.
+    pub fn iter(&self) -> std::slice::Iter<'_, Entry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The files that could not be indexed, with why.
+    pub fn invalid(&self) -> &[(PathBuf, BundleParseError)] {
+        &self.invalid
+    }
+
+    /// The entries that match `query`, by name and then version.
+    pub fn search<'c>(&'c self, query: &'c Query) -> impl Iterator<Item = &'c Entry> + 'c {
+        self.entries.iter().filter(move |e| query.matches(e))
+    }
+
+    /// The newest version of the bundle `name`.
+    pub fn latest(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().rev().find(|e| e.name() == name)
+    }
+}
+
+impl<'c> IntoIterator for &'c Catalog {
+    type Item = &'c Entry;
+    type IntoIter = std::slice::Iter<'c, Entry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Entry {
+    /// The file the bundle is read from
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn name(&self) -> &str {
+        &self.summary.name
+    }
+
+    pub fn version(&self) -> &Version {
+        &self.summary.version
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.summary.description.as_deref()
+    }
+
+    pub fn keywords(&self) -> &[String] {
+        self.summary.keywords.as_deref().unwrap_or_default()
+    }
+
+    /// The bundle, parsed the first time it is asked for.
+    pub fn bundle(&self) -> Result<&Bundle, BundleParseError> {
+        if let Some(bundle) = self.bundle.get() {
+            return Ok(bundle);
+        }
+        let bundle = Bundle::from_file(&self.path)?;
+        Ok(self.bundle.get_or_init(|| bundle))
+    }
+}
+
+impl Query {
+    /// A query every entry matches.
+    pub fn new() -> Self {
+        Query::default()
+    }
+
+    /// Match bundles whose name contains `name`, ignoring case.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_lowercase());
+        self
+    }
+
+    /// Match bundles with the keyword `keyword`, ignoring case. Given more than once,
+    /// bundles must have every keyword.
+    pub fn keyword(mut self, keyword: &str) -> Self {
+        self.keywords.push(keyword.to_lowercase());
+        self
+    }
+
+    /// Match bundles whose version is in `range`.
+    pub fn version(mut self, range: VersionReq) -> Self {
+        self.version = Some(range);
+        self
+    }
+
+    /// Whether `entry` matches the query.
+    pub fn matches(&self, entry: &Entry) -> bool {
+        if let Some(name) = &self.name {
+            if !entry.name().to_lowercase().contains(name.as_str()) {
+                return false;
+            }
+        }
+        if let Some(range) = &self.version {
+            if !range.matches(entry.version()) {
+                return false;
+            }
+        }
+        self.keywords
+            .iter()
+            .all(|k| entry.keywords().iter().any(|e| e.to_lowercase() == *k))
+    }
+}
+
+/// Read the fields of the bundle at `path` a catalog is indexed by.
+fn summarize(path: &Path) -> Result<Summary, BundleParseError> {
+    let file = File::open(path)?;
+    #[cfg(feature = "compression")]
+    let file = crate::compression::decompressed(file)?;
+    Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        for (version, keywords) in [("0.1.0", vec!["demo"]), ("0.2.0", vec!["demo", "web"])] {
+            bundle.version = Version::parse(version).unwrap();
+            bundle.keywords = Some(keywords.into_iter().map(String::from).collect());
+            let path = dir.path().join(format!("helloworld-{}.json", version));
+            std::fs::write(path, serde_json::to_vec(&bundle).unwrap()).unwrap();
+        }
+        bundle.name = "mysql".to_string();
+        bundle.keywords = None;
+        std::fs::write(
+            dir.path().join("mysql.json"),
+            serde_json::to_vec(&bundle).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("broken.json"), "{\"name\": \"broken\"}").unwrap();
+        std::fs::write(dir.path().join("README.md"), "not a bundle").unwrap();
+
+        let catalog = Catalog::load_dir(dir.path()).unwrap();
+        let found: Vec<_> = catalog
+            .iter()
+            .map(|e| (e.name(), e.version().to_string()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("helloworld", "0.1.0".to_string()),
+                ("helloworld", "0.2.0".to_string()),
+                ("mysql", "0.2.0".to_string()),
+            ]
+        );
+        assert_eq!(catalog.invalid().len(), 1);
+        assert!(catalog.invalid()[0].0.ends_with("broken.json"));
+
+        let names = |query: Query| -> Vec<String> {
+            catalog
+                .search(&query)
+                .map(|e| format!("{}@{}", e.name(), e.version()))
+                .collect()
+        };
+        assert_eq!(
+            names(Query::new().keyword("DEMO")),
+            vec!["helloworld@0.1.0", "helloworld@0.2.0"]
+        );
+        assert_eq!(
+            names(Query::new().keyword("demo").keyword("web")),
+            vec!["helloworld@0.2.0"]
+        );
+        assert_eq!(
+            names(Query::new().version(VersionReq::parse(">=0.2").unwrap())),
+            vec!["helloworld@0.2.0", "mysql@0.2.0"]
+        );
+        assert_eq!(names(Query::new().name("SQL")), vec!["mysql@0.2.0"]);
+
+        let latest = catalog.latest("helloworld").unwrap();
+        assert_eq!(latest.version(), &Version::parse("0.2.0").unwrap());
+        assert!(latest.bundle.get().is_none());
+        assert_eq!(latest.bundle().unwrap().name, "helloworld");
+        assert!(latest.bundle.get().is_some());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::claim::Claim;
+use crate::cnab::Bundle;
+use crate::error_code::{Coded, ErrorCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+
+/// Bundles and claims can be stored as CBOR (RFC 8949), which is smaller and quicker
+/// to parse than JSON where state is kept on constrained devices. The encoding holds
+/// the same data model as the JSON, so a bundle decoded from CBOR has the same
+/// canonical JSON, and digest, as the one encoded.
+///
+/// ```
+/// use libcnab::Bundle;
+///
+/// let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+/// let cbor = bundle.to_cbor().unwrap();
+/// let decoded = Bundle::from_cbor(&cbor).unwrap();
+/// assert_eq!(decoded.to_canonical_json(), bundle.to_canonical_json());
+/// ```
+impl Bundle {
+    /// Serialize the bundle as CBOR.
+    pub fn to_cbor(&self) -> Result<Vec<u8>, CborError> {
+        encode(self)
+    }
+
+    /// Deserialize a `Bundle` from CBOR.
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, CborError> {
+        decode(bytes)
+    }
+}
+
+impl Claim {
+    /// Serialize the claim as CBOR.
+    pub fn to_cbor(&self) -> Result<Vec<u8>, CborError> {
+        encode(self)
+    }
+
+    /// Deserialize a `Claim` from CBOR.
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, CborError> {
+        decode(bytes)
+    }
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, CborError> {
+    let mut bytes = Vec::new();
+    ciborium::ser::into_writer(value, &mut bytes)?;
+    Ok(bytes)
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CborError> {
+    Ok(ciborium::de::from_reader(bytes)?)
+}
+
+/// Represents an error encoding or decoding CBOR
+#[derive(Debug)]
+pub enum CborError {
+    Encode(ciborium::ser::Error<std::io::Error>),
+    Decode(ciborium::de::Error<std::io::Error>),
+}
+
+impl fmt::Display for CborError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CborError::Encode(e) => write!(f, "encoding CBOR: {}", e),
+            CborError::Decode(e) => write!(f, "invalid CBOR: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CborError {}
+
+impl Coded for CborError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            CborError::Encode(_) => ErrorCode::CborEncode,
+            CborError::Decode(_) => ErrorCode::CborDecode,
+        }
+    }
+}
+
+impl From<ciborium::ser::Error<std::io::Error>> for CborError {
+    fn from(error: ciborium::ser::Error<std::io::Error>) -> Self {
+        CborError::Encode(error)
+    }
+}
+
+impl From<ciborium::de::Error<std::io::Error>> for CborError {
+    fn from(error: ciborium::de::Error<std::io::Error>) -> Self {
+        CborError::Decode(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::claim::{Response, Status};
+
+    #[test]
+    fn test_cbor() {
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let cbor = bundle.to_cbor().unwrap();
+        assert!(cbor.len() < bundle.to_canonical_json().len());
+        let decoded = Bundle::from_cbor(&cbor).unwrap();
+        assert_eq!(decoded.to_canonical_json(), bundle.to_canonical_json());
+
+        let now = chrono::Utc::now();
+        let claim = Claim {
+            bundle,
+            created: now,
+            custom: Some(serde_json::json!({ "com.example.retries": 2, "ratio": 0.5 })),
+            modified: now,
+            name: "edge".to_string(),
+            outputs: None,
+            parameters: Some(
+                vec![("backend_port".to_string(), "80".to_string())]
+                    .into_iter()
+                    .collect(),
+            ),
+            result: Response::new("install", Status::Success, None),
+            revision: crate::Ulid::new().to_string(),
+            bundle_reference: None,
+        };
+        let decoded = Claim::from_cbor(&claim.to_cbor().unwrap()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&decoded).unwrap(),
+            serde_json::to_value(&claim).unwrap()
+        );
+
+        assert!(matches!(
+            Bundle::from_cbor(&cbor[..cbor.len() / 2]),
+            Err(CborError::Decode(_))
+        ));
+    }
+}
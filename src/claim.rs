@@ -5,7 +5,8 @@ use std::collections::BTreeMap;
 ///
 /// This provides a struct that matches the CNAB Claims 1.0 specification at the
 /// time when the CNAB Core 1.0 specification was finalized.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Claim {
     /// The bundle descriptor
@@ -35,7 +36,8 @@ pub struct Claim {
 /// Response represents the result of a CNAB operation, as described in a Claim.
 ///
 /// Since 'result' is a technical term in Rust, this is called Response instead.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Response {
     action: String,
@@ -43,13 +45,51 @@ pub struct Response {
     status: Status,
 }
 
-/// Status is one of 'success', 'failure', or 'pending'
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+impl Response {
+    pub fn new(action: &str, status: Status, message: Option<String>) -> Self {
+        Response {
+            action: action.to_string(),
+            message,
+            status,
+        }
+    }
+
+    /// The action that was performed
+    pub fn action(&self) -> &str {
+        &self.action
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+}
+
+/// Status is one of 'success', 'failure', 'pending' or 'canceled'
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub enum Status {
     Success,
     Failure,
     Pending,
+    /// The operation was stopped before it completed
+    Canceled,
+}
+
+impl Status {
+    /// The status as it is written in claims, such as `success`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Success => "success",
+            Status::Failure => "failure",
+            Status::Pending => "pending",
+            Status::Canceled => "canceled",
+        }
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,646 @@
+//! Persistence for claims.
+//!
+//! A `ClaimStore` keeps the latest claim of each installation, keyed by the claim's
+//! name, along with the contents of the outputs of its latest run. `FileClaimStore`
+//! keeps one JSON document per installation in a directory; `MemoryClaimStore` is
+//! useful for tests and short-lived tools.
+//!
+//! Installations are kept in namespaces, so that a service can keep those of
+//! several tenants in one store. A store is a view of one namespace, at first
+//! `DEFAULT_NAMESPACE`, where installation names are unique; the `namespaced`
+//! method of each store opens the view of another, and `list_all` lists the
+//! installations of every namespace.
+//!
+//! An installation is locked while an action modifies it, with `lock`, so that two
+//! runs never change it at once.
+//!
+//! ```
+//! use libcnab::claimstore::{ClaimStore, MemoryClaimStore, DEFAULT_NAMESPACE};
+//!
+//! let store = MemoryClaimStore::new();
+//! let tenant = store.namespaced("acme").unwrap();
+//! assert_eq!(tenant.namespace(), "acme");
+//! assert!(tenant.list().unwrap().is_empty());
+//! assert_eq!(store.namespace(), DEFAULT_NAMESPACE);
+//! ```
+use crate::claim::Claim;
+use crate::error_code::{Coded, ErrorCode};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "tokio")]
+mod asynchronous;
+#[cfg(feature = "tokio")]
+pub use self::asynchronous::*;
+
+/// The namespace of a store that was not opened in another
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+/// ClaimStore saves and loads the claims of installations in a namespace.
+pub trait ClaimStore {
+    /// Read the claim of an installation, or `None` if there is none.
+    fn read(&self, installation: &str) -> Result<Option<Claim>, ClaimStoreError>;
+
+    /// Save a claim, replacing any previous claim of the same installation.
+    fn store(&self, claim: &Claim) -> Result<(), ClaimStoreError>;
+
+    /// Remove the claim of an installation. Removing a missing claim is not an error.
+    fn delete(&self, installation: &str) -> Result<(), ClaimStoreError>;
+
+    /// The names of the installations with claims, in sorted order.
+    fn list(&self) -> Result<Vec<String>, ClaimStoreError>;
+
+    /// The namespace and name of the installations with claims in any namespace,
+    /// sorted by namespace and then name.
+    ///
+    /// Stores without namespaces keep all installations in `DEFAULT_NAMESPACE`.
+    fn list_all(&self) -> Result<Vec<(String, String)>, ClaimStoreError> {
+        let names = self.list()?.into_iter();
+        Ok(names.map(|n| (DEFAULT_NAMESPACE.to_string(), n)).collect())
+    }
+
+    /// Save the contents of an output of an installation, streaming from `contents`.
+    /// Returns the number of bytes stored.
+    fn store_output(
+        &self,
+        installation: &str,
+        output: &str,
+        contents: &mut dyn Read,
+    ) -> Result<u64, ClaimStoreError>;
+
+    /// Open the stored contents of an output, or `None` if it was never stored.
+    fn read_output(
+        &self,
+        installation: &str,
+        output: &str,
+    ) -> Result<Option<Box<dyn Read>>, ClaimStoreError>;
+
+    /// Lock an installation for the run `run_id`, failing with
+    /// `ClaimStoreError::Locked` and the ID of the run that holds it if another run
+    /// does.
+    ///
+    /// Stores that cannot lock do nothing, which is the default.
+    fn lock(&self, _installation: &str, _run_id: &str) -> Result<(), ClaimStoreError> {
+        Ok(())
+    }
+
+    /// Release the lock `run_id` holds on an installation. Releasing a lock that
+    /// another run holds, or that nobody does, does nothing. A lock left behind by a
+    /// process that stopped is released with the run ID of the error `lock` fails
+    /// with.
+    fn unlock(&self, _installation: &str, _run_id: &str) -> Result<(), ClaimStoreError> {
+        Ok(())
+    }
+}
+
+/// Whether `name` can be used as a file name.
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(&['/', '\\'][..])
+}
+
+/// FileClaimStore stores each claim as `<dir>/<installation>.json` and its outputs
+/// in `<dir>/<installation>.outputs/`. An installation is locked by creating
+/// `<dir>/<installation>.lock`, holding the run ID, so the processes sharing the
+/// directory exclude each other.
+///
+/// `<dir>` is the directory the store was created with for `DEFAULT_NAMESPACE`,
+/// and `namespaces/<namespace>` in it for other namespaces.
+#[derive(Debug, Clone)]
+pub struct FileClaimStore {
+    root: PathBuf,
+    namespace: String,
+    dir: PathBuf,
+}
+
+impl FileClaimStore {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        FileClaimStore {
+            root: dir.as_ref().to_path_buf(),
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The store of the installations of `namespace`, in the same directory.
+    pub fn namespaced(&self, namespace: &str) -> Result<Self, ClaimStoreError> {
+        if !valid_name(namespace) {
+            return Err(ClaimStoreError::InvalidNamespace(namespace.to_string()));
+        }
+        let dir = match namespace {
+            DEFAULT_NAMESPACE => self.root.clone(),
+            namespace => self.root.join("namespaces").join(namespace),
+        };
+        Ok(FileClaimStore {
+            root: self.root.clone(),
+            namespace: namespace.to_string(),
+            dir,
+        })
+    }
+
+    /// The namespace of the installations of this store.
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// The namespaces other than `DEFAULT_NAMESPACE` with a directory in the store.
+    fn namespaces(&self) -> Result<Vec<String>, ClaimStoreError> {
+        let entries = match std::fs::read_dir(self.root.join("namespaces")) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut namespaces = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            match entry.file_name().to_str() {
+                Some(name) if entry.file_type()?.is_dir() && name != DEFAULT_NAMESPACE => {
+                    namespaces.push(name.to_string())
+                }
+                _ => {}
+            }
+        }
+        Ok(namespaces)
+    }
+
+    fn path(&self, installation: &str) -> Result<PathBuf, ClaimStoreError> {
+        if !valid_name(installation) {
+            return Err(ClaimStoreError::InvalidName(installation.to_string()));
+        }
+        Ok(self.dir.join(format!("{}.json", installation)))
+    }
+
+    fn output_path(&self, installation: &str, output: &str) -> Result<PathBuf, ClaimStoreError> {
+        let dir = self.path(installation)?.with_extension("outputs");
+        if output.is_empty() || output.starts_with('.') || output.contains(&['/', '\\'][..]) {
+            return Err(ClaimStoreError::InvalidName(output.to_string()));
+        }
+        Ok(dir.join(output))
+    }
+}
+
+impl ClaimStore for FileClaimStore {
+    fn read(&self, installation: &str) -> Result<Option<Claim>, ClaimStoreError> {
+        match std::fs::File::open(self.path(installation)?) {
+            Ok(file) => Ok(Some(serde_json::from_reader(std::io::BufReader::new(
+                file,
+            ))?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn store(&self, claim: &Claim) -> Result<(), ClaimStoreError> {
+        let path = self.path(&claim.name)?;
+        std::fs::create_dir_all(&self.dir)?;
+        // Write to a temporary file and rename it so a crash never leaves a partial claim.
+        let partial = path.with_extension("json.tmp");
+        std::fs::write(&partial, serde_json::to_vec_pretty(claim)?)?;
+        std::fs::rename(&partial, &path)?;
+        Ok(())
+    }
+
+    fn delete(&self, installation: &str) -> Result<(), ClaimStoreError> {
+        let path = self.path(installation)?;
+        match std::fs::remove_dir_all(path.with_extension("outputs")) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>, ClaimStoreError> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            let name = entry?.file_name();
+            if let Some(name) = name.to_str().and_then(|n| n.strip_suffix(".json")) {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn list_all(&self) -> Result<Vec<(String, String)>, ClaimStoreError> {
+        let mut namespaces = self.namespaces()?;
+        namespaces.push(DEFAULT_NAMESPACE.to_string());
+        let mut all = Vec::new();
+        for namespace in namespaces {
+            let store = self.namespaced(&namespace)?;
+            all.extend(
+                ClaimStore::list(&store)?
+                    .into_iter()
+                    .map(|n| (namespace.clone(), n)),
+            );
+        }
+        all.sort();
+        Ok(all)
+    }
+
+    fn store_output(
+        &self,
+        installation: &str,
+        output: &str,
+        contents: &mut dyn Read,
+    ) -> Result<u64, ClaimStoreError> {
+        let path = self.output_path(installation, output)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let partial = path.with_extension("tmp");
+        let written = std::io::copy(contents, &mut std::fs::File::create(&partial)?)?;
+        std::fs::rename(&partial, &path)?;
+        Ok(written)
+    }
+
+    fn read_output(
+        &self,
+        installation: &str,
+        output: &str,
+    ) -> Result<Option<Box<dyn Read>>, ClaimStoreError> {
+        match std::fs::File::open(self.output_path(installation, output)?) {
+            Ok(file) => Ok(Some(Box::new(file))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn lock(&self, installation: &str, run_id: &str) -> Result<(), ClaimStoreError> {
+        let path = self.path(installation)?.with_extension("lock");
+        std::fs::create_dir_all(&self.dir)?;
+        let created = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path);
+        match created {
+            Ok(mut file) => Ok(file.write_all(run_id.as_bytes())?),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                Err(ClaimStoreError::Locked {
+                    installation: installation.to_string(),
+                    run_id: std::fs::read_to_string(&path)?,
+                })
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn unlock(&self, installation: &str, run_id: &str) -> Result<(), ClaimStoreError> {
+        let path = self.path(installation)?.with_extension("lock");
+        match std::fs::read_to_string(&path) {
+            Ok(holder) if holder == run_id => Ok(std::fs::remove_file(&path)?),
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Claims and outputs in memory, keyed by namespace and installation
+type MemoryClaims = BTreeMap<(String, String), Claim>;
+type MemoryOutputs = BTreeMap<(String, String, String), Vec<u8>>;
+/// The runs holding the locks of installations, keyed by namespace and installation
+type MemoryLocks = BTreeMap<(String, String), String>;
+
+/// MemoryClaimStore keeps claims in memory.
+///
+/// The stores of the namespaces of a store share its memory.
+#[derive(Debug)]
+pub struct MemoryClaimStore {
+    namespace: String,
+    claims: Arc<Mutex<MemoryClaims>>,
+    outputs: Arc<Mutex<MemoryOutputs>>,
+    locks: Arc<Mutex<MemoryLocks>>,
+}
+
+impl Default for MemoryClaimStore {
+    fn default() -> Self {
+        MemoryClaimStore {
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            claims: Arc::default(),
+            outputs: Arc::default(),
+            locks: Arc::default(),
+        }
+    }
+}
+
+impl MemoryClaimStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The store of the installations of `namespace`, in the same memory.
+    pub fn namespaced(&self, namespace: &str) -> Result<Self, ClaimStoreError> {
+        if !valid_name(namespace) {
+            return Err(ClaimStoreError::InvalidNamespace(namespace.to_string()));
+        }
+        Ok(MemoryClaimStore {
+            namespace: namespace.to_string(),
+            claims: self.claims.clone(),
+            outputs: self.outputs.clone(),
+            locks: self.locks.clone(),
+        })
+    }
+
+    /// The namespace of the installations of this store.
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    fn key(&self, installation: &str) -> (String, String) {
+        (self.namespace.clone(), installation.to_string())
+    }
+
+    fn output_key(&self, installation: &str, output: &str) -> (String, String, String) {
+        (
+            self.namespace.clone(),
+            installation.to_string(),
+            output.to_string(),
+        )
+    }
+}
+
+impl ClaimStore for MemoryClaimStore {
+    fn read(&self, installation: &str) -> Result<Option<Claim>, ClaimStoreError> {
+        Ok(self
+            .claims
+            .lock()
+            .expect("lock poisoned")
+            .get(&self.key(installation))
+            .cloned())
+    }
+
+    fn store(&self, claim: &Claim) -> Result<(), ClaimStoreError> {
+        self.claims
+            .lock()
+            .expect("lock poisoned")
+            .insert(self.key(&claim.name), claim.clone());
+        Ok(())
+    }
+
+    fn delete(&self, installation: &str) -> Result<(), ClaimStoreError> {
+        self.claims
+            .lock()
+            .expect("lock poisoned")
+            .remove(&self.key(installation));
+        self.outputs
+            .lock()
+            .expect("lock poisoned")
+            .retain(|(n, i, _), _| n != &self.namespace || i != installation);
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ClaimStoreError> {
+        Ok(self
+            .claims
+            .lock()
+            .expect("lock poisoned")
+            .keys()
+            .filter(|(namespace, _)| namespace == &self.namespace)
+            .map(|(_, installation)| installation.clone())
+            .collect())
+    }
+
+    fn list_all(&self) -> Result<Vec<(String, String)>, ClaimStoreError> {
+        Ok(self
+            .claims
+            .lock()
+            .expect("lock poisoned")
+            .keys()
+            .cloned()
+            .collect())
+    }
+
+    fn store_output(
+        &self,
+        installation: &str,
+        output: &str,
+        contents: &mut dyn Read,
+    ) -> Result<u64, ClaimStoreError> {
+        let mut bytes = Vec::new();
+        contents.read_to_end(&mut bytes)?;
+        let len = bytes.len() as u64;
+        self.outputs
+            .lock()
+            .expect("lock poisoned")
+            .insert(self.output_key(installation, output), bytes);
+        Ok(len)
+    }
+
+    fn read_output(
+        &self,
+        installation: &str,
+        output: &str,
+    ) -> Result<Option<Box<dyn Read>>, ClaimStoreError> {
+        let outputs = self.outputs.lock().expect("lock poisoned");
+        Ok(outputs
+            .get(&self.output_key(installation, output))
+            .map(|bytes| Box::new(std::io::Cursor::new(bytes.clone())) as Box<dyn Read>))
+    }
+
+    fn lock(&self, installation: &str, run_id: &str) -> Result<(), ClaimStoreError> {
+        let mut locks = self.locks.lock().expect("lock poisoned");
+        match locks.get(&self.key(installation)) {
+            Some(holder) => Err(ClaimStoreError::Locked {
+                installation: installation.to_string(),
+                run_id: holder.clone(),
+            }),
+            None => {
+                locks.insert(self.key(installation), run_id.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    fn unlock(&self, installation: &str, run_id: &str) -> Result<(), ClaimStoreError> {
+        let mut locks = self.locks.lock().expect("lock poisoned");
+        if locks.get(&self.key(installation)).map(String::as_str) == Some(run_id) {
+            locks.remove(&self.key(installation));
+        }
+        Ok(())
+    }
+}
+
+/// ClaimStoreError describes a failure to read or write a claim.
+#[derive(Debug)]
+pub enum ClaimStoreError {
+    /// The installation name cannot be used as a key in this store
+    InvalidName(String),
+    /// The namespace cannot be used as a key in this store
+    InvalidNamespace(String),
+    /// The installation is locked by the run `run_id`
+    Locked {
+        installation: String,
+        run_id: String,
+    },
+    IoError(std::io::Error),
+    SerdeJSONError(serde_json::Error),
+}
+
+impl fmt::Display for ClaimStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClaimStoreError::InvalidName(name) => {
+                write!(f, "{:?} is not a valid installation name", name)
+            }
+            ClaimStoreError::InvalidNamespace(namespace) => {
+                write!(f, "{:?} is not a valid namespace", namespace)
+            }
+            ClaimStoreError::Locked {
+                installation,
+                run_id,
+            } => write!(
+                f,
+                "an operation is already in progress on {}, run {}",
+                installation, run_id
+            ),
+            ClaimStoreError::IoError(e) => write!(f, "{}", e),
+            ClaimStoreError::SerdeJSONError(e) => write!(f, "invalid claim: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClaimStoreError {}
+
+impl Coded for ClaimStoreError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ClaimStoreError::InvalidName(_) => ErrorCode::ClaimsInvalidName,
+            ClaimStoreError::InvalidNamespace(_) => ErrorCode::ClaimsInvalidNamespace,
+            ClaimStoreError::Locked { .. } => ErrorCode::EngineInProgress,
+            ClaimStoreError::IoError(_) => ErrorCode::Io,
+            ClaimStoreError::SerdeJSONError(_) => ErrorCode::ClaimsInvalidJson,
+        }
+    }
+}
+
+impl From<std::io::Error> for ClaimStoreError {
+    fn from(error: std::io::Error) -> Self {
+        ClaimStoreError::IoError(error)
+    }
+}
+
+impl From<serde_json::Error> for ClaimStoreError {
+    fn from(error: serde_json::Error) -> Self {
+        ClaimStoreError::SerdeJSONError(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::claim::{Response, Status};
+    use crate::Bundle;
+
+    pub(super) fn claim(name: &str) -> Claim {
+        let now = chrono::Utc::now();
+        Claim {
+            bundle: Bundle::from_file("testdata/bundle.json").unwrap(),
+            created: now,
+            custom: None,
+            modified: now,
+            name: name.to_string(),
+            outputs: None,
+            parameters: None,
+            result: Response::new("install", Status::Success, None),
+            revision: crate::Ulid::new().to_string(),
+            bundle_reference: None,
+        }
+    }
+
+    fn exercise(store: &dyn ClaimStore) {
+        assert!(store.read("wordpress").unwrap().is_none());
+        store.store(&claim("wordpress")).unwrap();
+        store.store(&claim("mysql")).unwrap();
+        assert_eq!(
+            store.read("wordpress").unwrap().unwrap().result.status(),
+            Status::Success
+        );
+        assert_eq!(store.list().unwrap(), vec!["mysql", "wordpress"]);
+
+        assert_eq!(
+            store
+                .store_output("mysql", "connstr", &mut "mysql://db".as_bytes())
+                .unwrap(),
+            10
+        );
+        let mut contents = String::new();
+        store
+            .read_output("mysql", "connstr")
+            .unwrap()
+            .expect("output stored")
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "mysql://db");
+        assert!(store.read_output("mysql", "missing").unwrap().is_none());
+
+        store.delete("mysql").unwrap();
+        assert!(store.read_output("mysql", "connstr").unwrap().is_none());
+        store.delete("mysql").unwrap();
+        assert_eq!(store.list().unwrap(), vec!["wordpress"]);
+
+        store.lock("wordpress", "run-1").unwrap();
+        match store.lock("wordpress", "run-2") {
+            Err(ClaimStoreError::Locked { run_id, .. }) => assert_eq!(run_id, "run-1"),
+            other => panic!("expected a lock held by run-1, got {:?}", other),
+        }
+        store.unlock("wordpress", "run-2").unwrap();
+        assert!(store.lock("wordpress", "run-2").is_err());
+        store.unlock("wordpress", "run-1").unwrap();
+        store.lock("wordpress", "run-2").unwrap();
+        store.unlock("wordpress", "run-2").unwrap();
+        assert_eq!(store.list().unwrap(), vec!["wordpress"]);
+    }
+
+    /// Exercise the namespaces `default` and `acme`, with `store` in the default.
+    fn exercise_namespaces(store: &dyn ClaimStore, acme: &dyn ClaimStore) {
+        store.store(&claim("wordpress")).unwrap();
+        acme.store(&claim("wordpress")).unwrap();
+        acme.store(&claim("redis")).unwrap();
+        assert_eq!(store.list().unwrap(), vec!["wordpress"]);
+        assert_eq!(acme.list().unwrap(), vec!["redis", "wordpress"]);
+        assert!(store.read("redis").unwrap().is_none());
+
+        acme.store_output("redis", "url", &mut "redis://".as_bytes())
+            .unwrap();
+        assert!(store.read_output("redis", "url").unwrap().is_none());
+        store.delete("wordpress").unwrap();
+        assert!(acme.read("wordpress").unwrap().is_some());
+
+        let all = vec![
+            ("acme".to_string(), "redis".to_string()),
+            ("acme".to_string(), "wordpress".to_string()),
+        ];
+        assert_eq!(store.list_all().unwrap(), all);
+        assert_eq!(acme.list_all().unwrap(), all);
+    }
+
+    #[test]
+    fn test_claim_stores() {
+        exercise(&MemoryClaimStore::new());
+        let memory = MemoryClaimStore::new();
+        exercise_namespaces(&memory, &memory.namespaced("acme").unwrap());
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileClaimStore::new(dir.path());
+        exercise(&store);
+        assert!(ClaimStore::read(&store, "../etc").is_err());
+        assert!(matches!(
+            store.namespaced(".."),
+            Err(ClaimStoreError::InvalidNamespace(_))
+        ));
+        ClaimStore::delete(&store, "wordpress").unwrap();
+        exercise_namespaces(&store, &store.namespaced("acme").unwrap());
+        assert!(dir.path().join("namespaces/acme/redis.json").exists());
+        assert_eq!(store.namespaced(DEFAULT_NAMESPACE).unwrap().dir, dir.path());
+    }
+}
@@ -0,0 +1,243 @@
+use super::{ClaimStore, ClaimStoreError, FileClaimStore, MemoryClaimStore, DEFAULT_NAMESPACE};
+use crate::asynchronous::{blocking, BoxFuture};
+use crate::claim::Claim;
+use std::sync::Arc;
+
+/// AsyncClaimStore saves and loads the claims of installations without blocking the
+/// async runtime.
+///
+/// `FileClaimStore` uses tokio's file system operations, and `BlockingClaimStore`
+/// runs any `ClaimStore` on tokio's blocking threads. Outputs are read and written
+/// through the `ClaimStore` methods.
+pub trait AsyncClaimStore: Send + Sync {
+    /// Read the claim of an installation, or `None` if there is none.
+    fn read<'a>(
+        &'a self,
+        installation: &'a str,
+    ) -> BoxFuture<'a, Result<Option<Claim>, ClaimStoreError>>;
+
+    /// Save a claim, replacing any previous claim of the same installation.
+    fn store<'a>(&'a self, claim: &'a Claim) -> BoxFuture<'a, Result<(), ClaimStoreError>>;
+
+    /// Remove the claim of an installation. Removing a missing claim is not an error.
+    fn delete<'a>(&'a self, installation: &'a str) -> BoxFuture<'a, Result<(), ClaimStoreError>>;
+
+    /// The names of the installations with claims, in sorted order.
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, ClaimStoreError>>;
+
+    /// The namespace and name of the installations with claims in any namespace,
+    /// as `ClaimStore::list_all`.
+    fn list_all(&self) -> BoxFuture<'_, Result<Vec<(String, String)>, ClaimStoreError>> {
+        Box::pin(async move {
+            let names = self.list().await?.into_iter();
+            Ok(names.map(|n| (DEFAULT_NAMESPACE.to_string(), n)).collect())
+        })
+    }
+}
+
+fn not_found(e: &std::io::Error) -> bool {
+    e.kind() == std::io::ErrorKind::NotFound
+}
+
+impl AsyncClaimStore for FileClaimStore {
+    fn read<'a>(
+        &'a self,
+        installation: &'a str,
+    ) -> BoxFuture<'a, Result<Option<Claim>, ClaimStoreError>> {
+        Box::pin(async move {
+            match tokio::fs::read(self.path(installation)?).await {
+                Ok(json) => Ok(Some(serde_json::from_slice(&json)?)),
+                Err(e) if not_found(&e) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn store<'a>(&'a self, claim: &'a Claim) -> BoxFuture<'a, Result<(), ClaimStoreError>> {
+        Box::pin(async move {
+            let path = self.path(&claim.name)?;
+            tokio::fs::create_dir_all(&self.dir).await?;
+            let partial = path.with_extension("json.tmp");
+            tokio::fs::write(&partial, serde_json::to_vec_pretty(claim)?).await?;
+            tokio::fs::rename(&partial, &path).await?;
+            Ok(())
+        })
+    }
+
+    fn delete<'a>(&'a self, installation: &'a str) -> BoxFuture<'a, Result<(), ClaimStoreError>> {
+        Box::pin(async move {
+            let path = self.path(installation)?;
+            match tokio::fs::remove_dir_all(path.with_extension("outputs")).await {
+                Err(e) if !not_found(&e) => return Err(e.into()),
+                _ => {}
+            }
+            match tokio::fs::remove_file(path).await {
+                Err(e) if !not_found(&e) => Err(e.into()),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, ClaimStoreError>> {
+        Box::pin(async move {
+            let mut entries = match tokio::fs::read_dir(&self.dir).await {
+                Ok(entries) => entries,
+                Err(e) if not_found(&e) => return Ok(Vec::new()),
+                Err(e) => return Err(e.into()),
+            };
+            let mut names = Vec::new();
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name();
+                if let Some(name) = name.to_str().and_then(|n| n.strip_suffix(".json")) {
+                    names.push(name.to_string());
+                }
+            }
+            names.sort();
+            Ok(names)
+        })
+    }
+
+    fn list_all(&self) -> BoxFuture<'_, Result<Vec<(String, String)>, ClaimStoreError>> {
+        let store = self.clone();
+        Box::pin(blocking(move || ClaimStore::list_all(&store)))
+    }
+}
+
+/// Claims in memory are read and written without blocking.
+impl AsyncClaimStore for MemoryClaimStore {
+    fn read<'a>(
+        &'a self,
+        installation: &'a str,
+    ) -> BoxFuture<'a, Result<Option<Claim>, ClaimStoreError>> {
+        Box::pin(async move { ClaimStore::read(self, installation) })
+    }
+
+    fn store<'a>(&'a self, claim: &'a Claim) -> BoxFuture<'a, Result<(), ClaimStoreError>> {
+        Box::pin(async move { ClaimStore::store(self, claim) })
+    }
+
+    fn delete<'a>(&'a self, installation: &'a str) -> BoxFuture<'a, Result<(), ClaimStoreError>> {
+        Box::pin(async move { ClaimStore::delete(self, installation) })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, ClaimStoreError>> {
+        Box::pin(async move { ClaimStore::list(self) })
+    }
+
+    fn list_all(&self) -> BoxFuture<'_, Result<Vec<(String, String)>, ClaimStoreError>> {
+        Box::pin(async move { ClaimStore::list_all(self) })
+    }
+}
+
+/// BlockingClaimStore adapts a `ClaimStore` to `AsyncClaimStore`, running each call
+/// on tokio's blocking threads.
+#[derive(Debug)]
+pub struct BlockingClaimStore<S> {
+    store: Arc<S>,
+}
+
+impl<S> BlockingClaimStore<S> {
+    pub fn new(store: S) -> Self {
+        BlockingClaimStore {
+            store: Arc::new(store),
+        }
+    }
+
+    /// The wrapped store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+}
+
+impl<S> Clone for BlockingClaimStore<S> {
+    fn clone(&self) -> Self {
+        BlockingClaimStore {
+            store: self.store.clone(),
+        }
+    }
+}
+
+impl<S: ClaimStore + Send + Sync + 'static> AsyncClaimStore for BlockingClaimStore<S> {
+    fn read<'a>(
+        &'a self,
+        installation: &'a str,
+    ) -> BoxFuture<'a, Result<Option<Claim>, ClaimStoreError>> {
+        let (store, installation) = (self.store.clone(), installation.to_string());
+        Box::pin(blocking(move || store.read(&installation)))
+    }
+
+    fn store<'a>(&'a self, claim: &'a Claim) -> BoxFuture<'a, Result<(), ClaimStoreError>> {
+        let (store, claim) = (self.store.clone(), claim.clone());
+        Box::pin(blocking(move || store.store(&claim)))
+    }
+
+    fn delete<'a>(&'a self, installation: &'a str) -> BoxFuture<'a, Result<(), ClaimStoreError>> {
+        let (store, installation) = (self.store.clone(), installation.to_string());
+        Box::pin(blocking(move || store.delete(&installation)))
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, ClaimStoreError>> {
+        let store = self.store.clone();
+        Box::pin(blocking(move || store.list()))
+    }
+
+    fn list_all(&self) -> BoxFuture<'_, Result<Vec<(String, String)>, ClaimStoreError>> {
+        let store = self.store.clone();
+        Box::pin(blocking(move || store.list_all()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::claim;
+    use super::*;
+
+    async fn exercise(store: &dyn AsyncClaimStore) {
+        assert!(store.read("blog").await.unwrap().is_none());
+        store.store(&claim("blog")).await.unwrap();
+        store.store(&claim("wiki")).await.unwrap();
+        assert_eq!(store.read("blog").await.unwrap().unwrap().name, "blog");
+        assert_eq!(store.list().await.unwrap(), vec!["blog", "wiki"]);
+        store.delete("blog").await.unwrap();
+        store.delete("blog").await.unwrap();
+        assert_eq!(store.list().await.unwrap(), vec!["wiki"]);
+        let all = store.list_all().await.unwrap();
+        assert!(all.contains(&("default".to_string(), "wiki".to_string())));
+    }
+
+    #[test]
+    fn test_async_claim_stores() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileClaimStore::new(dir.path());
+        runtime.block_on(exercise(&store));
+        assert!(matches!(
+            runtime.block_on(AsyncClaimStore::read(&store, "../etc")),
+            Err(ClaimStoreError::InvalidName(_))
+        ));
+        assert_eq!(
+            ClaimStore::list(&FileClaimStore::new(dir.path())).unwrap(),
+            vec!["wiki"]
+        );
+        let acme = FileClaimStore::new(dir.path()).namespaced("acme").unwrap();
+        runtime
+            .block_on(AsyncClaimStore::store(&acme, &claim("blog")))
+            .unwrap();
+        assert_eq!(
+            runtime.block_on(AsyncClaimStore::list(&acme)).unwrap(),
+            vec!["blog"]
+        );
+        assert_eq!(
+            runtime.block_on(AsyncClaimStore::list_all(&store)).unwrap(),
+            vec![
+                ("acme".to_string(), "blog".to_string()),
+                ("default".to_string(), "wiki".to_string())
+            ]
+        );
+
+        runtime.block_on(exercise(&MemoryClaimStore::new()));
+        runtime.block_on(exercise(&BlockingClaimStore::new(MemoryClaimStore::new())));
+    }
+}
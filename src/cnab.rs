@@ -1,11 +1,22 @@
+use crate::env_var::EnvVarName;
+use crate::error_code::{Coded, ErrorCode};
+use crate::profile::{ValidationProfile, ValidationRule};
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
-use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+/// The actions every bundle supports without declaring them
+pub const BUILTIN_ACTIONS: &[&str] = &["install", "upgrade", "uninstall"];
+
+/// The `schemaVersion`s of the CNAB specification this crate reads bundles of.
+///
+/// Bundles of earlier versions, and of patch releases of these, are read too; those
+/// of later versions are refused with `BundleParseError::UnsupportedSchemaVersion`.
+pub const SUPPORTED_SCHEMA_VERSIONS: &[&str] = &["v1.0.0"];
+
 /// Bundle implements a CNAB bundle descriptor
 ///
 /// Bundle descriptors describe the properties of a bundle, including which images
@@ -13,7 +24,8 @@ use std::str::FromStr;
 /// are any additional target actions that can be executed on this bundle.
 ///
 /// The fields here are in canonical order.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Bundle {
     /// The list of additional actions that this bundle can perform.
@@ -61,9 +73,15 @@ pub struct Bundle {
     ///
     /// Parameters can be injected into a bundle during startup time.
     pub parameters: Option<BTreeMap<String, Parameter>>,
+    /// The extensions a runtime must support to execute this bundle
+    ///
+    /// Each entry names a key of `custom`. A runtime that does not understand one of
+    /// them must refuse to run the bundle.
+    pub required_extensions: Option<Vec<String>>,
     /// schema_version is the version of the CNAB specification used to describe this
     pub schema_version: String,
     /// version is the version of the bundle
+    #[cfg_attr(feature = "schemars", schemars(with = "String"))]
     pub version: Version,
 }
 
@@ -77,24 +95,297 @@ impl Bundle {
     /// let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
     /// assert_eq!(bundle.name, "helloworld");
     /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(path = %path.as_ref().display()), err)
+    )]
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, BundleParseError> {
-        let file = File::open(path)?;
-        Self::from_json(file)
+        // One read of the whole file, rather than the many small reads parsing
+        // straight from an unbuffered file makes.
+        let bytes = std::fs::read(path)?;
+        Self::from_json(bytes.as_slice())
     }
 
     /// Deserialize a `Bundle` from any type implementing `Read`.
+    ///
+    /// With the `compression` feature, gzip and zstd streams are decompressed, and
+    /// the `bundle.json` of a tarball, such as a `.cnab` or `.tgz`, is read out of it.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, err)
+    )]
     pub fn from_json<R: Read>(reader: R) -> Result<Self, BundleParseError> {
-        let bundle = serde_json::from_reader(reader)?;
-        Ok(bundle)
+        Self::from_json_with(reader, &ValidationProfile::Strict1_0)
+    }
+
+    /// Deserialize a `Bundle` as `from_json` does, enforcing only the parsing rules of
+    /// `profile`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, err)
+    )]
+    pub fn from_json_with<R: Read>(
+        reader: R,
+        profile: &ValidationProfile,
+    ) -> Result<Self, BundleParseError> {
+        #[cfg(feature = "compression")]
+        let mut reader = crate::compression::decompressed(reader)?;
+        #[cfg(not(feature = "compression"))]
+        let mut reader = reader;
+        let mut json = Vec::new();
+        reader.read_to_end(&mut json)?;
+        Self::from_json_slice(&json, profile)
+    }
+
+    /// Deserialize a `Bundle` from JSON, refusing one of a `schemaVersion` this crate
+    /// does not support rather than failing on the fields it does not know, unless
+    /// `profile` does not enforce `ValidationRule::SchemaVersion`.
+    pub(crate) fn from_json_slice(
+        json: &[u8],
+        profile: &ValidationProfile,
+    ) -> Result<Self, BundleParseError> {
+        #[derive(Deserialize)]
+        struct Declared {
+            #[serde(rename = "schemaVersion")]
+            schema_version: String,
+        }
+
+        match serde_json::from_slice::<Bundle>(json) {
+            Ok(bundle) => {
+                check_schema_version(&bundle.schema_version, profile)?;
+                Ok(bundle)
+            }
+            Err(e) => {
+                if let Ok(declared) = serde_json::from_slice::<Declared>(json) {
+                    check_schema_version(&declared.schema_version, profile)?;
+                }
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Serialize the bundle as canonical JSON, with sorted keys and no insignificant
+    /// whitespace, the form the CNAB specification asks a `bundle.json` to be
+    /// digested and signed in.
+    ///
+    /// ```
+    /// use libcnab::Bundle;
+    ///
+    /// let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+    /// let json = String::from_utf8(bundle.to_canonical_json()).unwrap();
+    /// assert!(json.starts_with('{') && !json.contains('\n'));
+    /// assert!(json.find(r#""name":"#) < json.find(r#""version":"#));
+    /// ```
+    pub fn to_canonical_json(&self) -> Vec<u8> {
+        fn sorted(value: serde_json::Value) -> serde_json::Value {
+            match value {
+                serde_json::Value::Object(map) => {
+                    let mut entries: Vec<_> = map.into_iter().collect();
+                    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+                    serde_json::Value::Object(
+                        entries.into_iter().map(|(k, v)| (k, sorted(v))).collect(),
+                    )
+                }
+                serde_json::Value::Array(values) => {
+                    serde_json::Value::Array(values.into_iter().map(sorted).collect())
+                }
+                value => value,
+            }
+        }
+        let value = serde_json::to_value(self).expect("bundles serialize");
+        serde_json::to_vec(&sorted(value)).expect("bundles serialize")
+    }
+}
+
+impl Bundle {
+    /// Whether the named definition marks its values as sensitive (`writeOnly: true`).
+    pub fn is_sensitive(&self, definition: &str) -> bool {
+        self.definitions
+            .as_ref()
+            .and_then(|d| d.get(definition))
+            .and_then(|d| d.get("writeOnly"))
+            .and_then(|w| w.as_bool())
+            .unwrap_or(false)
+    }
+
+    /// Return the names of the credentials required by `action` that are not in `provided`.
+    ///
+    /// A credential is required by an action when its `required` flag is set and its
+    /// `applyTo` list is either unset or names the action.
+    ///
+    /// ```
+    /// use libcnab::Bundle;
+    ///
+    /// let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+    /// assert!(bundle.missing_credentials("install", &["hostkey"]).is_empty());
+    /// ```
+    pub fn missing_credentials<S: AsRef<str>>(&self, action: &str, provided: &[S]) -> Vec<String> {
+        self.credentials
+            .iter()
+            .flatten()
+            .filter(|(_, c)| c.required.unwrap_or(false) && c.applies_to(action))
+            .filter(|(name, _)| !provided.iter().any(|p| p.as_ref() == name.as_str()))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Tidy the keywords for indexing: trim and lowercase them, and remove the empty
+    /// ones and repeats, keeping the first of each.
+    pub fn normalize_keywords(&mut self) {
+        self.normalize_keywords_with(&[])
+    }
+
+    /// Tidy the keywords as `normalize_keywords` does, and also remove those in
+    /// `stop_words`, such as `cnab` or `bundle`, which every bundle could claim.
+    pub fn normalize_keywords_with(&mut self, stop_words: &[&str]) {
+        if let Some(keywords) = self.keywords.as_mut() {
+            let mut seen = Vec::with_capacity(keywords.len());
+            for keyword in keywords.drain(..) {
+                let keyword = keyword.trim().to_lowercase();
+                if !keyword.is_empty()
+                    && !stop_words.iter().any(|s| s.eq_ignore_ascii_case(&keyword))
+                    && !seen.contains(&keyword)
+                {
+                    seen.push(keyword);
+                }
+            }
+            *keywords = seen;
+        }
+    }
+
+    /// Pin the bundle's OCI images to digests, so the bundle always refers to the same
+    /// content.
+    ///
+    /// An image referenced by tag becomes `<image>@<digest>`. The digest is its
+    /// `contentDigest` when the bundle records one, and otherwise whatever `resolve`
+    /// returns for the reference, which is then recorded as the `contentDigest`.
+    /// Images that are already referenced by digest, or that are not OCI images, are
+    /// left alone. With the `registry` feature, `registry::Client::resolve_digest`
+    /// resolves references.
+    ///
+    /// ```
+    /// use libcnab::Bundle;
+    ///
+    /// let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+    /// bundle
+    ///     .pin_images(|_| Ok::<_, std::io::Error>("sha256:0123".to_string()))
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     bundle.invocation_images[0].image,
+    ///     "technosophos/helloworld:0.1.0@sha256:0123"
+    /// );
+    /// ```
+    pub fn pin_images<F, E>(&mut self, mut resolve: F) -> Result<(), E>
+    where
+        F: FnMut(&str) -> Result<String, E>,
+    {
+        let invocation_images = self
+            .invocation_images
+            .iter_mut()
+            .map(|i| (&mut i.image, &i.image_type, &mut i.content_digest));
+        let images = self
+            .images
+            .iter_mut()
+            .flat_map(|images| images.values_mut())
+            .map(|i| (&mut i.image, &i.image_type, &mut i.content_digest));
+        for (image, image_type, content_digest) in invocation_images.chain(images) {
+            if !is_oci_image(image_type.as_deref()) || image.contains('@') {
+                continue;
+            }
+            let digest = match content_digest {
+                Some(digest) => digest.clone(),
+                None => resolve(image)?,
+            };
+            *image = format!("{}@{}", image, digest);
+            *content_digest = Some(digest);
+        }
+        Ok(())
     }
+
+    /// The OCI images of the bundle that are referenced by tag alone, which may
+    /// point at other content by the time the bundle runs. A `contentDigest` does not
+    /// pin an image, since drivers pull the reference; `pin_images` adds the digest
+    /// to it.
+    ///
+    /// ```
+    /// use libcnab::Bundle;
+    ///
+    /// let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+    /// assert_eq!(bundle.unpinned_images().len(), 2);
+    /// bundle
+    ///     .pin_images(|_| Ok::<_, std::io::Error>("sha256:0123".to_string()))
+    ///     .unwrap();
+    /// assert!(bundle.unpinned_images().is_empty());
+    /// ```
+    pub fn unpinned_images(&self) -> Vec<&str> {
+        let invocation_images = self
+            .invocation_images
+            .iter()
+            .map(|i| (&i.image, &i.image_type));
+        let images = self
+            .images
+            .iter()
+            .flat_map(|images| images.values())
+            .map(|i| (&i.image, &i.image_type));
+        invocation_images
+            .chain(images)
+            .filter(|(image, image_type)| {
+                is_oci_image(image_type.as_deref()) && !image.contains('@')
+            })
+            .map(|(image, _)| image.as_str())
+            .collect()
+    }
+}
+
+/// Whether an image of the given `imageType` is an OCI image, which is the default.
+pub(crate) fn is_oci_image(image_type: Option<&str>) -> bool {
+    matches!(image_type, None | Some("oci") | Some("docker"))
 }
 
 impl FromStr for Bundle {
-    type Err = serde_json::Error;
+    type Err = BundleParseError;
 
+    /// Parse a bundle as `Bundle::from_json` does, refusing one of a `schemaVersion`
+    /// this crate does not support.
     fn from_str(json_data: &str) -> Result<Self, Self::Err> {
-        let bundle = serde_json::from_str(json_data)?;
-        Ok(bundle)
+        Self::from_json_slice(json_data.as_bytes(), &ValidationProfile::Strict1_0)
+    }
+}
+
+/// Refuse a `schemaVersion` newer than the `SUPPORTED_SCHEMA_VERSIONS`, unless
+/// `profile` does not enforce `ValidationRule::SchemaVersion`. Every format a bundle
+/// is parsed from is checked with this.
+///
+/// Versions are compared by major and minor version, with or without a leading `v`.
+/// One that is not a version at all is left for validation to report.
+pub(crate) fn check_schema_version(
+    found: &str,
+    profile: &ValidationProfile,
+) -> Result<(), BundleParseError> {
+    fn major_minor(version: &str) -> Option<(u64, u64)> {
+        let version = version.trim().trim_start_matches('v');
+        let release = version.split(['-', '+']).next()?;
+        let mut parts = release.split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next().map_or(Some(0), |m| m.parse().ok())?;
+        Some((major, minor))
+    }
+
+    if !profile.enforces(ValidationRule::SchemaVersion) {
+        return Ok(());
+    }
+    let newest = SUPPORTED_SCHEMA_VERSIONS
+        .iter()
+        .filter_map(|v| major_minor(v))
+        .max();
+    match (major_minor(found), newest) {
+        (Some(found_version), Some(newest)) if found_version > newest => {
+            Err(BundleParseError::UnsupportedSchemaVersion {
+                found: found.to_string(),
+                supported: SUPPORTED_SCHEMA_VERSIONS,
+            })
+        }
+        _ => Ok(()),
     }
 }
 
@@ -106,6 +397,52 @@ impl FromStr for Bundle {
 pub enum BundleParseError {
     SerdeJSONError(serde_json::Error),
     IoError(std::io::Error),
+    #[cfg(feature = "toml")]
+    TomlError(::toml::de::Error),
+    #[cfg(feature = "yaml")]
+    YamlError(serde_yaml::Error),
+    /// The bundle is of a later version of the specification than this crate reads
+    UnsupportedSchemaVersion {
+        found: String,
+        supported: &'static [&'static str],
+    },
+}
+
+impl std::fmt::Display for BundleParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BundleParseError::SerdeJSONError(e) => write!(f, "invalid bundle JSON: {}", e),
+            BundleParseError::IoError(e) => write!(f, "reading bundle: {}", e),
+            #[cfg(feature = "toml")]
+            BundleParseError::TomlError(e) => write!(f, "invalid bundle TOML: {}", e),
+            #[cfg(feature = "yaml")]
+            BundleParseError::YamlError(e) => write!(f, "invalid bundle YAML: {}", e),
+            BundleParseError::UnsupportedSchemaVersion { found, supported } => write!(
+                f,
+                "bundle is of schemaVersion {}, newer than the supported {}",
+                found,
+                supported.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BundleParseError {}
+
+impl Coded for BundleParseError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            BundleParseError::SerdeJSONError(_) => ErrorCode::BundleInvalidJson,
+            BundleParseError::IoError(_) => ErrorCode::Io,
+            #[cfg(feature = "toml")]
+            BundleParseError::TomlError(_) => ErrorCode::BundleInvalidToml,
+            #[cfg(feature = "yaml")]
+            BundleParseError::YamlError(_) => ErrorCode::BundleInvalidYaml,
+            BundleParseError::UnsupportedSchemaVersion { .. } => {
+                ErrorCode::BundleUnsupportedSchemaVersion
+            }
+        }
+    }
 }
 
 impl From<std::io::Error> for BundleParseError {
@@ -123,7 +460,8 @@ impl From<serde_json::Error> for BundleParseError {
 /// Maintainer describes a bundle maintainer.
 ///
 /// The name field is required, though the format of its value is unspecified.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Maintainer {
     /// The email address of the maintainer
     pub email: Option<String>,
@@ -136,7 +474,8 @@ pub struct Maintainer {
 /// Image describes a CNAB image.
 ///
 /// Both invocation images and regular images can be described using this object.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Image {
     /// A description of the purpose of this image
@@ -163,7 +502,8 @@ pub struct Image {
 /// In the final CNAB Core 1.0 spec, this is subtly different than the regular Image type.
 ///
 /// This conforms to the CNAB Core 1.0 specification
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct InvocationImage {
     /// A digest to be used to verify the integrity of the image
@@ -187,11 +527,16 @@ pub struct InvocationImage {
 }
 
 /// Platform defines a platform as a machine architecture plus and operating system
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Platform {
     /// The architecture
     ///
     /// Typical values are amd64, i386, and arm64
+    ///
+    /// This is `architecture` in JSON, as the specification names it, though `arch`
+    /// is read too.
+    #[serde(rename = "architecture", alias = "arch")]
     pub arch: Option<String>,
     /// The operating system.
     ///
@@ -202,24 +547,38 @@ pub struct Platform {
 /// Credential describes a particular credential that may be injected into a bundle
 ///
 /// Satisfies the CNAB Core 1.0 specification
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
 pub struct Credential {
+    /// The actions to which this credential applies.
+    ///
+    /// If unset, this credential will be applied to all actions.
+    pub apply_to: Option<Vec<String>>,
     /// The description of this credential
     pub description: Option<String>,
     /// The name of the environment variable into which the value will be placed
-    pub env: Option<String>,
+    pub env: Option<EnvVarName>,
     /// The fully qualified path into which the value will be placed
     pub path: Option<PathBuf>,
     /// Indicates whether this credential must be supplied. None is interpreted as "Some(false)".
     pub required: Option<bool>,
 }
 
+impl Credential {
+    /// Whether this credential is applied to the given action.
+    pub fn applies_to(&self, action: &str) -> bool {
+        applies_to(&self.apply_to, action)
+    }
+}
+
 /// Parameter describes a parameter that will be put into the invocation image
 ///
 /// Paramters are injected into the invocation image at startup time
 ///
 /// Conforms to CNAB Core 1.0
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Parameter {
     /// The actions to which this parameter applies.
@@ -239,11 +598,26 @@ pub struct Parameter {
     pub required: Option<bool>,
 }
 
+impl Parameter {
+    /// Whether this parameter is applied to the given action.
+    pub fn applies_to(&self, action: &str) -> bool {
+        applies_to(&self.apply_to, action)
+    }
+}
+
+fn applies_to(apply_to: &Option<Vec<String>>, action: &str) -> bool {
+    match apply_to {
+        Some(actions) => actions.iter().any(|a| a == action),
+        None => true,
+    }
+}
+
 /// An Action is a custom action in an invocation image.
 ///
 /// For example, an invocation image may provide help text by creating a 'help'
 /// action that, when triggered, prints help text to STDOUT.
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Action {
     /// Describes what this action does
     pub description: Option<String>,
@@ -259,7 +633,8 @@ pub struct Action {
 }
 
 /// Describe a parameter
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Metadata {
     /// A description of a parameter
     pub description: Option<String>,
@@ -271,10 +646,11 @@ pub struct Metadata {
 /// A parameter value can be placed into an environment variable (`env`) or a file at
 /// a particular location on the filesystem (`path`). This is a non-exclusive or, meaning
 /// that the same paramter can be written to both an env var and a path.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Destination {
     /// The name of the destination environment variable
-    pub env: Option<String>,
+    pub env: Option<EnvVarName>,
     /// The fully qualified path to the destination file
     pub path: Option<PathBuf>,
 }
@@ -282,7 +658,8 @@ pub struct Destination {
 /// A value that is produced by running an invocation image
 ///
 /// Complies to CNAB Core 1.0
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Output {
     /// An optional exhaustive list of actions producing this output
@@ -294,3 +671,10 @@ pub struct Output {
     /// The path inside of the invocation image where output will be written
     pub path: Option<PathBuf>,
 }
+
+impl Output {
+    /// Whether this output is produced by the given action.
+    pub fn applies_to(&self, action: &str) -> bool {
+        applies_to(&self.apply_to, action)
+    }
+}
@@ -0,0 +1,400 @@
+//! Check libcnab against golden test vectors, the bundle and claim fixtures other
+//! CNAB implementations are tested with, so that the JSON it writes stays byte for
+//! byte what cnab-go writes for the same document.
+//!
+//! A test-vector directory holds a `bundles` and a `claims` directory, laid out the
+//! way cnab-spec and cnab-go keep their fixtures:
+//!
+//! - each `<name>.json` is parsed, as a bundle or a claim, and written back with
+//!   `go_canonical_json`, which must be exactly the `<name>.canonical.json` beside
+//!   it. A document without a canonical file only has to parse.
+//! - each `<name>.invalid.json` must fail to parse.
+//!
+//! The vectors this crate is tested with are built in, so that a crate embedding it
+//! can check that its build, features and dependency versions keep the output the
+//! Go ecosystem expects:
+//!
+//! ```
+//! libcnab::compat::assert_compatible(&libcnab::compat::vendored());
+//! ```
+//!
+//! Other vectors, such as a checkout of cnab-go's, are read with `load_dir`.
+use crate::claim::Claim;
+use crate::cnab::Bundle;
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+
+/// The keys whose values are documents of their own, JSON Schemata and extension
+/// data, which are written as they are rather than the way cnab-go writes its types
+const OPAQUE_KEYS: &[&str] = &["custom", "definitions"];
+/// The boolean fields cnab-go leaves out when they are false
+const OMITTED_WHEN_FALSE: &[&str] = &["modifies", "required", "stateless"];
+
+/// Kind is what a test vector holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Bundle,
+    Claim,
+}
+
+impl Kind {
+    /// The directory of a test-vector directory holding documents of this kind
+    pub fn dir(self) -> &'static str {
+        match self {
+            Kind::Bundle => "bundles",
+            Kind::Claim => "claims",
+        }
+    }
+}
+
+/// Expected is what checking a test vector must find.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expected {
+    /// The document is written back as this canonical JSON
+    Canonical(String),
+    /// The document parses
+    Parses,
+    /// The document does not parse
+    Invalid,
+}
+
+/// Vector is a golden test vector: a document and what libcnab must make of it.
+#[derive(Debug, Clone)]
+pub struct Vector {
+    pub name: String,
+    pub kind: Kind,
+    pub document: String,
+    pub expected: Expected,
+}
+
+/// Outcome is the result of checking one test vector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    /// The document was written back differently
+    Mismatch {
+        expected: String,
+        actual: String,
+    },
+    /// The document did not parse, though it should have
+    Unparsable(String),
+    /// The document parsed, though it is invalid
+    UnexpectedlyValid,
+}
+
+/// Report is the outcome of checking each of a set of test vectors, in order.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub outcomes: Vec<(String, Kind, Outcome)>,
+}
+
+impl Report {
+    /// The vectors that did not pass, with their outcomes.
+    pub fn failures(&self) -> impl Iterator<Item = &(String, Kind, Outcome)> {
+        self.outcomes
+            .iter()
+            .filter(|(_, _, o)| *o != Outcome::Passed)
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.failures().next().is_none()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let failures: Vec<_> = self.failures().collect();
+        writeln!(
+            f,
+            "{} of {} test vectors passed",
+            self.outcomes.len() - failures.len(),
+            self.outcomes.len()
+        )?;
+        for (name, kind, outcome) in failures {
+            write!(f, "{}/{}: ", kind.dir(), name)?;
+            match outcome {
+                Outcome::Passed => writeln!(f, "passed")?,
+                Outcome::Mismatch { expected, actual } => {
+                    writeln!(f, "expected\n  {}\ngot\n  {}", expected, actual)?
+                }
+                Outcome::Unparsable(e) => writeln!(f, "does not parse: {}", e)?,
+                Outcome::UnexpectedlyValid => writeln!(f, "parses, though it is invalid")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write `value` the way cnab-go writes a bundle or claim: as canonical JSON with
+/// sorted keys and no insignificant whitespace, leaving out the fields that are
+/// unset, and the boolean flags that are false, and escaping `<`, `>`, `&`, U+2028
+/// and U+2029 as Go's encoder does. The contents of `custom` and `definitions` are
+/// written as they are.
+///
+/// Times are written as chrono writes them, which is what Go writes for times of
+/// whole seconds, but not always for fractions of one.
+pub fn go_canonical_json<T: Serialize>(value: &T) -> Vec<u8> {
+    fn trimmed(value: serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Object(map) => {
+                let mut entries: Vec<_> = map
+                    .into_iter()
+                    .filter(|(key, value)| match value {
+                        serde_json::Value::Null => false,
+                        serde_json::Value::Bool(false) => {
+                            !OMITTED_WHEN_FALSE.contains(&key.as_str())
+                        }
+                        _ => true,
+                    })
+                    .map(|(key, value)| {
+                        let value = if OPAQUE_KEYS.contains(&key.as_str()) {
+                            sorted(value)
+                        } else {
+                            trimmed(value)
+                        };
+                        (key, value)
+                    })
+                    .collect();
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+                serde_json::Value::Object(entries.into_iter().collect())
+            }
+            serde_json::Value::Array(values) => {
+                serde_json::Value::Array(values.into_iter().map(trimmed).collect())
+            }
+            value => value,
+        }
+    }
+    fn sorted(value: serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Object(map) => {
+                let mut entries: Vec<_> = map.into_iter().collect();
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+                serde_json::Value::Object(
+                    entries.into_iter().map(|(k, v)| (k, sorted(v))).collect(),
+                )
+            }
+            serde_json::Value::Array(values) => {
+                serde_json::Value::Array(values.into_iter().map(sorted).collect())
+            }
+            value => value,
+        }
+    }
+    let value = serde_json::to_value(value).expect("documents serialize");
+    let json = serde_json::to_string(&trimmed(value)).expect("documents serialize");
+    // These only occur within strings, so they can be escaped after the fact.
+    let mut escaped = String::with_capacity(json.len());
+    for c in json.chars() {
+        match c {
+            '<' => escaped.push_str("\\u003c"),
+            '>' => escaped.push_str("\\u003e"),
+            '&' => escaped.push_str("\\u0026"),
+            '\u{2028}' => escaped.push_str("\\u2028"),
+            '\u{2029}' => escaped.push_str("\\u2029"),
+            c => escaped.push(c),
+        }
+    }
+    escaped.into_bytes()
+}
+
+/// Check `vectors`, reporting the outcome of each.
+pub fn check(vectors: &[Vector]) -> Report {
+    let outcomes = vectors
+        .iter()
+        .map(|vector| {
+            let outcome = match (written(vector), &vector.expected) {
+                (Ok(_), Expected::Invalid) => Outcome::UnexpectedlyValid,
+                (Err(_), Expected::Invalid) => Outcome::Passed,
+                (Err(e), _) => Outcome::Unparsable(e.to_string()),
+                (Ok(_), Expected::Parses) => Outcome::Passed,
+                (Ok(actual), Expected::Canonical(expected)) => {
+                    let expected = expected.trim_end();
+                    if actual == expected {
+                        Outcome::Passed
+                    } else {
+                        Outcome::Mismatch {
+                            expected: expected.to_string(),
+                            actual,
+                        }
+                    }
+                }
+            };
+            (vector.name.clone(), vector.kind, outcome)
+        })
+        .collect();
+    Report { outcomes }
+}
+
+/// Check `vectors`, and panic with the report unless every one of them passed.
+pub fn assert_compatible(vectors: &[Vector]) {
+    let report = check(vectors);
+    if !report.is_ok() {
+        panic!("{}", report);
+    }
+}
+
+/// Parse the document of `vector`, and write it back.
+fn written(vector: &Vector) -> Result<String, serde_json::Error> {
+    let json = match vector.kind {
+        Kind::Bundle => go_canonical_json(&serde_json::from_str::<Bundle>(&vector.document)?),
+        Kind::Claim => go_canonical_json(&serde_json::from_str::<Claim>(&vector.document)?),
+    };
+    Ok(String::from_utf8(json).expect("JSON is UTF-8"))
+}
+
+/// Read the test vectors of the directory `dir`, ordered by kind and name.
+pub fn load_dir<P: AsRef<Path>>(dir: P) -> std::io::Result<Vec<Vector>> {
+    let mut vectors = Vec::new();
+    for kind in [Kind::Bundle, Kind::Claim] {
+        let dir = dir.as_ref().join(kind.dir());
+        let mut names = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries
+                .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+                .collect::<Result<Vec<_>, _>>()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        names.sort();
+        for file in &names {
+            if file.ends_with(".canonical.json") {
+                continue;
+            }
+            let document = || std::fs::read_to_string(dir.join(file));
+            if let Some(name) = file.strip_suffix(".invalid.json") {
+                vectors.push(Vector {
+                    name: name.to_string(),
+                    kind,
+                    document: document()?,
+                    expected: Expected::Invalid,
+                });
+            } else if let Some(name) = file.strip_suffix(".json") {
+                let canonical = dir.join(format!("{}.canonical.json", name));
+                let expected = match std::fs::read_to_string(canonical) {
+                    Ok(canonical) => Expected::Canonical(canonical),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Expected::Parses,
+                    Err(e) => return Err(e),
+                };
+                vectors.push(Vector {
+                    name: name.to_string(),
+                    kind,
+                    document: document()?,
+                    expected,
+                });
+            }
+        }
+    }
+    Ok(vectors)
+}
+
+/// The test vectors this crate is tested with, from `testdata/compat`.
+pub fn vendored() -> Vec<Vector> {
+    vec![
+        vector(
+            Kind::Bundle,
+            "helloworld",
+            include_str!("../testdata/compat/bundles/helloworld.json"),
+            Expected::Canonical(
+                include_str!("../testdata/compat/bundles/helloworld.canonical.json").to_string(),
+            ),
+        ),
+        vector(
+            Kind::Bundle,
+            "minimal",
+            include_str!("../testdata/compat/bundles/minimal.json"),
+            Expected::Canonical(
+                include_str!("../testdata/compat/bundles/minimal.canonical.json").to_string(),
+            ),
+        ),
+        vector(
+            Kind::Bundle,
+            "missing-invocation-images",
+            include_str!("../testdata/compat/bundles/missing-invocation-images.invalid.json"),
+            Expected::Invalid,
+        ),
+        vector(
+            Kind::Bundle,
+            "missing-name",
+            include_str!("../testdata/compat/bundles/missing-name.invalid.json"),
+            Expected::Invalid,
+        ),
+        vector(
+            Kind::Claim,
+            "install",
+            include_str!("../testdata/compat/claims/install.json"),
+            Expected::Canonical(
+                include_str!("../testdata/compat/claims/install.canonical.json").to_string(),
+            ),
+        ),
+        vector(
+            Kind::Claim,
+            "invalid-status",
+            include_str!("../testdata/compat/claims/invalid-status.invalid.json"),
+            Expected::Invalid,
+        ),
+    ]
+}
+
+fn vector(kind: Kind, name: &str, document: &str, expected: Expected) -> Vector {
+    Vector {
+        name: name.to_string(),
+        kind,
+        document: document.to_string(),
+        expected,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_vendored() {
+        let vectors = vendored();
+        assert_compatible(&vectors);
+
+        let loaded = load_dir("testdata/compat").unwrap();
+        let names = |v: &[Vector]| -> Vec<(String, Kind, Expected)> {
+            v.iter()
+                .map(|v| (v.name.clone(), v.kind, v.expected.clone()))
+                .collect()
+        };
+        assert_eq!(names(&loaded), names(&vectors), "every vector is vendored");
+    }
+
+    #[test]
+    fn test_go_canonical_json() {
+        let bundle: Bundle = serde_json::from_str(
+            r#"{
+                "schemaVersion": "v1.0.0",
+                "name": "hello",
+                "version": "0.1.0",
+                "description": "<b>hello</b> & goodbye",
+                "invocationImages": [{ "image": "example/hello:0.1.0" }],
+                "actions": { "status": { "modifies": false } },
+                "custom": { "com.example": { "enabled": false, "unset": null } }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(go_canonical_json(&bundle)).unwrap(),
+            concat!(
+                r#"{"actions":{"status":{}},"custom":{"com.example":{"enabled":false,"unset":null}},"#,
+                r#""description":"\u003cb\u003ehello\u003c/b\u003e \u0026 goodbye","#,
+                r#""invocationImages":[{"image":"example/hello:0.1.0"}],"name":"hello","#,
+                r#""schemaVersion":"v1.0.0","version":"0.1.0"}"#
+            )
+        );
+
+        let mut vectors = vendored();
+        vectors[0].expected = Expected::Canonical("{}".to_string());
+        vectors[2].expected = Expected::Parses;
+        let report = check(&vectors);
+        assert!(!report.is_ok());
+        let failures: Vec<_> = report
+            .failures()
+            .map(|(name, _, _)| name.as_str())
+            .collect();
+        assert_eq!(failures, vec!["helloworld", "missing-invocation-images"]);
+        assert!(report.to_string().starts_with("4 of 6 test vectors passed"));
+    }
+}
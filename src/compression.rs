@@ -0,0 +1,117 @@
+use flate2::read::GzDecoder;
+use ruzstd::decoding::StreamingDecoder;
+use std::io::{Cursor, Read};
+
+/// The name of the bundle descriptor in a `.cnab` or `.tgz` archive
+pub const ARCHIVE_BUNDLE_PATH: &str = "bundle.json";
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+/// Where a tar header holds the `ustar` magic
+const TAR_MAGIC: std::ops::Range<usize> = 257..262;
+
+/// The bundle descriptor `reader` holds: decompressed if it is gzip or zstd, and read
+/// out of the archive if it is a tarball, such as a thick bundle.
+pub(crate) fn decompressed<'r, R: Read + 'r>(reader: R) -> std::io::Result<Box<dyn Read + 'r>> {
+    let (head, reader) = peek(Box::new(reader), TAR_MAGIC.end)?;
+    let (head, reader) = if head.starts_with(GZIP_MAGIC) {
+        peek(Box::new(GzDecoder::new(reader)), TAR_MAGIC.end)?
+    } else if head.starts_with(ZSTD_MAGIC) {
+        let decoder = StreamingDecoder::new(reader)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+        peek(Box::new(decoder), TAR_MAGIC.end)?
+    } else {
+        (head, reader)
+    };
+    if head.get(TAR_MAGIC) == Some(&b"ustar"[..]) {
+        return in_archive(reader);
+    }
+    Ok(reader)
+}
+
+/// Read up to `n` bytes of `reader`, returning them and a reader that reads them
+/// again.
+fn peek<'r>(
+    mut reader: Box<dyn Read + 'r>,
+    n: usize,
+) -> std::io::Result<(Vec<u8>, Box<dyn Read + 'r>)> {
+    let mut head = Vec::with_capacity(n);
+    (&mut reader).take(n as u64).read_to_end(&mut head)?;
+    Ok((head.clone(), Box::new(Cursor::new(head).chain(reader))))
+}
+
+fn in_archive<'r>(reader: Box<dyn Read + 'r>) -> std::io::Result<Box<dyn Read + 'r>> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.to_string_lossy().trim_start_matches("./") == ARCHIVE_BUNDLE_PATH {
+            let mut json = Vec::new();
+            entry.read_to_end(&mut json)?;
+            return Ok(Box::new(Cursor::new(json)));
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("the archive holds no {}", ARCHIVE_BUNDLE_PATH),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+
+    use crate::cnab::{Bundle, BundleParseError};
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    fn gzip(content: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(content).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn tarball(name: &str, content: &[u8]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, name, content).unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_compressed_bundles() {
+        let json = std::fs::read("testdata/bundle.json").unwrap();
+        let golden = Bundle::from_json(&json[..]).unwrap().to_canonical_json();
+        let zstd = ruzstd::encoding::compress_to_vec(
+            &json[..],
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+        let inputs = vec![
+            ("gzip", gzip(&json)),
+            ("zstd", zstd),
+            ("tar", tarball("./bundle.json", &json)),
+            ("tgz", gzip(&tarball("bundle.json", &json))),
+        ];
+        for (kind, input) in inputs {
+            let bundle =
+                Bundle::from_json(&input[..]).unwrap_or_else(|e| panic!("{}: {}", kind, e));
+            assert_eq!(bundle.to_canonical_json(), golden, "{}", kind);
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bundle.tgz");
+        std::fs::write(&path, gzip(&tarball("bundle.json", &json))).unwrap();
+        let bundle = Bundle::from_file(&path);
+        assert_eq!(bundle.unwrap().name, "helloworld");
+
+        match Bundle::from_json(&gzip(&tarball("README.md", b"hello"))[..]) {
+            Err(BundleParseError::IoError(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::NotFound)
+            }
+            other => panic!(
+                "expected a missing bundle.json, got {:?}",
+                other.map(|b| b.name)
+            ),
+        }
+    }
+}
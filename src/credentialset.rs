@@ -1,23 +1,218 @@
-use serde::{Serialize, Deserialize};
+use crate::error_code::{Coded, ErrorCode};
+use crate::secrets::{SecretRef, SecretResolver};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The service name under which credentials are looked up in the OS secret store.
+///
+/// A credential source of `keychain: "kubeconfig"` resolves to the secret stored for
+/// account `kubeconfig` under this service.
+pub const KEYCHAIN_SERVICE: &str = "cnab";
+
 /// CredentialSet implements section 802 of the CNAB specification at the time CNAB Core 1.0 was finalized.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct CredentialSet {
     pub name: String,
     pub credentials: Vec<Credential>,
 }
-#[derive(Debug, Serialize, Deserialize)]
+
+impl CredentialSet {
+    /// Resolve every credential in the set, returning the values keyed by credential name.
+    ///
+    /// Secret lookups use the default `SecretResolver`.
+    pub fn resolve(&self) -> Result<BTreeMap<String, String>, ResolveError> {
+        self.resolve_with(&SecretResolver::default())
+    }
+
+    /// Resolve every credential in the set, looking up secrets with the given resolver.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(set = %self.name), err)
+    )]
+    pub fn resolve_with(
+        &self,
+        secrets: &SecretResolver,
+    ) -> Result<BTreeMap<String, String>, ResolveError> {
+        self.credentials
+            .iter()
+            .map(|c| Ok((c.name.clone(), c.source.resolve(&c.name, secrets)?)))
+            .collect()
+    }
+
+    /// A set of literal values, such as credentials already resolved for another
+    /// installation.
+    pub fn from_values(name: &str, values: &BTreeMap<String, String>) -> Self {
+        CredentialSet {
+            name: name.to_string(),
+            credentials: values
+                .iter()
+                .map(|(name, value)| Credential {
+                    name: name.clone(),
+                    source: CredentialSource::from_value(value),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Credential {
     name: String,
     source: CredentialSource,
 }
-#[derive(Debug, Serialize, Deserialize)]
+
+/// CredentialSource describes where the value of a credential comes from.
+///
+/// Sources are tried in the order value, env, path, keychain, vault, secret. The first one that
+/// yields a value wins, so an unset environment variable may fall back to a file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct CredentialSource {
     value: Option<String>,
     env: Option<String>,
     path: Option<std::path::PathBuf>,
+    /// The name of an entry in the OS secret store (see `KEYCHAIN_SERVICE`)
+    keychain: Option<String>,
+    /// A key within a HashiCorp Vault KV secret
+    vault: Option<VaultSource>,
+    /// A secret held by a source registered on the `SecretResolver`
+    secret: Option<SecretRef>,
+}
+
+/// VaultSource addresses a single key of a secret stored in a Vault KV engine.
+///
+/// Both KV version 1 and version 2 paths are supported; for version 2 the path
+/// includes the `data/` segment (e.g. `secret/data/myapp`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct VaultSource {
+    /// The path of the secret, relative to `/v1/`
+    pub path: String,
+    /// The key within the secret whose value is used
+    pub key: String,
+}
+
+impl CredentialSource {
+    pub(crate) fn from_value(value: &str) -> Self {
+        CredentialSource {
+            value: Some(value.to_string()),
+            env: None,
+            path: None,
+            keychain: None,
+            vault: None,
+            secret: None,
+        }
+    }
+
+    pub(crate) fn resolve(
+        &self,
+        name: &str,
+        secrets: &SecretResolver,
+    ) -> Result<String, ResolveError> {
+        if let Some(value) = &self.value {
+            return Ok(value.clone());
+        }
+        if let Some(value) = self.env.as_ref().and_then(|e| std::env::var(e).ok()) {
+            return Ok(value);
+        }
+        if let Some(path) = &self.path {
+            let has_fallback =
+                self.keychain.is_some() || self.vault.is_some() || self.secret.is_some();
+            if path.exists() || !has_fallback {
+                return Ok(std::fs::read_to_string(path)?);
+            }
+        }
+        if let Some(entry) = &self.keychain {
+            return secrets.get("keychain", entry);
+        }
+        if let Some(source) = &self.vault {
+            return secrets.get("vault", &format!("{}#{}", source.path, source.key));
+        }
+        if let Some(secret) = &self.secret {
+            return secrets.get(&secret.source, &secret.key);
+        }
+        Err(ResolveError::Unresolved(name.to_string()))
+    }
+}
+
+/// Represents an error resolving the value of a credential or parameter
+#[derive(Debug)]
+pub enum ResolveError {
+    /// None of the sources declared for the named value produced anything
+    Unresolved(String),
+    IoError(std::io::Error),
+    /// Bundle data consulted during resolution could not be parsed
+    SerdeJSONError(serde_json::Error),
+    /// No secret source is registered under this name
+    UnknownSecretSource(String),
+    /// A secret source failed to produce the requested secret
+    SecretError {
+        source: String,
+        message: String,
+    },
+}
+
+impl ResolveError {
+    /// Build a `SecretError`, for use by `SecretSource` implementations.
+    pub fn secret<E: fmt::Display>(source: &str, key: &str, error: E) -> Self {
+        ResolveError::SecretError {
+            source: source.to_string(),
+            message: format!("{}: {}", key, error),
+        }
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::Unresolved(name) => {
+                write!(f, "no source produced a value for {}", name)
+            }
+            ResolveError::IoError(e) => write!(f, "reading source file: {}", e),
+            ResolveError::SerdeJSONError(e) => write!(f, "parsing bundle data: {}", e),
+            ResolveError::UnknownSecretSource(name) => write!(
+                f,
+                "no secret source named {} is registered (is the `{}` feature enabled?)",
+                name, name
+            ),
+            ResolveError::SecretError { source, message } => {
+                write!(f, "{} lookup failed: {}", source, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+impl Coded for ResolveError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ResolveError::Unresolved(_) => ErrorCode::ResolveUnresolved,
+            ResolveError::IoError(_) => ErrorCode::Io,
+            ResolveError::SerdeJSONError(_) => ErrorCode::ResolveInvalidJson,
+            ResolveError::UnknownSecretSource(_) => ErrorCode::ResolveUnknownSecretSource,
+            ResolveError::SecretError { .. } => ErrorCode::ResolveSecretFailed,
+        }
+    }
+}
+
+impl From<std::io::Error> for ResolveError {
+    fn from(error: std::io::Error) -> Self {
+        ResolveError::IoError(error)
+    }
+}
+
+impl From<serde_json::Error> for ResolveError {
+    fn from(error: serde_json::Error) -> Self {
+        ResolveError::SerdeJSONError(error)
+    }
 }
 
 #[cfg(test)]
@@ -47,9 +242,80 @@ mod test {
                             "env": "HOSTKEY",
                             "path": "$HOME/.thing/hostkey"
                         }
+                    },
+                    {
+                        "name": "registry_password",
+                        "source": {
+                            "keychain": "registry.example.com"
+                        }
+                    },
+                    {
+                        "name": "database_password",
+                        "source": {
+                            "vault": {
+                                "path": "secret/data/myapp",
+                                "key": "password"
+                            }
+                        }
+                    },
+                    {
+                        "name": "api_key",
+                        "source": {
+                            "secret": {
+                                "source": "aws",
+                                "key": "prod/api#key"
+                            }
+                        }
+                    }
+                ]
+            }"#,
+        )
+        .expect("credential set parsed");
+    }
+
+    #[test]
+    fn test_credentialset_resolve() {
+        std::env::set_var("LIBCNAB_TEST_RESOLVE_TOKEN", "from-env");
+        let set: CredentialSet = serde_json::from_str(
+            r#"{
+                "name": "test_credentials",
+                "credentials": [
+                    {
+                        "name": "literal",
+                        "source": { "value": "1234" }
+                    },
+                    {
+                        "name": "token",
+                        "source": { "env": "LIBCNAB_TEST_RESOLVE_TOKEN" }
+                    }
+                ]
+            }"#,
+        )
+        .expect("credential set parsed");
+
+        let values = set.resolve().expect("credentials resolved");
+        assert_eq!(values["literal"], "1234");
+        assert_eq!(values["token"], "from-env");
+    }
+
+    #[test]
+    fn test_credentialset_unresolved() {
+        let set: CredentialSet = serde_json::from_str(
+            r#"{
+                "name": "test_credentials",
+                "credentials": [
+                    {
+                        "name": "missing",
+                        "source": { "env": "LIBCNAB_TEST_NO_SUCH_VARIABLE" }
                     }
                 ]
-            }"#
-        ).expect("credential set parsed");
+            }"#,
+        )
+        .expect("credential set parsed");
+
+        match set.resolve() {
+            Err(ResolveError::Unresolved(name)) => assert_eq!(name, "missing"),
+            other => panic!("expected unresolved credential, got {:?}", other),
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,386 @@
+use crate::cnab::Bundle;
+use crate::error_code::{Coded, ErrorCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use serde_path_to_error::{Path, Segment};
+use std::fmt;
+
+mod namespace;
+pub(crate) use self::namespace::merge;
+pub use self::namespace::*;
+
+impl Bundle {
+    /// Deserialize the `custom` section under `key` into `T`, if present.
+    ///
+    /// An invalid section is reported with the path of the offending value within it.
+    ///
+    /// ```
+    /// use libcnab::Bundle;
+    /// use serde::{Deserialize, Serialize};
+    ///
+    /// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    /// struct Team {
+    ///     name: String,
+    /// }
+    ///
+    /// let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+    /// let team = Team { name: "platform".to_string() };
+    /// bundle.set_custom_extension("com.example.team", &team).unwrap();
+    /// assert_eq!(bundle.custom_extension::<Team>("com.example.team").unwrap(), Some(team));
+    /// ```
+    pub fn custom_extension<T: DeserializeOwned>(
+        &self,
+        key: &str,
+    ) -> Result<Option<T>, CustomExtensionError> {
+        let value = match self.custom.as_ref().and_then(|c| c.get(key)) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        deserialize(key, value).map(Some)
+    }
+
+    /// Deserialize the `custom` section under `key` into `T`, if present, leaving out
+    /// the values that do not match `T`, such as a field of the wrong type or an
+    /// invalid element of a list.
+    ///
+    /// Each value left out is removed from the section, or, when `T` requires it,
+    /// the closest value around it that `T` does without. Only a section that does
+    /// not match `T` however much is left out is an error.
+    ///
+    /// ```
+    /// use libcnab::Bundle;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Debug, Deserialize)]
+    /// struct Team {
+    ///     name: String,
+    ///     #[serde(default)]
+    ///     size: Option<u32>,
+    /// }
+    ///
+    /// let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+    /// let team = serde_json::json!({ "name": "platform", "size": "large" });
+    /// bundle.set_custom_extension("com.example.team", &team).unwrap();
+    /// let team = bundle
+    ///     .custom_extension_lenient::<Team>("com.example.team")
+    ///     .unwrap()
+    ///     .unwrap();
+    /// assert_eq!((team.value.name.as_str(), team.value.size), ("platform", None));
+    /// assert_eq!(team.skipped[0].expected().as_deref(), Some("u32"));
+    /// ```
+    pub fn custom_extension_lenient<T: DeserializeOwned>(
+        &self,
+        key: &str,
+    ) -> Result<Option<Lenient<T>>, CustomExtensionError> {
+        let value = match self.custom.as_ref().and_then(|c| c.get(key)) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        deserialize_lenient(key, value).map(Some)
+    }
+
+    /// Serialize `value` into the `custom` section under `key`, replacing what was
+    /// there.
+    pub fn set_custom_extension<T: Serialize + ?Sized>(
+        &mut self,
+        key: &str,
+        value: &T,
+    ) -> Result<(), CustomExtensionError> {
+        let value =
+            serde_json::to_value(value).map_err(|error| CustomExtensionError::Unserializable {
+                key: key.to_string(),
+                error,
+            })?;
+        self.custom
+            .get_or_insert_with(Default::default)
+            .insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+/// Lenient is a `custom` section read with `Bundle::custom_extension_lenient`.
+#[derive(Debug)]
+pub struct Lenient<T> {
+    /// The section, without the values left out
+    pub value: T,
+    /// Why each value was left out, in the order they were found
+    pub skipped: Vec<CustomExtensionError>,
+}
+
+/// Deserialize the `custom` section `value` under `key`, locating what is invalid.
+pub(crate) fn deserialize<T: DeserializeOwned>(
+    key: &str,
+    value: &Value,
+) -> Result<T, CustomExtensionError> {
+    serde_path_to_error::deserialize(value).map_err(|e| invalid(key, e))
+}
+
+/// Deserialize the `custom` section `value` under `key`, leaving out what is
+/// invalid.
+pub(crate) fn deserialize_lenient<T: DeserializeOwned>(
+    key: &str,
+    value: &Value,
+) -> Result<Lenient<T>, CustomExtensionError> {
+    let mut value = value.clone();
+    let mut skipped = Vec::new();
+    loop {
+        match serde_path_to_error::deserialize(&value) {
+            Ok(parsed) => {
+                return Ok(Lenient {
+                    value: parsed,
+                    skipped,
+                })
+            }
+            // Every value removed makes the section smaller, so this ends.
+            Err(e) => match remove(&mut value, e.path()) {
+                Some(_) => skipped.push(invalid(key, e)),
+                None => return Err(invalid(key, e)),
+            },
+        }
+    }
+}
+
+fn invalid(key: &str, e: serde_path_to_error::Error<serde_json::Error>) -> CustomExtensionError {
+    let path = e.path().to_string();
+    CustomExtensionError::Invalid {
+        key: key.to_string(),
+        path: if path == "." { None } else { Some(path) },
+        error: e.into_inner(),
+    }
+}
+
+/// Remove the value at `path` from `value`. There is nothing to remove at the top,
+/// nor at paths that are not made of the keys of objects and indexes of arrays.
+fn remove(value: &mut Value, path: &Path) -> Option<Value> {
+    let segments: Vec<&Segment> = path.iter().collect();
+    let (last, parents) = segments.split_last()?;
+    let mut parent = value;
+    for segment in parents {
+        parent = match (segment, parent) {
+            (Segment::Map { key }, Value::Object(map)) => map.get_mut(key)?,
+            (Segment::Seq { index }, Value::Array(values)) => values.get_mut(*index)?,
+            _ => return None,
+        };
+    }
+    match (last, parent) {
+        (Segment::Map { key }, Value::Object(map)) => map.remove(key),
+        (Segment::Seq { index }, Value::Array(values)) if *index < values.len() => {
+            Some(values.remove(*index))
+        }
+        _ => None,
+    }
+}
+
+/// CustomExtensionError describes a `custom` section that could not be read or
+/// written as the requested type.
+#[derive(Debug)]
+pub enum CustomExtensionError {
+    /// The section under `key` does not match the type. `path` locates the offending
+    /// value within the section, such as `requires.mysql.version`, unless it is the
+    /// section itself.
+    Invalid {
+        key: String,
+        path: Option<String>,
+        error: serde_json::Error,
+    },
+    /// The value for `key` could not be serialized
+    Unserializable {
+        key: String,
+        error: serde_json::Error,
+    },
+    /// Merging into the section under `key` would change the value at `path`, or the
+    /// section itself when `path` is `None`
+    Conflict { key: String, path: Option<String> },
+}
+
+impl CustomExtensionError {
+    /// What the type asked for where the section is invalid, such as `u32` or `a
+    /// sequence`, when the error says.
+    pub fn expected(&self) -> Option<String> {
+        match self {
+            CustomExtensionError::Invalid { error, .. } => error
+                .to_string()
+                .split_once(", expected ")
+                .map(|(_, expected)| expected.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CustomExtensionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CustomExtensionError::Invalid {
+                key,
+                path: Some(path),
+                error,
+            } => write!(f, "invalid custom extension {} at {}: {}", key, path, error),
+            CustomExtensionError::Invalid {
+                key,
+                path: None,
+                error,
+            } => write!(f, "invalid custom extension {}: {}", key, error),
+            CustomExtensionError::Unserializable { key, error } => {
+                write!(f, "serializing custom extension {}: {}", key, error)
+            }
+            CustomExtensionError::Conflict {
+                key,
+                path: Some(path),
+            } => write!(
+                f,
+                "custom extension {} already has another value at {}",
+                key, path
+            ),
+            CustomExtensionError::Conflict { key, path: None } => {
+                write!(f, "custom extension {} already has another value", key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CustomExtensionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CustomExtensionError::Invalid { error, .. }
+            | CustomExtensionError::Unserializable { error, .. } => Some(error),
+            CustomExtensionError::Conflict { .. } => None,
+        }
+    }
+}
+
+impl Coded for CustomExtensionError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            CustomExtensionError::Invalid { .. } => ErrorCode::CustomInvalid,
+            CustomExtensionError::Unserializable { .. } => ErrorCode::CustomUnserializable,
+            CustomExtensionError::Conflict { .. } => ErrorCode::CustomConflict,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dependencies::{Dependencies, DEPENDENCIES_KEY};
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_custom_extension() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        assert!(bundle
+            .custom_extension::<Dependencies>(DEPENDENCIES_KEY)
+            .unwrap()
+            .is_none());
+
+        bundle
+            .set_custom_extension(
+                DEPENDENCIES_KEY,
+                &serde_json::json!({
+                    "requires": {
+                        "mysql": {
+                            "bundle": "example.com/bundles/mysql",
+                            "version": { "ranges": "5.7.x" }
+                        }
+                    }
+                }),
+            )
+            .unwrap();
+        let err = bundle
+            .custom_extension::<Dependencies>(DEPENDENCIES_KEY)
+            .unwrap_err();
+        match &err {
+            CustomExtensionError::Invalid { key, path, .. } => {
+                assert_eq!(key, DEPENDENCIES_KEY);
+                assert_eq!(path.as_deref(), Some("requires.mysql.version.ranges"));
+            }
+            other => panic!("expected an invalid extension, got {:?}", other),
+        }
+        assert!(err
+            .to_string()
+            .starts_with("invalid custom extension io.cnab.dependencies at requires.mysql.version.ranges: invalid type"));
+
+        let err = bundle
+            .custom_extension::<u32>(DEPENDENCIES_KEY)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            CustomExtensionError::Invalid { path: None, .. }
+        ));
+        assert_eq!(err.expected().as_deref(), Some("u32"));
+
+        let mut unserializable = BTreeMap::new();
+        unserializable.insert(vec![1u8], "keys must be strings");
+        assert!(matches!(
+            bundle.set_custom_extension("com.example.broken", &unserializable),
+            Err(CustomExtensionError::Unserializable { .. })
+        ));
+        assert!(bundle
+            .custom
+            .as_ref()
+            .unwrap()
+            .get("com.example.broken")
+            .is_none());
+    }
+
+    #[test]
+    fn test_custom_extension_lenient() {
+        #[derive(Debug, Deserialize)]
+        struct Owner {
+            email: String,
+        }
+        #[derive(Debug, Deserialize)]
+        struct Team {
+            name: String,
+            #[serde(default)]
+            tags: Vec<String>,
+            #[serde(default)]
+            owners: BTreeMap<String, Owner>,
+        }
+
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let team = serde_json::json!({
+            "name": "platform",
+            "tags": ["infra", 3, "oncall"],
+            "owners": { "ana": { "email": 7 }, "bo": { "email": "bo@example.com" } }
+        });
+        bundle
+            .set_custom_extension("com.example.team", &team)
+            .unwrap();
+        assert!(bundle.custom_extension::<Team>("com.example.team").is_err());
+
+        let team = bundle
+            .custom_extension_lenient::<Team>("com.example.team")
+            .unwrap()
+            .unwrap();
+        assert_eq!(team.value.name, "platform");
+        assert_eq!(team.value.tags, vec!["infra", "oncall"]);
+        // Without its email, the owner is left out too.
+        assert_eq!(team.value.owners.len(), 1);
+        assert_eq!(team.value.owners["bo"].email, "bo@example.com");
+        let skipped: Vec<_> = team
+            .skipped
+            .iter()
+            .map(|e| match e {
+                CustomExtensionError::Invalid { path, .. } => path.clone().unwrap(),
+                other => panic!("expected an invalid extension, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(skipped, vec!["owners.ana.email", "owners.ana", "tags[1]"]);
+        assert_eq!(team.skipped[0].expected().as_deref(), Some("a string"));
+
+        // The section cannot do without its name.
+        bundle
+            .set_custom_extension("com.example.team", &serde_json::json!({ "name": 1 }))
+            .unwrap();
+        let err = bundle
+            .custom_extension_lenient::<Team>("com.example.team")
+            .unwrap_err();
+        assert!(err.to_string().contains("missing field `name`"), "{}", err);
+        assert!(bundle
+            .custom_extension_lenient::<Team>("com.example.missing")
+            .unwrap()
+            .is_none());
+    }
+}
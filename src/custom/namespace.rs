@@ -0,0 +1,273 @@
+use super::{deserialize, CustomExtensionError};
+use crate::cnab::Bundle;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// CustomNamespace reads the `custom` sections a tool keeps under its namespace,
+/// such as `sh.porter` or `io.cnab`: the section named after the namespace itself,
+/// and those named `<namespace>.<name>`.
+///
+/// The namespace's own section has the empty name.
+#[derive(Debug, Clone, Copy)]
+pub struct CustomNamespace<'b> {
+    namespace: &'b str,
+    custom: Option<&'b BTreeMap<String, Value>>,
+}
+
+/// CustomNamespaceMut writes the `custom` sections under a namespace, leaving the
+/// sections of other tools alone.
+#[derive(Debug)]
+pub struct CustomNamespaceMut<'b> {
+    namespace: &'b str,
+    custom: &'b mut BTreeMap<String, Value>,
+}
+
+impl Bundle {
+    /// The `custom` sections under `namespace`.
+    ///
+    /// ```
+    /// use libcnab::Bundle;
+    ///
+    /// let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+    /// bundle.custom_ns_mut("sh.porter").set("manifest", "cG9ydGVyLnlhbWw=").unwrap();
+    /// let porter = bundle.custom_ns("sh.porter");
+    /// assert_eq!(porter.names(), vec!["manifest"]);
+    /// assert_eq!(porter.get::<String>("manifest").unwrap().unwrap(), "cG9ydGVyLnlhbWw=");
+    /// ```
+    pub fn custom_ns<'b>(&'b self, namespace: &'b str) -> CustomNamespace<'b> {
+        CustomNamespace {
+            namespace,
+            custom: self.custom.as_ref(),
+        }
+    }
+
+    /// Write the `custom` sections under `namespace`.
+    pub fn custom_ns_mut<'b>(&'b mut self, namespace: &'b str) -> CustomNamespaceMut<'b> {
+        CustomNamespaceMut {
+            namespace,
+            custom: self.custom.get_or_insert_with(Default::default),
+        }
+    }
+}
+
+/// The key of the section `name` in `namespace`.
+fn key(namespace: &str, name: &str) -> String {
+    if name.is_empty() {
+        namespace.to_string()
+    } else {
+        format!("{}.{}", namespace, name)
+    }
+}
+
+/// The name within `namespace` of the section `key`, if it is in the namespace.
+fn name<'k>(namespace: &str, key: &'k str) -> Option<&'k str> {
+    if key == namespace {
+        return Some("");
+    }
+    key.strip_prefix(namespace)?.strip_prefix('.')
+}
+
+impl<'b> CustomNamespace<'b> {
+    /// Deserialize the section `name` into `T`, if present.
+    pub fn get<T: DeserializeOwned>(&self, name: &str) -> Result<Option<T>, CustomExtensionError> {
+        let key = key(self.namespace, name);
+        match self.custom.and_then(|c| c.get(&key)) {
+            Some(value) => deserialize(&key, value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.custom
+            .map(|c| c.contains_key(&key(self.namespace, name)))
+            .unwrap_or(false)
+    }
+
+    /// The names of the sections in the namespace, in sorted order.
+    pub fn names(&self) -> Vec<&'b str> {
+        self.custom
+            .into_iter()
+            .flatten()
+            .filter_map(|(key, _)| name(self.namespace, key))
+            .collect()
+    }
+
+    /// The sections in the namespace, keyed by name.
+    pub fn entries(&self) -> BTreeMap<&'b str, &'b Value> {
+        self.custom
+            .into_iter()
+            .flatten()
+            .filter_map(|(key, value)| Some((name(self.namespace, key)?, value)))
+            .collect()
+    }
+}
+
+impl CustomNamespaceMut<'_> {
+    /// Serialize `value` into the section `name`, replacing what was there.
+    pub fn set<T: Serialize + ?Sized>(
+        &mut self,
+        name: &str,
+        value: &T,
+    ) -> Result<(), CustomExtensionError> {
+        let key = key(self.namespace, name);
+        let value = to_value(&key, value)?;
+        self.custom.insert(key, value);
+        Ok(())
+    }
+
+    /// Merge `value` into the section `name`.
+    ///
+    /// Objects are merged key by key, at every depth, so that tools can each add
+    /// their own keys to a shared section. Anything else must be equal to what is
+    /// there already: a different value is a conflict, and leaves the section as it
+    /// was.
+    pub fn merge<T: Serialize + ?Sized>(
+        &mut self,
+        name: &str,
+        value: &T,
+    ) -> Result<(), CustomExtensionError> {
+        let key = key(self.namespace, name);
+        let value = to_value(&key, value)?;
+        match self.custom.get(&key) {
+            Some(existing) => {
+                let mut merged = existing.clone();
+                merge(&mut merged, value, &mut Vec::new()).map_err(|path| {
+                    CustomExtensionError::Conflict {
+                        key: key.clone(),
+                        path,
+                    }
+                })?;
+                self.custom.insert(key, merged);
+            }
+            None => {
+                self.custom.insert(key, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the section `name`, returning it if it was there.
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        self.custom.remove(&key(self.namespace, name))
+    }
+
+    /// Remove every section in the namespace.
+    pub fn clear(&mut self) {
+        let namespace = self.namespace;
+        self.custom.retain(|key, _| name(namespace, key).is_none());
+    }
+}
+
+fn to_value<T: Serialize + ?Sized>(key: &str, value: &T) -> Result<Value, CustomExtensionError> {
+    serde_json::to_value(value).map_err(|error| CustomExtensionError::Unserializable {
+        key: key.to_string(),
+        error,
+    })
+}
+
+/// Merge `value` into `into`, or fail with the path of the first conflict.
+pub(crate) fn merge(
+    into: &mut Value,
+    value: Value,
+    path: &mut Vec<String>,
+) -> Result<(), Option<String>> {
+    match (into, value) {
+        (Value::Object(into), Value::Object(value)) => {
+            for (field, value) in value {
+                match into.get_mut(&field) {
+                    Some(existing) => {
+                        path.push(field);
+                        merge(existing, value, path)?;
+                        path.pop();
+                    }
+                    None => {
+                        into.insert(field, value);
+                    }
+                }
+            }
+            Ok(())
+        }
+        (into, value) if *into == value => Ok(()),
+        _ => Err(if path.is_empty() {
+            None
+        } else {
+            Some(path.join("."))
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_custom_ns() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let mut porter = bundle.custom_ns_mut("sh.porter");
+        porter
+            .set("", &serde_json::json!({ "manifestDigest": "sha256:abc" }))
+            .unwrap();
+        porter
+            .merge(
+                "mixins",
+                &serde_json::json!({ "exec": { "version": "1.0" } }),
+            )
+            .unwrap();
+        porter
+            .merge(
+                "mixins",
+                &serde_json::json!({ "helm3": { "version": "2.0" } }),
+            )
+            .unwrap();
+        porter
+            .merge(
+                "mixins",
+                &serde_json::json!({ "exec": { "version": "1.0" } }),
+            )
+            .unwrap();
+        match porter.merge(
+            "mixins",
+            &serde_json::json!({ "exec": { "version": "1.1" } }),
+        ) {
+            Err(CustomExtensionError::Conflict { key, path }) => {
+                assert_eq!(key, "sh.porter.mixins");
+                assert_eq!(path.as_deref(), Some("exec.version"));
+            }
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+        assert!(matches!(
+            porter.merge("", "replaced"),
+            Err(CustomExtensionError::Conflict { path: None, .. })
+        ));
+        bundle
+            .custom_ns_mut("sh.porterish")
+            .set("other", &1)
+            .unwrap();
+
+        let porter = bundle.custom_ns("sh.porter");
+        assert_eq!(porter.names(), vec!["", "mixins"]);
+        assert!(porter.contains("mixins"));
+        assert_eq!(
+            porter.get::<serde_json::Value>("mixins").unwrap().unwrap(),
+            serde_json::json!({ "exec": { "version": "1.0" }, "helm3": { "version": "2.0" } })
+        );
+        assert_eq!(
+            bundle.custom_ns("com.example").names(),
+            vec!["backup-preferences", "duffle-bag"]
+        );
+        assert!(bundle.custom_ns("io.cnab").entries().is_empty());
+
+        bundle.custom_ns_mut("sh.porter").clear();
+        let custom = bundle.custom.as_ref().unwrap();
+        assert_eq!(
+            custom.keys().collect::<Vec<_>>(),
+            vec![
+                "com.example.backup-preferences",
+                "com.example.duffle-bag",
+                "sh.porterish.other"
+            ]
+        );
+    }
+}
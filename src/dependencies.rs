@@ -0,0 +1,172 @@
+use crate::cnab::Bundle;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+mod resolve;
+pub use self::resolve::*;
+
+/// The custom key under which the dependencies extension is stored
+pub const DEPENDENCIES_KEY: &str = "io.cnab.dependencies";
+
+/// Dependencies implements the `io.cnab.dependencies` extension: the other bundles a
+/// bundle needs installed before it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Dependencies {
+    /// The order in which the dependencies are installed, by name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sequence: Vec<String>,
+    /// The dependencies, keyed by name
+    #[serde(default)]
+    pub requires: BTreeMap<String, Dependency>,
+}
+
+/// Dependency is another bundle a bundle requires.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Dependency {
+    /// The repository of the bundle, such as `example.com/bundles/mysql`, or a
+    /// reference to one version of it
+    pub bundle: String,
+    /// The versions of the bundle that are acceptable. Any version is when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<DependencyVersion>,
+    /// The parameters of the dependency set from those of this bundle: each
+    /// parameter of the dependency mapped to the parameter of this bundle whose value
+    /// it takes
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub parameters: BTreeMap<String, String>,
+    /// The credentials of the dependency set from this bundle's, in the same way
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub credentials: BTreeMap<String, String>,
+}
+
+/// DependencyVersion describes the acceptable versions of a dependency.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyVersion {
+    /// Whether pre-release versions are acceptable
+    #[serde(default)]
+    pub prereleases: bool,
+    /// Semver ranges, such as `5.7.x` or `>=1.2, <2`, any of which a version may match
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ranges: Vec<String>,
+}
+
+impl DependencyVersion {
+    /// Whether `version` is acceptable. A pre-release is when pre-releases are, and
+    /// its release matches one of the ranges.
+    pub fn matches(&self, version: &Version) -> Result<bool, semver::ReqParseError> {
+        let mut version = version.clone();
+        if version.is_prerelease() {
+            if !self.prereleases {
+                return Ok(false);
+            }
+            version.pre.clear();
+        }
+        if self.ranges.is_empty() {
+            return Ok(true);
+        }
+        for range in &self.ranges {
+            if VersionReq::parse(range)?.matches(&version) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl Dependencies {
+    /// The dependencies in the order they are installed: those in `sequence` first,
+    /// and then the rest by name. Names in `sequence` that are not required are
+    /// skipped.
+    pub fn in_sequence(&self) -> Vec<(&str, &Dependency)> {
+        let sequenced = self
+            .sequence
+            .iter()
+            .filter_map(|name| self.requires.get_key_value(name));
+        let rest = self
+            .requires
+            .iter()
+            .filter(|(name, _)| !self.sequence.contains(name));
+        sequenced
+            .chain(rest)
+            .map(|(name, dependency)| (name.as_str(), dependency))
+            .collect()
+    }
+}
+
+impl Bundle {
+    /// Parse the dependencies extension from `custom`, if present.
+    pub fn dependencies(&self) -> Result<Option<Dependencies>, serde_json::Error> {
+        match self.custom.as_ref().and_then(|c| c.get(DEPENDENCIES_KEY)) {
+            Some(value) => serde_json::from_value(value.clone()).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dependencies() {
+        let bundle: Bundle = r#"{
+            "name": "wordpress",
+            "invocationImages": [],
+            "schemaVersion": "1.0.0",
+            "version": "1.0.0",
+            "requiredExtensions": ["io.cnab.dependencies"],
+            "custom": {
+                "io.cnab.dependencies": {
+                    "sequence": ["mysql"],
+                    "requires": {
+                        "storage": {
+                            "bundle": "example.com/bundles/blob-storage"
+                        },
+                        "mysql": {
+                            "bundle": "example.com/bundles/mysql",
+                            "version": { "prereleases": true, "ranges": ["5.7.x", ">=8, <9"] },
+                            "parameters": { "database": "wordpress-db" }
+                        }
+                    }
+                }
+            }
+        }"#
+        .parse()
+        .unwrap();
+
+        let dependencies = bundle.dependencies().unwrap().expect("dependencies");
+        let names: Vec<_> = dependencies
+            .in_sequence()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, vec!["mysql", "storage"]);
+        let mysql = &dependencies.requires["mysql"];
+        assert_eq!(mysql.bundle, "example.com/bundles/mysql");
+        assert_eq!(mysql.parameters["database"], "wordpress-db");
+        let version = mysql.version.as_ref().unwrap();
+        let matches = |v: &str| version.matches(&Version::parse(v).unwrap()).unwrap();
+        assert!(matches("5.7.21"));
+        assert!(matches("8.0.1"));
+        assert!(matches("8.1.0-rc.1"));
+        assert!(!matches("5.6.0"));
+        assert!(!matches("9.0.0"));
+        assert!(!DependencyVersion {
+            prereleases: false,
+            ranges: Vec::new(),
+        }
+        .matches(&Version::parse("1.0.0-beta").unwrap())
+        .unwrap());
+        assert!(dependencies.requires["storage"].version.is_none());
+
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        assert_eq!(bundle.dependencies().unwrap(), None);
+    }
+}
@@ -0,0 +1,812 @@
+use super::{Dependencies, DependencyVersion, DEPENDENCIES_KEY};
+use crate::claim::Claim;
+use crate::cnab::Bundle;
+use crate::error_code::{Coded, ErrorCode};
+use crate::reference::{BundleReference, InvalidReference};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+
+/// How many times the resolver reconsiders its choices when a version it picked for
+/// one dependency turns out not to suit another that requires the same bundle
+const MAX_PASSES: usize = 16;
+
+/// BundleSource is where a `DependencyResolver` finds the bundles dependencies name,
+/// such as a registry `Client`.
+pub trait BundleSource {
+    /// The versions of the bundle in `repository`, a reference without tag or digest.
+    fn versions(&self, repository: &str) -> Result<Vec<AvailableVersion>, DependencyError>;
+
+    /// Fetch the bundle `reference` points at.
+    fn fetch(&self, reference: &str) -> Result<Bundle, DependencyError>;
+}
+
+/// AvailableVersion is a version of a bundle a `BundleSource` has.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AvailableVersion {
+    /// The `version` of the bundle
+    pub version: String,
+    /// Where to fetch this version from, ideally by digest
+    pub reference: String,
+}
+
+/// MemoryBundleSource holds bundles in memory, useful for tests and for bundles
+/// that were pulled ahead of time. Each version of a repository is fetched as
+/// `<repository>:<version>`.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBundleSource {
+    bundles: BTreeMap<String, Vec<Bundle>>,
+}
+
+impl MemoryBundleSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `bundle` as a version of `repository`.
+    pub fn add(&mut self, repository: &str, bundle: Bundle) -> Result<(), InvalidReference> {
+        let repository = BundleReference::parse(repository)?.name();
+        self.bundles.entry(repository).or_default().push(bundle);
+        Ok(())
+    }
+}
+
+impl BundleSource for MemoryBundleSource {
+    fn versions(&self, repository: &str) -> Result<Vec<AvailableVersion>, DependencyError> {
+        let repository = BundleReference::parse(repository)?.name();
+        Ok(self
+            .bundles
+            .get(&repository)
+            .into_iter()
+            .flatten()
+            .map(|bundle| AvailableVersion {
+                version: bundle.version.to_string(),
+                reference: format!("{}:{}", repository, bundle.version),
+            })
+            .collect())
+    }
+
+    fn fetch(&self, reference: &str) -> Result<Bundle, DependencyError> {
+        let parsed = BundleReference::parse(reference)?;
+        self.bundles
+            .get(&parsed.name())
+            .into_iter()
+            .flatten()
+            .find(|bundle| parsed.tag.as_deref() == Some(bundle.version.to_string().as_str()))
+            .cloned()
+            .ok_or_else(|| DependencyError::Source {
+                reference: reference.to_string(),
+                message: "no such bundle".to_string(),
+            })
+    }
+}
+
+/// Repositories are resolved to the newest bundle version that is in range, and
+/// fetched by the digest of its manifest.
+#[cfg(feature = "registry")]
+impl BundleSource for crate::registry::Client {
+    fn versions(&self, repository: &str) -> Result<Vec<AvailableVersion>, DependencyError> {
+        let name = BundleReference::parse(repository)?.name();
+        let versions =
+            self.list_bundle_versions(repository)
+                .map_err(|e| DependencyError::Source {
+                    reference: repository.to_string(),
+                    message: e.to_string(),
+                })?;
+        Ok(versions
+            .into_iter()
+            .map(|v| AvailableVersion {
+                version: v.version,
+                reference: format!("{}@{}", name, v.digest),
+            })
+            .collect())
+    }
+
+    fn fetch(&self, reference: &str) -> Result<Bundle, DependencyError> {
+        self.pull(reference)
+            .map(|pulled| pulled.bundle)
+            .map_err(|e| DependencyError::Source {
+                reference: reference.to_string(),
+                message: e.to_string(),
+            })
+    }
+}
+
+/// InstallPlan is the installations a bundle and its dependencies, and theirs, make
+/// up, in the order they are installed: every installation after those it depends
+/// on, and the bundle's own last.
+#[derive(Debug, Clone)]
+pub struct InstallPlan {
+    pub steps: Vec<InstallStep>,
+}
+
+impl InstallPlan {
+    /// The installation of the bundle the plan was resolved for.
+    pub fn root(&self) -> &InstallStep {
+        self.steps.last().expect("plans have a root")
+    }
+}
+
+/// InstallStep is one installation of an `InstallPlan`.
+#[derive(Debug, Clone)]
+pub struct InstallStep {
+    /// The name of the installation. The installation of a dependency is named after
+    /// the installation that first requires it and the dependency, as
+    /// `<parent>-<dependency>`.
+    pub installation: String,
+    pub bundle: Bundle,
+    /// Where the bundle was fetched from, or `None` for the root
+    pub reference: Option<String>,
+    /// The installations that depend on this one. A bundle several of them require
+    /// is installed once, configured by the first.
+    pub parents: Vec<String>,
+    /// The installations this one depends on, keyed by dependency name
+    pub dependencies: BTreeMap<String, String>,
+    /// The parameters of this installation mapped to the parameter of the first parent
+    /// whose value they take
+    pub parameters: BTreeMap<String, String>,
+    /// The credentials of this installation mapped to the credential of the first
+    /// parent whose value they take
+    pub credentials: BTreeMap<String, String>,
+}
+
+/// DependencyLinks is what the claim of an installation in an `InstallPlan` records,
+/// under `DEPENDENCIES_KEY` in its custom data, about the installations around it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyLinks {
+    /// The installations that depend on this one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parents: Vec<String>,
+    /// The installations this one depends on, keyed by dependency name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, String>,
+}
+
+impl From<&InstallStep> for DependencyLinks {
+    fn from(step: &InstallStep) -> Self {
+        DependencyLinks {
+            parents: step.parents.clone(),
+            dependencies: step.dependencies.clone(),
+        }
+    }
+}
+
+impl Claim {
+    /// The dependency links recorded in the claim, if it was installed as part of an
+    /// `InstallPlan`.
+    pub fn dependency_links(&self) -> Result<Option<DependencyLinks>, serde_json::Error> {
+        match self.custom.as_ref().and_then(|c| c.get(DEPENDENCIES_KEY)) {
+            Some(value) => serde_json::from_value(value.clone()).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// DependencyResolver works out the bundles a bundle depends on, much as a package
+/// manager would.
+///
+/// A dependency names a repository, and is resolved to the newest version there that
+/// is in the ranges of every installation that requires it. A dependency that names
+/// a tag or digest is used as it is. A bundle required by several installations,
+/// through the same repository or reference, is installed once.
+///
+/// ```
+/// use libcnab::{Bundle, DependencyResolver, MemoryBundleSource};
+///
+/// let mut mysql = Bundle::from_file("testdata/bundle.json").unwrap();
+/// mysql.name = "mysql".to_string();
+/// let mut source = MemoryBundleSource::new();
+/// source.add("example.com/bundles/mysql", mysql).unwrap();
+///
+/// let mut wordpress = Bundle::from_file("testdata/bundle.json").unwrap();
+/// wordpress.custom = Some(
+///     vec![(
+///         "io.cnab.dependencies".to_string(),
+///         serde_json::json!({ "requires": { "mysql": { "bundle": "example.com/bundles/mysql" } } }),
+///     )]
+///     .into_iter()
+///     .collect(),
+/// );
+/// let plan = DependencyResolver::new(&source).resolve(&wordpress, "blog").unwrap();
+/// let order: Vec<_> = plan.steps.iter().map(|s| s.installation.as_str()).collect();
+/// assert_eq!(order, vec!["blog-mysql", "blog"]);
+/// ```
+pub struct DependencyResolver<'a> {
+    source: &'a dyn BundleSource,
+}
+
+impl<'a> DependencyResolver<'a> {
+    pub fn new(source: &'a dyn BundleSource) -> Self {
+        DependencyResolver { source }
+    }
+
+    /// Resolve the dependencies of `bundle`, to be installed as `installation`, into
+    /// an install plan.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(bundle = %bundle.name, installation), err)
+    )]
+    pub fn resolve(
+        &self,
+        bundle: &Bundle,
+        installation: &str,
+    ) -> Result<InstallPlan, DependencyError> {
+        let mut fetched = Fetched::default();
+        let mut known: BTreeMap<String, Vec<Requirement>> = BTreeMap::new();
+        for _ in 0..MAX_PASSES {
+            let graph = self.walk(bundle, installation, &known, &mut fetched)?;
+            let mut conflicts = false;
+            for (key, requirements) in &graph.requirements {
+                let node = &graph.nodes[graph.index[key]];
+                let unmet: Vec<&Requirement> = requirements
+                    .iter()
+                    .filter(|r| !r.accepts(&node.version))
+                    .collect();
+                if unmet.is_empty() {
+                    continue;
+                }
+                if node.pinned {
+                    return Err(DependencyError::NoMatchingVersion {
+                        bundle: key.clone(),
+                        requirements: unmet.iter().map(|r| r.to_string()).collect(),
+                    });
+                }
+                known.insert(key.clone(), requirements.clone());
+                conflicts = true;
+            }
+            if !conflicts {
+                return graph.plan();
+            }
+        }
+        Err(DependencyError::Unresolvable(installation.to_string()))
+    }
+
+    /// Find the bundles `bundle` depends on, picking each version with the
+    /// requirements known from previous passes and those met so far in this one.
+    fn walk(
+        &self,
+        bundle: &Bundle,
+        installation: &str,
+        known: &BTreeMap<String, Vec<Requirement>>,
+        fetched: &mut Fetched,
+    ) -> Result<Graph, DependencyError> {
+        let mut graph = Graph {
+            nodes: vec![Node {
+                installation: installation.to_string(),
+                reference: None,
+                pinned: true,
+                version: bundle.version.clone(),
+                bundle: bundle.clone(),
+                parents: Vec::new(),
+                dependencies: Vec::new(),
+                parameters: BTreeMap::new(),
+                credentials: BTreeMap::new(),
+            }],
+            index: BTreeMap::new(),
+            requirements: BTreeMap::new(),
+        };
+        let mut queue = VecDeque::from(vec![0]);
+        while let Some(parent) = queue.pop_front() {
+            let dependencies = declared(&graph.nodes[parent].bundle)?;
+            for (name, dependency) in dependencies.in_sequence() {
+                let (key, pinned) = dependency_key(&dependency.bundle)?;
+                let requirement = Requirement {
+                    requirer: graph.nodes[parent].installation.clone(),
+                    version: dependency.version.clone().unwrap_or_default(),
+                };
+                graph
+                    .requirements
+                    .entry(key.clone())
+                    .or_default()
+                    .push(requirement);
+                let child = match graph.index.get(&key) {
+                    Some(child) => *child,
+                    None => {
+                        let reference = if pinned {
+                            key.clone()
+                        } else {
+                            let mut requirements: Vec<&Requirement> =
+                                known.get(&key).into_iter().flatten().collect();
+                            for requirement in &graph.requirements[&key] {
+                                if !requirements.contains(&requirement) {
+                                    requirements.push(requirement);
+                                }
+                            }
+                            self.choose(&key, &requirements, fetched)?
+                        };
+                        let bundle = fetched.bundle(self.source, &reference)?;
+                        let child = graph.nodes.len();
+                        graph.nodes.push(Node {
+                            installation: format!("{}-{}", graph.nodes[parent].installation, name),
+                            reference: Some(reference),
+                            pinned,
+                            version: bundle.version.clone(),
+                            bundle,
+                            parents: Vec::new(),
+                            dependencies: Vec::new(),
+                            parameters: dependency.parameters.clone(),
+                            credentials: dependency.credentials.clone(),
+                        });
+                        graph.index.insert(key, child);
+                        queue.push_back(child);
+                        child
+                    }
+                };
+                graph.nodes[child].parents.push(parent);
+                graph.nodes[parent]
+                    .dependencies
+                    .push((name.to_string(), child));
+            }
+        }
+        Ok(graph)
+    }
+
+    /// The reference of the newest version of `repository` that meets every
+    /// requirement.
+    fn choose(
+        &self,
+        repository: &str,
+        requirements: &[&Requirement],
+        fetched: &mut Fetched,
+    ) -> Result<String, DependencyError> {
+        for requirement in requirements {
+            requirement.check()?;
+        }
+        let mut best: Option<(Version, &AvailableVersion)> = None;
+        for candidate in fetched.versions(self.source, repository)? {
+            let version = match Version::parse(&candidate.version) {
+                Ok(version) => version,
+                Err(_) => continue,
+            };
+            if !requirements.iter().all(|r| r.accepts(&version)) {
+                continue;
+            }
+            if best.as_ref().is_none_or(|(newest, _)| version > *newest) {
+                best = Some((version, candidate));
+            }
+        }
+        best.map(|(_, candidate)| candidate.reference.clone())
+            .ok_or_else(|| DependencyError::NoMatchingVersion {
+                bundle: repository.to_string(),
+                requirements: requirements.iter().map(|r| r.to_string()).collect(),
+            })
+    }
+}
+
+/// The dependencies `bundle` declares.
+fn declared(bundle: &Bundle) -> Result<Dependencies, DependencyError> {
+    bundle
+        .dependencies()
+        .map(Option::unwrap_or_default)
+        .map_err(|error| DependencyError::Invalid {
+            bundle: bundle.name.clone(),
+            error,
+        })
+}
+
+/// The key a dependency is known by, its repository or the reference it pins, and
+/// whether it pins one.
+fn dependency_key(bundle: &str) -> Result<(String, bool), DependencyError> {
+    let reference = BundleReference::parse(bundle)?;
+    let last = bundle.rsplit('/').next().unwrap_or(bundle);
+    if reference.digest.is_some() || last.contains(':') {
+        Ok((reference.to_string(), true))
+    } else {
+        Ok((reference.name(), false))
+    }
+}
+
+/// The versions listed and bundles fetched so far, kept across passes.
+#[derive(Default)]
+struct Fetched {
+    versions: BTreeMap<String, Vec<AvailableVersion>>,
+    bundles: BTreeMap<String, Bundle>,
+}
+
+impl Fetched {
+    fn versions(
+        &mut self,
+        source: &dyn BundleSource,
+        repository: &str,
+    ) -> Result<&[AvailableVersion], DependencyError> {
+        if !self.versions.contains_key(repository) {
+            let versions = source.versions(repository)?;
+            self.versions.insert(repository.to_string(), versions);
+        }
+        Ok(&self.versions[repository])
+    }
+
+    fn bundle(
+        &mut self,
+        source: &dyn BundleSource,
+        reference: &str,
+    ) -> Result<Bundle, DependencyError> {
+        if let Some(bundle) = self.bundles.get(reference) {
+            return Ok(bundle.clone());
+        }
+        let bundle = source.fetch(reference)?;
+        self.bundles.insert(reference.to_string(), bundle.clone());
+        Ok(bundle)
+    }
+}
+
+/// A version range an installation puts on one of its dependencies
+#[derive(Debug, Clone, PartialEq)]
+struct Requirement {
+    requirer: String,
+    version: DependencyVersion,
+}
+
+impl Requirement {
+    /// Whether the ranges parse.
+    fn check(&self) -> Result<(), DependencyError> {
+        self.version
+            .matches(&Version::new(0, 0, 0))
+            .map(|_| ())
+            .map_err(|e| DependencyError::InvalidRange {
+                requirer: self.requirer.clone(),
+                message: e.to_string(),
+            })
+    }
+
+    /// Whether `version` meets the requirement. Ranges that do not parse meet nothing.
+    fn accepts(&self, version: &Version) -> bool {
+        self.version.matches(version).unwrap_or(false)
+    }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.version.ranges.is_empty() {
+            write!(f, "{} requires any version", self.requirer)
+        } else {
+            write!(
+                f,
+                "{} requires {}",
+                self.requirer,
+                self.version.ranges.join(" or ")
+            )
+        }
+    }
+}
+
+struct Node {
+    installation: String,
+    reference: Option<String>,
+    pinned: bool,
+    version: Version,
+    bundle: Bundle,
+    parents: Vec<usize>,
+    /// In the order the dependencies are installed
+    dependencies: Vec<(String, usize)>,
+    parameters: BTreeMap<String, String>,
+    credentials: BTreeMap<String, String>,
+}
+
+/// The bundles found in a walk, the root first
+struct Graph {
+    nodes: Vec<Node>,
+    /// The node of each dependency key
+    index: BTreeMap<String, usize>,
+    /// The requirements on each dependency key
+    requirements: BTreeMap<String, Vec<Requirement>>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    New,
+    InProgress,
+    Done,
+}
+
+impl Graph {
+    /// Order the installations so that each comes after its dependencies.
+    fn plan(self) -> Result<InstallPlan, DependencyError> {
+        let mut visits = vec![Visit::New; self.nodes.len()];
+        let mut order = Vec::new();
+        let mut path = Vec::new();
+        self.visit(0, &mut visits, &mut order, &mut path)?;
+        let steps = order
+            .into_iter()
+            .map(|i| {
+                let node = &self.nodes[i];
+                InstallStep {
+                    installation: node.installation.clone(),
+                    bundle: node.bundle.clone(),
+                    reference: node.reference.clone(),
+                    parents: node
+                        .parents
+                        .iter()
+                        .map(|p| self.nodes[*p].installation.clone())
+                        .collect(),
+                    dependencies: node
+                        .dependencies
+                        .iter()
+                        .map(|(name, child)| {
+                            (name.clone(), self.nodes[*child].installation.clone())
+                        })
+                        .collect(),
+                    parameters: node.parameters.clone(),
+                    credentials: node.credentials.clone(),
+                }
+            })
+            .collect();
+        Ok(InstallPlan { steps })
+    }
+
+    fn visit(
+        &self,
+        node: usize,
+        visits: &mut [Visit],
+        order: &mut Vec<usize>,
+        path: &mut Vec<usize>,
+    ) -> Result<(), DependencyError> {
+        visits[node] = Visit::InProgress;
+        path.push(node);
+        for (_, child) in &self.nodes[node].dependencies {
+            match visits[*child] {
+                Visit::New => self.visit(*child, visits, order, path)?,
+                Visit::InProgress => {
+                    let start = path.iter().position(|n| n == child).unwrap_or(0);
+                    let cycle = path[start..]
+                        .iter()
+                        .chain(Some(child))
+                        .map(|n| self.name(*n))
+                        .collect();
+                    return Err(DependencyError::Cycle(cycle));
+                }
+                Visit::Done => {}
+            }
+        }
+        path.pop();
+        visits[node] = Visit::Done;
+        order.push(node);
+        Ok(())
+    }
+
+    fn name(&self, node: usize) -> String {
+        let node = &self.nodes[node];
+        node.reference
+            .clone()
+            .unwrap_or_else(|| node.bundle.name.clone())
+    }
+}
+
+/// DependencyError describes why the dependencies of a bundle could not be resolved.
+#[derive(Debug)]
+pub enum DependencyError {
+    /// The dependencies extension of the named bundle is invalid
+    Invalid {
+        bundle: String,
+        error: serde_json::Error,
+    },
+    InvalidReference(InvalidReference),
+    /// A version range of the installation does not parse
+    InvalidRange {
+        requirer: String,
+        message: String,
+    },
+    /// No version of the bundle meets the requirements
+    NoMatchingVersion {
+        bundle: String,
+        requirements: Vec<String>,
+    },
+    /// The bundles depend on each other in a loop, listed from the first back to it
+    Cycle(Vec<String>),
+    /// The resolver could not settle on versions that meet every requirement on them
+    Unresolvable(String),
+    /// The source could not list or fetch the bundle
+    Source {
+        reference: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for DependencyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DependencyError::Invalid { bundle, error } => {
+                write!(f, "invalid dependencies in bundle {}: {}", bundle, error)
+            }
+            DependencyError::InvalidReference(e) => e.fmt(f),
+            DependencyError::InvalidRange { requirer, message } => {
+                write!(f, "invalid version range in {}: {}", requirer, message)
+            }
+            DependencyError::NoMatchingVersion {
+                bundle,
+                requirements,
+            } => write!(
+                f,
+                "no version of {} meets the requirements: {}",
+                bundle,
+                requirements.join(", ")
+            ),
+            DependencyError::Cycle(cycle) => {
+                write!(f, "dependency cycle: {}", cycle.join(" -> "))
+            }
+            DependencyError::Unresolvable(installation) => write!(
+                f,
+                "could not find versions that meet every requirement of the dependencies of {}",
+                installation
+            ),
+            DependencyError::Source { reference, message } => {
+                write!(f, "fetching dependency {}: {}", reference, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DependencyError {}
+
+impl Coded for DependencyError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            DependencyError::Invalid { .. } => ErrorCode::DependencyInvalid,
+            DependencyError::InvalidReference(e) => e.code(),
+            DependencyError::InvalidRange { .. } => ErrorCode::DependencyInvalidRange,
+            DependencyError::NoMatchingVersion { .. } => ErrorCode::DependencyNoMatchingVersion,
+            DependencyError::Cycle(_) => ErrorCode::DependencyCycle,
+            DependencyError::Unresolvable(_) => ErrorCode::DependencyUnresolvable,
+            DependencyError::Source { .. } => ErrorCode::DependencySourceFailed,
+        }
+    }
+}
+
+impl From<InvalidReference> for DependencyError {
+    fn from(error: InvalidReference) -> Self {
+        DependencyError::InvalidReference(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bundle(name: &str, version: &str, dependencies: serde_json::Value) -> Bundle {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.name = name.to_string();
+        bundle.version = Version::parse(version).unwrap();
+        if !dependencies.is_null() {
+            bundle.custom = Some(
+                vec![(DEPENDENCIES_KEY.to_string(), dependencies)]
+                    .into_iter()
+                    .collect(),
+            );
+        }
+        bundle
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut source = MemoryBundleSource::new();
+        for version in &["5.7.20", "5.7.30", "8.0.1"] {
+            let mysql = bundle("mysql", version, serde_json::Value::Null);
+            source.add("example.com/mysql", mysql).unwrap();
+        }
+        let cache = bundle(
+            "cache",
+            "1.0.0",
+            serde_json::json!({
+                "requires": {
+                    "db": { "bundle": "example.com/mysql", "version": { "ranges": ["<5.7.25"] } }
+                }
+            }),
+        );
+        source.add("example.com/cache", cache).unwrap();
+        let storage = bundle("storage", "0.1.0", serde_json::Value::Null);
+        source.add("example.com/storage", storage).unwrap();
+
+        let wordpress = bundle(
+            "wordpress",
+            "1.0.0",
+            serde_json::json!({
+                "sequence": ["mysql", "cache"],
+                "requires": {
+                    "storage": { "bundle": "example.com/storage:0.1.0" },
+                    "mysql": {
+                        "bundle": "example.com/mysql",
+                        "version": { "ranges": ["5.7.x"] },
+                        "parameters": { "database": "db-name" }
+                    },
+                    "cache": { "bundle": "example.com/cache" }
+                }
+            }),
+        );
+        let plan = DependencyResolver::new(&source)
+            .resolve(&wordpress, "blog")
+            .unwrap();
+        let order: Vec<_> = plan.steps.iter().map(|s| s.installation.as_str()).collect();
+        assert_eq!(
+            order,
+            vec!["blog-mysql", "blog-cache", "blog-storage", "blog"]
+        );
+
+        let mysql = &plan.steps[0];
+        assert_eq!(
+            mysql.bundle.version.to_string(),
+            "5.7.20",
+            "the newest version within every requirement"
+        );
+        assert_eq!(mysql.reference.as_deref(), Some("example.com/mysql:5.7.20"));
+        assert_eq!(mysql.parents, vec!["blog", "blog-cache"]);
+        assert_eq!(mysql.parameters["database"], "db-name");
+        assert_eq!(plan.steps[1].dependencies["db"], "blog-mysql");
+        assert_eq!(plan.root().installation, "blog");
+        assert_eq!(plan.root().reference, None);
+        assert_eq!(plan.root().dependencies.len(), 3);
+
+        let strict = bundle(
+            "strict",
+            "1.0.0",
+            serde_json::json!({
+                "requires": {
+                    "mysql": { "bundle": "example.com/mysql", "version": { "ranges": ["8.x"] } },
+                    "cache": { "bundle": "example.com/cache" }
+                }
+            }),
+        );
+        match DependencyResolver::new(&source).resolve(&strict, "strict") {
+            Err(DependencyError::NoMatchingVersion {
+                bundle,
+                requirements,
+            }) => {
+                assert_eq!(bundle, "example.com/mysql");
+                assert_eq!(
+                    requirements,
+                    vec!["strict requires 8.x", "strict-cache requires <5.7.25"]
+                );
+            }
+            other => panic!("expected no matching version, got {:?}", other),
+        }
+
+        let invalid = bundle(
+            "invalid",
+            "1.0.0",
+            serde_json::json!({
+                "requires": {
+                    "mysql": { "bundle": "example.com/mysql", "version": { "ranges": ["five"] } }
+                }
+            }),
+        );
+        assert!(matches!(
+            DependencyResolver::new(&source).resolve(&invalid, "invalid"),
+            Err(DependencyError::InvalidRange { .. })
+        ));
+    }
+
+    #[test]
+    fn test_resolve_cycle() {
+        let mut source = MemoryBundleSource::new();
+        let a = bundle(
+            "a",
+            "1.0.0",
+            serde_json::json!({ "requires": { "b": { "bundle": "example.com/b" } } }),
+        );
+        let b = bundle(
+            "b",
+            "1.0.0",
+            serde_json::json!({ "requires": { "a": { "bundle": "example.com/a" } } }),
+        );
+        source.add("example.com/a", a).unwrap();
+        source.add("example.com/b", b).unwrap();
+        let root = bundle(
+            "root",
+            "1.0.0",
+            serde_json::json!({ "requires": { "a": { "bundle": "example.com/a" } } }),
+        );
+        match DependencyResolver::new(&source).resolve(&root, "root") {
+            Err(DependencyError::Cycle(cycle)) => assert_eq!(
+                cycle,
+                vec![
+                    "example.com/a:1.0.0",
+                    "example.com/b:1.0.0",
+                    "example.com/a:1.0.0"
+                ]
+            ),
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,451 @@
+use crate::cnab::Bundle;
+use semver::Version;
+use serde::Serialize;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// The fields of a bundle compared as a whole, in their JSON names
+const METADATA_FIELDS: &[&str] = &[
+    "name",
+    "schemaVersion",
+    "description",
+    "keywords",
+    "license",
+    "maintainers",
+    "requiredExtensions",
+];
+
+/// BundleDiff is what changes between two versions of a bundle, such as for showing
+/// what an upgrade would change.
+///
+/// ```
+/// use libcnab::{Bump, Bundle};
+///
+/// let old = Bundle::from_file("testdata/bundle.json").unwrap();
+/// let mut new = old.clone();
+/// new.version = semver::Version::parse("0.2.0").unwrap();
+/// new.credentials = None;
+/// let diff = old.diff(&new);
+/// assert_eq!(diff.version.unwrap().bump, Bump::Minor);
+/// assert_eq!(diff.credentials.removed, vec!["hostkey"]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleDiff {
+    /// How the version changed, unless it did not
+    pub version: Option<VersionChange>,
+    /// The top-level fields that changed, such as `description` or `maintainers`,
+    /// in their JSON names
+    pub metadata: Vec<String>,
+    pub parameters: SectionDiff,
+    pub credentials: SectionDiff,
+    pub outputs: SectionDiff,
+    pub actions: SectionDiff,
+    /// The invocation images, named by their position
+    pub invocation_images: SectionDiff,
+    pub images: SectionDiff,
+    pub definitions: SectionDiff,
+    /// The custom extensions, named by their keys
+    pub custom: SectionDiff,
+}
+
+/// VersionChange is how the version of a bundle changed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VersionChange {
+    #[serde(serialize_with = "display")]
+    pub from: Version,
+    #[serde(serialize_with = "display")]
+    pub to: Version,
+    pub bump: Bump,
+}
+
+/// Bump is the most significant part of a version that changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Bump {
+    Major,
+    Minor,
+    Patch,
+    /// Only the pre-release or build metadata changed
+    Prerelease,
+    /// The new version is older
+    Downgrade,
+}
+
+impl fmt::Display for Bump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Bump::Major => "major",
+            Bump::Minor => "minor",
+            Bump::Patch => "patch",
+            Bump::Prerelease => "prerelease",
+            Bump::Downgrade => "downgrade",
+        })
+    }
+}
+
+/// SectionDiff is what changes in one section of a bundle, such as its parameters.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SectionDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<Changed>,
+}
+
+/// Changed is an entry of a section that both versions have, but not alike.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Changed {
+    pub name: String,
+    /// The fields of the entry that changed, in their JSON names. A parameter or
+    /// output whose definition changed has `schema` among them.
+    pub fields: Vec<String>,
+}
+
+impl Bundle {
+    /// What changes from this bundle to `other`, such as a newer version of it.
+    pub fn diff(&self, other: &Bundle) -> BundleDiff {
+        let (old, new) = (to_object(self), to_object(other));
+        let field = |name: &str| (old.get(name), new.get(name));
+        let metadata = METADATA_FIELDS
+            .iter()
+            .filter(|name| {
+                let (a, b) = field(name);
+                a != b
+            })
+            .map(|name| name.to_string())
+            .collect();
+
+        let mut parameters = section(field("parameters"));
+        let mut outputs = section(field("outputs"));
+        for (diff, name) in [(&mut parameters, "parameters"), (&mut outputs, "outputs")] {
+            let (a, b) = field(name);
+            for (entry, (a, b)) in common(a, b) {
+                if schema(self, a) != schema(other, b) {
+                    diff.change(entry, "schema");
+                }
+            }
+        }
+        let invocation_images = |o: &serde_json::Map<String, Value>| {
+            let images = o.get("invocationImages").and_then(Value::as_array);
+            let indexed = images.into_iter().flatten().enumerate();
+            Value::Object(indexed.map(|(i, v)| (i.to_string(), v.clone())).collect())
+        };
+
+        BundleDiff {
+            version: version_change(&self.version, &other.version),
+            metadata,
+            parameters,
+            credentials: section(field("credentials")),
+            outputs,
+            actions: section(field("actions")),
+            invocation_images: section((
+                Some(&invocation_images(&old)),
+                Some(&invocation_images(&new)),
+            )),
+            images: section(field("images")),
+            definitions: section(field("definitions")),
+            custom: section(field("custom")),
+        }
+    }
+}
+
+impl BundleDiff {
+    /// Whether the bundles are alike.
+    pub fn is_empty(&self) -> bool {
+        *self == BundleDiff::default()
+    }
+
+    fn sections(&self) -> [(&'static str, &SectionDiff); 8] {
+        [
+            ("parameter", &self.parameters),
+            ("credential", &self.credentials),
+            ("output", &self.outputs),
+            ("action", &self.actions),
+            ("invocation image", &self.invocation_images),
+            ("image", &self.images),
+            ("definition", &self.definitions),
+            ("custom extension", &self.custom),
+        ]
+    }
+}
+
+impl SectionDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Record that `field` of the entry `name` changed.
+    fn change(&mut self, name: &str, field: &str) {
+        match self.changed.iter_mut().find(|c| c.name == name) {
+            Some(changed) => changed.fields.push(field.to_string()),
+            None => self.changed.push(Changed {
+                name: name.to_string(),
+                fields: vec![field.to_string()],
+            }),
+        }
+    }
+}
+
+/// One line per change, such as `changed parameter port: default, schema`.
+impl fmt::Display for BundleDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(version) = &self.version {
+            writeln!(
+                f,
+                "version {} -> {} ({})",
+                version.from, version.to, version.bump
+            )?;
+        }
+        if !self.metadata.is_empty() {
+            writeln!(f, "changed {}", self.metadata.join(", "))?;
+        }
+        for (kind, section) in self.sections().iter() {
+            for name in &section.added {
+                writeln!(f, "added {} {}", kind, name)?;
+            }
+            for name in &section.removed {
+                writeln!(f, "removed {} {}", kind, name)?;
+            }
+            for changed in &section.changed {
+                writeln!(
+                    f,
+                    "changed {} {}: {}",
+                    kind,
+                    changed.name,
+                    changed.fields.join(", ")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn display<S: serde::Serializer>(version: &Version, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(version)
+}
+
+fn to_object(bundle: &Bundle) -> serde_json::Map<String, Value> {
+    match serde_json::to_value(bundle).expect("bundles serialize") {
+        Value::Object(map) => map,
+        _ => unreachable!("bundles serialize as objects"),
+    }
+}
+
+fn version_change(from: &Version, to: &Version) -> Option<VersionChange> {
+    let bump = match to.cmp(from) {
+        Ordering::Equal if to.to_string() == from.to_string() => return None,
+        Ordering::Less => Bump::Downgrade,
+        _ if to.major != from.major => Bump::Major,
+        _ if to.minor != from.minor => Bump::Minor,
+        _ if to.patch != from.patch => Bump::Patch,
+        _ => Bump::Prerelease,
+    };
+    Some(VersionChange {
+        from: from.clone(),
+        to: to.clone(),
+        bump,
+    })
+}
+
+/// The entries of two versions of a section, an object keyed by name, by how they
+/// changed. Entries that are objects are compared field by field.
+fn section((old, new): (Option<&Value>, Option<&Value>)) -> SectionDiff {
+    let entries = |v: Option<&Value>| v.and_then(Value::as_object).cloned().unwrap_or_default();
+    let (old, new) = (entries(old), entries(new));
+    let mut diff = SectionDiff {
+        added: new
+            .keys()
+            .filter(|k| !old.contains_key(*k))
+            .cloned()
+            .collect(),
+        removed: old
+            .keys()
+            .filter(|k| !new.contains_key(*k))
+            .cloned()
+            .collect(),
+        changed: Vec::new(),
+    };
+    for (name, a) in &old {
+        let b = match new.get(name) {
+            Some(b) if b != a => b,
+            _ => continue,
+        };
+        match (a, b) {
+            (Value::Object(a), Value::Object(b)) => {
+                let fields: BTreeSet<&String> = a
+                    .keys()
+                    .chain(b.keys())
+                    .filter(|k| a.get(*k) != b.get(*k))
+                    .collect();
+                for field in fields {
+                    diff.change(name, field);
+                }
+            }
+            _ => diff.changed.push(Changed {
+                name: name.clone(),
+                fields: Vec::new(),
+            }),
+        }
+    }
+    diff
+}
+
+/// The entries both versions of a section have, with each version of them.
+fn common<'v>(
+    old: Option<&'v Value>,
+    new: Option<&'v Value>,
+) -> Vec<(&'v str, (&'v Value, &'v Value))> {
+    let (old, new) = match (
+        old.and_then(Value::as_object),
+        new.and_then(Value::as_object),
+    ) {
+        (Some(old), Some(new)) => (old, new),
+        _ => return Vec::new(),
+    };
+    old.iter()
+        .filter_map(|(name, a)| Some((name.as_str(), (a, new.get(name)?))))
+        .collect()
+}
+
+/// The schema of a parameter or output of `bundle`, from its definition.
+fn schema<'b>(bundle: &'b Bundle, entry: &Value) -> Option<&'b Value> {
+    let definition = entry.get("definition")?.as_str()?;
+    bundle.definitions.as_ref()?.get(definition)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cnab::{Credential, InvocationImage};
+
+    #[test]
+    fn test_diff() {
+        let old = Bundle::from_file("testdata/bundle.json").unwrap();
+        assert!(old.diff(&old).is_empty());
+
+        let mut new = old.clone();
+        new.version = Version::parse("1.0.0").unwrap();
+        new.description = Some("a new description".to_string());
+        new.credentials.as_mut().unwrap().insert(
+            "kubeconfig".to_string(),
+            Credential {
+                apply_to: None,
+                description: None,
+                env: None,
+                path: Some("/root/.kube/config".into()),
+                required: Some(true),
+            },
+        );
+        new.credentials
+            .as_mut()
+            .unwrap()
+            .get_mut("hostkey")
+            .unwrap()
+            .env = None;
+        new.invocation_images.push(InvocationImage {
+            content_digest: None,
+            image: "example/arm64-installer:1.0".to_string(),
+            image_type: Some("docker".to_string()),
+            media_type: None,
+            size: None,
+            labels: None,
+        });
+        let parameter = new
+            .parameters
+            .as_ref()
+            .unwrap()
+            .keys()
+            .next()
+            .unwrap()
+            .clone();
+        new.parameters
+            .as_mut()
+            .unwrap()
+            .get_mut(&parameter)
+            .unwrap()
+            .description = Some("changed".to_string());
+        new.custom = None;
+
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.version,
+            Some(VersionChange {
+                from: old.version.clone(),
+                to: new.version.clone(),
+                bump: Bump::Major,
+            })
+        );
+        assert_eq!(diff.metadata, vec!["description"]);
+        assert_eq!(diff.credentials.added, vec!["kubeconfig"]);
+        assert_eq!(
+            diff.credentials.changed,
+            vec![Changed {
+                name: "hostkey".to_string(),
+                fields: vec!["env".to_string()],
+            }]
+        );
+        assert_eq!(diff.invocation_images.added, vec!["1"]);
+        assert_eq!(diff.parameters.changed[0].fields, vec!["description"]);
+        assert_eq!(
+            diff.custom.removed,
+            vec!["com.example.backup-preferences", "com.example.duffle-bag"]
+        );
+        let rendered = diff.to_string();
+        assert!(
+            rendered.starts_with("version 0.1.2 -> 1.0.0 (major)\n"),
+            "{}",
+            rendered
+        );
+        assert!(
+            rendered.contains("added credential kubeconfig\n"),
+            "{}",
+            rendered
+        );
+        assert!(
+            rendered.contains("changed credential hostkey: env\n"),
+            "{}",
+            rendered
+        );
+
+        assert_eq!(
+            serde_json::to_value(&diff).unwrap()["version"],
+            serde_json::json!({ "from": old.version.to_string(), "to": "1.0.0", "bump": "major" })
+        );
+        let bump = |from: &str, to: &str| {
+            version_change(&Version::parse(from).unwrap(), &Version::parse(to).unwrap())
+                .map(|c| c.bump)
+        };
+        assert_eq!(bump("1.2.3", "1.3.0"), Some(Bump::Minor));
+        assert_eq!(bump("1.2.3", "1.2.4-beta.1"), Some(Bump::Patch));
+        assert_eq!(bump("1.2.4-beta.1", "1.2.4"), Some(Bump::Prerelease));
+        assert_eq!(bump("1.2.3", "1.2.2"), Some(Bump::Downgrade));
+        assert_eq!(bump("1.2.3", "1.2.3"), None);
+    }
+
+    #[test]
+    fn test_diff_schema() {
+        let mut old = Bundle::from_file("testdata/bundle.json").unwrap();
+        old.definitions
+            .get_or_insert_with(Default::default)
+            .insert("port".to_string(), serde_json::json!({ "type": "integer" }));
+        let parameters = old.parameters.as_mut().unwrap();
+        parameters.get_mut("backend_port").unwrap().definition = Some("port".to_string());
+        let mut new = old.clone();
+        new.definitions
+            .as_mut()
+            .unwrap()
+            .insert("port".to_string(), serde_json::json!({ "type": "string" }));
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.definitions.changed[0].name, "port");
+        assert_eq!(
+            diff.parameters.changed,
+            vec![Changed {
+                name: "backend_port".to_string(),
+                fields: vec!["schema".to_string()],
+            }]
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use crate::cnab::Bundle;
+use serde::{Deserialize, Serialize};
+
+/// The custom key under which the Docker extension is stored
+pub const DOCKER_EXTENSION_KEY: &str = "io.cnab.docker";
+
+/// DockerExtension implements the `io.cnab.docker` extension: what an invocation
+/// image needs from the Docker host that runs it, typically to run containers itself.
+///
+/// A bundle that lists the extension in `requiredExtensions` without a value in
+/// `custom` gets the defaults: access to the Docker socket, without privileges.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct DockerExtension {
+    /// Whether the container runs in privileged mode, as Docker-in-Docker needs
+    #[serde(default)]
+    pub privileged: bool,
+    /// Whether the host's Docker socket is mounted into the container
+    #[serde(default = "default_docker_socket")]
+    pub docker_socket: bool,
+}
+
+fn default_docker_socket() -> bool {
+    true
+}
+
+impl Default for DockerExtension {
+    fn default() -> Self {
+        DockerExtension {
+            privileged: false,
+            docker_socket: default_docker_socket(),
+        }
+    }
+}
+
+impl Bundle {
+    /// Parse the Docker extension from `custom`, if present.
+    pub fn docker_extension(&self) -> Result<Option<DockerExtension>, serde_json::Error> {
+        match self
+            .custom
+            .as_ref()
+            .and_then(|c| c.get(DOCKER_EXTENSION_KEY))
+        {
+            Some(value) => serde_json::from_value(value.clone()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The Docker extension settings the invocation image runs with, if the bundle
+    /// lists the extension in `requiredExtensions`.
+    pub fn required_docker_extension(&self) -> Result<Option<DockerExtension>, serde_json::Error> {
+        let required = self
+            .required_extensions
+            .iter()
+            .flatten()
+            .any(|e| e == DOCKER_EXTENSION_KEY);
+        if !required {
+            return Ok(None);
+        }
+        Ok(Some(self.docker_extension()?.unwrap_or_default()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_docker_extension() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        assert_eq!(bundle.docker_extension().unwrap(), None);
+        assert_eq!(bundle.required_docker_extension().unwrap(), None);
+
+        bundle.custom = Some(
+            vec![(
+                DOCKER_EXTENSION_KEY.to_string(),
+                serde_json::json!({ "privileged": true }),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        let docker = DockerExtension {
+            privileged: true,
+            docker_socket: true,
+        };
+        assert_eq!(bundle.docker_extension().unwrap(), Some(docker.clone()));
+        assert_eq!(bundle.required_docker_extension().unwrap(), None);
+
+        bundle.required_extensions = Some(vec![DOCKER_EXTENSION_KEY.to_string()]);
+        assert_eq!(bundle.required_docker_extension().unwrap(), Some(docker));
+        bundle.custom = None;
+        assert_eq!(
+            bundle.required_docker_extension().unwrap(),
+            Some(DockerExtension::default())
+        );
+
+        bundle.custom = Some(
+            vec![(
+                DOCKER_EXTENSION_KEY.to_string(),
+                serde_json::json!({ "privileged": "yes" }),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        assert!(bundle.required_docker_extension().is_err());
+    }
+}
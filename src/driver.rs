@@ -0,0 +1,323 @@
+//! Execution of invocation images.
+//!
+//! An `Operation` is everything needed to run one action of a bundle: the invocation
+//! image, the environment variables and files to inject, and the outputs to collect.
+//! A `Driver` runs operations on a particular container backend, optionally streaming
+//! the image's output to a `LogSink`, from which `ChunkLogs` picks the chunks of
+//! output the image sends while it runs. Downstream tools can implement `Driver` to run
+//! images somewhere this crate does not support.
+//!
+//! Drivers are expected to watch `Operation::interrupted` while an image runs, and to
+//! stop the image and clean up after it once the operation is cancelled or its
+//! deadline passes.
+use crate::cnab::InvocationImage;
+use crate::error_code::{Coded, ErrorCode};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Child, ExitStatus};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "tokio")]
+mod asynchronous;
+#[cfg(feature = "tokio")]
+pub use self::asynchronous::*;
+mod chunks;
+pub use self::chunks::*;
+mod command;
+pub use self::command::*;
+mod debug;
+pub use self::debug::*;
+mod docker;
+pub use self::docker::*;
+mod kubernetes;
+pub use self::kubernetes::*;
+mod logs;
+pub use self::logs::*;
+mod operation;
+pub use self::operation::*;
+mod redact;
+pub use self::redact::*;
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "wasm")]
+pub use self::wasm::*;
+
+/// Driver runs invocation images.
+pub trait Driver {
+    /// Run the operation to completion and collect its outputs.
+    fn run(&self, op: &Operation) -> Result<OperationResult, DriverError>;
+
+    /// Run the operation, sending the image's output to `logs` while it runs.
+    ///
+    /// Each line is to be sent once it is read, not when the image exits, since the
+    /// engine follows long-running actions through the `Event::Chunk`s the lines
+    /// carry. Drivers that cannot stream fall back to `run`, and nothing is logged.
+    fn run_with_logs(
+        &self,
+        op: &Operation,
+        logs: &dyn LogSink,
+    ) -> Result<OperationResult, DriverError> {
+        let _ = logs;
+        self.run(op)
+    }
+
+    /// Whether this driver can run images of the given type.
+    fn handles(&self, image_type: &ImageType) -> bool;
+}
+
+/// ImageType is the `imageType` of an invocation image.
+///
+/// Images that do not declare a type are OCI images.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ImageType {
+    Docker,
+    #[default]
+    Oci,
+    /// Any other type, such as `wasm` or `qcow`
+    Other(String),
+}
+
+impl ImageType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ImageType::Docker => "docker",
+            ImageType::Oci => "oci",
+            ImageType::Other(t) => t,
+        }
+    }
+}
+
+impl From<&str> for ImageType {
+    fn from(image_type: &str) -> Self {
+        match image_type {
+            "docker" => ImageType::Docker,
+            "oci" => ImageType::Oci,
+            other => ImageType::Other(other.to_string()),
+        }
+    }
+}
+
+impl From<&InvocationImage> for ImageType {
+    fn from(image: &InvocationImage) -> Self {
+        image
+            .image_type
+            .as_deref()
+            .map(ImageType::from)
+            .unwrap_or_default()
+    }
+}
+
+impl serde::Serialize for ImageType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl fmt::Display for ImageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// OperationResult is what a driver collects from a completed operation.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OperationResult {
+    /// The raw contents of each output the image wrote
+    ///
+    /// Use `Bundle::output_value` to decode an output according to its definition.
+    pub outputs: BTreeMap<String, OutputContents>,
+}
+
+/// OutputContents holds an output collected by a driver.
+///
+/// Drivers keep small outputs in memory and may copy large ones to a local file, which
+/// the engine streams into the claim store and then removes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputContents {
+    Bytes(Vec<u8>),
+    File(PathBuf),
+}
+
+impl OutputContents {
+    /// Open the contents for reading.
+    pub fn open(&self) -> std::io::Result<Box<dyn Read + '_>> {
+        match self {
+            OutputContents::Bytes(bytes) => Ok(Box::new(bytes.as_slice())),
+            OutputContents::File(path) => Ok(Box::new(std::fs::File::open(path)?)),
+        }
+    }
+
+    /// The size of the contents in bytes.
+    pub fn size(&self) -> std::io::Result<u64> {
+        match self {
+            OutputContents::Bytes(bytes) => Ok(bytes.len() as u64),
+            OutputContents::File(path) => Ok(std::fs::metadata(path)?.len()),
+        }
+    }
+}
+
+impl From<Vec<u8>> for OutputContents {
+    fn from(bytes: Vec<u8>) -> Self {
+        OutputContents::Bytes(bytes)
+    }
+}
+
+impl Operation {
+    /// The type of the invocation image.
+    pub fn image_type(&self) -> ImageType {
+        ImageType::from(&self.image)
+    }
+
+    /// The error to stop with if the operation was cancelled or is past its deadline.
+    pub fn interrupted(&self) -> Option<DriverError> {
+        if self.cancellation.is_cancelled() {
+            Some(DriverError::Cancelled)
+        } else if self.deadline.map(|d| Instant::now() >= d).unwrap_or(false) {
+            Some(DriverError::TimedOut)
+        } else {
+            None
+        }
+    }
+}
+
+/// How often a running process is checked for cancellation and its deadline
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Wait for a process to exit, killing it if the operation is interrupted first.
+pub(crate) fn wait(child: &mut Child, op: &Operation) -> Result<ExitStatus, DriverError> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if let Some(e) = op.interrupted() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(e);
+        }
+        op.cancellation.sleep(POLL_INTERVAL);
+    }
+}
+
+/// DriverError describes why a driver could not complete an operation.
+#[derive(Debug)]
+pub enum DriverError {
+    /// The driver cannot run images of this type
+    UnsupportedImageType(ImageType),
+    /// The invocation image ran but did not succeed
+    Failed {
+        exit_code: Option<i32>,
+        message: String,
+    },
+    /// The backend could not start the image for a reason that may go away on its own,
+    /// such as an image pull timeout or a registry error
+    Transient(String),
+    /// The operation was cancelled and the image stopped
+    Cancelled,
+    /// The image was stopped because it ran past the operation's deadline
+    TimedOut,
+    IoError(std::io::Error),
+    /// A backend-specific error
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl fmt::Display for DriverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DriverError::UnsupportedImageType(t) => {
+                write!(f, "the driver cannot run images of type {}", t)
+            }
+            DriverError::Failed {
+                exit_code: Some(code),
+                message,
+            } => write!(f, "invocation image exited with code {}: {}", code, message),
+            DriverError::Failed {
+                exit_code: None,
+                message,
+            } => write!(f, "invocation image failed: {}", message),
+            DriverError::Transient(message) => write!(f, "{}", message),
+            DriverError::Cancelled => write!(f, "operation cancelled"),
+            DriverError::TimedOut => write!(f, "operation timed out"),
+            DriverError::IoError(e) => write!(f, "{}", e),
+            DriverError::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl DriverError {
+    /// Whether the failure happened before the image ran and may succeed if retried.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, DriverError::Transient(_))
+    }
+}
+
+impl std::error::Error for DriverError {}
+
+impl Coded for DriverError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            DriverError::UnsupportedImageType(_) => ErrorCode::DriverUnsupportedImageType,
+            DriverError::Failed { .. } => ErrorCode::DriverFailed,
+            DriverError::Transient(_) => ErrorCode::DriverTransient,
+            DriverError::Cancelled => ErrorCode::DriverCancelled,
+            DriverError::TimedOut => ErrorCode::DriverTimedOut,
+            DriverError::IoError(_) => ErrorCode::Io,
+            DriverError::Other(_) => ErrorCode::DriverOther,
+        }
+    }
+}
+
+impl From<std::io::Error> for DriverError {
+    fn from(error: std::io::Error) -> Self {
+        DriverError::IoError(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Bundle;
+
+    /// A driver that echoes the operation's environment back as outputs.
+    struct EchoDriver;
+
+    impl Driver for EchoDriver {
+        fn run(&self, op: &Operation) -> Result<OperationResult, DriverError> {
+            if !self.handles(&op.image_type()) {
+                return Err(DriverError::UnsupportedImageType(op.image_type()));
+            }
+            let outputs = op
+                .outputs
+                .keys()
+                .map(|name| (name.clone(), op.action.clone().into_bytes().into()))
+                .collect();
+            Ok(OperationResult { outputs })
+        }
+
+        fn handles(&self, image_type: &ImageType) -> bool {
+            *image_type == ImageType::Docker
+        }
+    }
+
+    #[test]
+    fn test_driver() {
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let op = OperationBuilder::new(&bundle, "install", "my-install")
+            .credential("hostkey", "key")
+            .build()
+            .unwrap();
+        assert_eq!(op.image_type(), ImageType::Docker);
+
+        let driver: Box<dyn Driver> = Box::new(EchoDriver);
+        let result = driver.run(&op).expect("operation ran");
+        assert_eq!(result.outputs.len(), op.outputs.len());
+
+        let mut op = op;
+        op.image.image_type = None;
+        match driver.run(&op) {
+            Err(DriverError::UnsupportedImageType(t)) => assert_eq!(t, ImageType::Oci),
+            other => panic!("expected unsupported image type, got {:?}", other),
+        }
+    }
+}
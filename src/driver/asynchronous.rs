@@ -0,0 +1,82 @@
+use super::{Driver, DriverError, ImageType, Operation, OperationResult};
+use crate::asynchronous::{blocking, BoxFuture};
+use std::sync::Arc;
+
+/// AsyncDriver runs invocation images without blocking the async runtime.
+///
+/// `BlockingDriver` runs any `Driver` on tokio's blocking threads.
+pub trait AsyncDriver: Send + Sync {
+    /// Run the operation to completion and collect its outputs.
+    fn run<'a>(&'a self, op: &'a Operation) -> BoxFuture<'a, Result<OperationResult, DriverError>>;
+
+    /// Whether this driver can run images of the given type.
+    fn handles(&self, image_type: &ImageType) -> bool;
+}
+
+/// BlockingDriver adapts a `Driver` to `AsyncDriver`, running each operation on
+/// tokio's blocking threads.
+///
+/// ```
+/// use libcnab::driver::{AsyncDriver, BlockingDriver, DebugDriver, ImageType};
+///
+/// let driver = BlockingDriver::new(DebugDriver::new());
+/// assert!(driver.handles(&ImageType::Docker));
+/// ```
+#[derive(Debug)]
+pub struct BlockingDriver<D> {
+    driver: Arc<D>,
+}
+
+impl<D> BlockingDriver<D> {
+    pub fn new(driver: D) -> Self {
+        BlockingDriver {
+            driver: Arc::new(driver),
+        }
+    }
+
+    /// The wrapped driver.
+    pub fn driver(&self) -> &D {
+        &self.driver
+    }
+}
+
+impl<D> Clone for BlockingDriver<D> {
+    fn clone(&self) -> Self {
+        BlockingDriver {
+            driver: self.driver.clone(),
+        }
+    }
+}
+
+impl<D: Driver + Send + Sync + 'static> AsyncDriver for BlockingDriver<D> {
+    fn run<'a>(&'a self, op: &'a Operation) -> BoxFuture<'a, Result<OperationResult, DriverError>> {
+        let (driver, op) = (self.driver.clone(), op.clone());
+        Box::pin(blocking(move || driver.run(&op)))
+    }
+
+    fn handles(&self, image_type: &ImageType) -> bool {
+        self.driver.handles(image_type)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cnab::Bundle;
+    use crate::driver::{DebugDriver, OperationBuilder};
+
+    #[test]
+    fn test_blocking_driver() {
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let op = OperationBuilder::new(&bundle, "install", "blog")
+            .credential("hostkey", "s3cr3t")
+            .build()
+            .unwrap();
+        let driver = BlockingDriver::new(DebugDriver::new());
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(driver.run(&op)).unwrap();
+        assert_eq!(driver.driver().operations()[0].installation, "blog");
+    }
+}
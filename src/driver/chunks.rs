@@ -0,0 +1,100 @@
+use crate::driver::{LogLine, LogSink};
+use crate::events::Event;
+use chrono::{DateTime, Utc};
+use std::sync::mpsc::Sender;
+
+/// OutputChunk is a piece of an output that an invocation image sent while it was
+/// still running, with an `Event::Chunk`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputChunk {
+    /// The installation the image runs an action of
+    pub installation: String,
+    pub output: String,
+    /// When the driver received the chunk
+    pub timestamp: DateTime<Utc>,
+    pub data: String,
+}
+
+/// ChunkLogs is a LogSink that sends the chunks of output carried by log lines to a
+/// channel, and passes every line on to another sink if there is one.
+///
+/// Chunks arrive as the driver reads the lines, so they can be shown while a
+/// long-running action, such as one following a log, has yet to exit. Once the
+/// receiver is dropped, chunks are discarded.
+pub struct ChunkLogs<'a> {
+    installation: &'a str,
+    chunks: &'a Sender<OutputChunk>,
+    logs: Option<&'a dyn LogSink>,
+}
+
+impl<'a> ChunkLogs<'a> {
+    pub fn new(installation: &'a str, chunks: &'a Sender<OutputChunk>) -> Self {
+        ChunkLogs {
+            installation,
+            chunks,
+            logs: None,
+        }
+    }
+
+    /// Pass every line on to `logs`, chunks included.
+    pub fn logs(mut self, logs: &'a dyn LogSink) -> Self {
+        self.logs = Some(logs);
+        self
+    }
+}
+
+impl LogSink for ChunkLogs<'_> {
+    fn log(&self, line: LogLine) {
+        if let Some(Event::Chunk { name, data }) = line.event() {
+            let _ = self.chunks.send(OutputChunk {
+                installation: self.installation.to_string(),
+                output: name,
+                timestamp: line.timestamp,
+                data,
+            });
+        }
+        if let Some(logs) = self.logs {
+            logs.log(line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::driver::{forward_lines, LogStream};
+    use std::sync::mpsc::channel;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_chunk_logs() {
+        let (sender, receiver) = channel();
+        let lines = Mutex::new(Vec::new());
+        let sink = |line: LogLine| lines.lock().unwrap().push(line.line);
+        let output = format!(
+            "following log\n{}\n{}\n",
+            Event::chunk("log", "ready").to_line(),
+            Event::output("port").to_line()
+        );
+        let chunks = ChunkLogs::new("hello", &sender).logs(&sink);
+        forward_lines(output.as_bytes(), LogStream::Stdout, &chunks).unwrap();
+
+        let chunk = receiver.try_recv().unwrap();
+        assert_eq!(
+            (
+                chunk.installation.as_str(),
+                chunk.output.as_str(),
+                chunk.data.as_str()
+            ),
+            ("hello", "log", "ready")
+        );
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(lines.into_inner().unwrap().len(), 3);
+
+        drop(receiver);
+        ChunkLogs::new("hello", &sender).log(LogLine::new(
+            LogStream::Stdout,
+            &Event::chunk("log", "dropped").to_line(),
+        ));
+    }
+}
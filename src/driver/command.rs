@@ -0,0 +1,349 @@
+//! A driver that delegates to an external command.
+//!
+//! The command is started once per operation and sent the operation as a single JSON
+//! document on stdin. It runs the image however it likes and writes a single JSON
+//! response to stdout before exiting:
+//!
+//! ```text
+//! request:  {"action": "install", "installation": "my-install", "revision": "...",
+//!            "image": {"image": "example/app:1.0.0", "imageType": "docker"},
+//!            "environment": {"CNAB_ACTION": "install", ...},
+//!            "files": {"/cnab/bundle.json": "<base64>", ...},
+//!            "outputs": {"port": "/cnab/app/outputs/port"}}
+//! response: {"outputs": {"port": "8080"}}
+//!       or: {"error": "image not found"}
+//! ```
+//!
+//! File contents are base64 encoded since parameters can be binary. Run with the single
+//! argument `--handles`, the command prints the image types it supports, separated by
+//! commas.
+//!
+//! A command that fails without writing a response, including one that exits before
+//! reading its request, fails the operation with its stderr as the message.
+//!
+//! When the operation is cancelled or passes its deadline the command is killed, so a
+//! command that starts the image in the background should stop it when it exits.
+use super::{
+    wait, Driver, DriverError, ImageType, LogLine, LogSink, LogStream, Operation, OperationResult,
+};
+use crate::cnab::InvocationImage;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+/// The filename prefix of driver commands installed on `PATH`.
+pub const COMMAND_DRIVER_PREFIX: &str = "cnab-";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandRequest<'a> {
+    pub action: &'a str,
+    pub installation: &'a str,
+    pub revision: &'a str,
+    pub image: &'a InvocationImage,
+    pub environment: &'a BTreeMap<String, String>,
+    /// Base64-encoded file contents, keyed by path
+    pub files: BTreeMap<&'a Path, String>,
+    pub outputs: &'a BTreeMap<String, PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommandResponse {
+    #[serde(default)]
+    pub outputs: BTreeMap<String, String>,
+    pub error: Option<String>,
+}
+
+/// CommandDriver runs operations by handing them to an external command.
+#[derive(Debug)]
+pub struct CommandDriver {
+    path: PathBuf,
+    image_types: OnceLock<Vec<String>>,
+}
+
+impl CommandDriver {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        CommandDriver {
+            path: path.as_ref().to_path_buf(),
+            image_types: OnceLock::new(),
+        }
+    }
+
+    /// Find the command for the named driver, `cnab-<name>`, on `PATH`.
+    pub fn find(name: &str) -> Option<Self> {
+        let file = format!("{}{}", COMMAND_DRIVER_PREFIX, name);
+        std::env::split_paths(&std::env::var_os("PATH")?)
+            .flat_map(|dir| vec![dir.join(&file), dir.join(format!("{}.exe", file))])
+            .find(|path| path.is_file())
+            .map(CommandDriver::new)
+    }
+
+    /// Declare the image types the command supports instead of asking it with
+    /// `--handles`.
+    pub fn with_image_types<S: AsRef<str>>(self, image_types: &[S]) -> Self {
+        let types = image_types.iter().map(|t| t.as_ref().to_string()).collect();
+        let _ = self.image_types.set(types);
+        self
+    }
+
+    /// The image types the command supports. The command is asked once and the answer
+    /// is remembered; a command that fails to answer supports nothing.
+    pub fn image_types(&self) -> &[String] {
+        self.image_types.get_or_init(|| {
+            Command::new(&self.path)
+                .arg("--handles")
+                .stderr(Stdio::inherit())
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| {
+                    String::from_utf8_lossy(&output.stdout)
+                        .split(',')
+                        .map(|t| t.trim().to_string())
+                        .filter(|t| !t.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default()
+        })
+    }
+}
+
+impl Driver for CommandDriver {
+    fn run(&self, op: &Operation) -> Result<OperationResult, DriverError> {
+        self.execute(op, None)
+    }
+
+    /// The command's stderr is streamed to `logs`; its stdout carries the response.
+    fn run_with_logs(
+        &self,
+        op: &Operation,
+        logs: &dyn LogSink,
+    ) -> Result<OperationResult, DriverError> {
+        self.execute(op, Some(logs))
+    }
+
+    fn handles(&self, image_type: &ImageType) -> bool {
+        self.image_types().iter().any(|t| t == image_type.as_str())
+    }
+}
+
+impl CommandDriver {
+    fn execute(
+        &self,
+        op: &Operation,
+        logs: Option<&dyn LogSink>,
+    ) -> Result<OperationResult, DriverError> {
+        let encoder = base64::engine::general_purpose::STANDARD;
+        let request = CommandRequest {
+            action: &op.action,
+            installation: &op.installation,
+            revision: &op.revision,
+            image: &op.image,
+            environment: &op.environment,
+            files: op
+                .files
+                .iter()
+                .map(|(path, contents)| (path.as_path(), encoder.encode(contents)))
+                .collect(),
+            outputs: &op.outputs,
+        };
+        let request = serde_json::to_vec(&request).map_err(|e| DriverError::Other(e.into()))?;
+
+        let mut child = Command::new(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // The request carries every file, so it is written while stdout and stderr
+        // are read: a command that writes before it has read all of stdin would
+        // otherwise block on a full pipe while this blocks on its stdin.
+        let (status, stdout, stderr) = std::thread::scope(|scope| {
+            let stdin = child.stdin.take();
+            let request = &request;
+            let written = scope.spawn(move || match stdin {
+                Some(mut stdin) => stdin.write_all(request),
+                None => Ok(()),
+            });
+            let stderr = child.stderr.take();
+            let stderr = scope.spawn(move || match stderr {
+                Some(stderr) => tee_stderr(stderr, logs),
+                None => Ok(Vec::new()),
+            });
+            let stdout = child.stdout.take();
+            let stdout = scope.spawn(move || {
+                let mut bytes = Vec::new();
+                if let Some(mut stdout) = stdout {
+                    stdout.read_to_end(&mut bytes)?;
+                }
+                Ok::<_, std::io::Error>(bytes)
+            });
+            let status = wait(&mut child, op)?;
+            // A command that exits without reading all of its request closes the
+            // pipe; its exit status and stderr explain why.
+            match written.join().expect("stdin writer panicked") {
+                Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => return Err(e.into()),
+                _ => {}
+            }
+            let stdout = stdout.join().expect("stdout reader panicked")?;
+            let stderr = stderr.join().expect("stderr reader panicked")?;
+            Ok::<_, DriverError>((status, stdout, stderr))
+        })?;
+
+        let response: Option<CommandResponse> = serde_json::from_slice(&stdout).ok();
+        match response {
+            Some(CommandResponse {
+                error: Some(message),
+                ..
+            }) => Err(DriverError::Failed {
+                exit_code: status.code(),
+                message,
+            }),
+            Some(response) if status.success() => Ok(OperationResult {
+                outputs: response
+                    .outputs
+                    .into_iter()
+                    .map(|(name, value)| (name, value.into_bytes().into()))
+                    .collect(),
+            }),
+            _ => {
+                let stderr = String::from_utf8_lossy(&stderr).trim().to_string();
+                Err(DriverError::Failed {
+                    exit_code: status.code(),
+                    message: if stderr.is_empty() {
+                        format!("driver command {} failed", self.path.display())
+                    } else {
+                        stderr
+                    },
+                })
+            }
+        }
+    }
+}
+
+/// Read the command's stderr, sending it to `logs`, or to this process's stderr
+/// when there are none, and return what was read.
+fn tee_stderr<R: Read>(stderr: R, logs: Option<&dyn LogSink>) -> std::io::Result<Vec<u8>> {
+    let mut stderr = BufReader::new(stderr);
+    let mut read = Vec::new();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if stderr.read_until(b'\n', &mut line)? == 0 {
+            return Ok(read);
+        }
+        match logs {
+            Some(logs) => logs.log(LogLine::new(
+                LogStream::Stderr,
+                &String::from_utf8_lossy(&line),
+            )),
+            None => {
+                let _ = std::io::stderr().write_all(&line);
+            }
+        }
+        read.extend_from_slice(&line);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use crate::driver::{LogLine, OperationBuilder, OutputContents};
+    use crate::fixture::script;
+    use crate::Bundle;
+
+    #[test]
+    fn test_command_driver() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = script(
+            dir.path(),
+            "cnab-echo",
+            "#!/bin/sh\nif [ \"$1\" = --handles ]; then echo 'docker, oci'; exit 0; fi\nread req\ncase \"$req\" in\n  *'\"action\":\"uninstall\"'*) echo '{\"error\":\"not installed\"}'; exit 1 ;;\n  *'\"action\":\"upgrade\"'*) exec sleep 30 ;;\n  *CNAB_ACTION*) echo 'installing' >&2; echo '{\"outputs\":{\"clientCert\":\"cert\"}}' ;;\nesac\n",
+        );
+
+        let driver = CommandDriver::new(&script);
+        assert!(driver.handles(&ImageType::Docker));
+        assert!(driver.handles(&ImageType::Oci));
+        assert!(!driver.handles(&"wasm".into()));
+
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let op = OperationBuilder::new(&bundle, "install", "my-install")
+            .credential("hostkey", "key")
+            .build()
+            .unwrap();
+        let lines = std::sync::Mutex::new(Vec::new());
+        let sink = |line: LogLine| lines.lock().unwrap().push(line.line);
+        let result = driver.run_with_logs(&op, &sink).expect("operation ran");
+        assert_eq!(*lines.lock().unwrap(), vec!["installing"]);
+        assert_eq!(
+            result.outputs["clientCert"],
+            OutputContents::Bytes(b"cert".to_vec())
+        );
+
+        let op = OperationBuilder::new(&bundle, "uninstall", "my-install")
+            .credential("hostkey", "key")
+            .build()
+            .unwrap();
+        match driver.run(&op) {
+            Err(DriverError::Failed { exit_code, message }) => {
+                assert_eq!(exit_code, Some(1));
+                assert_eq!(message, "not installed");
+            }
+            other => panic!("expected failure, got {:?}", other),
+        }
+
+        let mut op = OperationBuilder::new(&bundle, "upgrade", "my-install")
+            .credential("hostkey", "key")
+            .build()
+            .unwrap();
+        op.deadline = Some(std::time::Instant::now() + std::time::Duration::from_millis(100));
+        let started = std::time::Instant::now();
+        match driver.run(&op) {
+            Err(DriverError::TimedOut) => {}
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_large_requests() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let mut op = OperationBuilder::new(&bundle, "install", "my-install")
+            .credential("hostkey", "key")
+            .build()
+            .unwrap();
+        op.files
+            .insert(PathBuf::from("/cnab/app/large"), vec![b'x'; 1024 * 1024]);
+
+        // Writes more than a pipe holds to stderr before it reads its request.
+        let chatty = script(
+            dir.path(),
+            "cnab-chatty",
+            "#!/bin/sh\nhead -c 262144 /dev/zero | tr '\\0' 'x' >&2\ncat > /dev/null\necho '{\"outputs\":{}}'\n",
+        );
+        let lines = std::sync::Mutex::new(0);
+        let sink = |_: LogLine| *lines.lock().unwrap() += 1;
+        CommandDriver::new(&chatty)
+            .run_with_logs(&op, &sink)
+            .expect("operation ran");
+        assert_eq!(*lines.lock().unwrap(), 1);
+
+        // Exits without reading its request.
+        let early = script(
+            dir.path(),
+            "cnab-early",
+            "#!/bin/sh\necho 'no docker daemon' >&2\nexit 3\n",
+        );
+        match CommandDriver::new(&early).run_with_logs(&op, &|_: LogLine| {}) {
+            Err(DriverError::Failed { exit_code, message }) => {
+                assert_eq!(exit_code, Some(3));
+                assert_eq!(message, "no docker daemon");
+            }
+            other => panic!("expected failure, got {:?}", other),
+        }
+    }
+}
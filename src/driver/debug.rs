@@ -0,0 +1,102 @@
+use super::{Driver, DriverError, ImageType, Operation, OperationResult};
+use serde_json::json;
+use std::sync::Mutex;
+
+/// DebugDriver records operations instead of running them.
+///
+/// Every operation is redacted with `Operation::redacted` before it is kept, so
+/// the recorded operations and anything printed are safe to share. The driver
+/// accepts every image type and succeeds with no outputs, unless the operation was
+/// already interrupted.
+#[derive(Debug, Default)]
+pub struct DebugDriver {
+    print: bool,
+    operations: Mutex<Vec<Operation>>,
+}
+
+impl DebugDriver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also pretty-print each operation to stderr as it is run.
+    pub fn print(mut self) -> Self {
+        self.print = true;
+        self
+    }
+
+    /// The redacted operations run so far.
+    pub fn operations(&self) -> Vec<Operation> {
+        self.operations.lock().expect("lock poisoned").clone()
+    }
+
+    /// Describe a redacted operation as pretty-printed JSON.
+    pub fn describe(op: &Operation) -> String {
+        let files: serde_json::Map<String, serde_json::Value> = op
+            .files
+            .iter()
+            .map(|(path, contents)| {
+                (
+                    path.display().to_string(),
+                    String::from_utf8_lossy(contents).into(),
+                )
+            })
+            .collect();
+        let description = json!({
+            "action": op.action,
+            "installation": op.installation,
+            "revision": op.revision,
+            "image": op.image,
+            "environment": op.environment,
+            "files": files,
+            "outputs": op.outputs,
+            "warnings": op.warnings,
+        });
+        serde_json::to_string_pretty(&description).expect("json values serialize")
+    }
+}
+
+impl Driver for DebugDriver {
+    fn run(&self, op: &Operation) -> Result<OperationResult, DriverError> {
+        if let Some(e) = op.interrupted() {
+            return Err(e);
+        }
+        let op = op.redacted();
+        if self.print {
+            eprintln!("{}", Self::describe(&op));
+        }
+        self.operations.lock().expect("lock poisoned").push(op);
+        Ok(OperationResult::default())
+    }
+
+    fn handles(&self, _image_type: &ImageType) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::driver::{OperationBuilder, REDACTED};
+    use crate::Bundle;
+
+    #[test]
+    fn test_debug_driver() {
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let op = OperationBuilder::new(&bundle, "install", "my-install")
+            .credential("hostkey", "s3cr3t")
+            .build()
+            .unwrap();
+
+        let driver = DebugDriver::new();
+        assert!(driver.handles(&"wasm".into()));
+        driver.run(&op).expect("debug driver succeeds");
+
+        let recorded = driver.operations();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].environment["HOST_KEY"], REDACTED);
+        let description = DebugDriver::describe(&recorded[0]);
+        assert!(description.contains("\"/etc/hostkey.txt\": \"*******\""));
+        assert!(!description.contains("s3cr3t"));
+    }
+}
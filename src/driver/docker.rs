@@ -0,0 +1,573 @@
+//! A driver that runs invocation images with the Docker CLI.
+//!
+//! Each operation runs in a new container. The driver creates the container with the
+//! operation's environment, copies the operation's files into it, starts it and waits
+//! for it to exit, then copies the outputs out and removes the container. The `docker`
+//! command talks to whichever daemon it is configured for, through `DOCKER_HOST` or the
+//! current context.
+//!
+//! Bundles that require the `io.cnab.docker` extension get what it asks for on top of
+//! the driver's configuration: the host's Docker socket and, if asked, privileged
+//! mode. Engines only run such bundles once the host allows the extension with
+//! `Engine::extension`.
+//!
+//! ```no_run
+//! use libcnab::driver::{DockerConfig, DockerDriver, Mount};
+//!
+//! let driver = DockerDriver::with_config(DockerConfig {
+//!     mounts: vec![Mount::docker_socket(), Mount::tmpfs("/tmp")],
+//!     network: Some("none".to_string()),
+//!     memory: Some(512 * 1024 * 1024),
+//!     ..DockerConfig::default()
+//! });
+//! ```
+use super::{
+    forward_lines, wait, Driver, DriverError, ImageType, LogSink, LogStream, Operation,
+    OperationResult, OutputContents,
+};
+use crate::layout::RUN_PATH;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use ulid::Ulid;
+
+#[cfg(feature = "docker")]
+mod images;
+#[cfg(feature = "docker")]
+pub use self::images::*;
+
+/// The path of the Docker daemon's socket on Linux hosts
+pub const DOCKER_SOCKET: &str = "/var/run/docker.sock";
+
+/// DockerConfig holds the settings applied to every container the driver creates.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DockerConfig {
+    /// Volumes to mount in addition to the operation's files
+    pub mounts: Vec<Mount>,
+    /// The network to connect the container to, such as `none`, `host` or the name
+    /// of a user-defined network. Defaults to the daemon's default bridge.
+    pub network: Option<String>,
+    /// DNS servers for the container to use instead of the daemon's
+    pub dns: Vec<String>,
+    /// The number of CPUs the container may use, such as `1.5`
+    pub cpus: Option<f64>,
+    /// The memory limit of the container, in bytes
+    pub memory: Option<u64>,
+    /// The user to run the image as, as `name`, `uid` or `uid:gid`. Defaults to the
+    /// image's user.
+    pub user: Option<String>,
+}
+
+/// Mount is a volume mounted into an invocation image's container.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mount {
+    /// A file or directory of the host
+    Bind {
+        source: PathBuf,
+        target: PathBuf,
+        read_only: bool,
+    },
+    /// An in-memory filesystem, limited to `size` bytes if given
+    Tmpfs { target: PathBuf, size: Option<u64> },
+}
+
+impl Mount {
+    pub fn bind<S: AsRef<Path>, T: AsRef<Path>>(source: S, target: T) -> Self {
+        Mount::Bind {
+            source: source.as_ref().to_path_buf(),
+            target: target.as_ref().to_path_buf(),
+            read_only: false,
+        }
+    }
+
+    pub fn bind_read_only<S: AsRef<Path>, T: AsRef<Path>>(source: S, target: T) -> Self {
+        Mount::Bind {
+            source: source.as_ref().to_path_buf(),
+            target: target.as_ref().to_path_buf(),
+            read_only: true,
+        }
+    }
+
+    /// Mount the host's Docker socket at the same path, for bundles that run
+    /// containers themselves.
+    pub fn docker_socket() -> Self {
+        Mount::bind(DOCKER_SOCKET, DOCKER_SOCKET)
+    }
+
+    pub fn tmpfs<T: AsRef<Path>>(target: T) -> Self {
+        Mount::Tmpfs {
+            target: target.as_ref().to_path_buf(),
+            size: None,
+        }
+    }
+
+    /// The value of the `--mount` flag for this mount.
+    pub fn to_arg(&self) -> String {
+        let mut fields = Vec::new();
+        match self {
+            Mount::Bind {
+                source,
+                target,
+                read_only,
+            } => {
+                fields.push("type=bind".to_string());
+                fields.push(format!("source={}", source.display()));
+                fields.push(format!("target={}", target.display()));
+                if *read_only {
+                    fields.push("readonly".to_string());
+                }
+            }
+            Mount::Tmpfs { target, size } => {
+                fields.push("type=tmpfs".to_string());
+                fields.push(format!("target={}", target.display()));
+                if let Some(size) = size {
+                    fields.push(format!("tmpfs-size={}", size));
+                }
+            }
+        }
+        fields
+            .iter()
+            .map(|f| csv_field(f))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Quote a field of the comma-separated `--mount` value if it needs it.
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// DockerDriver runs `docker` and `oci` invocation images in local containers.
+#[derive(Debug, Clone)]
+pub struct DockerDriver {
+    command: PathBuf,
+    config: DockerConfig,
+}
+
+impl Default for DockerDriver {
+    fn default() -> Self {
+        DockerDriver::with_config(DockerConfig::default())
+    }
+}
+
+impl DockerDriver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_config(config: DockerConfig) -> Self {
+        DockerDriver {
+            command: PathBuf::from("docker"),
+            config,
+        }
+    }
+
+    /// Run a different Docker-compatible command instead of `docker` from `PATH`.
+    pub fn command<P: AsRef<Path>>(mut self, command: P) -> Self {
+        self.command = command.as_ref().to_path_buf();
+        self
+    }
+
+    pub fn config(&self) -> &DockerConfig {
+        &self.config
+    }
+
+    /// The arguments of `docker create` for an operation.
+    ///
+    /// Only the names of environment variables are passed, with `--env NAME`, so
+    /// credentials never appear on a command line; their values are taken from the
+    /// environment of the `docker` process.
+    ///
+    /// The operation's Docker extension settings, if any, add `--privileged` and a
+    /// mount of the Docker socket unless one is configured already.
+    pub fn create_args(&self, op: &Operation) -> Vec<String> {
+        let mut args = vec!["create".to_string()];
+        for name in op.environment.keys() {
+            args.push("--env".to_string());
+            args.push(name.clone());
+        }
+        let config = &self.config;
+        let mut mounts = config.mounts.clone();
+        if let Some(docker) = &op.docker {
+            let socket = Mount::docker_socket();
+            if docker.docker_socket && !mounts.contains(&socket) {
+                mounts.push(socket);
+            }
+        }
+        for mount in &mounts {
+            args.push("--mount".to_string());
+            args.push(mount.to_arg());
+        }
+        if op.docker.as_ref().map(|d| d.privileged).unwrap_or(false) {
+            args.push("--privileged".to_string());
+        }
+        if let Some(network) = &config.network {
+            args.push(format!("--network={}", network));
+        }
+        for dns in &config.dns {
+            args.push(format!("--dns={}", dns));
+        }
+        if let Some(cpus) = config.cpus {
+            args.push(format!("--cpus={}", cpus));
+        }
+        if let Some(memory) = config.memory {
+            args.push(format!("--memory={}", memory));
+        }
+        if let Some(user) = &config.user {
+            args.push(format!("--user={}", user));
+        }
+        args.push("--entrypoint".to_string());
+        args.push(RUN_PATH.to_string());
+        args.push(op.image.image.clone());
+        args
+    }
+
+    fn docker(&self) -> Command {
+        Command::new(&self.command)
+    }
+
+    fn execute(
+        &self,
+        op: &Operation,
+        logs: Option<&dyn LogSink>,
+    ) -> Result<OperationResult, DriverError> {
+        if !self.handles(&op.image_type()) {
+            return Err(DriverError::UnsupportedImageType(op.image_type()));
+        }
+        if let Some(e) = op.interrupted() {
+            return Err(e);
+        }
+        let created = self
+            .output(
+                op,
+                self.docker()
+                    .args(self.create_args(op))
+                    .envs(&op.environment),
+            )
+            .map_err(|e| match e {
+                DriverError::Failed { message, .. } if is_transient(&message) => {
+                    DriverError::Transient(message)
+                }
+                e => e,
+            })?;
+        let container = String::from_utf8_lossy(&created).trim().to_string();
+        let result = self.run_container(&container, op, logs);
+        let _ = self
+            .docker()
+            .args(["rm", "--force", &container])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        result
+    }
+
+    fn run_container(
+        &self,
+        container: &str,
+        op: &Operation,
+        logs: Option<&dyn LogSink>,
+    ) -> Result<OperationResult, DriverError> {
+        let staging = std::env::temp_dir().join(format!("libcnab-docker-{}", Ulid::new()));
+        let copied = self.copy_files(container, op, &staging);
+        let _ = std::fs::remove_dir_all(&staging);
+        copied?;
+
+        let (stdout, stderr) = match logs {
+            Some(_) => (Stdio::piped(), Stdio::piped()),
+            None => (Stdio::inherit(), Stdio::inherit()),
+        };
+        let mut child = self
+            .docker()
+            .args(["start", "--attach", container])
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr)
+            .spawn()?;
+        let status = std::thread::scope(|scope| {
+            if let Some(logs) = logs {
+                if let Some(stdout) = child.stdout.take() {
+                    scope.spawn(move || forward_lines(stdout, LogStream::Stdout, logs));
+                }
+                if let Some(stderr) = child.stderr.take() {
+                    scope.spawn(move || forward_lines(stderr, LogStream::Stderr, logs));
+                }
+            }
+            wait(&mut child, op)
+        })?;
+        if !status.success() {
+            return Err(DriverError::Failed {
+                exit_code: status.code(),
+                message: format!("container {} failed", container),
+            });
+        }
+
+        let mut outputs = BTreeMap::new();
+        for (name, path) in &op.outputs {
+            let local = std::env::temp_dir().join(format!("libcnab-output-{}", Ulid::new()));
+            let source = format!("{}:{}", container, path.display());
+            // Outputs the image did not write are left out rather than failing the run.
+            if self
+                .output(op, self.docker().arg("cp").arg(&source).arg(&local))
+                .is_ok()
+            {
+                outputs.insert(name.clone(), OutputContents::File(local));
+            }
+        }
+        Ok(OperationResult { outputs })
+    }
+
+    /// Copy the operation's files into the container through a private staging
+    /// directory.
+    fn copy_files(
+        &self,
+        container: &str,
+        op: &Operation,
+        staging: &Path,
+    ) -> Result<(), DriverError> {
+        #[cfg_attr(not(unix), allow(unused_mut))]
+        let mut builder = std::fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        builder.create(staging)?;
+        for (path, contents) in &op.files {
+            let local = staging.join(path.strip_prefix("/").unwrap_or(path));
+            if let Some(parent) = local.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&local, contents)?;
+        }
+        let source = staging.join(".");
+        self.output(
+            op,
+            self.docker()
+                .arg("cp")
+                .arg(source)
+                .arg(format!("{}:/", container)),
+        )?;
+        Ok(())
+    }
+
+    /// Run a docker command to completion and return its stdout, stopping it if the
+    /// operation is interrupted.
+    fn output(&self, op: &Operation, command: &mut Command) -> Result<Vec<u8>, DriverError> {
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+        std::thread::scope(|scope| {
+            let stdout = scope.spawn(move || read_all(stdout));
+            let stderr = scope.spawn(move || read_all(stderr));
+            let status = wait(&mut child, op)?;
+            let stdout = stdout.join().expect("stdout reader panicked")?;
+            let stderr = stderr.join().expect("stderr reader panicked")?;
+            if status.success() {
+                Ok(stdout)
+            } else {
+                Err(DriverError::Failed {
+                    exit_code: status.code(),
+                    message: String::from_utf8_lossy(&stderr).trim().to_string(),
+                })
+            }
+        })
+    }
+}
+
+/// Whether a `docker create` error, which includes pulling the image, looks like a
+/// network or registry problem rather than a mistake in the operation.
+fn is_transient(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "timeout",
+        "timed out",
+        "connection refused",
+        "connection reset",
+        "toomanyrequests",
+        "500 internal server error",
+        "502 bad gateway",
+        "503 service unavailable",
+        "504 gateway timeout",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+fn read_all<R: Read>(reader: Option<R>) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    if let Some(mut reader) = reader {
+        reader.read_to_end(&mut bytes)?;
+    }
+    Ok(bytes)
+}
+
+impl Driver for DockerDriver {
+    /// The container's output goes to this process's stdout and stderr.
+    fn run(&self, op: &Operation) -> Result<OperationResult, DriverError> {
+        self.execute(op, None)
+    }
+
+    fn run_with_logs(
+        &self,
+        op: &Operation,
+        logs: &dyn LogSink,
+    ) -> Result<OperationResult, DriverError> {
+        self.execute(op, Some(logs))
+    }
+
+    fn handles(&self, image_type: &ImageType) -> bool {
+        matches!(image_type, ImageType::Docker | ImageType::Oci)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::driver::{LogLine, OperationBuilder};
+    use crate::Bundle;
+
+    #[test]
+    fn test_transient_errors() {
+        assert!(is_transient(
+            "Error response from daemon: Get \"https://registry-1.docker.io/v2/\": net/http: request canceled (Client.Timeout exceeded)"
+        ));
+        assert!(is_transient(
+            "received unexpected HTTP status: 503 Service Unavailable"
+        ));
+        assert!(!is_transient("Error: No such image: example/missing:1.0"));
+    }
+
+    #[test]
+    fn test_mount_args() {
+        assert_eq!(
+            Mount::docker_socket().to_arg(),
+            "type=bind,source=/var/run/docker.sock,target=/var/run/docker.sock"
+        );
+        assert_eq!(
+            Mount::bind_read_only("/home/me/a,b", "/cache").to_arg(),
+            "type=bind,\"source=/home/me/a,b\",target=/cache,readonly"
+        );
+        let tmpfs = Mount::Tmpfs {
+            target: PathBuf::from("/tmp"),
+            size: Some(1 << 20),
+        };
+        assert_eq!(tmpfs.to_arg(), "type=tmpfs,target=/tmp,tmpfs-size=1048576");
+    }
+
+    #[test]
+    fn test_docker_extension_args() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let driver = DockerDriver::with_config(DockerConfig {
+            mounts: vec![Mount::docker_socket()],
+            ..DockerConfig::default()
+        });
+        let op = OperationBuilder::new(&bundle, "install", "my-install")
+            .credential("hostkey", "key")
+            .build()
+            .unwrap();
+        assert!(!driver
+            .create_args(&op)
+            .contains(&"--privileged".to_string()));
+
+        bundle.required_extensions = Some(vec![crate::DOCKER_EXTENSION_KEY.to_string()]);
+        bundle.custom = Some(
+            vec![(
+                crate::DOCKER_EXTENSION_KEY.to_string(),
+                serde_json::json!({ "privileged": true }),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        let op = OperationBuilder::new(&bundle, "install", "my-install")
+            .credential("hostkey", "key")
+            .build()
+            .unwrap();
+        let socket = Mount::docker_socket().to_arg();
+        for driver in [driver, DockerDriver::new()] {
+            let args = driver.create_args(&op);
+            assert_eq!(args.iter().filter(|a| **a == socket).count(), 1);
+            assert!(args.contains(&"--privileged".to_string()));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_docker_driver() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("calls");
+        let script = crate::fixture::script(
+            dir.path(),
+            "docker",
+            format!(
+                "#!/bin/sh\necho \"$@\" >> {log}\ncase \"$1\" in\n  create) echo \"HOST_KEY=$HOST_KEY\" >> {log}; echo c0ffee ;;\n  cp) case \"$2\" in\n    c0ffee:*) echo 8080 > \"$3\" ;;\n    *) test -f \"$2/etc/hostkey.txt\" || exit 1 ;;\n  esac ;;\n  start) echo installing; echo careful >&2 ;;\nesac\n",
+                log = log.display()
+            ),
+        );
+
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.outputs = Some(
+            vec![(
+                "port".to_string(),
+                serde_json::from_str(r#"{"definition": "port", "path": "/cnab/app/port"}"#)
+                    .unwrap(),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        let op = OperationBuilder::new(&bundle, "install", "my-install")
+            .credential("hostkey", "key")
+            .build()
+            .unwrap();
+
+        let driver = DockerDriver::with_config(DockerConfig {
+            mounts: vec![Mount::docker_socket()],
+            network: Some("none".to_string()),
+            memory: Some(1 << 30),
+            user: Some("1000:1000".to_string()),
+            ..DockerConfig::default()
+        })
+        .command(&script);
+        let lines = std::sync::Mutex::new(Vec::new());
+        let sink = |line: LogLine| lines.lock().unwrap().push((line.stream, line.line));
+        let result = driver.run_with_logs(&op, &sink).expect("operation ran");
+
+        let mut lines = lines.into_inner().unwrap();
+        lines.sort_by_key(|(stream, _)| *stream == LogStream::Stderr);
+        assert_eq!(
+            lines,
+            vec![
+                (LogStream::Stdout, "installing".to_string()),
+                (LogStream::Stderr, "careful".to_string())
+            ]
+        );
+        let port = match &result.outputs["port"] {
+            OutputContents::File(path) => path.clone(),
+            other => panic!("expected an output file, got {:?}", other),
+        };
+        assert_eq!(std::fs::read_to_string(&port).unwrap(), "8080\n");
+        std::fs::remove_file(port).unwrap();
+
+        let calls = std::fs::read_to_string(&log).unwrap();
+        let calls: Vec<&str> = calls.lines().collect();
+        assert!(calls[0].starts_with("create --env CNAB_ACTION"));
+        assert!(calls[0].contains("--env HOST_KEY --mount type=bind,source=/var/run/docker.sock"));
+        assert!(calls[0].contains(
+            "target=/var/run/docker.sock --network=none --memory=1073741824 --user=1000:1000"
+        ));
+        assert!(calls[0].ends_with("--entrypoint /cnab/app/run technosophos/helloworld:0.1.0"));
+        assert_eq!(calls[1], "HOST_KEY=key");
+        assert!(calls[2].starts_with("cp ") && calls[2].ends_with(" c0ffee:/"));
+        assert_eq!(calls[3], "start --attach c0ffee");
+        assert!(calls[4].starts_with("cp c0ffee:/cnab/app/port "));
+        assert_eq!(calls[5], "rm --force c0ffee");
+    }
+}
@@ -0,0 +1,235 @@
+//! Check a bundle's images against the local Docker daemon.
+use super::DockerDriver;
+use crate::cnab::{is_oci_image, Bundle};
+use crate::driver::DriverError;
+use serde::Deserialize;
+use std::fmt;
+use std::process::Stdio;
+
+/// ImageMismatch is an image of a bundle that the local daemon does not have as the
+/// bundle describes it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageMismatch {
+    /// Where the bundle refers to the image, such as `invocationImages[0]` or
+    /// `images.web`
+    pub path: String,
+    /// The image reference
+    pub image: String,
+    pub problem: ImageProblem,
+}
+
+/// ImageProblem is how a local image differs from what a bundle says of it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageProblem {
+    /// The daemon has no image by the reference
+    Missing,
+    /// The bundle's `contentDigest` is neither the image's id nor one of its
+    /// repository digests
+    Digest {
+        expected: String,
+        actual: Vec<String>,
+    },
+    /// The bundle's `size` is not the size of the image in the daemon
+    Size { expected: i64, actual: i64 },
+}
+
+impl fmt::Display for ImageMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} ", self.path, self.image)?;
+        match &self.problem {
+            ImageProblem::Missing => write!(f, "is not in the local daemon"),
+            ImageProblem::Digest { expected, actual } => write!(
+                f,
+                "has digest {} locally, not {}",
+                actual.join(", "),
+                expected
+            ),
+            ImageProblem::Size { expected, actual } => {
+                write!(f, "is {} bytes locally, not {}", actual, expected)
+            }
+        }
+    }
+}
+
+/// What `docker image inspect` says of an image
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct LocalImage {
+    id: String,
+    repo_digests: Option<Vec<String>>,
+    size: i64,
+}
+
+impl DockerDriver {
+    /// Check that the daemon has each OCI image the bundle refers to, with the
+    /// `contentDigest` and `size` the bundle gives for it, if any, before the bundle
+    /// is pushed anywhere.
+    ///
+    /// A digest matches the image's id or one of its repository digests. An error is
+    /// returned only when the daemon cannot be asked; what it does not have is a
+    /// mismatch.
+    pub fn verify_images(&self, bundle: &Bundle) -> Result<Vec<ImageMismatch>, DriverError> {
+        let invocation_images = bundle
+            .invocation_images
+            .iter()
+            .enumerate()
+            .map(|(i, image)| {
+                (
+                    format!("invocationImages[{}]", i),
+                    image.image_type.as_deref(),
+                    &image.image,
+                    &image.content_digest,
+                    image.size,
+                )
+            });
+        let images = bundle.images.iter().flatten().map(|(name, image)| {
+            (
+                format!("images.{}", name),
+                image.image_type.as_deref(),
+                &image.image,
+                &image.content_digest,
+                image.size,
+            )
+        });
+
+        let mut mismatches = Vec::new();
+        for (path, image_type, image, digest, size) in invocation_images.chain(images) {
+            if !is_oci_image(image_type) {
+                continue;
+            }
+            let mismatch = |problem| ImageMismatch {
+                path: path.clone(),
+                image: image.clone(),
+                problem,
+            };
+            let local = match self.inspect_image(image)? {
+                Some(local) => local,
+                None => {
+                    mismatches.push(mismatch(ImageProblem::Missing));
+                    continue;
+                }
+            };
+            if let Some(expected) = digest {
+                let mut actual = vec![local.id];
+                actual.extend(
+                    local
+                        .repo_digests
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter_map(|d| d.split_once('@').map(|(_, d)| d.to_string())),
+                );
+                if !actual.contains(expected) {
+                    mismatches.push(mismatch(ImageProblem::Digest {
+                        expected: expected.clone(),
+                        actual,
+                    }));
+                }
+            }
+            if let Some(expected) = size {
+                if expected != local.size {
+                    mismatches.push(mismatch(ImageProblem::Size {
+                        expected,
+                        actual: local.size,
+                    }));
+                }
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// The daemon's image by `reference`, if it has one.
+    fn inspect_image(&self, reference: &str) -> Result<Option<LocalImage>, DriverError> {
+        let output = self
+            .docker()
+            .args(["image", "inspect", reference])
+            .stdin(Stdio::null())
+            .output()?;
+        if !output.status.success() {
+            let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            if message.to_lowercase().contains("no such image") {
+                return Ok(None);
+            }
+            return Err(DriverError::Failed {
+                exit_code: output.status.code(),
+                message,
+            });
+        }
+        let images: Vec<LocalImage> =
+            serde_json::from_slice(&output.stdout).map_err(|e| DriverError::Other(Box::new(e)))?;
+        Ok(images.into_iter().next())
+    }
+}
+
+impl Bundle {
+    /// Check the bundle's images against the local Docker daemon, with
+    /// `DockerDriver::verify_images`.
+    ///
+    /// ```no_run
+    /// use libcnab::Bundle;
+    ///
+    /// let bundle = Bundle::from_file("bundle.json").unwrap();
+    /// for mismatch in bundle.verify_local_images().unwrap() {
+    ///     eprintln!("{}", mismatch);
+    /// }
+    /// ```
+    pub fn verify_local_images(&self) -> Result<Vec<ImageMismatch>, DriverError> {
+        DockerDriver::new().verify_images(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_verify_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = crate::fixture::script(
+            dir.path(),
+            "docker",
+            "#!/bin/sh\ncase \"$3\" in\n  technosophos/helloworld:0.1.0) echo '[{\"Id\": \"sha256:1d\", \"RepoDigests\": [\"technosophos/helloworld@sha256:abc\"], \"Size\": 1024}]' ;;\n  technosophos/microservice:1.2.3) echo '[{\"Id\": \"sha256:2d\", \"RepoDigests\": null, \"Size\": 2048}]' ;;\n  *) echo \"Error: No such image: $3\" >&2; exit 1 ;;\nesac\n",
+        );
+        let driver = DockerDriver::new().command(&script);
+
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.invocation_images[0].content_digest = Some("sha256:abc".to_string());
+        bundle.invocation_images[0].size = Some(1024);
+        assert_eq!(driver.verify_images(&bundle).unwrap(), vec![]);
+
+        let mut missing = bundle.invocation_images[0].clone();
+        missing.image = "example/missing:1.0".to_string();
+        bundle.invocation_images.push(missing);
+        let mut wasm = bundle.invocation_images[0].clone();
+        wasm.image_type = Some("wasm".to_string());
+        wasm.image = "module.wasm".to_string();
+        bundle.invocation_images.push(wasm);
+        let microservice = bundle.images.as_mut().unwrap().get_mut("my-microservice");
+        let microservice = microservice.unwrap();
+        microservice.content_digest = Some("sha256:other".to_string());
+        microservice.size = Some(4096);
+
+        let mismatches = driver.verify_images(&bundle).unwrap();
+        assert_eq!(
+            mismatches
+                .iter()
+                .map(|m| m.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "invocationImages[1]: example/missing:1.0 is not in the local daemon",
+                "images.my-microservice: technosophos/microservice:1.2.3 has digest sha256:2d locally, not sha256:other",
+                "images.my-microservice: technosophos/microservice:1.2.3 is 2048 bytes locally, not 4096",
+            ]
+        );
+
+        std::fs::write(
+            &script,
+            "#!/bin/sh\necho 'Cannot connect to the Docker daemon' >&2\nexit 1\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            driver.verify_images(&bundle),
+            Err(DriverError::Failed { .. })
+        ));
+    }
+}
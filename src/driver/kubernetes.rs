@@ -0,0 +1,910 @@
+//! A driver that runs invocation images as Kubernetes Jobs with `kubectl`.
+//!
+//! Each operation creates a Secret holding the operation's environment and files, and
+//! a Job whose pod runs the invocation image with them. The driver waits for the
+//! invocation image to exit and then deletes both. `kubectl` talks to whichever
+//! cluster its current context points at.
+//!
+//! The generated Job can be customized with `PodOptions`, for the common settings, or
+//! with a patch function that edits the Job's JSON just before it is applied:
+//!
+//! ```no_run
+//! use libcnab::driver::{KubernetesDriver, PodOptions, Toleration};
+//!
+//! let mut options = PodOptions::default();
+//! options.node_selector.insert("pool".to_string(), "bundles".to_string());
+//! options.tolerations.push(Toleration::exists("dedicated"));
+//! options.resources.limits.insert("memory".to_string(), "512Mi".to_string());
+//! let driver = KubernetesDriver::new()
+//!     .namespace("cnab")
+//!     .options(options)
+//!     .patch(|job| job["spec"]["ttlSecondsAfterFinished"] = 600.into());
+//! ```
+//!
+//! A finished container cannot be read from, so when the operation has outputs the
+//! directories they are written to are shared with a second container, `outputs`,
+//! that keeps running until the driver has copied the outputs out of it with
+//! `kubectl exec`. Its image, `busybox` unless set with `outputs_image`, needs `sh`
+//! and `cat`. The directories are empty volumes, so an output may not be written
+//! to a directory that holds the image's `/cnab/app/run`.
+use super::{
+    forward_lines, wait, Driver, DriverError, ImageType, LogSink, LogStream, Operation,
+    OperationResult, OutputContents,
+};
+use crate::layout::RUN_PATH;
+use base64::Engine;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use ulid::Ulid;
+
+/// The label holding the installation name on the resources the driver creates
+pub const INSTALLATION_LABEL: &str = "cnab.io/installation";
+/// The label holding the action on the resources the driver creates
+pub const ACTION_LABEL: &str = "cnab.io/action";
+/// The label holding the revision on the resources the driver creates
+pub const REVISION_LABEL: &str = "cnab.io/revision";
+
+/// The image of the container that holds the outputs, unless set with
+/// `KubernetesDriver::outputs_image`
+pub const DEFAULT_OUTPUTS_IMAGE: &str = "busybox:1.36";
+
+/// The container that runs the invocation image
+const INVOCATION_CONTAINER: &str = "invocation";
+/// The container that holds the outputs until they are copied out
+const OUTPUTS_CONTAINER: &str = "outputs";
+
+/// How often the driver checks whether the invocation image has exited
+const JOB_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Why a container waits to start when it never will without someone fixing the
+/// image or the pod, so the operation fails rather than waits
+const FATAL_WAITING_REASONS: &[&str] = &[
+    "ErrImagePull",
+    "ImagePullBackOff",
+    "CreateContainerConfigError",
+    "InvalidImageName",
+];
+
+/// PodOptions customizes the pod of each Job the driver creates.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PodOptions {
+    /// Labels added to the Job and its pod
+    pub labels: BTreeMap<String, String>,
+    /// Annotations added to the Job and its pod
+    pub annotations: BTreeMap<String, String>,
+    pub node_selector: BTreeMap<String, String>,
+    pub tolerations: Vec<Toleration>,
+    /// A Kubernetes `Affinity` object, used as is
+    pub affinity: Option<Value>,
+    /// The names of the Secrets used to pull the invocation image
+    pub image_pull_secrets: Vec<String>,
+    /// The service account to run the pod as
+    pub service_account: Option<String>,
+    pub resources: ResourceRequirements,
+}
+
+/// Toleration lets the pod be scheduled on nodes with a matching taint.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Toleration {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// `Equal` or `Exists`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// `NoSchedule`, `PreferNoSchedule` or `NoExecute`; all effects if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub effect: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub toleration_seconds: Option<i64>,
+}
+
+impl Toleration {
+    /// Tolerate any taint with the given key.
+    pub fn exists(key: &str) -> Self {
+        Toleration {
+            key: Some(key.to_string()),
+            operator: Some("Exists".to_string()),
+            ..Toleration::default()
+        }
+    }
+
+    /// Tolerate taints with the given key and value.
+    pub fn equal(key: &str, value: &str) -> Self {
+        Toleration {
+            key: Some(key.to_string()),
+            operator: Some("Equal".to_string()),
+            value: Some(value.to_string()),
+            ..Toleration::default()
+        }
+    }
+}
+
+/// ResourceRequirements are the compute resources of the invocation image's container,
+/// as Kubernetes quantities such as `cpu: 500m` or `memory: 1Gi`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ResourceRequirements {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub requests: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub limits: BTreeMap<String, String>,
+}
+
+type JobPatch = Box<dyn Fn(&mut Value) + Send + Sync>;
+
+/// KubernetesDriver runs `docker` and `oci` invocation images as Kubernetes Jobs.
+pub struct KubernetesDriver {
+    command: PathBuf,
+    namespace: Option<String>,
+    options: PodOptions,
+    outputs_image: String,
+    patches: Vec<JobPatch>,
+}
+
+impl Default for KubernetesDriver {
+    fn default() -> Self {
+        KubernetesDriver {
+            command: PathBuf::from("kubectl"),
+            namespace: None,
+            options: PodOptions::default(),
+            outputs_image: DEFAULT_OUTPUTS_IMAGE.to_string(),
+            patches: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Debug for KubernetesDriver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KubernetesDriver")
+            .field("command", &self.command)
+            .field("namespace", &self.namespace)
+            .field("options", &self.options)
+            .field("outputs_image", &self.outputs_image)
+            .field("patches", &self.patches.len())
+            .finish()
+    }
+}
+
+impl KubernetesDriver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run a different command instead of `kubectl` from `PATH`.
+    pub fn command<P: AsRef<Path>>(mut self, command: P) -> Self {
+        self.command = command.as_ref().to_path_buf();
+        self
+    }
+
+    /// The namespace to create Jobs in. Defaults to the namespace of the current
+    /// context.
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.namespace = Some(namespace.to_string());
+        self
+    }
+
+    pub fn options(mut self, options: PodOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// The image of the container that holds the outputs of an operation. It must
+    /// have `sh` and `cat`.
+    pub fn outputs_image(mut self, image: &str) -> Self {
+        self.outputs_image = image.to_string();
+        self
+    }
+
+    /// Edit the JSON of each Job after `PodOptions` are applied. Patches run in the
+    /// order they were added.
+    pub fn patch<F>(mut self, patch: F) -> Self
+    where
+        F: Fn(&mut Value) + Send + Sync + 'static,
+    {
+        self.patches.push(Box::new(patch));
+        self
+    }
+
+    /// The name of the Job and Secret created for an operation.
+    pub fn resource_name(op: &Operation) -> String {
+        format!("cnab-{}", label_value(&op.revision))
+    }
+
+    /// The Secret holding the operation's environment and files.
+    pub fn secret(&self, op: &Operation) -> Value {
+        let encoder = base64::engine::general_purpose::STANDARD;
+        let mut data = serde_json::Map::new();
+        for (name, value) in &op.environment {
+            data.insert(env_key(name), encoder.encode(value).into());
+        }
+        for (index, contents) in op.files.values().enumerate() {
+            data.insert(file_key(index), encoder.encode(contents).into());
+        }
+        json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": self.metadata(op, false),
+            "type": "Opaque",
+            "data": data,
+        })
+    }
+
+    /// The Job that runs the operation, with the options and patches applied.
+    pub fn job(&self, op: &Operation) -> Value {
+        let name = Self::resource_name(op);
+        let env: Vec<Value> = op
+            .environment
+            .keys()
+            .map(|key| {
+                json!({
+                    "name": key,
+                    "valueFrom": { "secretKeyRef": { "name": name, "key": env_key(key) } },
+                })
+            })
+            .collect();
+        let mut mounts: Vec<Value> = op
+            .files
+            .keys()
+            .enumerate()
+            .map(|(index, path)| {
+                json!({
+                    "name": "files",
+                    "mountPath": path,
+                    "subPath": file_key(index),
+                    "readOnly": true,
+                })
+            })
+            .collect();
+
+        let output_mounts: Vec<Value> = output_dirs(op)
+            .iter()
+            .enumerate()
+            .map(|(index, dir)| {
+                json!({
+                    "name": "outputs",
+                    "mountPath": dir,
+                    "subPath": format!("dir.{}", index),
+                })
+            })
+            .collect();
+        mounts.extend(output_mounts.iter().cloned());
+
+        let options = &self.options;
+        let mut container = json!({
+            "name": INVOCATION_CONTAINER,
+            "image": op.image.image,
+            "command": [RUN_PATH],
+            "env": env,
+            "volumeMounts": mounts,
+        });
+        if options.resources != ResourceRequirements::default() {
+            container["resources"] = json!(options.resources);
+        }
+        let mut containers = vec![container];
+        let mut volumes = vec![json!({ "name": "files", "secret": { "secretName": name } })];
+        if !output_mounts.is_empty() {
+            containers.push(json!({
+                "name": OUTPUTS_CONTAINER,
+                "image": self.outputs_image,
+                "command": ["sh", "-c", "trap 'exit 0' TERM; while true; do sleep 1; done"],
+                "volumeMounts": output_mounts,
+            }));
+            volumes.push(json!({ "name": "outputs", "emptyDir": {} }));
+        }
+        let mut pod = json!({
+            "restartPolicy": "Never",
+            "containers": containers,
+            "volumes": volumes,
+        });
+        if !options.node_selector.is_empty() {
+            pod["nodeSelector"] = json!(options.node_selector);
+        }
+        if !options.tolerations.is_empty() {
+            pod["tolerations"] = json!(options.tolerations);
+        }
+        if let Some(affinity) = &options.affinity {
+            pod["affinity"] = affinity.clone();
+        }
+        if !options.image_pull_secrets.is_empty() {
+            pod["imagePullSecrets"] = options
+                .image_pull_secrets
+                .iter()
+                .map(|name| json!({ "name": name }))
+                .collect();
+        }
+        if let Some(account) = &options.service_account {
+            pod["serviceAccountName"] = account.clone().into();
+        }
+
+        let mut job = json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": self.metadata(op, true),
+            "spec": {
+                "backoffLimit": 0,
+                "template": {
+                    "metadata": self.metadata(op, true),
+                    "spec": pod,
+                },
+            },
+        });
+        job["spec"]["template"]["metadata"]
+            .as_object_mut()
+            .expect("metadata is an object")
+            .remove("name");
+        for patch in &self.patches {
+            patch(&mut job);
+        }
+        job
+    }
+
+    fn metadata(&self, op: &Operation, customized: bool) -> Value {
+        let mut labels = BTreeMap::new();
+        let mut annotations = BTreeMap::new();
+        if customized {
+            labels.extend(self.options.labels.clone());
+            annotations.extend(self.options.annotations.clone());
+        }
+        labels.insert(
+            INSTALLATION_LABEL.to_string(),
+            label_value(&op.installation),
+        );
+        labels.insert(ACTION_LABEL.to_string(), label_value(&op.action));
+        labels.insert(REVISION_LABEL.to_string(), label_value(&op.revision));
+        let mut metadata = json!({ "name": Self::resource_name(op), "labels": labels });
+        if !annotations.is_empty() {
+            metadata["annotations"] = json!(annotations);
+        }
+        metadata
+    }
+
+    fn kubectl(&self) -> Command {
+        let mut command = Command::new(&self.command);
+        if let Some(namespace) = &self.namespace {
+            command.arg("--namespace").arg(namespace);
+        }
+        command
+    }
+
+    fn execute(
+        &self,
+        op: &Operation,
+        logs: Option<&dyn LogSink>,
+    ) -> Result<OperationResult, DriverError> {
+        if !self.handles(&op.image_type()) {
+            return Err(DriverError::UnsupportedImageType(op.image_type()));
+        }
+        if let Some(dir) = output_dirs(op)
+            .into_iter()
+            .find(|dir| Path::new(RUN_PATH).starts_with(dir))
+        {
+            return Err(DriverError::Other(
+                format!(
+                    "outputs cannot be collected from {}, which holds {}",
+                    dir.display(),
+                    RUN_PATH
+                )
+                .into(),
+            ));
+        }
+        if let Some(e) = op.interrupted() {
+            return Err(e);
+        }
+
+        let resources = json!({
+            "apiVersion": "v1",
+            "kind": "List",
+            "items": [self.secret(op), self.job(op)],
+        });
+        let resources = serde_json::to_vec(&resources).expect("json values serialize");
+        let mut apply = self
+            .kubectl()
+            .args(["apply", "--filename", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // The Secret carries every file, so it is written while stderr is read:
+        // kubectl could otherwise block on a full stderr before reading all of it.
+        let applied = std::thread::scope(|scope| {
+            let stdin = apply.stdin.take();
+            let resources = &resources;
+            let written = scope.spawn(move || match stdin {
+                Some(mut stdin) => stdin.write_all(resources),
+                None => Ok(()),
+            });
+            let applied = apply.wait_with_output()?;
+            // kubectl exits without reading all of stdin when it fails; its exit
+            // status and stderr explain why.
+            match written.join().expect("stdin writer panicked") {
+                Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(e),
+                _ => Ok(applied),
+            }
+        })?;
+        if !applied.status.success() {
+            return Err(DriverError::Failed {
+                exit_code: applied.status.code(),
+                message: String::from_utf8_lossy(&applied.stderr).trim().to_string(),
+            });
+        }
+
+        let name = Self::resource_name(op);
+        let result = self
+            .wait_for_job(&name, op, logs)
+            .and_then(|pod| self.collect_outputs(&pod, op));
+        let _ = self
+            .kubectl()
+            .args(["delete", "--ignore-not-found", "--wait=false"])
+            .arg(format!("job/{}", name))
+            .arg(format!("secret/{}", name))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        result
+    }
+
+    /// Copy the outputs out of the `outputs` container of `pod`.
+    fn collect_outputs(&self, pod: &str, op: &Operation) -> Result<OperationResult, DriverError> {
+        let mut outputs = BTreeMap::new();
+        for (name, path) in &op.outputs {
+            let local = std::env::temp_dir().join(format!("libcnab-output-{}", Ulid::new()));
+            let status = self
+                .kubectl()
+                .args(["exec", pod, "--container", OUTPUTS_CONTAINER, "--", "cat"])
+                .arg(path)
+                .stdin(Stdio::null())
+                .stdout(std::fs::File::create(&local)?)
+                .stderr(Stdio::null())
+                .status()?;
+            // Outputs the image did not write are left out rather than failing the run.
+            if status.success() {
+                outputs.insert(name.clone(), OutputContents::File(local));
+            } else {
+                let _ = std::fs::remove_file(&local);
+            }
+        }
+        Ok(OperationResult { outputs })
+    }
+
+    /// Wait for the invocation image to exit successfully, following its logs, and
+    /// return the name of its pod.
+    fn wait_for_job(
+        &self,
+        name: &str,
+        op: &Operation,
+        logs: Option<&dyn LogSink>,
+    ) -> Result<String, DriverError> {
+        std::thread::scope(|scope| {
+            let follower = match logs {
+                Some(logs) => {
+                    let mut child = self
+                        .kubectl()
+                        .args(["logs", "--follow", "--pod-running-timeout=1h"])
+                        .args(["--container", INVOCATION_CONTAINER])
+                        .arg(format!("job/{}", name))
+                        .stdout(Stdio::piped())
+                        .stderr(Stdio::null())
+                        .spawn()?;
+                    if let Some(stdout) = child.stdout.take() {
+                        scope.spawn(move || forward_lines(stdout, LogStream::Stdout, logs));
+                    }
+                    Some(child)
+                }
+                None => None,
+            };
+            let finished = self.poll_pod(name, op);
+            if let Some(mut child) = follower {
+                if finished.is_ok() {
+                    // The log stream ends with the container; wait for the last lines.
+                    let _ = wait(&mut child, op);
+                } else {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                }
+            }
+            finished
+        })
+    }
+
+    /// Wait until the invocation image of the Job's pod exits, cannot start, or the
+    /// operation is interrupted, and return the name of the pod. The pod does not finish by itself
+    /// when it has an `outputs` container, so the Job is not waited on.
+    fn poll_pod(&self, name: &str, op: &Operation) -> Result<String, DriverError> {
+        let selector = format!("{}={}", REVISION_LABEL, label_value(&op.revision));
+        loop {
+            let output = self
+                .kubectl()
+                .args(["get", "pods", "--selector", &selector, "--output", "json"])
+                .stderr(Stdio::piped())
+                .output()?;
+            if !output.status.success() {
+                return Err(DriverError::Failed {
+                    exit_code: output.status.code(),
+                    message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                });
+            }
+            let pods: Value =
+                serde_json::from_slice(&output.stdout).map_err(|e| DriverError::Other(e.into()))?;
+            if let Some(pod) = pods["items"].as_array().and_then(|items| items.first()) {
+                let state = pod["status"]["containerStatuses"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .find(|status| status["name"] == INVOCATION_CONTAINER)
+                    .map(|status| &status["state"]);
+                let waiting = state.map(|state| &state["waiting"]);
+                if let Some(reason) = waiting
+                    .and_then(|waiting| waiting["reason"].as_str())
+                    .filter(|reason| FATAL_WAITING_REASONS.contains(reason))
+                {
+                    let message = match waiting.and_then(|w| w["message"].as_str()) {
+                        Some(message) => {
+                            format!("job {} cannot start: {}: {}", name, reason, message)
+                        }
+                        None => format!("job {} cannot start: {}", name, reason),
+                    };
+                    return Err(DriverError::Failed {
+                        exit_code: None,
+                        message,
+                    });
+                }
+                let terminated = state
+                    .map(|state| &state["terminated"])
+                    .filter(|terminated| terminated.is_object());
+                match terminated.map(|t| t["exitCode"].as_i64()) {
+                    Some(Some(0)) => {
+                        let pod = pod["metadata"]["name"].as_str().unwrap_or_default();
+                        return Ok(pod.to_string());
+                    }
+                    Some(code) => {
+                        return Err(DriverError::Failed {
+                            exit_code: code.map(|c| c as i32),
+                            message: format!("job {} failed", name),
+                        })
+                    }
+                    None if pod["status"]["phase"] == "Failed" => {
+                        return Err(DriverError::Failed {
+                            exit_code: None,
+                            message: format!("job {} failed", name),
+                        })
+                    }
+                    None => {}
+                }
+            }
+            if let Some(e) = op.interrupted() {
+                return Err(e);
+            }
+            op.cancellation.sleep(JOB_POLL_INTERVAL);
+        }
+    }
+}
+
+impl Driver for KubernetesDriver {
+    /// The pod's logs are not read; use `run_with_logs` to stream them.
+    fn run(&self, op: &Operation) -> Result<OperationResult, DriverError> {
+        self.execute(op, None)
+    }
+
+    fn run_with_logs(
+        &self,
+        op: &Operation,
+        logs: &dyn LogSink,
+    ) -> Result<OperationResult, DriverError> {
+        self.execute(op, Some(logs))
+    }
+
+    fn handles(&self, image_type: &ImageType) -> bool {
+        matches!(image_type, ImageType::Docker | ImageType::Oci)
+    }
+}
+
+/// The directories the operation's outputs are written to, which are shared with the
+/// `outputs` container.
+fn output_dirs(op: &Operation) -> BTreeSet<&Path> {
+    op.outputs
+        .values()
+        .filter_map(|path| path.parent())
+        .collect()
+}
+
+/// The Secret key holding an environment variable.
+fn env_key(name: &str) -> String {
+    format!("env.{}", secret_key(name))
+}
+
+/// The Secret key holding the file at an index of the operation's files.
+fn file_key(index: usize) -> String {
+    format!("file.{}", index)
+}
+
+/// Replace the characters Secret keys cannot contain.
+fn secret_key(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Turn any string into a valid label value: lowercase alphanumerics and dashes, at
+/// most 50 characters so it can also be used in resource names.
+fn label_value(value: &str) -> String {
+    let value: String = value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .take(50)
+        .collect();
+    value.trim_matches('-').to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::driver::OperationBuilder;
+    use crate::Bundle;
+
+    fn operation() -> Operation {
+        operation_with_outputs(&[])
+    }
+
+    /// The install operation of a bundle with outputs at each of `paths`.
+    fn operation_with_outputs(paths: &[(&str, &str)]) -> Operation {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        if !paths.is_empty() {
+            let outputs = paths
+                .iter()
+                .map(|(name, path)| {
+                    let output = json!({ "definition": "port", "path": path });
+                    (name.to_string(), serde_json::from_value(output).unwrap())
+                })
+                .collect();
+            bundle.outputs = Some(outputs);
+        }
+        OperationBuilder::new(&bundle, "install", "My Install")
+            .revision("01CP6XM0KVB9V1BQDZ9NK8VP29")
+            .credential("hostkey", "key")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_job_spec() {
+        let op = operation();
+        let mut options = PodOptions::default();
+        options
+            .labels
+            .insert("team".to_string(), "platform".to_string());
+        options
+            .node_selector
+            .insert("pool".to_string(), "bundles".to_string());
+        options.tolerations.push(Toleration::exists("dedicated"));
+        options.image_pull_secrets.push("registry".to_string());
+        options
+            .resources
+            .limits
+            .insert("memory".to_string(), "512Mi".to_string());
+        let driver = KubernetesDriver::new()
+            .options(options)
+            .patch(|job| job["spec"]["ttlSecondsAfterFinished"] = 600.into());
+
+        let job = driver.job(&op);
+        assert_eq!(job["metadata"]["name"], "cnab-01cp6xm0kvb9v1bqdz9nk8vp29");
+        assert_eq!(job["metadata"]["labels"][INSTALLATION_LABEL], "my-install");
+        assert_eq!(job["metadata"]["labels"]["team"], "platform");
+        assert_eq!(job["spec"]["ttlSecondsAfterFinished"], 600);
+        let template = &job["spec"]["template"];
+        assert!(template["metadata"].get("name").is_none());
+        let pod = &template["spec"];
+        assert_eq!(pod["nodeSelector"]["pool"], "bundles");
+        assert_eq!(
+            pod["tolerations"],
+            json!([{ "key": "dedicated", "operator": "Exists" }])
+        );
+        assert_eq!(pod["imagePullSecrets"], json!([{ "name": "registry" }]));
+        assert!(pod.get("affinity").is_none());
+
+        let container = &pod["containers"][0];
+        assert_eq!(container["image"], "technosophos/helloworld:0.1.0");
+        assert_eq!(
+            container["resources"],
+            json!({ "limits": { "memory": "512Mi" } })
+        );
+        let env = container["env"].as_array().unwrap();
+        let host_key = env.iter().find(|e| e["name"] == "HOST_KEY").unwrap();
+        assert_eq!(host_key["valueFrom"]["secretKeyRef"]["key"], "env.HOST_KEY");
+        let mounts = container["volumeMounts"].as_array().unwrap();
+        assert_eq!(mounts.len(), op.files.len());
+        assert!(mounts.iter().any(|m| m["mountPath"] == "/etc/hostkey.txt"));
+
+        let secret = driver.secret(&op);
+        assert!(secret["metadata"]["labels"].get("team").is_none());
+        assert_eq!(secret["data"]["env.HOST_KEY"], "a2V5");
+        assert_eq!(pod["containers"].as_array().unwrap().len(), 1);
+
+        // Outputs are written to a volume shared with the outputs container.
+        let op = operation_with_outputs(&[
+            ("port", "/cnab/app/outputs/port"),
+            ("address", "/cnab/app/outputs/address"),
+            ("report", "/tmp/report"),
+        ]);
+        let job = KubernetesDriver::new().outputs_image("alpine:3").job(&op);
+        let pod = &job["spec"]["template"]["spec"];
+        let mounts = json!([
+            { "name": "outputs", "mountPath": "/cnab/app/outputs", "subPath": "dir.0" },
+            { "name": "outputs", "mountPath": "/tmp", "subPath": "dir.1" },
+        ]);
+        let invocation = &pod["containers"][0]["volumeMounts"];
+        assert!(mounts
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|m| invocation.as_array().unwrap().contains(m)));
+        let outputs = &pod["containers"][1];
+        assert_eq!(outputs["name"], "outputs");
+        assert_eq!(outputs["image"], "alpine:3");
+        assert_eq!(outputs["volumeMounts"], mounts);
+        assert_eq!(
+            pod["volumes"][1],
+            json!({ "name": "outputs", "emptyDir": {} })
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_kubernetes_driver() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("calls");
+        let pods = json!({ "items": [{
+            "metadata": { "name": "cnab-pod" },
+            "status": {
+                "phase": "Running",
+                "containerStatuses": [
+                    { "name": "outputs", "state": { "running": {} } },
+                    { "name": "invocation", "state": { "terminated": { "exitCode": 0 } } },
+                ],
+            },
+        }]});
+        let script = crate::fixture::script(
+            dir.path(),
+            "kubectl",
+            format!(
+                "#!/bin/sh\necho \"$@\" >> {log}\ncase \"$3\" in\n  apply) cat > /dev/null ;;\n  get) echo '{pods}' ;;\n  logs) echo installing ;;\n  exec) test \"$9\" = /cnab/app/outputs/port || exit 1; echo 8080 ;;\nesac\n",
+                log = log.display(),
+                pods = pods
+            ),
+        );
+
+        let driver = KubernetesDriver::new().namespace("cnab").command(&script);
+        let lines = std::sync::Mutex::new(Vec::new());
+        let sink = |line: crate::driver::LogLine| lines.lock().unwrap().push(line.line);
+        let op = operation_with_outputs(&[
+            ("port", "/cnab/app/outputs/port"),
+            ("missing", "/cnab/app/outputs/missing"),
+        ]);
+        let result = driver.run_with_logs(&op, &sink).expect("operation ran");
+        assert_eq!(lines.into_inner().unwrap(), vec!["installing"]);
+        assert_eq!(result.outputs.keys().collect::<Vec<_>>(), vec!["port"]);
+        let port = match &result.outputs["port"] {
+            OutputContents::File(path) => path.clone(),
+            other => panic!("expected an output file, got {:?}", other),
+        };
+        assert_eq!(std::fs::read_to_string(&port).unwrap(), "8080\n");
+        std::fs::remove_file(port).unwrap();
+
+        let calls = std::fs::read_to_string(&log).unwrap();
+        let name = "cnab-01cp6xm0kvb9v1bqdz9nk8vp29";
+        assert!(calls.starts_with("--namespace cnab apply --filename -\n"));
+        assert!(calls.contains(&format!(
+            "--namespace cnab logs --follow --pod-running-timeout=1h --container invocation job/{}\n",
+            name
+        )));
+        assert!(calls.contains(
+            "--namespace cnab get pods --selector cnab.io/revision=01cp6xm0kvb9v1bqdz9nk8vp29 --output json\n"
+        ));
+        assert!(calls.contains(
+            "--namespace cnab exec cnab-pod --container outputs -- cat /cnab/app/outputs/port\n"
+        ));
+        assert!(calls.ends_with(&format!(
+            "--namespace cnab delete --ignore-not-found --wait=false job/{} secret/{}\n",
+            name, name
+        )));
+
+        // Outputs may not hide the image's run tool.
+        let op = operation_with_outputs(&[("port", "/cnab/app/port")]);
+        match driver.run(&op) {
+            Err(DriverError::Other(e)) => assert_eq!(
+                e.to_string(),
+                "outputs cannot be collected from /cnab/app, which holds /cnab/app/run"
+            ),
+            other => panic!("expected the outputs to be refused, got {:?}", other),
+        }
+
+        // A failed invocation image fails the operation with its exit code.
+        let failed = pods
+            .to_string()
+            .replace(r#""exitCode":0"#, r#""exitCode":3"#);
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\ncase \"$3\" in\n  get) echo '{}' ;;\nesac\n",
+                failed
+            ),
+        )
+        .unwrap();
+        match driver.run(&operation()) {
+            Err(DriverError::Failed { exit_code, .. }) => assert_eq!(exit_code, Some(3)),
+            other => panic!("expected a failure, got {:?}", other),
+        }
+
+        // An invocation image that can never start fails the operation rather than
+        // being waited on.
+        for reason in FATAL_WAITING_REASONS {
+            let waiting = json!({ "items": [{
+                "metadata": { "name": "cnab-pod" },
+                "status": {
+                    "phase": "Pending",
+                    "containerStatuses": [{
+                        "name": "invocation",
+                        "state": { "waiting": { "reason": reason, "message": "no such image" } },
+                    }],
+                },
+            }]});
+            std::fs::write(
+                &script,
+                format!(
+                    "#!/bin/sh\ncase \"$3\" in\n  get) echo '{}' ;;\nesac\n",
+                    waiting
+                ),
+            )
+            .unwrap();
+            match driver.run(&operation()) {
+                Err(DriverError::Failed { exit_code, message }) => {
+                    assert_eq!(exit_code, None);
+                    assert_eq!(
+                        message,
+                        format!(
+                            "job cnab-01cp6xm0kvb9v1bqdz9nk8vp29 cannot start: {}: no such image",
+                            reason
+                        )
+                    );
+                }
+                other => panic!("expected {} to fail the operation, got {:?}", reason, other),
+            }
+        }
+
+        // kubectl refusing a large Secret before reading it fails with its stderr.
+        std::fs::write(
+            &script,
+            "#!/bin/sh\nhead -c 1 > /dev/null\nhead -c 200000 /dev/zero | tr '\\0' x >&2\nexit 1\n",
+        )
+        .unwrap();
+        let mut large = operation();
+        large
+            .files
+            .insert("/cnab/app/large".into(), vec![b'x'; 1 << 20]);
+        match driver.run(&large) {
+            Err(DriverError::Failed { exit_code, message }) => {
+                assert_eq!(exit_code, Some(1));
+                assert_eq!(message.len(), 200_000);
+            }
+            other => panic!("expected apply to fail, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use crate::events::Event;
+use chrono::{DateTime, Utc};
+use std::io::{BufRead, BufReader, Read};
+
+/// The output stream of the invocation image a log line was written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+/// LogLine is a single line of output from a running invocation image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLine {
+    pub stream: LogStream,
+    /// When the driver received the line
+    pub timestamp: DateTime<Utc>,
+    /// The line, without its trailing newline
+    pub line: String,
+}
+
+impl LogLine {
+    pub fn new(stream: LogStream, line: &str) -> Self {
+        LogLine {
+            stream,
+            timestamp: Utc::now(),
+            line: line.trim_end_matches(&['\r', '\n'][..]).to_string(),
+        }
+    }
+
+    /// The structured event carried by this line, if it is one.
+    pub fn event(&self) -> Option<Event> {
+        Event::parse(&self.line)
+    }
+}
+
+/// LogSink receives the output of invocation images as it is produced.
+///
+/// Drivers may call `log` from several threads at once. Any
+/// `Fn(LogLine) + Send + Sync` is a LogSink.
+pub trait LogSink: Send + Sync {
+    fn log(&self, line: LogLine);
+}
+
+impl<F> LogSink for F
+where
+    F: Fn(LogLine) + Send + Sync,
+{
+    fn log(&self, line: LogLine) {
+        self(line)
+    }
+}
+
+/// A LogSink that drops everything
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DiscardLogs;
+
+impl LogSink for DiscardLogs {
+    fn log(&self, _line: LogLine) {}
+}
+
+/// Send each line read from `reader` to `sink` until the reader is exhausted.
+///
+/// Drivers use this to forward a container's stdout or stderr, usually from a thread
+/// per stream. Invalid UTF-8 is replaced rather than treated as an error.
+pub fn forward_lines<R: Read>(
+    reader: R,
+    stream: LogStream,
+    sink: &dyn LogSink,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(());
+        }
+        sink.log(LogLine::new(stream, &String::from_utf8_lossy(&buf)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_forward_lines() {
+        let lines = Mutex::new(Vec::new());
+        let sink = |line: LogLine| lines.lock().unwrap().push(line);
+        let output = format!(
+            "pulling images\r\n{}\nno newline",
+            Event::output("port").to_line()
+        );
+        forward_lines(output.as_bytes(), LogStream::Stderr, &sink).unwrap();
+
+        let lines = lines.into_inner().unwrap();
+        assert_eq!(
+            lines.iter().map(|l| l.line.as_str()).collect::<Vec<_>>(),
+            vec![
+                "pulling images",
+                r#"::cnab {"type":"output","name":"port"}"#,
+                "no newline"
+            ]
+        );
+        assert!(lines.iter().all(|l| l.stream == LogStream::Stderr));
+        assert_eq!(lines[1].event(), Some(Event::output("port")));
+        assert_eq!(lines[0].event(), None);
+    }
+}
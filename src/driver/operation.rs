@@ -0,0 +1,654 @@
+use super::Redactor;
+use crate::cancel::CancellationToken;
+use crate::cnab::{Bundle, InvocationImage, BUILTIN_ACTIONS};
+use crate::docker_extension::DockerExtension;
+use crate::encoding::EncodingError;
+use crate::error_code::{Coded, ErrorCode};
+use crate::layout::{BUNDLE_PATH, OUTPUTS_DIR, PARAMETERS_DIR, RELOCATION_MAPPING_PATH};
+use crate::relocation::RelocationMap;
+use crate::runtime::{
+    coerce, CNAB_ACTION, CNAB_BUNDLE_NAME, CNAB_BUNDLE_VERSION, CNAB_INSTALLATION_NAME,
+    CNAB_REVISION,
+};
+use crate::schema::{check_value, resolve};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use ulid::Ulid;
+
+/// Operation describes a single invocation of an invocation image.
+///
+/// It is the contract between the bundle model and a driver: the driver starts
+/// `image` with `environment` set and `files` written, and collects `outputs`
+/// after the image exits.
+///
+/// Its `Debug` output is that of the redacted operation.
+#[derive(Clone)]
+pub struct Operation {
+    /// The action to perform
+    pub action: String,
+    /// The name of the installation the action is performed against
+    pub installation: String,
+    /// The revision the action creates
+    pub revision: String,
+    /// The image to run
+    pub image: InvocationImage,
+    /// Environment variables to set in the image
+    pub environment: BTreeMap<String, String>,
+    /// Files to create in the image, keyed by absolute path
+    pub files: BTreeMap<PathBuf, Vec<u8>>,
+    /// The outputs the action produces, mapped to the path the image writes them to
+    pub outputs: BTreeMap<String, PathBuf>,
+    /// The environment variables holding credentials or sensitive parameters
+    pub sensitive_environment: BTreeSet<String>,
+    /// The files holding credentials or sensitive parameters
+    pub sensitive_files: BTreeSet<PathBuf>,
+    /// When the driver should give up on the image and stop it, if ever
+    pub deadline: Option<Instant>,
+    /// Cancelling the token asks the driver to stop the image
+    pub cancellation: CancellationToken,
+    /// Problems found while building the operation that did not stop it
+    pub warnings: Vec<String>,
+    /// What the image needs from a Docker host, when the bundle requires the Docker
+    /// extension
+    pub docker: Option<DockerExtension>,
+}
+
+/// The text that replaces sensitive values in a redacted operation
+pub const REDACTED: &str = "*******";
+
+/// The largest parameter value passed in an environment variable, in bytes
+///
+/// Container runtimes and kernels reject environment variables well below the size of
+/// a parameter such as a kubeconfig or a certificate bundle, so larger values are
+/// written to a file instead.
+pub const MAX_ENV_VALUE_SIZE: usize = 32 * 1024;
+
+impl Operation {
+    /// A copy of the operation with the values of credentials and sensitive
+    /// parameters replaced by `REDACTED`, suitable for logging.
+    pub fn redacted(&self) -> Operation {
+        let mut op = self.clone();
+        for (name, value) in op.environment.iter_mut() {
+            if op.sensitive_environment.contains(name) {
+                *value = REDACTED.to_string();
+            }
+        }
+        for (path, contents) in op.files.iter_mut() {
+            if op.sensitive_files.contains(path) {
+                *contents = REDACTED.as_bytes().to_vec();
+            }
+        }
+        op
+    }
+
+    /// A redactor tracking the values of credentials and sensitive parameters, to
+    /// scrub them from logs and messages about the operation.
+    pub fn redactor(&self) -> Redactor {
+        let mut redactor = Redactor::new();
+        for name in &self.sensitive_environment {
+            if let Some(value) = self.environment.get(name) {
+                redactor.add(value);
+            }
+        }
+        for path in &self.sensitive_files {
+            if let Some(contents) = self.files.get(path) {
+                redactor.add(&String::from_utf8_lossy(contents));
+            }
+        }
+        redactor
+    }
+}
+
+impl fmt::Debug for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = self.redacted();
+        let files: BTreeMap<_, _> = op
+            .files
+            .iter()
+            .map(|(path, contents)| (path, String::from_utf8_lossy(contents)))
+            .collect();
+        f.debug_struct("Operation")
+            .field("action", &op.action)
+            .field("installation", &op.installation)
+            .field("revision", &op.revision)
+            .field("image", &op.image)
+            .field("environment", &op.environment)
+            .field("files", &files)
+            .field("outputs", &op.outputs)
+            .field("sensitive_environment", &op.sensitive_environment)
+            .field("sensitive_files", &op.sensitive_files)
+            .field("deadline", &op.deadline)
+            .field("cancellation", &op.cancellation)
+            .field("warnings", &op.warnings)
+            .field("docker", &op.docker)
+            .finish()
+    }
+}
+
+/// OperationBuilder assembles an Operation from a bundle and resolved values.
+///
+/// ```
+/// use libcnab::driver::OperationBuilder;
+/// use libcnab::Bundle;
+///
+/// let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+/// let op = OperationBuilder::new(&bundle, "install", "my-install")
+///     .parameter("port", "8080")
+///     .credential("hostkey", "s3cr3t")
+///     .build()
+///     .unwrap();
+/// assert_eq!(op.environment["CNAB_ACTION"], "install");
+/// ```
+pub struct OperationBuilder<'a> {
+    bundle: &'a Bundle,
+    action: String,
+    installation: String,
+    revision: Option<String>,
+    image: usize,
+    parameters: BTreeMap<String, String>,
+    credentials: BTreeMap<String, String>,
+    relocation: RelocationMap,
+}
+
+impl<'a> OperationBuilder<'a> {
+    pub fn new(bundle: &'a Bundle, action: &str, installation: &str) -> Self {
+        OperationBuilder {
+            bundle,
+            action: action.to_string(),
+            installation: installation.to_string(),
+            revision: None,
+            image: 0,
+            parameters: BTreeMap::new(),
+            credentials: BTreeMap::new(),
+            relocation: RelocationMap::new(),
+        }
+    }
+
+    /// Run a relocated bundle: the invocation image is pulled from its relocated
+    /// reference, and a non-empty `map` is mounted at `RELOCATION_MAPPING_PATH`.
+    pub fn relocation(mut self, map: &RelocationMap) -> Self {
+        self.relocation = map.clone();
+        self
+    }
+
+    /// The revision of the installation. Defaults to a new ULID.
+    pub fn revision(mut self, revision: &str) -> Self {
+        self.revision = Some(revision.to_string());
+        self
+    }
+
+    /// Choose which of the bundle's invocation images to run. Defaults to the first.
+    pub fn invocation_image(mut self, index: usize) -> Self {
+        self.image = index;
+        self
+    }
+
+    pub fn parameter(mut self, name: &str, value: &str) -> Self {
+        self.parameters.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Add resolved parameter values, such as those from `ParameterSet::resolve`.
+    pub fn parameters(mut self, values: BTreeMap<String, String>) -> Self {
+        self.parameters.extend(values);
+        self
+    }
+
+    pub fn credential(mut self, name: &str, value: &str) -> Self {
+        self.credentials.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Add resolved credential values, such as those from `CredentialSet::resolve`.
+    pub fn credentials(mut self, values: BTreeMap<String, String>) -> Self {
+        self.credentials.extend(values);
+        self
+    }
+
+    /// Build the operation.
+    ///
+    /// Parameters that apply to the action and were not given fall back to the
+    /// `default` of their definition. A required parameter or credential with no value
+    /// is an error.
+    ///
+    /// A parameter value larger than `MAX_ENV_VALUE_SIZE` is not put in the environment.
+    /// It is delivered at the parameter's path destination, or at
+    /// `/cnab/parameters/<name>` when it has none, and a warning is added to the
+    /// operation.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(action = %self.action, installation = %self.installation),
+            err
+        )
+    )]
+    pub fn build(self) -> Result<Operation, OperationError> {
+        let bundle = self.bundle;
+        let declared = bundle
+            .actions
+            .as_ref()
+            .map(|a| a.contains_key(&self.action))
+            .unwrap_or(false);
+        if !declared && !BUILTIN_ACTIONS.contains(&self.action.as_str()) {
+            return Err(OperationError::UnknownAction(self.action));
+        }
+        let mut image = bundle
+            .invocation_images
+            .get(self.image)
+            .cloned()
+            .ok_or(OperationError::NoInvocationImage)?;
+        image.image = self.relocation.relocate(&image.image).to_string();
+        let revision = self
+            .revision
+            .clone()
+            .unwrap_or_else(|| Ulid::new().to_string());
+
+        let mut environment = BTreeMap::new();
+        environment.insert(CNAB_ACTION.to_string(), self.action.clone());
+        environment.insert(
+            CNAB_INSTALLATION_NAME.to_string(),
+            self.installation.clone(),
+        );
+        environment.insert(CNAB_BUNDLE_NAME.to_string(), bundle.name.clone());
+        environment.insert(CNAB_BUNDLE_VERSION.to_string(), bundle.version.to_string());
+        environment.insert(CNAB_REVISION.to_string(), revision.clone());
+
+        let mut files = BTreeMap::new();
+        let mut sensitive_environment = BTreeSet::new();
+        let mut sensitive_files = BTreeSet::new();
+        let mut warnings = Vec::new();
+        files.insert(PathBuf::from(BUNDLE_PATH), serde_json::to_vec(bundle)?);
+        if !self.relocation.is_empty() {
+            files.insert(
+                PathBuf::from(RELOCATION_MAPPING_PATH),
+                serde_json::to_vec(&self.relocation)?,
+            );
+        }
+
+        for (name, parameter) in bundle.parameters.iter().flatten() {
+            if !parameter.applies_to(&self.action) {
+                continue;
+            }
+            let value = match self.parameters.get(name) {
+                Some(value) => {
+                    check_parameter(bundle, parameter.definition.as_deref(), value).map_err(
+                        |message| OperationError::InvalidParameter {
+                            name: name.clone(),
+                            message,
+                        },
+                    )?;
+                    value.clone()
+                }
+                None => match parameter_default(bundle, parameter.definition.as_deref()) {
+                    Some(value) => value,
+                    None if parameter.required.unwrap_or(false) => {
+                        return Err(OperationError::MissingParameter(name.clone()))
+                    }
+                    None => continue,
+                },
+            };
+            let sensitive = parameter
+                .definition
+                .as_ref()
+                .map(|d| bundle.is_sensitive(d))
+                .unwrap_or(false);
+            if let Some(path) = &parameter.destination.path {
+                files.insert(path.clone(), bundle.parameter_file_contents(name, &value)?);
+                if sensitive {
+                    sensitive_files.insert(path.clone());
+                }
+            }
+            match &parameter.destination.env {
+                Some(env) if value.len() > MAX_ENV_VALUE_SIZE => {
+                    let path = match &parameter.destination.path {
+                        Some(path) => path.clone(),
+                        None => {
+                            let path = Path::new(PARAMETERS_DIR).join(name);
+                            files.insert(
+                                path.clone(),
+                                bundle.parameter_file_contents(name, &value)?,
+                            );
+                            if sensitive {
+                                sensitive_files.insert(path.clone());
+                            }
+                            path
+                        }
+                    };
+                    let warning = format!(
+                        "parameter {} is too large for the environment variable {}, it is only available at {}",
+                        name,
+                        env,
+                        path.display()
+                    );
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!("{}", warning);
+                    warnings.push(warning);
+                }
+                Some(env) => {
+                    environment.insert(env.to_string(), value);
+                    if sensitive {
+                        sensitive_environment.insert(env.to_string());
+                    }
+                }
+                None => {}
+            }
+        }
+
+        for (name, credential) in bundle.credentials.iter().flatten() {
+            if !credential.applies_to(&self.action) {
+                continue;
+            }
+            let value = match self.credentials.get(name) {
+                Some(value) => value,
+                None if credential.required.unwrap_or(false) => {
+                    return Err(OperationError::MissingCredential(name.clone()))
+                }
+                None => continue,
+            };
+            if let Some(path) = &credential.path {
+                files.insert(path.clone(), value.clone().into_bytes());
+                sensitive_files.insert(path.clone());
+            }
+            if let Some(env) = &credential.env {
+                environment.insert(env.to_string(), value.clone());
+                sensitive_environment.insert(env.to_string());
+            }
+        }
+
+        let outputs = bundle
+            .outputs
+            .iter()
+            .flatten()
+            .filter(|(_, output)| output.applies_to(&self.action))
+            .map(|(name, output)| {
+                let path = output
+                    .path
+                    .clone()
+                    .unwrap_or_else(|| Path::new(OUTPUTS_DIR).join(name));
+                (name.clone(), path)
+            })
+            .collect();
+
+        Ok(Operation {
+            action: self.action,
+            installation: self.installation,
+            revision,
+            image,
+            environment,
+            files,
+            outputs,
+            sensitive_environment,
+            sensitive_files,
+            deadline: None,
+            cancellation: CancellationToken::new(),
+            warnings,
+            docker: bundle.required_docker_extension()?,
+        })
+    }
+}
+
+/// The default value of a definition, in the form it is injected into the image.
+pub(crate) fn parameter_default(bundle: &Bundle, definition: Option<&str>) -> Option<String> {
+    match definition
+        .and_then(|d| bundle.definition(d))?
+        .get("default")?
+    {
+        serde_json::Value::String(s) => Some(s.clone()),
+        value => Some(value.to_string()),
+    }
+}
+
+/// Check a parameter value against its definition, following `$ref`s to the other
+/// definitions of the bundle.
+fn check_parameter(bundle: &Bundle, definition: Option<&str>, value: &str) -> Result<(), String> {
+    let definition = match definition.and_then(|d| bundle.definition(d)) {
+        Some(definition) => definition,
+        None => return Ok(()),
+    };
+    let definitions = bundle.definitions.as_ref();
+    let typed = coerce(Some(resolve(definitions, definition)), value.to_string())?;
+    let violations = check_value(definitions, definition, &typed);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations.join("; "))
+    }
+}
+
+/// OperationError describes why an Operation could not be built.
+#[derive(Debug)]
+pub enum OperationError {
+    /// The bundle does not support the requested action
+    UnknownAction(String),
+    /// The bundle has no invocation image at the requested index
+    NoInvocationImage,
+    /// A required parameter has no value and no default
+    MissingParameter(String),
+    /// A parameter value does not match its definition
+    InvalidParameter {
+        name: String,
+        message: String,
+    },
+    /// A required credential has no value
+    MissingCredential(String),
+    EncodingError(EncodingError),
+    SerdeJSONError(serde_json::Error),
+}
+
+impl fmt::Display for OperationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OperationError::UnknownAction(action) => {
+                write!(f, "action {} is not supported by the bundle", action)
+            }
+            OperationError::NoInvocationImage => write!(f, "the bundle has no invocation image"),
+            OperationError::MissingParameter(name) => {
+                write!(f, "required parameter {} has no value", name)
+            }
+            OperationError::InvalidParameter { name, message } => {
+                write!(f, "parameter {} is invalid: {}", name, message)
+            }
+            OperationError::MissingCredential(name) => {
+                write!(f, "required credential {} has no value", name)
+            }
+            OperationError::EncodingError(e) => write!(f, "{}", e),
+            OperationError::SerdeJSONError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for OperationError {}
+
+impl Coded for OperationError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            OperationError::UnknownAction(_) => ErrorCode::OperationUnknownAction,
+            OperationError::NoInvocationImage => ErrorCode::OperationNoInvocationImage,
+            OperationError::MissingParameter(_) => ErrorCode::OperationMissingParameter,
+            OperationError::InvalidParameter { .. } => ErrorCode::OperationInvalidParameter,
+            OperationError::MissingCredential(_) => ErrorCode::OperationMissingCredential,
+            OperationError::EncodingError(e) => e.code(),
+            OperationError::SerdeJSONError(_) => ErrorCode::OperationInvalidJson,
+        }
+    }
+}
+
+impl From<EncodingError> for OperationError {
+    fn from(error: EncodingError) -> Self {
+        OperationError::EncodingError(error)
+    }
+}
+
+impl From<serde_json::Error> for OperationError {
+    fn from(error: serde_json::Error) -> Self {
+        OperationError::SerdeJSONError(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bundle() -> Bundle {
+        Bundle::from_json(
+            r#"{
+                "name": "operation",
+                "invocationImages": [{ "image": "example/operation:1.0.0", "imageType": "docker" }],
+                "schemaVersion": "v1.0.0",
+                "version": "1.0.0",
+                "actions": { "status": { "modifies": false } },
+                "definitions": {
+                    "port": { "type": "integer", "default": 8080 },
+                    "string": { "type": "string" },
+                    "password": { "type": "string", "writeOnly": true }
+                },
+                "parameters": {
+                    "port": { "definition": "port", "destination": { "env": "PORT" } },
+                    "config": { "definition": "string", "required": true, "destination": { "path": "/etc/config" } },
+                    "backup": { "definition": "string", "applyTo": ["backup"], "destination": { "env": "BACKUP" } },
+                    "password": { "definition": "password", "destination": { "env": "PASSWORD" } }
+                },
+                "credentials": {
+                    "token": { "env": "TOKEN", "required": true }
+                },
+                "outputs": {
+                    "address": { "definition": "string" },
+                    "report": { "definition": "string", "applyTo": ["status"], "path": "/tmp/report" }
+                }
+            }"#
+            .as_bytes(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_build_operation() {
+        let bundle = bundle();
+        let op = OperationBuilder::new(&bundle, "install", "my-install")
+            .revision("01CP6XM0KVB9V1BQDZ9NK8VP29")
+            .parameter("config", "debug: true")
+            .parameter("password", "hunter2")
+            .credential("token", "s3cr3t")
+            .build()
+            .expect("operation built");
+
+        assert_eq!(op.image.image, "example/operation:1.0.0");
+        assert_eq!(op.environment[CNAB_REVISION], "01CP6XM0KVB9V1BQDZ9NK8VP29");
+        assert_eq!(op.environment["PORT"], "8080");
+        assert_eq!(op.environment["TOKEN"], "s3cr3t");
+        assert!(!op.environment.contains_key("BACKUP"));
+        assert_eq!(op.files[Path::new("/etc/config")], b"debug: true");
+        assert!(op.files.contains_key(Path::new(BUNDLE_PATH)));
+        assert_eq!(op.outputs.keys().collect::<Vec<_>>(), vec!["address"]);
+        assert_eq!(
+            op.outputs["address"],
+            Path::new("/cnab/app/outputs/address")
+        );
+    }
+
+    #[test]
+    fn test_build_operation_errors() {
+        let bundle = bundle();
+        match OperationBuilder::new(&bundle, "install", "i").build() {
+            Err(OperationError::MissingParameter(name)) => assert_eq!(name, "config"),
+            other => panic!("expected missing parameter, got {:?}", other),
+        }
+        match OperationBuilder::new(&bundle, "install", "i")
+            .parameter("config", "")
+            .build()
+        {
+            Err(OperationError::MissingCredential(name)) => assert_eq!(name, "token"),
+            other => panic!("expected missing credential, got {:?}", other),
+        }
+        match OperationBuilder::new(&bundle, "dance", "i").build() {
+            Err(OperationError::UnknownAction(action)) => assert_eq!(action, "dance"),
+            other => panic!("expected unknown action, got {:?}", other),
+        }
+        let op = OperationBuilder::new(&bundle, "status", "i")
+            .parameter("config", "")
+            .credential("token", "t")
+            .build()
+            .expect("custom action built");
+        assert_eq!(op.outputs["report"], Path::new("/tmp/report"));
+
+        // Values are checked against their definition, through its `$ref`s.
+        let mut bundle = bundle.clone();
+        bundle.definitions.as_mut().unwrap().insert(
+            "admin-port".to_string(),
+            serde_json::json!({ "$ref": "#/definitions/port", "minimum": 1024 }),
+        );
+        bundle
+            .parameters
+            .as_mut()
+            .unwrap()
+            .get_mut("port")
+            .unwrap()
+            .definition = Some("admin-port".to_string());
+        let build = |port: &str| {
+            OperationBuilder::new(&bundle, "install", "i")
+                .parameter("config", "")
+                .parameter("port", port)
+                .credential("token", "t")
+                .build()
+        };
+        assert_eq!(build("8443").unwrap().environment["PORT"], "8443");
+        for (port, expected) in [
+            ("80", "80 is less than the minimum 1024"),
+            ("http", "\"http\" is not a valid integer"),
+        ] {
+            match build(port) {
+                Err(OperationError::InvalidParameter { name, message }) => {
+                    assert_eq!((name.as_str(), message.as_str()), ("port", expected))
+                }
+                other => panic!("expected invalid parameter, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_oversized_parameters() {
+        let mut bundle = bundle();
+        let parameters = bundle.parameters.as_mut().unwrap();
+        parameters.get_mut("config").unwrap().destination.env = "CONFIG".parse().ok();
+        let large = "x".repeat(MAX_ENV_VALUE_SIZE + 1);
+        let op = OperationBuilder::new(&bundle, "install", "i")
+            .parameter("config", &large)
+            .parameter("password", &large)
+            .credential("token", "t")
+            .build()
+            .expect("operation built");
+
+        assert!(!op.environment.contains_key("CONFIG"));
+        assert_eq!(op.files[Path::new("/etc/config")], large.as_bytes());
+        assert!(!op.environment.contains_key("PASSWORD"));
+        let password = Path::new("/cnab/parameters/password");
+        assert_eq!(op.files[password], large.as_bytes());
+        assert!(op.sensitive_files.contains(password));
+        assert_eq!(op.warnings.len(), 2);
+    }
+
+    #[test]
+    fn test_relocation() {
+        let bundle = bundle();
+        let mut map = RelocationMap::new();
+        map.insert(
+            "example/operation:1.0.0",
+            "mirror.example.com/operation@sha256:0123",
+        );
+        let op = OperationBuilder::new(&bundle, "install", "i")
+            .parameter("config", "")
+            .credential("token", "t")
+            .relocation(&map)
+            .build()
+            .expect("operation built");
+
+        assert_eq!(op.image.image, "mirror.example.com/operation@sha256:0123");
+        let mounted: RelocationMap =
+            serde_json::from_slice(&op.files[Path::new(RELOCATION_MAPPING_PATH)]).unwrap();
+        assert_eq!(mounted, map);
+    }
+}
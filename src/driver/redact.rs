@@ -0,0 +1,124 @@
+use super::{LogLine, LogSink, REDACTED};
+use std::borrow::Cow;
+use std::fmt;
+
+/// Redactor scrubs known sensitive values, such as resolved credentials and
+/// `writeOnly` parameters, out of text before it leaves the engine.
+///
+/// `Operation::redactor` tracks the sensitive values of an operation. Values that
+/// span several lines, such as a kubeconfig, are also tracked line by line, since
+/// logs are streamed a line at a time.
+///
+/// ```
+/// use libcnab::driver::{Redactor, REDACTED};
+///
+/// let mut redactor = Redactor::new();
+/// redactor.add("hunter2");
+/// assert_eq!(redactor.redact("password=hunter2"), format!("password={}", REDACTED));
+/// ```
+#[derive(Clone, Default)]
+pub struct Redactor {
+    /// The values to scrub, longest first so that a value containing another is
+    /// scrubbed whole
+    values: Vec<String>,
+}
+
+impl Redactor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Track `value`. Empty values are ignored.
+    pub fn add(&mut self, value: &str) {
+        let lines = value.lines().map(str::trim).filter(|l| *l != value);
+        for value in Some(value).into_iter().chain(lines) {
+            if !value.is_empty() && !self.values.iter().any(|v| v == value) {
+                self.values.push(value.to_string());
+            }
+        }
+        self.values.sort_by_key(|v| std::cmp::Reverse(v.len()));
+    }
+
+    /// Whether no values are tracked.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// `text` with every tracked value replaced by `REDACTED`.
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for value in &self.values {
+            if text.contains(value.as_str()) {
+                text = Cow::Owned(text.replace(value.as_str(), REDACTED));
+            }
+        }
+        text
+    }
+}
+
+/// The values themselves are left out.
+impl fmt::Debug for Redactor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Redactor")
+            .field("values", &self.values.len())
+            .finish()
+    }
+}
+
+/// RedactedLogs is a LogSink that scrubs lines with a `Redactor` before passing them
+/// on.
+pub struct RedactedLogs<'a> {
+    sink: &'a dyn LogSink,
+    redactor: Redactor,
+}
+
+impl<'a> RedactedLogs<'a> {
+    pub fn new(sink: &'a dyn LogSink, redactor: Redactor) -> Self {
+        RedactedLogs { sink, redactor }
+    }
+}
+
+impl LogSink for RedactedLogs<'_> {
+    fn log(&self, mut line: LogLine) {
+        if let Cow::Owned(redacted) = self.redactor.redact(&line.line) {
+            line.line = redacted;
+        }
+        self.sink.log(line);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::driver::LogStream;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_redactor() {
+        let mut redactor = Redactor::new();
+        assert!(matches!(
+            redactor.redact("nothing to hide"),
+            Cow::Borrowed(_)
+        ));
+        redactor.add("");
+        assert!(redactor.is_empty());
+        redactor.add("abc");
+        redactor.add("abcdef");
+        redactor.add("apiVersion: v1\n  token: s3cr3t\n");
+        assert_eq!(
+            redactor.redact("abcdef abc"),
+            format!("{} {}", REDACTED, REDACTED)
+        );
+        assert_eq!(redactor.redact("token: s3cr3t"), REDACTED);
+        assert!(!format!("{:?}", redactor).contains("abc"));
+
+        let lines = Mutex::new(Vec::new());
+        let sink = |line: LogLine| lines.lock().unwrap().push(line.line);
+        let logs = RedactedLogs::new(&sink, redactor);
+        logs.log(LogLine::new(LogStream::Stdout, "logging in with abc"));
+        assert_eq!(
+            lines.into_inner().unwrap(),
+            vec![format!("logging in with {}", REDACTED)]
+        );
+    }
+}
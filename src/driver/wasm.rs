@@ -0,0 +1,285 @@
+//! A driver that runs WebAssembly invocation images with wasmtime.
+//!
+//! The `image` of a `wasm` or `wasi` invocation image is the path of a WASI
+//! (preview 1) command module, optionally as a `file://` URL. Each operation runs the
+//! module's `_start` in a fresh instance with the operation's environment set. Its
+//! files are written into a private directory that the module sees as `/`, and the
+//! outputs are read back from that directory once the module exits. No container
+//! runtime or daemon is involved.
+use super::{
+    forward_lines, Driver, DriverError, ImageType, LogSink, LogStream, Operation, OperationResult,
+    OutputContents, POLL_INTERVAL,
+};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use ulid::Ulid;
+use wasmtime::{Config, Engine, Linker, Module, Store, Trap};
+use wasmtime_wasi::p1::WasiP1Ctx;
+use wasmtime_wasi::p2::pipe::MemoryOutputPipe;
+use wasmtime_wasi::{FsPerms, I32Exit, WasiCtxBuilder};
+
+/// The most output kept from each of a module's stdout and stderr when it is sent to
+/// a `LogSink`
+pub const WASM_LOG_LIMIT: usize = 16 * 1024 * 1024;
+
+/// WasmDriver runs `wasm` and `wasi` invocation images in-process.
+pub struct WasmDriver {
+    engine: Engine,
+}
+
+impl Default for WasmDriver {
+    fn default() -> Self {
+        let mut config = Config::new();
+        config.epoch_interruption(true);
+        WasmDriver {
+            engine: Engine::new(&config).expect("the default wasmtime configuration is valid"),
+        }
+    }
+}
+
+impl std::fmt::Debug for WasmDriver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WasmDriver").finish()
+    }
+}
+
+impl WasmDriver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The local path of the module of an invocation image.
+    pub fn module_path(op: &Operation) -> PathBuf {
+        let image = &op.image.image;
+        PathBuf::from(image.strip_prefix("file://").unwrap_or(image))
+    }
+
+    fn execute(
+        &self,
+        op: &Operation,
+        logs: Option<&dyn LogSink>,
+    ) -> Result<OperationResult, DriverError> {
+        if !self.handles(&op.image_type()) {
+            return Err(DriverError::UnsupportedImageType(op.image_type()));
+        }
+        if let Some(e) = op.interrupted() {
+            return Err(e);
+        }
+        let root = std::env::temp_dir().join(format!("libcnab-wasm-{}", Ulid::new()));
+        let result = self.run_in(&root, op, logs);
+        let _ = std::fs::remove_dir_all(&root);
+        result
+    }
+
+    fn run_in(
+        &self,
+        root: &Path,
+        op: &Operation,
+        logs: Option<&dyn LogSink>,
+    ) -> Result<OperationResult, DriverError> {
+        let mut builder = std::fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        builder.create(root)?;
+        let guest = |path: &Path| root.join(path.strip_prefix("/").unwrap_or(path));
+        for (path, contents) in &op.files {
+            let local = guest(path);
+            if let Some(parent) = local.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(local, contents)?;
+        }
+        for path in op.outputs.values() {
+            if let Some(parent) = guest(path).parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+
+        let module = Module::from_file(&self.engine, Self::module_path(op)).map_err(other)?;
+        let mut wasi = WasiCtxBuilder::new();
+        wasi.arg(crate::layout::RUN_PATH);
+        for (name, value) in &op.environment {
+            wasi.env(name, value);
+        }
+        wasi.preopened_dir(root, "/", FsPerms::ReadWrite)
+            .map_err(other)?;
+        let pipes = logs.map(|_| {
+            (
+                MemoryOutputPipe::new(WASM_LOG_LIMIT),
+                MemoryOutputPipe::new(WASM_LOG_LIMIT),
+            )
+        });
+        match &pipes {
+            Some((stdout, stderr)) => {
+                wasi.stdout(stdout.clone()).stderr(stderr.clone());
+            }
+            None => {
+                wasi.inherit_stdout().inherit_stderr();
+            }
+        }
+
+        let mut linker: Linker<WasiP1Ctx> = Linker::new(&self.engine);
+        wasmtime_wasi::p1::add_to_linker_sync(&mut linker, |ctx| ctx).map_err(other)?;
+        let mut store = Store::new(&self.engine, wasi.build_p1());
+        store.set_epoch_deadline(1);
+        let instance = linker.instantiate(&mut store, &module).map_err(other)?;
+        let start = instance
+            .get_typed_func::<(), ()>(&mut store, "_start")
+            .map_err(other)?;
+
+        // Interrupt the module from another thread once the operation is interrupted.
+        let finished = AtomicBool::new(false);
+        let outcome = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                while !finished.load(Ordering::SeqCst) {
+                    if op.interrupted().is_some() {
+                        self.engine.increment_epoch();
+                        return;
+                    }
+                    std::thread::sleep(POLL_INTERVAL);
+                }
+            });
+            let outcome = start.call(&mut store, ());
+            finished.store(true, Ordering::SeqCst);
+            outcome
+        });
+
+        if let (Some((stdout, stderr)), Some(logs)) = (pipes, logs) {
+            forward_lines(&stdout.contents()[..], LogStream::Stdout, logs)?;
+            forward_lines(&stderr.contents()[..], LogStream::Stderr, logs)?;
+        }
+        let exit_code = match outcome {
+            Ok(()) => 0,
+            Err(e) => match e.downcast_ref::<I32Exit>() {
+                Some(exit) => exit.0,
+                None if e.downcast_ref::<Trap>() == Some(&Trap::Interrupt) => {
+                    return Err(op.interrupted().unwrap_or(DriverError::Cancelled))
+                }
+                None => {
+                    return Err(DriverError::Failed {
+                        exit_code: None,
+                        message: e.to_string(),
+                    })
+                }
+            },
+        };
+        if exit_code != 0 {
+            return Err(DriverError::Failed {
+                exit_code: Some(exit_code),
+                message: format!("module {} failed", Self::module_path(op).display()),
+            });
+        }
+
+        let mut outputs = BTreeMap::new();
+        for (name, path) in &op.outputs {
+            match std::fs::read(guest(path)) {
+                Ok(contents) => {
+                    outputs.insert(name.clone(), OutputContents::Bytes(contents));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(OperationResult { outputs })
+    }
+}
+
+fn other(error: wasmtime::Error) -> DriverError {
+    DriverError::Other(error.into())
+}
+
+impl Driver for WasmDriver {
+    /// The module's output goes to this process's stdout and stderr.
+    fn run(&self, op: &Operation) -> Result<OperationResult, DriverError> {
+        self.execute(op, None)
+    }
+
+    /// The module's output is collected and sent to `logs` when it exits.
+    fn run_with_logs(
+        &self,
+        op: &Operation,
+        logs: &dyn LogSink,
+    ) -> Result<OperationResult, DriverError> {
+        self.execute(op, Some(logs))
+    }
+
+    fn handles(&self, image_type: &ImageType) -> bool {
+        image_type.as_str() == "wasm" || image_type.as_str() == "wasi"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::driver::{LogLine, OperationBuilder};
+    use crate::Bundle;
+
+    /// Prints `installing` and writes `8080` to the `port` output, exiting with 2 if
+    /// the output cannot be created.
+    const MODULE: &str = r#"(module
+        (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 0) "installing\n")
+        (data (i32.const 16) "cnab/app/outputs/port")
+        (data (i32.const 48) "8080")
+        (data (i32.const 64) "\00\00\00\00\0b\00\00\00")
+        (data (i32.const 72) "\30\00\00\00\04\00\00\00")
+        (func (export "_start")
+            (drop (call $fd_write (i32.const 1) (i32.const 64) (i32.const 1) (i32.const 80)))
+            (if (call $path_open (i32.const 3) (i32.const 0) (i32.const 16) (i32.const 21)
+                    (i32.const 9) (i64.const 64) (i64.const 0) (i32.const 0) (i32.const 84))
+                (then (call $proc_exit (i32.const 2))))
+            (drop (call $fd_write (i32.load (i32.const 84)) (i32.const 72) (i32.const 1) (i32.const 80)))))"#;
+
+    #[test]
+    fn test_wasm_driver() {
+        let dir = tempfile::tempdir().unwrap();
+        let module = dir.path().join("install.wat");
+        std::fs::write(&module, MODULE).unwrap();
+
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.invocation_images[0].image = format!("file://{}", module.display());
+        bundle.invocation_images[0].image_type = Some("wasm".to_string());
+        bundle.outputs = Some(
+            vec![(
+                "port".to_string(),
+                serde_json::from_str(r#"{"definition": "port"}"#).unwrap(),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        let op = OperationBuilder::new(&bundle, "install", "my-install")
+            .credential("hostkey", "key")
+            .build()
+            .unwrap();
+
+        let driver = WasmDriver::new();
+        assert!(!driver.handles(&ImageType::Docker));
+        let lines = std::sync::Mutex::new(Vec::new());
+        let sink = |line: LogLine| lines.lock().unwrap().push(line.line);
+        let result = driver.run_with_logs(&op, &sink).expect("module ran");
+        assert_eq!(lines.into_inner().unwrap(), vec!["installing"]);
+        assert_eq!(
+            result.outputs["port"],
+            OutputContents::Bytes(b"8080".to_vec())
+        );
+
+        std::fs::write(
+            &module,
+            r#"(module (func (export "_start") (loop (br 0))))"#,
+        )
+        .unwrap();
+        let mut op = op;
+        op.deadline = Some(std::time::Instant::now() + std::time::Duration::from_millis(100));
+        match driver.run(&op) {
+            Err(DriverError::TimedOut) => {}
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+    }
+}
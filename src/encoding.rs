@@ -0,0 +1,180 @@
+use crate::cnab::Bundle;
+use crate::error_code::{Coded, ErrorCode};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::fmt;
+
+/// ContentEncoding is the `contentEncoding` declared by a JSON Schema definition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentEncoding {
+    /// The value is binary data carried as a base64 string
+    Base64,
+}
+
+impl ContentEncoding {
+    /// Read the content encoding declared by a definition, if any.
+    pub fn from_definition(
+        definition: &serde_json::Value,
+    ) -> Result<Option<ContentEncoding>, EncodingError> {
+        match definition.get("contentEncoding").and_then(|e| e.as_str()) {
+            None => Ok(None),
+            Some(e) if e.eq_ignore_ascii_case("base64") => Ok(Some(ContentEncoding::Base64)),
+            Some(e) => Err(EncodingError::UnsupportedEncoding(e.to_string())),
+        }
+    }
+
+    /// Decode a value into the bytes it represents.
+    pub fn decode(self, value: &str) -> Result<Vec<u8>, EncodingError> {
+        match self {
+            ContentEncoding::Base64 => Ok(STANDARD.decode(value.trim())?),
+        }
+    }
+
+    /// Encode bytes as a value.
+    pub fn encode(self, contents: &[u8]) -> String {
+        match self {
+            ContentEncoding::Base64 => STANDARD.encode(contents),
+        }
+    }
+}
+
+/// Represents an error converting between a value and its encoded file contents
+#[derive(Debug)]
+pub enum EncodingError {
+    /// The definition declares an encoding this crate does not understand
+    UnsupportedEncoding(String),
+    InvalidBase64(base64::DecodeError),
+    /// File contents without a declared encoding were not valid UTF-8
+    InvalidUtf8(std::string::FromUtf8Error),
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodingError::UnsupportedEncoding(e) => write!(f, "unsupported contentEncoding {}", e),
+            EncodingError::InvalidBase64(e) => write!(f, "invalid base64 content: {}", e),
+            EncodingError::InvalidUtf8(e) => write!(f, "content is not valid UTF-8: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EncodingError {}
+
+impl Coded for EncodingError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            EncodingError::UnsupportedEncoding(_) => ErrorCode::EncodingUnsupported,
+            EncodingError::InvalidBase64(_) => ErrorCode::EncodingInvalidBase64,
+            EncodingError::InvalidUtf8(_) => ErrorCode::EncodingInvalidUtf8,
+        }
+    }
+}
+
+impl From<base64::DecodeError> for EncodingError {
+    fn from(error: base64::DecodeError) -> Self {
+        EncodingError::InvalidBase64(error)
+    }
+}
+
+impl From<std::string::FromUtf8Error> for EncodingError {
+    fn from(error: std::string::FromUtf8Error) -> Self {
+        EncodingError::InvalidUtf8(error)
+    }
+}
+
+impl Bundle {
+    /// Look up a definition by name.
+    pub fn definition(&self, name: &str) -> Option<&serde_json::Value> {
+        self.definitions.as_ref().and_then(|d| d.get(name))
+    }
+
+    fn content_encoding(
+        &self,
+        definition: Option<&str>,
+    ) -> Result<Option<ContentEncoding>, EncodingError> {
+        match definition.and_then(|d| self.definition(d)) {
+            Some(def) => ContentEncoding::from_definition(def),
+            None => Ok(None),
+        }
+    }
+
+    /// Compute the file contents used when injecting a parameter value into a path destination.
+    ///
+    /// Values of parameters whose definition declares `contentEncoding: base64` are
+    /// decoded, so the invocation image sees the original binary data.
+    pub fn parameter_file_contents(
+        &self,
+        parameter: &str,
+        value: &str,
+    ) -> Result<Vec<u8>, EncodingError> {
+        let definition = self
+            .parameters
+            .as_ref()
+            .and_then(|p| p.get(parameter))
+            .and_then(|p| p.definition.as_deref());
+        match self.content_encoding(definition)? {
+            Some(encoding) => encoding.decode(value),
+            None => Ok(value.as_bytes().to_vec()),
+        }
+    }
+
+    /// Compute the value of an output from the contents of the file the invocation image wrote.
+    ///
+    /// Contents of outputs whose definition declares `contentEncoding: base64` are
+    /// base64-encoded; all other outputs must be valid UTF-8.
+    pub fn output_value(&self, output: &str, contents: &[u8]) -> Result<String, EncodingError> {
+        let definition = self
+            .outputs
+            .as_ref()
+            .and_then(|o| o.get(output))
+            .map(|o| o.definition.as_str());
+        match self.content_encoding(definition)? {
+            Some(encoding) => Ok(encoding.encode(contents)),
+            None => Ok(String::from_utf8(contents.to_vec())?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_base64_content_encoding() {
+        let bundle: Bundle = r#"{
+            "name": "aristotle",
+            "invocationImages": [],
+            "schemaVersion": "1.0.0",
+            "version": "1.0.0",
+            "definitions": {
+                "binary": { "type": "string", "contentEncoding": "base64" },
+                "text": { "type": "string" }
+            },
+            "parameters": {
+                "cert": { "definition": "binary", "destination": { "path": "/cnab/app/cert.der" } },
+                "name": { "definition": "text", "destination": { "path": "/cnab/app/name" } }
+            },
+            "outputs": {
+                "keystore": { "definition": "binary", "path": "/cnab/app/outputs/keystore" },
+                "log": { "definition": "text", "path": "/cnab/app/outputs/log" }
+            }
+        }"#
+        .parse()
+        .expect("bundle parsed");
+
+        let bytes = vec![0u8, 159, 146, 150];
+        assert_eq!(
+            bundle.parameter_file_contents("cert", "AJ+Slg==").unwrap(),
+            bytes
+        );
+        assert_eq!(
+            bundle.parameter_file_contents("name", "AJ+Slg==").unwrap(),
+            b"AJ+Slg=="
+        );
+        assert_eq!(bundle.output_value("keystore", &bytes).unwrap(), "AJ+Slg==");
+        assert!(bundle.output_value("log", &bytes).is_err());
+        assert!(bundle
+            .parameter_file_contents("cert", "not base64!")
+            .is_err());
+    }
+}
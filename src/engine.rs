@@ -0,0 +1,1647 @@
+//! Running bundle actions end to end.
+//!
+//! The `Engine` is the top-level API of the crate. For each action it resolves the
+//! parameter and credential sets, builds the `Operation`, runs it with a `Driver` and
+//! records the outcome as a claim in a `ClaimStore`.
+//!
+//! ```
+//! use libcnab::claimstore::MemoryClaimStore;
+//! use libcnab::driver::DebugDriver;
+//! use libcnab::engine::Engine;
+//! use libcnab::{Bundle, CredentialSet};
+//!
+//! let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+//! let credentials: CredentialSet = serde_json::from_str(
+//!     r#"{"name": "dev", "credentials": [{"name": "hostkey", "source": {"value": "key"}}]}"#,
+//! )
+//! .unwrap();
+//!
+//! let (driver, claims) = (DebugDriver::new(), MemoryClaimStore::new());
+//! let engine = Engine::new(&driver, &claims);
+//! let claim = engine.install("hello", &bundle, &[], &[credentials]).unwrap();
+//! assert_eq!(claim.result.action(), "install");
+//! ```
+use crate::cancel::CancellationToken;
+use crate::claim::{Claim, Response, Status};
+use crate::claimstore::{ClaimStore, ClaimStoreError};
+use crate::cnab::{Bundle, InvocationImage, Platform, BUILTIN_ACTIONS};
+use crate::credentialset::{CredentialSet, ResolveError};
+use crate::dependencies::DEPENDENCIES_KEY;
+use crate::driver::{
+    ChunkLogs, Driver, DriverError, ImageType, LogSink, Operation, OperationBuilder,
+    OperationError, OperationResult, OutputChunk, RedactedLogs,
+};
+use crate::encoding::EncodingError;
+use crate::error_code::{Coded, ErrorCode};
+use crate::extensions::{ExtensionRegistry, ValidationError};
+use crate::metrics::{Metrics, ACTIONS, ACTION_DURATION};
+use crate::parameter_sources::{SourceValues, PARAMETER_SOURCES_KEY};
+use crate::parameterset::ParameterSet;
+use crate::profile::ValidationProfile;
+use crate::relocation::{Mirrors, RelocationMap};
+use crate::secrets::SecretResolver;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+mod audit;
+pub use self::audit::*;
+mod dependencies;
+use self::dependencies::{record_links, Linked};
+mod events;
+pub use self::events::*;
+mod hooks;
+pub use self::hooks::*;
+mod outputs;
+pub use self::outputs::{
+    OutputSink, INLINE_OUTPUT_LIMIT, OUTPUT_DIGESTS_KEY, OUTPUT_REFERENCES_KEY,
+};
+mod plan;
+pub use self::plan::*;
+mod policy;
+pub use self::policy::*;
+mod retry;
+pub use self::retry::*;
+
+/// Engine runs bundle actions and records their claims.
+pub struct Engine<'a> {
+    drivers: Vec<&'a dyn Driver>,
+    claims: &'a dyn ClaimStore,
+    secrets: SecretResolver,
+    extensions: BTreeSet<String>,
+    hooks: Vec<&'a dyn EngineHook>,
+    logs: Option<&'a dyn LogSink>,
+    chunks: Option<&'a Sender<OutputChunk>>,
+    timeout: Option<Duration>,
+    cancellation: CancellationToken,
+    retry: RetryPolicy,
+    policies: Vec<&'a dyn ExecutionPolicy>,
+    signatures: Option<&'a dyn SignatureVerifier>,
+    audit: Option<&'a dyn AuditSink>,
+    events: Option<&'a dyn EventSink>,
+    metrics: Option<&'a dyn Metrics>,
+    actor: Option<String>,
+    relocation: RelocationMap,
+    mirrors: Mirrors,
+    require_pinned_images: bool,
+    platform: Option<Platform>,
+    output_limit: Option<u64>,
+    output_limits: BTreeMap<String, u64>,
+    output_sink: Option<&'a dyn OutputSink>,
+    validation: ValidationProfile,
+}
+
+impl<'a> Engine<'a> {
+    pub fn new(driver: &'a dyn Driver, claims: &'a dyn ClaimStore) -> Self {
+        Engine {
+            drivers: vec![driver],
+            claims,
+            secrets: SecretResolver::default(),
+            extensions: vec![PARAMETER_SOURCES_KEY.to_string()]
+                .into_iter()
+                .collect(),
+            hooks: Vec::new(),
+            logs: None,
+            chunks: None,
+            timeout: None,
+            cancellation: CancellationToken::new(),
+            retry: RetryPolicy::default(),
+            policies: Vec::new(),
+            signatures: None,
+            audit: None,
+            events: None,
+            metrics: None,
+            actor: None,
+            relocation: RelocationMap::new(),
+            mirrors: Mirrors::new(),
+            require_pinned_images: false,
+            platform: None,
+            output_limit: None,
+            output_limits: BTreeMap::new(),
+            output_sink: None,
+            validation: ValidationProfile::default(),
+        }
+    }
+
+    /// Add a policy that must allow each action before it runs.
+    pub fn policy(mut self, policy: &'a dyn ExecutionPolicy) -> Self {
+        self.policies.push(policy);
+        self
+    }
+
+    /// Check bundle signatures with `verifier`, so policies can see the result.
+    pub fn signatures(mut self, verifier: &'a dyn SignatureVerifier) -> Self {
+        self.signatures = Some(verifier);
+        self
+    }
+
+    /// Run relocated bundles, pulling their images from where `map` says they were
+    /// copied to.
+    pub fn relocation(mut self, map: RelocationMap) -> Self {
+        self.relocation = map;
+        self
+    }
+
+    /// Pull the images of bundles from the mirrors `mirrors` sends them to, from
+    /// where the relocation map sends them. The relocation map the invocation image
+    /// is given sends them there too.
+    pub fn mirrors(mut self, mirrors: Mirrors) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
+    /// Refuse to run bundles with images referenced by tag alone, since their content
+    /// can change without the bundle, and whatever verified it, noticing. Images the
+    /// relocation map moves to a digest reference count as pinned.
+    pub fn require_pinned_images(mut self) -> Self {
+        self.require_pinned_images = true;
+        self
+    }
+
+    /// Run only invocation images for `platform`, such as the host's from
+    /// `Platform::current`, skipping those whose `PLATFORM_LABEL` names another. By
+    /// default images for every platform are run.
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.platform = Some(platform);
+        self
+    }
+
+    /// Refuse bundles with unpinned images, if the engine requires pinned ones.
+    fn check_pinned_images(&self, bundle: &Bundle) -> Result<(), EngineError> {
+        if !self.require_pinned_images {
+            return Ok(());
+        }
+        let relocation = self.mirrors.relocation(bundle, &self.relocation);
+        let unpinned: Vec<String> = bundle
+            .unpinned_images()
+            .into_iter()
+            .filter(|image| !relocation.relocate(image).contains('@'))
+            .map(str::to_string)
+            .collect();
+        if unpinned.is_empty() {
+            Ok(())
+        } else {
+            Err(EngineError::UnpinnedImages(unpinned))
+        }
+    }
+
+    /// Send a record of each action the engine runs to `sink`.
+    pub fn audit(mut self, sink: &'a dyn AuditSink) -> Self {
+        self.audit = Some(sink);
+        self
+    }
+
+    /// Send the changes the engine makes to claims to `sink`, as `ClaimEvent`s.
+    pub fn events(mut self, sink: &'a dyn EventSink) -> Self {
+        self.events = Some(sink);
+        self
+    }
+
+    /// Count and time the actions the engine runs in `metrics`, as `ACTIONS` and
+    /// `ACTION_DURATION`.
+    pub fn metrics(mut self, metrics: &'a dyn Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Name who runs actions with this engine in their audit records.
+    pub fn actor(mut self, actor: &str) -> Self {
+        self.actor = Some(actor.to_string());
+        self
+    }
+
+    /// Evaluate the policies for an action.
+    fn check_policies(
+        &self,
+        action: &str,
+        installation: &str,
+        bundle: &Bundle,
+    ) -> Result<(), EngineError> {
+        if self.policies.is_empty() {
+            return Ok(());
+        }
+        let digest = bundle_digest(bundle);
+        let signature = self
+            .signatures
+            .map(|v| v.verify(bundle, &digest))
+            .unwrap_or_default();
+        let input = PolicyInput {
+            bundle,
+            digest: &digest,
+            signature: &signature,
+            action,
+            installation,
+        };
+        for policy in &self.policies {
+            policy.evaluate(&input).map_err(EngineError::Denied)?;
+        }
+        Ok(())
+    }
+
+    /// Retry operations whose driver fails before the image runs, as `policy` allows.
+    /// By default nothing is retried.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Stop invocation images that run for longer than `timeout`. The run is recorded
+    /// as a failure.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Stop running invocation images when `token` is cancelled. The run is recorded
+    /// with the `canceled` status.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Keep outputs of more than `limit` bytes out of the claim store, unless they
+    /// have a limit of their own. They are stored with the sink set with
+    /// `output_sink`, and fail the action when there is none.
+    pub fn output_limit(mut self, limit: u64) -> Self {
+        self.output_limit = Some(limit);
+        self
+    }
+
+    /// Limit the size of the output `output` kept in the claim store to `limit`
+    /// bytes, as `output_limit` does for every output.
+    pub fn output_limit_for(mut self, output: &str, limit: u64) -> Self {
+        self.output_limits.insert(output.to_string(), limit);
+        self
+    }
+
+    /// Store the outputs that are over their limit with `sink`.
+    pub fn output_sink(mut self, sink: &'a dyn OutputSink) -> Self {
+        self.output_sink = Some(sink);
+        self
+    }
+
+    /// Stream the output of invocation images to `logs` while they run.
+    pub fn logs(mut self, logs: &'a dyn LogSink) -> Self {
+        self.logs = Some(logs);
+        self
+    }
+
+    /// Send the chunks of output that invocation images send while they run to
+    /// `chunks`, such as for a custom action that follows a log until it is cancelled.
+    ///
+    /// Chunks are read from the image's output, so the driver must stream it; they
+    /// are not recorded, and outputs are collected once the image exits as they
+    /// always are.
+    pub fn chunks(mut self, chunks: &'a Sender<OutputChunk>) -> Self {
+        self.chunks = Some(chunks);
+        self
+    }
+
+    /// Check bundles against `profile` before running their actions, and refuse those
+    /// that do not pass, with the extensions the engine supports counted as known.
+    /// Like parsing, the engine holds bundles to `ValidationProfile::Strict1_0` by
+    /// default; `ValidationProfile::Permissive` only refuses what it cannot run.
+    pub fn validation(mut self, profile: ValidationProfile) -> Self {
+        self.validation = profile;
+        self
+    }
+
+    /// Add a hook that is called around every operation.
+    pub fn hook(mut self, hook: &'a dyn EngineHook) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// Declare that the host supports a bundle extension, so bundles that list it in
+    /// `requiredExtensions` may run.
+    ///
+    /// The parameter sources extension is always supported.
+    pub fn extension(mut self, name: &str) -> Self {
+        self.extensions.insert(name.to_string());
+        self
+    }
+
+    /// The extensions the engine will accept in `requiredExtensions`.
+    pub fn extensions(&self) -> impl Iterator<Item = &str> {
+        self.extensions.iter().map(String::as_str)
+    }
+
+    /// Pick the first invocation image that one of the drivers can run.
+    fn select_driver(&self, bundle: &Bundle) -> Result<(usize, &'a dyn Driver), EngineError> {
+        if bundle.invocation_images.is_empty() {
+            return Err(OperationError::NoInvocationImage.into());
+        }
+        let runs_here = |image: &InvocationImage| match (&self.platform, image.platform()) {
+            (None, _) | (Some(_), None) => true,
+            (Some(platform), Some(Ok(p))) => p.matches(platform),
+            (Some(_), Some(Err(_))) => false,
+        };
+        if let Some(platform) = &self.platform {
+            if !bundle.invocation_images.iter().any(runs_here) {
+                return Err(EngineError::NoImageForPlatform(platform.to_string()));
+            }
+        }
+        for (index, image) in bundle.invocation_images.iter().enumerate() {
+            if !runs_here(image) {
+                continue;
+            }
+            let image_type = ImageType::from(image);
+            if let Some(driver) = self.drivers.iter().find(|d| d.handles(&image_type)) {
+                return Ok((index, *driver));
+            }
+        }
+        Err(EngineError::NoDriver(
+            bundle
+                .invocation_images
+                .iter()
+                .map(ImageType::from)
+                .collect(),
+        ))
+    }
+
+    /// Refuse bundles that require extensions the host does not support. The
+    /// dependencies extension is supported when the bundle is installed as part of an
+    /// install plan, with its dependencies.
+    fn check_extensions(&self, bundle: &Bundle, linked: bool) -> Result<(), EngineError> {
+        let unsupported: Vec<String> = bundle
+            .required_extensions
+            .iter()
+            .flatten()
+            .filter(|e| !self.extensions.contains(*e))
+            .filter(|e| !(linked && *e == DEPENDENCIES_KEY))
+            .cloned()
+            .collect();
+        if unsupported.is_empty() {
+            Ok(())
+        } else {
+            Err(EngineError::UnsupportedExtensions(unsupported))
+        }
+    }
+
+    /// Refuse bundles that do not pass the engine's validation profile.
+    fn check_validation(&self, bundle: &Bundle) -> Result<(), EngineError> {
+        let registry = self
+            .extensions
+            .iter()
+            .fold(ExtensionRegistry::default(), |registry, e| {
+                registry.register(e)
+            });
+        bundle
+            .validate_with(&registry, &self.validation)
+            .map_err(EngineError::InvalidBundle)
+    }
+
+    /// Add another driver. Each invocation is run by the first driver, in the order
+    /// they were added, that handles the invocation image's type.
+    pub fn driver(mut self, driver: &'a dyn Driver) -> Self {
+        self.drivers.push(driver);
+        self
+    }
+
+    /// Resolve secret sources with the given resolver instead of the default one.
+    pub fn secrets(mut self, secrets: SecretResolver) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    /// Install a bundle as a new installation.
+    ///
+    /// Fails with `EngineError::AlreadyInstalled` if the installation exists and was
+    /// not uninstalled.
+    pub fn install(
+        &self,
+        installation: &str,
+        bundle: &Bundle,
+        parameters: &[ParameterSet],
+        credentials: &[CredentialSet],
+    ) -> Result<Claim, EngineError> {
+        self.check_installable(installation)?;
+        self.run(
+            "install",
+            installation,
+            bundle,
+            None,
+            Inputs::new(parameters, credentials),
+        )
+    }
+
+    /// Upgrade an installation to `bundle`, which may be a new version of its bundle.
+    ///
+    /// Parameter values of the previous run are kept unless overridden.
+    pub fn upgrade(
+        &self,
+        installation: &str,
+        bundle: &Bundle,
+        parameters: &[ParameterSet],
+        credentials: &[CredentialSet],
+    ) -> Result<Claim, EngineError> {
+        let previous = self.installed(installation)?;
+        self.run(
+            "upgrade",
+            installation,
+            bundle,
+            Some(&previous),
+            Inputs::new(parameters, credentials),
+        )
+    }
+
+    /// Uninstall an installation using the bundle it was last run with.
+    pub fn uninstall(
+        &self,
+        installation: &str,
+        parameters: &[ParameterSet],
+        credentials: &[CredentialSet],
+    ) -> Result<Claim, EngineError> {
+        let previous = self.installed(installation)?;
+        let bundle = previous.bundle.clone();
+        self.run(
+            "uninstall",
+            installation,
+            &bundle,
+            Some(&previous),
+            Inputs::new(parameters, credentials),
+        )
+    }
+
+    /// Run any action, built-in or custom, against an installation.
+    ///
+    /// The claim is only updated when the action modifies the installation.
+    pub fn invoke(
+        &self,
+        action: &str,
+        installation: &str,
+        parameters: &[ParameterSet],
+        credentials: &[CredentialSet],
+    ) -> Result<Claim, EngineError> {
+        let previous = self.installed(installation)?;
+        let bundle = previous.bundle.clone();
+        self.run(
+            action,
+            installation,
+            &bundle,
+            Some(&previous),
+            Inputs::new(parameters, credentials),
+        )
+    }
+
+    /// Work out how an action would run without running it.
+    ///
+    /// Everything `install`, `upgrade`, `uninstall` and `invoke` check before starting
+    /// the invocation image is checked here too: the installation's state, required
+    /// extensions, parameter and credential resolution, and which image and driver to
+    /// use. Nothing is recorded and no driver runs anything. For actions on an existing
+    /// installation other than `upgrade`, pass the bundle from its claim.
+    pub fn plan(
+        &self,
+        action: &str,
+        installation: &str,
+        bundle: &Bundle,
+        parameters: &[ParameterSet],
+        credentials: &[CredentialSet],
+    ) -> Result<ExecutionPlan, EngineError> {
+        let previous = if action == "install" {
+            self.check_installable(installation)?;
+            None
+        } else {
+            Some(self.installed(installation)?)
+        };
+        let prepared = self.prepare(
+            action,
+            installation,
+            bundle,
+            previous.as_ref(),
+            Inputs::new(parameters, credentials),
+        )?;
+        Ok(ExecutionPlan::new(bundle, &prepared.op))
+    }
+
+    fn check_installable(&self, installation: &str) -> Result<(), EngineError> {
+        if let Some(claim) = self.claims.read(installation)? {
+            let uninstalled =
+                claim.result.action() == "uninstall" && claim.result.status() == Status::Success;
+            if !uninstalled {
+                return Err(EngineError::AlreadyInstalled(installation.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    fn installed(&self, installation: &str) -> Result<Claim, EngineError> {
+        self.claims
+            .read(installation)?
+            .ok_or_else(|| EngineError::NotInstalled(installation.to_string()))
+    }
+
+    /// Resolve the values of an action, build its operation and choose its driver.
+    fn prepare(
+        &self,
+        action: &str,
+        installation: &str,
+        bundle: &Bundle,
+        previous: Option<&Claim>,
+        inputs: Inputs<'_>,
+    ) -> Result<Prepared<'a>, EngineError> {
+        self.check_extensions(bundle, inputs.linked.is_some())?;
+        self.check_validation(bundle)?;
+        self.check_pinned_images(bundle)?;
+        self.check_policies(action, installation, bundle)?;
+        let (image, driver) = self.select_driver(bundle)?;
+        let mut values: BTreeMap<String, String> = previous
+            .and_then(|c| c.parameters.clone())
+            .unwrap_or_default();
+        let merged = ParameterSet {
+            name: installation.to_string(),
+            parameters: inputs
+                .parameters
+                .iter()
+                .flat_map(|set| set.parameters.iter().cloned())
+                .collect(),
+        };
+        let mut sources = previous.map(SourceValues::from_claim).unwrap_or_default();
+        if let Some(linked) = inputs.linked {
+            sources.dependency_outputs = linked.outputs.clone();
+        }
+        values.extend(merged.resolve_with_sources(bundle, &sources, &self.secrets)?);
+
+        let mut secrets = BTreeMap::new();
+        for set in inputs.credentials {
+            secrets.extend(set.resolve_with(&self.secrets)?);
+        }
+
+        let mut op = OperationBuilder::new(bundle, action, installation)
+            .invocation_image(image)
+            .relocation(&self.mirrors.relocation(bundle, &self.relocation))
+            .parameters(values.clone())
+            .credentials(secrets)
+            .build()?;
+        op.deadline = self.timeout.map(|t| Instant::now() + t);
+        op.cancellation = self.cancellation.clone();
+        Ok(Prepared { op, driver, values })
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(action, installation, bundle = %bundle.name),
+            err
+        )
+    )]
+    fn run(
+        &self,
+        action: &str,
+        installation: &str,
+        bundle: &Bundle,
+        previous: Option<&Claim>,
+        inputs: Inputs<'_>,
+    ) -> Result<Claim, EngineError> {
+        let Prepared {
+            mut op,
+            driver,
+            values,
+        } = self.prepare(action, installation, bundle, previous, inputs)?;
+        for hook in &self.hooks {
+            hook.before_operation(&mut op)
+                .map_err(EngineError::Rejected)?;
+        }
+
+        let modifies = modifies(bundle, action);
+        let _lock = if modifies {
+            let lock = InstallationLock::acquire(self.claims, installation, &op.revision)?;
+            // Another install may have finished before the lock was taken.
+            if action == "install" {
+                self.check_installable(installation)?;
+            }
+            Some(lock)
+        } else {
+            None
+        };
+        let now = chrono::Utc::now();
+        let mut claim = Claim {
+            bundle: bundle.clone(),
+            created: previous.map(|c| c.created).unwrap_or(now),
+            custom: previous.and_then(|c| c.custom.clone()),
+            modified: now,
+            name: installation.to_string(),
+            outputs: None,
+            parameters: Some(values),
+            result: Response::new(action, Status::Pending, None),
+            revision: op.revision.clone(),
+            bundle_reference: previous.and_then(|c| c.bundle_reference.clone()),
+        };
+        if let Some(linked) = inputs.linked {
+            record_links(&mut claim, &linked.links);
+            if linked.reference.is_some() {
+                claim.bundle_reference = linked.reference.clone();
+            }
+        }
+        if modifies {
+            self.claims.store(&claim)?;
+            if previous.is_none() {
+                self.emit(ClaimEvent::InstallationCreated {
+                    installation: installation.to_string(),
+                    revision: claim.revision.clone(),
+                });
+            }
+        }
+
+        self.emit(ClaimEvent::RunStarted {
+            installation: installation.to_string(),
+            action: action.to_string(),
+            revision: claim.revision.clone(),
+        });
+        let started = Instant::now();
+        let result = self
+            .execute(driver, &op)
+            .map_err(EngineError::from)
+            .and_then(|r| self.collect_outputs(bundle, installation, action, &r, modifies));
+        claim.modified = chrono::Utc::now();
+        let result = match result {
+            Ok(outputs) => {
+                for (output, digest) in outputs.digests.iter().filter(|_| modifies) {
+                    self.emit(ClaimEvent::OutputStored {
+                        installation: installation.to_string(),
+                        revision: claim.revision.clone(),
+                        output: output.clone(),
+                        digest: digest.clone(),
+                        reference: outputs.references.get(output).cloned(),
+                    });
+                }
+                claim.outputs = Some(outputs.values);
+                record_outputs(&mut claim, OUTPUT_DIGESTS_KEY, outputs.digests);
+                record_outputs(&mut claim, OUTPUT_REFERENCES_KEY, outputs.references);
+                claim.result = Response::new(action, Status::Success, None);
+                Ok(())
+            }
+            Err(e) => {
+                let status = match e {
+                    EngineError::DriverError(DriverError::Cancelled) => Status::Canceled,
+                    _ => Status::Failure,
+                };
+                // The message is kept in the claim and the audit record.
+                let message = op.redactor().redact(&e.to_string()).into_owned();
+                claim.result = Response::new(action, status, Some(message));
+                Err(e)
+            }
+        };
+        if modifies {
+            self.claims.store(&claim)?;
+            self.emit(ClaimEvent::ResultRecorded {
+                installation: installation.to_string(),
+                action: action.to_string(),
+                revision: claim.revision.clone(),
+                status: claim.result.status(),
+            });
+        }
+        for hook in &self.hooks {
+            hook.after_result(&op, &claim);
+        }
+        if let Some(metrics) = self.metrics {
+            let labels = [
+                ("action", action),
+                ("status", claim.result.status().as_str()),
+            ];
+            metrics.counter(ACTIONS, &labels, 1);
+            metrics.timer(ACTION_DURATION, &labels, started.elapsed());
+        }
+        #[cfg(feature = "tracing")]
+        tracing::info!(revision = %claim.revision, status = ?claim.result.status(), "action finished");
+        if let Some(sink) = self.audit {
+            sink.record(&AuditRecord {
+                timestamp: claim.modified,
+                actor: self.actor.clone(),
+                installation: installation.to_string(),
+                action: action.to_string(),
+                revision: claim.revision.clone(),
+                bundle_digest: bundle_digest(bundle),
+                parameters_digest: claim
+                    .parameters
+                    .as_ref()
+                    .map(parameters_digest)
+                    .unwrap_or_default(),
+                status: claim.result.status(),
+                message: claim.result.message().map(str::to_string),
+            })?;
+        }
+        result.map(|()| claim)
+    }
+
+    fn emit(&self, event: ClaimEvent) {
+        if let Some(sink) = self.events {
+            sink.event(&event);
+        }
+    }
+
+    /// Run the operation with the driver, retrying as the retry policy allows.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(revision = %op.revision, image = %op.image.image, image_type = %op.image_type().as_str()),
+            err
+        )
+    )]
+    fn execute(&self, driver: &dyn Driver, op: &Operation) -> Result<OperationResult, DriverError> {
+        let chunks = self.chunks.map(|chunks| {
+            let chunks = ChunkLogs::new(&op.installation, chunks);
+            match self.logs {
+                Some(logs) => chunks.logs(logs),
+                None => chunks,
+            }
+        });
+        let logs = match &chunks {
+            Some(chunks) => Some(chunks as &dyn LogSink),
+            None => self.logs,
+        };
+        let mut attempt = 1;
+        loop {
+            let result = match logs {
+                Some(logs) => driver.run_with_logs(op, &RedactedLogs::new(logs, op.redactor())),
+                None => driver.run(op),
+            };
+            match result {
+                Err(e) if self.retry.retries(attempt, &e) => {
+                    let delay = self.retry.delay(attempt);
+                    let too_late = op
+                        .deadline
+                        .map(|d| Instant::now() + delay >= d)
+                        .unwrap_or(false);
+                    if too_late || !op.cancellation.sleep(delay) {
+                        return Err(e);
+                    }
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(attempt, error = %e, ?delay, "retrying operation");
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// InstallationLock holds the lock of an installation in the claim store for a run,
+/// and releases it when dropped.
+struct InstallationLock<'s> {
+    claims: &'s dyn ClaimStore,
+    installation: &'s str,
+    run_id: &'s str,
+}
+
+impl<'s> InstallationLock<'s> {
+    fn acquire(
+        claims: &'s dyn ClaimStore,
+        installation: &'s str,
+        run_id: &'s str,
+    ) -> Result<Self, EngineError> {
+        claims.lock(installation, run_id)?;
+        Ok(InstallationLock {
+            claims,
+            installation,
+            run_id,
+        })
+    }
+}
+
+impl Drop for InstallationLock<'_> {
+    fn drop(&mut self) {
+        let _ = self.claims.unlock(self.installation, self.run_id);
+    }
+}
+
+/// What an action is run with
+#[derive(Clone, Copy)]
+struct Inputs<'s> {
+    parameters: &'s [ParameterSet],
+    credentials: &'s [CredentialSet],
+    /// Set when the installation is part of an install plan
+    linked: Option<&'s Linked>,
+}
+
+impl<'s> Inputs<'s> {
+    fn new(parameters: &'s [ParameterSet], credentials: &'s [CredentialSet]) -> Self {
+        Inputs {
+            parameters,
+            credentials,
+            linked: None,
+        }
+    }
+}
+
+/// An operation ready to run
+struct Prepared<'a> {
+    op: Operation,
+    driver: &'a dyn Driver,
+    /// The parameter values to record in the claim
+    values: BTreeMap<String, String>,
+}
+
+/// Whether running the action changes the installation, so its claim must be updated.
+fn modifies(bundle: &Bundle, action: &str) -> bool {
+    BUILTIN_ACTIONS.contains(&action)
+        || bundle
+            .actions
+            .as_ref()
+            .and_then(|a| a.get(action))
+            .map(|a| a.modifies)
+            .unwrap_or(false)
+}
+
+/// Record what is known of each output, such as its digest, under `key` in the
+/// claim's custom data, keeping any other custom keys.
+fn record_outputs(claim: &mut Claim, key: &str, outputs: BTreeMap<String, String>) {
+    let mut custom = match claim.custom.take() {
+        Some(serde_json::Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    };
+    if outputs.is_empty() {
+        custom.remove(key);
+    } else {
+        custom.insert(key.to_string(), serde_json::json!(outputs));
+    }
+    if !custom.is_empty() {
+        claim.custom = Some(serde_json::Value::Object(custom));
+    }
+}
+
+/// EngineError describes why an action could not be run.
+#[derive(Debug)]
+pub enum EngineError {
+    /// The installation already exists
+    AlreadyInstalled(String),
+    /// The installation has no claim
+    NotInstalled(String),
+    /// Another run holds the lock of the installation, and is modifying it
+    InProgress {
+        installation: String,
+        run_id: String,
+    },
+    /// The bundle requires extensions the host does not support
+    UnsupportedExtensions(Vec<String>),
+    /// The bundle does not pass the engine's validation profile
+    InvalidBundle(ValidationError),
+    /// A hook stopped the operation before it ran
+    Rejected(HookError),
+    /// An execution policy refused the action, for the given reason
+    Denied(String),
+    /// The engine requires pinned images, and the bundle refers to these by tag
+    UnpinnedImages(Vec<String>),
+    /// None of the drivers can run any of the bundle's invocation images, whose types
+    /// are listed
+    NoDriver(Vec<ImageType>),
+    /// None of the bundle's invocation images runs on the engine's platform
+    NoImageForPlatform(String),
+    ResolveError(ResolveError),
+    OperationError(OperationError),
+    DriverError(DriverError),
+    ClaimStoreError(ClaimStoreError),
+    /// An output could not be decoded according to its definition
+    EncodingError(EncodingError),
+    /// An output does not match its declaration
+    InvalidOutput {
+        name: String,
+        message: String,
+    },
+    IoError(std::io::Error),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::AlreadyInstalled(name) => {
+                write!(f, "installation {} already exists", name)
+            }
+            EngineError::NotInstalled(name) => write!(f, "installation {} does not exist", name),
+            EngineError::InProgress {
+                installation,
+                run_id,
+            } => write!(
+                f,
+                "operation already in progress on {}, by run {}",
+                installation, run_id
+            ),
+            EngineError::Rejected(e) => write!(f, "operation rejected: {}", e),
+            EngineError::Denied(reason) => write!(f, "denied by policy: {}", reason),
+            EngineError::UnpinnedImages(images) => write!(
+                f,
+                "the bundle refers to images by tag: {}",
+                images.join(", ")
+            ),
+            EngineError::NoDriver(types) => write!(
+                f,
+                "no driver can run invocation images of type {}",
+                types
+                    .iter()
+                    .map(ImageType::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            EngineError::NoImageForPlatform(platform) => {
+                write!(f, "no invocation image runs on {}", platform)
+            }
+            EngineError::UnsupportedExtensions(extensions) => write!(
+                f,
+                "the bundle requires unsupported extensions: {}",
+                extensions.join(", ")
+            ),
+            EngineError::InvalidBundle(e) => write!(f, "the bundle is invalid: {}", e),
+            EngineError::ResolveError(e) => write!(f, "{}", e),
+            EngineError::OperationError(e) => write!(f, "{}", e),
+            EngineError::DriverError(e) => write!(f, "{}", e),
+            EngineError::ClaimStoreError(e) => write!(f, "{}", e),
+            EngineError::EncodingError(e) => write!(f, "invalid output: {}", e),
+            EngineError::InvalidOutput { name, message } => {
+                write!(f, "output {} is invalid: {}", name, message)
+            }
+            EngineError::IoError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+impl Coded for EngineError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            EngineError::AlreadyInstalled(_) => ErrorCode::EngineAlreadyInstalled,
+            EngineError::NotInstalled(_) => ErrorCode::EngineNotInstalled,
+            EngineError::InProgress { .. } => ErrorCode::EngineInProgress,
+            EngineError::UnsupportedExtensions(_) => ErrorCode::EngineUnsupportedExtensions,
+            EngineError::InvalidBundle(e) => e.code(),
+            EngineError::Rejected(_) => ErrorCode::EngineRejected,
+            EngineError::Denied(_) => ErrorCode::EngineDenied,
+            EngineError::UnpinnedImages(_) => ErrorCode::EngineUnpinnedImages,
+            EngineError::NoDriver(_) => ErrorCode::EngineNoDriver,
+            EngineError::NoImageForPlatform(_) => ErrorCode::EngineNoImageForPlatform,
+            EngineError::ResolveError(e) => e.code(),
+            EngineError::OperationError(e) => e.code(),
+            EngineError::DriverError(e) => e.code(),
+            EngineError::ClaimStoreError(e) => e.code(),
+            EngineError::EncodingError(e) => e.code(),
+            EngineError::InvalidOutput { .. } => ErrorCode::EngineInvalidOutput,
+            EngineError::IoError(_) => ErrorCode::Io,
+        }
+    }
+}
+
+impl From<ResolveError> for EngineError {
+    fn from(error: ResolveError) -> Self {
+        EngineError::ResolveError(error)
+    }
+}
+
+impl From<OperationError> for EngineError {
+    fn from(error: OperationError) -> Self {
+        EngineError::OperationError(error)
+    }
+}
+
+impl From<DriverError> for EngineError {
+    fn from(error: DriverError) -> Self {
+        EngineError::DriverError(error)
+    }
+}
+
+impl From<ClaimStoreError> for EngineError {
+    fn from(error: ClaimStoreError) -> Self {
+        match error {
+            ClaimStoreError::Locked {
+                installation,
+                run_id,
+            } => EngineError::InProgress {
+                installation,
+                run_id,
+            },
+            error => EngineError::ClaimStoreError(error),
+        }
+    }
+}
+
+impl From<std::io::Error> for EngineError {
+    fn from(error: std::io::Error) -> Self {
+        EngineError::IoError(error)
+    }
+}
+
+impl From<EncodingError> for EngineError {
+    fn from(error: EncodingError) -> Self {
+        EngineError::EncodingError(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::claimstore::{MemoryClaimStore, DEFAULT_NAMESPACE};
+    use crate::driver::DebugDriver;
+    use crate::metrics::test::Recorded;
+    use crate::platform::PLATFORM_LABEL;
+
+    struct FailingDriver;
+
+    impl Driver for FailingDriver {
+        fn run(&self, _: &Operation) -> Result<OperationResult, DriverError> {
+            Err(DriverError::Failed {
+                exit_code: Some(1),
+                message: "boom".to_string(),
+            })
+        }
+
+        fn handles(&self, _: &ImageType) -> bool {
+            true
+        }
+    }
+
+    fn credentials() -> Vec<CredentialSet> {
+        vec![serde_json::from_str(
+            r#"{"name": "dev", "credentials": [{"name": "hostkey", "source": {"value": "key"}}]}"#,
+        )
+        .unwrap()]
+    }
+
+    fn parameters(port: &str) -> Vec<ParameterSet> {
+        vec![serde_json::from_str(&format!(
+            r#"{{"name": "dev", "parameters": [{{"name": "backend_port", "source": {{"value": "{}"}}}}]}}"#,
+            port
+        ))
+        .unwrap()]
+    }
+
+    /// A driver that upgrades the installation it runs for with another engine on
+    /// the same claims, as a concurrent run would, and keeps what happened.
+    struct ConcurrentDriver<'c> {
+        claims: &'c MemoryClaimStore,
+        upgrade: std::sync::Mutex<Option<(String, Result<Claim, EngineError>)>>,
+    }
+
+    impl Driver for ConcurrentDriver<'_> {
+        fn run(&self, op: &Operation) -> Result<OperationResult, DriverError> {
+            let (driver, bundle) = (
+                DebugDriver::new(),
+                Bundle::from_file("testdata/bundle.json").unwrap(),
+            );
+            let claims = self.claims.namespaced(DEFAULT_NAMESPACE).unwrap();
+            let result = Engine::new(&driver, &claims)
+                .secrets(SecretResolver::empty())
+                .upgrade(&op.installation, &bundle, &[], &credentials());
+            *self.upgrade.lock().unwrap() = Some((op.revision.clone(), result));
+            Ok(OperationResult::default())
+        }
+
+        fn handles(&self, _: &ImageType) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_engine_locks_installations() {
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let claims = MemoryClaimStore::new();
+        let installed = Engine::new(&DebugDriver::new(), &claims)
+            .secrets(SecretResolver::empty())
+            .install("hello", &bundle, &[], &credentials())
+            .expect("installed");
+
+        let driver = ConcurrentDriver {
+            claims: &claims,
+            upgrade: std::sync::Mutex::new(None),
+        };
+        let upgraded = Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .upgrade("hello", &bundle, &[], &credentials())
+            .expect("upgraded");
+        let (revision, concurrent) = driver.upgrade.into_inner().unwrap().unwrap();
+        assert_eq!(revision, upgraded.revision);
+        match concurrent {
+            Err(e @ EngineError::InProgress { .. }) => assert_eq!(
+                e.to_string(),
+                format!(
+                    "operation already in progress on hello, by run {}",
+                    upgraded.revision
+                )
+            ),
+            other => panic!("expected an operation in progress, got {:?}", other),
+        }
+        assert_ne!(upgraded.revision, installed.revision);
+
+        // The lock is released once the run is over.
+        Engine::new(&DebugDriver::new(), &claims)
+            .secrets(SecretResolver::empty())
+            .upgrade("hello", &bundle, &[], &credentials())
+            .expect("upgraded again");
+    }
+
+    #[test]
+    fn test_engine_lifecycle() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let dry_run = serde_json::from_str(r#"{"modifies": false}"#).unwrap();
+        bundle.actions = Some(
+            vec![("io.cnab.dry-run".to_string(), dry_run)]
+                .into_iter()
+                .collect(),
+        );
+        let (driver, claims) = (DebugDriver::new(), MemoryClaimStore::new());
+        let engine = Engine::new(&driver, &claims).secrets(SecretResolver::empty());
+
+        let installed = engine
+            .install("hello", &bundle, &parameters("8080"), &credentials())
+            .expect("installed");
+        assert_eq!(installed.result.status(), Status::Success);
+        match engine.install("hello", &bundle, &[], &credentials()) {
+            Err(EngineError::AlreadyInstalled(name)) => assert_eq!(name, "hello"),
+            other => panic!("expected already installed, got {:?}", other),
+        }
+
+        let upgraded = engine
+            .upgrade("hello", &bundle, &[], &credentials())
+            .expect("upgraded");
+        assert_eq!(upgraded.created, installed.created);
+        assert_ne!(upgraded.revision, installed.revision);
+        assert_eq!(
+            upgraded.parameters.as_ref().unwrap()["backend_port"],
+            "8080",
+            "parameters carry over from the previous run"
+        );
+
+        engine
+            .invoke("io.cnab.dry-run", "hello", &[], &credentials())
+            .expect("custom action run");
+        assert_eq!(
+            claims.read("hello").unwrap().unwrap().result.action(),
+            "upgrade",
+            "non-modifying actions leave the claim alone"
+        );
+
+        engine
+            .uninstall("hello", &[], &credentials())
+            .expect("uninstalled");
+        engine
+            .install("hello", &bundle, &[], &credentials())
+            .expect("reinstalled after uninstall");
+
+        assert_eq!(driver.operations().len(), 5);
+        match engine.uninstall("missing", &[], &[]) {
+            Err(EngineError::NotInstalled(name)) => assert_eq!(name, "missing"),
+            other => panic!("expected not installed, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_engine_required_extensions() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.required_extensions = Some(vec![
+            PARAMETER_SOURCES_KEY.to_string(),
+            "io.cnab.dependencies".to_string(),
+        ]);
+        let (driver, claims) = (DebugDriver::new(), MemoryClaimStore::new());
+        let engine = Engine::new(&driver, &claims).secrets(SecretResolver::empty());
+        match engine.install("hello", &bundle, &[], &credentials()) {
+            Err(EngineError::UnsupportedExtensions(extensions)) => {
+                assert_eq!(extensions, vec!["io.cnab.dependencies"])
+            }
+            other => panic!("expected unsupported extension, got {:?}", other),
+        }
+        assert!(driver.operations().is_empty());
+        assert!(claims.read("hello").unwrap().is_none());
+
+        let engine = engine.extension("io.cnab.dependencies");
+        engine
+            .install("hello", &bundle, &[], &credentials())
+            .expect("extension supported");
+    }
+
+    #[test]
+    fn test_engine_mirrors() {
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let (driver, claims) = (DebugDriver::new(), MemoryClaimStore::new());
+        let mirrors = Mirrors::new().rule("docker.io/technosophos/*", "mirror.example.com/hub/*");
+        let mut relocation = RelocationMap::new();
+        relocation.insert(
+            "technosophos/microservice:1.2.3",
+            "example.com/microservice@sha256:0123",
+        );
+        Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .relocation(relocation)
+            .mirrors(mirrors)
+            .install("hello", &bundle, &[], &credentials())
+            .expect("bundle installed");
+        let op = &driver.operations()[0];
+        assert_eq!(op.image.image, "mirror.example.com/hub/helloworld:0.1.0");
+        let mounted: RelocationMap = serde_json::from_slice(
+            &op.files[std::path::Path::new(crate::layout::RELOCATION_MAPPING_PATH)],
+        )
+        .unwrap();
+        assert_eq!(
+            mounted.get("technosophos/microservice:1.2.3"),
+            Some("example.com/microservice@sha256:0123")
+        );
+    }
+
+    #[test]
+    fn test_engine_requires_pinned_images() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let (driver, claims) = (DebugDriver::new(), MemoryClaimStore::new());
+        let engine = Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .require_pinned_images();
+        match engine.install("hello", &bundle, &[], &credentials()) {
+            Err(EngineError::UnpinnedImages(images)) => assert_eq!(
+                images,
+                vec![
+                    "technosophos/helloworld:0.1.0",
+                    "technosophos/microservice:1.2.3"
+                ]
+            ),
+            other => panic!("expected unpinned images, got {:?}", other),
+        }
+        assert!(driver.operations().is_empty());
+
+        let mut relocation = RelocationMap::new();
+        relocation.insert(
+            "technosophos/microservice:1.2.3",
+            "example.com/microservice@sha256:0123",
+        );
+        bundle.invocation_images[0].image.push_str("@sha256:4567");
+        let engine = engine.relocation(relocation);
+        engine
+            .install("hello", &bundle, &[], &credentials())
+            .expect("pinned images run");
+
+        let engine = Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .policy(&pinned_images_only);
+        match engine.install("policy", &bundle, &[], &credentials()) {
+            Err(EngineError::Denied(reason)) => {
+                assert!(reason.ends_with("refers to images by tag: technosophos/microservice:1.2.3"))
+            }
+            other => panic!("expected a denial, got {:?}", other),
+        }
+    }
+
+    /// A driver that only runs docker images.
+    struct DockerOnlyDriver(DebugDriver);
+
+    impl Driver for DockerOnlyDriver {
+        fn run(&self, op: &Operation) -> Result<OperationResult, DriverError> {
+            self.0.run(op)
+        }
+
+        fn handles(&self, image_type: &ImageType) -> bool {
+            *image_type == ImageType::Docker
+        }
+    }
+
+    #[test]
+    fn test_engine_selects_driver() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let mut wasm: InvocationImage = bundle.invocation_images[0].clone();
+        wasm.image_type = Some("wasm".to_string());
+        bundle.invocation_images.insert(0, wasm.clone());
+
+        let claims = MemoryClaimStore::new();
+        let docker = DockerOnlyDriver(DebugDriver::new());
+        let engine = Engine::new(&docker, &claims).secrets(SecretResolver::empty());
+        engine
+            .install("hello", &bundle, &[], &credentials())
+            .expect("docker image selected");
+        let ops = docker.0.operations();
+        assert_eq!(ops[0].image_type(), ImageType::Docker);
+
+        bundle.invocation_images = vec![wasm];
+        match engine.install("wasm", &bundle, &[], &credentials()) {
+            Err(EngineError::NoDriver(types)) => {
+                assert_eq!(types, vec![ImageType::Other("wasm".to_string())])
+            }
+            other => panic!("expected no driver, got {:?}", other),
+        }
+
+        let fallback = DebugDriver::new();
+        let engine = engine.driver(&fallback);
+        engine
+            .install("wasm", &bundle, &[], &credentials())
+            .expect("fallback driver handles wasm");
+        assert_eq!(fallback.operations().len(), 1);
+    }
+
+    #[test]
+    fn test_engine_selects_platform() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let labelled = |image: &InvocationImage, platform: &str, name: &str| {
+            let mut image = image.clone();
+            image.image = name.to_string();
+            image.labels = Some(
+                vec![(PLATFORM_LABEL.to_string(), platform.to_string())]
+                    .into_iter()
+                    .collect(),
+            );
+            image
+        };
+        let first = bundle.invocation_images[0].clone();
+        bundle.invocation_images = vec![
+            labelled(&first, Platform::LINUX_AMD64, "installer:amd64"),
+            labelled(&first, Platform::LINUX_ARM64, "installer:arm64"),
+        ];
+
+        let claims = MemoryClaimStore::new();
+        let driver = DebugDriver::new();
+        let engine = Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .platform(Platform::new("linux", "aarch64"));
+        engine
+            .install("hello", &bundle, &[], &credentials())
+            .expect("arm64 image selected");
+        assert_eq!(driver.operations()[0].image.image, "installer:arm64");
+
+        let engine = engine.platform(Platform::WINDOWS_AMD64.parse().unwrap());
+        match engine.install("windows", &bundle, &[], &credentials()) {
+            Err(EngineError::NoImageForPlatform(platform)) => {
+                assert_eq!(platform, "windows/amd64")
+            }
+            other => panic!("expected no image for the platform, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_engine_records_failures() {
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let claims = MemoryClaimStore::new();
+        let metrics = Recorded::default();
+        let engine = Engine::new(&FailingDriver, &claims)
+            .secrets(SecretResolver::empty())
+            .metrics(&metrics);
+
+        assert!(engine
+            .install("hello", &bundle, &[], &credentials())
+            .is_err());
+        let claim = claims.read("hello").unwrap().expect("failure recorded");
+        assert_eq!(claim.result.status(), Status::Failure);
+        assert_eq!(
+            claim.result.message(),
+            Some("invocation image exited with code 1: boom")
+        );
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let driver = DebugDriver::new();
+        let engine = Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .cancellation(token)
+            .metrics(&metrics);
+        match engine.install("cancelled", &bundle, &[], &credentials()) {
+            Err(EngineError::DriverError(DriverError::Cancelled)) => {}
+            other => panic!("expected cancellation, got {:?}", other),
+        }
+        let claim = claims
+            .read("cancelled")
+            .unwrap()
+            .expect("cancellation recorded");
+        assert_eq!(claim.result.status(), Status::Canceled);
+
+        let counters = metrics.counters.into_inner().unwrap();
+        assert_eq!(
+            counters.into_iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    "cnab.actions{action=install,status=canceled}".to_string(),
+                    1
+                ),
+                ("cnab.actions{action=install,status=failure}".to_string(), 1)
+            ]
+        );
+        assert_eq!(metrics.timers.into_inner().unwrap().len(), 2);
+    }
+
+    /// Prints the host key, and then fails with it.
+    struct LeakyDriver;
+
+    impl Driver for LeakyDriver {
+        fn run(&self, op: &Operation) -> Result<OperationResult, DriverError> {
+            Err(DriverError::Failed {
+                exit_code: Some(1),
+                message: format!("bad key {}", op.environment["HOST_KEY"]),
+            })
+        }
+
+        fn run_with_logs(
+            &self,
+            op: &Operation,
+            logs: &dyn LogSink,
+        ) -> Result<OperationResult, DriverError> {
+            let line = format!("using key {}", op.environment["HOST_KEY"]);
+            logs.log(crate::driver::LogLine::new(
+                crate::driver::LogStream::Stdout,
+                &line,
+            ));
+            self.run(op)
+        }
+
+        fn handles(&self, _: &ImageType) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_engine_redacts_secrets() {
+        use crate::driver::{LogLine, REDACTED};
+
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let credentials: Vec<CredentialSet> = vec![serde_json::from_str(
+            r#"{"name": "dev", "credentials": [{"name": "hostkey", "source": {"value": "s3cr3t"}}]}"#,
+        )
+        .unwrap()];
+        let lines = std::sync::Mutex::new(Vec::new());
+        let logs = |line: LogLine| lines.lock().unwrap().push(line.line);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redact.log");
+        let audit = AuditLog::open(&path).unwrap();
+        let claims = MemoryClaimStore::new();
+        let engine = Engine::new(&LeakyDriver, &claims)
+            .secrets(SecretResolver::empty())
+            .logs(&logs)
+            .audit(&audit);
+
+        assert!(engine.install("hello", &bundle, &[], &credentials).is_err());
+        assert_eq!(
+            lines.into_inner().unwrap(),
+            vec![format!("using key {}", REDACTED)]
+        );
+        let message = format!("invocation image exited with code 1: bad key {}", REDACTED);
+        let claim = claims.read("hello").unwrap().unwrap();
+        assert_eq!(claim.result.message(), Some(message.as_str()));
+        let record = std::fs::read_to_string(&path).unwrap();
+        let record: AuditRecord = serde_json::from_str(&record).unwrap();
+        assert_eq!(record.message.as_deref(), Some(message.as_str()));
+
+        let op = OperationBuilder::new(&bundle, "install", "hello")
+            .credentials(
+                credentials[0]
+                    .resolve_with(&SecretResolver::empty())
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        assert_eq!(op.environment["HOST_KEY"], "s3cr3t");
+        assert!(!format!("{:?}", op).contains("s3cr3t"));
+    }
+
+    /// Follows a log for the `io.cnab.logs` action: sends each line of it as a chunk,
+    /// waiting for the chunk to be received before it sends the next one.
+    struct FollowDriver {
+        received: std::sync::Mutex<std::sync::mpsc::Receiver<()>>,
+    }
+
+    impl Driver for FollowDriver {
+        fn run(&self, _: &Operation) -> Result<OperationResult, DriverError> {
+            Ok(OperationResult::default())
+        }
+
+        fn run_with_logs(
+            &self,
+            op: &Operation,
+            logs: &dyn LogSink,
+        ) -> Result<OperationResult, DriverError> {
+            if op.action == "io.cnab.logs" {
+                for line in &["started", "ready"] {
+                    let chunk = crate::events::Event::chunk("log", line).to_line();
+                    logs.log(crate::driver::LogLine::new(
+                        crate::driver::LogStream::Stdout,
+                        &chunk,
+                    ));
+                    self.received.lock().unwrap().recv().unwrap();
+                }
+            }
+            self.run(op)
+        }
+
+        fn handles(&self, _: &ImageType) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_engine_streams_chunks() {
+        use crate::cnab::Action;
+        use std::sync::mpsc::channel;
+
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let action = Action {
+            description: Some("Follow the logs".to_string()),
+            modifies: false,
+            stateless: true,
+        };
+        bundle.actions = Some(
+            vec![("io.cnab.logs".to_string(), action)]
+                .into_iter()
+                .collect(),
+        );
+        let (ack, received) = channel();
+        let driver = FollowDriver {
+            received: std::sync::Mutex::new(received),
+        };
+        let claims = MemoryClaimStore::new();
+        let (sender, chunks) = channel();
+        let lines = std::sync::Mutex::new(Vec::new());
+        let logs = |line: crate::driver::LogLine| lines.lock().unwrap().push(line.line);
+        let engine = Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .logs(&logs)
+            .chunks(&sender);
+        engine
+            .install("hello", &bundle, &[], &credentials())
+            .unwrap();
+
+        // The driver only returns once the chunks were received, while it still runs.
+        let follower = std::thread::spawn(move || {
+            let mut followed = Vec::new();
+            for _ in 0..2 {
+                let chunk: OutputChunk = chunks.recv().unwrap();
+                assert_eq!(
+                    (chunk.installation.as_str(), chunk.output.as_str()),
+                    ("hello", "log")
+                );
+                followed.push(chunk.data);
+                ack.send(()).unwrap();
+            }
+            followed
+        });
+        engine
+            .invoke("io.cnab.logs", "hello", &[], &credentials())
+            .unwrap();
+        assert_eq!(follower.join().unwrap(), vec!["started", "ready"]);
+        assert_eq!(lines.into_inner().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_engine_validation() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let parameters = bundle.parameters.as_mut().unwrap();
+        parameters.get_mut("backend_port").unwrap().destination.env = None;
+        let (driver, claims) = (DebugDriver::new(), MemoryClaimStore::new());
+
+        let engine = Engine::new(&driver, &claims).secrets(SecretResolver::empty());
+        let err = engine
+            .install("strict", &bundle, &[], &credentials())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the bundle is invalid: parameter backend_port has no destination: set env, path, or both"
+        );
+        assert_eq!(err.code(), ErrorCode::BundleInvalid);
+        assert!(claims.read("strict").unwrap().is_none());
+
+        engine
+            .validation(ValidationProfile::Permissive)
+            .install("permissive", &bundle, &[], &credentials())
+            .unwrap();
+    }
+
+    /// Fails with a transient error until it has been run `failures` times.
+    struct FlakyDriver {
+        failures: u32,
+        runs: std::sync::atomic::AtomicU32,
+    }
+
+    impl Driver for FlakyDriver {
+        fn run(&self, _: &Operation) -> Result<OperationResult, DriverError> {
+            let runs = self.runs.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if runs < self.failures {
+                Err(DriverError::Transient("registry unavailable".to_string()))
+            } else {
+                Ok(OperationResult::default())
+            }
+        }
+
+        fn handles(&self, _: &ImageType) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_engine_retries() {
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let claims = MemoryClaimStore::new();
+        let flaky = FlakyDriver {
+            failures: 2,
+            runs: Default::default(),
+        };
+        let policy =
+            RetryPolicy::new(3).backoff(Duration::from_millis(1), Duration::from_millis(1));
+        let engine = Engine::new(&flaky, &claims)
+            .secrets(SecretResolver::empty())
+            .retry(policy);
+        let claim = engine
+            .install("hello", &bundle, &[], &credentials())
+            .expect("third attempt succeeds");
+        assert_eq!(claim.result.status(), Status::Success);
+        assert_eq!(flaky.runs.into_inner(), 3);
+
+        let failure = DriverError::Failed {
+            exit_code: Some(1),
+            message: "boom".to_string(),
+        };
+        assert!(!policy.retry_if(|_| true).retries(1, &failure));
+        assert!(policy.retries(2, &DriverError::Transient("pull timed out".to_string())));
+        assert!(!policy.retries(3, &DriverError::Transient("pull timed out".to_string())));
+    }
+}
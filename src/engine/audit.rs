@@ -0,0 +1,122 @@
+use crate::claim::Status;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// AuditRecord describes one action the engine ran.
+///
+/// Parameter values are only recorded as a digest, so records can be kept where the
+/// values themselves may not.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    /// When the action finished
+    pub timestamp: DateTime<Utc>,
+    /// Who ran the action, as set with `Engine::actor`
+    pub actor: Option<String>,
+    pub installation: String,
+    pub action: String,
+    /// The revision the action created
+    pub revision: String,
+    /// The `sha256:<hex>` digest of the bundle's JSON
+    pub bundle_digest: String,
+    /// The `sha256:<hex>` digest of the parameter values, as a JSON object
+    pub parameters_digest: String,
+    pub status: Status,
+    /// Why the action failed, if it did
+    pub message: Option<String>,
+}
+
+/// The `sha256:<hex>` digest of a set of parameter values.
+pub(crate) fn parameters_digest(values: &BTreeMap<String, String>) -> String {
+    let json = serde_json::to_vec(values).expect("strings serialize");
+    format!("sha256:{}", hex::encode(Sha256::digest(&json)))
+}
+
+/// AuditSink receives a record of every action the engine runs, whether it succeeded
+/// or not.
+///
+/// A sink that fails makes the engine return the error, although the action has
+/// already run and its claim has been saved.
+pub trait AuditSink {
+    fn record(&self, record: &AuditRecord) -> std::io::Result<()>;
+}
+
+/// AuditLog appends records to a file, one JSON document per line.
+#[derive(Debug)]
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    /// Open the log at `path` for appending, creating it if needed.
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(AuditLog {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl AuditSink for AuditLog {
+    fn record(&self, record: &AuditRecord) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let mut file = self.file.lock().expect("lock poisoned");
+        // A single write keeps concurrent writers from interleaving lines.
+        file.write_all(&line)?;
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::claimstore::MemoryClaimStore;
+    use crate::driver::DebugDriver;
+    use crate::engine::Engine;
+    use crate::secrets::SecretResolver;
+    use crate::{Bundle, CredentialSet};
+
+    #[test]
+    fn test_audit_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let log = AuditLog::open(&path).unwrap();
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let credentials: Vec<CredentialSet> = vec![serde_json::from_str(
+            r#"{"name": "dev", "credentials": [{"name": "hostkey", "source": {"value": "key"}}]}"#,
+        )
+        .unwrap()];
+        let (driver, claims) = (DebugDriver::new(), MemoryClaimStore::new());
+        let engine = Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .actor("alice")
+            .audit(&log);
+
+        let installed = engine.install("hello", &bundle, &[], &credentials).unwrap();
+        engine.uninstall("hello", &[], &credentials).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<AuditRecord> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].actor.as_deref(), Some("alice"));
+        assert_eq!(records[0].action, "install");
+        assert_eq!(records[0].revision, installed.revision);
+        assert_eq!(records[0].status, Status::Success);
+        assert_eq!(
+            records[0].parameters_digest,
+            parameters_digest(&BTreeMap::new())
+        );
+        assert_eq!(records[1].action, "uninstall");
+        assert!(!contents.contains("\"key\""));
+    }
+}
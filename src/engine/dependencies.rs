@@ -0,0 +1,264 @@
+use super::{Engine, EngineError, Inputs};
+use crate::claim::{Claim, Status};
+use crate::cnab::Bundle;
+use crate::credentialset::CredentialSet;
+use crate::dependencies::{DependencyLinks, InstallPlan, DEPENDENCIES_KEY};
+use crate::driver::parameter_default;
+use crate::parameterset::ParameterSet;
+use std::collections::BTreeMap;
+
+/// Parameter or credential values, keyed by name
+type Values = BTreeMap<String, String>;
+
+/// What an installation of an install plan takes from the installations around it
+pub(super) struct Linked {
+    pub links: DependencyLinks,
+    /// Where the bundle was fetched from, recorded as the claim's bundle reference
+    pub reference: Option<String>,
+    /// The outputs of the installations it depends on, keyed by dependency name
+    pub outputs: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl<'a> Engine<'a> {
+    /// Install every installation of an install plan, each after those it depends on,
+    /// and return their claims in the order of the plan.
+    ///
+    /// `parameters` and `credentials` are those of the root. Each dependency takes the
+    /// values its mappings name from its first parent, falling back to the defaults
+    /// of the parent's parameters, and the outputs of the installations a bundle
+    /// depends on are available to its parameter sources. Each claim records the
+    /// installation's parents and dependencies, see `Claim::dependency_links`.
+    ///
+    /// A dependency already installed with the same version of its bundle is left as
+    /// it is, so a plan that failed part of the way through can be run again.
+    pub fn install_plan(
+        &self,
+        plan: &InstallPlan,
+        parameters: &[ParameterSet],
+        credentials: &[CredentialSet],
+    ) -> Result<Vec<Claim>, EngineError> {
+        let root = plan.root();
+        let mut given_parameters = BTreeMap::new();
+        for set in parameters {
+            given_parameters.extend(set.resolve_with(&self.secrets)?);
+        }
+        let mut given_credentials = BTreeMap::new();
+        for set in credentials {
+            given_credentials.extend(set.resolve_with(&self.secrets)?);
+        }
+        let mut values: BTreeMap<&str, (Values, Values)> = BTreeMap::new();
+        values.insert(&root.installation, (given_parameters, given_credentials));
+
+        // Parents come before their dependencies in the reverse of the plan.
+        for step in plan.steps.iter().rev() {
+            let parent = match step.parents.first() {
+                Some(parent) => parent,
+                None => continue,
+            };
+            let parent_bundle = &plan
+                .steps
+                .iter()
+                .find(|s| s.installation == *parent)
+                .expect("parents are in the plan")
+                .bundle;
+            let (parent_parameters, parent_credentials) = &values[parent.as_str()];
+            let parameters = step
+                .parameters
+                .iter()
+                .filter_map(|(name, from)| {
+                    let value = parent_parameters
+                        .get(from)
+                        .cloned()
+                        .or_else(|| default_value(parent_bundle, from))?;
+                    Some((name.clone(), value))
+                })
+                .collect();
+            let credentials = step
+                .credentials
+                .iter()
+                .filter_map(|(name, from)| {
+                    Some((name.clone(), parent_credentials.get(from)?.clone()))
+                })
+                .collect();
+            values.insert(&step.installation, (parameters, credentials));
+        }
+
+        let mut outputs: BTreeMap<&str, BTreeMap<String, String>> = BTreeMap::new();
+        let mut claims = Vec::new();
+        for step in &plan.steps {
+            let claim = match self.claims.read(&step.installation)? {
+                Some(claim) if step.reference.is_some() && installed_with(&claim, &step.bundle) => {
+                    claim
+                }
+                _ => {
+                    self.check_installable(&step.installation)?;
+                    let linked = Linked {
+                        links: DependencyLinks::from(step),
+                        reference: step.reference.clone(),
+                        outputs: step
+                            .dependencies
+                            .iter()
+                            .map(|(name, installation)| {
+                                let outputs = outputs.get(installation.as_str());
+                                (name.clone(), outputs.cloned().unwrap_or_default())
+                            })
+                            .collect(),
+                    };
+                    let (parameters, credentials) = &values[step.installation.as_str()];
+                    let parameters = [ParameterSet::from_values(&step.installation, parameters)];
+                    let credentials = [CredentialSet::from_values(&step.installation, credentials)];
+                    self.run(
+                        "install",
+                        &step.installation,
+                        &step.bundle,
+                        None,
+                        Inputs {
+                            parameters: &parameters,
+                            credentials: &credentials,
+                            linked: Some(&linked),
+                        },
+                    )?
+                }
+            };
+            outputs.insert(
+                &step.installation,
+                claim.outputs.clone().unwrap_or_default(),
+            );
+            claims.push(claim);
+        }
+        Ok(claims)
+    }
+}
+
+/// The default of a parameter of `bundle`, if it has one.
+fn default_value(bundle: &Bundle, parameter: &str) -> Option<String> {
+    let parameter = bundle.parameters.as_ref()?.get(parameter)?;
+    parameter_default(bundle, parameter.definition.as_deref())
+}
+
+/// Whether the claim is of a successful installation of `bundle`'s version.
+fn installed_with(claim: &Claim, bundle: &Bundle) -> bool {
+    claim.result.action() != "uninstall"
+        && claim.result.status() == Status::Success
+        && claim.bundle.name == bundle.name
+        && claim.bundle.version == bundle.version
+}
+
+/// Record an installation's place in its install plan in the claim's custom data,
+/// keeping any other custom keys.
+pub(super) fn record_links(claim: &mut Claim, links: &DependencyLinks) {
+    let mut custom = match claim.custom.take() {
+        Some(serde_json::Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    };
+    custom.insert(
+        DEPENDENCIES_KEY.to_string(),
+        serde_json::to_value(links).expect("links serialize"),
+    );
+    claim.custom = Some(serde_json::Value::Object(custom));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::claimstore::{ClaimStore, MemoryClaimStore};
+    use crate::dependencies::{DependencyResolver, MemoryBundleSource};
+    use crate::driver::DebugDriver;
+    use crate::secrets::SecretResolver;
+
+    fn bundle(json: serde_json::Value) -> Bundle {
+        let mut bundle = serde_json::json!({
+            "schemaVersion": "1.0.0",
+            "invocationImages": [{ "image": "example/installer:1.0", "imageType": "docker" }],
+            "definitions": { "string": { "type": "string", "default": "wordpress" } }
+        });
+        bundle
+            .as_object_mut()
+            .unwrap()
+            .extend(json.as_object().unwrap().clone());
+        serde_json::from_value(bundle).unwrap()
+    }
+
+    #[test]
+    fn test_engine_installs_plan() {
+        let mysql = bundle(serde_json::json!({
+            "name": "mysql",
+            "version": "5.7.20",
+            "parameters": {
+                "database": { "definition": "string", "destination": { "env": "DATABASE" } }
+            },
+            "credentials": { "password": { "env": "PASSWORD" } }
+        }));
+        let mut source = MemoryBundleSource::new();
+        source.add("example.com/mysql", mysql).unwrap();
+        let wordpress = bundle(serde_json::json!({
+            "name": "wordpress",
+            "version": "1.0.0",
+            "requiredExtensions": [DEPENDENCIES_KEY],
+            "parameters": {
+                "db-name": { "definition": "string", "destination": { "env": "DB_NAME" } }
+            },
+            "credentials": { "db-password": { "env": "DB_PASSWORD" } },
+            "custom": {
+                "io.cnab.dependencies": {
+                    "requires": {
+                        "mysql": {
+                            "bundle": "example.com/mysql",
+                            "parameters": { "database": "db-name" },
+                            "credentials": { "password": "db-password" }
+                        }
+                    }
+                }
+            }
+        }));
+        let plan = DependencyResolver::new(&source)
+            .resolve(&wordpress, "blog")
+            .unwrap();
+        let credentials: CredentialSet = serde_json::from_str(
+            r#"{"name": "dev", "credentials": [{"name": "db-password", "source": {"value": "s3cr3t"}}]}"#,
+        )
+        .unwrap();
+
+        let (driver, claims) = (DebugDriver::new(), MemoryClaimStore::new());
+        let engine = Engine::new(&driver, &claims).secrets(SecretResolver::empty());
+        assert!(matches!(
+            engine.install("blog", &wordpress, &[], std::slice::from_ref(&credentials)),
+            Err(EngineError::UnsupportedExtensions(_))
+        ));
+        let installed = engine
+            .install_plan(&plan, &[], std::slice::from_ref(&credentials))
+            .unwrap();
+        let names: Vec<_> = installed.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["blog-mysql", "blog"]);
+
+        let operations = driver.operations();
+        assert_eq!(operations[0].environment["DATABASE"], "wordpress");
+        assert!(operations[0].sensitive_environment.contains("PASSWORD"));
+        assert!(operations[1].sensitive_environment.contains("DB_PASSWORD"));
+
+        let mysql = claims.read("blog-mysql").unwrap().unwrap();
+        assert_eq!(
+            mysql.bundle_reference.as_deref(),
+            Some("example.com/mysql:5.7.20")
+        );
+        assert_eq!(
+            mysql.dependency_links().unwrap().unwrap().parents,
+            vec!["blog"]
+        );
+        let blog = claims.read("blog").unwrap().unwrap();
+        assert_eq!(
+            blog.dependency_links().unwrap().unwrap().dependencies["mysql"],
+            "blog-mysql"
+        );
+
+        match engine.install_plan(&plan, &[], &[credentials]) {
+            Err(EngineError::AlreadyInstalled(name)) => assert_eq!(name, "blog"),
+            other => panic!("expected already installed, got {:?}", other),
+        }
+        assert_eq!(
+            driver.operations().len(),
+            2,
+            "installed dependencies are not installed again"
+        );
+    }
+}
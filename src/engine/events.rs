@@ -0,0 +1,172 @@
+use crate::claim::Status;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// ClaimEvent is a change the engine made to the claims of an installation.
+///
+/// Events of a run come in this order: `InstallationCreated` for an install, then
+/// `RunStarted`, an `OutputStored` for each output, and `ResultRecorded`. Actions that
+/// do not modify the installation only start runs, since nothing of theirs is
+/// recorded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ClaimEvent {
+    /// An install stored the first claim of the installation, or of a new one in its
+    /// place after an uninstall
+    #[serde(rename_all = "camelCase")]
+    InstallationCreated {
+        installation: String,
+        revision: String,
+    },
+    /// The invocation image of an action is about to run
+    #[serde(rename_all = "camelCase")]
+    RunStarted {
+        installation: String,
+        action: String,
+        revision: String,
+    },
+    /// An output of a run was stored, in the claim store or at `reference` in the
+    /// engine's `OutputSink`
+    #[serde(rename_all = "camelCase")]
+    OutputStored {
+        installation: String,
+        revision: String,
+        output: String,
+        /// The `sha256:<hex>` digest of the output
+        digest: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reference: Option<String>,
+    },
+    /// The result of a run was recorded in its claim
+    #[serde(rename_all = "camelCase")]
+    ResultRecorded {
+        installation: String,
+        action: String,
+        revision: String,
+        status: Status,
+    },
+}
+
+impl ClaimEvent {
+    /// The installation the event is about.
+    pub fn installation(&self) -> &str {
+        match self {
+            ClaimEvent::InstallationCreated { installation, .. }
+            | ClaimEvent::RunStarted { installation, .. }
+            | ClaimEvent::OutputStored { installation, .. }
+            | ClaimEvent::ResultRecorded { installation, .. } => installation,
+        }
+    }
+}
+
+/// EventSink receives the `ClaimEvent`s of the actions the engine runs, as they
+/// happen, such as to send webhooks or to invalidate caches of the claim store. It
+/// is given to the engine with `Engine::events`.
+///
+/// Events are sent once what they describe is done, so a sink that reads the claim
+/// store finds it there. Any `Fn(&ClaimEvent)` is an EventSink.
+pub trait EventSink {
+    fn event(&self, event: &ClaimEvent);
+}
+
+impl<F> EventSink for F
+where
+    F: Fn(&ClaimEvent),
+{
+    fn event(&self, event: &ClaimEvent) {
+        self(event)
+    }
+}
+
+impl fmt::Debug for dyn EventSink + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EventSink")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::claimstore::{ClaimStore, MemoryClaimStore};
+    use crate::driver::DebugDriver;
+    use crate::engine::Engine;
+    use crate::secrets::SecretResolver;
+    use crate::{Bundle, CredentialSet};
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_claim_events() {
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let credentials: Vec<CredentialSet> = vec![serde_json::from_str(
+            r#"{"name": "dev", "credentials": [{"name": "hostkey", "source": {"value": "key"}}]}"#,
+        )
+        .unwrap()];
+        let (driver, claims) = (DebugDriver::new(), MemoryClaimStore::new());
+        let events = Mutex::new(Vec::new());
+        let sink = |event: &ClaimEvent| {
+            // The claim store has what the event describes already.
+            if let ClaimEvent::ResultRecorded { status, .. } = event {
+                let claim = claims.read(event.installation()).unwrap().unwrap();
+                assert_eq!(claim.result.status(), *status);
+            }
+            events.lock().unwrap().push(event.clone());
+        };
+        let engine = Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .events(&sink);
+
+        let installed = engine.install("hello", &bundle, &[], &credentials).unwrap();
+        let upgraded = engine.upgrade("hello", &bundle, &[], &credentials).unwrap();
+        let revision = |claim: &crate::Claim| claim.revision.clone();
+        assert_eq!(
+            events.into_inner().unwrap(),
+            vec![
+                ClaimEvent::InstallationCreated {
+                    installation: "hello".to_string(),
+                    revision: revision(&installed),
+                },
+                ClaimEvent::RunStarted {
+                    installation: "hello".to_string(),
+                    action: "install".to_string(),
+                    revision: revision(&installed),
+                },
+                ClaimEvent::ResultRecorded {
+                    installation: "hello".to_string(),
+                    action: "install".to_string(),
+                    revision: revision(&installed),
+                    status: Status::Success,
+                },
+                ClaimEvent::RunStarted {
+                    installation: "hello".to_string(),
+                    action: "upgrade".to_string(),
+                    revision: revision(&upgraded),
+                },
+                ClaimEvent::ResultRecorded {
+                    installation: "hello".to_string(),
+                    action: "upgrade".to_string(),
+                    revision: revision(&upgraded),
+                    status: Status::Success,
+                },
+            ]
+        );
+
+        let json = serde_json::to_value(ClaimEvent::OutputStored {
+            installation: "hello".to_string(),
+            revision: "01".to_string(),
+            output: "port".to_string(),
+            digest: "sha256:0123".to_string(),
+            reference: None,
+        })
+        .unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "outputStored",
+                "installation": "hello",
+                "revision": "01",
+                "output": "port",
+                "digest": "sha256:0123"
+            })
+        );
+    }
+}
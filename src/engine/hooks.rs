@@ -0,0 +1,93 @@
+use crate::claim::Claim;
+use crate::driver::Operation;
+
+/// The error a hook returns to stop an operation.
+pub type HookError = Box<dyn std::error::Error + Send + Sync>;
+
+/// EngineHook lets embedders take part in every operation the engine runs.
+///
+/// Hooks are registered with `Engine::hook` and called in the order they were added.
+/// Both methods do nothing by default, so a hook only implements what it needs.
+pub trait EngineHook {
+    /// Called once the operation is built and its driver chosen, before anything is
+    /// recorded or run. The hook may change the operation, or return an error to stop
+    /// it; a stopped operation leaves no claim behind.
+    fn before_operation(&self, _op: &mut Operation) -> Result<(), HookError> {
+        Ok(())
+    }
+
+    /// Called with the final claim after the operation has run, whether it succeeded
+    /// or failed.
+    fn after_result(&self, _op: &Operation, _claim: &Claim) {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::claim::Status;
+    use crate::claimstore::{ClaimStore, MemoryClaimStore};
+    use crate::driver::DebugDriver;
+    use crate::engine::{Engine, EngineError};
+    use crate::secrets::SecretResolver;
+    use crate::{Bundle, CredentialSet};
+    use std::sync::Mutex;
+
+    /// Labels every operation, refuses the "prod" installation and records results.
+    #[derive(Default)]
+    struct Policy {
+        results: Mutex<Vec<(String, Status)>>,
+    }
+
+    impl EngineHook for Policy {
+        fn before_operation(&self, op: &mut Operation) -> Result<(), HookError> {
+            if op.installation == "prod" {
+                return Err("installing to prod requires approval".into());
+            }
+            op.environment
+                .insert("POLICY".to_string(), "checked".to_string());
+            Ok(())
+        }
+
+        fn after_result(&self, op: &Operation, claim: &Claim) {
+            self.results
+                .lock()
+                .unwrap()
+                .push((op.action.clone(), claim.result.status()));
+        }
+    }
+
+    #[test]
+    fn test_engine_hooks() {
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let credentials: Vec<CredentialSet> = vec![serde_json::from_str(
+            r#"{"name": "dev", "credentials": [{"name": "hostkey", "source": {"value": "key"}}]}"#,
+        )
+        .unwrap()];
+        let (driver, claims, policy) = (
+            DebugDriver::new(),
+            MemoryClaimStore::new(),
+            Policy::default(),
+        );
+        let engine = Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .hook(&policy);
+
+        engine
+            .install("dev", &bundle, &[], &credentials)
+            .expect("installed");
+        assert_eq!(driver.operations()[0].environment["POLICY"], "checked");
+        assert_eq!(
+            *policy.results.lock().unwrap(),
+            vec![("install".to_string(), Status::Success)]
+        );
+
+        match engine.install("prod", &bundle, &[], &credentials) {
+            Err(EngineError::Rejected(e)) => {
+                assert_eq!(e.to_string(), "installing to prod requires approval")
+            }
+            other => panic!("expected rejection, got {:?}", other),
+        }
+        assert!(claims.read("prod").unwrap().is_none());
+        assert_eq!(driver.operations().len(), 1);
+    }
+}
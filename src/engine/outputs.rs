@@ -0,0 +1,516 @@
+use super::{Engine, EngineError};
+use crate::cnab::Bundle;
+use crate::driver::{OperationResult, OutputContents};
+use crate::runtime::coerce;
+use crate::schema::{check_value, resolve};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::Read;
+
+/// Outputs up to this size are decoded and recorded in the claim itself. Larger
+/// outputs are only kept in the claim store.
+pub const INLINE_OUTPUT_LIMIT: usize = 64 * 1024;
+
+/// The key in a claim's `custom` data under which output digests are recorded
+pub const OUTPUT_DIGESTS_KEY: &str = "libcnab.output-digests";
+
+/// The key in a claim's `custom` data under which the references an `OutputSink`
+/// returned are recorded
+pub const OUTPUT_REFERENCES_KEY: &str = "libcnab.output-references";
+
+/// OutputSink keeps the outputs that are over their size limit, such as in object
+/// storage, so they do not fill the claim store. It is given to the engine with
+/// `Engine::output_sink`.
+///
+/// The claim of the run records the reference the sink returns for an output under
+/// `OUTPUT_REFERENCES_KEY`, and the output's digest as it does for every output.
+pub trait OutputSink {
+    /// Store the output `output` of `installation`, streaming from `contents`, and
+    /// return where it was stored, such as a URL.
+    fn store(
+        &self,
+        installation: &str,
+        output: &str,
+        contents: &mut dyn Read,
+    ) -> std::io::Result<String>;
+}
+
+/// The outputs collected from a run
+#[derive(Debug, Default)]
+pub(crate) struct CollectedOutputs {
+    /// Decoded values of the outputs small enough to keep in the claim
+    pub values: BTreeMap<String, String>,
+    /// `sha256:<hex>` digests of every output
+    pub digests: BTreeMap<String, String>,
+    /// Where the `OutputSink` stored the outputs over their limit
+    pub references: BTreeMap<String, String>,
+}
+
+impl<'a> Engine<'a> {
+    /// Validate, digest and store each output of a run.
+    ///
+    /// Outputs up to `INLINE_OUTPUT_LIMIT` are checked against their definition
+    /// before they are stored, and an invalid one fails the run. Larger outputs are
+    /// streamed, so they never need to fit in memory, and are not checked. Outputs
+    /// over their size limit go to the output sink instead of the claim store, and
+    /// are not inlined. When `persist` is false the outputs are checked but not
+    /// saved.
+    ///
+    /// Every output is checked before any is stored, so an invalid output leaves
+    /// the outputs of the last successful run in place. The files of the outputs
+    /// are removed whether or not the run succeeds.
+    pub(crate) fn collect_outputs(
+        &self,
+        bundle: &Bundle,
+        installation: &str,
+        action: &str,
+        result: &OperationResult,
+        persist: bool,
+    ) -> Result<CollectedOutputs, EngineError> {
+        let collected = self
+            .check_outputs(bundle, action, result, persist)
+            .and_then(|checked| self.store_outputs(installation, result, checked, persist));
+        for contents in result.outputs.values() {
+            if let OutputContents::File(path) = contents {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+        collected
+    }
+
+    /// Validate and digest every output, and decide where each is stored.
+    fn check_outputs(
+        &self,
+        bundle: &Bundle,
+        action: &str,
+        result: &OperationResult,
+        persist: bool,
+    ) -> Result<Vec<CheckedOutput<'a>>, EngineError> {
+        let mut checked = Vec::new();
+        for (name, contents) in &result.outputs {
+            let invalid = |message: String| EngineError::InvalidOutput {
+                name: name.clone(),
+                message,
+            };
+            let output = bundle
+                .outputs
+                .as_ref()
+                .and_then(|o| o.get(name))
+                .ok_or_else(|| invalid("output is not declared by the bundle".to_string()))?;
+            if !output.applies_to(action) {
+                return Err(invalid(format!(
+                    "output does not apply to action {}",
+                    action
+                )));
+            }
+
+            let mut reader = DigestReader::new(contents.open()?);
+            let mut head = Vec::new();
+            (&mut reader)
+                .take(INLINE_OUTPUT_LIMIT as u64 + 1)
+                .read_to_end(&mut head)?;
+            let value = inline_value(bundle, name, &output.definition, &head)?;
+            std::io::copy(&mut reader, &mut std::io::sink())?;
+
+            let limit = self.output_limits.get(name).copied().or(self.output_limit);
+            let over_limit = match limit {
+                Some(limit) if persist && contents.size()? > limit => Some(limit),
+                _ => None,
+            };
+            let sink = match (over_limit, self.output_sink) {
+                (Some(limit), None) => {
+                    return Err(invalid(format!(
+                        "output is over the limit of {} bytes",
+                        limit
+                    )))
+                }
+                (Some(_), Some(sink)) => Some(sink),
+                (None, _) => None,
+            };
+            checked.push(CheckedOutput {
+                name: name.clone(),
+                value: value.filter(|_| sink.is_none()),
+                digest: format!("sha256:{}", hex::encode(reader.hasher.finalize())),
+                sink,
+            });
+        }
+        Ok(checked)
+    }
+
+    /// Store the checked outputs in the claim store, or their sink.
+    fn store_outputs(
+        &self,
+        installation: &str,
+        result: &OperationResult,
+        checked: Vec<CheckedOutput<'a>>,
+        persist: bool,
+    ) -> Result<CollectedOutputs, EngineError> {
+        let mut collected = CollectedOutputs::default();
+        for output in checked {
+            let contents = &result.outputs[&output.name];
+            if let Some(sink) = output.sink {
+                let reference = sink.store(installation, &output.name, &mut contents.open()?)?;
+                collected.references.insert(output.name.clone(), reference);
+            } else if persist {
+                self.claims
+                    .store_output(installation, &output.name, &mut contents.open()?)?;
+            }
+            collected.digests.insert(output.name.clone(), output.digest);
+            if let Some(value) = output.value {
+                collected.values.insert(output.name, value);
+            }
+        }
+        Ok(collected)
+    }
+}
+
+/// An output that passed its checks, waiting to be stored
+struct CheckedOutput<'a> {
+    name: String,
+    /// The decoded value to keep in the claim, if it is small enough
+    value: Option<String>,
+    /// The `sha256:<hex>` digest of the contents
+    digest: String,
+    /// The sink to store the output in instead of the claim store
+    sink: Option<&'a dyn OutputSink>,
+}
+
+/// The value of an output whose contents start with `head`, checked against its
+/// definition, or `None` if the output is too large to inline.
+fn inline_value(
+    bundle: &Bundle,
+    name: &str,
+    definition: &str,
+    head: &[u8],
+) -> Result<Option<String>, EngineError> {
+    if head.len() > INLINE_OUTPUT_LIMIT {
+        return Ok(None);
+    }
+    let value = bundle.output_value(name, head)?;
+    if let Some(definition) = bundle.definition(definition) {
+        let definitions = bundle.definitions.as_ref();
+        let violations = match coerce(Some(resolve(definitions, definition)), value.clone()) {
+            Ok(typed) => check_value(definitions, definition, &typed),
+            Err(message) => vec![message],
+        };
+        if !violations.is_empty() {
+            return Err(EngineError::InvalidOutput {
+                name: name.to_string(),
+                message: violations.join("; "),
+            });
+        }
+    }
+    Ok(Some(value))
+}
+
+/// DigestReader hashes everything read through it, so an output is only read once.
+struct DigestReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> DigestReader<R> {
+    fn new(inner: R) -> Self {
+        DigestReader {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+}
+
+impl<R: Read> Read for DigestReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::claimstore::{ClaimStore, MemoryClaimStore};
+    use crate::driver::{Driver, DriverError, ImageType, Operation};
+    use crate::engine::ClaimEvent;
+    use crate::secrets::SecretResolver;
+
+    /// A driver that reports the outputs it was built with.
+    struct OutputDriver(BTreeMap<String, OutputContents>);
+
+    impl Driver for OutputDriver {
+        fn run(&self, _: &Operation) -> Result<OperationResult, DriverError> {
+            Ok(OperationResult {
+                outputs: self.0.clone(),
+            })
+        }
+
+        fn handles(&self, _: &ImageType) -> bool {
+            true
+        }
+    }
+
+    fn bundle() -> Bundle {
+        Bundle::from_json(
+            r#"{
+                "name": "outputs",
+                "invocationImages": [{ "image": "example/outputs:1.0.0" }],
+                "schemaVersion": "v1.0.0",
+                "version": "1.0.0",
+                "definitions": {
+                    "level": { "type": "string", "enum": ["debug", "info"] },
+                    "port": { "type": "integer", "maximum": 65535 },
+                    "string": { "type": "string" },
+                    "token": { "type": "string", "pattern": "^[0-9a-f]+$" }
+                },
+                "outputs": {
+                    "level": { "definition": "level" },
+                    "port": { "definition": "port" },
+                    "state": { "definition": "string" },
+                    "token": { "definition": "token" }
+                }
+            }"#
+            .as_bytes(),
+        )
+        .unwrap()
+    }
+
+    fn install(outputs: Vec<(&str, OutputContents)>) -> Result<crate::Claim, EngineError> {
+        install_in(&MemoryClaimStore::new(), outputs)
+    }
+
+    fn install_in(
+        claims: &MemoryClaimStore,
+        outputs: Vec<(&str, OutputContents)>,
+    ) -> Result<crate::Claim, EngineError> {
+        let driver = OutputDriver(
+            outputs
+                .into_iter()
+                .map(|(name, contents)| (name.to_string(), contents))
+                .collect(),
+        );
+        let engine = Engine::new(&driver, claims).secrets(SecretResolver::empty());
+        let claim = engine.install("outputs", &bundle(), &[], &[])?;
+
+        let mut stored = Vec::new();
+        for name in claim.outputs.iter().flat_map(|o| o.keys()) {
+            claims
+                .read_output("outputs", name)
+                .unwrap()
+                .expect("output stored")
+                .read_to_end(&mut stored)
+                .unwrap();
+        }
+        Ok(claim)
+    }
+
+    #[test]
+    fn test_collect_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = dir.path().join("state");
+        std::fs::write(&state, vec![b'x'; INLINE_OUTPUT_LIMIT + 1]).unwrap();
+
+        let claim = install(vec![
+            ("port", b"8080".to_vec().into()),
+            ("state", OutputContents::File(state.clone())),
+        ])
+        .expect("installed");
+        let outputs = claim.outputs.as_ref().unwrap();
+        assert_eq!(outputs["port"], "8080");
+        assert!(
+            !outputs.contains_key("state"),
+            "large outputs are not inlined"
+        );
+        assert!(!state.exists(), "collected output files are removed");
+
+        let digests = &claim.custom.as_ref().unwrap()[OUTPUT_DIGESTS_KEY];
+        assert_eq!(
+            digests["port"],
+            "sha256:6c237681e70921603a306be9a1a5d9833fce5c1e268f52b1650970eaad0dce21"
+        );
+        assert!(digests["state"].as_str().unwrap().starts_with("sha256:"));
+    }
+
+    /// Keeps outputs in memory, referenced as `memory:<installation>/<output>`.
+    #[derive(Default)]
+    struct MemorySink(std::sync::Mutex<BTreeMap<String, Vec<u8>>>);
+
+    impl OutputSink for MemorySink {
+        fn store(
+            &self,
+            installation: &str,
+            output: &str,
+            contents: &mut dyn Read,
+        ) -> std::io::Result<String> {
+            let mut stored = Vec::new();
+            contents.read_to_end(&mut stored)?;
+            let reference = format!("memory:{}/{}", installation, output);
+            self.0.lock().unwrap().insert(reference.clone(), stored);
+            Ok(reference)
+        }
+    }
+
+    #[test]
+    fn test_output_limits() {
+        let driver = OutputDriver(
+            vec![
+                ("port".to_string(), b"8080".to_vec().into()),
+                ("state".to_string(), b"a large state".to_vec().into()),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let (claims, sink) = (MemoryClaimStore::new(), MemorySink::default());
+        let events = std::sync::Mutex::new(Vec::new());
+        let stored = |event: &ClaimEvent| {
+            if let ClaimEvent::OutputStored {
+                output, reference, ..
+            } = event
+            {
+                events
+                    .lock()
+                    .unwrap()
+                    .push((output.clone(), reference.clone()));
+            }
+        };
+        let claim = Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .output_limit(4)
+            .output_sink(&sink)
+            .events(&stored)
+            .install("outputs", &bundle(), &[], &[])
+            .expect("installed");
+        assert_eq!(
+            events.into_inner().unwrap(),
+            vec![
+                ("port".to_string(), None),
+                (
+                    "state".to_string(),
+                    Some("memory:outputs/state".to_string())
+                )
+            ]
+        );
+
+        assert_eq!(
+            claim.outputs.as_ref().unwrap().keys().collect::<Vec<_>>(),
+            ["port"]
+        );
+        assert!(claims.read_output("outputs", "state").unwrap().is_none());
+        assert_eq!(
+            sink.0.lock().unwrap()["memory:outputs/state"],
+            b"a large state"
+        );
+        let custom = claim.custom.as_ref().unwrap();
+        assert_eq!(
+            custom[OUTPUT_REFERENCES_KEY],
+            serde_json::json!({ "state": "memory:outputs/state" })
+        );
+        assert!(custom[OUTPUT_DIGESTS_KEY]["state"]
+            .as_str()
+            .unwrap()
+            .starts_with("sha256:"));
+
+        // Without a sink, an output over its limit fails the action.
+        let claims = MemoryClaimStore::new();
+        let result = Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .output_limit_for("port", 2)
+            .install("outputs", &bundle(), &[], &[]);
+        match result {
+            Err(EngineError::InvalidOutput { name, message }) => {
+                assert_eq!(name, "port");
+                assert_eq!(message, "output is over the limit of 2 bytes");
+            }
+            other => panic!("expected an output over its limit, got {:?}", other),
+        }
+        assert!(claims.read_output("outputs", "port").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_reject_invalid_outputs() {
+        match install(vec![("port", b"eighty".to_vec().into())]) {
+            Err(EngineError::InvalidOutput { name, .. }) => assert_eq!(name, "port"),
+            other => panic!("expected invalid output, got {:?}", other),
+        }
+        match install(vec![("extra", b"1".to_vec().into())]) {
+            Err(EngineError::InvalidOutput { name, .. }) => assert_eq!(name, "extra"),
+            other => panic!("expected undeclared output, got {:?}", other),
+        }
+
+        let invalid = [
+            ("port", "70000", "70000 is greater than the maximum 65535"),
+            (
+                "level",
+                "trace",
+                "\"trace\" is not one of the allowed values",
+            ),
+            ("token", "not hex", "\"not hex\" does not match ^[0-9a-f]+$"),
+        ];
+        for (output, contents, expected) in invalid {
+            let claims = MemoryClaimStore::new();
+            match install_in(&claims, vec![(output, contents.as_bytes().to_vec().into())]) {
+                Err(EngineError::InvalidOutput { name, message }) => {
+                    assert_eq!((name.as_str(), message.as_str()), (output, expected))
+                }
+                other => panic!("expected invalid {}, got {:?}", output, other),
+            }
+            assert!(
+                claims.read_output("outputs", output).unwrap().is_none(),
+                "invalid outputs are not stored"
+            );
+            let claim = claims.read("outputs").unwrap().expect("claim stored");
+            assert!(claim.result.message().unwrap().contains(expected));
+        }
+    }
+
+    #[test]
+    fn test_invalid_output_keeps_previous_outputs() {
+        let claims = MemoryClaimStore::new();
+        install_in(
+            &claims,
+            vec![
+                ("level", b"info".to_vec().into()),
+                ("port", b"8080".to_vec().into()),
+            ],
+        )
+        .expect("installed");
+
+        let dir = tempfile::tempdir().unwrap();
+        let level = dir.path().join("level");
+        let state = dir.path().join("state");
+        std::fs::write(&level, b"debug").unwrap();
+        std::fs::write(&state, b"state").unwrap();
+        let driver = OutputDriver(
+            vec![
+                ("level".to_string(), OutputContents::File(level.clone())),
+                ("port".to_string(), b"eighty".to_vec().into()),
+                ("state".to_string(), OutputContents::File(state.clone())),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let result = Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .upgrade("outputs", &bundle(), &[], &[]);
+        match result {
+            Err(EngineError::InvalidOutput { name, .. }) => assert_eq!(name, "port"),
+            other => panic!("expected invalid output, got {:?}", other),
+        }
+
+        let mut stored = String::new();
+        claims
+            .read_output("outputs", "level")
+            .unwrap()
+            .expect("output stored")
+            .read_to_string(&mut stored)
+            .unwrap();
+        assert_eq!(
+            stored, "info",
+            "valid outputs of a failed run are not stored"
+        );
+        assert!(claims.read_output("outputs", "state").unwrap().is_none());
+        assert!(
+            !level.exists() && !state.exists(),
+            "output files are removed"
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use super::modifies;
+use crate::cnab::{Bundle, InvocationImage};
+use crate::driver::{ImageType, Operation};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// ExecutionPlan describes how the engine would run an action, as returned by
+/// `Engine::plan`.
+///
+/// Credentials and sensitive parameters are redacted wherever they appear, so a plan
+/// is safe to print or keep for review.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionPlan {
+    pub action: String,
+    pub installation: String,
+    /// The invocation image the engine selected
+    pub image: InvocationImage,
+    pub image_type: ImageType,
+    /// Whether the action updates the installation's claim
+    pub modifies: bool,
+    /// The environment of the image, with sensitive values redacted
+    pub environment: BTreeMap<String, String>,
+    /// The paths of the files written into the image
+    pub files: Vec<PathBuf>,
+    /// The outputs the action produces, mapped to the path the image writes them to
+    pub outputs: BTreeMap<String, PathBuf>,
+    /// The extensions the bundle requires
+    pub required_extensions: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl ExecutionPlan {
+    pub(crate) fn new(bundle: &Bundle, op: &Operation) -> Self {
+        let redactor = op.redactor();
+        let redact = |text: &str| redactor.redact(text).into_owned();
+        let mut op = op.redacted();
+        // Sensitive values can also end up in other variables and in warnings.
+        for value in op.environment.values_mut() {
+            *value = redact(value);
+        }
+        op.warnings = op.warnings.iter().map(|w| redact(w)).collect();
+        ExecutionPlan {
+            image_type: op.image_type(),
+            modifies: modifies(bundle, &op.action),
+            files: op.files.keys().cloned().collect(),
+            required_extensions: bundle.required_extensions.clone().unwrap_or_default(),
+            action: op.action,
+            installation: op.installation,
+            image: op.image,
+            environment: op.environment,
+            outputs: op.outputs,
+            warnings: op.warnings,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::claimstore::{ClaimStore, MemoryClaimStore};
+    use crate::driver::{DebugDriver, REDACTED};
+    use crate::engine::{Engine, EngineError};
+    use crate::secrets::SecretResolver;
+    use crate::{Bundle, CredentialSet};
+    use std::path::Path;
+
+    #[test]
+    fn test_plan() {
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let credentials: Vec<CredentialSet> = vec![serde_json::from_str(
+            r#"{"name": "dev", "credentials": [{"name": "hostkey", "source": {"value": "key"}}]}"#,
+        )
+        .unwrap()];
+        let (driver, claims) = (DebugDriver::new(), MemoryClaimStore::new());
+        let engine = Engine::new(&driver, &claims).secrets(SecretResolver::empty());
+
+        let plan = engine
+            .plan("install", "hello", &bundle, &[], &credentials)
+            .expect("install planned");
+        assert_eq!(plan.image.image, "technosophos/helloworld:0.1.0");
+        assert_eq!(plan.image_type.as_str(), "docker");
+        assert!(plan.modifies);
+        assert_eq!(plan.environment["HOST_KEY"], REDACTED);
+        assert!(plan
+            .files
+            .iter()
+            .any(|f| f == Path::new("/etc/hostkey.txt")));
+        let json = serde_json::to_value(&plan).unwrap();
+        assert_eq!(json["imageType"], "docker");
+        assert!(!json.to_string().contains("\"key\""));
+
+        assert!(driver.operations().is_empty());
+        assert!(claims.list().unwrap().is_empty());
+        match engine.plan("uninstall", "hello", &bundle, &[], &credentials) {
+            Err(EngineError::NotInstalled(name)) => assert_eq!(name, "hello"),
+            other => panic!("expected not installed, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use crate::cnab::Bundle;
+use sha2::{Digest, Sha256};
+
+/// SignatureStatus is what is known about the signature of a bundle.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum SignatureStatus {
+    /// No signature was found, or no verifier is configured
+    #[default]
+    Unsigned,
+    /// The bundle is signed by `signer` and the signature is valid
+    Verified { signer: String },
+    /// A signature was found but does not match the bundle
+    Invalid(String),
+}
+
+/// SignatureVerifier looks up and checks the signature of a bundle for policies.
+pub trait SignatureVerifier {
+    fn verify(&self, bundle: &Bundle, digest: &str) -> SignatureStatus;
+}
+
+/// PolicyInput is what an `ExecutionPolicy` decides on.
+#[derive(Debug, Clone, Copy)]
+pub struct PolicyInput<'a> {
+    pub bundle: &'a Bundle,
+    /// The `sha256:<hex>` digest of the bundle's JSON
+    pub digest: &'a str,
+    pub signature: &'a SignatureStatus,
+    pub action: &'a str,
+    /// The installation the action targets
+    pub installation: &'a str,
+}
+
+/// ExecutionPolicy decides whether the engine may run an action.
+///
+/// Policies are registered with `Engine::policy` and evaluated, in order, before any
+/// value is resolved. The first one to return an error stops the action with
+/// `EngineError::Denied`. Any `Fn(&PolicyInput) -> Result<(), String>` is a policy:
+///
+/// ```
+/// use libcnab::engine::{PolicyInput, SignatureStatus};
+///
+/// let approved_uninstalls = ["staging"];
+/// let policy = move |input: &PolicyInput| match (input.action, input.signature) {
+///     (_, SignatureStatus::Unsigned) => Err("only signed bundles may run".to_string()),
+///     ("uninstall", _) if !approved_uninstalls.contains(&input.installation) => {
+///         Err(format!("uninstalling {} needs approval", input.installation))
+///     }
+///     _ => Ok(()),
+/// };
+/// # let _: &dyn libcnab::engine::ExecutionPolicy = &policy;
+/// ```
+pub trait ExecutionPolicy {
+    /// Allow the action, or refuse it with the reason.
+    fn evaluate(&self, input: &PolicyInput<'_>) -> Result<(), String>;
+}
+
+impl<F> ExecutionPolicy for F
+where
+    F: Fn(&PolicyInput<'_>) -> Result<(), String>,
+{
+    fn evaluate(&self, input: &PolicyInput<'_>) -> Result<(), String> {
+        self(input)
+    }
+}
+
+/// A policy that only allows bundles with a valid signature, as the verifier set with
+/// `Engine::signatures` finds.
+pub fn verified_only(input: &PolicyInput<'_>) -> Result<(), String> {
+    match input.signature {
+        SignatureStatus::Verified { .. } => Ok(()),
+        SignatureStatus::Unsigned => Err(format!("{} is not signed", input.digest)),
+        SignatureStatus::Invalid(reason) => Err(format!(
+            "the signature of {} is invalid: {}",
+            input.digest, reason
+        )),
+    }
+}
+
+/// A policy that only allows bundles whose images are all pinned to digests, as
+/// `Bundle::unpinned_images` tells. Unlike `Engine::require_pinned_images`, it does
+/// not look at where the engine relocates images to.
+pub fn pinned_images_only(input: &PolicyInput<'_>) -> Result<(), String> {
+    let unpinned = input.bundle.unpinned_images();
+    if unpinned.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} refers to images by tag: {}",
+            input.digest,
+            unpinned.join(", ")
+        ))
+    }
+}
+
+/// The `sha256:<hex>` digest of a bundle's JSON as this crate writes it.
+pub(crate) fn bundle_digest(bundle: &Bundle) -> String {
+    let json = serde_json::to_vec(bundle).expect("bundles serialize");
+    format!("sha256:{}", hex::encode(Sha256::digest(&json)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::claimstore::MemoryClaimStore;
+    use crate::driver::DebugDriver;
+    use crate::engine::{Engine, EngineError};
+    use crate::secrets::SecretResolver;
+    use crate::CredentialSet;
+
+    struct SignedBy(&'static str);
+
+    impl SignatureVerifier for SignedBy {
+        fn verify(&self, _: &Bundle, _: &str) -> SignatureStatus {
+            SignatureStatus::Verified {
+                signer: self.0.to_string(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_execution_policy() {
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let credentials: Vec<CredentialSet> = vec![serde_json::from_str(
+            r#"{"name": "dev", "credentials": [{"name": "hostkey", "source": {"value": "key"}}]}"#,
+        )
+        .unwrap()];
+        let only_signed = |input: &PolicyInput<'_>| match input.signature {
+            SignatureStatus::Verified { signer } if signer == "release-team" => Ok(()),
+            _ => Err(format!(
+                "{} is not signed by the release team",
+                input.digest
+            )),
+        };
+        let (driver, claims) = (DebugDriver::new(), MemoryClaimStore::new());
+
+        let engine = Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .policy(&only_signed);
+        match engine.install("hello", &bundle, &[], &credentials) {
+            Err(EngineError::Denied(reason)) => {
+                assert_eq!(
+                    reason,
+                    format!(
+                        "{} is not signed by the release team",
+                        bundle_digest(&bundle)
+                    )
+                )
+            }
+            other => panic!("expected a denial, got {:?}", other),
+        }
+        assert!(driver.operations().is_empty());
+
+        let verifier = SignedBy("release-team");
+        let engine = engine.signatures(&verifier);
+        engine
+            .install("hello", &bundle, &[], &credentials)
+            .expect("signed bundles run");
+
+        let engine = Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .policy(&verified_only);
+        match engine.install("verified", &bundle, &[], &credentials) {
+            Err(EngineError::Denied(reason)) => {
+                assert_eq!(reason, format!("{} is not signed", bundle_digest(&bundle)))
+            }
+            other => panic!("expected a denial, got {:?}", other),
+        }
+        engine
+            .signatures(&verifier)
+            .install("verified", &bundle, &[], &credentials)
+            .expect("verified bundles run");
+    }
+}
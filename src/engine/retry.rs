@@ -0,0 +1,83 @@
+use crate::driver::DriverError;
+use std::time::Duration;
+
+/// RetryPolicy decides whether the engine runs an operation again after its driver
+/// fails.
+///
+/// Only failures that happened before the image ran are retried: a failed, cancelled
+/// or timed out invocation image may have changed the installation, so those are
+/// always reported as they are. Among the rest, the policy retries the errors its
+/// classifier accepts, `DriverError::is_transient` by default, waiting longer before
+/// each attempt.
+///
+/// ```
+/// use libcnab::engine::RetryPolicy;
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy::new(4).backoff(Duration::from_secs(1), Duration::from_secs(30));
+/// assert_eq!(policy.delay(1), Duration::from_secs(1));
+/// assert_eq!(policy.delay(3), Duration::from_secs(4));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    retryable: fn(&DriverError) -> bool,
+}
+
+impl Default for RetryPolicy {
+    /// Run each operation once.
+    fn default() -> Self {
+        RetryPolicy::new(1)
+    }
+}
+
+impl RetryPolicy {
+    /// Run an operation up to `max_attempts` times in all, waiting 1 second before the
+    /// first retry and doubling the wait up to 1 minute.
+    pub fn new(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            retryable: DriverError::is_transient,
+        }
+    }
+
+    /// Wait `initial` before the first retry, doubling the wait for each later retry
+    /// but never waiting longer than `max`.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Classify which driver errors are worth retrying.
+    pub fn retry_if(mut self, retryable: fn(&DriverError) -> bool) -> Self {
+        self.retryable = retryable;
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Whether to run the operation again after `attempt` attempts failed with `error`.
+    pub fn retries(&self, attempt: u32, error: &DriverError) -> bool {
+        let ran = matches!(
+            error,
+            DriverError::Failed { .. } | DriverError::Cancelled | DriverError::TimedOut
+        );
+        attempt < self.max_attempts && !ran && (self.retryable)(error)
+    }
+
+    /// How long to wait after `attempt` attempts failed.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
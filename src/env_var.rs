@@ -0,0 +1,166 @@
+use crate::error_code::{Coded, ErrorCode};
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+/// EnvVarName is the name of an environment variable that a parameter or credential
+/// is placed in.
+///
+/// It is made only of ASCII letters, digits and underscores, and does not start with
+/// a digit, which POSIX requires of names shells and `env` can set. A bundle naming
+/// any other variable fails to parse, instead of failing when its image is run.
+///
+/// ```
+/// use libcnab::EnvVarName;
+///
+/// let name: EnvVarName = "BACKEND_PORT".parse().unwrap();
+/// assert_eq!(name, "BACKEND_PORT");
+/// assert!("backend-port".parse::<EnvVarName>().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(transparent)
+)]
+#[serde(try_from = "String", into = "String")]
+pub struct EnvVarName(String);
+
+impl EnvVarName {
+    pub fn new(name: impl Into<String>) -> Result<Self, InvalidEnvVarName> {
+        let name = name.into();
+        let mut chars = name.chars();
+        let valid = match chars.next() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+            }
+            _ => false,
+        };
+        if valid {
+            Ok(EnvVarName(name))
+        } else {
+            Err(InvalidEnvVarName(name))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for EnvVarName {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for EnvVarName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<OsStr> for EnvVarName {
+    fn as_ref(&self) -> &OsStr {
+        self.0.as_ref()
+    }
+}
+
+impl Borrow<str> for EnvVarName {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for EnvVarName {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for EnvVarName {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl fmt::Display for EnvVarName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for EnvVarName {
+    type Err = InvalidEnvVarName;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EnvVarName::new(s)
+    }
+}
+
+impl TryFrom<String> for EnvVarName {
+    type Error = InvalidEnvVarName;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        EnvVarName::new(name)
+    }
+}
+
+impl From<EnvVarName> for String {
+    fn from(name: EnvVarName) -> Self {
+        name.0
+    }
+}
+
+/// InvalidEnvVarName is a name that cannot be given to an environment variable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidEnvVarName(pub String);
+
+impl fmt::Display for InvalidEnvVarName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid environment variable name {:?}: use letters, digits and underscores, not starting with a digit",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidEnvVarName {}
+
+impl Coded for InvalidEnvVarName {
+    fn code(&self) -> ErrorCode {
+        ErrorCode::BundleInvalidEnvVarName
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_env_var_name() {
+        for name in &["PORT", "_PORT", "backend_port_2", "A"] {
+            assert_eq!(EnvVarName::new(*name).unwrap(), *name);
+        }
+        for name in &["", "2PORT", "BACKEND-PORT", "PORT=1", "PÖRT", "A B"] {
+            assert_eq!(
+                EnvVarName::new(*name),
+                Err(InvalidEnvVarName(name.to_string()))
+            );
+        }
+
+        let name: EnvVarName = serde_json::from_str(r#""PORT""#).unwrap();
+        assert_eq!(serde_json::to_string(&name).unwrap(), r#""PORT""#);
+        let error = serde_json::from_str::<EnvVarName>(r#""2PORT""#).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("invalid environment variable name"));
+    }
+}
@@ -0,0 +1,676 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// ErrorCode names what went wrong with an operation of this crate, for services
+/// that map failures to responses and guidance without matching on messages.
+///
+/// Codes are stable: one is never renamed or given to another kind of failure, so
+/// they can be stored and compared across versions of this crate. Their strings,
+/// from `as_str`, are dotted as `<area>.<failure>`. Errors that wrap another error,
+/// such as `EngineError::DriverError`, have the code of the error they wrap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ErrorCode {
+    /// `io`: reading or writing a file or stream failed
+    Io,
+
+    /// `bundle.invalid_json`: a bundle is not valid JSON, or not a valid bundle
+    BundleInvalidJson,
+    /// `bundle.invalid_toml`: a bundle is not valid TOML, or not a valid bundle
+    BundleInvalidToml,
+    /// `bundle.invalid_yaml`: a bundle is not valid YAML, or not a valid bundle
+    BundleInvalidYaml,
+    /// `bundle.unsupported_schema_version`: a bundle is of a schema version this
+    /// crate does not read
+    BundleUnsupportedSchemaVersion,
+    /// `bundle.invalid`: a bundle does not pass `Bundle::validate`
+    BundleInvalid,
+    /// `bundle.invalid_env_var_name`: a bundle places a value in an environment
+    /// variable that cannot be set
+    BundleInvalidEnvVarName,
+    /// `interpolate.undefined_variable`: a bundle template refers to a variable that
+    /// was not given
+    InterpolateUndefinedVariable,
+    /// `interpolate.malformed`: a bundle template has a malformed `${...}` reference
+    InterpolateMalformed,
+    /// `overlay.not_an_object`: an overlay, or its `custom` section, is not a JSON
+    /// object
+    OverlayNotAnObject,
+    /// `overlay.not_a_list`: a field of an overlay must be a list
+    OverlayNotAList,
+    /// `overlay.unknown_field`: an overlay has a field no bundle has
+    OverlayUnknownField,
+    /// `overlay.conflict`: an overlay changes a value it may only repeat
+    OverlayConflict,
+    /// `custom.invalid`: a `custom` section does not match the type it is read as
+    CustomInvalid,
+    /// `custom.unserializable`: a value could not be serialized into a `custom`
+    /// section
+    CustomUnserializable,
+    /// `custom.conflict`: merging into a `custom` section would change a value
+    CustomConflict,
+    /// `stamp.missing`: a bundle has no build stamp
+    StampMissing,
+    /// `stamp.mismatch`: a bundle or its build info changed since it was stamped
+    StampMismatch,
+    /// `layout.invalid`: a directory is not a valid CNAB layout
+    LayoutInvalid,
+    /// `reference.invalid`: a bundle or image reference could not be parsed
+    ReferenceInvalid,
+
+    /// `encoding.unsupported`: a value is in an encoding this crate does not know
+    EncodingUnsupported,
+    /// `encoding.invalid_base64`: a base64 value could not be decoded
+    EncodingInvalidBase64,
+    /// `encoding.invalid_utf8`: a decoded value is not UTF-8
+    EncodingInvalidUtf8,
+    /// `cbor.encode`: a value could not be encoded as CBOR
+    CborEncode,
+    /// `cbor.decode`: CBOR could not be decoded, or is not a valid value
+    CborDecode,
+
+    /// `resolve.unresolved`: none of the sources of a credential or parameter
+    /// produced a value
+    ResolveUnresolved,
+    /// `resolve.invalid_json`: data consulted during resolution could not be parsed
+    ResolveInvalidJson,
+    /// `resolve.unknown_secret_source`: no secret source is registered under a name
+    ResolveUnknownSecretSource,
+    /// `resolve.secret_failed`: a secret source failed to produce a secret
+    ResolveSecretFailed,
+
+    /// `dependency.invalid`: the dependencies of a bundle are malformed
+    DependencyInvalid,
+    /// `dependency.invalid_range`: a dependency has a version range that does not
+    /// parse
+    DependencyInvalidRange,
+    /// `dependency.no_matching_version`: no version of a dependency satisfies what
+    /// requires it
+    DependencyNoMatchingVersion,
+    /// `dependency.cycle`: bundles depend on each other in a cycle
+    DependencyCycle,
+    /// `dependency.unresolvable`: a dependency could not be found
+    DependencyUnresolvable,
+    /// `dependency.source_failed`: the source of dependencies failed to list or
+    /// fetch a bundle
+    DependencySourceFailed,
+    /// `lock.resolve_failed`: the digest of an image could not be resolved for a
+    /// lockfile
+    LockResolveFailed,
+
+    /// `registry.not_found`: a repository, tag, or blob does not exist
+    RegistryNotFound,
+    /// `registry.status`: a registry answered with an unexpected status
+    RegistryStatus,
+    /// `registry.not_a_bundle`: an artifact is not a CNAB bundle
+    RegistryNotABundle,
+    /// `registry.invalid_manifest`: a manifest or index is malformed
+    RegistryInvalidManifest,
+    /// `registry.invalid_config`: the content of a config blob is malformed
+    RegistryInvalidConfig,
+    /// `registry.invalid_certificate`: a TLS certificate or key could not be read
+    RegistryInvalidCertificate,
+    /// `registry.invalid_token`: a token service answered with no usable token
+    RegistryInvalidToken,
+    /// `registry.credential_helper`: a credential helper failed
+    RegistryCredentialHelper,
+    /// `registry.invalid_version_requirement`: a version requirement does not parse
+    RegistryInvalidVersionRequirement,
+    /// `registry.no_matching_version`: no tag of a repository satisfies a version
+    /// requirement
+    RegistryNoMatchingVersion,
+    /// `registry.unsupported_image`: an image cannot be copied, such as one that
+    /// is not an OCI image
+    RegistryUnsupportedImage,
+    /// `registry.digest_mismatch`: content does not have the digest it was fetched
+    /// by
+    RegistryDigestMismatch,
+    /// `registry.unsupported_digest`: content is named by a digest whose algorithm
+    /// cannot be checked
+    RegistryUnsupportedDigest,
+    /// `registry.http`: a registry could not be reached
+    RegistryHttp,
+    /// `fetch.http`: a bundle descriptor could not be fetched over HTTP(S)
+    FetchHttp,
+    /// `fetch.status`: a server answered a fetch with an unexpected status
+    FetchStatus,
+    /// `fetch.too_large`: a fetched bundle descriptor is over the size limit
+    FetchTooLarge,
+    /// `fetch.invalid_checksum`: a pinned checksum is not a sha256 digest
+    FetchInvalidChecksum,
+    /// `fetch.checksum_mismatch`: a fetched bundle descriptor does not have its
+    /// pinned checksum
+    FetchChecksumMismatch,
+    /// `export.invalid_digest`: a digest to export is malformed
+    ExportInvalidDigest,
+    /// `import.invalid_archive`: an archive is not a thick bundle
+    ImportInvalidArchive,
+    /// `import.digest_mismatch`: content of an archive does not have its digest
+    ImportDigestMismatch,
+    /// `sbom.invalid`: an SBOM document is not JSON
+    SbomInvalid,
+    /// `sbom.unknown_format`: a document is neither SPDX nor CycloneDX
+    SbomUnknownFormat,
+
+    /// `claims.invalid_name`: an installation name cannot be stored
+    ClaimsInvalidName,
+    /// `claims.invalid_namespace`: a namespace name cannot be stored
+    ClaimsInvalidNamespace,
+    /// `claims.invalid_json`: a stored claim could not be parsed
+    ClaimsInvalidJson,
+    /// `signature.invalid_key`: a public key could not be parsed
+    SignatureInvalidKey,
+    /// `signature.invalid_signature`: a signature could not be parsed
+    SignatureInvalidSignature,
+    /// `signature.untrusted_key`: a signature was made by a key that is not trusted
+    SignatureUntrustedKey,
+    /// `signature.bad_signature`: a signature does not match what it signs
+    SignatureBadSignature,
+    /// `keyring.invalid_name`: a key name cannot be used in a keyring
+    KeyringInvalidName,
+    /// `keyring.not_found`: a keyring has no key of a name
+    KeyringNotFound,
+    /// `keyring.exists`: a keyring already has a key of a name
+    KeyringExists,
+    /// `keyring.invalid_key`: a stored or imported key could not be parsed
+    KeyringInvalidKey,
+    /// `keyring.wrong_passphrase`: a passphrase does not unseal a key
+    KeyringWrongPassphrase,
+    /// `sigstore.service`: Fulcio or Rekor could not be reached, or refused a
+    /// request
+    SigstoreService,
+    /// `sigstore.invalid_token`: an OIDC identity token could not be read
+    SigstoreInvalidToken,
+    /// `sigstore.invalid`: a certificate, key or Sigstore bundle could not be parsed
+    SigstoreInvalid,
+    /// `sigstore.untrusted_certificate`: a signing certificate was not issued by a
+    /// trusted Fulcio
+    SigstoreUntrustedCertificate,
+    /// `sigstore.untrusted_identity`: a signing certificate was issued to an
+    /// identity that is not allowed
+    SigstoreUntrustedIdentity,
+    /// `sigstore.transparency_log`: a transparency log entry is missing, or does not
+    /// verify
+    SigstoreTransparencyLog,
+    /// `sigstore.bad_signature`: a Sigstore signature does not match the signed
+    /// content
+    SigstoreBadSignature,
+    /// `attestation.invalid_envelope`: an attestation envelope or its statement
+    /// could not be parsed
+    AttestationInvalidEnvelope,
+    /// `attestation.payload_type`: an envelope holds something other than an in-toto
+    /// statement
+    AttestationPayloadType,
+    /// `attestation.unsigned`: an attestation envelope has no signature
+    AttestationUnsigned,
+    /// `attestation.wrong_subject`: an attestation is not about the bundle
+    AttestationWrongSubject,
+    /// `trust.invalid`: a trust policy could not be read or parsed
+    TrustInvalid,
+    /// `trust.no_rule`: no rule of a trust policy applies to a bundle
+    TrustNoRule,
+    /// `trust.unsigned`: a bundle has no signature a trust policy trusts
+    TrustUnsigned,
+    /// `trust.missing_signatures`: a bundle lacks trusted signatures of types its
+    /// rule requires
+    TrustMissingSignatures,
+
+    /// `engine.already_installed`: an install was asked of an installation that
+    /// exists
+    EngineAlreadyInstalled,
+    /// `engine.not_installed`: an action was asked of an installation that does not
+    /// exist
+    EngineNotInstalled,
+    /// `engine.in_progress`: another run is modifying the installation
+    EngineInProgress,
+    /// `engine.unsupported_extensions`: a bundle requires extensions the engine
+    /// does not support
+    EngineUnsupportedExtensions,
+    /// `engine.rejected`: a hook rejected the action
+    EngineRejected,
+    /// `engine.denied`: an execution policy denied the action
+    EngineDenied,
+    /// `engine.unpinned_images`: images of a bundle are not pinned to digests
+    EngineUnpinnedImages,
+    /// `engine.no_driver`: no driver can run the invocation images of a bundle
+    EngineNoDriver,
+    /// `engine.no_image_for_platform`: a bundle has no invocation image for the
+    /// platform
+    EngineNoImageForPlatform,
+    /// `engine.invalid_output`: an output of a run does not satisfy its definition
+    EngineInvalidOutput,
+
+    /// `operation.unknown_action`: a bundle does not support an action
+    OperationUnknownAction,
+    /// `operation.no_invocation_image`: a bundle has no invocation image to run
+    OperationNoInvocationImage,
+    /// `operation.missing_parameter`: a required parameter has no value
+    OperationMissingParameter,
+    /// `operation.invalid_parameter`: a parameter value does not match its
+    /// definition
+    OperationInvalidParameter,
+    /// `operation.missing_credential`: a required credential has no value
+    OperationMissingCredential,
+    /// `operation.invalid_json`: a value of an operation could not be serialized
+    OperationInvalidJson,
+
+    /// `driver.unsupported_image_type`: a driver cannot run images of a type
+    DriverUnsupportedImageType,
+    /// `driver.failed`: the invocation image ran but did not succeed
+    DriverFailed,
+    /// `driver.transient`: the invocation image could not start, for a reason that
+    /// may go away when retried
+    DriverTransient,
+    /// `driver.cancelled`: the operation was cancelled
+    DriverCancelled,
+    /// `driver.timed_out`: the operation ran past its deadline
+    DriverTimedOut,
+    /// `driver.other`: a driver failed for a reason of its backend
+    DriverOther,
+
+    /// `runtime.missing_variable`: a variable the runtime sets is missing
+    RuntimeMissingVariable,
+    /// `runtime.invalid_variable`: a variable the runtime sets does not parse
+    RuntimeInvalidVariable,
+    /// `runtime.unknown_action`: no handler is registered for an action
+    RuntimeUnknownAction,
+    /// `runtime.action_failed`: the handler of an action failed
+    RuntimeActionFailed,
+    /// `runtime.undeclared_output`: an output was written that the bundle does not
+    /// declare
+    RuntimeUndeclaredOutput,
+    /// `runtime.invalid_output`: an output does not satisfy its declaration
+    RuntimeInvalidOutput,
+    /// `runtime.undeclared_parameter`: a parameter the bundle does not declare was
+    /// requested
+    RuntimeUndeclaredParameter,
+    /// `runtime.undeclared_credential`: a credential the bundle does not declare was
+    /// requested
+    RuntimeUndeclaredCredential,
+    /// `runtime.invalid_parameter`: an injected parameter does not match its
+    /// definition
+    RuntimeInvalidParameter,
+    /// `runtime.preflight_failed`: required parameters or credentials were not
+    /// injected
+    RuntimePreflightFailed,
+}
+
+impl ErrorCode {
+    /// Every code, in the order they are declared.
+    pub const ALL: &'static [ErrorCode] = &[
+        ErrorCode::Io,
+        ErrorCode::BundleInvalidJson,
+        ErrorCode::BundleInvalidToml,
+        ErrorCode::BundleInvalidYaml,
+        ErrorCode::BundleUnsupportedSchemaVersion,
+        ErrorCode::BundleInvalid,
+        ErrorCode::BundleInvalidEnvVarName,
+        ErrorCode::InterpolateUndefinedVariable,
+        ErrorCode::InterpolateMalformed,
+        ErrorCode::OverlayNotAnObject,
+        ErrorCode::OverlayNotAList,
+        ErrorCode::OverlayUnknownField,
+        ErrorCode::OverlayConflict,
+        ErrorCode::CustomInvalid,
+        ErrorCode::CustomUnserializable,
+        ErrorCode::CustomConflict,
+        ErrorCode::StampMissing,
+        ErrorCode::StampMismatch,
+        ErrorCode::LayoutInvalid,
+        ErrorCode::ReferenceInvalid,
+        ErrorCode::EncodingUnsupported,
+        ErrorCode::EncodingInvalidBase64,
+        ErrorCode::EncodingInvalidUtf8,
+        ErrorCode::CborEncode,
+        ErrorCode::CborDecode,
+        ErrorCode::ResolveUnresolved,
+        ErrorCode::ResolveInvalidJson,
+        ErrorCode::ResolveUnknownSecretSource,
+        ErrorCode::ResolveSecretFailed,
+        ErrorCode::DependencyInvalid,
+        ErrorCode::DependencyInvalidRange,
+        ErrorCode::DependencyNoMatchingVersion,
+        ErrorCode::DependencyCycle,
+        ErrorCode::DependencyUnresolvable,
+        ErrorCode::DependencySourceFailed,
+        ErrorCode::LockResolveFailed,
+        ErrorCode::RegistryNotFound,
+        ErrorCode::RegistryStatus,
+        ErrorCode::RegistryNotABundle,
+        ErrorCode::RegistryInvalidManifest,
+        ErrorCode::RegistryInvalidConfig,
+        ErrorCode::RegistryInvalidCertificate,
+        ErrorCode::RegistryInvalidToken,
+        ErrorCode::RegistryCredentialHelper,
+        ErrorCode::RegistryInvalidVersionRequirement,
+        ErrorCode::RegistryNoMatchingVersion,
+        ErrorCode::RegistryUnsupportedImage,
+        ErrorCode::RegistryDigestMismatch,
+        ErrorCode::RegistryUnsupportedDigest,
+        ErrorCode::RegistryHttp,
+        ErrorCode::FetchHttp,
+        ErrorCode::FetchStatus,
+        ErrorCode::FetchTooLarge,
+        ErrorCode::FetchInvalidChecksum,
+        ErrorCode::FetchChecksumMismatch,
+        ErrorCode::ExportInvalidDigest,
+        ErrorCode::ImportInvalidArchive,
+        ErrorCode::ImportDigestMismatch,
+        ErrorCode::SbomInvalid,
+        ErrorCode::SbomUnknownFormat,
+        ErrorCode::ClaimsInvalidName,
+        ErrorCode::ClaimsInvalidNamespace,
+        ErrorCode::ClaimsInvalidJson,
+        ErrorCode::SignatureInvalidKey,
+        ErrorCode::SignatureInvalidSignature,
+        ErrorCode::SignatureUntrustedKey,
+        ErrorCode::SignatureBadSignature,
+        ErrorCode::KeyringInvalidName,
+        ErrorCode::KeyringNotFound,
+        ErrorCode::KeyringExists,
+        ErrorCode::KeyringInvalidKey,
+        ErrorCode::KeyringWrongPassphrase,
+        ErrorCode::SigstoreService,
+        ErrorCode::SigstoreInvalidToken,
+        ErrorCode::SigstoreInvalid,
+        ErrorCode::SigstoreUntrustedCertificate,
+        ErrorCode::SigstoreUntrustedIdentity,
+        ErrorCode::SigstoreTransparencyLog,
+        ErrorCode::SigstoreBadSignature,
+        ErrorCode::AttestationInvalidEnvelope,
+        ErrorCode::AttestationPayloadType,
+        ErrorCode::AttestationUnsigned,
+        ErrorCode::AttestationWrongSubject,
+        ErrorCode::TrustInvalid,
+        ErrorCode::TrustNoRule,
+        ErrorCode::TrustUnsigned,
+        ErrorCode::TrustMissingSignatures,
+        ErrorCode::EngineAlreadyInstalled,
+        ErrorCode::EngineNotInstalled,
+        ErrorCode::EngineInProgress,
+        ErrorCode::EngineUnsupportedExtensions,
+        ErrorCode::EngineRejected,
+        ErrorCode::EngineDenied,
+        ErrorCode::EngineUnpinnedImages,
+        ErrorCode::EngineNoDriver,
+        ErrorCode::EngineNoImageForPlatform,
+        ErrorCode::EngineInvalidOutput,
+        ErrorCode::OperationUnknownAction,
+        ErrorCode::OperationNoInvocationImage,
+        ErrorCode::OperationMissingParameter,
+        ErrorCode::OperationInvalidParameter,
+        ErrorCode::OperationMissingCredential,
+        ErrorCode::OperationInvalidJson,
+        ErrorCode::DriverUnsupportedImageType,
+        ErrorCode::DriverFailed,
+        ErrorCode::DriverTransient,
+        ErrorCode::DriverCancelled,
+        ErrorCode::DriverTimedOut,
+        ErrorCode::DriverOther,
+        ErrorCode::RuntimeMissingVariable,
+        ErrorCode::RuntimeInvalidVariable,
+        ErrorCode::RuntimeUnknownAction,
+        ErrorCode::RuntimeActionFailed,
+        ErrorCode::RuntimeUndeclaredOutput,
+        ErrorCode::RuntimeInvalidOutput,
+        ErrorCode::RuntimeUndeclaredParameter,
+        ErrorCode::RuntimeUndeclaredCredential,
+        ErrorCode::RuntimeInvalidParameter,
+        ErrorCode::RuntimePreflightFailed,
+    ];
+
+    /// The string of the code, which is what it serializes as.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::Io => "io",
+            ErrorCode::BundleInvalidJson => "bundle.invalid_json",
+            ErrorCode::BundleInvalidToml => "bundle.invalid_toml",
+            ErrorCode::BundleInvalidYaml => "bundle.invalid_yaml",
+            ErrorCode::BundleUnsupportedSchemaVersion => "bundle.unsupported_schema_version",
+            ErrorCode::BundleInvalid => "bundle.invalid",
+            ErrorCode::BundleInvalidEnvVarName => "bundle.invalid_env_var_name",
+            ErrorCode::InterpolateUndefinedVariable => "interpolate.undefined_variable",
+            ErrorCode::InterpolateMalformed => "interpolate.malformed",
+            ErrorCode::OverlayNotAnObject => "overlay.not_an_object",
+            ErrorCode::OverlayNotAList => "overlay.not_a_list",
+            ErrorCode::OverlayUnknownField => "overlay.unknown_field",
+            ErrorCode::OverlayConflict => "overlay.conflict",
+            ErrorCode::CustomInvalid => "custom.invalid",
+            ErrorCode::CustomUnserializable => "custom.unserializable",
+            ErrorCode::CustomConflict => "custom.conflict",
+            ErrorCode::StampMissing => "stamp.missing",
+            ErrorCode::StampMismatch => "stamp.mismatch",
+            ErrorCode::LayoutInvalid => "layout.invalid",
+            ErrorCode::ReferenceInvalid => "reference.invalid",
+            ErrorCode::EncodingUnsupported => "encoding.unsupported",
+            ErrorCode::EncodingInvalidBase64 => "encoding.invalid_base64",
+            ErrorCode::EncodingInvalidUtf8 => "encoding.invalid_utf8",
+            ErrorCode::CborEncode => "cbor.encode",
+            ErrorCode::CborDecode => "cbor.decode",
+            ErrorCode::ResolveUnresolved => "resolve.unresolved",
+            ErrorCode::ResolveInvalidJson => "resolve.invalid_json",
+            ErrorCode::ResolveUnknownSecretSource => "resolve.unknown_secret_source",
+            ErrorCode::ResolveSecretFailed => "resolve.secret_failed",
+            ErrorCode::DependencyInvalid => "dependency.invalid",
+            ErrorCode::DependencyInvalidRange => "dependency.invalid_range",
+            ErrorCode::DependencyNoMatchingVersion => "dependency.no_matching_version",
+            ErrorCode::DependencyCycle => "dependency.cycle",
+            ErrorCode::DependencyUnresolvable => "dependency.unresolvable",
+            ErrorCode::DependencySourceFailed => "dependency.source_failed",
+            ErrorCode::LockResolveFailed => "lock.resolve_failed",
+            ErrorCode::RegistryNotFound => "registry.not_found",
+            ErrorCode::RegistryStatus => "registry.status",
+            ErrorCode::RegistryNotABundle => "registry.not_a_bundle",
+            ErrorCode::RegistryInvalidManifest => "registry.invalid_manifest",
+            ErrorCode::RegistryInvalidConfig => "registry.invalid_config",
+            ErrorCode::RegistryInvalidCertificate => "registry.invalid_certificate",
+            ErrorCode::RegistryInvalidToken => "registry.invalid_token",
+            ErrorCode::RegistryCredentialHelper => "registry.credential_helper",
+            ErrorCode::RegistryInvalidVersionRequirement => "registry.invalid_version_requirement",
+            ErrorCode::RegistryNoMatchingVersion => "registry.no_matching_version",
+            ErrorCode::RegistryUnsupportedImage => "registry.unsupported_image",
+            ErrorCode::RegistryDigestMismatch => "registry.digest_mismatch",
+            ErrorCode::RegistryUnsupportedDigest => "registry.unsupported_digest",
+            ErrorCode::RegistryHttp => "registry.http",
+            ErrorCode::FetchHttp => "fetch.http",
+            ErrorCode::FetchStatus => "fetch.status",
+            ErrorCode::FetchTooLarge => "fetch.too_large",
+            ErrorCode::FetchInvalidChecksum => "fetch.invalid_checksum",
+            ErrorCode::FetchChecksumMismatch => "fetch.checksum_mismatch",
+            ErrorCode::ExportInvalidDigest => "export.invalid_digest",
+            ErrorCode::ImportInvalidArchive => "import.invalid_archive",
+            ErrorCode::ImportDigestMismatch => "import.digest_mismatch",
+            ErrorCode::SbomInvalid => "sbom.invalid",
+            ErrorCode::SbomUnknownFormat => "sbom.unknown_format",
+            ErrorCode::ClaimsInvalidName => "claims.invalid_name",
+            ErrorCode::ClaimsInvalidNamespace => "claims.invalid_namespace",
+            ErrorCode::ClaimsInvalidJson => "claims.invalid_json",
+            ErrorCode::SignatureInvalidKey => "signature.invalid_key",
+            ErrorCode::SignatureInvalidSignature => "signature.invalid_signature",
+            ErrorCode::SignatureUntrustedKey => "signature.untrusted_key",
+            ErrorCode::SignatureBadSignature => "signature.bad_signature",
+            ErrorCode::KeyringInvalidName => "keyring.invalid_name",
+            ErrorCode::KeyringNotFound => "keyring.not_found",
+            ErrorCode::KeyringExists => "keyring.exists",
+            ErrorCode::KeyringInvalidKey => "keyring.invalid_key",
+            ErrorCode::KeyringWrongPassphrase => "keyring.wrong_passphrase",
+            ErrorCode::SigstoreService => "sigstore.service",
+            ErrorCode::SigstoreInvalidToken => "sigstore.invalid_token",
+            ErrorCode::SigstoreInvalid => "sigstore.invalid",
+            ErrorCode::SigstoreUntrustedCertificate => "sigstore.untrusted_certificate",
+            ErrorCode::SigstoreUntrustedIdentity => "sigstore.untrusted_identity",
+            ErrorCode::SigstoreTransparencyLog => "sigstore.transparency_log",
+            ErrorCode::SigstoreBadSignature => "sigstore.bad_signature",
+            ErrorCode::AttestationInvalidEnvelope => "attestation.invalid_envelope",
+            ErrorCode::AttestationPayloadType => "attestation.payload_type",
+            ErrorCode::AttestationUnsigned => "attestation.unsigned",
+            ErrorCode::AttestationWrongSubject => "attestation.wrong_subject",
+            ErrorCode::TrustInvalid => "trust.invalid",
+            ErrorCode::TrustNoRule => "trust.no_rule",
+            ErrorCode::TrustUnsigned => "trust.unsigned",
+            ErrorCode::TrustMissingSignatures => "trust.missing_signatures",
+            ErrorCode::EngineAlreadyInstalled => "engine.already_installed",
+            ErrorCode::EngineNotInstalled => "engine.not_installed",
+            ErrorCode::EngineInProgress => "engine.in_progress",
+            ErrorCode::EngineUnsupportedExtensions => "engine.unsupported_extensions",
+            ErrorCode::EngineRejected => "engine.rejected",
+            ErrorCode::EngineDenied => "engine.denied",
+            ErrorCode::EngineUnpinnedImages => "engine.unpinned_images",
+            ErrorCode::EngineNoDriver => "engine.no_driver",
+            ErrorCode::EngineNoImageForPlatform => "engine.no_image_for_platform",
+            ErrorCode::EngineInvalidOutput => "engine.invalid_output",
+            ErrorCode::OperationUnknownAction => "operation.unknown_action",
+            ErrorCode::OperationNoInvocationImage => "operation.no_invocation_image",
+            ErrorCode::OperationMissingParameter => "operation.missing_parameter",
+            ErrorCode::OperationInvalidParameter => "operation.invalid_parameter",
+            ErrorCode::OperationMissingCredential => "operation.missing_credential",
+            ErrorCode::OperationInvalidJson => "operation.invalid_json",
+            ErrorCode::DriverUnsupportedImageType => "driver.unsupported_image_type",
+            ErrorCode::DriverFailed => "driver.failed",
+            ErrorCode::DriverTransient => "driver.transient",
+            ErrorCode::DriverCancelled => "driver.cancelled",
+            ErrorCode::DriverTimedOut => "driver.timed_out",
+            ErrorCode::DriverOther => "driver.other",
+            ErrorCode::RuntimeMissingVariable => "runtime.missing_variable",
+            ErrorCode::RuntimeInvalidVariable => "runtime.invalid_variable",
+            ErrorCode::RuntimeUnknownAction => "runtime.unknown_action",
+            ErrorCode::RuntimeActionFailed => "runtime.action_failed",
+            ErrorCode::RuntimeUndeclaredOutput => "runtime.undeclared_output",
+            ErrorCode::RuntimeInvalidOutput => "runtime.invalid_output",
+            ErrorCode::RuntimeUndeclaredParameter => "runtime.undeclared_parameter",
+            ErrorCode::RuntimeUndeclaredCredential => "runtime.undeclared_credential",
+            ErrorCode::RuntimeInvalidParameter => "runtime.invalid_parameter",
+            ErrorCode::RuntimePreflightFailed => "runtime.preflight_failed",
+        }
+    }
+
+    /// The code with this string, if there is one.
+    pub fn from_code(code: &str) -> Option<ErrorCode> {
+        ErrorCode::ALL.iter().copied().find(|c| c.as_str() == code)
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ErrorCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        ErrorCode::from_code(&code)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown error code {}", code)))
+    }
+}
+
+/// Coded is an error with an `ErrorCode`.
+///
+/// It is implemented by the errors of parsing, validation, resolution, the
+/// registry, and execution.
+pub trait Coded: fmt::Display {
+    fn code(&self) -> ErrorCode;
+
+    /// The error as it is serialized for callers of a service.
+    fn report(&self) -> ErrorReport {
+        ErrorReport {
+            code: self.code(),
+            message: self.to_string(),
+        }
+    }
+}
+
+/// ErrorReport is the serialized form of an error: its code, for the caller to act
+/// on, and its message, for a person to read.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorReport {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl fmt::Display for ErrorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        BundleParseError, CustomExtensionError, EncodingError, InterpolateError, InvalidReference,
+        OverlayError, StampError,
+    };
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_error_codes() {
+        let codes: BTreeSet<&str> = ErrorCode::ALL.iter().map(|c| c.as_str()).collect();
+        assert_eq!(codes.len(), ErrorCode::ALL.len());
+        for code in ErrorCode::ALL {
+            assert_eq!(ErrorCode::from_code(code.as_str()), Some(*code));
+        }
+        assert_eq!(ErrorCode::from_code("engine.unknown"), None);
+
+        let error = crate::Bundle::from_json("{".as_bytes()).unwrap_err();
+        assert_eq!(error.code(), ErrorCode::BundleInvalidJson);
+        let error = BundleParseError::UnsupportedSchemaVersion {
+            found: "v2.0.0".to_string(),
+            supported: &["v1.0.0"],
+        };
+        assert_eq!(error.code(), ErrorCode::BundleUnsupportedSchemaVersion);
+        let error = EncodingError::UnsupportedEncoding("rot13".to_string());
+        assert_eq!(error.code(), ErrorCode::EncodingUnsupported);
+        let error = InterpolateError::Undefined {
+            variable: "version".to_string(),
+            path: "/version".to_string(),
+        };
+        assert_eq!(error.code().as_str(), "interpolate.undefined_variable");
+        let error = OverlayError::UnknownField("color".to_string());
+        assert_eq!(error.code().as_str(), "overlay.unknown_field");
+        let error = CustomExtensionError::Conflict {
+            key: "io.cnab.example".to_string(),
+            path: None,
+        };
+        assert_eq!(error.code().as_str(), "custom.conflict");
+        assert_eq!(StampError::Missing.code().as_str(), "stamp.missing");
+        // Errors that wrap another have the code of the error they wrap.
+        let error = InterpolateError::Parse(crate::Bundle::from_json("{".as_bytes()).unwrap_err());
+        assert_eq!(error.code(), ErrorCode::BundleInvalidJson);
+
+        let report = InvalidReference("a b".to_string()).report();
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"code": "reference.invalid", "message": report.message})
+        );
+        assert_eq!(serde_json::from_value::<ErrorReport>(json).unwrap(), report);
+        assert!(serde_json::from_str::<ErrorCode>(r#""engine.unknown""#).is_err());
+    }
+
+    #[cfg(feature = "signing")]
+    #[test]
+    fn test_signing_error_codes() {
+        use crate::attestation::AttestationError;
+        use crate::signing::{KeyringError, SignatureError};
+        use crate::trust::TrustError;
+
+        let error = SignatureError::UntrustedKey("key".to_string());
+        assert_eq!(error.code().as_str(), "signature.untrusted_key");
+        assert_eq!(
+            KeyringError::WrongPassphrase.code().as_str(),
+            "keyring.wrong_passphrase"
+        );
+        assert_eq!(
+            AttestationError::Unsigned.code().as_str(),
+            "attestation.unsigned"
+        );
+        let error = TrustError::InvalidKey(SignatureError::InvalidKey("key".to_string()));
+        assert_eq!(error.code(), ErrorCode::SignatureInvalidKey);
+    }
+}
@@ -0,0 +1,120 @@
+//! A machine-readable event protocol between invocation images and hosts.
+//!
+//! Invocation images write events to stdout as single lines: `EVENT_PREFIX` followed
+//! by a JSON object. Hosts parse each line of output with `Event::parse`; lines that
+//! are not events are ordinary log output and are shown as-is.
+//!
+//! ```
+//! use libcnab::events::Event;
+//!
+//! let line = Event::warning("disk nearly full").to_line();
+//! assert_eq!(Event::parse(&line), Some(Event::warning("disk nearly full")));
+//! assert_eq!(Event::parse("installing..."), None);
+//! ```
+use serde::{Deserialize, Serialize};
+
+/// The prefix that marks a line of output as an event
+pub const EVENT_PREFIX: &str = "::cnab ";
+
+/// Event is a single structured message from an invocation image.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Event {
+    /// Progress through the action, optionally as `current` of `total` steps
+    Progress {
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        current: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        total: Option<u64>,
+    },
+    /// A problem that did not stop the action
+    Warning { message: String },
+    /// An output has been written and is ready to be collected
+    Output { name: String },
+    /// More of an output that is still being written, such as the next lines of a log
+    /// an action follows, for hosts to show before the action exits
+    Chunk { name: String, data: String },
+}
+
+impl Event {
+    pub fn progress(message: &str) -> Self {
+        Event::Progress {
+            message: message.to_string(),
+            current: None,
+            total: None,
+        }
+    }
+
+    pub fn step(message: &str, current: u64, total: u64) -> Self {
+        Event::Progress {
+            message: message.to_string(),
+            current: Some(current),
+            total: Some(total),
+        }
+    }
+
+    pub fn warning(message: &str) -> Self {
+        Event::Warning {
+            message: message.to_string(),
+        }
+    }
+
+    pub fn output(name: &str) -> Self {
+        Event::Output {
+            name: name.to_string(),
+        }
+    }
+
+    pub fn chunk(name: &str, data: &str) -> Self {
+        Event::Chunk {
+            name: name.to_string(),
+            data: data.to_string(),
+        }
+    }
+
+    /// Encode the event as a line of output, without the trailing newline.
+    pub fn to_line(&self) -> String {
+        format!(
+            "{}{}",
+            EVENT_PREFIX,
+            serde_json::to_string(self).expect("events serialize")
+        )
+    }
+
+    /// Decode a line of output. Returns `None` for lines that are not well-formed events.
+    pub fn parse(line: &str) -> Option<Self> {
+        let json = line
+            .trim_end_matches(&['\r', '\n'][..])
+            .strip_prefix(EVENT_PREFIX)?;
+        serde_json::from_str(json).ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_event_lines() {
+        let line = Event::step("creating database", 2, 5).to_line();
+        assert_eq!(
+            line,
+            r#"::cnab {"type":"progress","message":"creating database","current":2,"total":5}"#
+        );
+        assert_eq!(
+            Event::parse(&format!("{}\n", line)),
+            Some(Event::step("creating database", 2, 5))
+        );
+        assert_eq!(
+            Event::parse(r#"::cnab {"type":"output","name":"port"}"#),
+            Some(Event::output("port"))
+        );
+        assert_eq!(
+            Event::chunk("log", "line 1\nline 2").to_line(),
+            r#"::cnab {"type":"chunk","name":"log","data":"line 1\nline 2"}"#
+        );
+        assert_eq!(Event::parse(r#"::cnab {"type":"unknown"}"#), None);
+        assert_eq!(Event::parse("plain log output"), None);
+    }
+}
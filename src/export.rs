@@ -0,0 +1,630 @@
+//! Export bundles as thick bundles, archives that carry their images with them.
+//!
+//! A thick bundle is the `bundle.cnab` archive other CNAB tools exchange: a gzipped
+//! tarball holding the `bundle.json`, and an OCI image layout at `artifacts/layout`
+//! with every invocation image and component image of the bundle. Each image is
+//! named in the layout's `index.json` by its reference in the bundle, so the bundle
+//! can be moved into an environment without access to the images' registries.
+//!
+//! ```no_run
+//! let bundle = libcnab::Bundle::from_file("bundle.json").unwrap();
+//! let file = std::fs::File::create("bundle.cnab").unwrap();
+//! libcnab::export::thick(&bundle, file).unwrap();
+//! ```
+use crate::cnab::Bundle;
+use crate::error_code::{Coded, ErrorCode};
+use crate::reference::BundleReference;
+use crate::registry::{
+    image_reference, in_parallel, parse, sha256_digest, Client, Descriptor, Index, Manifest, Phase,
+    RegistryError, Sbom, Tracker, Verified, DEFAULT_PARALLELISM, DOCKER_MANIFEST_LIST, OCI_INDEX,
+    OCI_MANIFEST, REF_NAME_ANNOTATION,
+};
+use crate::relocation::RelocationMap;
+use flate2::write::GzEncoder;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use ulid::Ulid;
+
+/// The path of the bundle's `bundle.json` in a thick bundle
+pub const BUNDLE_PATH: &str = "bundle.json";
+/// The path of the OCI image layout in a thick bundle
+pub const LAYOUT_PATH: &str = "artifacts/layout";
+/// The directory of the SBOMs in a thick bundle, each named `<sha256 hex>.json`
+pub const SBOM_PATH: &str = "artifacts/sboms";
+/// The most blobs that are fetched, or pushed, and checked against their digests at
+/// once when exporting or importing a thick bundle, unless the client's
+/// `Client::parallelism` says otherwise
+pub const MAX_WORKERS: usize = DEFAULT_PARALLELISM;
+
+/// Write `bundle` as a thick bundle to `writer`, fetching its images with a default
+/// registry `Client`.
+pub fn thick<W: Write>(bundle: &Bundle, writer: W) -> Result<W, ExportError> {
+    thick_with(&Client::new(), bundle, &RelocationMap::new(), writer)
+}
+
+/// Write `bundle` as a thick bundle to `writer`, fetching its images with `client`
+/// from where `relocation` says they are, such as the relocation map of a pulled
+/// bundle. Returns the writer once the archive is finished.
+///
+/// Every blob is checked against its digest before it is written. The blobs of each
+/// image are fetched and checked several at a time, to a temporary directory that
+/// is removed afterwards, as many at once as the client's `Client::parallelism`.
+pub fn thick_with<W: Write>(
+    client: &Client,
+    bundle: &Bundle,
+    relocation: &RelocationMap,
+    writer: W,
+) -> Result<W, ExportError> {
+    thick_with_sboms(client, bundle, relocation, &[], writer)
+}
+
+/// Write `bundle` as a thick bundle to `writer` the way `thick_with` does, with
+/// `sboms` alongside it under `artifacts/sboms`, so that the bundle's software can
+/// be scanned wherever the archive is taken.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(bundle = %bundle.name), err)
+)]
+pub fn thick_with_sboms<W: Write>(
+    client: &Client,
+    bundle: &Bundle,
+    relocation: &RelocationMap,
+    sboms: &[Sbom],
+    writer: W,
+) -> Result<W, ExportError> {
+    let staging = std::env::temp_dir().join(format!("libcnab-export-{}", Ulid::new()));
+    let result = export(client, bundle, relocation, sboms, &staging, false, writer);
+    let _ = std::fs::remove_dir_all(&staging);
+    result
+}
+
+/// Write `bundle` as a thick bundle to `writer` the way `thick_with_sboms` does,
+/// fetching its blobs to `staging` and keeping them there until the archive is
+/// written, so that an export that failed, such as when a connection dropped, can be
+/// run again from where it stopped.
+///
+/// The blobs fetched in full are not fetched again, and those fetched in part are
+/// fetched from where they stopped, with range requests, when their registries serve
+/// them. Each blob is checked against its digest again, from its first byte, before
+/// it is written; one that does not match is removed from `staging`, to be fetched
+/// again the next time. `staging` is removed once the archive is written.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(bundle = %bundle.name), err)
+)]
+pub fn thick_resumable<W: Write>(
+    client: &Client,
+    bundle: &Bundle,
+    relocation: &RelocationMap,
+    sboms: &[Sbom],
+    staging: &Path,
+    writer: W,
+) -> Result<W, ExportError> {
+    let writer = export(client, bundle, relocation, sboms, staging, true, writer)?;
+    let _ = std::fs::remove_dir_all(staging);
+    Ok(writer)
+}
+
+fn export<W: Write>(
+    client: &Client,
+    bundle: &Bundle,
+    relocation: &RelocationMap,
+    sboms: &[Sbom],
+    staging: &Path,
+    keep: bool,
+    writer: W,
+) -> Result<W, ExportError> {
+    let mut archive = Archive {
+        client,
+        tar: tar::Builder::new(GzEncoder::new(writer, flate2::Compression::default())),
+        written: BTreeSet::new(),
+        staging,
+        keep,
+        tracker: client.tracker(),
+    };
+    archive.contents(bundle, relocation, sboms)?;
+    Ok(archive.tar.into_inner()?.finish()?)
+}
+
+struct Archive<'a, W: Write> {
+    client: &'a Client,
+    tar: tar::Builder<GzEncoder<W>>,
+    /// The digests of the blobs in the layout so far
+    written: BTreeSet<String>,
+    /// Where blobs are fetched to, and checked, before they are written
+    staging: &'a Path,
+    /// Whether the staged blobs are kept once they are written, for an export that
+    /// is run again to find them
+    keep: bool,
+    tracker: Tracker<'a>,
+}
+
+impl<W: Write> Archive<'_, W> {
+    /// Add the bundle, its images and `sboms`.
+    fn contents(
+        &mut self,
+        bundle: &Bundle,
+        relocation: &RelocationMap,
+        sboms: &[Sbom],
+    ) -> Result<(), ExportError> {
+        let json = serde_json::to_vec_pretty(bundle).expect("bundles serialize");
+        self.file(BUNDLE_PATH, json.len() as u64, &json[..])?;
+        let layout = br#"{"imageLayoutVersion":"1.0.0"}"#;
+        self.file(
+            &format!("{}/oci-layout", LAYOUT_PATH),
+            layout.len() as u64,
+            &layout[..],
+        )?;
+
+        let images = bundle
+            .invocation_images
+            .iter()
+            .map(|i| (&i.image, &i.image_type, &i.content_digest))
+            .chain(
+                bundle
+                    .images
+                    .iter()
+                    .flat_map(|images| images.values())
+                    .map(|i| (&i.image, &i.image_type, &i.content_digest)),
+            );
+        let mut manifests = Vec::new();
+        self.tracker.phase(Phase::Transferring);
+        for (image, image_type, content_digest) in images {
+            let source = self.client.mirrored(image_reference(
+                relocation.relocate(image),
+                image_type.as_deref(),
+                content_digest.as_deref(),
+            )?);
+            let descriptor = self.manifest(&source, source.reference())?;
+            manifests.push(descriptor.annotate(REF_NAME_ANNOTATION, image));
+        }
+
+        let index = serde_json::to_vec(&Index {
+            schema_version: 2,
+            media_type: Some(OCI_INDEX.to_string()),
+            manifests,
+            annotations: None,
+        })
+        .expect("indexes serialize");
+        self.file(
+            &format!("{}/index.json", LAYOUT_PATH),
+            index.len() as u64,
+            &index[..],
+        )?;
+        for sbom in sboms {
+            let content = sbom.to_vec();
+            let path = sbom_path(&sha256_digest(&content)).expect("sha256 digests are valid");
+            self.file(&path, content.len() as u64, &content[..])?;
+        }
+        Ok(())
+    }
+
+    fn file<R: Read>(&mut self, path: &str, size: u64, content: R) -> Result<(), ExportError> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(size);
+        header.set_mode(0o644);
+        self.tar.append_data(&mut header, path, content)?;
+        Ok(())
+    }
+
+    fn blob<R: Read>(&mut self, digest: &str, size: u64, content: R) -> Result<(), ExportError> {
+        let path =
+            blob_path(digest).ok_or_else(|| ExportError::InvalidDigest(digest.to_string()))?;
+        self.file(&path, size, Verified::new(content, digest, size))?;
+        self.written.insert(digest.to_string());
+        Ok(())
+    }
+
+    /// Fetch `blobs` of `source` to the staging directory, checking each against its
+    /// digest, several at a time.
+    fn stage(&self, source: &BundleReference, blobs: &[&Descriptor]) -> Result<(), ExportError> {
+        let (client, staging, tracker) = (self.client, self.staging, &self.tracker);
+        tracker.layers(blobs.len());
+        in_parallel(client.parallel_transfers(), blobs, |blob| {
+            let path = blob_path(&blob.digest)
+                .map(|path| staging.join(path))
+                .ok_or_else(|| ExportError::InvalidDigest(blob.digest.clone()))?;
+            match client.layers() {
+                Some(store) => {
+                    store.fetch(client, source, blob, tracker)?;
+                }
+                None => {
+                    std::fs::create_dir_all(path.parent().expect("blob paths have parents"))?;
+                    fetch(client, source, blob, &path, tracker)?;
+                }
+            }
+            tracker.layer_done();
+            Ok(())
+        })
+    }
+
+    /// Add the manifest `reference` of `source`, and everything it refers to. Returns
+    /// the manifest's descriptor.
+    fn manifest(
+        &mut self,
+        source: &BundleReference,
+        reference: &str,
+    ) -> Result<Descriptor, ExportError> {
+        let manifest = self.client.manifest(source, reference)?;
+        match manifest.media_type.as_str() {
+            OCI_INDEX | DOCKER_MANIFEST_LIST => {
+                let index: Index = parse(source, &manifest.body)?;
+                for child in &index.manifests {
+                    self.manifest(source, &child.digest)?;
+                }
+            }
+            _ => {
+                let image: Manifest = parse(source, &manifest.body)?;
+                let mut blobs: Vec<&Descriptor> = Vec::new();
+                for blob in Some(&image.config).into_iter().chain(&image.layers) {
+                    if !self.written.contains(&blob.digest)
+                        && !blobs.iter().any(|b| b.digest == blob.digest)
+                    {
+                        blobs.push(blob);
+                    }
+                }
+                self.stage(source, &blobs)?;
+                for blob in blobs {
+                    let path = blob_path(&blob.digest).expect("staged blobs have digests");
+                    let stored = self.client.layers().map(|store| store.path(&blob.digest));
+                    let from_store = stored.is_some();
+                    let staged = match stored {
+                        Some(stored) => stored.ok_or_else(|| {
+                            std::io::Error::new(
+                                std::io::ErrorKind::NotFound,
+                                format!("{} is not in the layer store", blob.digest),
+                            )
+                        })?,
+                        None => self.staging.join(&path),
+                    };
+                    self.file(&path, blob.size, File::open(&staged)?)?;
+                    if !from_store && !self.keep {
+                        std::fs::remove_file(&staged)?;
+                    }
+                    self.written.insert(blob.digest.clone());
+                }
+            }
+        }
+        let size = manifest.body.len() as u64;
+        if !self.written.contains(&manifest.digest) {
+            self.blob(&manifest.digest, size, &manifest.body[..])?;
+        }
+        Ok(Descriptor {
+            media_type: if manifest.media_type.is_empty() {
+                OCI_MANIFEST.to_string()
+            } else {
+                manifest.media_type
+            },
+            digest: manifest.digest,
+            size,
+            platform: None,
+            annotations: None,
+            artifact_type: None,
+        })
+    }
+}
+
+/// Fetch `blob` of `source` to `path`, checked against its digest, from where an
+/// earlier fetch to `path` stopped. A blob that does not match is removed.
+fn fetch(
+    client: &Client,
+    source: &BundleReference,
+    blob: &Descriptor,
+    path: &Path,
+    tracker: &Tracker<'_>,
+) -> Result<(), ExportError> {
+    let checked = |result: std::io::Result<u64>| match result {
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+            let _ = std::fs::remove_file(path);
+            Err(ExportError::IoError(e))
+        }
+        result => result.map(|_| ()).map_err(ExportError::from),
+    };
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    let staged = file.metadata()?.len();
+    if staged == 0 || staged < blob.size {
+        let (content, start) = client.blob_reader_at(source, &blob.digest, staged)?;
+        file.set_len(start)?;
+        file.seek(SeekFrom::Start(start))?;
+        let content = tracker.reader_at(&blob.digest, blob.size, start, content);
+        if start == 0 {
+            let mut content = Verified::new(content, &blob.digest, blob.size);
+            return checked(std::io::copy(&mut content, &mut file));
+        }
+        // One byte more than the rest of the blob is enough to tell it is longer.
+        std::io::copy(&mut content.take(blob.size - start + 1), &mut file)?;
+    }
+    // What was fetched before is checked along with the rest.
+    file.seek(SeekFrom::Start(0))?;
+    let mut content = Verified::new(file, &blob.digest, blob.size);
+    checked(std::io::copy(&mut content, &mut std::io::sink()))
+}
+
+/// The path of the blob `digest` in a thick bundle: `blobs/<algorithm>/<hex>` under
+/// the layout. There is none for something that is not a digest.
+pub(crate) fn blob_path(digest: &str) -> Option<String> {
+    let valid = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric());
+    match digest.split_once(':') {
+        Some((algorithm, hex)) if valid(algorithm) && valid(hex) => {
+            Some(format!("{}/blobs/{}/{}", LAYOUT_PATH, algorithm, hex))
+        }
+        _ => None,
+    }
+}
+
+/// The digest of the blob at `path` in a thick bundle, the reverse of `blob_path`.
+pub(crate) fn blob_digest(path: &str) -> Option<String> {
+    let (algorithm, hex) = path
+        .strip_prefix(LAYOUT_PATH)?
+        .strip_prefix("/blobs/")?
+        .split_once('/')?;
+    let digest = format!("{}:{}", algorithm, hex);
+    blob_path(&digest).filter(|p| p == path).map(|_| digest)
+}
+
+/// The path of the SBOM with the digest `digest` in a thick bundle.
+pub(crate) fn sbom_path(digest: &str) -> Option<String> {
+    match digest.split_once(':') {
+        Some(("sha256", hex)) if !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Some(format!("{}/{}.json", SBOM_PATH, hex))
+        }
+        _ => None,
+    }
+}
+
+/// ExportError describes a failure to export a thick bundle.
+#[derive(Debug)]
+pub enum ExportError {
+    /// An image could not be fetched from its registry
+    RegistryError(RegistryError),
+    /// A manifest refers to a blob by something other than a digest
+    InvalidDigest(String),
+    /// The archive could not be written, or a blob did not match its digest
+    IoError(std::io::Error),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::RegistryError(e) => write!(f, "{}", e),
+            ExportError::InvalidDigest(d) => write!(f, "invalid digest {}", d),
+            ExportError::IoError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl Coded for ExportError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ExportError::RegistryError(e) => e.code(),
+            ExportError::InvalidDigest(_) => ErrorCode::ExportInvalidDigest,
+            ExportError::IoError(_) => ErrorCode::Io,
+        }
+    }
+}
+
+impl From<RegistryError> for ExportError {
+    fn from(error: RegistryError) -> Self {
+        ExportError::RegistryError(error)
+    }
+}
+
+impl From<std::io::Error> for ExportError {
+    fn from(error: std::io::Error) -> Self {
+        ExportError::IoError(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::registry::testing::FakeRegistry;
+    use flate2::read::GzDecoder;
+    use std::collections::BTreeMap;
+
+    fn descriptor(media_type: &str, digest: &str, size: usize) -> Descriptor {
+        Descriptor {
+            media_type: media_type.to_string(),
+            digest: digest.to_string(),
+            size: size as u64,
+            platform: None,
+            annotations: None,
+            artifact_type: None,
+        }
+    }
+
+    #[test]
+    fn test_thick() {
+        let registry = FakeRegistry::start();
+        let config = registry.put_blob("images/hello", b"{}");
+        let layer = registry.put_blob("images/hello", b"layer");
+        let image = serde_json::to_vec(&Manifest {
+            schema_version: 2,
+            media_type: Some(OCI_MANIFEST.to_string()),
+            config: descriptor("application/vnd.oci.image.config.v1+json", &config, 2),
+            layers: vec![descriptor(
+                "application/vnd.oci.image.layer.v1.tar",
+                &layer,
+                5,
+            )],
+            annotations: None,
+            artifact_type: None,
+            subject: None,
+        })
+        .unwrap();
+        let image_digest =
+            registry.put_manifest("images/hello", Some("0.1.0"), OCI_MANIFEST, &image);
+
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let tagged = format!("{}/images/hello:0.1.0", registry.host);
+        let pinned = format!("{}/images/hello@{}", registry.host, image_digest);
+        bundle.invocation_images[0].image = tagged.clone();
+        let component = bundle.images.as_mut().unwrap().get_mut("my-microservice");
+        component.unwrap().image = pinned.clone();
+        let archive = thick(&bundle, Vec::new()).expect("bundle exported");
+
+        let mut files = BTreeMap::new();
+        let mut entries = tar::Archive::new(GzDecoder::new(&archive[..]));
+        for entry in entries.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).unwrap();
+            files.insert(path, content);
+        }
+        let exported = Bundle::from_json(&files["bundle.json"][..]).unwrap();
+        assert_eq!(exported.invocation_images[0].image, tagged);
+        let index: Index = serde_json::from_slice(&files["artifacts/layout/index.json"]).unwrap();
+        let names: Vec<_> = index
+            .manifests
+            .iter()
+            .map(|m| {
+                (
+                    m.annotation(REF_NAME_ANNOTATION).unwrap(),
+                    m.digest.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (tagged.as_str(), image_digest.as_str()),
+                (pinned.as_str(), image_digest.as_str())
+            ]
+        );
+        let blob = |digest: &str| &files[&blob_path(digest).unwrap()];
+        assert_eq!(blob(&image_digest), &image);
+        assert_eq!(blob(&layer), b"layer");
+        assert_eq!(blob(&config), b"{}");
+        assert_eq!(files.len(), 6);
+
+        // SBOMs go alongside the layout.
+        let sbom = Sbom::parse(br#"{"bomFormat":"CycloneDX","components":[]}"#).unwrap();
+        let archive = thick_with_sboms(
+            &Client::new(),
+            &bundle,
+            &RelocationMap::new(),
+            std::slice::from_ref(&sbom),
+            Vec::new(),
+        )
+        .unwrap();
+        let mut entries = tar::Archive::new(GzDecoder::new(&archive[..]));
+        let path = sbom_path(&sha256_digest(&sbom.to_vec())).unwrap();
+        let entry = entries
+            .entries()
+            .unwrap()
+            .map(Result::unwrap)
+            .find(|e| e.path().unwrap().to_string_lossy() == path);
+        let mut content = Vec::new();
+        entry
+            .expect("SBOM exported")
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(Sbom::parse(&content).unwrap(), sbom);
+
+        // A blob that does not match its digest is not exported.
+        registry
+            .state
+            .lock()
+            .unwrap()
+            .blobs
+            .insert(("images/hello".to_string(), layer), b"other".to_vec());
+        match thick(&bundle, Vec::new()) {
+            Err(ExportError::IoError(e)) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidData),
+            other => panic!("expected a digest mismatch, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_thick_resumable() {
+        let registry = FakeRegistry::start();
+        let config = registry.put_blob("images/hello", b"{}");
+        let content: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+        let layer = registry.put_blob("images/hello", &content);
+        let image = serde_json::to_vec(&Manifest {
+            schema_version: 2,
+            media_type: Some(OCI_MANIFEST.to_string()),
+            config: descriptor("application/vnd.oci.image.config.v1+json", &config, 2),
+            layers: vec![descriptor(
+                "application/vnd.oci.image.layer.v1.tar",
+                &layer,
+                content.len(),
+            )],
+            annotations: None,
+            artifact_type: None,
+            subject: None,
+        })
+        .unwrap();
+        registry.put_manifest("images/hello", Some("0.1.0"), OCI_MANIFEST, &image);
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.invocation_images[0].image = format!("{}/images/hello:0.1.0", registry.host);
+        bundle.images = None;
+        let expected = thick_with(&Client::new(), &bundle, &RelocationMap::new(), Vec::new())
+            .expect("bundle exported");
+
+        let dir = tempfile::tempdir().unwrap();
+        let staging = dir.path().join("staging");
+        let staged = staging.join(blob_path(&layer).unwrap());
+        let export = || {
+            thick_resumable(
+                &Client::new(),
+                &bundle,
+                &RelocationMap::new(),
+                &[],
+                &staging,
+                Vec::new(),
+            )
+        };
+        // A download that stopped half way is resumed from there.
+        std::fs::create_dir_all(staged.parent().unwrap()).unwrap();
+        std::fs::write(&staged, &content[..content.len() / 2]).unwrap();
+        let archive = export().expect("bundle exported");
+        assert_eq!(archive, expected);
+        let half = format!("bytes={}-", content.len() / 2);
+        assert_eq!(registry.state.lock().unwrap().ranges, vec![half]);
+        assert!(!staging.exists());
+
+        // What was fetched before is checked again, and fetched anew if it is wrong.
+        std::fs::create_dir_all(staged.parent().unwrap()).unwrap();
+        std::fs::write(&staged, b"other").unwrap();
+        match export() {
+            Err(ExportError::IoError(e)) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidData),
+            other => panic!("expected a digest mismatch, got {:?}", other.map(|_| ())),
+        }
+        assert!(!staged.exists());
+        assert_eq!(export().expect("bundle exported"), expected);
+
+        // From the start, when the registry does not serve ranges.
+        registry.state.lock().unwrap().ignore_ranges = true;
+        std::fs::create_dir_all(staged.parent().unwrap()).unwrap();
+        std::fs::write(&staged, &content[..10]).unwrap();
+        assert_eq!(export().expect("bundle exported"), expected);
+        assert!(!staging.exists());
+
+        // Blobs in a layer store are fetched once, whatever exports them.
+        let store = crate::registry::LayerStore::new(dir.path().join("layers"));
+        let client = Client::new().layer_store(store.clone());
+        let exported = thick_with(&client, &bundle, &RelocationMap::new(), Vec::new()).unwrap();
+        assert_eq!(exported, expected);
+        assert!(store.contains(&layer));
+        registry.state.lock().unwrap().requests.clear();
+        let exported = thick_with(&client, &bundle, &RelocationMap::new(), Vec::new()).unwrap();
+        assert_eq!(exported, expected);
+        let requests = registry.state.lock().unwrap().requests.clone();
+        assert!(
+            !requests.iter().any(|r| r.contains("/blobs/")),
+            "{:?}",
+            requests
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use crate::cnab::Bundle;
+use crate::custom::{deserialize, CustomExtensionError};
+use crate::dependencies::{Dependencies, DEPENDENCIES_KEY};
+use crate::docker_extension::{DockerExtension, DOCKER_EXTENSION_KEY};
+use crate::error_code::{Coded, ErrorCode};
+use crate::parameter_sources::{ParameterSources, PARAMETER_SOURCES_KEY};
+use crate::profile::{ValidationProfile, ValidationRule};
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A validator checks the `custom` payload of an extension, and says what is wrong
+/// with it.
+pub type ExtensionValidator = Box<dyn Fn(&serde_json::Value) -> Result<(), String> + Send + Sync>;
+
+/// ExtensionRegistry is the extensions a host knows, each with the validators of its
+/// payload in `custom`.
+///
+/// ```
+/// use libcnab::{Bundle, ExtensionRegistry};
+///
+/// let registry = ExtensionRegistry::default().validator("com.example.backup-preferences", |v| {
+///     match v.get("frequency").and_then(|f| f.as_str()) {
+///         Some("daily") | Some("weekly") => Ok(()),
+///         _ => Err("frequency must be daily or weekly".to_string()),
+///     }
+/// });
+/// let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+/// bundle.validate(&registry).unwrap();
+/// ```
+pub struct ExtensionRegistry {
+    extensions: BTreeMap<String, Vec<ExtensionValidator>>,
+}
+
+impl ExtensionRegistry {
+    /// Create a registry that knows no extensions.
+    pub fn empty() -> Self {
+        ExtensionRegistry {
+            extensions: BTreeMap::new(),
+        }
+    }
+
+    /// Know the extension `key`, without checking its payload.
+    pub fn register(mut self, key: &str) -> Self {
+        self.extensions.entry(key.to_string()).or_default();
+        self
+    }
+
+    /// Know the extension `key`, and check its payload with `validator`. An extension
+    /// may have several validators, which are run in the order they were added.
+    pub fn validator<F>(mut self, key: &str, validator: F) -> Self
+    where
+        F: Fn(&serde_json::Value) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.extensions
+            .entry(key.to_string())
+            .or_default()
+            .push(Box::new(validator));
+        self
+    }
+
+    /// Know the extension `key`, and check that its payload deserializes into `T`.
+    pub fn typed<T: DeserializeOwned + 'static>(self, key: &str) -> Self {
+        let name = key.to_string();
+        self.validator(key, move |value| {
+            deserialize::<T>(&name, value)
+                .map(|_| ())
+                .map_err(|e| match e {
+                    CustomExtensionError::Invalid {
+                        path: Some(path),
+                        error,
+                        ..
+                    } => format!("at {}: {}", path, error),
+                    CustomExtensionError::Invalid { error, .. } => error.to_string(),
+                    e => e.to_string(),
+                })
+        })
+    }
+
+    pub fn is_known(&self, key: &str) -> bool {
+        self.extensions.contains_key(key)
+    }
+
+    /// The keys of the known extensions, in sorted order.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.extensions.keys().map(String::as_str)
+    }
+}
+
+impl Default for ExtensionRegistry {
+    /// A registry that knows the extensions this crate implements, and checks their
+    /// payloads against its types for them.
+    fn default() -> Self {
+        ExtensionRegistry::empty()
+            .typed::<Dependencies>(DEPENDENCIES_KEY)
+            .typed::<DockerExtension>(DOCKER_EXTENSION_KEY)
+            .typed::<ParameterSources>(PARAMETER_SOURCES_KEY)
+    }
+}
+
+impl fmt::Debug for ExtensionRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtensionRegistry")
+            .field("extensions", &self.extensions.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Bundle {
+    /// Check the bundle's extensions against `registry`: every extension in
+    /// `requiredExtensions` must be known, and the payload in `custom` of every known
+    /// extension must pass its validators. Keys of `custom` the registry does not know
+    /// are left alone, as the specification allows.
+    ///
+    /// Every parameter and credential must also be placed somewhere, in an environment
+    /// variable, a file, or both.
+    ///
+    /// Every problem found is reported, not only the first. Those with the bundle's
+    /// extensions are `ValidationProblem::Extension`s, and those with the core
+    /// specification are the other `ValidationProblem`s.
+    pub fn validate(&self, registry: &ExtensionRegistry) -> Result<(), ValidationError> {
+        self.validate_with(registry, &ValidationProfile::Strict1_0)
+    }
+
+    /// Check the bundle as `validate` does, enforcing only the rules of `profile`.
+    pub fn validate_with(
+        &self,
+        registry: &ExtensionRegistry,
+        profile: &ValidationProfile,
+    ) -> Result<(), ValidationError> {
+        let mut problems = Vec::new();
+        if profile.enforces(ValidationRule::Destinations) {
+            for (name, parameter) in self.parameters.iter().flatten() {
+                let destination = &parameter.destination;
+                if destination.env.is_none() && destination.path.is_none() {
+                    problems.push(ValidationProblem::NoParameterDestination(name.clone()));
+                }
+            }
+            for (name, credential) in self.credentials.iter().flatten() {
+                if credential.env.is_none() && credential.path.is_none() {
+                    problems.push(ValidationProblem::NoCredentialDestination(name.clone()));
+                }
+            }
+        }
+        if profile.enforces(ValidationRule::RequiredExtensions) {
+            for key in self.required_extensions.iter().flatten() {
+                if !registry.is_known(key) {
+                    problems.push(ExtensionProblem::UnknownRequired(key.clone()).into());
+                }
+            }
+        }
+        if profile.enforces(ValidationRule::ExtensionPayloads) {
+            for (key, value) in self.custom.iter().flatten() {
+                for validator in registry.extensions.get(key).into_iter().flatten() {
+                    if let Err(message) = validator(value) {
+                        problems.push(
+                            ExtensionProblem::Invalid {
+                                key: key.clone(),
+                                message,
+                            }
+                            .into(),
+                        );
+                    }
+                }
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError { problems })
+        }
+    }
+}
+
+/// ValidationProblem is something `Bundle::validate` finds wrong with a bundle:
+/// where it places a parameter or credential, which the core specification
+/// requires, or one of its extensions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationProblem {
+    /// A parameter has neither `env` nor `path` in its destination
+    NoParameterDestination(String),
+    /// A credential has neither `env` nor `path`
+    NoCredentialDestination(String),
+    Extension(ExtensionProblem),
+}
+
+impl fmt::Display for ValidationProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationProblem::NoParameterDestination(name) => {
+                write!(
+                    f,
+                    "parameter {} has no destination: set env, path, or both",
+                    name
+                )
+            }
+            ValidationProblem::NoCredentialDestination(name) => {
+                write!(
+                    f,
+                    "credential {} has no destination: set env, path, or both",
+                    name
+                )
+            }
+            ValidationProblem::Extension(problem) => problem.fmt(f),
+        }
+    }
+}
+
+impl From<ExtensionProblem> for ValidationProblem {
+    fn from(problem: ExtensionProblem) -> Self {
+        ValidationProblem::Extension(problem)
+    }
+}
+
+/// ExtensionProblem is something wrong with one of a bundle's extensions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExtensionProblem {
+    /// The bundle requires an extension the registry does not know
+    UnknownRequired(String),
+    /// A validator refused the payload of an extension
+    Invalid { key: String, message: String },
+}
+
+impl fmt::Display for ExtensionProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtensionProblem::UnknownRequired(key) => {
+                write!(f, "the bundle requires unknown extension {}", key)
+            }
+            ExtensionProblem::Invalid { key, message } => {
+                write!(f, "extension {} is invalid: {}", key, message)
+            }
+        }
+    }
+}
+
+/// ValidationError lists what `Bundle::validate` found wrong with a bundle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub problems: Vec<ValidationProblem>,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let problems: Vec<String> = self.problems.iter().map(|p| p.to_string()).collect();
+        write!(f, "{}", problems.join("; "))
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl Coded for ValidationError {
+    fn code(&self) -> ErrorCode {
+        ErrorCode::BundleInvalid
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.validate(&ExtensionRegistry::empty()).unwrap();
+
+        bundle.required_extensions = Some(vec![
+            DOCKER_EXTENSION_KEY.to_string(),
+            "com.example.backup-preferences".to_string(),
+        ]);
+        let custom = bundle.custom.as_mut().unwrap();
+        custom.insert(
+            DOCKER_EXTENSION_KEY.to_string(),
+            serde_json::json!({ "privileged": "yes" }),
+        );
+        let err = bundle.validate(&ExtensionRegistry::default()).unwrap_err();
+        assert_eq!(err.problems.len(), 2);
+        assert_eq!(
+            err.problems[0],
+            ExtensionProblem::UnknownRequired("com.example.backup-preferences".to_string()).into()
+        );
+        match &err.problems[1] {
+            ValidationProblem::Extension(ExtensionProblem::Invalid { key, message }) => {
+                assert_eq!(key, DOCKER_EXTENSION_KEY);
+                assert!(message.starts_with("at privileged: "), "{}", message);
+            }
+            other => panic!("expected an invalid payload, got {:?}", other),
+        }
+
+        bundle.custom.as_mut().unwrap().insert(
+            DOCKER_EXTENSION_KEY.to_string(),
+            serde_json::json!({ "privileged": true }),
+        );
+        let registry = ExtensionRegistry::default()
+            .register("com.example.backup-preferences")
+            .validator("com.example.duffle-bag", |value| {
+                match value.get("iconType") {
+                    Some(t) if t == "SVG" => Ok(()),
+                    _ => Err("icons must be SVG".to_string()),
+                }
+            });
+        assert!(registry.is_known("com.example.backup-preferences"));
+        let err = bundle.validate(&registry).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "extension com.example.duffle-bag is invalid: icons must be SVG"
+        );
+    }
+
+    #[test]
+    fn test_validate_destinations() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let registry = ExtensionRegistry::empty();
+        let hostkey = bundle
+            .credentials
+            .as_mut()
+            .unwrap()
+            .get_mut("hostkey")
+            .unwrap();
+        hostkey.env = None;
+        bundle.validate(&registry).unwrap();
+
+        let hostkey = bundle
+            .credentials
+            .as_mut()
+            .unwrap()
+            .get_mut("hostkey")
+            .unwrap();
+        hostkey.path = None;
+        let parameters = bundle.parameters.as_mut().unwrap();
+        parameters.get_mut("backend_port").unwrap().destination.env = None;
+        let err = bundle.validate(&registry).unwrap_err();
+        assert_eq!(
+            err.problems,
+            vec![
+                ValidationProblem::NoParameterDestination("backend_port".to_string()),
+                ValidationProblem::NoCredentialDestination("hostkey".to_string()),
+            ]
+        );
+
+        let json = r#"{"name": "b", "version": "0.1.0", "schemaVersion": "v1.0.0",
+            "invocationImages": [], "credentials": {"c": {"env": "HOST-KEY"}}}"#;
+        let err = Bundle::from_json(json.as_bytes()).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("invalid environment variable name"),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use crate::cnab::{Bundle, BundleParseError};
+use crate::error_code::{Coded, ErrorCode};
+use crate::proxy::ProxyConfig;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::time::Duration;
+
+/// The largest `bundle.json` fetched by default, as for bundles pulled from registries
+pub const DEFAULT_FETCH_LIMIT: u64 = 64 * 1024 * 1024;
+
+/// BundleFetcher downloads bundle descriptors over HTTP(S), such as those published
+/// on static hosting or release pages.
+///
+/// By default a fetch gives up after 30 seconds, follows up to 5 redirects, reads at
+/// most `DEFAULT_FETCH_LIMIT` bytes, and goes through the proxies of
+/// `ProxyConfig::from_env`.
+///
+/// ```no_run
+/// use libcnab::BundleFetcher;
+/// use std::time::Duration;
+///
+/// let bundle = BundleFetcher::new()
+///     .timeout(Duration::from_secs(10))
+///     .max_redirects(0)
+///     .sha256("7c2d1fb9c7b1e6a5a4e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a7f6e5d4c3b2a1")
+///     .fetch("https://example.com/releases/v1.0.0/bundle.json")
+///     .unwrap();
+/// println!("{} {}", bundle.name, bundle.version);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BundleFetcher {
+    timeout: Duration,
+    max_size: u64,
+    max_redirects: u32,
+    https_only: bool,
+    sha256: Option<String>,
+    proxy: ProxyConfig,
+}
+
+impl Default for BundleFetcher {
+    fn default() -> Self {
+        BundleFetcher {
+            timeout: Duration::from_secs(30),
+            max_size: DEFAULT_FETCH_LIMIT,
+            max_redirects: 5,
+            https_only: false,
+            sha256: None,
+            proxy: ProxyConfig::from_env(),
+        }
+    }
+}
+
+impl BundleFetcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give up on a fetch, redirects and all, after `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Refuse descriptors larger than `bytes`.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// Follow at most `redirects` redirects; a response redirecting further is an
+    /// error. Zero refuses every redirect.
+    pub fn max_redirects(mut self, redirects: u32) -> Self {
+        self.max_redirects = redirects;
+        self
+    }
+
+    /// Refuse `http://` URLs, including those redirected to.
+    pub fn https_only(mut self, https_only: bool) -> Self {
+        self.https_only = https_only;
+        self
+    }
+
+    /// Pin the descriptor to the sha256 digest of its bytes, as hex with or without
+    /// a `sha256:` prefix. A descriptor with another digest is refused.
+    pub fn sha256(mut self, digest: &str) -> Self {
+        let hex = digest.strip_prefix("sha256:").unwrap_or(digest);
+        self.sha256 = Some(hex.to_ascii_lowercase());
+        self
+    }
+
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Download and parse the bundle descriptor at `url`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self), err))]
+    pub fn fetch(&self, url: &str) -> Result<Bundle, FetchError> {
+        if let Some(hex) = &self.sha256 {
+            if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(FetchError::InvalidChecksum(hex.clone()));
+            }
+        }
+        let agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .timeout_global(Some(self.timeout))
+            .max_redirects(self.max_redirects)
+            .max_redirects_will_error(true)
+            .https_only(self.https_only)
+            .proxy(self.proxy.proxy(url)?)
+            .build()
+            .new_agent();
+        let mut response = agent.get(url).call()?;
+        let status = response.status().as_u16();
+        if !(200..300).contains(&status) {
+            return Err(FetchError::Status {
+                url: url.to_string(),
+                status,
+            });
+        }
+        let body = response
+            .body_mut()
+            .with_config()
+            .limit(self.max_size)
+            .read_to_vec()
+            .map_err(|e| match e {
+                ureq::Error::BodyExceedsLimit(limit) => FetchError::TooLarge {
+                    url: url.to_string(),
+                    limit,
+                },
+                e => FetchError::Http(e),
+            })?;
+        if let Some(expected) = &self.sha256 {
+            let actual = hex::encode(Sha256::digest(&body));
+            if *expected != actual {
+                return Err(FetchError::ChecksumMismatch {
+                    url: url.to_string(),
+                    expected: format!("sha256:{}", expected),
+                    actual: format!("sha256:{}", actual),
+                });
+            }
+        }
+        Ok(Bundle::from_json(&body[..])?)
+    }
+
+    /// Download and parse the bundle descriptor at `url` on tokio's blocking threads.
+    #[cfg(feature = "tokio")]
+    pub async fn fetch_async(&self, url: &str) -> Result<Bundle, FetchError> {
+        let (fetcher, url) = (self.clone(), url.to_string());
+        crate::asynchronous::blocking(move || fetcher.fetch(&url)).await
+    }
+}
+
+impl Bundle {
+    /// Download and parse the bundle descriptor at `url`, with the defaults of
+    /// `BundleFetcher`.
+    pub fn from_url(url: &str) -> Result<Self, FetchError> {
+        BundleFetcher::new().fetch(url)
+    }
+
+    /// Download and parse the bundle descriptor at `url` without blocking the
+    /// runtime, with the defaults of `BundleFetcher`.
+    #[cfg(feature = "tokio")]
+    pub async fn from_url_async(url: &str) -> Result<Self, FetchError> {
+        BundleFetcher::new().fetch_async(url).await
+    }
+}
+
+/// Represents an error fetching a bundle descriptor over HTTP(S)
+#[derive(Debug)]
+pub enum FetchError {
+    /// The request failed, timed out, or was redirected too many times
+    Http(ureq::Error),
+    /// The server answered with a status other than success
+    Status {
+        url: String,
+        status: u16,
+    },
+    /// The descriptor is larger than the fetcher's limit
+    TooLarge {
+        url: String,
+        limit: u64,
+    },
+    /// The pinned checksum is not a sha256 digest
+    InvalidChecksum(String),
+    /// The descriptor is not the one the checksum pins
+    ChecksumMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+    Parse(BundleParseError),
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Http(e) => write!(f, "fetching bundle: {}", e),
+            FetchError::Status { url, status } => write!(f, "{} answered {}", url, status),
+            FetchError::TooLarge { url, limit } => {
+                write!(f, "the bundle at {} is larger than {} bytes", url, limit)
+            }
+            FetchError::InvalidChecksum(c) => write!(f, "invalid sha256 checksum {}", c),
+            FetchError::ChecksumMismatch {
+                url,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "the bundle at {} has digest {}, expected {}",
+                url, actual, expected
+            ),
+            FetchError::Parse(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+impl Coded for FetchError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            FetchError::Http(_) => ErrorCode::FetchHttp,
+            FetchError::Status { .. } => ErrorCode::FetchStatus,
+            FetchError::TooLarge { .. } => ErrorCode::FetchTooLarge,
+            FetchError::InvalidChecksum(_) => ErrorCode::FetchInvalidChecksum,
+            FetchError::ChecksumMismatch { .. } => ErrorCode::FetchChecksumMismatch,
+            FetchError::Parse(e) => e.code(),
+        }
+    }
+}
+
+impl From<ureq::Error> for FetchError {
+    fn from(error: ureq::Error) -> Self {
+        FetchError::Http(error)
+    }
+}
+
+impl From<BundleParseError> for FetchError {
+    fn from(error: BundleParseError) -> Self {
+        FetchError::Parse(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// Serve `testdata/bundle.json` at `/bundle.json`, redirects to it from
+    /// `/redirect/<n>`, and a slow response at `/slow`.
+    fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                std::thread::spawn(move || {
+                    let mut line = String::new();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    reader.read_line(&mut line).unwrap();
+                    let path = line.split(' ').nth(1).unwrap_or_default().to_string();
+                    loop {
+                        let mut header = String::new();
+                        if reader.read_line(&mut header).unwrap_or(0) <= 2 {
+                            break;
+                        }
+                    }
+                    let response = if path == "/bundle.json" {
+                        let body = std::fs::read("testdata/bundle.json").unwrap();
+                        let mut head =
+                            format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len())
+                                .into_bytes();
+                        head.extend(body);
+                        head
+                    } else if let Some(n) = path.strip_prefix("/redirect/") {
+                        let n: u32 = n.parse().unwrap();
+                        let location = if n <= 1 {
+                            "/bundle.json".to_string()
+                        } else {
+                            format!("/redirect/{}", n - 1)
+                        };
+                        format!(
+                            "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\n\r\n",
+                            location
+                        )
+                        .into_bytes()
+                    } else if path == "/slow" {
+                        std::thread::sleep(Duration::from_secs(2));
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}".to_vec()
+                    } else {
+                        b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec()
+                    };
+                    let _ = stream.write_all(&response);
+                });
+            }
+        });
+        format!("http://{}", host)
+    }
+
+    #[test]
+    fn test_fetch() {
+        let base = serve();
+        let fetcher = BundleFetcher::new().proxy(ProxyConfig::none());
+        let url = format!("{}/bundle.json", base);
+        assert_eq!(fetcher.fetch(&url).unwrap().name, "helloworld");
+
+        let digest = hex::encode(Sha256::digest(
+            std::fs::read("testdata/bundle.json").unwrap(),
+        ));
+        let pinned = fetcher.clone().sha256(&format!("sha256:{}", digest));
+        pinned.fetch(&url).unwrap();
+        match fetcher.clone().sha256(&"0".repeat(64)).fetch(&url) {
+            Err(FetchError::ChecksumMismatch { actual, .. }) => {
+                assert_eq!(actual, format!("sha256:{}", digest))
+            }
+            other => panic!("expected a checksum mismatch, got {:?}", other),
+        }
+        assert!(matches!(
+            fetcher.clone().sha256("sha256:abc").fetch(&url),
+            Err(FetchError::InvalidChecksum(_))
+        ));
+
+        assert!(matches!(
+            fetcher.clone().max_size(100).fetch(&url),
+            Err(FetchError::TooLarge { limit: 100, .. })
+        ));
+        assert!(matches!(
+            fetcher.fetch(&format!("{}/missing.json", base)),
+            Err(FetchError::Status { status: 404, .. })
+        ));
+
+        let redirected = format!("{}/redirect/2", base);
+        assert_eq!(fetcher.fetch(&redirected).unwrap().name, "helloworld");
+        assert!(matches!(
+            fetcher.clone().max_redirects(1).fetch(&redirected),
+            Err(FetchError::Http(ureq::Error::TooManyRedirects))
+        ));
+        assert!(matches!(
+            fetcher.clone().https_only(true).fetch(&url),
+            Err(FetchError::Http(ureq::Error::RequireHttpsOnly(_)))
+        ));
+        assert!(matches!(
+            fetcher
+                .clone()
+                .timeout(Duration::from_millis(200))
+                .fetch(&format!("{}/slow", base)),
+            Err(FetchError::Http(ureq::Error::Timeout(_)))
+        ));
+    }
+}
@@ -0,0 +1,155 @@
+//! A C interface for parsing and validating bundles, for runtimes written in other
+//! languages to embed this crate.
+//!
+//! Bundles are opaque handles, and everything else crosses the interface as
+//! NUL-terminated UTF-8 strings, JSON where there is structure. Strings returned by
+//! these functions are owned by the caller, and freed with `cnab_string_free`;
+//! handles are freed with `cnab_bundle_free`. The declarations are in
+//! `include/libcnab.h`. Build the library with
+//! `cargo rustc --release --features ffi --crate-type cdylib` (or `staticlib`).
+use crate::cnab::Bundle;
+use crate::extensions::ExtensionRegistry;
+use sha2::{Digest, Sha256};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+
+/// A parsed bundle, behind a pointer C code holds but does not look into.
+#[derive(Debug)]
+pub struct CnabBundle(Bundle);
+
+/// Hand a string to the caller, to be freed with `cnab_string_free`.
+fn to_c(s: String) -> *mut c_char {
+    // JSON and error messages never contain NUL, but a caller's input might.
+    CString::new(s.replace('\0', "\\u0000"))
+        .expect("NUL bytes are escaped")
+        .into_raw()
+}
+
+/// Parse the bundle descriptor `json`.
+///
+/// Returns a handle to the bundle, or null if `json` is not a bundle, in which case
+/// the reason is stored in `*error`, unless `error` is null.
+///
+/// # Safety
+///
+/// `json` must be a NUL-terminated string, and `error` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn cnab_bundle_parse(
+    json: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut CnabBundle {
+    let fail = |message: String| {
+        if !error.is_null() {
+            *error = to_c(message);
+        }
+        ptr::null_mut()
+    };
+    if json.is_null() {
+        return fail("bundle JSON is null".to_string());
+    }
+    let json = match CStr::from_ptr(json).to_str() {
+        Ok(json) => json,
+        Err(e) => return fail(format!("bundle JSON is not UTF-8: {}", e)),
+    };
+    match json.parse::<Bundle>() {
+        Ok(bundle) => Box::into_raw(Box::new(CnabBundle(bundle))),
+        Err(e) => fail(e.to_string()),
+    }
+}
+
+/// The problems `Bundle::validate` finds with the bundle and its extensions, using
+/// the default registry, as a JSON array of strings that is empty when there are none.
+///
+/// # Safety
+///
+/// `bundle` must be a handle returned by `cnab_bundle_parse` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn cnab_bundle_validate(bundle: *const CnabBundle) -> *mut c_char {
+    let problems: Vec<String> = match (*bundle).0.validate(&ExtensionRegistry::default()) {
+        Ok(()) => Vec::new(),
+        Err(e) => e.problems.iter().map(|p| p.to_string()).collect(),
+    };
+    to_c(serde_json::to_string(&problems).expect("strings serialize"))
+}
+
+/// The `sha256:<hex>` digest of the bundle's canonical JSON.
+///
+/// # Safety
+///
+/// `bundle` must be a handle returned by `cnab_bundle_parse` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn cnab_bundle_digest(bundle: *const CnabBundle) -> *mut c_char {
+    let json = (*bundle).0.to_canonical_json();
+    to_c(format!("sha256:{}", hex::encode(Sha256::digest(&json))))
+}
+
+/// The bundle as canonical JSON.
+///
+/// # Safety
+///
+/// `bundle` must be a handle returned by `cnab_bundle_parse` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn cnab_bundle_to_json(bundle: *const CnabBundle) -> *mut c_char {
+    to_c(String::from_utf8((*bundle).0.to_canonical_json()).expect("JSON is UTF-8"))
+}
+
+/// Free a bundle handle. Freeing null does nothing.
+///
+/// # Safety
+///
+/// `bundle` must be null, or a handle returned by `cnab_bundle_parse` that has not
+/// been freed.
+#[no_mangle]
+pub unsafe extern "C" fn cnab_bundle_free(bundle: *mut CnabBundle) {
+    if !bundle.is_null() {
+        drop(Box::from_raw(bundle));
+    }
+}
+
+/// Free a string returned by one of these functions. Freeing null does nothing.
+///
+/// # Safety
+///
+/// `s` must be null, or a string returned by one of these functions that has not
+/// been freed.
+#[no_mangle]
+pub unsafe extern "C" fn cnab_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Take ownership of a returned string.
+    unsafe fn take(s: *mut c_char) -> String {
+        let owned = CStr::from_ptr(s).to_str().unwrap().to_string();
+        cnab_string_free(s);
+        owned
+    }
+
+    #[test]
+    fn test_ffi() {
+        let json = CString::new(std::fs::read("testdata/bundle.json").unwrap()).unwrap();
+        unsafe {
+            let mut error = ptr::null_mut();
+            let bundle = cnab_bundle_parse(json.as_ptr(), &mut error);
+            assert!(!bundle.is_null() && error.is_null());
+            assert_eq!(take(cnab_bundle_validate(bundle)), "[]");
+            let digest = take(cnab_bundle_digest(bundle));
+            assert!(digest.starts_with("sha256:") && digest.len() == 71);
+            let canonical = take(cnab_bundle_to_json(bundle));
+            assert!(canonical.contains(r#""name":"helloworld""#));
+            cnab_bundle_free(bundle);
+
+            let invalid = CString::new(r#"{"name": "missing fields"}"#).unwrap();
+            assert!(cnab_bundle_parse(invalid.as_ptr(), &mut error).is_null());
+            assert!(take(error).starts_with("invalid bundle JSON: missing field"));
+            assert!(cnab_bundle_parse(ptr::null(), ptr::null_mut()).is_null());
+            cnab_bundle_free(ptr::null_mut());
+        }
+    }
+}
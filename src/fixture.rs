@@ -0,0 +1,14 @@
+//! Fixtures shared by the crate's unit tests.
+
+use std::path::{Path, PathBuf};
+
+/// Write an executable shell script called `name` into `dir`, to stand in for
+/// a command such as `docker`, `kubectl` or a plugin.
+pub(crate) fn script(dir: &Path, name: &str, body: impl AsRef<str>) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = dir.join(name);
+    std::fs::write(&path, body.as_ref()).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
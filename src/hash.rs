@@ -0,0 +1,195 @@
+use crate::claim::{Claim, Response};
+use crate::cnab::Bundle;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// The fields whose content is the author's, and hashed as it is
+const OPAQUE_KEYS: &[&str] = &["custom", "definitions"];
+
+impl Bundle {
+    /// A `sha256:<hex>` digest of the content of the bundle, for use as a cache key.
+    ///
+    /// The hash is of the bundle's JSON with sorted keys, no insignificant
+    /// whitespace, and without the fields that are null, empty arrays or empty
+    /// objects, outside of `custom` and `definitions`. It does not change with the
+    /// order of fields in the document the bundle was read from, nor across
+    /// versions of this crate that add fields to the model which are unset, or
+    /// empty collections, by default. A version that adds a field with any other
+    /// default, such as `false` or a string, changes the hash of every bundle.
+    ///
+    /// Unlike the digest of `to_canonical_json`, which is what signatures cover,
+    /// it is not meant to be compared with digests made by other tools.
+    ///
+    /// ```
+    /// use libcnab::Bundle;
+    ///
+    /// let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+    /// let mut changed = bundle.clone();
+    /// assert_eq!(bundle.stable_hash(), changed.stable_hash());
+    /// changed.description = None;
+    /// assert_ne!(bundle.stable_hash(), changed.stable_hash());
+    /// ```
+    pub fn stable_hash(&self) -> String {
+        stable_hash(self)
+    }
+}
+
+impl Claim {
+    /// A digest of the content of the claim, as `Bundle::stable_hash` is of a bundle.
+    pub fn stable_hash(&self) -> String {
+        stable_hash(self)
+    }
+}
+
+impl Response {
+    /// A digest of the content of the response, as `Bundle::stable_hash` is of a
+    /// bundle.
+    pub fn stable_hash(&self) -> String {
+        stable_hash(self)
+    }
+}
+
+/// The `sha256:<hex>` digest of the stable form of `value`.
+fn stable_hash<T: Serialize>(value: &T) -> String {
+    let value = serde_json::to_value(value).expect("model types serialize");
+    let json = serde_json::to_vec(&stable(value, false)).expect("json values serialize");
+    format!("sha256:{}", hex::encode(Sha256::digest(&json)))
+}
+
+/// `value` with sorted keys, and without fields that are null or empty unless it
+/// is `opaque`.
+fn stable(value: Value, opaque: bool) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map
+                .into_iter()
+                .map(|(key, value)| {
+                    let opaque = opaque || OPAQUE_KEYS.contains(&key.as_str());
+                    (key, stable(value, opaque))
+                })
+                .filter(|(_, value)| opaque || !is_unset(value))
+                .collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(entries.into_iter().collect())
+        }
+        Value::Array(values) => {
+            Value::Array(values.into_iter().map(|v| stable(v, opaque)).collect())
+        }
+        value => value,
+    }
+}
+
+/// Whether `value` is what a field that is not set serializes as.
+fn is_unset(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Array(values) => values.is_empty(),
+        Value::Object(map) => map.is_empty(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::stable_hash;
+    use crate::claim::Claim;
+    use crate::cnab::Bundle;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    // These hashes are part of the crate's interface: if they change, caches keyed
+    // on them are invalidated, so the change must be deliberate.
+    const BUNDLE_HASH: &str =
+        "sha256:fe274fa0da53efdd4fc7c0e38e6fe0bf5012250fc0abc2bbc1a8973b0e5b0692";
+    const CLAIM_HASH: &str =
+        "sha256:b1b48a23531c996b171155b95ca621e8b606ae415f7cc197f5ea9f5f3a02fd28";
+
+    #[test]
+    fn test_stable_hash() {
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        assert_eq!(bundle.stable_hash(), BUNDLE_HASH);
+
+        // Neither the order of fields nor unset fields change the hash.
+        let mut document: serde_json::Value =
+            serde_json::from_str(include_str!("../testdata/bundle.json")).unwrap();
+        document["license"] = serde_json::Value::Null;
+        let reordered: String = {
+            let fields = document.as_object().unwrap();
+            let mut keys: Vec<_> = fields.keys().collect();
+            keys.reverse();
+            let body: Vec<String> = keys
+                .iter()
+                .map(|k| format!("{:?}:{}", k, fields[k.as_str()]))
+                .collect();
+            format!("{{{}}}", body.join(","))
+        };
+        let reparsed = Bundle::from_json(reordered.as_bytes()).unwrap();
+        assert_eq!(reparsed.stable_hash(), BUNDLE_HASH);
+
+        // A null in custom is content.
+        let mut with_null = bundle.clone();
+        with_null
+            .custom
+            .as_mut()
+            .unwrap()
+            .insert("com.example.empty".to_string(), serde_json::Value::Null);
+        assert_ne!(with_null.stable_hash(), BUNDLE_HASH);
+
+        let claim: Claim =
+            serde_json::from_str(include_str!("../testdata/compat/claims/install.json")).unwrap();
+        assert_eq!(claim.stable_hash(), CLAIM_HASH);
+        let mut changed = claim.clone();
+        changed.revision = "01H0000000000000000000000".to_string();
+        assert_ne!(changed.stable_hash(), CLAIM_HASH);
+        assert_ne!(claim.result.stable_hash(), claim.stable_hash());
+    }
+
+    #[test]
+    fn test_added_fields() {
+        #[derive(Serialize)]
+        struct Before {
+            name: String,
+        }
+        #[derive(Default, Serialize)]
+        struct Nested {
+            note: Option<String>,
+        }
+        #[derive(Serialize)]
+        struct After {
+            name: String,
+            description: Option<String>,
+            tags: Vec<String>,
+            labels: BTreeMap<String, String>,
+            nested: Nested,
+        }
+        #[derive(Serialize)]
+        struct Flagged {
+            name: String,
+            deprecated: bool,
+        }
+
+        let before = stable_hash(&Before {
+            name: "hello".to_string(),
+        });
+        let after = After {
+            name: "hello".to_string(),
+            description: None,
+            tags: Vec::new(),
+            labels: BTreeMap::new(),
+            nested: Nested::default(),
+        };
+        assert_eq!(stable_hash(&after), before);
+        let tagged = After {
+            tags: vec!["web".to_string()],
+            ..after
+        };
+        assert_ne!(stable_hash(&tagged), before);
+        // A default that is not empty is content.
+        let flagged = Flagged {
+            name: "hello".to_string(),
+            deprecated: false,
+        };
+        assert_ne!(stable_hash(&flagged), before);
+    }
+}
@@ -0,0 +1,656 @@
+//! Import thick bundles into a registry.
+//!
+//! Importing a `bundle.cnab` archive, such as one `export::thick` wrote, pushes every
+//! image of its OCI image layout into the target repository and then the bundle
+//! itself, the way `registry::Client::push` stores it, with the archive's SBOMs
+//! attached to it as referrers. Nothing is fetched from the registries the images
+//! came from, so a thick bundle can be imported where they cannot be reached.
+//!
+//! ```no_run
+//! let file = std::fs::File::open("bundle.cnab").unwrap();
+//! let imported = libcnab::import::thick(file, "registry.internal/bundles/hello:0.1.0").unwrap();
+//! let bundle = imported.relocated();
+//! println!("{}", bundle.invocation_images[0].image);
+//! ```
+use crate::cnab::{Bundle, BundleParseError};
+use crate::error_code::{Coded, ErrorCode};
+use crate::export::{blob_digest, sbom_path, BUNDLE_PATH, LAYOUT_PATH, SBOM_PATH};
+use crate::reference::{AsReference, BundleReference};
+use crate::registry::{
+    parse, sha256_digest, Client, Descriptor, Index, Manifest, Phase, RegistryError, Sbom, Tracker,
+    DOCKER_MANIFEST_LIST, MANIFEST_LIMIT, OCI_INDEX, REF_NAME_ANNOTATION,
+};
+use crate::relocation::RelocationMap;
+use flate2::read::GzDecoder;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex};
+use ulid::Ulid;
+
+/// ImportedBundle is a thick bundle as it was pushed to a registry.
+#[derive(Debug, Clone)]
+pub struct ImportedBundle {
+    /// The bundle as it was in the archive
+    pub bundle: Bundle,
+    /// The digest of the bundle's index
+    pub digest: String,
+    /// Where each image of the bundle was pushed to
+    pub relocation: RelocationMap,
+    /// The SBOMs in the archive, attached to the pushed bundle
+    pub sboms: Vec<Sbom>,
+}
+
+impl ImportedBundle {
+    /// The bundle with its images' references rewritten to where they were pushed to,
+    /// for use by tools that do not read relocation maps.
+    pub fn relocated(&self) -> Bundle {
+        let mut bundle = self.bundle.clone();
+        for image in bundle.invocation_images.iter_mut() {
+            image.image = self.relocation.relocate(&image.image).to_string();
+        }
+        for image in bundle.images.iter_mut().flat_map(|i| i.values_mut()) {
+            image.image = self.relocation.relocate(&image.image).to_string();
+        }
+        bundle
+    }
+}
+
+/// Import the thick bundle read from `reader` to `reference`, a tag such as
+/// `example.com/bundles/hello:0.1.0`, with a default registry `Client`.
+pub fn thick<R, T>(reader: R, reference: &T) -> Result<ImportedBundle, ImportError>
+where
+    R: Read,
+    T: AsReference + ?Sized,
+{
+    thick_with(&Client::new(), reader, reference)
+}
+
+/// Import the thick bundle read from `reader` to `reference` with `client`.
+///
+/// The archive is read once, from start to end, and never unpacked: each blob is
+/// pushed as it is read, checked against its digest on the way. Only the
+/// `bundle.json`, the layout's `index.json`, the SBOMs and the small JSON blobs that
+/// may be manifests are kept until the end of the archive, when the manifests the
+/// index names are pushed. Every image of the bundle must be in the archive. The
+/// blobs the target has already are not pushed again, so an import that failed can
+/// be run again to push only what it did not.
+///
+/// Blobs are pushed as many at once as the client's `Client::parallelism`, while the
+/// archive is read on. The large ones that are read before there is a worker to push
+/// them wait in a temporary directory, removed afterwards; with a parallelism of 1,
+/// each blob is streamed from the archive to the registry, so that importing takes
+/// little memory and no disk however large the images are.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(client, reader), err))]
+pub fn thick_with<R, T>(
+    client: &Client,
+    reader: R,
+    reference: &T,
+) -> Result<ImportedBundle, ImportError>
+where
+    R: Read,
+    T: AsReference + ?Sized,
+{
+    let target = reference.to_reference().map_err(RegistryError::from)?;
+    let tracker = client.tracker();
+    tracker.phase(Phase::Transferring);
+    let staging = std::env::temp_dir().join(format!("libcnab-import-{}", Ulid::new()));
+    let failed = AtomicBool::new(false);
+    let mut layout = Layout {
+        client,
+        target: &target,
+        documents: BTreeMap::new(),
+        pushed: BTreeSet::new(),
+        tracker: &tracker,
+        uploads: None,
+    };
+    let read = match client.parallel_transfers() {
+        1 => layout.read(reader),
+        workers => {
+            let read = layout.read_in_parallel(workers, &staging, &failed, reader);
+            let _ = std::fs::remove_dir_all(&staging);
+            read
+        }
+    };
+    let (bundle, index, sboms) = read?;
+
+    tracker.phase(Phase::Publishing);
+    let mut relocation = RelocationMap::new();
+    for entry in &index.manifests {
+        let name = entry.annotation(REF_NAME_ANNOTATION).ok_or_else(|| {
+            ImportError::InvalidArchive(format!("{} has no image name", entry.digest))
+        })?;
+        layout.manifest(entry)?;
+        relocation.insert(name, &target.with_digest(&entry.digest));
+    }
+    let images = bundle.invocation_images.iter().map(|i| &i.image).chain(
+        bundle
+            .images
+            .iter()
+            .flat_map(|i| i.values())
+            .map(|i| &i.image),
+    );
+    for image in images {
+        // Pushing the bundle would go looking for the image where it came from.
+        if relocation.get(image).is_none() {
+            return Err(ImportError::InvalidArchive(format!(
+                "image {} is not in the archive",
+                image
+            )));
+        }
+    }
+
+    // SBOMs are attached in the order of their names.
+    let sboms: Vec<Sbom> = sboms.into_values().collect();
+    let pushed = client.push_tracked(&bundle, &relocation, &target, &tracker)?;
+    for sbom in &sboms {
+        client.attach_sbom(&target.with_digest(&pushed.digest), sbom)?;
+    }
+    Ok(ImportedBundle {
+        bundle,
+        digest: pushed.digest,
+        relocation: pushed.relocation,
+        sboms,
+    })
+}
+/// The SBOMs in the thick bundle read from `reader`, such as for a scanner to look
+/// at before the bundle is imported.
+pub fn sboms<R: Read>(reader: R) -> Result<Vec<Sbom>, ImportError> {
+    let mut sboms = Vec::new();
+    let mut archive = tar::Archive::new(GzDecoder::new(reader));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().into_owned();
+        let path = path.trim_start_matches("./");
+        if let Some(name) = path
+            .strip_prefix(SBOM_PATH)
+            .and_then(|p| p.strip_prefix('/'))
+        {
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+            sboms.push(sbom(name, &content)?);
+        }
+    }
+    Ok(sboms)
+}
+
+/// Parse the SBOM `name` of a thick bundle, checking it against the digest it is
+/// named by.
+fn sbom(name: &str, content: &[u8]) -> Result<Sbom, ImportError> {
+    let actual = sha256_digest(content);
+    if sbom_path(&actual).as_deref() != Some(&format!("{}/{}", SBOM_PATH, name)) {
+        return Err(ImportError::DigestMismatch {
+            expected: format!("sha256:{}", name.trim_end_matches(".json")),
+            actual,
+        });
+    }
+    Sbom::parse(content).map_err(|e| ImportError::InvalidArchive(format!("{}: {}", name, e)))
+}
+
+/// The OCI image layout of a thick bundle, as it is pushed while the archive is read.
+struct Layout<'a> {
+    client: &'a Client,
+    target: &'a BundleReference,
+    /// The blobs small enough to be manifests that are JSON, keyed by digest, which
+    /// are pushed once it is known whether they are manifests
+    documents: BTreeMap<String, Vec<u8>>,
+    /// The digests of the blobs pushed so far
+    pushed: BTreeSet<String>,
+    /// Counts each blob of the archive as a layer, as it is read
+    tracker: &'a Tracker<'a>,
+    /// Where blobs are sent to be pushed by other threads, if they are
+    uploads: Option<Uploads<'a>>,
+}
+
+/// The end of the queue of blobs that workers push while the archive is read.
+struct Uploads<'a> {
+    sender: SyncSender<Upload>,
+    /// Where the large blobs wait for a worker
+    staging: &'a Path,
+    /// Set when a worker failed, so that the archive is not read any further
+    failed: &'a AtomicBool,
+}
+
+/// Upload is a blob read from the archive, to push.
+struct Upload {
+    blob: Descriptor,
+    content: Staged,
+}
+
+enum Staged {
+    Memory(Vec<u8>),
+    File(PathBuf),
+}
+
+impl Upload {
+    fn push(
+        self,
+        client: &Client,
+        target: &BundleReference,
+        tracker: &Tracker<'_>,
+    ) -> Result<(), ImportError> {
+        match self.content {
+            Staged::Memory(body) => client.upload_blob(target, &self.blob, &body[..], tracker)?,
+            Staged::File(path) => {
+                client.upload_blob(target, &self.blob, File::open(&path)?, tracker)?;
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+        tracker.layer_done();
+        Ok(())
+    }
+}
+
+impl<'a> Layout<'a> {
+    /// Read the archive from `reader`, pushing its blobs, and return its bundle, its
+    /// layout's index and its SBOMs by name.
+    fn read<R: Read>(
+        &mut self,
+        reader: R,
+    ) -> Result<(Bundle, Index, BTreeMap<String, Sbom>), ImportError> {
+        let index_path = format!("{}/index.json", LAYOUT_PATH);
+        let (mut bundle, mut index, mut sboms) = (None, None, BTreeMap::new());
+        for entry in tar::Archive::new(GzDecoder::new(reader)).entries()? {
+            if self.stopped() {
+                break;
+            }
+            let mut entry = entry?;
+            let path = entry.path()?.to_string_lossy().into_owned();
+            let path = path.trim_start_matches("./");
+            if path == BUNDLE_PATH {
+                bundle = Some(Bundle::from_json(&mut entry)?);
+            } else if path == index_path {
+                let parsed: Index = serde_json::from_reader(&mut entry)
+                    .map_err(|e| ImportError::InvalidArchive(format!("index.json: {}", e)))?;
+                index = Some(parsed);
+            } else if let Some(name) = path
+                .strip_prefix(SBOM_PATH)
+                .and_then(|p| p.strip_prefix('/'))
+            {
+                let mut content = Vec::new();
+                entry.read_to_end(&mut content)?;
+                sboms.insert(name.to_string(), sbom(name, &content)?);
+            } else if let Some(digest) = blob_digest(path) {
+                let size = entry.header().size()?;
+                self.blob(digest, size, entry)?;
+            }
+        }
+        let missing =
+            |path: &str| ImportError::InvalidArchive(format!("{} is not in the archive", path));
+        let bundle = bundle.ok_or_else(|| missing(BUNDLE_PATH))?;
+        let index = index.ok_or_else(|| missing(&index_path))?;
+        Ok((bundle, index, sboms))
+    }
+
+    /// Read the archive as `read` does, with `workers` threads pushing the blobs
+    /// while it is read. A failure to push a blob is returned rather than one to
+    /// read the rest of the archive.
+    fn read_in_parallel<R: Read>(
+        &mut self,
+        workers: usize,
+        staging: &'a Path,
+        failed: &'a AtomicBool,
+        reader: R,
+    ) -> Result<(Bundle, Index, BTreeMap<String, Sbom>), ImportError> {
+        let (sender, receiver) = mpsc::sync_channel::<Upload>(workers);
+        // The workers own the receiver, so that sending fails once they all stopped.
+        let receiver = Arc::new(Mutex::new(receiver));
+        let (client, target, tracker) = (self.client, self.target, self.tracker);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    let receiver = Arc::clone(&receiver);
+                    scope.spawn(move || loop {
+                        let next = receiver.lock().expect("lock poisoned").recv();
+                        let upload = match next {
+                            Ok(upload) => upload,
+                            Err(_) => return Ok(()),
+                        };
+                        if let Err(e) = upload.push(client, target, tracker) {
+                            failed.store(true, Ordering::SeqCst);
+                            return Err(e);
+                        }
+                    })
+                })
+                .collect();
+            drop(receiver);
+            self.uploads = Some(Uploads {
+                sender,
+                staging,
+                failed,
+            });
+            let read = self.read(reader);
+            // The workers finish once they pushed what was sent.
+            self.uploads = None;
+            for handle in handles {
+                handle
+                    .join()
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e))?;
+            }
+            read
+        })
+    }
+
+    /// Whether a worker failed to push a blob.
+    fn stopped(&self) -> bool {
+        let failed = self.uploads.as_ref().map(|u| u.failed);
+        failed.is_some_and(|f| f.load(Ordering::SeqCst))
+    }
+
+    /// Push the blob `digest` of `size` bytes read from `content`, unless it may be a
+    /// manifest, in which case it is kept.
+    fn blob<B: Read>(
+        &mut self,
+        digest: String,
+        size: u64,
+        mut content: B,
+    ) -> Result<(), ImportError> {
+        let blob = blob_descriptor(&digest, size);
+        self.tracker.layers(1);
+        if size <= MANIFEST_LIMIT {
+            let mut body = Vec::new();
+            content.read_to_end(&mut body)?;
+            if body.starts_with(b"{") {
+                self.documents.insert(digest, body);
+                self.tracker.layer_done();
+                return Ok(());
+            }
+            self.upload(Upload {
+                blob,
+                content: Staged::Memory(body),
+            })?;
+        } else if let Some(uploads) = &self.uploads {
+            std::fs::create_dir_all(uploads.staging)?;
+            let path = uploads.staging.join(digest.replace(':', "-"));
+            std::io::copy(&mut content, &mut File::create(&path)?)?;
+            self.upload(Upload {
+                blob,
+                content: Staged::File(path),
+            })?;
+        } else {
+            self.client
+                .upload_blob(self.target, &blob, content, self.tracker)?;
+            self.tracker.layer_done();
+        }
+        self.pushed.insert(digest);
+        Ok(())
+    }
+
+    /// Push `upload`, or send it to the workers if there are some.
+    fn upload(&self, upload: Upload) -> Result<(), ImportError> {
+        match &self.uploads {
+            // The workers only stop early when one failed, which is what is returned.
+            Some(uploads) => {
+                let _ = uploads.sender.send(upload);
+                Ok(())
+            }
+            None => upload.push(self.client, self.target, self.tracker),
+        }
+    }
+
+    /// Push the blob `blob` refers to, if it was kept rather than pushed as it was
+    /// read.
+    fn require(&mut self, blob: &Descriptor) -> Result<(), ImportError> {
+        if self.pushed.contains(&blob.digest) {
+            return Ok(());
+        }
+        let body = self.documents.get(&blob.digest).ok_or_else(|| {
+            ImportError::InvalidArchive(format!("{} is not in the archive", blob.digest))
+        })?;
+        self.client.upload_blob(
+            self.target,
+            &blob_descriptor(&blob.digest, body.len() as u64),
+            &body[..],
+            self.tracker,
+        )?;
+        self.pushed.insert(blob.digest.clone());
+        Ok(())
+    }
+
+    /// Push the manifest `manifest` points at, and everything it refers to.
+    fn manifest(&mut self, manifest: &Descriptor) -> Result<(), ImportError> {
+        let body = self
+            .documents
+            .get(&manifest.digest)
+            .cloned()
+            .ok_or_else(|| {
+                ImportError::InvalidArchive(format!("{} is not in the archive", manifest.digest))
+            })?;
+        let actual = sha256_digest(&body);
+        if actual != manifest.digest {
+            return Err(ImportError::DigestMismatch {
+                expected: manifest.digest.clone(),
+                actual,
+            });
+        }
+        match manifest.media_type.as_str() {
+            OCI_INDEX | DOCKER_MANIFEST_LIST => {
+                let index: Index = parse(self.target, &body)?;
+                for child in &index.manifests {
+                    self.manifest(child)?;
+                }
+            }
+            _ => {
+                let image: Manifest = parse(self.target, &body)?;
+                for blob in Some(&image.config).into_iter().chain(&image.layers) {
+                    self.require(blob)?;
+                }
+            }
+        }
+        self.client
+            .put_manifest(self.target, None, &manifest.media_type, &body)?;
+        Ok(())
+    }
+}
+
+/// The descriptor of a blob of a thick bundle to push.
+fn blob_descriptor(digest: &str, size: u64) -> Descriptor {
+    Descriptor {
+        media_type: "application/octet-stream".to_string(),
+        digest: digest.to_string(),
+        size,
+        platform: None,
+        annotations: None,
+        artifact_type: None,
+    }
+}
+
+/// ImportError describes a failure to import a thick bundle.
+#[derive(Debug)]
+pub enum ImportError {
+    /// The archive is not a thick bundle, or misses some of its content
+    InvalidArchive(String),
+    /// A manifest in the archive does not match its digest
+    DigestMismatch {
+        expected: String,
+        actual: String,
+    },
+    BundleParseError(BundleParseError),
+    /// The images or the bundle could not be pushed
+    RegistryError(RegistryError),
+    IoError(std::io::Error),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::InvalidArchive(m) => write!(f, "invalid thick bundle: {}", m),
+            ImportError::DigestMismatch { expected, actual } => {
+                write!(f, "expected digest {}, got {}", expected, actual)
+            }
+            ImportError::BundleParseError(e) => write!(f, "{}", e),
+            ImportError::RegistryError(e) => write!(f, "{}", e),
+            ImportError::IoError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl Coded for ImportError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ImportError::InvalidArchive(_) => ErrorCode::ImportInvalidArchive,
+            ImportError::DigestMismatch { .. } => ErrorCode::ImportDigestMismatch,
+            ImportError::BundleParseError(e) => e.code(),
+            ImportError::RegistryError(e) => e.code(),
+            ImportError::IoError(_) => ErrorCode::Io,
+        }
+    }
+}
+
+impl From<BundleParseError> for ImportError {
+    fn from(error: BundleParseError) -> Self {
+        ImportError::BundleParseError(error)
+    }
+}
+
+impl From<RegistryError> for ImportError {
+    fn from(error: RegistryError) -> Self {
+        ImportError::RegistryError(error)
+    }
+}
+
+impl From<std::io::Error> for ImportError {
+    fn from(error: std::io::Error) -> Self {
+        ImportError::IoError(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::export::blob_path;
+    use crate::registry::testing::FakeRegistry;
+    use crate::registry::OCI_MANIFEST;
+
+    fn descriptor(media_type: &str, digest: &str, size: usize) -> Descriptor {
+        Descriptor {
+            media_type: media_type.to_string(),
+            digest: digest.to_string(),
+            size: size as u64,
+            platform: None,
+            annotations: None,
+            artifact_type: None,
+        }
+    }
+
+    #[test]
+    fn test_thick() {
+        let source = FakeRegistry::start();
+        let config = source.put_blob("images/hello", b"{}");
+        let layer = source.put_blob("images/hello", b"layer");
+        // Too large to be a manifest, so pushed as it is read
+        let large = vec![7u8; MANIFEST_LIMIT as usize + 1];
+        let large_layer = source.put_blob("images/hello", &large);
+        let image = serde_json::to_vec(&Manifest {
+            schema_version: 2,
+            media_type: Some(OCI_MANIFEST.to_string()),
+            config: descriptor("application/vnd.oci.image.config.v1+json", &config, 2),
+            layers: vec![
+                descriptor("application/vnd.oci.image.layer.v1.tar", &layer, 5),
+                descriptor(
+                    "application/vnd.oci.image.layer.v1.tar",
+                    &large_layer,
+                    large.len(),
+                ),
+            ],
+            annotations: None,
+            artifact_type: None,
+            subject: None,
+        })
+        .unwrap();
+        let image_digest = source.put_manifest("images/hello", Some("0.1.0"), OCI_MANIFEST, &image);
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let original = format!("{}/images/hello:0.1.0", source.host);
+        bundle.invocation_images[0].image = original.clone();
+        bundle.images = None;
+        let sbom = Sbom::parse(br#"{"spdxVersion":"SPDX-2.3","packages":[]}"#).unwrap();
+        let archive = crate::export::thick_with_sboms(
+            &Client::new(),
+            &bundle,
+            &RelocationMap::new(),
+            std::slice::from_ref(&sbom),
+            Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(sboms(&archive[..]).unwrap(), vec![sbom.clone()]);
+
+        let target = FakeRegistry::start();
+        let reference = format!("{}/bundles/hello:0.1.0", target.host);
+        let imported = thick(&archive[..], &reference).expect("bundle imported");
+        let moved = format!("{}/bundles/hello@{}", target.host, image_digest);
+        assert_eq!(imported.relocation.get(&original), Some(moved.as_str()));
+        assert_eq!(imported.bundle.invocation_images[0].image, original);
+        assert_eq!(imported.relocated().invocation_images[0].image, moved);
+        {
+            let state = target.state.lock().unwrap();
+            let blob = ("bundles/hello".to_string(), layer.clone());
+            assert_eq!(state.blobs[&blob], b"layer");
+            let blob = ("bundles/hello".to_string(), large_layer.clone());
+            assert_eq!(state.blobs[&blob], large);
+        }
+        let pulled = Client::new().pull(&reference).unwrap();
+        assert_eq!(pulled.digest, imported.digest);
+        assert_eq!(pulled.relocation, imported.relocation);
+        assert_eq!(imported.sboms, vec![sbom.clone()]);
+        assert_eq!(Client::new().sboms(&reference).unwrap(), vec![sbom]);
+
+        // Importing again pushes none of the blobs again.
+        target.state.lock().unwrap().requests.clear();
+        let again = thick(&archive[..], &reference).expect("bundle imported");
+        assert_eq!(again.digest, imported.digest);
+        let requests = target.state.lock().unwrap().requests.clone();
+        assert!(
+            !requests.iter().any(|r| r.starts_with("POST")),
+            "{:?}",
+            requests
+        );
+
+        // One blob at a time, streamed from the archive, imports the same bundle.
+        let serial = FakeRegistry::start();
+        let serial_reference = format!("{}/bundles/hello:0.1.0", serial.host);
+        let streamed = thick_with(
+            &Client::new().parallelism(1),
+            &archive[..],
+            &serial_reference,
+        )
+        .expect("bundle imported");
+        assert_eq!(streamed.digest, imported.digest);
+        {
+            let state = serial.state.lock().unwrap();
+            let blob = ("bundles/hello".to_string(), large_layer.clone());
+            assert_eq!(state.blobs[&blob], large);
+        }
+
+        // An archive missing some of an image is not imported.
+        let mut archive = archive;
+        let unpacked = tempfile::tempdir().unwrap();
+        tar::Archive::new(GzDecoder::new(&archive[..]))
+            .unpack(unpacked.path())
+            .unwrap();
+        std::fs::remove_file(unpacked.path().join(blob_path(&layer).unwrap())).unwrap();
+        archive.clear();
+        {
+            let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+                &mut archive,
+                flate2::Compression::default(),
+            ));
+            tar.append_dir_all(".", unpacked.path()).unwrap();
+            tar.into_inner().unwrap().finish().unwrap();
+        }
+        let other = FakeRegistry::start();
+        match thick(&archive[..], &format!("{}/bundles/hello:0.1.0", other.host)) {
+            Err(ImportError::InvalidArchive(m)) => assert!(m.contains(&layer), "{}", m),
+            other => panic!("expected an invalid archive, got {:?}", other),
+        }
+        match thick(&b"not an archive"[..], &reference) {
+            Err(ImportError::IoError(_)) => {}
+            other => panic!("expected an unreadable archive, got {:?}", other),
+        }
+    }
+}
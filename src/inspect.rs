@@ -0,0 +1,558 @@
+//! Describe a bundle for display, with each section as a table of rows.
+//!
+//! An `Inspection` has what a UI shows of a bundle already worked out, such as the
+//! type and default of each parameter from its definition, so that web UIs and
+//! TUIs only lay it out. It serializes to JSON, with fields in camelCase.
+//!
+//! ```
+//! use libcnab::inspect::inspect;
+//! use libcnab::Bundle;
+//!
+//! let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+//! let inspection = inspect(&bundle);
+//! assert_eq!(inspection.parameters[0].name, "backend_port");
+//! assert_eq!(inspection.actions[0].name, "install");
+//! assert!(inspection.to_json().starts_with('{'));
+//! ```
+use crate::cnab::{Bundle, Platform, BUILTIN_ACTIONS};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+
+/// Inspection is a bundle as a UI shows it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Inspection {
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+    pub license: Option<String>,
+    pub keywords: Vec<String>,
+    /// The maintainers, as `name <email>` when they have an email
+    pub maintainers: Vec<String>,
+    pub parameters: Vec<ParameterRow>,
+    pub credentials: Vec<CredentialRow>,
+    pub outputs: Vec<OutputRow>,
+    /// The invocation images, and then the other images
+    pub images: Vec<ImageRow>,
+    /// The built-in actions, and then the custom ones
+    pub actions: Vec<ActionRow>,
+}
+
+/// ParameterRow is a parameter, with what its definition says of it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParameterRow {
+    pub name: String,
+    pub description: Option<String>,
+    /// The type of the definition, such as `string`, or `integer | null` for several
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub default: Option<Value>,
+    /// The values the definition allows, if it lists them
+    pub allowed: Option<Vec<Value>>,
+    pub required: bool,
+    pub sensitive: bool,
+    pub env: Option<String>,
+    pub path: Option<String>,
+    /// The actions the parameter applies to, empty for all of them
+    pub applies_to: Vec<String>,
+}
+
+/// CredentialRow is a credential.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialRow {
+    pub name: String,
+    pub description: Option<String>,
+    pub required: bool,
+    pub env: Option<String>,
+    pub path: Option<String>,
+    /// The actions the credential applies to, empty for all of them
+    pub applies_to: Vec<String>,
+}
+
+/// OutputRow is an output, with what its definition says of it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputRow {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub sensitive: bool,
+    pub path: Option<String>,
+    /// The actions that produce the output, empty for all of them
+    pub applies_to: Vec<String>,
+}
+
+/// ImageRow is an invocation image or an image of a bundle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageRow {
+    /// The name of the image, or `invocationImages[<index>]` for an invocation image
+    pub name: String,
+    pub invocation: bool,
+    pub image: String,
+    pub image_type: Option<String>,
+    pub digest: Option<String>,
+    /// The platform, such as `linux/amd64`
+    pub platform: Option<String>,
+    pub size: Option<i64>,
+    pub description: Option<String>,
+}
+
+/// ActionRow is an action the bundle can be run with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionRow {
+    pub name: String,
+    pub description: Option<String>,
+    pub builtin: bool,
+    pub modifies: bool,
+    pub stateless: bool,
+}
+
+/// Describe `bundle` for display.
+pub fn inspect(bundle: &Bundle) -> Inspection {
+    let invocation_images = bundle
+        .invocation_images
+        .iter()
+        .enumerate()
+        .map(|(i, image)| ImageRow {
+            name: format!("invocationImages[{}]", i),
+            invocation: true,
+            image: image.image.clone(),
+            image_type: image.image_type.clone(),
+            digest: image.content_digest.clone(),
+            platform: None,
+            size: image.size,
+            description: None,
+        });
+    let images = bundle
+        .images
+        .iter()
+        .flatten()
+        .map(|(name, image)| ImageRow {
+            name: name.clone(),
+            invocation: false,
+            image: image.image.clone(),
+            image_type: image.image_type.clone(),
+            digest: image.content_digest.clone(),
+            platform: image.platform.as_ref().and_then(platform),
+            size: image.size,
+            description: image.description.clone(),
+        });
+
+    Inspection {
+        name: bundle.name.clone(),
+        version: bundle.version.to_string(),
+        description: bundle.description.clone(),
+        license: bundle.license.clone(),
+        keywords: bundle.keywords.clone().unwrap_or_default(),
+        maintainers: bundle
+            .maintainers
+            .iter()
+            .flatten()
+            .map(|m| match &m.email {
+                Some(email) => format!("{} <{}>", m.name, email),
+                None => m.name.clone(),
+            })
+            .collect(),
+        parameters: parameters(bundle, None),
+        credentials: credentials(bundle, None),
+        outputs: outputs(bundle, None),
+        images: invocation_images.chain(images).collect(),
+        actions: actions(bundle),
+    }
+}
+
+impl Inspection {
+    /// The inspection as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("inspections serialize")
+    }
+}
+
+/// Explanation is what a bundle takes and gives when run with one action, as a
+/// `--help` shows it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Explanation {
+    pub action: ActionRow,
+    /// The parameters the action takes, the required ones first
+    pub parameters: Vec<ParameterRow>,
+    /// The credentials the action takes, the required ones first
+    pub credentials: Vec<CredentialRow>,
+    /// The outputs the action produces
+    pub outputs: Vec<OutputRow>,
+}
+
+impl Bundle {
+    /// What the bundle takes and gives when run with `action`, or `None` if it
+    /// does not have the action.
+    ///
+    /// ```
+    /// use libcnab::Bundle;
+    ///
+    /// let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+    /// let explanation = bundle.explain("install").unwrap();
+    /// assert_eq!(explanation.parameters[0].name, "backend_port");
+    /// assert!(bundle.explain("dance").is_none());
+    /// ```
+    pub fn explain(&self, action: &str) -> Option<Explanation> {
+        let action = actions(self).into_iter().find(|a| a.name == action)?;
+        let mut parameters = parameters(self, Some(&action.name));
+        parameters.sort_by_key(|p| !p.required);
+        let mut credentials = credentials(self, Some(&action.name));
+        credentials.sort_by_key(|c| !c.required);
+        Some(Explanation {
+            parameters,
+            credentials,
+            outputs: outputs(self, Some(&action.name)),
+            action,
+        })
+    }
+}
+
+impl Explanation {
+    /// The explanation as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("explanations serialize")
+    }
+}
+
+/// The parameters of `bundle`, of those that apply to `action` if there is one.
+fn parameters(bundle: &Bundle, action: Option<&str>) -> Vec<ParameterRow> {
+    bundle
+        .parameters
+        .iter()
+        .flatten()
+        .filter(|(_, p)| action.is_none_or(|a| p.applies_to(a)))
+        .map(|(name, p)| {
+            let schema = p.definition.as_deref().and_then(|d| bundle.definition(d));
+            ParameterRow {
+                name: name.clone(),
+                description: p.description.clone(),
+                kind: schema.and_then(kind),
+                default: schema.and_then(|s| s.get("default")).cloned(),
+                allowed: schema
+                    .and_then(|s| s.get("enum"))
+                    .and_then(Value::as_array)
+                    .cloned(),
+                required: p.required.unwrap_or(false),
+                sensitive: p
+                    .definition
+                    .as_deref()
+                    .is_some_and(|d| bundle.is_sensitive(d)),
+                env: p.destination.env.clone().map(String::from),
+                path: display_path(p.destination.path.as_deref()),
+                applies_to: p.apply_to.clone().unwrap_or_default(),
+            }
+        })
+        .collect()
+}
+
+/// The credentials of `bundle`, of those that apply to `action` if there is one.
+fn credentials(bundle: &Bundle, action: Option<&str>) -> Vec<CredentialRow> {
+    bundle
+        .credentials
+        .iter()
+        .flatten()
+        .filter(|(_, c)| action.is_none_or(|a| c.applies_to(a)))
+        .map(|(name, c)| CredentialRow {
+            name: name.clone(),
+            description: c.description.clone(),
+            required: c.required.unwrap_or(false),
+            env: c.env.clone().map(String::from),
+            path: display_path(c.path.as_deref()),
+            applies_to: c.apply_to.clone().unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// The outputs of `bundle`, of those `action` produces if there is one.
+fn outputs(bundle: &Bundle, action: Option<&str>) -> Vec<OutputRow> {
+    bundle
+        .outputs
+        .iter()
+        .flatten()
+        .filter(|(_, o)| action.is_none_or(|a| o.applies_to(a)))
+        .map(|(name, o)| OutputRow {
+            name: name.clone(),
+            description: o.description.clone(),
+            kind: bundle.definition(&o.definition).and_then(kind),
+            sensitive: bundle.is_sensitive(&o.definition),
+            path: display_path(o.path.as_deref()),
+            applies_to: o.apply_to.clone().unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// The built-in actions of `bundle`, and then the custom ones.
+fn actions(bundle: &Bundle) -> Vec<ActionRow> {
+    let mut actions: Vec<ActionRow> = BUILTIN_ACTIONS
+        .iter()
+        .map(|&name| ActionRow {
+            name: name.to_string(),
+            description: None,
+            builtin: true,
+            modifies: true,
+            stateless: false,
+        })
+        .collect();
+    for (name, action) in bundle.actions.iter().flatten() {
+        let row = ActionRow {
+            name: name.clone(),
+            description: action.description.clone(),
+            builtin: BUILTIN_ACTIONS.contains(&name.as_str()),
+            modifies: action.modifies,
+            stateless: action.stateless,
+        };
+        match actions.iter_mut().find(|a| a.name == *name) {
+            Some(existing) => *existing = row,
+            None => actions.push(row),
+        }
+    }
+    actions
+}
+
+/// The type of a schema, with several joined by `|`.
+fn kind(schema: &Value) -> Option<String> {
+    match schema.get("type")? {
+        Value::String(kind) => Some(kind.clone()),
+        Value::Array(kinds) => Some(
+            kinds
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+                .join(" | "),
+        ),
+        _ => None,
+    }
+}
+
+fn platform(platform: &Platform) -> Option<String> {
+    match (&platform.os, &platform.arch) {
+        (Some(os), Some(arch)) => Some(format!("{}/{}", os, arch)),
+        (Some(os), None) => Some(os.clone()),
+        (None, Some(arch)) => Some(arch.clone()),
+        (None, None) => None,
+    }
+}
+
+fn display_path(path: Option<&Path>) -> Option<String> {
+    path.map(|p| p.display().to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cnab::{Action, Credential, Destination, Output, Parameter};
+    use serde_json::json;
+
+    #[test]
+    fn test_inspect() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.definitions = Some(
+            vec![
+                (
+                    "port".to_string(),
+                    json!({ "type": "integer", "default": 8080 }),
+                ),
+                (
+                    "level".to_string(),
+                    json!({ "type": ["string", "null"], "enum": ["debug", "info"] }),
+                ),
+                (
+                    "token".to_string(),
+                    json!({ "type": "string", "writeOnly": true }),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let parameters = bundle.parameters.as_mut().unwrap();
+        parameters.get_mut("backend_port").unwrap().definition = Some("port".to_string());
+        parameters.insert(
+            "log_level".to_string(),
+            Parameter {
+                apply_to: Some(vec!["install".to_string()]),
+                definition: Some("level".to_string()),
+                description: Some("How much to log".to_string()),
+                destination: Destination {
+                    env: None,
+                    path: Some("/cnab/app/level".into()),
+                },
+                required: Some(true),
+            },
+        );
+        bundle.outputs = Some(
+            vec![(
+                "token".to_string(),
+                Output {
+                    apply_to: None,
+                    definition: "token".to_string(),
+                    description: None,
+                    path: Some("/cnab/app/outputs/token".into()),
+                },
+            )]
+            .into_iter()
+            .collect(),
+        );
+        bundle
+            .images
+            .as_mut()
+            .unwrap()
+            .get_mut("my-microservice")
+            .unwrap()
+            .platform = Some(Platform {
+            arch: Some("amd64".to_string()),
+            os: Some("linux".to_string()),
+        });
+        let actions = bundle.actions.get_or_insert_with(Default::default);
+        actions.insert(
+            "uninstall".to_string(),
+            Action {
+                description: Some("Remove everything".to_string()),
+                modifies: true,
+                stateless: false,
+            },
+        );
+        actions.insert(
+            "logs".to_string(),
+            Action {
+                description: None,
+                modifies: false,
+                stateless: true,
+            },
+        );
+
+        let inspection = inspect(&bundle);
+        assert_eq!(
+            inspection.maintainers,
+            vec!["Matt Butcher <matt.butcher@microsoft.com>"]
+        );
+
+        let port = &inspection.parameters[0];
+        assert_eq!(port.kind.as_deref(), Some("integer"));
+        assert_eq!(port.default, Some(json!(8080)));
+        assert_eq!(port.env.as_deref(), Some("BACKEND_PORT"));
+        assert!(!port.required);
+        let level = &inspection.parameters[1];
+        assert_eq!(level.kind.as_deref(), Some("string | null"));
+        assert_eq!(level.allowed, Some(vec![json!("debug"), json!("info")]));
+        assert_eq!(level.path.as_deref(), Some("/cnab/app/level"));
+        assert_eq!(level.applies_to, vec!["install"]);
+        assert!(level.required);
+
+        assert!(inspection.outputs[0].sensitive);
+        assert_eq!(inspection.credentials[0].env.as_deref(), Some("HOST_KEY"));
+
+        let images: Vec<_> = inspection
+            .images
+            .iter()
+            .map(|i| (i.name.as_str(), i.invocation, i.platform.as_deref()))
+            .collect();
+        assert_eq!(
+            images,
+            vec![
+                ("invocationImages[0]", true, None),
+                ("my-microservice", false, Some("linux/amd64")),
+            ]
+        );
+
+        let actions: Vec<_> = inspection
+            .actions
+            .iter()
+            .map(|a| (a.name.as_str(), a.builtin, a.description.as_deref()))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                ("install", true, None),
+                ("upgrade", true, None),
+                ("uninstall", true, Some("Remove everything")),
+                ("logs", false, None),
+            ]
+        );
+
+        let json: Value = serde_json::from_str(&inspection.to_json()).unwrap();
+        assert_eq!(json["parameters"][0]["type"], "integer");
+        assert_eq!(json["parameters"][1]["appliesTo"], json!(["install"]));
+    }
+
+    #[test]
+    fn test_explain() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let credential = |required: bool, apply_to: Option<&str>| Credential {
+            apply_to: apply_to.map(|a| vec![a.to_string()]),
+            description: None,
+            env: "TOKEN".parse().ok(),
+            path: None,
+            required: Some(required),
+        };
+        let credentials = bundle.credentials.as_mut().unwrap();
+        credentials.insert("admin".to_string(), credential(true, Some("uninstall")));
+        credentials.insert("token".to_string(), credential(true, None));
+        bundle.outputs = Some(
+            vec![(
+                "url".to_string(),
+                Output {
+                    apply_to: Some(vec!["install".to_string()]),
+                    definition: "url".to_string(),
+                    description: None,
+                    path: None,
+                },
+            )]
+            .into_iter()
+            .collect(),
+        );
+        bundle.actions = Some(
+            vec![(
+                "status".to_string(),
+                Action {
+                    description: Some("Show the status".to_string()),
+                    modifies: false,
+                    stateless: false,
+                },
+            )]
+            .into_iter()
+            .collect(),
+        );
+
+        let names = |explanation: &Explanation| -> (Vec<String>, Vec<String>) {
+            (
+                explanation
+                    .credentials
+                    .iter()
+                    .map(|c| c.name.clone())
+                    .collect(),
+                explanation.outputs.iter().map(|o| o.name.clone()).collect(),
+            )
+        };
+        let install = bundle.explain("install").unwrap();
+        assert!(install.action.builtin);
+        assert_eq!(
+            names(&install),
+            (
+                vec!["token".to_string(), "hostkey".to_string()],
+                vec!["url".to_string()]
+            )
+        );
+
+        let uninstall = bundle.explain("uninstall").unwrap();
+        assert_eq!(names(&uninstall).0, vec!["admin", "token", "hostkey"]);
+        assert!(uninstall.outputs.is_empty());
+
+        let status = bundle.explain("status").unwrap();
+        assert_eq!(
+            status.action.description.as_deref(),
+            Some("Show the status")
+        );
+        assert!(!status.action.modifies);
+        assert_eq!(status.parameters.len(), 1);
+
+        assert!(bundle.explain("dance").is_none());
+    }
+}
@@ -0,0 +1,267 @@
+use crate::cnab::{check_schema_version, Bundle, BundleParseError};
+use crate::error_code::{Coded, ErrorCode};
+use crate::profile::ValidationProfile;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Read;
+
+impl Bundle {
+    /// Deserialize a `Bundle` from a template, a bundle document whose strings
+    /// refer to `vars` as `${NAME}`, such as to stamp the registry or version of a
+    /// build into a shared bundle.
+    ///
+    /// Only string values are interpolated, not the names of fields or entries, and
+    /// `$${` stands for a literal `${`. A variable `vars` does not have is an error,
+    /// rather than left in or made empty.
+    ///
+    /// ```
+    /// use libcnab::Bundle;
+    /// use std::collections::BTreeMap;
+    ///
+    /// let template = r#"{
+    ///     "schemaVersion": "v1.0.0",
+    ///     "name": "helloworld",
+    ///     "version": "${VERSION}",
+    ///     "invocationImages": [{ "image": "${REGISTRY}/helloworld:${VERSION}" }]
+    /// }"#;
+    /// let mut vars = BTreeMap::new();
+    /// vars.insert("REGISTRY".to_string(), "registry.example.com".to_string());
+    /// vars.insert("VERSION".to_string(), "0.2.0".to_string());
+    /// let bundle = Bundle::from_template(template.as_bytes(), &vars).unwrap();
+    /// assert_eq!(bundle.version.to_string(), "0.2.0");
+    /// assert_eq!(bundle.invocation_images[0].image, "registry.example.com/helloworld:0.2.0");
+    /// ```
+    pub fn from_template<R: Read>(
+        reader: R,
+        vars: &BTreeMap<String, String>,
+    ) -> Result<Bundle, InterpolateError> {
+        Self::from_template_with(reader, vars, &ValidationProfile::Strict1_0)
+    }
+
+    /// Deserialize a `Bundle` from a template as `from_template` does, enforcing only
+    /// the parsing rules of `profile`.
+    pub fn from_template_with<R: Read>(
+        reader: R,
+        vars: &BTreeMap<String, String>,
+        profile: &ValidationProfile,
+    ) -> Result<Bundle, InterpolateError> {
+        #[cfg(feature = "compression")]
+        let reader = crate::compression::decompressed(reader).map_err(BundleParseError::from)?;
+        let mut document: Value =
+            serde_json::from_reader(reader).map_err(BundleParseError::from)?;
+        interpolate(&mut document, vars)?;
+        let bundle: Bundle = serde_json::from_value(document).map_err(BundleParseError::from)?;
+        check_schema_version(&bundle.schema_version, profile)?;
+        Ok(bundle)
+    }
+}
+
+/// Replace the `${NAME}` references to `vars` in the strings of `document`.
+pub fn interpolate(
+    document: &mut Value,
+    vars: &BTreeMap<String, String>,
+) -> Result<(), InterpolateError> {
+    walk(document, vars, &mut String::new())
+}
+
+fn walk(
+    value: &mut Value,
+    vars: &BTreeMap<String, String>,
+    path: &mut String,
+) -> Result<(), InterpolateError> {
+    let len = path.len();
+    match value {
+        Value::String(s) if s.contains('$') => {
+            *s = substitute(s, vars).map_err(|e| e.at(path))?;
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                path.push_str(&format!("[{}]", i));
+                walk(item, vars, path)?;
+                path.truncate(len);
+            }
+        }
+        Value::Object(fields) => {
+            for (name, field) in fields.iter_mut() {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(name);
+                walk(field, vars, path)?;
+                path.truncate(len);
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// `s` with its references replaced, or the error without a path
+fn substitute(s: &str, vars: &BTreeMap<String, String>) -> Result<String, InterpolateError> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(after) = after.strip_prefix("${") {
+            out.push_str("${");
+            rest = after;
+        } else if let Some(after) = after.strip_prefix('{') {
+            let end = after.find('}').ok_or_else(|| InterpolateError::Malformed {
+                path: String::new(),
+            })?;
+            let name = after[..end].trim();
+            if name.is_empty() {
+                return Err(InterpolateError::Malformed {
+                    path: String::new(),
+                });
+            }
+            let value = vars.get(name).ok_or_else(|| InterpolateError::Undefined {
+                variable: name.to_string(),
+                path: String::new(),
+            })?;
+            out.push_str(value);
+            rest = &after[end + 1..];
+        } else {
+            out.push('$');
+            rest = after;
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// InterpolateError describes why a bundle template could not be interpolated.
+#[derive(Debug)]
+pub enum InterpolateError {
+    /// The string at `path` refers to a variable that was not given
+    Undefined { variable: String, path: String },
+    /// The string at `path` has a `${` without a name and closing `}`
+    Malformed { path: String },
+    /// The template, or the bundle it makes, is not a valid bundle
+    Parse(BundleParseError),
+}
+
+impl InterpolateError {
+    fn at(self, at: &str) -> Self {
+        match self {
+            InterpolateError::Undefined { variable, .. } => InterpolateError::Undefined {
+                variable,
+                path: at.to_string(),
+            },
+            InterpolateError::Malformed { .. } => InterpolateError::Malformed {
+                path: at.to_string(),
+            },
+            error => error,
+        }
+    }
+}
+
+impl fmt::Display for InterpolateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpolateError::Undefined { variable, path } => {
+                write!(f, "undefined variable {} at {}", variable, path)
+            }
+            InterpolateError::Malformed { path } => {
+                write!(f, "malformed variable reference at {}", path)
+            }
+            InterpolateError::Parse(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for InterpolateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InterpolateError::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Coded for InterpolateError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            InterpolateError::Undefined { .. } => ErrorCode::InterpolateUndefinedVariable,
+            InterpolateError::Malformed { .. } => ErrorCode::InterpolateMalformed,
+            InterpolateError::Parse(e) => e.code(),
+        }
+    }
+}
+
+impl From<BundleParseError> for InterpolateError {
+    fn from(error: BundleParseError) -> Self {
+        InterpolateError::Parse(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_interpolate() {
+        let vars: BTreeMap<String, String> = vec![
+            ("REGISTRY".to_string(), "registry.example.com".to_string()),
+            ("TAG".to_string(), "1.2.3".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut document = json!({
+            "images": { "${TAG}": { "image": "${REGISTRY}/web:${ TAG }" } },
+            "price": "$5, $${literal}, ${TAG}$",
+            "port": 80,
+        });
+        interpolate(&mut document, &vars).unwrap();
+        assert_eq!(
+            document,
+            json!({
+                "images": { "${TAG}": { "image": "registry.example.com/web:1.2.3" } },
+                "price": "$5, ${literal}, 1.2.3$",
+                "port": 80,
+            })
+        );
+
+        let mut document = json!({ "invocationImages": [{ "image": "${REGISTRY}/${NAME}" }] });
+        let err = interpolate(&mut document, &vars).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "undefined variable NAME at invocationImages[0].image"
+        );
+
+        for malformed in &["${TAG", "${}"] {
+            let mut document = json!({ "description": malformed });
+            assert!(matches!(
+                interpolate(&mut document, &vars),
+                Err(InterpolateError::Malformed { path }) if path == "description"
+            ));
+        }
+    }
+
+    #[test]
+    fn test_template_profiles() {
+        let template = r#"{
+            "schemaVersion": "${SCHEMA}",
+            "name": "helloworld",
+            "version": "0.1.0",
+            "invocationImages": []
+        }"#;
+        let vars: BTreeMap<String, String> = vec![("SCHEMA".to_string(), "v1.1.0".to_string())]
+            .into_iter()
+            .collect();
+        assert!(matches!(
+            Bundle::from_template(template.as_bytes(), &vars),
+            Err(InterpolateError::Parse(
+                BundleParseError::UnsupportedSchemaVersion { .. }
+            ))
+        ));
+        let bundle =
+            Bundle::from_template_with(template.as_bytes(), &vars, &ValidationProfile::Permissive)
+                .unwrap();
+        assert_eq!(bundle.schema_version, "v1.1.0");
+    }
+}
@@ -0,0 +1,186 @@
+//! The standard `/cnab` filesystem layout of an invocation image.
+//!
+//! The constants give the absolute paths used inside a running invocation image. A
+//! `Layout` builds the same paths under a different root, which is how an execution
+//! engine plans mounts or how tests stage an image's filesystem in a temp directory.
+use crate::error_code::{Coded, ErrorCode};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The root of the CNAB filesystem
+pub const CNAB_DIR: &str = "/cnab";
+/// The directory holding the bundle's application code
+pub const APP_DIR: &str = "/cnab/app";
+/// The entrypoint executed by the runtime
+pub const RUN_PATH: &str = "/cnab/app/run";
+/// The directory from which the runtime collects outputs
+pub const OUTPUTS_DIR: &str = "/cnab/app/outputs";
+/// The directory holding parameters that are too large to pass in the environment
+pub const PARAMETERS_DIR: &str = "/cnab/parameters";
+/// The path at which the runtime mounts the bundle descriptor
+pub const BUNDLE_PATH: &str = "/cnab/bundle.json";
+/// The path at which the runtime mounts the relocation mapping, if any
+pub const RELOCATION_MAPPING_PATH: &str = "/cnab/app/relocation-mapping.json";
+
+/// Layout builds the paths of the CNAB filesystem relative to a root directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    root: PathBuf,
+}
+
+impl Default for Layout {
+    /// The layout rooted at `/`, as seen inside an invocation image.
+    fn default() -> Self {
+        Layout::new("/")
+    }
+}
+
+impl Layout {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Layout {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    fn path(&self, absolute: &str) -> PathBuf {
+        self.root.join(absolute.trim_start_matches('/'))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn cnab_dir(&self) -> PathBuf {
+        self.path(CNAB_DIR)
+    }
+
+    pub fn app_dir(&self) -> PathBuf {
+        self.path(APP_DIR)
+    }
+
+    pub fn run(&self) -> PathBuf {
+        self.path(RUN_PATH)
+    }
+
+    pub fn outputs_dir(&self) -> PathBuf {
+        self.path(OUTPUTS_DIR)
+    }
+
+    /// The file an output with the given name is written to
+    pub fn output(&self, name: &str) -> PathBuf {
+        self.outputs_dir().join(name)
+    }
+
+    pub fn parameters_dir(&self) -> PathBuf {
+        self.path(PARAMETERS_DIR)
+    }
+
+    pub fn bundle_json(&self) -> PathBuf {
+        self.path(BUNDLE_PATH)
+    }
+
+    pub fn relocation_mapping(&self) -> PathBuf {
+        self.path(RELOCATION_MAPPING_PATH)
+    }
+
+    /// Map an absolute path inside the image (such as a parameter destination) to a
+    /// path under this layout's root.
+    pub fn resolve<P: AsRef<Path>>(&self, image_path: P) -> PathBuf {
+        let relative = image_path
+            .as_ref()
+            .strip_prefix("/")
+            .unwrap_or_else(|_| image_path.as_ref());
+        self.root.join(relative)
+    }
+
+    /// Create the directories of the layout.
+    pub fn create(&self) -> std::io::Result<()> {
+        std::fs::create_dir_all(self.app_dir())?;
+        std::fs::create_dir_all(self.outputs_dir())
+    }
+
+    /// Check that the layout is complete: the app directory and outputs directory exist,
+    /// the run entrypoint is present (and executable on Unix), and a bundle descriptor is
+    /// mounted.
+    pub fn verify(&self) -> Result<(), LayoutError> {
+        let mut problems = Vec::new();
+        for dir in &[self.app_dir(), self.outputs_dir()] {
+            if !dir.is_dir() {
+                problems.push(format!("{} is not a directory", dir.display()));
+            }
+        }
+        match std::fs::metadata(self.run()) {
+            Err(_) => problems.push(format!("{} does not exist", self.run().display())),
+            Ok(_meta) => {
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    if _meta.permissions().mode() & 0o111 == 0 {
+                        problems.push(format!("{} is not executable", self.run().display()));
+                    }
+                }
+            }
+        }
+        if !self.bundle_json().is_file() {
+            problems.push(format!("{} does not exist", self.bundle_json().display()));
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(LayoutError(problems))
+        }
+    }
+}
+
+/// LayoutError lists the problems found when verifying a layout.
+#[derive(Debug)]
+pub struct LayoutError(pub Vec<String>);
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid CNAB layout: {}", self.0.join("; "))
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+impl Coded for LayoutError {
+    fn code(&self) -> ErrorCode {
+        ErrorCode::LayoutInvalid
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        let root = tempfile::tempdir().unwrap();
+        let layout = Layout::new(root.path());
+        assert_eq!(
+            layout.output("port"),
+            root.path().join("cnab/app/outputs/port")
+        );
+        assert_eq!(
+            layout.resolve("/etc/config"),
+            root.path().join("etc/config")
+        );
+        assert_eq!(Layout::default().bundle_json(), PathBuf::from(BUNDLE_PATH));
+
+        layout.create().unwrap();
+        let err = layout
+            .verify()
+            .expect_err("run and bundle.json are missing");
+        assert_eq!(err.0.len(), 2);
+
+        std::fs::write(layout.run(), "#!/bin/sh\n").unwrap();
+        std::fs::write(layout.bundle_json(), "{}").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(layout.run(), std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        layout.verify().expect("layout is complete");
+    }
+}
@@ -3,14 +3,133 @@
 
 mod cnab;
 pub use crate::cnab::*;
+mod custom;
+pub use crate::custom::*;
+mod borrowed;
+pub use crate::borrowed::*;
+mod diff;
+pub use crate::diff::*;
 mod claim;
 pub use crate::claim::*;
+mod encoding;
+pub use crate::encoding::*;
+mod env_var;
+pub use crate::env_var::*;
+mod error_code;
+pub use crate::error_code::*;
+mod cancel;
+pub use crate::cancel::*;
+mod hash;
+mod reference;
+pub use crate::reference::*;
+mod relocation;
+pub use crate::relocation::*;
+#[cfg(feature = "ureq")]
+mod proxy;
+#[cfg(feature = "drivers")]
+mod schema;
+#[cfg(feature = "ureq")]
+pub use crate::proxy::*;
+#[cfg(feature = "http")]
+mod fetch;
+#[cfg(feature = "http")]
+pub use crate::fetch::*;
+#[cfg(feature = "tokio")]
+mod asynchronous;
+#[cfg(feature = "tokio")]
+pub use crate::asynchronous::BoxFuture;
+#[cfg(feature = "compression")]
+mod compression;
+#[cfg(feature = "compression")]
+pub use crate::compression::ARCHIVE_BUNDLE_PATH;
+#[cfg(feature = "cbor")]
+mod cbor;
+#[cfg(feature = "cbor")]
+pub use crate::cbor::*;
+#[cfg(feature = "toml")]
+mod toml;
+#[cfg(feature = "yaml")]
+mod yaml;
 
 // Re-export Ulid for convenience
 pub use ulid::Ulid;
 
+#[cfg(feature = "derive")]
+pub use libcnab_derive::{cnab_action, cnab_main};
+
+#[cfg(all(test, unix))]
+mod fixture;
 #[cfg(test)]
 mod tests;
 
-mod credentialset;
-pub use crate::credentialset::*;
\ No newline at end of file
+pub mod credentialset;
+pub use crate::credentialset::{CredentialSet, CredentialSource, ResolveError, VaultSource};
+
+#[cfg(feature = "claims")]
+pub mod claimstore;
+#[cfg(feature = "claims")]
+pub use crate::claimstore::ClaimStore;
+
+mod parameterset;
+pub use crate::parameterset::*;
+mod dependencies;
+pub use crate::dependencies::*;
+mod docker_extension;
+pub use crate::docker_extension::*;
+mod extensions;
+pub use crate::extensions::*;
+mod interpolate;
+pub use crate::interpolate::*;
+mod overlay;
+pub use crate::overlay::*;
+mod parameter_sources;
+pub use crate::parameter_sources::*;
+mod platform;
+pub use crate::platform::*;
+mod profile;
+pub use crate::profile::*;
+mod stamp;
+pub use crate::stamp::*;
+
+#[cfg(feature = "signing")]
+pub mod attestation;
+pub mod catalog;
+#[cfg(feature = "compat")]
+pub mod compat;
+#[cfg(feature = "drivers")]
+pub mod driver;
+#[cfg(feature = "drivers")]
+pub mod engine;
+pub mod events;
+#[cfg(feature = "thick")]
+pub mod export;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "thick")]
+pub mod import;
+pub mod inspect;
+pub mod layout;
+pub mod lint;
+pub mod lock;
+pub mod metrics;
+#[cfg(feature = "claims")]
+pub mod reconcile;
+#[cfg(feature = "registry")]
+pub mod registry;
+#[cfg(feature = "runtime")]
+pub mod runtime;
+pub mod scaffold;
+pub mod secrets;
+#[cfg(feature = "signing")]
+pub mod signing;
+#[cfg(feature = "sigstore")]
+pub mod sigstore;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "signing")]
+pub mod trust;
+pub mod upgrade;
+#[cfg(feature = "vault")]
+pub mod vault;
+#[cfg(feature = "web")]
+pub mod web;
@@ -0,0 +1,632 @@
+//! Lint bundles: find what is valid, but likely a mistake or a bad idea.
+//!
+//! A `Linter` runs rules over a bundle, each of which reports findings at a
+//! severity. The built-in rules are
+//!
+//! - `image-digests`: an image has no `contentDigest`, and is not pinned by digest
+//! - `parameter-descriptions`: a parameter has no description
+//! - `maintainers`: the bundle names no maintainers
+//! - `permissive-enums`: an `enum` of a definition allows an empty value, allows a
+//!   value twice, or allows values of other types than the definition's
+//! - `reserved-actions`: a custom action is named as a built-in action, or in the
+//!   `io.cnab` namespace without being one of those the specification defines
+//! - `duplicate-keywords`: a keyword is repeated, ignoring case and surrounding
+//!   whitespace, which `Bundle::normalize_keywords` fixes
+//! - `platforms`: the platform of an image is invalid, or names an os or arch that
+//!   is not one of `KNOWN_OS` or `KNOWN_ARCH`
+//!
+//! Tools add their own rules by implementing `LintRule`, and tune the severity of
+//! any rule, or turn it off, with `Linter::severity` and `Linter::allow`.
+//!
+//! ```
+//! use libcnab::lint::{Linter, Severity};
+//! use libcnab::Bundle;
+//!
+//! let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+//! let report = Linter::default()
+//!     .severity("image-digests", Severity::Error)
+//!     .allow("parameter-descriptions")
+//!     .lint(&bundle);
+//! assert!(!report.passed());
+//! assert_eq!(report.findings[0].rule, "image-digests");
+//! ```
+use crate::cnab::{is_oci_image, Bundle, Platform, BUILTIN_ACTIONS};
+use crate::platform::{normalize, KNOWN_ARCH, KNOWN_OS};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The actions in the `io.cnab` namespace the specification defines
+pub const WELL_KNOWN_ACTIONS: &[&str] = &[
+    "io.cnab.dry-run",
+    "io.cnab.help",
+    "io.cnab.log",
+    "io.cnab.status",
+    "io.cnab.status+json",
+];
+
+/// Severity is how much a finding matters, from least to most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// Lint is something a rule found in a bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    /// Where in the bundle, such as `parameters.port`, or empty for the bundle itself
+    pub path: String,
+    pub message: String,
+}
+
+impl Lint {
+    pub fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Lint {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// LintRule checks a bundle for one kind of problem.
+pub trait LintRule: Send + Sync {
+    /// The name the rule is configured by, such as `image-digests`
+    fn name(&self) -> &str;
+
+    /// The severity of the rule's findings, unless the linter is told otherwise
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, bundle: &Bundle) -> Vec<Lint>;
+}
+
+/// Finding is a lint, with the rule that found it and its severity.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub rule: String,
+    pub severity: Severity,
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}[{}]: {}", self.severity, self.rule, self.message)
+        } else {
+            write!(
+                f,
+                "{}[{}] {}: {}",
+                self.severity, self.rule, self.path, self.message
+            )
+        }
+    }
+}
+
+/// ValidationReport is what linting a bundle found, most severe first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationReport {
+    pub findings: Vec<Finding>,
+    /// The severity from which a finding fails the bundle
+    pub threshold: Severity,
+}
+
+impl ValidationReport {
+    /// The findings at or above the threshold.
+    pub fn failures(&self) -> impl Iterator<Item = &Finding> {
+        self.findings
+            .iter()
+            .filter(move |f| f.severity >= self.threshold)
+    }
+
+    /// Whether no finding is at or above the threshold.
+    pub fn passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for finding in &self.findings {
+            writeln!(f, "{}", finding)?;
+        }
+        Ok(())
+    }
+}
+
+/// Linter is a set of rules, with the severity each is reported at.
+pub struct Linter {
+    rules: Vec<Box<dyn LintRule>>,
+    /// Severities that override those of the rules, or `None` for rules turned off
+    severities: BTreeMap<String, Option<Severity>>,
+    threshold: Severity,
+}
+
+impl Linter {
+    /// A linter with no rules, failing bundles on errors.
+    pub fn empty() -> Self {
+        Linter {
+            rules: Vec::new(),
+            severities: BTreeMap::new(),
+            threshold: Severity::Error,
+        }
+    }
+
+    /// Add `rule`.
+    pub fn rule<R: LintRule + 'static>(mut self, rule: R) -> Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// Report the findings of the rule `name` at `severity`.
+    pub fn severity(mut self, name: &str, severity: Severity) -> Self {
+        self.severities.insert(name.to_string(), Some(severity));
+        self
+    }
+
+    /// Turn the rule `name` off.
+    pub fn allow(mut self, name: &str) -> Self {
+        self.severities.insert(name.to_string(), None);
+        self
+    }
+
+    /// Fail bundles with findings at or above `threshold`, rather than only errors.
+    pub fn threshold(mut self, threshold: Severity) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Run every rule over `bundle`.
+    pub fn lint(&self, bundle: &Bundle) -> ValidationReport {
+        let mut findings = Vec::new();
+        for rule in &self.rules {
+            let severity = match self.severities.get(rule.name()) {
+                Some(Some(severity)) => *severity,
+                Some(None) => continue,
+                None => rule.severity(),
+            };
+            findings.extend(rule.check(bundle).into_iter().map(|lint| Finding {
+                rule: rule.name().to_string(),
+                severity,
+                path: lint.path,
+                message: lint.message,
+            }));
+        }
+        // Stable, so each rule's findings stay in the order it found them.
+        findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+        ValidationReport {
+            findings,
+            threshold: self.threshold,
+        }
+    }
+}
+
+impl Default for Linter {
+    /// A linter with the built-in rules, failing bundles on errors.
+    fn default() -> Self {
+        Linter::empty()
+            .rule(ImageDigests)
+            .rule(ParameterDescriptions)
+            .rule(Maintainers)
+            .rule(PermissiveEnums)
+            .rule(ReservedActions)
+            .rule(DuplicateKeywords)
+            .rule(Platforms)
+    }
+}
+
+impl fmt::Debug for Linter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Linter")
+            .field(
+                "rules",
+                &self.rules.iter().map(|r| r.name()).collect::<Vec<_>>(),
+            )
+            .field("severities", &self.severities)
+            .field("threshold", &self.threshold)
+            .finish()
+    }
+}
+
+/// The `image-digests` rule
+#[derive(Debug, Clone, Copy)]
+pub struct ImageDigests;
+
+impl LintRule for ImageDigests {
+    fn name(&self) -> &str {
+        "image-digests"
+    }
+
+    fn check(&self, bundle: &Bundle) -> Vec<Lint> {
+        let invocation_images = bundle
+            .invocation_images
+            .iter()
+            .enumerate()
+            .map(|(i, image)| {
+                (
+                    format!("invocationImages[{}]", i),
+                    &image.image,
+                    &image.image_type,
+                    &image.content_digest,
+                )
+            });
+        let images = bundle.images.iter().flatten().map(|(name, image)| {
+            (
+                format!("images.{}", name),
+                &image.image,
+                &image.image_type,
+                &image.content_digest,
+            )
+        });
+        invocation_images
+            .chain(images)
+            .filter(|(_, image, image_type, digest)| {
+                digest.is_none() && !(is_oci_image(image_type.as_deref()) && image.contains('@'))
+            })
+            .map(|(path, image, _, _)| {
+                Lint::new(path, format!("{} has no digest to verify it by", image))
+            })
+            .collect()
+    }
+}
+
+/// The `parameter-descriptions` rule
+#[derive(Debug, Clone, Copy)]
+pub struct ParameterDescriptions;
+
+impl LintRule for ParameterDescriptions {
+    fn name(&self) -> &str {
+        "parameter-descriptions"
+    }
+
+    fn check(&self, bundle: &Bundle) -> Vec<Lint> {
+        bundle
+            .parameters
+            .iter()
+            .flatten()
+            .filter(|(_, p)| p.description.as_deref().is_none_or(|d| d.trim().is_empty()))
+            .map(|(name, _)| Lint::new(format!("parameters.{}", name), "has no description"))
+            .collect()
+    }
+}
+
+/// The `maintainers` rule
+#[derive(Debug, Clone, Copy)]
+pub struct Maintainers;
+
+impl LintRule for Maintainers {
+    fn name(&self) -> &str {
+        "maintainers"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Info
+    }
+
+    fn check(&self, bundle: &Bundle) -> Vec<Lint> {
+        match &bundle.maintainers {
+            Some(maintainers) if !maintainers.is_empty() => Vec::new(),
+            _ => vec![Lint::new("maintainers", "the bundle names no maintainers")],
+        }
+    }
+}
+
+/// The `permissive-enums` rule
+#[derive(Debug, Clone, Copy)]
+pub struct PermissiveEnums;
+
+impl LintRule for PermissiveEnums {
+    fn name(&self) -> &str {
+        "permissive-enums"
+    }
+
+    fn check(&self, bundle: &Bundle) -> Vec<Lint> {
+        let mut lints = Vec::new();
+        for (name, definition) in bundle.definitions.iter().flatten() {
+            let values = match definition.get("enum").and_then(Value::as_array) {
+                Some(values) => values,
+                None => continue,
+            };
+            let path = format!("definitions.{}.enum", name);
+            if values
+                .iter()
+                .any(|v| v.is_null() || v.as_str().is_some_and(|s| s.trim().is_empty()))
+            {
+                lints.push(Lint::new(&path, "allows an empty value"));
+            }
+            if values
+                .iter()
+                .enumerate()
+                .any(|(i, v)| values[..i].contains(v))
+            {
+                lints.push(Lint::new(&path, "allows a value twice"));
+            }
+            if let Some(kind) = definition.get("type").and_then(Value::as_str) {
+                if let Some(other) = values.iter().find(|v| !v.is_null() && !is_of_type(v, kind)) {
+                    lints.push(Lint::new(
+                        &path,
+                        format!("allows {}, which is not of type {}", other, kind),
+                    ));
+                }
+            }
+        }
+        lints
+    }
+}
+
+/// Whether `value` is of the JSON Schema type `kind`.
+fn is_of_type(value: &Value, kind: &str) -> bool {
+    match kind {
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+/// The `reserved-actions` rule
+#[derive(Debug, Clone, Copy)]
+pub struct ReservedActions;
+
+impl LintRule for ReservedActions {
+    fn name(&self) -> &str {
+        "reserved-actions"
+    }
+
+    fn check(&self, bundle: &Bundle) -> Vec<Lint> {
+        bundle
+            .actions
+            .iter()
+            .flatten()
+            .filter_map(|(name, _)| {
+                let path = format!("actions.{}", name);
+                if BUILTIN_ACTIONS.contains(&name.as_str()) {
+                    Some(Lint::new(path, "redefines a built-in action"))
+                } else if name.starts_with("io.cnab.")
+                    && !WELL_KNOWN_ACTIONS.contains(&name.as_str())
+                {
+                    Some(Lint::new(
+                        path,
+                        "is in the io.cnab namespace, which is reserved for the specification",
+                    ))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// The `duplicate-keywords` rule
+#[derive(Debug, Clone, Copy)]
+pub struct DuplicateKeywords;
+
+impl LintRule for DuplicateKeywords {
+    fn name(&self) -> &str {
+        "duplicate-keywords"
+    }
+
+    fn check(&self, bundle: &Bundle) -> Vec<Lint> {
+        let keywords = bundle.keywords.as_deref().unwrap_or_default();
+        let normalized: Vec<String> = keywords.iter().map(|k| k.trim().to_lowercase()).collect();
+        keywords
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| normalized[..*i].contains(&normalized[*i]))
+            .map(|(i, keyword)| {
+                Lint::new(
+                    format!("keywords[{}]", i),
+                    format!("repeats the keyword {:?}", keyword.trim()),
+                )
+            })
+            .collect()
+    }
+}
+
+/// The `platforms` rule
+#[derive(Debug, Clone, Copy)]
+pub struct Platforms;
+
+impl LintRule for Platforms {
+    fn name(&self) -> &str {
+        "platforms"
+    }
+
+    fn check(&self, bundle: &Bundle) -> Vec<Lint> {
+        let mut lints = Vec::new();
+        for (i, image) in bundle.invocation_images.iter().enumerate() {
+            let path = format!("invocationImages[{}]", i);
+            match image.platform() {
+                Some(Ok(platform)) => lints.extend(platform_lints(&path, &platform)),
+                Some(Err(e)) => lints.push(Lint::new(path, e.to_string())),
+                None => {}
+            }
+        }
+        for (name, image) in bundle.images.iter().flatten() {
+            if let Some(platform) = &image.platform {
+                lints.extend(platform_lints(
+                    &format!("images.{}.platform", name),
+                    platform,
+                ));
+            }
+        }
+        lints
+    }
+}
+
+/// The os and arch of `platform` that are not known, pointing out those that are
+/// known by another name.
+fn platform_lints(path: &str, platform: &Platform) -> Vec<Lint> {
+    let parts = [
+        ("os", &platform.os, KNOWN_OS),
+        ("arch", &platform.arch, KNOWN_ARCH),
+    ];
+    let mut lints = Vec::new();
+    for (part, name, known) in parts {
+        let name = match name.as_deref() {
+            Some("*") | None => continue,
+            Some(name) => name,
+        };
+        if known.contains(&name) {
+            continue;
+        }
+        let message = match normalize(name) {
+            alias if alias != name && known.contains(&alias) => {
+                format!("{} {} is called {} in bundles", part, name, alias)
+            }
+            _ => format!("{} {} is not a known {}", part, name, part),
+        };
+        lints.push(Lint::new(path, message));
+    }
+    lints
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cnab::Action;
+
+    struct NoLatest;
+
+    impl LintRule for NoLatest {
+        fn name(&self) -> &str {
+            "no-latest"
+        }
+
+        fn severity(&self) -> Severity {
+            Severity::Error
+        }
+
+        fn check(&self, bundle: &Bundle) -> Vec<Lint> {
+            bundle
+                .invocation_images
+                .iter()
+                .filter(|i| i.image.ends_with(":latest"))
+                .map(|i| Lint::new("invocationImages", format!("{} is latest", i.image)))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_lint() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.maintainers = None;
+        bundle.keywords = Some(vec!["demo".to_string(), " Demo".to_string()]);
+        bundle
+            .images
+            .as_mut()
+            .unwrap()
+            .get_mut("my-microservice")
+            .unwrap()
+            .platform = Some(Platform::new("linux", "x86_64"));
+        bundle.invocation_images[0].image = "example/installer:latest".to_string();
+        bundle.invocation_images[0].content_digest = None;
+        bundle.definitions = Some(
+            vec![(
+                "color".to_string(),
+                serde_json::json!({ "type": "string", "enum": ["red", "", "red", 3] }),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        let action = Action {
+            description: None,
+            modifies: true,
+            stateless: false,
+        };
+        let actions = bundle.actions.get_or_insert_with(Default::default);
+        actions.insert("install".to_string(), action.clone());
+        actions.insert("io.cnab.migrate".to_string(), action.clone());
+        actions.insert("io.cnab.status".to_string(), action);
+
+        let report = Linter::default().rule(NoLatest).lint(&bundle);
+        let found: Vec<_> = report
+            .findings
+            .iter()
+            .map(|f| (f.rule.as_str(), f.severity, f.path.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("no-latest", Severity::Error, "invocationImages"),
+                ("image-digests", Severity::Warning, "invocationImages[0]"),
+                ("image-digests", Severity::Warning, "images.my-microservice"),
+                (
+                    "parameter-descriptions",
+                    Severity::Warning,
+                    "parameters.backend_port"
+                ),
+                (
+                    "permissive-enums",
+                    Severity::Warning,
+                    "definitions.color.enum"
+                ),
+                (
+                    "permissive-enums",
+                    Severity::Warning,
+                    "definitions.color.enum"
+                ),
+                (
+                    "permissive-enums",
+                    Severity::Warning,
+                    "definitions.color.enum"
+                ),
+                ("reserved-actions", Severity::Warning, "actions.install"),
+                (
+                    "reserved-actions",
+                    Severity::Warning,
+                    "actions.io.cnab.migrate"
+                ),
+                ("duplicate-keywords", Severity::Warning, "keywords[1]"),
+                (
+                    "platforms",
+                    Severity::Warning,
+                    "images.my-microservice.platform"
+                ),
+                ("maintainers", Severity::Info, "maintainers"),
+            ]
+        );
+        assert!(!report.passed());
+        assert!(report
+            .findings
+            .iter()
+            .any(|f| f.message == "arch x86_64 is called amd64 in bundles"));
+        assert_eq!(
+            report.findings[6].message,
+            "allows 3, which is not of type string"
+        );
+        assert_eq!(
+            report.findings[0].to_string(),
+            "error[no-latest] invocationImages: example/installer:latest is latest"
+        );
+
+        let report = Linter::default()
+            .rule(NoLatest)
+            .allow("no-latest")
+            .severity("maintainers", Severity::Warning)
+            .threshold(Severity::Warning)
+            .lint(&bundle);
+        assert!(report.findings.iter().all(|f| f.rule != "no-latest"));
+        assert!(!report.passed());
+        assert_eq!(report.failures().count(), report.findings.len());
+    }
+}
@@ -0,0 +1,537 @@
+//! Lockfiles that pin the images and dependency bundles of a bundle to digests.
+//!
+//! `generate` resolves every OCI image a bundle references by tag, and every bundle
+//! it depends on, to what they are now, and records them in a `Lockfile`. Installs
+//! run with the lockfile's `relocation` pull what was locked, whatever the tags
+//! point at by then, without the bundle itself being changed. `verify` tells
+//! whether the bundle still matches its lockfile, such as in CI before a release.
+//!
+//! ```
+//! use libcnab::lock::{self, LockDrift};
+//! use libcnab::Bundle;
+//!
+//! let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+//! let lockfile: lock::Lockfile = serde_json::from_value(serde_json::json!({
+//!     "schemaVersion": "v1",
+//!     "bundle": bundle.name,
+//!     "version": bundle.version.to_string(),
+//!     "bundleHash": bundle.stable_hash(),
+//!     "images": { "technosophos/helloworld:0.1.0": "sha256:0123" }
+//! }))
+//! .unwrap();
+//! let drift = lock::verify(&bundle, &lockfile).unwrap();
+//! assert_eq!(drift, vec![LockDrift::Unlocked("technosophos/microservice:1.2.3".to_string())]);
+//! ```
+use crate::cnab::{is_oci_image, Bundle};
+use crate::dependencies::{
+    BundleSource, DependencyError, DependencyResolver, InstallPlan, InstallStep,
+};
+use crate::error_code::{Coded, ErrorCode};
+use crate::reference::{BundleReference, InvalidReference};
+use crate::relocation::RelocationMap;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// The `schemaVersion` of the lockfiles this module writes
+pub const LOCKFILE_SCHEMA_VERSION: &str = "v1";
+
+/// Resolver finds the bundles a bundle depends on and the digests of the images its
+/// tags point at, such as a registry `Client`.
+pub trait Resolver: BundleSource {
+    /// The digest of the manifest `image` points at now.
+    fn image_digest(&self, image: &str) -> Result<String, LockError>;
+}
+
+/// Images are resolved through the client's mirrors, as its pulls are.
+#[cfg(feature = "registry")]
+impl Resolver for crate::registry::Client {
+    fn image_digest(&self, image: &str) -> Result<String, LockError> {
+        self.resolve_digest(image).map_err(|e| LockError::Resolve {
+            reference: image.to_string(),
+            message: e.to_string(),
+        })
+    }
+}
+
+/// Lockfile is what a bundle's images and dependencies were resolved to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lockfile {
+    pub schema_version: String,
+    /// The name of the bundle
+    pub bundle: String,
+    pub version: String,
+    /// The `stable_hash` of the bundle the lockfile was generated for
+    pub bundle_hash: String,
+    /// The digest of each OCI image of the bundle, keyed by its reference
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub images: BTreeMap<String, String>,
+    /// The bundles the bundle depends on, keyed by dependency name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, LockedDependency>,
+}
+
+/// LockedDependency is a dependency bundle as it was resolved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LockedDependency {
+    /// Where the bundle was fetched from, by digest when its source has them
+    pub reference: String,
+    pub version: String,
+    /// The digest of each OCI image of the bundle, keyed by its reference
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub images: BTreeMap<String, String>,
+    /// The bundles this one depends on, keyed by dependency name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, LockedDependency>,
+}
+
+impl Lockfile {
+    /// Read a lockfile written as JSON.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// The relocation map that pulls the locked digest of every image of the bundle
+    /// referenced by tag.
+    pub fn relocation(&self) -> RelocationMap {
+        relocation(&self.images)
+    }
+}
+
+impl LockedDependency {
+    /// The relocation map that pulls the locked digest of every image of the
+    /// dependency referenced by tag, as `Lockfile::relocation`.
+    pub fn relocation(&self) -> RelocationMap {
+        relocation(&self.images)
+    }
+}
+
+fn relocation(images: &BTreeMap<String, String>) -> RelocationMap {
+    let mut map = RelocationMap::new();
+    for (image, digest) in images.iter().filter(|(image, _)| !image.contains('@')) {
+        map.insert(image, &format!("{}@{}", image, digest));
+    }
+    map
+}
+
+/// LockDrift is a way a bundle no longer matches its lockfile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockDrift {
+    /// The bundle changed since the lockfile was generated, from the `locked`
+    /// `stable_hash` to the `actual` one
+    Bundle { locked: String, actual: String },
+    /// An OCI image of the bundle is not in the lockfile
+    Unlocked(String),
+    /// An image of the lockfile is not in the bundle anymore
+    Removed(String),
+    /// The bundle pins an image, by its reference or `contentDigest`, to another
+    /// digest than the lockfile
+    Digest {
+        image: String,
+        locked: String,
+        pinned: String,
+    },
+    /// A dependency of the bundle is not in the lockfile
+    UnlockedDependency(String),
+    /// A dependency of the lockfile is not a dependency of the bundle anymore
+    RemovedDependency(String),
+    /// The locked bundle of a dependency is not of the repository it names now, or
+    /// not of a version it accepts
+    DependencyChanged {
+        name: String,
+        locked: String,
+        declared: String,
+    },
+}
+
+impl fmt::Display for LockDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockDrift::Bundle { locked, actual } => {
+                write!(f, "bundle changed from {} to {}", locked, actual)
+            }
+            LockDrift::Unlocked(image) => write!(f, "image {} is not locked", image),
+            LockDrift::Removed(image) => write!(f, "locked image {} is not in the bundle", image),
+            LockDrift::Digest {
+                image,
+                locked,
+                pinned,
+            } => write!(
+                f,
+                "image {} is locked to {} but pinned to {}",
+                image, locked, pinned
+            ),
+            LockDrift::UnlockedDependency(name) => write!(f, "dependency {} is not locked", name),
+            LockDrift::RemovedDependency(name) => {
+                write!(f, "locked dependency {} is not in the bundle", name)
+            }
+            LockDrift::DependencyChanged {
+                name,
+                locked,
+                declared,
+            } => write!(
+                f,
+                "dependency {} is locked to {}, which {} does not accept",
+                name, locked, declared
+            ),
+        }
+    }
+}
+
+/// LockError is why a lockfile could not be generated or checked.
+#[derive(Debug)]
+pub enum LockError {
+    Dependency(DependencyError),
+    InvalidReference(InvalidReference),
+    /// The dependencies extension of the bundle is invalid
+    InvalidDependencies(serde_json::Error),
+    /// The resolver could not find the digest of the image `reference`
+    Resolve {
+        reference: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::Dependency(e) => write!(f, "{}", e),
+            LockError::InvalidReference(e) => write!(f, "{}", e),
+            LockError::InvalidDependencies(e) => write!(f, "invalid dependencies: {}", e),
+            LockError::Resolve { reference, message } => {
+                write!(f, "cannot resolve {}: {}", reference, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LockError {}
+
+impl Coded for LockError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            LockError::Dependency(e) => e.code(),
+            LockError::InvalidReference(e) => e.code(),
+            LockError::InvalidDependencies(_) => ErrorCode::DependencyInvalid,
+            LockError::Resolve { .. } => ErrorCode::LockResolveFailed,
+        }
+    }
+}
+
+impl From<DependencyError> for LockError {
+    fn from(error: DependencyError) -> Self {
+        LockError::Dependency(error)
+    }
+}
+
+impl From<InvalidReference> for LockError {
+    fn from(error: InvalidReference) -> Self {
+        LockError::InvalidReference(error)
+    }
+}
+
+/// Generate the lockfile of `bundle`: the digest of each of its OCI images, and the
+/// bundles its dependencies resolve to, with their images and dependencies in turn.
+///
+/// An image pinned by digest is locked to it. One referenced by tag is locked to
+/// the digest `resolver` has for it, not its `contentDigest`, since that is what
+/// the tag would pull.
+pub fn generate<R: Resolver>(bundle: &Bundle, resolver: &R) -> Result<Lockfile, LockError> {
+    let plan = DependencyResolver::new(resolver).resolve(bundle, &bundle.name)?;
+    Ok(Lockfile {
+        schema_version: LOCKFILE_SCHEMA_VERSION.to_string(),
+        bundle: bundle.name.clone(),
+        version: bundle.version.to_string(),
+        bundle_hash: bundle.stable_hash(),
+        images: lock_images(bundle, resolver)?,
+        dependencies: lock_dependencies(&plan, plan.root(), resolver)?,
+    })
+}
+
+fn lock_images<R: Resolver>(
+    bundle: &Bundle,
+    resolver: &R,
+) -> Result<BTreeMap<String, String>, LockError> {
+    let mut images = BTreeMap::new();
+    for (image, _) in oci_images(bundle) {
+        let digest = match BundleReference::parse(&image)?.digest {
+            Some(digest) => digest,
+            None => resolver.image_digest(&image)?,
+        };
+        images.insert(image, digest);
+    }
+    Ok(images)
+}
+
+fn lock_dependencies<R: Resolver>(
+    plan: &InstallPlan,
+    step: &InstallStep,
+    resolver: &R,
+) -> Result<BTreeMap<String, LockedDependency>, LockError> {
+    let mut dependencies = BTreeMap::new();
+    for (name, installation) in &step.dependencies {
+        let dependency = plan
+            .steps
+            .iter()
+            .find(|s| &s.installation == installation)
+            .expect("plans have the installations their steps depend on");
+        dependencies.insert(
+            name.clone(),
+            LockedDependency {
+                reference: dependency.reference.clone().unwrap_or_default(),
+                version: dependency.bundle.version.to_string(),
+                images: lock_images(&dependency.bundle, resolver)?,
+                dependencies: lock_dependencies(plan, dependency, resolver)?,
+            },
+        );
+    }
+    Ok(dependencies)
+}
+
+/// The OCI images of `bundle`, with the digest they are pinned to, from their
+/// reference or their `contentDigest`.
+fn oci_images(bundle: &Bundle) -> Vec<(String, Option<String>)> {
+    let invocation_images = bundle
+        .invocation_images
+        .iter()
+        .map(|i| (&i.image, &i.image_type, &i.content_digest));
+    let images = bundle
+        .images
+        .iter()
+        .flat_map(|images| images.values())
+        .map(|i| (&i.image, &i.image_type, &i.content_digest));
+    invocation_images
+        .chain(images)
+        .filter(|(_, image_type, _)| is_oci_image(image_type.as_deref()))
+        .map(|(image, _, content_digest)| (image.clone(), content_digest.clone()))
+        .collect()
+}
+
+/// Compare `bundle` with `lockfile`, the drift in the order of the images and then
+/// the dependencies. No drift means installs with the lockfile pull what was locked.
+///
+/// Only the bundle's own dependencies are checked, since theirs are in bundles it
+/// only has the references of.
+pub fn verify(bundle: &Bundle, lockfile: &Lockfile) -> Result<Vec<LockDrift>, LockError> {
+    let mut drift = Vec::new();
+    let actual = bundle.stable_hash();
+    if actual != lockfile.bundle_hash {
+        drift.push(LockDrift::Bundle {
+            locked: lockfile.bundle_hash.clone(),
+            actual,
+        });
+    }
+
+    let images = oci_images(bundle);
+    for (image, content_digest) in &images {
+        let pinned = BundleReference::parse(image)?
+            .digest
+            .or_else(|| content_digest.clone());
+        match (lockfile.images.get(image), pinned) {
+            (None, _) => drift.push(LockDrift::Unlocked(image.clone())),
+            (Some(locked), Some(pinned)) if *locked != pinned => drift.push(LockDrift::Digest {
+                image: image.clone(),
+                locked: locked.clone(),
+                pinned,
+            }),
+            _ => {}
+        }
+    }
+    for image in lockfile.images.keys() {
+        if !images.iter().any(|(i, _)| i == image) {
+            drift.push(LockDrift::Removed(image.clone()));
+        }
+    }
+
+    let requires = bundle
+        .dependencies()
+        .map_err(LockError::InvalidDependencies)?
+        .map(|d| d.requires)
+        .unwrap_or_default();
+    for (name, dependency) in &requires {
+        let locked = match lockfile.dependencies.get(name) {
+            Some(locked) => locked,
+            None => {
+                drift.push(LockDrift::UnlockedDependency(name.clone()));
+                continue;
+            }
+        };
+        let same_repository = BundleReference::parse(&locked.reference)?.name()
+            == BundleReference::parse(&dependency.bundle)?.name();
+        let in_range = match (&dependency.version, Version::parse(&locked.version)) {
+            (None, _) => true,
+            (Some(range), Ok(version)) => range.matches(&version).unwrap_or(false),
+            (Some(_), Err(_)) => false,
+        };
+        if !same_repository || !in_range {
+            drift.push(LockDrift::DependencyChanged {
+                name: name.clone(),
+                locked: format!("{} {}", locked.reference, locked.version),
+                declared: dependency.bundle.clone(),
+            });
+        }
+    }
+    for name in lockfile.dependencies.keys() {
+        if !requires.contains_key(name) {
+            drift.push(LockDrift::RemovedDependency(name.clone()));
+        }
+    }
+    Ok(drift)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dependencies::{AvailableVersion, MemoryBundleSource};
+
+    /// Resolves every image to the digest of its reference.
+    struct TestResolver {
+        bundles: MemoryBundleSource,
+        digests: BTreeMap<String, String>,
+    }
+
+    impl BundleSource for TestResolver {
+        fn versions(&self, repository: &str) -> Result<Vec<AvailableVersion>, DependencyError> {
+            self.bundles.versions(repository)
+        }
+
+        fn fetch(&self, reference: &str) -> Result<Bundle, DependencyError> {
+            self.bundles.fetch(reference)
+        }
+    }
+
+    impl Resolver for TestResolver {
+        fn image_digest(&self, image: &str) -> Result<String, LockError> {
+            self.digests
+                .get(image)
+                .cloned()
+                .ok_or_else(|| LockError::Resolve {
+                    reference: image.to_string(),
+                    message: "not found".to_string(),
+                })
+        }
+    }
+
+    fn bundle(name: &str, version: &str, images: &[&str], requires: serde_json::Value) -> Bundle {
+        let invocation_images: Vec<_> = images
+            .iter()
+            .map(|image| serde_json::json!({ "image": image }))
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "version": version,
+            "schemaVersion": "v1.0.0",
+            "invocationImages": invocation_images,
+            "custom": { "io.cnab.dependencies": { "requires": requires } }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_lockfile() {
+        let mut bundles = MemoryBundleSource::new();
+        let mysql = bundle(
+            "mysql",
+            "5.7.1",
+            &["example.com/mysql:5.7"],
+            serde_json::json!({}),
+        );
+        bundles.add("example.com/bundles/mysql", mysql).unwrap();
+        let resolver = TestResolver {
+            bundles,
+            digests: vec![
+                (
+                    "example.com/wordpress:6".to_string(),
+                    "sha256:0001".to_string(),
+                ),
+                (
+                    "example.com/mysql:5.7".to_string(),
+                    "sha256:0002".to_string(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let requires = serde_json::json!({
+            "mysql": { "bundle": "example.com/bundles/mysql", "version": { "ranges": ["5.7.x"] } }
+        });
+        let wordpress = bundle(
+            "wordpress",
+            "1.0.0",
+            &["example.com/wordpress:6", "example.com/tools@sha256:0003"],
+            requires.clone(),
+        );
+
+        let lockfile = generate(&wordpress, &resolver).unwrap();
+        assert_eq!(lockfile.bundle_hash, wordpress.stable_hash());
+        assert_eq!(lockfile.images["example.com/wordpress:6"], "sha256:0001");
+        assert_eq!(
+            lockfile.images["example.com/tools@sha256:0003"],
+            "sha256:0003"
+        );
+        let mysql = &lockfile.dependencies["mysql"];
+        assert_eq!(mysql.reference, "example.com/bundles/mysql:5.7.1");
+        assert_eq!(mysql.images["example.com/mysql:5.7"], "sha256:0002");
+        assert_eq!(
+            lockfile.relocation().iter().collect::<Vec<_>>(),
+            vec![(
+                "example.com/wordpress:6",
+                "example.com/wordpress:6@sha256:0001"
+            )]
+        );
+        assert!(verify(&wordpress, &lockfile).unwrap().is_empty());
+
+        let json = serde_json::to_string(&lockfile).unwrap();
+        assert_eq!(serde_json::from_str::<Lockfile>(&json).unwrap(), lockfile);
+
+        let mut drifted = bundle(
+            "wordpress",
+            "1.0.1",
+            &["example.com/wordpress:6", "example.com/php:8"],
+            serde_json::json!({
+                "mysql": { "bundle": "example.com/bundles/mysql", "version": { "ranges": ["8.x"] } },
+                "cache": { "bundle": "example.com/bundles/redis" }
+            }),
+        );
+        drifted.invocation_images[0].content_digest = Some("sha256:0009".to_string());
+        assert_eq!(
+            verify(&drifted, &lockfile).unwrap(),
+            vec![
+                LockDrift::Bundle {
+                    locked: wordpress.stable_hash(),
+                    actual: drifted.stable_hash(),
+                },
+                LockDrift::Digest {
+                    image: "example.com/wordpress:6".to_string(),
+                    locked: "sha256:0001".to_string(),
+                    pinned: "sha256:0009".to_string(),
+                },
+                LockDrift::Unlocked("example.com/php:8".to_string()),
+                LockDrift::Removed("example.com/tools@sha256:0003".to_string()),
+                LockDrift::UnlockedDependency("cache".to_string()),
+                LockDrift::DependencyChanged {
+                    name: "mysql".to_string(),
+                    locked: "example.com/bundles/mysql:5.7.1 5.7.1".to_string(),
+                    declared: "example.com/bundles/mysql".to_string(),
+                },
+            ]
+        );
+
+        // An image the resolver does not know fails the lockfile.
+        let unknown = bundle(
+            "web",
+            "1.0.0",
+            &["example.com/web:1"],
+            serde_json::json!({}),
+        );
+        assert!(matches!(
+            generate(&unknown, &resolver),
+            Err(LockError::Resolve { .. })
+        ));
+    }
+}
@@ -0,0 +1,122 @@
+//! Counters and timers for dashboards of the actions and registry operations run
+//! with this crate.
+//!
+//! The `Engine` and the registry `Client` report to a `Metrics` given with their
+//! `metrics` method. The names are the constants of this module, dotted the way
+//! statsd names are; an implementation for Prometheus can replace the dots with
+//! underscores. Labels are name and value pairs, with values from a small set, such
+//! as the statuses of claims, so they can be used as dimensions as they are.
+//!
+//! ```
+//! use libcnab::metrics::{Metrics, ACTIONS};
+//! use std::collections::BTreeMap;
+//! use std::sync::Mutex;
+//!
+//! #[derive(Default)]
+//! struct Counts(Mutex<BTreeMap<String, u64>>);
+//!
+//! impl Metrics for Counts {
+//!     fn counter(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+//!         let labels: Vec<String> = labels.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+//!         let key = format!("{}{{{}}}", name, labels.join(","));
+//!         *self.0.lock().unwrap().entry(key).or_default() += value;
+//!     }
+//! }
+//!
+//! let counts = Counts::default();
+//! counts.counter(ACTIONS, &[("action", "install"), ("status", "success")], 1);
+//! assert_eq!(counts.0.lock().unwrap()["cnab.actions{action=install,status=success}"], 1);
+//! ```
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The actions run by the engine, labelled with `action` and the `status` of their
+/// claim
+pub const ACTIONS: &str = "cnab.actions";
+/// How long the actions run by the engine took, labelled as `ACTIONS` is
+pub const ACTION_DURATION: &str = "cnab.action.duration";
+/// How long pulling a bundle took, labelled with `result`, `ok` or `error`
+pub const PULL_DURATION: &str = "cnab.registry.pull.duration";
+/// How long pushing a bundle and its images took, labelled as `PULL_DURATION` is
+pub const PUSH_DURATION: &str = "cnab.registry.push.duration";
+/// The lookups in the registry cache that found what they looked for, labelled
+/// with the `kind` of content: `tag`, `manifest`, `blob`, or `layer` for a
+/// `LayerStore`
+pub const CACHE_HITS: &str = "cnab.registry.cache.hits";
+/// The lookups in the registry cache that did not, labelled as `CACHE_HITS` is
+pub const CACHE_MISSES: &str = "cnab.registry.cache.misses";
+
+/// Metrics receives counters and timers.
+///
+/// It may be called from several threads at once. Both methods do nothing by
+/// default, so an implementation only implements what it records.
+pub trait Metrics: Send + Sync {
+    /// Add `value` to the counter `name`.
+    fn counter(&self, _name: &str, _labels: &[(&str, &str)], _value: u64) {}
+
+    /// Record that something measured by the timer `name` took `duration`.
+    fn timer(&self, _name: &str, _labels: &[(&str, &str)], _duration: Duration) {}
+}
+
+/// A shared `Metrics`, such as one the embedder keeps to serve its metrics from
+impl<M: Metrics + ?Sized> Metrics for Arc<M> {
+    fn counter(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        (**self).counter(name, labels, value)
+    }
+
+    fn timer(&self, name: &str, labels: &[(&str, &str)], duration: Duration) {
+        (**self).timer(name, labels, duration)
+    }
+}
+
+impl fmt::Debug for dyn Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Metrics")
+    }
+}
+
+/// The `result` label of an operation's outcome.
+#[cfg(feature = "registry")]
+pub(crate) fn result_label<T, E>(result: &Result<T, E>) -> &'static str {
+    if result.is_ok() {
+        "ok"
+    } else {
+        "error"
+    }
+}
+
+#[cfg(all(test, any(feature = "drivers", feature = "registry")))]
+pub(crate) mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+
+    /// Recorded keeps the counters and the names of the timers reported to it, as
+    /// `name{label=value,...}`.
+    #[derive(Debug, Default)]
+    pub(crate) struct Recorded {
+        pub(crate) counters: Mutex<BTreeMap<String, u64>>,
+        pub(crate) timers: Mutex<Vec<String>>,
+    }
+
+    fn key(name: &str, labels: &[(&str, &str)]) -> String {
+        let labels: Vec<String> = labels.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        format!("{}{{{}}}", name, labels.join(","))
+    }
+
+    impl Metrics for Recorded {
+        fn counter(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+            *self
+                .counters
+                .lock()
+                .unwrap()
+                .entry(key(name, labels))
+                .or_default() += value;
+        }
+
+        fn timer(&self, name: &str, labels: &[(&str, &str)], _duration: Duration) {
+            self.timers.lock().unwrap().push(key(name, labels));
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use crate::cnab::Bundle;
+use crate::custom::merge;
+use crate::error_code::{Coded, ErrorCode};
+use serde_json::{Map, Value};
+use std::fmt;
+
+impl Bundle {
+    /// The bundle with `overlay`, a partial bundle document such as the image
+    /// overrides or extra custom data of one environment, merged onto it.
+    ///
+    /// Each top-level field of the overlay is merged its own way:
+    ///
+    /// - `schemaVersion` and `name` must be those of the bundle, as a variant is of
+    ///   the same bundle.
+    /// - `version`, `description` and `license` replace the bundle's.
+    /// - `keywords`, `maintainers` and `requiredExtensions` add the entries the
+    ///   bundle does not have yet.
+    /// - `actions`, `credentials`, `definitions`, `images`, `outputs` and
+    ///   `parameters` are merged as JSON merge patches: objects are merged key by
+    ///   key, at every depth, anything else replaces what is there, and `null`
+    ///   removes it, so that `{"parameters": {"debug": null}}` removes a parameter.
+    /// - `invocationImages` is merged the same way, each image onto the bundle's
+    ///   image in the same position, with any more images added.
+    /// - `custom` adds extensions, and merges into the existing ones as
+    ///   `CustomNamespaceMut::merge` does: a value different from the bundle's is a
+    ///   conflict, as the extension's owner may rely on it. `null` removes an
+    ///   extension.
+    ///
+    /// Any other field is refused, as is an overlay that would make the bundle
+    /// invalid.
+    ///
+    /// ```
+    /// use libcnab::Bundle;
+    ///
+    /// let base = Bundle::from_file("testdata/bundle.json").unwrap();
+    /// let production = base
+    ///     .overlay(&serde_json::json!({
+    ///         "invocationImages": [{ "image": "registry.internal/helloworld:0.1.0" }],
+    ///         "custom": { "com.example.backup-preferences": { "retention": "30d" } }
+    ///     }))
+    ///     .unwrap();
+    /// assert_eq!(production.invocation_images[0].image, "registry.internal/helloworld:0.1.0");
+    /// assert_eq!(production.invocation_images[0].image_type.as_deref(), Some("docker"));
+    /// ```
+    pub fn overlay(&self, overlay: &Value) -> Result<Bundle, OverlayError> {
+        let fields = overlay.as_object().ok_or(OverlayError::NotAnObject)?;
+        let mut bundle = match serde_json::to_value(self).expect("bundles serialize") {
+            Value::Object(map) => map,
+            _ => unreachable!("bundles serialize as objects"),
+        };
+        for (field, value) in fields {
+            match field.as_str() {
+                "schemaVersion" | "name" => {
+                    if bundle.get(field) != Some(value) {
+                        return Err(OverlayError::Conflict {
+                            path: field.clone(),
+                        });
+                    }
+                }
+                "version" | "description" | "license" => {
+                    bundle.insert(field.clone(), value.clone());
+                }
+                "keywords" | "maintainers" | "requiredExtensions" => {
+                    union(entry(&mut bundle, field), value, field)?
+                }
+                "actions" | "credentials" | "definitions" | "images" | "outputs" | "parameters" => {
+                    patch(entry(&mut bundle, field), value)
+                }
+                "invocationImages" => {
+                    let images = value.as_array().ok_or_else(|| OverlayError::NotAList {
+                        path: field.clone(),
+                    })?;
+                    let base = entry(&mut bundle, field);
+                    if !base.is_array() {
+                        *base = Value::Array(Vec::new());
+                    }
+                    let base = base.as_array_mut().expect("made an array");
+                    for (i, image) in images.iter().enumerate() {
+                        match base.get_mut(i) {
+                            Some(existing) => patch(existing, image),
+                            None => base.push(image.clone()),
+                        }
+                    }
+                }
+                "custom" => custom(entry(&mut bundle, field), value)?,
+                _ => return Err(OverlayError::UnknownField(field.clone())),
+            }
+        }
+        serde_json::from_value(Value::Object(bundle)).map_err(OverlayError::Invalid)
+    }
+}
+
+fn entry<'b>(bundle: &'b mut Map<String, Value>, field: &str) -> &'b mut Value {
+    bundle.entry(field.to_string()).or_insert(Value::Null)
+}
+
+/// Add the entries of the list `value` that `into` does not have, or clear `into`
+/// if `value` is `null`.
+fn union(into: &mut Value, value: &Value, path: &str) -> Result<(), OverlayError> {
+    let entries = match value {
+        Value::Null => {
+            *into = Value::Null;
+            return Ok(());
+        }
+        Value::Array(entries) => entries,
+        _ => {
+            return Err(OverlayError::NotAList {
+                path: path.to_string(),
+            })
+        }
+    };
+    if !into.is_array() {
+        *into = Value::Array(Vec::new());
+    }
+    let into = into.as_array_mut().expect("made an array");
+    for value in entries {
+        if !into.contains(value) {
+            into.push(value.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Apply `value` to `into` as a JSON merge patch (RFC 7386).
+fn patch(into: &mut Value, value: &Value) {
+    let fields = match value {
+        Value::Object(fields) => fields,
+        value => {
+            *into = value.clone();
+            return;
+        }
+    };
+    if !into.is_object() {
+        *into = Value::Object(Map::new());
+    }
+    let into = into.as_object_mut().expect("made an object");
+    for (field, value) in fields {
+        if value.is_null() {
+            into.remove(field);
+        } else {
+            patch(into.entry(field.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+fn custom(into: &mut Value, value: &Value) -> Result<(), OverlayError> {
+    let extensions = match value {
+        Value::Null => {
+            *into = Value::Null;
+            return Ok(());
+        }
+        Value::Object(extensions) => extensions,
+        _ => return Err(OverlayError::NotAnObject),
+    };
+    if !into.is_object() {
+        *into = Value::Object(Map::new());
+    }
+    let into = into.as_object_mut().expect("made an object");
+    for (key, value) in extensions {
+        match (into.get_mut(key), value) {
+            (_, Value::Null) => {
+                into.remove(key);
+            }
+            (Some(existing), value) => {
+                let mut merged = existing.clone();
+                merge(&mut merged, value.clone(), &mut Vec::new()).map_err(|path| {
+                    OverlayError::Conflict {
+                        path: match path {
+                            Some(path) => format!("custom.{}.{}", key, path),
+                            None => format!("custom.{}", key),
+                        },
+                    }
+                })?;
+                *existing = merged;
+            }
+            (None, value) => {
+                into.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// OverlayError describes an overlay that could not be merged onto a bundle.
+#[derive(Debug)]
+pub enum OverlayError {
+    /// The overlay, or its `custom` section, is not a JSON object
+    NotAnObject,
+    /// The field at `path` must be a list
+    NotAList { path: String },
+    /// The overlay has a field no bundle has
+    UnknownField(String),
+    /// The overlay changes what it may only repeat, the value at `path`
+    Conflict { path: String },
+    /// The merged document is not a valid bundle
+    Invalid(serde_json::Error),
+}
+
+impl fmt::Display for OverlayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverlayError::NotAnObject => write!(f, "an overlay must be a JSON object"),
+            OverlayError::NotAList { path } => write!(f, "overlay field {} must be a list", path),
+            OverlayError::UnknownField(field) => write!(f, "unknown overlay field {}", field),
+            OverlayError::Conflict { path } => {
+                write!(f, "the overlay conflicts with the bundle at {}", path)
+            }
+            OverlayError::Invalid(e) => write!(f, "the overlaid bundle is invalid: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for OverlayError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OverlayError::Invalid(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Coded for OverlayError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            OverlayError::NotAnObject => ErrorCode::OverlayNotAnObject,
+            OverlayError::NotAList { .. } => ErrorCode::OverlayNotAList,
+            OverlayError::UnknownField(_) => ErrorCode::OverlayUnknownField,
+            OverlayError::Conflict { .. } => ErrorCode::OverlayConflict,
+            OverlayError::Invalid(_) => ErrorCode::BundleInvalidJson,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_overlay() {
+        let base = Bundle::from_file("testdata/bundle.json").unwrap();
+        let bundle = base
+            .overlay(&json!({
+                "name": "helloworld",
+                "version": "0.1.2-prod",
+                "keywords": ["production"],
+                "images": {
+                    "my-microservice": { "image": "registry.internal/microservice:1.2.3" }
+                },
+                "invocationImages": [{}, { "image": "example/arm64-installer:0.1.0" }],
+                "parameters": {
+                    "backend_port": { "destination": { "path": "/cnab/app/port" } }
+                },
+                "credentials": { "hostkey": null },
+                "custom": {
+                    "com.example.backup-preferences": { "frequency": "daily", "retention": "30d" },
+                    "com.example.duffle-bag": null,
+                    "com.example.team": "platform"
+                }
+            }))
+            .unwrap();
+        assert_eq!(bundle.version.to_string(), "0.1.2-prod");
+        assert_eq!(bundle.keywords, Some(vec!["production".to_string()]));
+        let image = &bundle.images.as_ref().unwrap()["my-microservice"];
+        assert_eq!(image.image, "registry.internal/microservice:1.2.3");
+        assert_eq!(image.description.as_deref(), Some("my microservice"));
+        assert_eq!(bundle.invocation_images.len(), 2);
+        assert_eq!(
+            bundle.invocation_images[0].image,
+            base.invocation_images[0].image
+        );
+        let destination = &bundle.parameters.as_ref().unwrap()["backend_port"].destination;
+        assert_eq!(destination.env.as_deref(), Some("BACKEND_PORT"));
+        assert_eq!(destination.path.as_deref(), Some("/cnab/app/port".as_ref()));
+        assert!(bundle.credentials.unwrap().is_empty());
+        let custom = bundle.custom.unwrap();
+        assert_eq!(
+            custom["com.example.backup-preferences"],
+            json!({ "frequency": "daily", "retention": "30d" })
+        );
+        assert!(!custom.contains_key("com.example.duffle-bag"));
+        assert_eq!(custom["com.example.team"], "platform");
+
+        let error = |overlay: Value| base.overlay(&overlay).unwrap_err();
+        match error(
+            json!({ "custom": { "com.example.backup-preferences": { "frequency": "hourly" } } }),
+        ) {
+            OverlayError::Conflict { path } => {
+                assert_eq!(path, "custom.com.example.backup-preferences.frequency")
+            }
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+        assert!(matches!(
+            error(json!({ "name": "goodbyeworld" })),
+            OverlayError::Conflict { path } if path == "name"
+        ));
+        assert!(matches!(
+            error(json!({ "imagez": {} })),
+            OverlayError::UnknownField(field) if field == "imagez"
+        ));
+        assert!(matches!(
+            error(json!({ "keywords": "production" })),
+            OverlayError::NotAList { .. }
+        ));
+        assert!(matches!(
+            error(json!({ "version": "not semver" })),
+            OverlayError::Invalid(_)
+        ));
+        assert!(matches!(
+            error(json!(["production"])),
+            OverlayError::NotAnObject
+        ));
+    }
+}
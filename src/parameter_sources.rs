@@ -0,0 +1,209 @@
+use crate::claim::Claim;
+use crate::cnab::Bundle;
+use crate::credentialset::ResolveError;
+use crate::parameterset::ParameterSet;
+use crate::secrets::SecretResolver;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The custom key under which the parameter-sources extension is stored
+pub const PARAMETER_SOURCES_KEY: &str = "io.cnab.parameter-sources";
+
+/// ParameterSources implements the `io.cnab.parameter-sources` extension.
+///
+/// It maps parameter names to the places a value for that parameter may be taken from
+/// when none is supplied by the user, such as an output of a previous run.
+pub type ParameterSources = BTreeMap<String, ParameterSourceDefinition>;
+
+/// ParameterSourceDefinition lists the sources for a single parameter.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ParameterSourceDefinition {
+    /// The order in which source kinds are consulted (e.g. `["output"]`)
+    #[serde(default)]
+    pub priority: Vec<String>,
+    /// The sources, keyed by source kind
+    pub sources: SourceKinds,
+}
+
+/// SourceKinds holds the known kinds of parameter source.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct SourceKinds {
+    /// Take the value from an output of the installation's previous run
+    pub output: Option<OutputSource>,
+    /// Take the value from an output of a dependency's installation
+    #[serde(rename = "dependencies.output")]
+    pub dependency_output: Option<DependencyOutputSource>,
+}
+
+/// OutputSource refers to an output of this bundle.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OutputSource {
+    /// The name of the output
+    pub name: String,
+}
+
+/// DependencyOutputSource refers to an output of a dependency.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct DependencyOutputSource {
+    /// The name of the dependency, as declared by the dependencies extension
+    pub dependency: String,
+    /// The name of the output on the dependency
+    pub name: String,
+}
+
+/// SourceValues holds the output values that parameter sources may draw from.
+#[derive(Debug, Default)]
+pub struct SourceValues {
+    /// Outputs of the installation's previous run
+    pub outputs: BTreeMap<String, String>,
+    /// Outputs of dependency installations, keyed by dependency name
+    pub dependency_outputs: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl SourceValues {
+    /// Source values taken from the outputs recorded on a previous claim.
+    pub fn from_claim(claim: &Claim) -> Self {
+        SourceValues {
+            outputs: claim.outputs.clone().unwrap_or_default(),
+            dependency_outputs: BTreeMap::new(),
+        }
+    }
+}
+
+impl ParameterSourceDefinition {
+    /// Find a value for this parameter, trying source kinds in priority order.
+    ///
+    /// Kinds that are defined but not listed in `priority` are tried afterwards.
+    pub fn resolve(&self, values: &SourceValues) -> Option<String> {
+        let mut kinds: Vec<&str> = self.priority.iter().map(String::as_str).collect();
+        for kind in &["output", "dependencies.output"] {
+            if !kinds.contains(kind) {
+                kinds.push(kind);
+            }
+        }
+        kinds.into_iter().find_map(|kind| match kind {
+            "output" => self
+                .sources
+                .output
+                .as_ref()
+                .and_then(|o| values.outputs.get(&o.name).cloned()),
+            "dependencies.output" => self.sources.dependency_output.as_ref().and_then(|d| {
+                values
+                    .dependency_outputs
+                    .get(&d.dependency)
+                    .and_then(|outputs| outputs.get(&d.name).cloned())
+            }),
+            _ => None,
+        })
+    }
+}
+
+impl Bundle {
+    /// Parse the parameter-sources extension from `custom`, if present.
+    pub fn parameter_sources(&self) -> Result<Option<ParameterSources>, serde_json::Error> {
+        match self
+            .custom
+            .as_ref()
+            .and_then(|c| c.get(PARAMETER_SOURCES_KEY))
+        {
+            Some(value) => serde_json::from_value(value.clone()).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl ParameterSet {
+    /// Resolve the parameter set, filling in any parameter the set does not provide from
+    /// the bundle's parameter sources.
+    ///
+    /// Values in the set always take precedence over sourced values.
+    pub fn resolve_with_sources(
+        &self,
+        bundle: &Bundle,
+        values: &SourceValues,
+        secrets: &SecretResolver,
+    ) -> Result<BTreeMap<String, String>, ResolveError> {
+        let mut resolved: BTreeMap<String, String> = bundle
+            .parameter_sources()?
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|(name, def)| def.resolve(values).map(|v| (name.clone(), v)))
+            .collect();
+        resolved.extend(self.resolve_with(secrets)?);
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parameter_sources() {
+        let bundle: Bundle = r#"{
+            "name": "aristotle",
+            "invocationImages": [],
+            "schemaVersion": "1.0.0",
+            "version": "1.0.0",
+            "requiredExtensions": ["io.cnab.parameter-sources"],
+            "custom": {
+                "io.cnab.parameter-sources": {
+                    "tfstate": {
+                        "priority": ["output"],
+                        "sources": {
+                            "output": { "name": "tfstate" }
+                        }
+                    },
+                    "connstr": {
+                        "priority": ["dependencies.output"],
+                        "sources": {
+                            "dependencies.output": { "dependency": "mysql", "name": "connstr" }
+                        }
+                    },
+                    "port": {
+                        "sources": {
+                            "output": { "name": "port" }
+                        }
+                    }
+                }
+            }
+        }"#
+        .parse()
+        .expect("bundle parsed");
+
+        let sources = bundle
+            .parameter_sources()
+            .expect("extension parsed")
+            .expect("extension present");
+        assert_eq!(sources.len(), 3);
+
+        let mut values = SourceValues::default();
+        values.outputs.insert("tfstate".into(), "state".into());
+        values.outputs.insert("port".into(), "80".into());
+        values.dependency_outputs.insert(
+            "mysql".into(),
+            vec![("connstr".to_string(), "mysql://db".to_string())]
+                .into_iter()
+                .collect(),
+        );
+
+        let set: ParameterSet = serde_json::from_str(
+            r#"{
+                "name": "upgrade",
+                "parameters": [{ "name": "port", "source": { "value": "8080" } }]
+            }"#,
+        )
+        .unwrap();
+        let resolved = set
+            .resolve_with_sources(&bundle, &values, &SecretResolver::empty())
+            .expect("parameters resolved");
+        assert_eq!(resolved["tfstate"], "state");
+        assert_eq!(resolved["connstr"], "mysql://db");
+        assert_eq!(resolved["port"], "8080");
+    }
+}
@@ -0,0 +1,98 @@
+use crate::credentialset::{CredentialSource, ResolveError};
+use crate::secrets::SecretResolver;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// ParameterSource describes where the value of a parameter comes from.
+///
+/// Parameter values may be drawn from the same places as credentials.
+pub type ParameterSource = CredentialSource;
+
+/// ParameterSet is the parameter counterpart to a `CredentialSet`: a named list of
+/// parameters and the sources their values are resolved from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ParameterSet {
+    pub name: String,
+    pub parameters: Vec<ParameterValue>,
+}
+
+impl ParameterSet {
+    /// Resolve every parameter in the set, returning the values keyed by parameter name.
+    ///
+    /// Secret lookups use the default `SecretResolver`.
+    pub fn resolve(&self) -> Result<BTreeMap<String, String>, ResolveError> {
+        self.resolve_with(&SecretResolver::default())
+    }
+
+    /// Resolve every parameter in the set, looking up secrets with the given resolver.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(set = %self.name), err)
+    )]
+    pub fn resolve_with(
+        &self,
+        secrets: &SecretResolver,
+    ) -> Result<BTreeMap<String, String>, ResolveError> {
+        self.parameters
+            .iter()
+            .map(|p| Ok((p.name.clone(), p.source.resolve(&p.name, secrets)?)))
+            .collect()
+    }
+
+    /// A set of literal values, such as parameters already resolved for another
+    /// installation.
+    pub fn from_values(name: &str, values: &BTreeMap<String, String>) -> Self {
+        ParameterSet {
+            name: name.to_string(),
+            parameters: values
+                .iter()
+                .map(|(name, value)| ParameterValue {
+                    name: name.clone(),
+                    source: ParameterSource::from_value(value),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ParameterValue {
+    name: String,
+    source: ParameterSource,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parameterset_resolve() {
+        let set: ParameterSet = serde_json::from_str(
+            r#"{
+                "name": "test_parameters",
+                "parameters": [
+                    {
+                        "name": "port",
+                        "source": { "value": "8080" }
+                    },
+                    {
+                        "name": "db_password",
+                        "source": {
+                            "vault": { "path": "secret/data/db", "key": "password" },
+                            "value": "fallback"
+                        }
+                    }
+                ]
+            }"#,
+        )
+        .expect("parameter set parsed");
+
+        let values = set.resolve().expect("parameters resolved");
+        assert_eq!(values["port"], "8080");
+        assert_eq!(values["db_password"], "fallback");
+    }
+}
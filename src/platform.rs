@@ -0,0 +1,206 @@
+use crate::cnab::{Bundle, InvocationImage, Platform};
+use std::fmt;
+use std::str::FromStr;
+
+/// The label of an invocation image that names the platform it runs on, such as
+/// `linux/amd64`. An invocation image without it is taken to run anywhere.
+pub const PLATFORM_LABEL: &str = "libcnab.platform";
+
+/// The operating systems the `os` of a platform is usually one of
+pub const KNOWN_OS: &[&str] = &["darwin", "freebsd", "linux", "windows", "wasip1"];
+
+/// The architectures the `arch` of a platform is usually one of
+pub const KNOWN_ARCH: &[&str] = &[
+    "386", "amd64", "arm", "arm64", "ppc64le", "riscv64", "s390x", "wasm",
+];
+
+impl Platform {
+    pub const LINUX_AMD64: &'static str = "linux/amd64";
+    pub const LINUX_ARM64: &'static str = "linux/arm64";
+    pub const WINDOWS_AMD64: &'static str = "windows/amd64";
+    pub const DARWIN_ARM64: &'static str = "darwin/arm64";
+
+    pub fn new(os: &str, arch: &str) -> Self {
+        Platform {
+            arch: Some(arch.to_string()),
+            os: Some(os.to_string()),
+        }
+    }
+
+    /// The platform this program runs on, in the names of the specification, such
+    /// as `amd64` rather than `x86_64`.
+    pub fn current() -> Self {
+        Platform::new(
+            normalize(std::env::consts::OS),
+            normalize(std::env::consts::ARCH),
+        )
+    }
+
+    /// Whether something for this platform runs on `other`, or the other way around.
+    ///
+    /// An unset or `*` os or arch matches any, and the names Rust and others use
+    /// for some of them, such as `x86_64` and `aarch64`, match the names of the
+    /// specification.
+    ///
+    /// ```
+    /// use libcnab::Platform;
+    ///
+    /// let linux: Platform = "linux/*".parse().unwrap();
+    /// assert!(linux.matches(&Platform::LINUX_ARM64.parse().unwrap()));
+    /// assert!(linux.matches(&Platform::new("linux", "x86_64")));
+    /// assert!(!linux.matches(&Platform::WINDOWS_AMD64.parse().unwrap()));
+    /// ```
+    pub fn matches(&self, other: &Platform) -> bool {
+        fn part(a: &Option<String>, b: &Option<String>) -> bool {
+            match (a.as_deref(), b.as_deref()) {
+                (None, _) | (_, None) | (Some("*"), _) | (_, Some("*")) => true,
+                (Some(a), Some(b)) => normalize(a).eq_ignore_ascii_case(normalize(b)),
+            }
+        }
+        part(&self.os, &other.os) && part(&self.arch, &other.arch)
+    }
+}
+
+/// The name the specification uses for an os or arch.
+pub(crate) fn normalize(name: &str) -> &str {
+    match name {
+        "macos" => "darwin",
+        "x86_64" | "x86-64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" | "i386" | "i686" => "386",
+        "powerpc64" | "powerpc64le" => "ppc64le",
+        "wasm32" => "wasm",
+        name => name,
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}",
+            self.os.as_deref().unwrap_or("*"),
+            self.arch.as_deref().unwrap_or("*")
+        )
+    }
+}
+
+impl FromStr for Platform {
+    type Err = InvalidPlatform;
+
+    /// Parse `<os>/<arch>`, or `<os>` for any arch, where `*` is any.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let part = |p: &str| match p.trim() {
+            "" => Err(InvalidPlatform(s.to_string())),
+            "*" => Ok(None),
+            p => Ok(Some(p.to_string())),
+        };
+        let (os, arch) = match s.split_once('/') {
+            Some((_, arch)) if arch.contains('/') => return Err(InvalidPlatform(s.to_string())),
+            Some((os, arch)) => (part(os)?, part(arch)?),
+            None => (part(s)?, None),
+        };
+        Ok(Platform { arch, os })
+    }
+}
+
+/// InvalidPlatform is a platform that could not be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidPlatform(pub String);
+
+impl fmt::Display for InvalidPlatform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid platform {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidPlatform {}
+
+impl InvocationImage {
+    /// The platform the image runs on, from its `PLATFORM_LABEL` label.
+    pub fn platform(&self) -> Option<Result<Platform, InvalidPlatform>> {
+        self.labels.as_ref()?.get(PLATFORM_LABEL).map(|p| p.parse())
+    }
+}
+
+impl Bundle {
+    /// The index of the first invocation image that runs on `platform`.
+    ///
+    /// An image without a platform label runs anywhere, and one with an invalid
+    /// label nowhere.
+    pub fn invocation_image_for(&self, platform: &Platform) -> Option<usize> {
+        self.invocation_images
+            .iter()
+            .position(|image| match image.platform() {
+                None => true,
+                Some(Ok(p)) => p.matches(platform),
+                Some(Err(_)) => false,
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_platform() {
+        let parse = |s: &str| s.parse::<Platform>().unwrap();
+        assert_eq!(parse("linux/amd64"), Platform::new("linux", "amd64"));
+        assert_eq!(
+            parse("windows"),
+            Platform {
+                arch: None,
+                os: Some("windows".to_string())
+            }
+        );
+        assert_eq!(parse("*/arm64").to_string(), "*/arm64");
+        for invalid in &["", "linux/", "/amd64", "linux/arm/v7"] {
+            assert_eq!(
+                invalid.parse::<Platform>(),
+                Err(InvalidPlatform(invalid.to_string()))
+            );
+        }
+
+        assert!(parse("linux/amd64").matches(&Platform::new("linux", "x86_64")));
+        assert!(parse("darwin/arm64").matches(&Platform::new("macos", "aarch64")));
+        assert!(parse("*/amd64").matches(&parse(Platform::WINDOWS_AMD64)));
+        assert!(!parse(Platform::LINUX_AMD64).matches(&parse(Platform::LINUX_ARM64)));
+
+        let current = Platform::current();
+        assert!(KNOWN_OS.contains(&current.os.as_deref().unwrap()));
+        assert!(KNOWN_ARCH.contains(&current.arch.as_deref().unwrap()));
+    }
+
+    #[test]
+    fn test_invocation_image_for() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let labelled = |platform: &str| {
+            let mut image = bundle.invocation_images[0].clone();
+            image.labels = Some(
+                vec![(PLATFORM_LABEL.to_string(), platform.to_string())]
+                    .into_iter()
+                    .collect(),
+            );
+            image
+        };
+        let images = vec![
+            labelled("not/a/platform"),
+            labelled(Platform::LINUX_ARM64),
+            labelled(Platform::LINUX_AMD64),
+        ];
+        bundle.invocation_images = images;
+
+        let amd64 = Platform::new("linux", "amd64");
+        assert_eq!(bundle.invocation_image_for(&amd64), Some(2));
+        assert_eq!(
+            bundle.invocation_image_for(&Platform::new("linux", "aarch64")),
+            Some(1)
+        );
+        assert_eq!(
+            bundle.invocation_image_for(&Platform::new("windows", "amd64")),
+            None
+        );
+        assert!(bundle.invocation_images[0].platform().unwrap().is_err());
+    }
+}
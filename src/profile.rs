@@ -0,0 +1,141 @@
+use std::collections::BTreeSet;
+
+/// ValidationRule is one of the checks of a bundle a `ValidationProfile` can enforce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ValidationRule {
+    /// When parsing, refuse a `schemaVersion` newer than the
+    /// `SUPPORTED_SCHEMA_VERSIONS`
+    SchemaVersion,
+    /// Every parameter and credential is placed in an environment variable, a file,
+    /// or both
+    Destinations,
+    /// Every extension in `requiredExtensions` is known
+    RequiredExtensions,
+    /// The payload in `custom` of every known extension passes its validators
+    ExtensionPayloads,
+}
+
+impl ValidationRule {
+    /// Every rule.
+    pub const ALL: &'static [ValidationRule] = &[
+        ValidationRule::SchemaVersion,
+        ValidationRule::Destinations,
+        ValidationRule::RequiredExtensions,
+        ValidationRule::ExtensionPayloads,
+    ];
+}
+
+/// ValidationProfile is how closely a bundle is held to the CNAB specification when
+/// it is parsed with `Bundle::from_json_with` and the other `_with` parsers, validated
+/// with `Bundle::validate_with`, and checked by the engine before it runs an action.
+///
+/// `Strict1_0` is the default of both parsing and the engine, so a bundle is held
+/// to the same profile when it is read as when it is run. Publishing wants it, so what is pushed
+/// is a conforming bundle, and consuming may want `Permissive`, so bundles of older
+/// or third-party tools can be run as long as they can be read. Which profile matters only for the checks it
+/// can turn off: names of environment variables are always checked, since drivers
+/// cannot set others, and the engine always refuses bundles that require extensions
+/// it does not support.
+///
+/// ```
+/// use libcnab::{Bundle, ExtensionRegistry, ValidationProfile, ValidationRule};
+///
+/// let json = r#"{"name": "legacy", "version": "0.1.0", "schemaVersion": "v1.1.0",
+///     "invocationImages": [], "credentials": {"token": {}}}"#;
+/// assert!(Bundle::from_json(json.as_bytes()).is_err());
+///
+/// let permissive = ValidationProfile::Permissive;
+/// let bundle = Bundle::from_json_with(json.as_bytes(), &permissive).unwrap();
+/// bundle.validate_with(&ExtensionRegistry::default(), &permissive).unwrap();
+///
+/// let destinations = ValidationProfile::custom(vec![ValidationRule::Destinations]);
+/// assert!(bundle.validate_with(&ExtensionRegistry::default(), &destinations).is_err());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ValidationProfile {
+    /// Every rule, for bundles that must conform to CNAB Core 1.0
+    #[default]
+    Strict1_0,
+    /// No rule, for reading whatever bundle can be read. Names of environment
+    /// variables are still checked when a bundle is parsed, so one that names a
+    /// variable drivers cannot set is refused even so
+    Permissive,
+    /// Only the rules given
+    Custom { rules: BTreeSet<ValidationRule> },
+}
+
+impl ValidationProfile {
+    pub fn custom<I: IntoIterator<Item = ValidationRule>>(rules: I) -> Self {
+        ValidationProfile::Custom {
+            rules: rules.into_iter().collect(),
+        }
+    }
+
+    /// Whether the profile enforces `rule`.
+    pub fn enforces(&self, rule: ValidationRule) -> bool {
+        match self {
+            ValidationProfile::Strict1_0 => true,
+            ValidationProfile::Permissive => false,
+            ValidationProfile::Custom { rules } => rules.contains(&rule),
+        }
+    }
+
+    /// The rules the profile enforces.
+    pub fn rules(&self) -> BTreeSet<ValidationRule> {
+        ValidationRule::ALL
+            .iter()
+            .copied()
+            .filter(|r| self.enforces(*r))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Bundle, ExtensionProblem, ExtensionRegistry, ValidationProblem};
+
+    #[test]
+    fn test_validation_profiles() {
+        assert_eq!(
+            ValidationProfile::Strict1_0.rules().len(),
+            ValidationRule::ALL.len()
+        );
+        assert!(ValidationProfile::Permissive.rules().is_empty());
+        let custom = ValidationProfile::custom(vec![ValidationRule::RequiredExtensions]);
+        assert!(custom.enforces(ValidationRule::RequiredExtensions));
+        assert!(!custom.enforces(ValidationRule::Destinations));
+
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.required_extensions = Some(vec!["com.example.unknown".to_string()]);
+        let hostkey = bundle
+            .credentials
+            .as_mut()
+            .unwrap()
+            .get_mut("hostkey")
+            .unwrap();
+        hostkey.env = None;
+        hostkey.path = None;
+        let registry = ExtensionRegistry::default();
+        let problems = |profile: &ValidationProfile| {
+            let result = bundle.validate_with(&registry, profile);
+            result.err().map(|e| e.problems).unwrap_or_default()
+        };
+        assert_eq!(
+            problems(&ValidationProfile::Strict1_0),
+            vec![
+                ValidationProblem::NoCredentialDestination("hostkey".to_string()),
+                ExtensionProblem::UnknownRequired("com.example.unknown".to_string()).into(),
+            ]
+        );
+        assert_eq!(
+            problems(&custom),
+            vec![ExtensionProblem::UnknownRequired("com.example.unknown".to_string()).into()]
+        );
+        assert!(problems(&ValidationProfile::Permissive).is_empty());
+
+        let json = r#"{"name": "b", "version": "0.1.0", "schemaVersion": "v1.0.0",
+            "invocationImages": [], "credentials": {"c": {"env": "HOST-KEY"}}}"#;
+        assert!(Bundle::from_json_with(json.as_bytes(), &ValidationProfile::Permissive).is_err());
+    }
+}
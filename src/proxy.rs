@@ -0,0 +1,179 @@
+/// ProxyConfig says which HTTP proxies the crate's network clients go through: the
+/// registry client, the Vault, AWS and Azure secret sources, and the Sigstore
+/// signer.
+///
+/// `ProxyConfig::from_env` reads the variables curl and docker do. Hosts on the
+/// local host are always reached directly.
+///
+/// ```
+/// use libcnab::ProxyConfig;
+///
+/// let proxy = ProxyConfig::all("http://proxy.corp:3128").no_proxy("registry.corp");
+/// assert_eq!(
+///     proxy.proxy_url("https://ghcr.io/v2/"),
+///     Some("http://proxy.corp:3128")
+/// );
+/// assert_eq!(proxy.proxy_url("https://mirror.registry.corp/v2/"), None);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProxyConfig {
+    /// The proxy for `http://` URLs
+    pub http: Option<String>,
+    /// The proxy for `https://` URLs
+    pub https: Option<String>,
+    /// The hosts to reach directly. An entry matches its host and the subdomains of
+    /// it, with or without a leading `.` or `*.`, and `*` matches every host.
+    pub no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+    /// Reach every host directly.
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Send both `http://` and `https://` requests through the proxy `url`.
+    pub fn all(url: &str) -> Self {
+        ProxyConfig {
+            http: Some(url.to_string()),
+            https: Some(url.to_string()),
+            no_proxy: Vec::new(),
+        }
+    }
+
+    /// Read `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` as the fallback of both, and the
+    /// comma-separated `NO_PROXY`. The lowercase variables are read too.
+    pub fn from_env() -> Self {
+        let var = |name: &str| {
+            std::env::var(name)
+                .or_else(|_| std::env::var(name.to_lowercase()))
+                .ok()
+                .filter(|v| !v.is_empty())
+        };
+        let all = var("ALL_PROXY");
+        ProxyConfig {
+            http: var("HTTP_PROXY").or_else(|| all.clone()),
+            https: var("HTTPS_PROXY").or(all),
+            no_proxy: var("NO_PROXY")
+                .map(|hosts| {
+                    hosts
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|h| !h.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Reach `host` directly as well.
+    pub fn no_proxy(mut self, host: &str) -> Self {
+        self.no_proxy.push(host.to_string());
+        self
+    }
+
+    /// The proxy a request to `url` goes through, if any.
+    pub fn proxy_url(&self, url: &str) -> Option<&str> {
+        let (scheme, rest) = url.split_once("://")?;
+        let proxy = match scheme.to_ascii_lowercase().as_str() {
+            "http" => self.http.as_deref(),
+            "https" => self.https.as_deref(),
+            _ => None,
+        }?;
+        let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+        let authority = authority.rsplit('@').next().unwrap_or_default();
+        let host = host_of(authority).to_ascii_lowercase();
+        if is_loopback(&host) || self.no_proxy.iter().any(|entry| matches(entry, &host)) {
+            return None;
+        }
+        Some(proxy)
+    }
+
+    /// The `ureq` proxy a request to `url` goes through, if any.
+    pub(crate) fn proxy(&self, url: &str) -> Result<Option<ureq::Proxy>, ureq::Error> {
+        self.proxy_url(url).map(ureq::Proxy::new).transpose()
+    }
+
+    /// An agent with the default configuration, going through the proxy for `url`.
+    #[cfg(any(
+        feature = "vault",
+        feature = "aws",
+        feature = "azure",
+        feature = "sigstore"
+    ))]
+    pub(crate) fn agent(&self, url: &str) -> Result<ureq::Agent, ureq::Error> {
+        Ok(ureq::Agent::config_builder()
+            .proxy(self.proxy(url)?)
+            .build()
+            .new_agent())
+    }
+}
+
+/// The host of an authority such as `example.com:443` or `[::1]:5000`.
+fn host_of(authority: &str) -> &str {
+    if authority.starts_with('[') {
+        return authority
+            .split_once(']')
+            .map(|(host, _)| &host[1..])
+            .unwrap_or(authority);
+    }
+    authority.split(':').next().unwrap_or(authority)
+}
+
+fn is_loopback(host: &str) -> bool {
+    host == "localhost" || host == "::1" || host.starts_with("127.")
+}
+
+fn matches(entry: &str, host: &str) -> bool {
+    let entry = entry.trim().to_ascii_lowercase();
+    if entry == "*" {
+        return true;
+    }
+    let entry = entry.trim_start_matches("*.").trim_start_matches('.');
+    let entry = host_of(entry);
+    !entry.is_empty()
+        && (host == entry
+            || host
+                .strip_suffix(entry)
+                .is_some_and(|rest| rest.ends_with('.')))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_proxy_url() {
+        let proxy = ProxyConfig {
+            http: Some("http://proxy:80".to_string()),
+            https: Some("http://proxy:443".to_string()),
+            no_proxy: vec![".corp".to_string(), "mirror.example.com:5000".to_string()],
+        };
+        assert_eq!(
+            proxy.proxy_url("http://example.com/v2/"),
+            Some("http://proxy:80")
+        );
+        assert_eq!(
+            proxy.proxy_url("https://user@example.com:8443/v2/"),
+            Some("http://proxy:443")
+        );
+        assert_eq!(proxy.proxy_url("https://registry.corp/v2/"), None);
+        assert_eq!(proxy.proxy_url("https://corp/v2/"), None);
+        assert_eq!(proxy.proxy_url("https://mirror.example.com/v2/"), None);
+        assert_eq!(
+            proxy.proxy_url("https://notcorp/v2/"),
+            Some("http://proxy:443")
+        );
+        assert_eq!(proxy.proxy_url("http://localhost:5000/v2/"), None);
+        assert_eq!(proxy.proxy_url("http://127.0.0.1:5000/v2/"), None);
+        assert_eq!(proxy.proxy_url("http://[::1]:5000/v2/"), None);
+
+        let all = ProxyConfig::all("http://proxy").no_proxy("*");
+        assert_eq!(all.proxy_url("https://example.com/"), None);
+        assert_eq!(ProxyConfig::none().proxy_url("https://example.com/"), None);
+        assert!(ProxyConfig::all("not a proxy")
+            .proxy("https://example.com/")
+            .is_err());
+    }
+}
@@ -0,0 +1,320 @@
+//! Drift between an installation and the state it is meant to be in.
+//!
+//! A controller that keeps installations in a desired state, such as one reading
+//! bundles and parameters from git, asks `detect_drift` what it takes to get each
+//! installation there: nothing, an install, an upgrade or a reinstall.
+//!
+//! ```
+//! use libcnab::claimstore::MemoryClaimStore;
+//! use libcnab::reconcile::{detect_drift, Reconciliation};
+//! use libcnab::Bundle;
+//! use std::collections::BTreeMap;
+//!
+//! let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+//! let drift = detect_drift("helloworld", &MemoryClaimStore::new(), &bundle, &BTreeMap::new())
+//!     .unwrap();
+//! assert_eq!(drift.reconciliation, Reconciliation::Install);
+//! ```
+use crate::claim::Status;
+use crate::claimstore::{ClaimStore, ClaimStoreError};
+use crate::cnab::Bundle;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Reconciliation is what brings an installation to its desired state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reconciliation {
+    /// The installation is as desired
+    NoOp,
+    /// There is no installation, or it was uninstalled
+    Install,
+    /// The bundle or parameters changed, or the last action did not succeed
+    Upgrade,
+    /// The installation has to be uninstalled and installed again: it never finished
+    /// installing, it is of another bundle, or a parameter changed that upgrades do
+    /// not take
+    Reinstall,
+}
+
+impl fmt::Display for Reconciliation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Reconciliation::NoOp => "no-op",
+            Reconciliation::Install => "install",
+            Reconciliation::Upgrade => "upgrade",
+            Reconciliation::Reinstall => "reinstall",
+        })
+    }
+}
+
+/// BundleChange is a change of the bundle of an installation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleChange {
+    /// The `stable_hash` of the installed bundle
+    pub from: String,
+    /// The `stable_hash` of the desired bundle
+    pub to: String,
+}
+
+/// ParameterChange is a parameter whose installed value is not the desired one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParameterChange {
+    pub name: String,
+    pub installed: Option<String>,
+    pub desired: Option<String>,
+}
+
+/// Drift is how an installation differs from its desired state, and what it takes
+/// to reconcile them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Drift {
+    pub reconciliation: Reconciliation,
+    /// Set when the bundle installed is not the desired one
+    pub bundle: Option<BundleChange>,
+    /// The parameters whose value changes, in name order
+    pub parameters: Vec<ParameterChange>,
+}
+
+impl Drift {
+    /// Whether anything needs to be run.
+    pub fn is_drifted(&self) -> bool {
+        self.reconciliation != Reconciliation::NoOp
+    }
+}
+
+/// Compare the last run of `installation` in `claims` with the `desired` bundle and
+/// parameter values.
+///
+/// A parameter without a desired value is taken to be at the default of its
+/// definition in the desired bundle. Since a claim store keeps only the latest
+/// claim of an installation, an action that failed there is compared as if it had
+/// been applied, and retried with an upgrade; an install that never succeeded is
+/// retried with a reinstall.
+pub fn detect_drift(
+    installation: &str,
+    claims: &dyn ClaimStore,
+    desired: &Bundle,
+    desired_parameters: &BTreeMap<String, String>,
+) -> Result<Drift, ClaimStoreError> {
+    let drift = |reconciliation| Drift {
+        reconciliation,
+        bundle: None,
+        parameters: Vec::new(),
+    };
+    let claim = match claims.read(installation)? {
+        Some(claim) => claim,
+        None => return Ok(drift(Reconciliation::Install)),
+    };
+    let status = claim.result.status();
+    let action = claim.result.action();
+    if action == "uninstall" && status == Status::Success {
+        return Ok(drift(Reconciliation::Install));
+    }
+
+    let (from, to) = (claim.bundle.stable_hash(), desired.stable_hash());
+    let bundle = Some(BundleChange { from, to }).filter(|c| c.from != c.to);
+    let installed_parameters = claim.parameters.unwrap_or_default();
+    let parameters = parameter_changes(desired, &installed_parameters, desired_parameters);
+
+    let not_upgradable = parameters.iter().any(|change| {
+        desired
+            .parameters
+            .as_ref()
+            .and_then(|p| p.get(&change.name))
+            .is_some_and(|p| !p.applies_to("upgrade"))
+    });
+    let reconciliation = if (action == "install" && status != Status::Success)
+        || claim.bundle.name != desired.name
+        || not_upgradable
+    {
+        Reconciliation::Reinstall
+    } else if bundle.is_some() || !parameters.is_empty() || status != Status::Success {
+        Reconciliation::Upgrade
+    } else {
+        Reconciliation::NoOp
+    };
+    Ok(Drift {
+        reconciliation,
+        bundle,
+        parameters,
+    })
+}
+
+/// The parameters of the desired bundle, and the desired parameters, whose value
+/// differs from the installed one.
+fn parameter_changes(
+    desired: &Bundle,
+    installed: &BTreeMap<String, String>,
+    values: &BTreeMap<String, String>,
+) -> Vec<ParameterChange> {
+    let declared = desired.parameters.iter().flatten().map(|(name, _)| name);
+    let names: BTreeSet<&String> = declared.chain(values.keys()).collect();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let default = || default_value(desired, name);
+            let installed = installed.get(name).cloned().or_else(default);
+            let desired = values.get(name).cloned().or_else(default);
+            (installed != desired).then(|| ParameterChange {
+                name: name.clone(),
+                installed,
+                desired,
+            })
+        })
+        .collect()
+}
+
+/// The default of a parameter's definition, in the form values are given in.
+fn default_value(bundle: &Bundle, parameter: &str) -> Option<String> {
+    let definition = bundle
+        .parameters
+        .as_ref()?
+        .get(parameter)?
+        .definition
+        .as_ref()?;
+    match bundle.definition(definition)?.get("default")? {
+        Value::String(s) => Some(s.clone()),
+        value => Some(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::claim::{Claim, Response};
+    use crate::claimstore::MemoryClaimStore;
+
+    fn bundle() -> Bundle {
+        Bundle::from_json(
+            r#"{
+                "name": "web",
+                "invocationImages": [{ "image": "example/web:1.0.0" }],
+                "schemaVersion": "v1.0.0",
+                "version": "1.0.0",
+                "definitions": {
+                    "port": { "type": "integer", "default": 8080 },
+                    "string": { "type": "string" }
+                },
+                "parameters": {
+                    "port": { "definition": "port", "destination": { "env": "PORT" } },
+                    "region": {
+                        "definition": "string",
+                        "applyTo": ["install"],
+                        "destination": { "env": "REGION" }
+                    }
+                }
+            }"#
+            .as_bytes(),
+        )
+        .unwrap()
+    }
+
+    fn installed(
+        bundle: &Bundle,
+        action: &str,
+        status: Status,
+        parameters: &[(&str, &str)],
+    ) -> MemoryClaimStore {
+        let claims = MemoryClaimStore::new();
+        let now = chrono::Utc::now();
+        claims
+            .store(&Claim {
+                bundle: bundle.clone(),
+                created: now,
+                custom: None,
+                modified: now,
+                name: "web".to_string(),
+                outputs: None,
+                parameters: Some(values(parameters)),
+                result: Response::new(action, status, None),
+                revision: crate::Ulid::new().to_string(),
+                bundle_reference: None,
+            })
+            .unwrap();
+        claims
+    }
+
+    fn values(parameters: &[(&str, &str)]) -> BTreeMap<String, String> {
+        parameters
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_detect_drift() {
+        let bundle = bundle();
+        let region = [("region", "eu")];
+        let store = installed(&bundle, "install", Status::Success, &region);
+        let drift = detect_drift("web", &store, &bundle, &values(&region)).unwrap();
+        assert_eq!(drift.reconciliation, Reconciliation::NoOp);
+        assert!(!drift.is_drifted());
+
+        // A parameter at its default is not drift, whether it is given or not.
+        let with_port = values(&[("region", "eu"), ("port", "8080")]);
+        let drift = detect_drift("web", &store, &bundle, &with_port).unwrap();
+        assert_eq!(drift.reconciliation, Reconciliation::NoOp);
+
+        let drift = detect_drift(
+            "web",
+            &store,
+            &bundle,
+            &values(&[("region", "eu"), ("port", "9090")]),
+        )
+        .unwrap();
+        assert_eq!(drift.reconciliation, Reconciliation::Upgrade);
+        assert_eq!(
+            drift.parameters,
+            vec![ParameterChange {
+                name: "port".to_string(),
+                installed: Some("8080".to_string()),
+                desired: Some("9090".to_string()),
+            }]
+        );
+
+        let mut newer = bundle.clone();
+        newer.version = semver::Version::new(1, 1, 0);
+        let drift = detect_drift("web", &store, &newer, &values(&region)).unwrap();
+        assert_eq!(drift.reconciliation, Reconciliation::Upgrade);
+        let change = drift.bundle.unwrap();
+        assert_eq!(
+            (change.from, change.to),
+            (bundle.stable_hash(), newer.stable_hash())
+        );
+
+        // Upgrades do not take the region.
+        let drift = detect_drift("web", &store, &bundle, &values(&[("region", "us")])).unwrap();
+        assert_eq!(drift.reconciliation, Reconciliation::Reinstall);
+
+        let mut renamed = bundle.clone();
+        renamed.name = "api".to_string();
+        let drift = detect_drift("web", &store, &renamed, &values(&region)).unwrap();
+        assert_eq!(drift.reconciliation, Reconciliation::Reinstall);
+    }
+
+    #[test]
+    fn test_detect_drift_after_failures() {
+        let bundle = bundle();
+        let region = values(&[("region", "eu")]);
+        let none = MemoryClaimStore::new();
+        assert_eq!(
+            detect_drift("web", &none, &bundle, &region)
+                .unwrap()
+                .reconciliation,
+            Reconciliation::Install
+        );
+
+        let cases = [
+            ("install", Status::Failure, Reconciliation::Reinstall),
+            ("upgrade", Status::Canceled, Reconciliation::Upgrade),
+            ("uninstall", Status::Success, Reconciliation::Install),
+            ("uninstall", Status::Failure, Reconciliation::Upgrade),
+        ];
+        for (action, status, expected) in cases {
+            let store = installed(&bundle, action, status, &[("region", "eu")]);
+            let drift = detect_drift("web", &store, &bundle, &region).unwrap();
+            assert_eq!(drift.reconciliation, expected, "{} {:?}", action, status);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use crate::error_code::{Coded, ErrorCode};
+use std::fmt;
+use std::str::FromStr;
+
+/// BundleReference addresses a bundle, or an image, in a registry:
+/// `registry/repository:tag@digest`.
+///
+/// References are normalized the way `docker` normalizes them. A first component
+/// without a `.` or a `:` that is not `localhost` is part of a Docker Hub
+/// repository, official images live under `library/`, and a reference without a
+/// tag or digest means the `latest` tag.
+///
+/// ```
+/// use libcnab::BundleReference;
+///
+/// let reference: BundleReference = "hello".parse().unwrap();
+/// assert_eq!(reference.to_string(), "docker.io/library/hello:latest");
+///
+/// let reference: BundleReference = "localhost:5000/bundles/hello@sha256:0123".parse().unwrap();
+/// assert_eq!(reference.registry, "localhost:5000");
+/// assert_eq!(reference.repository, "bundles/hello");
+/// assert_eq!(reference.tag, None);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BundleReference {
+    /// The registry host, with its port if it has one
+    pub registry: String,
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+impl BundleReference {
+    pub fn parse(reference: &str) -> Result<Self, InvalidReference> {
+        let invalid = || InvalidReference(reference.to_string());
+        let (rest, digest) = match reference.split_once('@') {
+            Some((rest, digest)) if digest.contains(':') => (rest, Some(digest.to_string())),
+            Some(_) => return Err(invalid()),
+            None => (reference, None),
+        };
+        let (rest, tag) = match rest.rsplit_once(':') {
+            Some((name, tag)) if !tag.contains('/') => (name, Some(tag.to_string())),
+            _ => (rest, None),
+        };
+        let (registry, repository) = match rest.split_once('/') {
+            Some((host, repository))
+                if host.contains('.') || host.contains(':') || host == "localhost" =>
+            {
+                (host.to_string(), repository.to_string())
+            }
+            Some(_) => ("docker.io".to_string(), rest.to_string()),
+            None => ("docker.io".to_string(), format!("library/{}", rest)),
+        };
+        let valid = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || "._-/".contains(c);
+        if repository.is_empty() || !repository.chars().all(valid) {
+            return Err(invalid());
+        }
+        let tag = match (tag, &digest) {
+            (None, None) => Some("latest".to_string()),
+            (tag, _) => tag,
+        };
+        Ok(BundleReference {
+            registry,
+            repository,
+            tag,
+            digest,
+        })
+    }
+
+    /// The registry and repository, without tag or digest.
+    pub fn name(&self) -> String {
+        format!("{}/{}", self.registry, self.repository)
+    }
+}
+
+impl FromStr for BundleReference {
+    type Err = InvalidReference;
+
+    fn from_str(reference: &str) -> Result<Self, Self::Err> {
+        BundleReference::parse(reference)
+    }
+}
+
+/// AsReference is what the registry APIs accept as a reference: a `BundleReference`,
+/// or a string that is parsed as one.
+pub trait AsReference: fmt::Debug {
+    fn to_reference(&self) -> Result<BundleReference, InvalidReference>;
+}
+
+impl AsReference for BundleReference {
+    fn to_reference(&self) -> Result<BundleReference, InvalidReference> {
+        Ok(self.clone())
+    }
+}
+
+impl AsReference for str {
+    fn to_reference(&self) -> Result<BundleReference, InvalidReference> {
+        BundleReference::parse(self)
+    }
+}
+
+impl AsReference for String {
+    fn to_reference(&self) -> Result<BundleReference, InvalidReference> {
+        BundleReference::parse(self)
+    }
+}
+
+impl fmt::Display for BundleReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.registry, self.repository)?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{}", tag)?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
+}
+
+/// InvalidReference is an image reference that could not be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidReference(pub String);
+
+impl fmt::Display for InvalidReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid reference {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidReference {}
+
+impl Coded for InvalidReference {
+    fn code(&self) -> ErrorCode {
+        ErrorCode::ReferenceInvalid
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_reference() {
+        let r = BundleReference::parse("helloworld").unwrap();
+        assert_eq!(r.registry, "docker.io");
+        assert_eq!(r.repository, "library/helloworld");
+        assert_eq!(r.tag.as_deref(), Some("latest"));
+        assert_eq!(r.to_string(), "docker.io/library/helloworld:latest");
+
+        let r = BundleReference::parse("localhost:5000/bundles/hello:0.1.0").unwrap();
+        assert_eq!(r.registry, "localhost:5000");
+        assert_eq!(r.repository, "bundles/hello");
+        assert_eq!(r.tag.as_deref(), Some("0.1.0"));
+
+        let r = BundleReference::parse("deislabs/hello:v1@sha256:abc").unwrap();
+        assert_eq!(r.repository, "deislabs/hello");
+        assert_eq!(r.digest.as_deref(), Some("sha256:abc"));
+        assert_eq!(r.name(), "docker.io/deislabs/hello");
+        assert_eq!(r.to_string(), "docker.io/deislabs/hello:v1@sha256:abc");
+        assert_eq!(
+            BundleReference::parse("hello@sha256:abc").unwrap().tag,
+            None
+        );
+        assert_eq!("hello".to_reference(), BundleReference::parse("hello"));
+
+        assert!(BundleReference::parse("Upper/Case").is_err());
+        assert!(BundleReference::parse("hello@latest").is_err());
+    }
+}
@@ -0,0 +1,2544 @@
+//! Publishing bundles to OCI registries.
+//!
+//! Bundles are stored the way [cnab-to-oci](https://github.com/cnabio/cnab-to-oci)
+//! stores them: an OCI index whose `config` entry points at a manifest holding the
+//! `bundle.json` as its config blob, followed by one entry for each invocation image
+//! and each component image. The images must live in the same repository as the
+//! index, so pulling a bundle also tells where its images can be found now.
+//!
+//! ```no_run
+//! let pulled = libcnab::registry::pull("example.com/bundles/helloworld:0.1.0").unwrap();
+//! println!("{} {}", pulled.bundle.name, pulled.digest);
+//! ```
+use self::tls::RegistryTls;
+use self::token::{Challenge, Token};
+use crate::cnab::{is_oci_image, Bundle, BundleParseError};
+use crate::error_code::{Coded, ErrorCode};
+use crate::metrics::{
+    result_label, Metrics, CACHE_HITS, CACHE_MISSES, PULL_DURATION, PUSH_DURATION,
+};
+use crate::proxy::ProxyConfig;
+use crate::reference::{AsReference, BundleReference, InvalidReference};
+use crate::relocation::{Mirrors, RelocationMap};
+use semver::{Version, VersionReq};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use ureq::tls::ClientCert;
+
+#[cfg(feature = "tokio")]
+mod asynchronous;
+#[cfg(feature = "tokio")]
+pub use self::asynchronous::*;
+mod auth;
+pub use self::auth::*;
+mod cache;
+pub use self::cache::*;
+mod layers;
+pub use self::layers::*;
+mod oci;
+pub use self::oci::*;
+mod progress;
+pub use self::progress::*;
+mod referrers;
+pub use self::referrers::*;
+mod sbom;
+pub use self::sbom::*;
+#[cfg(test)]
+pub(crate) mod testing;
+mod tls;
+mod token;
+
+/// The largest manifest the client reads
+pub(crate) const MANIFEST_LIMIT: u64 = 4 * 1024 * 1024;
+/// The largest `bundle.json` the client reads
+const CONFIG_LIMIT: u64 = 64 * 1024 * 1024;
+/// The most blobs a client transfers at once, unless `Client::parallelism` says
+/// otherwise
+pub const DEFAULT_PARALLELISM: usize = 8;
+
+/// The `sha256:<hex>` digest of some content.
+pub(crate) fn sha256_digest(content: &[u8]) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(content)))
+}
+
+/// The digest of `content` by the algorithm of `digest`, to compare with it, or
+/// `None` if that is neither `sha256` nor `sha512`.
+pub(crate) fn digest_like(digest: &str, content: &[u8]) -> Option<String> {
+    let mut hasher = Hasher::for_digest(digest)?;
+    hasher.update(content);
+    Some(hasher.finish())
+}
+
+/// The hash of content as it streams by, by the algorithm a digest names.
+#[derive(Clone)]
+enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    fn for_digest(digest: &str) -> Option<Self> {
+        match digest.split_once(':')?.0 {
+            "sha256" => Some(Hasher::Sha256(Sha256::new())),
+            "sha512" => Some(Hasher::Sha512(Sha512::new())),
+            _ => None,
+        }
+    }
+
+    fn update(&mut self, content: &[u8]) {
+        match self {
+            Hasher::Sha256(h) => h.update(content),
+            Hasher::Sha512(h) => h.update(content),
+        }
+    }
+
+    fn finish(self) -> String {
+        match self {
+            Hasher::Sha256(h) => format!("sha256:{}", hex::encode(h.finalize())),
+            Hasher::Sha512(h) => format!("sha512:{}", hex::encode(h.finalize())),
+        }
+    }
+}
+
+/// Check `content` against the `digest` it was fetched by from `url`.
+fn check_digest(url: &str, digest: &str, content: &[u8]) -> Result<(), RegistryError> {
+    let actual = digest_like(digest, content)
+        .ok_or_else(|| RegistryError::UnsupportedDigest(digest.to_string()))?;
+    if actual != digest {
+        return Err(RegistryError::DigestMismatch {
+            url: url.to_string(),
+            expected: digest.to_string(),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Run `f` on each of `items` with a pool of at most `limit` threads, stopping at the
+/// first error.
+pub(crate) fn in_parallel<T, E, F>(limit: usize, items: &[T], f: F) -> Result<(), E>
+where
+    T: Sync,
+    E: Send,
+    F: Fn(&T) -> Result<(), E> + Sync,
+{
+    let workers = limit.max(1).min(items.len());
+    if workers <= 1 {
+        return items.iter().try_for_each(f);
+    }
+    let next = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    while let Some(item) = items.get(next.fetch_add(1, Ordering::SeqCst)) {
+                        if let Err(e) = f(item) {
+                            // The other workers finish the item they are on.
+                            next.store(items.len(), Ordering::SeqCst);
+                            return Err(e);
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .try_for_each(|w| w.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+    })
+}
+
+/// Verified reads a blob, failing at its end unless it had the size and the
+/// digest its descriptor says it has. A digest by an algorithm other than `sha256`
+/// or `sha512` fails the first read.
+pub(crate) struct Verified<R> {
+    inner: R,
+    hasher: Option<Hasher>,
+    digest: String,
+    size: u64,
+    read: u64,
+}
+
+impl<R: Read> Verified<R> {
+    pub(crate) fn new(inner: R, digest: &str, size: u64) -> Self {
+        Verified {
+            inner,
+            hasher: Hasher::for_digest(digest),
+            digest: digest.to_string(),
+            size,
+            read: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for Verified<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let Some(hasher) = self.hasher.as_mut() else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unsupported digest {}", self.digest),
+            ));
+        };
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        if self.read > self.size || (n == 0 && !buf.is_empty() && self.read < self.size) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} is {} bytes, not {}", self.digest, self.read, self.size),
+            ));
+        }
+        hasher.update(&buf[..n]);
+        if n == 0 && !buf.is_empty() {
+            let actual = hasher.clone().finish();
+            if actual != self.digest {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("expected digest {}, got {}", self.digest, actual),
+                ));
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl BundleReference {
+    /// The host of the registry's API.
+    pub(crate) fn host(&self) -> &str {
+        match self.registry.as_str() {
+            "docker.io" => "registry-1.docker.io",
+            host => host,
+        }
+    }
+
+    fn is_local(&self) -> bool {
+        let host = self.host();
+        ["localhost", "127.0.0.1", "[::1]"]
+            .iter()
+            .any(|h| host == *h || host.starts_with(&format!("{}:", h)))
+    }
+
+    /// The tag or digest to ask the registry for.
+    pub(crate) fn reference(&self) -> &str {
+        self.digest
+            .as_deref()
+            .or(self.tag.as_deref())
+            .unwrap_or("latest")
+    }
+
+    /// Address other content of the same repository by digest.
+    pub(crate) fn with_digest(&self, digest: &str) -> String {
+        format!("{}/{}@{}", self.registry, self.repository, digest)
+    }
+
+    pub(crate) fn same_repository(&self, other: &BundleReference) -> bool {
+        self.registry == other.registry && self.repository == other.repository
+    }
+}
+
+/// PulledBundle is a bundle as it was found in a registry.
+#[derive(Debug, Clone)]
+pub struct PulledBundle {
+    pub bundle: Bundle,
+    /// The digest of the bundle's index
+    pub digest: String,
+    /// Where each image of the bundle can be pulled from the bundle's repository
+    pub relocation: RelocationMap,
+}
+
+/// PushedBundle is the outcome of pushing a bundle.
+#[derive(Debug, Clone)]
+pub struct PushedBundle {
+    /// The digest of the bundle's index
+    pub digest: String,
+    /// Where each image of the bundle was copied to
+    pub relocation: RelocationMap,
+}
+
+/// ImageVerification is what `Bundle::verify_image_digests` found for one image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageVerification {
+    /// The image as the bundle references it
+    pub image: String,
+    /// The name of the component image, or `None` for an invocation image
+    pub component: Option<String>,
+    pub status: ImageStatus,
+}
+
+impl ImageVerification {
+    pub fn is_verified(&self) -> bool {
+        matches!(self.status, ImageStatus::Verified { .. })
+    }
+}
+
+/// ImageStatus says how an image in its registry compares to its bundle.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageStatus {
+    /// The registry serves the content the bundle records the digest of
+    Verified { digest: String },
+    /// The registry serves other content than the bundle records
+    Mismatch { expected: String, actual: String },
+    /// The bundle records no digest for the image, so there is nothing to compare
+    /// the registry's content with
+    Unpinned { actual: String },
+    /// The registry does not have the image
+    Missing,
+}
+
+/// BundleVersion is a tag of a repository that holds a bundle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BundleVersion {
+    pub tag: String,
+    /// The digest of the manifest the tag points at
+    pub digest: String,
+    /// The `version` of the bundle
+    pub version: String,
+}
+
+#[derive(serde::Deserialize)]
+struct TagList {
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+}
+
+/// Content as a registry returned it.
+pub(crate) struct Fetched {
+    pub media_type: String,
+    pub digest: String,
+    pub body: Vec<u8>,
+}
+
+/// Client talks to OCI registries.
+///
+/// Credentials given with `Client::credential` take precedence over the ones in the
+/// docker configuration, which is read from its default location unless another one
+/// is given with `Client::docker_config`. Registries that answer with a `Bearer`
+/// challenge, such as Docker Hub, GHCR and ACR, are sent the tokens their token
+/// service issues for the credential, or anonymous ones when there is none.
+///
+/// Registries are spoken to over TLS, verified against the public roots, except
+/// for the ones on the local host and those allowed with `Client::insecure_registry`.
+/// Requests go through the proxies of `ProxyConfig::from_env`, unless others are
+/// given with `Client::proxy`, and the progress of the operations that move images
+/// is reported to the sink given with `Client::progress`.
+/// A registry with its own PKI can be trusted with `Client::ca_certificates`:
+///
+/// ```no_run
+/// use libcnab::registry::Client;
+///
+/// let ca = std::fs::read("internal-ca.pem").unwrap();
+/// let client = Client::new()
+///     .ca_certificates("registry.internal", &ca)
+///     .unwrap();
+/// let pulled = client.pull("registry.internal/bundles/hello:0.1.0").unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Client {
+    /// The agent of each registry spoken to so far
+    agents: Mutex<BTreeMap<String, ureq::Agent>>,
+    tls: BTreeMap<String, RegistryTls>,
+    insecure: BTreeSet<String>,
+    proxy: ProxyConfig,
+    docker_config: DockerConfig,
+    credentials: BTreeMap<String, RegistryCredential>,
+    /// The credentials found for each registry so far
+    resolved: Mutex<BTreeMap<String, Option<RegistryCredential>>>,
+    /// The challenge of each registry that asked for bearer tokens
+    challenges: Mutex<BTreeMap<String, Challenge>>,
+    /// The tokens issued so far, by registry and scopes
+    tokens: Mutex<BTreeMap<(String, String), Token>>,
+    cache: Option<Cache>,
+    layers: Option<LayerStore>,
+    mirrors: Mirrors,
+    progress: Option<Arc<dyn ProgressSink>>,
+    metrics: Option<Arc<dyn Metrics>>,
+    parallelism: usize,
+}
+
+impl Default for Client {
+    /// A broken docker configuration is ignored, as if there were none.
+    fn default() -> Self {
+        Client {
+            agents: Mutex::new(BTreeMap::new()),
+            tls: BTreeMap::new(),
+            insecure: BTreeSet::new(),
+            proxy: ProxyConfig::from_env(),
+            docker_config: DockerConfig::from_env().unwrap_or_default(),
+            credentials: BTreeMap::new(),
+            resolved: Mutex::new(BTreeMap::new()),
+            challenges: Mutex::new(BTreeMap::new()),
+            tokens: Mutex::new(BTreeMap::new()),
+            cache: None,
+            layers: None,
+            mirrors: Mirrors::new(),
+            progress: None,
+            metrics: None,
+            parallelism: DEFAULT_PARALLELISM,
+        }
+    }
+}
+
+impl Client {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Look up credentials in `config` instead of the default docker configuration.
+    pub fn docker_config(mut self, config: DockerConfig) -> Self {
+        self.docker_config = config;
+        self.resolved = Mutex::new(BTreeMap::new());
+        self
+    }
+
+    /// Authenticate to `registry`, a host such as `ghcr.io`, with `credential`.
+    pub fn credential(mut self, registry: &str, credential: RegistryCredential) -> Self {
+        self.credentials.insert(registry.to_string(), credential);
+        self
+    }
+
+    /// Keep what is pulled in `cache`, and look for it there first.
+    pub fn cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Pull bundles and images from the mirrors `mirrors` sends them to, and resolve
+    /// their tags and digests there. Pushes go where they are told to.
+    pub fn mirrors(mut self, mirrors: Mirrors) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
+    /// Where to read `reference` from.
+    pub(crate) fn mirrored(&self, reference: BundleReference) -> BundleReference {
+        self.mirrors.mirror(&reference).unwrap_or(reference)
+    }
+
+    /// Keep the layers that exports of thick bundles fetch in `store`, and look for
+    /// them there first.
+    pub fn layer_store(mut self, store: LayerStore) -> Self {
+        self.layers = Some(store);
+        self
+    }
+
+    #[cfg(feature = "thick")]
+    pub(crate) fn layers(&self) -> Option<&LayerStore> {
+        self.layers.as_ref()
+    }
+
+    /// Report the progress of pulls, pushes and copies, and of the exports and
+    /// imports of thick bundles, to `sink`.
+    ///
+    /// ```no_run
+    /// use libcnab::registry::{Client, Progress};
+    ///
+    /// let client = Client::new().progress(|progress: &Progress| match progress {
+    ///     Progress::Phase(phase) => eprintln!("{}", phase),
+    ///     Progress::Layers { done, total } => eprintln!("{}/{} layers", done, total),
+    ///     Progress::Bytes { .. } => {}
+    /// });
+    /// let pulled = client.pull("example.com/bundles/hello:0.1.0").unwrap();
+    /// client.push(&pulled.bundle, "registry.internal/bundles/hello:0.1.0").unwrap();
+    /// ```
+    pub fn progress<S: ProgressSink + 'static>(mut self, sink: S) -> Self {
+        self.progress = Some(Arc::new(sink));
+        self
+    }
+
+    /// Transfer at most `limit` blobs at once, `DEFAULT_PARALLELISM` unless this is
+    /// called: when copying the layers of an image, and when exporting or importing
+    /// a thick bundle.
+    ///
+    /// With a limit of 1, blobs are transferred one at a time, and imports stream
+    /// each blob from the archive to the registry. With more, imports keep the large
+    /// blobs that are read ahead of their upload in a temporary directory.
+    pub fn parallelism(mut self, limit: usize) -> Self {
+        self.parallelism = limit.max(1);
+        self
+    }
+
+    /// The most blobs to transfer at once.
+    pub(crate) fn parallel_transfers(&self) -> usize {
+        self.parallelism
+    }
+
+    /// Time pulls and pushes, as `PULL_DURATION` and `PUSH_DURATION`, and count the
+    /// lookups in the cache, in `metrics`.
+    pub fn metrics<M: Metrics + 'static>(mut self, metrics: M) -> Self {
+        self.metrics = Some(Arc::new(metrics));
+        self
+    }
+
+    /// Time `operation` as the timer `name`.
+    fn timed<T>(
+        &self,
+        name: &str,
+        operation: impl FnOnce() -> Result<T, RegistryError>,
+    ) -> Result<T, RegistryError> {
+        let started = std::time::Instant::now();
+        let result = operation();
+        if let Some(metrics) = &self.metrics {
+            metrics.timer(
+                name,
+                &[("result", result_label(&result))],
+                started.elapsed(),
+            );
+        }
+        result
+    }
+
+    /// Count a lookup of a `kind` of content in the cache.
+    fn cached<T>(&self, kind: &str, found: Option<T>) -> Option<T> {
+        if let Some(metrics) = &self.metrics {
+            let name = if found.is_some() {
+                CACHE_HITS
+            } else {
+                CACHE_MISSES
+            };
+            metrics.counter(name, &[("kind", kind)], 1);
+        }
+        found
+    }
+
+    /// A tracker of the progress of an operation.
+    pub(crate) fn tracker(&self) -> Tracker<'_> {
+        Tracker::new(self.progress.as_deref())
+    }
+
+    /// Verify `registry`, a host such as `registry.internal:5000`, against the CA
+    /// certificates of the PEM file `pem` instead of the public roots.
+    pub fn ca_certificates(mut self, registry: &str, pem: &[u8]) -> Result<Self, RegistryError> {
+        let roots = tls::certificates(pem)?;
+        self.tls.entry(registry.to_string()).or_default().roots = roots;
+        self.agents = Mutex::new(BTreeMap::new());
+        Ok(self)
+    }
+
+    /// Present the certificate chain `certificate` and its private key `key`, both
+    /// PEM files, to `registry` when it asks for a client certificate.
+    pub fn client_certificate(
+        mut self,
+        registry: &str,
+        certificate: &[u8],
+        key: &[u8],
+    ) -> Result<Self, RegistryError> {
+        let chain = tls::certificates(certificate)?;
+        let key = tls::private_key(key)?;
+        self.tls
+            .entry(registry.to_string())
+            .or_default()
+            .client_certificate = Some(ClientCert::new_with_certs(&chain, key));
+        self.agents = Mutex::new(BTreeMap::new());
+        Ok(self)
+    }
+
+    /// Speak to `registry` over plain HTTP, like docker's `insecure-registries`.
+    /// Credentials sent to it can be read by anyone on the way.
+    pub fn insecure_registry(mut self, registry: &str) -> Self {
+        self.insecure.insert(registry.to_string());
+        self.agents = Mutex::new(BTreeMap::new());
+        self
+    }
+
+    /// Go through the proxies of `proxy` instead of the ones in the environment.
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = proxy;
+        self.agents = Mutex::new(BTreeMap::new());
+        self
+    }
+
+    /// The agent to send requests to the registry of `repository` with, made for its
+    /// TLS settings and proxy the first time it is needed.
+    fn agent(&self, repository: &BundleReference) -> Result<ureq::Agent, RegistryError> {
+        let mut agents = self.agents.lock().expect("lock poisoned");
+        if let Some(agent) = agents.get(&repository.registry) {
+            return Ok(agent.clone());
+        }
+        let tls = self
+            .tls
+            .get(&repository.registry)
+            .map(RegistryTls::config)
+            .unwrap_or_default();
+        let agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .tls_config(tls)
+            .proxy(self.proxy.proxy(&self.base(repository))?)
+            .build()
+            .new_agent();
+        agents.insert(repository.registry.clone(), agent.clone());
+        Ok(agent)
+    }
+
+    /// The scheme and host of the API of the registry of `repository`.
+    fn base(&self, repository: &BundleReference) -> String {
+        // Like docker, only registries on the local host and the ones allowed as
+        // insecure are spoken to without TLS.
+        let insecure = repository.is_local() || self.insecure.contains(&repository.registry);
+        let scheme = if insecure { "http" } else { "https" };
+        format!("{}://{}", scheme, repository.host())
+    }
+
+    /// The base URL of `repository` in its registry's API.
+    fn url(&self, repository: &BundleReference) -> String {
+        format!("{}/v2/{}", self.base(repository), repository.repository)
+    }
+
+    /// The credential to send to the registry of `repository`, if any.
+    fn credential_for(
+        &self,
+        repository: &BundleReference,
+    ) -> Result<Option<RegistryCredential>, RegistryError> {
+        if let Some(credential) = self.credentials.get(&repository.registry) {
+            return Ok(Some(credential.clone()));
+        }
+        let mut resolved = self.resolved.lock().expect("lock poisoned");
+        if let Some(credential) = resolved.get(&repository.registry) {
+            return Ok(credential.clone());
+        }
+        let credential = self.docker_config.credential(&repository.registry)?;
+        resolved.insert(repository.registry.clone(), credential.clone());
+        Ok(credential)
+    }
+
+    /// Authorize `request` to `repository` for what its method does: `pull` for
+    /// reads, and `push` too for the rest.
+    fn authorized<B>(
+        &self,
+        repository: &BundleReference,
+        request: ureq::RequestBuilder<B>,
+    ) -> Result<ureq::RequestBuilder<B>, RegistryError> {
+        let read = matches!(
+            request.method_ref(),
+            Some(&ureq::http::Method::GET) | Some(&ureq::http::Method::HEAD)
+        );
+        let actions = if read { "pull" } else { "pull,push" };
+        self.authorized_for(repository, &[scope(repository, actions)], request)
+    }
+
+    /// Authorize `request` to the registry of `repository`, with a token for `scopes`
+    /// if the registry asked for tokens, or else with the registry's credential.
+    fn authorized_for<B>(
+        &self,
+        repository: &BundleReference,
+        scopes: &[String],
+        request: ureq::RequestBuilder<B>,
+    ) -> Result<ureq::RequestBuilder<B>, RegistryError> {
+        let credential = self.credential_for(repository)?;
+        let challenge = self
+            .challenges
+            .lock()
+            .expect("lock poisoned")
+            .get(&repository.registry)
+            .cloned();
+        let header = match (&credential, challenge) {
+            // A token from the configuration is sent as is.
+            (Some(RegistryCredential::Bearer(token)), _) => Some(format!("Bearer {}", token)),
+            (_, Some(challenge)) => {
+                let token = self.token(repository, &challenge, scopes, credential.as_ref())?;
+                Some(format!("Bearer {}", token))
+            }
+            (credential, None) => credential.as_ref().and_then(RegistryCredential::header),
+        };
+        Ok(match header {
+            Some(header) => request.header("Authorization", header),
+            None => request,
+        })
+    }
+
+    /// A token for `scopes` in the registry of `repository`, issued again once it
+    /// is about to expire.
+    fn token(
+        &self,
+        repository: &BundleReference,
+        challenge: &Challenge,
+        scopes: &[String],
+        credential: Option<&RegistryCredential>,
+    ) -> Result<String, RegistryError> {
+        let key = (repository.registry.clone(), scopes.join(" "));
+        {
+            let tokens = self.tokens.lock().expect("lock poisoned");
+            if let Some(token) = tokens.get(&key).filter(|t| t.is_fresh()) {
+                return Ok(token.value.clone());
+            }
+        }
+        let token = token::fetch(&self.agent(repository)?, challenge, scopes, credential)?;
+        let value = token.value.clone();
+        self.tokens
+            .lock()
+            .expect("lock poisoned")
+            .insert(key, token);
+        Ok(value)
+    }
+
+    /// Send the request `request` builds, building it again to answer the registry's
+    /// challenge when it asks for a token, or for a new one because the one sent
+    /// expired or was revoked. The request is sent at most `AUTH_ATTEMPTS` times.
+    fn send<F>(
+        &self,
+        repository: &BundleReference,
+        mut request: F,
+    ) -> Result<Response, RegistryError>
+    where
+        F: FnMut() -> Result<Response, RegistryError>,
+    {
+        let mut attempts = 1;
+        loop {
+            let response = request()?;
+            if response.status().as_u16() != 401 || attempts == AUTH_ATTEMPTS {
+                return Ok(response);
+            }
+            let challenge = match header(&response, "WWW-Authenticate")
+                .as_deref()
+                .and_then(Challenge::parse)
+            {
+                Some(challenge) if challenge.is_bearer() => challenge,
+                _ => return Ok(response),
+            };
+            #[cfg(feature = "tracing")]
+            tracing::debug!(registry = %repository.registry, "registry asked for a new token");
+            self.tokens
+                .lock()
+                .expect("lock poisoned")
+                .retain(|(registry, _), _| *registry != repository.registry);
+            self.challenges
+                .lock()
+                .expect("lock poisoned")
+                .insert(repository.registry.clone(), challenge);
+            attempts += 1;
+        }
+    }
+
+    /// Pull the bundle stored at `reference`.
+    ///
+    /// Everything is checked against the digest it was fetched by: the `bundle.json`
+    /// against the digest of the config blob, and the index against the digest in
+    /// `reference` when it has one, such as `example.com/hello:0.1.0@sha256:...`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self), err))]
+    pub fn pull<R: AsReference + ?Sized>(
+        &self,
+        reference: &R,
+    ) -> Result<PulledBundle, RegistryError> {
+        let reference = self.mirrored(reference.to_reference()?);
+        self.timed(PULL_DURATION, || self.pull_reference(&reference))
+    }
+
+    fn pull_reference(&self, reference: &BundleReference) -> Result<PulledBundle, RegistryError> {
+        self.tracker().phase(Phase::Resolving);
+        let top = self.manifest(reference, reference.reference())?;
+        let (index, config) = match top.media_type.as_str() {
+            OCI_INDEX | DOCKER_MANIFEST_LIST => {
+                let index: Index = parse(reference, &top.body)?;
+                let entry = index
+                    .manifests
+                    .iter()
+                    .find(|m| m.annotation(MANIFEST_TYPE_ANNOTATION) == Some("config"))
+                    .ok_or_else(|| RegistryError::NotABundle(reference.to_string()))?;
+                let config: Manifest =
+                    parse(reference, &self.manifest(reference, &entry.digest)?.body)?;
+                (Some(index), config)
+            }
+            // A bundle pushed without its images is just the config manifest.
+            _ => (None, parse(reference, &top.body)?),
+        };
+        if config.config.media_type != CNAB_CONFIG {
+            return Err(RegistryError::NotABundle(reference.to_string()));
+        }
+        let blob = self.blob(reference, &config.config.digest, CONFIG_LIMIT)?;
+        let bundle = Bundle::from_json(&blob[..])?;
+
+        let mut relocation = RelocationMap::new();
+        let entries = index.iter().flat_map(|i| i.manifests.iter());
+        let mut invocation_images = bundle.invocation_images.iter();
+        for entry in entries {
+            let original = match entry.annotation(MANIFEST_TYPE_ANNOTATION) {
+                Some("invocation") => invocation_images.next().map(|i| &i.image),
+                Some("component") => entry
+                    .annotation(COMPONENT_NAME_ANNOTATION)
+                    .and_then(|name| bundle.images.as_ref()?.get(name))
+                    .map(|i| &i.image),
+                _ => None,
+            };
+            if let Some(original) = original {
+                relocation.insert(original, &reference.with_digest(&entry.digest));
+            }
+        }
+        Ok(PulledBundle {
+            bundle,
+            digest: top.digest,
+            relocation,
+        })
+    }
+
+    /// The digest of the manifest `reference` points at, such as an image tag.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self), err))]
+    pub fn resolve_digest<R: AsReference + ?Sized>(
+        &self,
+        reference: &R,
+    ) -> Result<String, RegistryError> {
+        let reference = self.mirrored(reference.to_reference()?);
+        self.resolve(&reference, reference.reference())
+    }
+
+    fn resolve(
+        &self,
+        repository: &BundleReference,
+        reference: &str,
+    ) -> Result<String, RegistryError> {
+        let url = format!("{}/manifests/{}", self.url(repository), reference);
+        let mut response = self.send(repository, || {
+            Ok(self
+                .authorized(repository, self.agent(repository)?.head(&url))?
+                .header("Accept", MANIFEST_TYPES.join(", "))
+                .call()?)
+        })?;
+        check(&url, &mut response)?;
+        match header(&response, "Docker-Content-Digest") {
+            Some(digest) => Ok(digest),
+            // Not every registry says, but the digest of the content is the same.
+            None => Ok(self.fetch_manifest(repository, reference)?.digest),
+        }
+    }
+
+    /// The tags of `repository`, such as `example.com/bundles/hello`, in the order the
+    /// registry lists them. A tag or digest in `repository` is ignored.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self), err))]
+    pub fn list_tags<R: AsReference + ?Sized>(
+        &self,
+        repository: &R,
+    ) -> Result<Vec<String>, RegistryError> {
+        self.tags(&self.mirrored(repository.to_reference()?))
+    }
+
+    fn tags(&self, repository: &BundleReference) -> Result<Vec<String>, RegistryError> {
+        let mut tags = Vec::new();
+        let mut url = format!("{}/tags/list", self.url(repository));
+        loop {
+            let mut response = self.send(repository, || {
+                Ok(self
+                    .authorized(repository, self.agent(repository)?.get(&url))?
+                    .call()?)
+            })?;
+            check(&url, &mut response)?;
+            let next =
+                header(&response, "Link").and_then(|link| next_link(&self.base(repository), &link));
+            let body = response
+                .body_mut()
+                .with_config()
+                .limit(MANIFEST_LIMIT)
+                .read_to_vec()?;
+            let page: TagList = parse(repository, &body)?;
+            tags.extend(page.tags.unwrap_or_default());
+            match next {
+                Some(next) => url = next,
+                None => return Ok(tags),
+            }
+        }
+    }
+
+    /// The tags of `repository` that hold a bundle, with the bundle's version. Tags of
+    /// anything else, such as the bundle's images, are left out.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self), err))]
+    pub fn list_bundle_versions<R: AsReference + ?Sized>(
+        &self,
+        repository: &R,
+    ) -> Result<Vec<BundleVersion>, RegistryError> {
+        let repository = self.mirrored(repository.to_reference()?);
+        let mut versions = Vec::new();
+        for tag in self.tags(&repository)? {
+            let manifest = self.manifest(&repository, &tag)?;
+            let version = match manifest.media_type.as_str() {
+                OCI_INDEX | DOCKER_MANIFEST_LIST => {
+                    let index: Index = parse(&repository, &manifest.body)?;
+                    let annotations = index.annotations.unwrap_or_default();
+                    if annotations
+                        .get(ARTIFACT_TYPE_ANNOTATION)
+                        .map(String::as_str)
+                        != Some(CNAB_ARTIFACT_TYPE)
+                    {
+                        continue;
+                    }
+                    match annotations.get(VERSION_ANNOTATION) {
+                        Some(version) => version.clone(),
+                        None => self
+                            .pull_reference(&BundleReference {
+                                tag: Some(tag.clone()),
+                                digest: None,
+                                ..repository.clone()
+                            })?
+                            .bundle
+                            .version
+                            .to_string(),
+                    }
+                }
+                _ => match parse::<Manifest>(&repository, &manifest.body) {
+                    Ok(config) if config.config.media_type == CNAB_CONFIG => {
+                        let blob = self.blob(&repository, &config.config.digest, CONFIG_LIMIT)?;
+                        Bundle::from_json(&blob[..])?.version.to_string()
+                    }
+                    _ => continue,
+                },
+            };
+            versions.push(BundleVersion {
+                tag,
+                digest: manifest.digest,
+                version,
+            });
+        }
+        Ok(versions)
+    }
+
+    /// The newest bundle version in `repository` that matches `requirement`, a semver
+    /// requirement such as `>=1.2, <2`.
+    ///
+    /// Pre-releases only match a requirement that names a pre-release of the same
+    /// version, as with cargo. Versions that are not semver are never matched, and of
+    /// the tags of the same version the first listed wins.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self), err))]
+    pub fn resolve_version<R: AsReference + ?Sized>(
+        &self,
+        repository: &R,
+        requirement: &str,
+    ) -> Result<BundleVersion, RegistryError> {
+        let repository = repository.to_reference()?;
+        let req = VersionReq::parse(requirement).map_err(|e| {
+            RegistryError::InvalidVersionRequirement(format!("{}: {}", requirement, e))
+        })?;
+        let mut best: Option<(Version, BundleVersion)> = None;
+        for candidate in self.list_bundle_versions(&repository)? {
+            let version = match Version::parse(&candidate.version) {
+                Ok(version) if req.matches(&version) => version,
+                _ => continue,
+            };
+            if best.as_ref().is_none_or(|(newest, _)| version > *newest) {
+                best = Some((version, candidate));
+            }
+        }
+        best.map(|(_, version)| version)
+            .ok_or_else(|| RegistryError::NoMatchingVersion {
+                repository: repository.name(),
+                requirement: requirement.to_string(),
+            })
+    }
+
+    /// Push `bundle` to `reference`.
+    ///
+    /// Every invocation image and component image is copied into the bundle's
+    /// repository first, by its `contentDigest` when the bundle records one. The
+    /// bundle itself is not changed: its images keep their original references, and
+    /// the returned relocation map says where the copies are.
+    pub fn push<R: AsReference + ?Sized>(
+        &self,
+        bundle: &Bundle,
+        reference: &R,
+    ) -> Result<PushedBundle, RegistryError> {
+        self.push_relocated(bundle, &RelocationMap::new(), reference)
+    }
+
+    /// Push a bundle whose images were relocated, copying them from where `relocation`
+    /// says they are now. The returned relocation map is keyed by the bundle's
+    /// original references all the same.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, bundle, relocation), fields(bundle = %bundle.name), err)
+    )]
+    pub fn push_relocated<R: AsReference + ?Sized>(
+        &self,
+        bundle: &Bundle,
+        relocation: &RelocationMap,
+        reference: &R,
+    ) -> Result<PushedBundle, RegistryError> {
+        let target = reference.to_reference()?;
+        self.timed(PUSH_DURATION, || {
+            self.push_tracked(bundle, relocation, &target, &self.tracker())
+        })
+    }
+
+    /// Push a bundle the way `push_relocated` does, as part of the operation
+    /// `tracker` tracks.
+    pub(crate) fn push_tracked(
+        &self,
+        bundle: &Bundle,
+        relocation: &RelocationMap,
+        target: &BundleReference,
+        tracker: &Tracker<'_>,
+    ) -> Result<PushedBundle, RegistryError> {
+        let mut pushed = RelocationMap::new();
+        if target.digest.is_some() {
+            return Err(RegistryError::InvalidReference(format!(
+                "{}: bundles are pushed to a tag",
+                target
+            )));
+        }
+        tracker.phase(Phase::Transferring);
+        let config = config_blob(bundle);
+        self.put_blob(target, &config)?;
+        let config_manifest =
+            serde_json::to_vec(&Manifest::bundle_config(&config)).expect("manifests serialize");
+        self.put_manifest(target, None, OCI_MANIFEST, &config_manifest)?;
+        let mut index =
+            IndexBuilder::new(bundle).config(Descriptor::of(OCI_MANIFEST, &config_manifest));
+
+        for image in &bundle.invocation_images {
+            let source = self.mirrored(image_reference(
+                relocation.relocate(&image.image),
+                image.image_type.as_deref(),
+                image.content_digest.as_deref(),
+            )?);
+            let descriptor = self.copy_manifest(&source, target, source.reference(), tracker)?;
+            pushed.insert(&image.image, &target.with_digest(&descriptor.digest));
+            index = index.invocation_image(descriptor);
+        }
+        for (name, image) in bundle.images.iter().flatten() {
+            let source = self.mirrored(image_reference(
+                relocation.relocate(&image.image),
+                image.image_type.as_deref(),
+                image.content_digest.as_deref(),
+            )?);
+            let descriptor = self.copy_manifest(&source, target, source.reference(), tracker)?;
+            pushed.insert(&image.image, &target.with_digest(&descriptor.digest));
+            index = index.component(name, descriptor);
+        }
+
+        tracker.phase(Phase::Publishing);
+        let index = serde_json::to_vec(&index.build()).expect("indexes serialize");
+        let digest = self.put_manifest(target, Some(target.reference()), OCI_INDEX, &index)?;
+        Ok(PushedBundle {
+            digest,
+            relocation: pushed,
+        })
+    }
+
+    /// Copy the bundle stored at `source`, with all of its images, to `target`.
+    ///
+    /// Blobs are streamed from one registry to the other, or mounted when both
+    /// repositories are in the same registry, so nothing is kept on disk. The bundle
+    /// is pushed unchanged, so `target` has the same digest as `source` when their
+    /// images were stored the same way.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self), err))]
+    pub fn copy<S, T>(&self, source: &S, target: &T) -> Result<PushedBundle, RegistryError>
+    where
+        S: AsReference + ?Sized,
+        T: AsReference + ?Sized,
+    {
+        let pulled = self.pull(source)?;
+        self.push_relocated(&pulled.bundle, &pulled.relocation, target)
+    }
+
+    /// Copy the manifest `reference` of `source`, and everything it refers to, into
+    /// `target`. Returns the manifest's descriptor.
+    fn copy_manifest(
+        &self,
+        source: &BundleReference,
+        target: &BundleReference,
+        reference: &str,
+        tracker: &Tracker<'_>,
+    ) -> Result<Descriptor, RegistryError> {
+        let manifest = self.manifest(source, reference)?;
+        if !source.same_repository(target) {
+            match manifest.media_type.as_str() {
+                OCI_INDEX | DOCKER_MANIFEST_LIST => {
+                    let index: Index = parse(source, &manifest.body)?;
+                    for child in &index.manifests {
+                        self.copy_manifest(source, target, &child.digest, tracker)?;
+                    }
+                }
+                _ => {
+                    let image: Manifest = parse(source, &manifest.body)?;
+                    let mut blobs: Vec<&Descriptor> = Vec::new();
+                    for blob in Some(&image.config).into_iter().chain(&image.layers) {
+                        if !blobs.iter().any(|b| b.digest == blob.digest) {
+                            blobs.push(blob);
+                        }
+                    }
+                    tracker.layers(blobs.len());
+                    in_parallel(self.parallelism, &blobs, |blob| {
+                        self.copy_blob(source, target, blob, tracker)?;
+                        tracker.layer_done();
+                        Ok::<_, RegistryError>(())
+                    })?;
+                }
+            }
+            self.put_manifest(target, None, &manifest.media_type, &manifest.body)?;
+        }
+        Ok(Descriptor {
+            media_type: manifest.media_type,
+            digest: manifest.digest,
+            size: manifest.body.len() as u64,
+            platform: None,
+            annotations: None,
+            artifact_type: None,
+        })
+    }
+
+    /// Copy a blob between repositories, mounting it when both are in the same
+    /// registry.
+    fn copy_blob(
+        &self,
+        source: &BundleReference,
+        target: &BundleReference,
+        blob: &Descriptor,
+        tracker: &Tracker<'_>,
+    ) -> Result<(), RegistryError> {
+        if self.has_blob(target, &blob.digest)? {
+            return Ok(());
+        }
+        let mount = if source.registry == target.registry {
+            Some((blob.digest.as_str(), source.repository.as_str()))
+        } else {
+            None
+        };
+        let location = match self.start_upload(target, mount)? {
+            Some(location) => location,
+            None => return Ok(()),
+        };
+        let content = self.blob_reader(source, &blob.digest)?;
+        self.send_blob(target, &location, blob, content, tracker)
+    }
+
+    /// Upload a blob to `repository` unless it is there already.
+    #[cfg(feature = "thick")]
+    pub(crate) fn upload_blob<R: Read>(
+        &self,
+        repository: &BundleReference,
+        blob: &Descriptor,
+        content: R,
+        tracker: &Tracker<'_>,
+    ) -> Result<(), RegistryError> {
+        if self.has_blob(repository, &blob.digest)? {
+            return Ok(());
+        }
+        match self.start_upload(repository, None)? {
+            Some(location) => self.send_blob(repository, &location, blob, content, tracker),
+            None => Ok(()),
+        }
+    }
+
+    /// Finish the upload at `location`, streaming `content` and checking it against
+    /// the blob's digest on the way.
+    fn send_blob<R: Read>(
+        &self,
+        repository: &BundleReference,
+        location: &str,
+        blob: &Descriptor,
+        content: R,
+        tracker: &Tracker<'_>,
+    ) -> Result<(), RegistryError> {
+        let content = tracker.reader(&blob.digest, blob.size, content);
+        let mut content = Verified::new(content, &blob.digest, blob.size);
+        let body = ureq::SendBody::from_reader(&mut content);
+        let mut response = self
+            .authorized(
+                repository,
+                self.agent(repository)?.put(&upload_url(
+                    &self.base(repository),
+                    location,
+                    &blob.digest,
+                )),
+            )?
+            .header("Content-Type", "application/octet-stream")
+            .header("Content-Length", blob.size.to_string())
+            .send(body)?;
+        check(location, &mut response)
+    }
+
+    fn has_blob(&self, repository: &BundleReference, digest: &str) -> Result<bool, RegistryError> {
+        let url = format!("{}/blobs/{}", self.url(repository), digest);
+        let mut response = self.send(repository, || {
+            Ok(self
+                .authorized(repository, self.agent(repository)?.head(&url))?
+                .call()?)
+        })?;
+        match check(&url, &mut response) {
+            Ok(()) => Ok(true),
+            Err(RegistryError::NotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Start an upload, returning where to send the blob, or `None` when the blob was
+    /// mounted from another repository instead.
+    fn start_upload(
+        &self,
+        repository: &BundleReference,
+        mount: Option<(&str, &str)>,
+    ) -> Result<Option<String>, RegistryError> {
+        let url = format!("{}/blobs/uploads/", self.url(repository));
+        let mut scopes = vec![scope(repository, "pull,push")];
+        if let Some((_, from)) = mount {
+            let source = BundleReference {
+                repository: from.to_string(),
+                ..repository.clone()
+            };
+            scopes.push(scope(&source, "pull"));
+        }
+        let mut response = self.send(repository, || {
+            let mut request =
+                self.authorized_for(repository, &scopes, self.agent(repository)?.post(&url))?;
+            if let Some((digest, from)) = mount {
+                request = request.query("mount", digest).query("from", from);
+            }
+            Ok(request.send_empty()?)
+        })?;
+        check(&url, &mut response)?;
+        if response.status().as_u16() == 201 {
+            return Ok(None);
+        }
+        header(&response, "Location")
+            .map(Some)
+            .ok_or_else(|| RegistryError::Status {
+                url,
+                status: response.status().as_u16(),
+                message: "no upload location returned".to_string(),
+            })
+    }
+
+    fn put_blob(
+        &self,
+        repository: &BundleReference,
+        content: &[u8],
+    ) -> Result<String, RegistryError> {
+        let digest = sha256_digest(content);
+        if self.has_blob(repository, &digest)? {
+            return Ok(digest);
+        }
+        if let Some(location) = self.start_upload(repository, None)? {
+            let url = upload_url(&self.base(repository), &location, &digest);
+            let mut response = self.send(repository, || {
+                Ok(self
+                    .authorized(repository, self.agent(repository)?.put(&url))?
+                    .header("Content-Type", "application/octet-stream")
+                    .send(content)?)
+            })?;
+            check(&location, &mut response)?;
+        }
+        Ok(digest)
+    }
+
+    /// Store a manifest by its digest, or under `tag`. Returns its digest.
+    pub(crate) fn put_manifest(
+        &self,
+        repository: &BundleReference,
+        tag: Option<&str>,
+        media_type: &str,
+        content: &[u8],
+    ) -> Result<String, RegistryError> {
+        let digest = sha256_digest(content);
+        let url = format!(
+            "{}/manifests/{}",
+            self.url(repository),
+            tag.unwrap_or(&digest)
+        );
+        let mut response = self.send(repository, || {
+            Ok(self
+                .authorized(repository, self.agent(repository)?.put(&url))?
+                .header("Content-Type", media_type)
+                .send(content)?)
+        })?;
+        check(&url, &mut response)?;
+        Ok(digest)
+    }
+
+    /// The manifest `reference` of `repository`, from the cache when it is there.
+    pub(crate) fn manifest(
+        &self,
+        repository: &BundleReference,
+        reference: &str,
+    ) -> Result<Fetched, RegistryError> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return self.fetch_manifest(repository, reference),
+        };
+        let digest = if reference.contains(':') {
+            reference.to_string()
+        } else if let Some(digest) = self.cached("tag", cache.tag(repository, reference)) {
+            digest
+        } else {
+            let digest = self.resolve(repository, reference)?;
+            cache.put_tag(repository, reference, &digest);
+            digest
+        };
+        if let Some((media_type, body)) = self.cached("manifest", cache.manifest(&digest)) {
+            return Ok(Fetched {
+                media_type,
+                digest,
+                body,
+            });
+        }
+        let fetched = self.fetch_manifest(repository, &digest)?;
+        cache.put_manifest(&fetched.digest, &fetched.media_type, &fetched.body);
+        Ok(fetched)
+    }
+
+    fn fetch_manifest(
+        &self,
+        repository: &BundleReference,
+        reference: &str,
+    ) -> Result<Fetched, RegistryError> {
+        let url = format!("{}/manifests/{}", self.url(repository), reference);
+        let mut response = self.send(repository, || {
+            Ok(self
+                .authorized(repository, self.agent(repository)?.get(&url))?
+                .header("Accept", MANIFEST_TYPES.join(", "))
+                .call()?)
+        })?;
+        check(&url, &mut response)?;
+        let media_type = header(&response, "Content-Type").unwrap_or_default();
+        let body = response
+            .body_mut()
+            .with_config()
+            .limit(MANIFEST_LIMIT)
+            .read_to_vec()?;
+        let digest = if reference.contains(':') {
+            check_digest(&url, reference, &body)?;
+            reference.to_string()
+        } else {
+            sha256_digest(&body)
+        };
+        Ok(Fetched {
+            media_type: media_type
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+            digest,
+            body,
+        })
+    }
+
+    fn blob(
+        &self,
+        repository: &BundleReference,
+        digest: &str,
+        limit: u64,
+    ) -> Result<Vec<u8>, RegistryError> {
+        if let Some(cache) = &self.cache {
+            if let Some(body) = self.cached("blob", cache.blob(digest)) {
+                return Ok(body);
+            }
+        }
+        let url = format!("{}/blobs/{}", self.url(repository), digest);
+        let mut response = self.send(repository, || {
+            Ok(self
+                .authorized(repository, self.agent(repository)?.get(&url))?
+                .call()?)
+        })?;
+        check(&url, &mut response)?;
+        let body = response
+            .body_mut()
+            .with_config()
+            .limit(limit)
+            .read_to_vec()?;
+        check_digest(&url, digest, &body)?;
+        if let Some(cache) = &self.cache {
+            cache.put_blob(digest, &body);
+        }
+        Ok(body)
+    }
+
+    /// Stream the blob `digest` of `repository`.
+    pub(crate) fn blob_reader(
+        &self,
+        repository: &BundleReference,
+        digest: &str,
+    ) -> Result<impl Read + Send + 'static, RegistryError> {
+        Ok(self.blob_reader_at(repository, digest, 0)?.0)
+    }
+
+    /// Stream the blob `digest` of `repository` from `offset` on, such as to resume
+    /// a download that stopped there. Returns where the stream starts: at `offset`,
+    /// or at 0 when the registry does not serve ranges and sends the whole blob.
+    pub(crate) fn blob_reader_at(
+        &self,
+        repository: &BundleReference,
+        digest: &str,
+        offset: u64,
+    ) -> Result<(impl Read + Send + 'static, u64), RegistryError> {
+        let url = format!("{}/blobs/{}", self.url(repository), digest);
+        let mut response = self.send(repository, || {
+            let mut request = self.authorized(repository, self.agent(repository)?.get(&url))?;
+            if offset > 0 {
+                request = request.header("Range", format!("bytes={}-", offset));
+            }
+            Ok(request.call()?)
+        })?;
+        check(&url, &mut response)?;
+        let start = match response.status().as_u16() {
+            206 => header(&response, "Content-Range")
+                .as_deref()
+                .and_then(|r| r.strip_prefix("bytes "))
+                .and_then(|r| r.split_once('-'))
+                .and_then(|(start, _)| start.parse().ok())
+                .filter(|start| *start == offset)
+                .ok_or_else(|| RegistryError::Status {
+                    url: url.clone(),
+                    status: 206,
+                    message: format!("not the range from byte {}", offset),
+                })?,
+            _ => 0,
+        };
+        Ok((response.into_body().into_reader(), start))
+    }
+}
+
+/// Pull the bundle stored at `reference` with a default `Client`.
+pub fn pull<R: AsReference + ?Sized>(reference: &R) -> Result<PulledBundle, RegistryError> {
+    Client::new().pull(reference)
+}
+
+/// Resolve `reference` to the digest of its manifest with a default `Client`.
+pub fn resolve_digest<R: AsReference + ?Sized>(reference: &R) -> Result<String, RegistryError> {
+    Client::new().resolve_digest(reference)
+}
+
+/// Push `bundle` to `reference` with a default `Client`.
+pub fn push<R: AsReference + ?Sized>(
+    bundle: &Bundle,
+    reference: &R,
+) -> Result<PushedBundle, RegistryError> {
+    Client::new().push(bundle, reference)
+}
+
+/// List the tags of `repository` with a default `Client`.
+pub fn list_tags<R: AsReference + ?Sized>(repository: &R) -> Result<Vec<String>, RegistryError> {
+    Client::new().list_tags(repository)
+}
+
+/// List the tags of `repository` that hold a bundle with a default `Client`.
+pub fn list_bundle_versions<R: AsReference + ?Sized>(
+    repository: &R,
+) -> Result<Vec<BundleVersion>, RegistryError> {
+    Client::new().list_bundle_versions(repository)
+}
+
+/// The newest bundle version in `repository` that matches `requirement` with a
+/// default `Client`.
+pub fn resolve_version<R: AsReference + ?Sized>(
+    repository: &R,
+    requirement: &str,
+) -> Result<BundleVersion, RegistryError> {
+    Client::new().resolve_version(repository, requirement)
+}
+
+/// Copy the bundle stored at `source`, with its images, to `target` with a default
+/// `Client`.
+pub fn copy<S, T>(source: &S, target: &T) -> Result<PushedBundle, RegistryError>
+where
+    S: AsReference + ?Sized,
+    T: AsReference + ?Sized,
+{
+    Client::new().copy(source, target)
+}
+
+impl Bundle {
+    /// Check the bundle's OCI images against their registries with `client`, so a
+    /// bundle whose images were replaced since it was built is not run.
+    ///
+    /// Each image's recorded digest, its `contentDigest` or else the digest in its
+    /// reference, is compared with the digest its registry serves now. That is the
+    /// digest of the image's tag when it has one, as a tag is what changes when an
+    /// image is replaced. Images that are not OCI images are not checked. An image
+    /// the registry does not have is reported as missing; any other failure to ask
+    /// the registry is an error.
+    ///
+    /// ```no_run
+    /// use libcnab::registry::Client;
+    /// use libcnab::Bundle;
+    ///
+    /// let bundle = Bundle::from_file("bundle.json").unwrap();
+    /// let report = bundle.verify_image_digests(&Client::new()).unwrap();
+    /// for image in report.iter().filter(|i| !i.is_verified()) {
+    ///     eprintln!("{}: {:?}", image.image, image.status);
+    /// }
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(bundle = %self.name), err)
+    )]
+    pub fn verify_image_digests(
+        &self,
+        client: &Client,
+    ) -> Result<Vec<ImageVerification>, RegistryError> {
+        let invocation_images = self
+            .invocation_images
+            .iter()
+            .map(|i| (None, &i.image, &i.image_type, &i.content_digest));
+        let images = self.images.iter().flat_map(|images| {
+            images
+                .iter()
+                .map(|(name, i)| (Some(name), &i.image, &i.image_type, &i.content_digest))
+        });
+        let mut report = Vec::new();
+        for (component, image, image_type, content_digest) in invocation_images.chain(images) {
+            if !is_oci_image(image_type.as_deref()) {
+                continue;
+            }
+            let reference = client.mirrored(BundleReference::parse(image)?);
+            let expected = content_digest.clone().or_else(|| reference.digest.clone());
+            let live = match &reference.tag {
+                Some(tag) => client.resolve(&reference, tag),
+                None => client
+                    .manifest(&reference, reference.reference())
+                    .map(|m| m.digest),
+            };
+            let status = match (live, expected) {
+                (Err(RegistryError::NotFound(_)), _) => ImageStatus::Missing,
+                (Err(e), _) => return Err(e),
+                (Ok(actual), None) => ImageStatus::Unpinned { actual },
+                (Ok(actual), Some(expected)) if actual == expected => {
+                    ImageStatus::Verified { digest: actual }
+                }
+                (Ok(actual), Some(expected)) => ImageStatus::Mismatch { expected, actual },
+            };
+            #[cfg(feature = "tracing")]
+            if !matches!(status, ImageStatus::Verified { .. }) {
+                tracing::warn!(image = %image, status = ?status, "image does not match the bundle");
+            }
+            report.push(ImageVerification {
+                image: image.clone(),
+                component: component.cloned(),
+                status,
+            });
+        }
+        Ok(report)
+    }
+}
+
+/// The reference of an image of a bundle, which must be an OCI image.
+pub(crate) fn image_reference(
+    image: &str,
+    image_type: Option<&str>,
+    content_digest: Option<&str>,
+) -> Result<BundleReference, RegistryError> {
+    if !is_oci_image(image_type) {
+        return Err(RegistryError::UnsupportedImage(image.to_string()));
+    }
+    let mut reference = BundleReference::parse(image)?;
+    if let Some(digest) = content_digest {
+        reference.digest = Some(digest.to_string());
+    }
+    Ok(reference)
+}
+
+/// The URL of the next page of a list, from a `Link` header such as
+/// `</v2/hello/tags/list?n=100&last=v1>; rel="next"`.
+fn next_link(base: &str, link: &str) -> Option<String> {
+    link.split(',').find_map(|link| {
+        let (target, params) = link.split_once(';')?;
+        if !params.replace(' ', "").contains("rel=\"next\"") {
+            return None;
+        }
+        let target = target.trim().strip_prefix('<')?.strip_suffix('>')?;
+        Some(if target.starts_with('/') {
+            format!("{}{}", base, target)
+        } else {
+            target.to_string()
+        })
+    })
+}
+
+/// Where to finish an upload whose `Location` the registry returned.
+fn upload_url(base: &str, location: &str, digest: &str) -> String {
+    let location = if location.starts_with('/') {
+        format!("{}{}", base, location)
+    } else {
+        location.to_string()
+    };
+    let separator = if location.contains('?') { '&' } else { '?' };
+    format!("{}{}digest={}", location, separator, digest)
+}
+
+type Response = ureq::http::Response<ureq::Body>;
+
+/// How many times a request is sent to answer a registry's challenges.
+const AUTH_ATTEMPTS: usize = 3;
+
+/// The token scope of `actions`, such as `pull,push`, on `repository`.
+fn scope(repository: &BundleReference, actions: &str) -> String {
+    format!("repository:{}:{}", repository.repository, actions)
+}
+
+fn header(response: &Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Turn an unsuccessful response into an error.
+fn check(url: &str, response: &mut Response) -> Result<(), RegistryError> {
+    let status = response.status().as_u16();
+    if (200..300).contains(&status) {
+        return Ok(());
+    }
+    if status == 404 {
+        return Err(RegistryError::NotFound(url.to_string()));
+    }
+    let message = response
+        .body_mut()
+        .with_config()
+        .limit(64 * 1024)
+        .read_to_string()
+        .unwrap_or_default();
+    Err(RegistryError::Status {
+        url: url.to_string(),
+        status,
+        message,
+    })
+}
+
+pub(crate) fn parse<T: serde::de::DeserializeOwned>(
+    reference: &BundleReference,
+    body: &[u8],
+) -> Result<T, RegistryError> {
+    serde_json::from_slice(body)
+        .map_err(|e| RegistryError::InvalidManifest(format!("{}: {}", reference, e)))
+}
+
+/// RegistryError describes a failure to talk to a registry.
+#[derive(Debug)]
+pub enum RegistryError {
+    /// A reference could not be parsed
+    InvalidReference(String),
+    /// The registry has nothing at the URL
+    NotFound(String),
+    /// The registry answered with an unexpected status
+    Status {
+        url: String,
+        status: u16,
+        message: String,
+    },
+    /// The reference holds something other than a bundle
+    NotABundle(String),
+    /// A manifest or index could not be parsed
+    InvalidManifest(String),
+    /// A docker configuration could not be read
+    InvalidConfig(String),
+    /// A PEM file holds no certificate or key, or one that cannot be read
+    InvalidCertificate(String),
+    /// A token service returned something other than a token
+    InvalidToken(String),
+    /// A docker credential helper failed
+    CredentialHelper {
+        helper: String,
+        message: String,
+    },
+    /// A version requirement could not be parsed
+    InvalidVersionRequirement(String),
+    /// No bundle version in a repository matches a requirement
+    NoMatchingVersion {
+        repository: String,
+        requirement: String,
+    },
+    /// An image of a bundle is not an OCI image, so it cannot be stored in a registry
+    UnsupportedImage(String),
+    /// Content did not match the digest it was fetched by, so it was tampered with or
+    /// corrupted on the way
+    DigestMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+    /// Content is named by a digest whose algorithm is neither `sha256` nor
+    /// `sha512`, so it cannot be checked
+    UnsupportedDigest(String),
+    BundleParseError(BundleParseError),
+    HttpError(ureq::Error),
+    /// A `LayerStore` could not be read or written
+    IoError(std::io::Error),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::InvalidReference(r) => write!(f, "invalid reference {}", r),
+            RegistryError::NotFound(url) => write!(f, "not found: {}", url),
+            RegistryError::Status {
+                url,
+                status,
+                message,
+            } => write!(f, "{} returned {}: {}", url, status, message),
+            RegistryError::NotABundle(r) => write!(f, "{} is not a CNAB bundle", r),
+            RegistryError::InvalidManifest(m) => write!(f, "invalid manifest: {}", m),
+            RegistryError::InvalidConfig(m) => write!(f, "invalid docker configuration {}", m),
+            RegistryError::InvalidCertificate(m) => write!(f, "invalid certificate: {}", m),
+            RegistryError::InvalidToken(m) => write!(f, "invalid token response: {}", m),
+            RegistryError::CredentialHelper { helper, message } => {
+                write!(f, "credential helper {} failed: {}", helper, message)
+            }
+            RegistryError::InvalidVersionRequirement(m) => {
+                write!(f, "invalid version requirement {}", m)
+            }
+            RegistryError::NoMatchingVersion {
+                repository,
+                requirement,
+            } => write!(f, "no version of {} matches {}", repository, requirement),
+            RegistryError::UnsupportedImage(i) => {
+                write!(f, "{} is not an OCI image and cannot be pushed", i)
+            }
+            RegistryError::DigestMismatch {
+                url,
+                expected,
+                actual,
+            } => write!(f, "{} has digest {}, expected {}", url, actual, expected),
+            RegistryError::UnsupportedDigest(d) => {
+                write!(f, "the algorithm of digest {} is not supported", d)
+            }
+            RegistryError::BundleParseError(e) => write!(f, "{}", e),
+            RegistryError::HttpError(e) => write!(f, "{}", e),
+            RegistryError::IoError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+impl Coded for RegistryError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            RegistryError::InvalidReference(_) => ErrorCode::ReferenceInvalid,
+            RegistryError::NotFound(_) => ErrorCode::RegistryNotFound,
+            RegistryError::Status { .. } => ErrorCode::RegistryStatus,
+            RegistryError::NotABundle(_) => ErrorCode::RegistryNotABundle,
+            RegistryError::InvalidManifest(_) => ErrorCode::RegistryInvalidManifest,
+            RegistryError::InvalidConfig(_) => ErrorCode::RegistryInvalidConfig,
+            RegistryError::InvalidCertificate(_) => ErrorCode::RegistryInvalidCertificate,
+            RegistryError::InvalidToken(_) => ErrorCode::RegistryInvalidToken,
+            RegistryError::CredentialHelper { .. } => ErrorCode::RegistryCredentialHelper,
+            RegistryError::InvalidVersionRequirement(_) => {
+                ErrorCode::RegistryInvalidVersionRequirement
+            }
+            RegistryError::NoMatchingVersion { .. } => ErrorCode::RegistryNoMatchingVersion,
+            RegistryError::UnsupportedImage(_) => ErrorCode::RegistryUnsupportedImage,
+            RegistryError::DigestMismatch { .. } => ErrorCode::RegistryDigestMismatch,
+            RegistryError::UnsupportedDigest(_) => ErrorCode::RegistryUnsupportedDigest,
+            RegistryError::BundleParseError(e) => e.code(),
+            RegistryError::HttpError(_) => ErrorCode::RegistryHttp,
+            RegistryError::IoError(_) => ErrorCode::Io,
+        }
+    }
+}
+
+impl From<BundleParseError> for RegistryError {
+    fn from(error: BundleParseError) -> Self {
+        RegistryError::BundleParseError(error)
+    }
+}
+
+impl From<InvalidReference> for RegistryError {
+    fn from(error: InvalidReference) -> Self {
+        RegistryError::InvalidReference(error.0)
+    }
+}
+
+impl From<ureq::Error> for RegistryError {
+    fn from(error: ureq::Error) -> Self {
+        RegistryError::HttpError(error)
+    }
+}
+
+impl From<std::io::Error> for RegistryError {
+    fn from(error: std::io::Error) -> Self {
+        RegistryError::IoError(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::testing::FakeRegistry;
+    use super::*;
+    use crate::metrics::test::Recorded;
+
+    fn descriptor(media_type: &str, digest: &str, size: usize) -> Descriptor {
+        Descriptor {
+            media_type: media_type.to_string(),
+            digest: digest.to_string(),
+            size: size as u64,
+            platform: None,
+            annotations: None,
+            artifact_type: None,
+        }
+    }
+
+    #[test]
+    fn test_in_parallel() {
+        let (running, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let items: Vec<usize> = (0..64).collect();
+        let sum = AtomicUsize::new(0);
+        in_parallel(4, &items, |i| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            sum.fetch_add(*i, Ordering::SeqCst);
+            running.fetch_sub(1, Ordering::SeqCst);
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+        assert_eq!(sum.into_inner(), items.iter().sum::<usize>());
+        assert!(most.into_inner() <= 4);
+
+        let tried = AtomicUsize::new(0);
+        let result = in_parallel(DEFAULT_PARALLELISM, &items, |i| {
+            tried.fetch_add(1, Ordering::SeqCst);
+            if *i == 3 {
+                Err(*i)
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!(result, Err(3));
+        assert!(tried.into_inner() < items.len());
+
+        // One at a time, in order.
+        let seen = Mutex::new(Vec::new());
+        in_parallel(1, &items[..3], |i| {
+            seen.lock().unwrap().push(*i);
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+        assert_eq!(seen.into_inner().unwrap(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_registry_url() {
+        let client = Client::new().insecure_registry("registry.internal:5000");
+        let url = |r| client.url(&BundleReference::parse(r).unwrap());
+        assert_eq!(
+            url("localhost:5000/bundles/hello:0.1.0"),
+            "http://localhost:5000/v2/bundles/hello"
+        );
+        assert_eq!(
+            url("deislabs/hello:v1"),
+            "https://registry-1.docker.io/v2/deislabs/hello"
+        );
+        assert_eq!(
+            url("registry.internal:5000/bundles/hello:0.1.0"),
+            "http://registry.internal:5000/v2/bundles/hello"
+        );
+        assert_eq!(
+            url("registry.internal/bundles/hello:0.1.0"),
+            "https://registry.internal/v2/bundles/hello"
+        );
+        assert_eq!(
+            BundleReference::parse("helloworld").unwrap().reference(),
+            "latest"
+        );
+        assert_eq!(
+            BundleReference::parse("hello:v1@sha256:abc")
+                .unwrap()
+                .reference(),
+            "sha256:abc"
+        );
+    }
+
+    #[test]
+    fn test_tls() {
+        let ca = std::fs::read("testdata/registry-ca.pem").unwrap();
+        let client = Client::new()
+            .ca_certificates("registry.internal", &ca)
+            .unwrap();
+        assert!(client.tls.contains_key("registry.internal"));
+        match Client::new().ca_certificates("registry.internal", b"not a certificate") {
+            Err(RegistryError::InvalidCertificate(_)) => {}
+            other => panic!(
+                "expected an invalid certificate, got {:?}",
+                other.map(|_| ())
+            ),
+        }
+        match Client::new().client_certificate("registry.internal", &ca, &ca) {
+            Err(RegistryError::InvalidCertificate(m)) => assert!(m.contains("key"), "{}", m),
+            other => panic!("expected a missing key, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_pull() {
+        let registry = FakeRegistry::start();
+        let json = std::fs::read("testdata/bundle.json").unwrap();
+        let config = registry.put_blob("bundles/hello", &json);
+        let config_manifest = serde_json::to_vec(&Manifest {
+            schema_version: 2,
+            media_type: Some(OCI_MANIFEST.to_string()),
+            config: descriptor(CNAB_CONFIG, &config, json.len()),
+            layers: Vec::new(),
+            annotations: None,
+            artifact_type: None,
+            subject: None,
+        })
+        .unwrap();
+        let config_digest =
+            registry.put_manifest("bundles/hello", None, OCI_MANIFEST, &config_manifest);
+        let image_digest = registry.put_manifest("bundles/hello", None, OCI_MANIFEST, b"{}");
+
+        let annotated = |d: Descriptor, kind: &str| Descriptor {
+            annotations: Some(
+                vec![(MANIFEST_TYPE_ANNOTATION.to_string(), kind.to_string())]
+                    .into_iter()
+                    .collect(),
+            ),
+            ..d
+        };
+        let index = serde_json::to_vec(&Index {
+            schema_version: 2,
+            media_type: Some(OCI_INDEX.to_string()),
+            manifests: vec![
+                annotated(
+                    descriptor(OCI_MANIFEST, &config_digest, config_manifest.len()),
+                    "config",
+                ),
+                annotated(descriptor(OCI_MANIFEST, &image_digest, 2), "invocation"),
+            ],
+            annotations: None,
+        })
+        .unwrap();
+        let index_digest = registry.put_manifest("bundles/hello", Some("0.1.0"), OCI_INDEX, &index);
+
+        let client = Client::new();
+        let pulled = client
+            .pull(&format!("{}/bundles/hello:0.1.0", registry.host))
+            .expect("bundle pulled");
+        assert_eq!(pulled.bundle.name, "helloworld");
+        assert_eq!(pulled.digest, index_digest);
+        assert_eq!(
+            pulled
+                .relocation
+                .get("technosophos/helloworld:0.1.0")
+                .unwrap(),
+            format!("{}/bundles/hello@{}", registry.host, image_digest)
+        );
+
+        match client.pull(&format!("{}/bundles/hello:missing", registry.host)) {
+            Err(RegistryError::NotFound(_)) => {}
+            other => panic!("expected not found, got {:?}", other),
+        }
+        match client.pull(&format!("{}/bundles/hello@{}", registry.host, image_digest)) {
+            Err(RegistryError::InvalidManifest(_)) | Err(RegistryError::NotABundle(_)) => {}
+            other => panic!("expected not a bundle, got {:?}", other),
+        }
+
+        let pinned: BundleReference =
+            format!("{}/bundles/hello:0.1.0@{}", registry.host, index_digest)
+                .parse()
+                .unwrap();
+        assert_eq!(client.pull(&pinned).unwrap().digest, index_digest);
+        let tampered = |state: &mut testing::State, key: (&str, &str)| {
+            let key = (key.0.to_string(), key.1.to_string());
+            if let Some((_, body)) = state.manifests.get_mut(&key) {
+                body.push(b' ');
+            } else {
+                state.blobs.get_mut(&key).unwrap().push(b' ');
+            }
+        };
+        tampered(
+            &mut registry.state.lock().unwrap(),
+            ("bundles/hello", &index_digest),
+        );
+        match client.pull(&pinned) {
+            Err(RegistryError::DigestMismatch { expected, .. }) => {
+                assert_eq!(expected, index_digest)
+            }
+            other => panic!("expected a digest mismatch, got {:?}", other),
+        }
+        tampered(
+            &mut registry.state.lock().unwrap(),
+            ("bundles/hello", &config),
+        );
+        match client.pull(&format!("{}/bundles/hello:0.1.0", registry.host)) {
+            Err(RegistryError::DigestMismatch { expected, .. }) => assert_eq!(expected, config),
+            other => panic!("expected a digest mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_digest_algorithms() {
+        let registry = FakeRegistry::start();
+        let repository: BundleReference = format!("{}/bundles/hello:0.1.0", registry.host)
+            .parse()
+            .unwrap();
+        let sha512 = format!("sha512:{}", hex::encode(Sha512::digest(b"{}")));
+        let unknown = format!("md5:{}", hex::encode([0u8; 16]));
+        {
+            let mut state = registry.state.lock().unwrap();
+            for digest in [&sha512, &unknown] {
+                state.blobs.insert(
+                    ("bundles/hello".to_string(), digest.clone()),
+                    b"{}".to_vec(),
+                );
+            }
+        }
+
+        let client = Client::new();
+        assert_eq!(client.blob(&repository, &sha512, 1024).unwrap(), b"{}");
+        match client.blob(&repository, &unknown, 1024) {
+            Err(RegistryError::UnsupportedDigest(digest)) => assert_eq!(digest, unknown),
+            other => panic!("expected an unsupported digest, got {:?}", other),
+        }
+        registry
+            .state
+            .lock()
+            .unwrap()
+            .blobs
+            .get_mut(&("bundles/hello".to_string(), sha512.clone()))
+            .unwrap()
+            .push(b' ');
+        match client.blob(&repository, &sha512, 1024) {
+            Err(RegistryError::DigestMismatch {
+                expected, actual, ..
+            }) => {
+                assert_eq!(expected, sha512);
+                assert!(actual.starts_with("sha512:"));
+            }
+            other => panic!("expected a digest mismatch, got {:?}", other),
+        }
+
+        let read = |digest: &str, content: &[u8]| {
+            let mut verified = Verified::new(content, digest, content.len() as u64);
+            std::io::copy(&mut verified, &mut std::io::sink())
+        };
+        read(&sha512, b"{}").unwrap();
+        assert!(read(&sha512, b"[]").is_err());
+        assert!(read(&unknown, b"{}").is_err());
+    }
+
+    #[test]
+    fn test_mirrors() {
+        let registry = FakeRegistry::start();
+        let config = registry.put_blob("mirror/images/hello", b"{}");
+        let image = serde_json::to_vec(&Manifest {
+            schema_version: 2,
+            media_type: Some(OCI_MANIFEST.to_string()),
+            config: descriptor("application/vnd.oci.image.config.v1+json", &config, 2),
+            layers: Vec::new(),
+            annotations: None,
+            artifact_type: None,
+            subject: None,
+        })
+        .unwrap();
+        let image_digest =
+            registry.put_manifest("mirror/images/hello", Some("0.1.0"), OCI_MANIFEST, &image);
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.invocation_images[0].image = "upstream.invalid/images/hello:0.1.0".to_string();
+        bundle.images = None;
+        let mirrors =
+            Mirrors::parse(&format!("upstream.invalid/* -> {}/mirror/*", registry.host)).unwrap();
+        let client = Client::new().mirrors(mirrors);
+
+        // Images are copied from the mirror, and pushes go where they are told.
+        let target = format!("{}/bundles/hello:0.1.0", registry.host);
+        let pushed = client.push(&bundle, &target).expect("bundle pushed");
+        assert_eq!(
+            client
+                .resolve_digest("upstream.invalid/images/hello:0.1.0")
+                .unwrap(),
+            image_digest
+        );
+        let report = bundle.verify_image_digests(&client).unwrap();
+        assert_eq!(
+            report[0].status,
+            ImageStatus::Unpinned {
+                actual: image_digest.clone()
+            }
+        );
+
+        // Bundles are pulled from the mirror.
+        client
+            .copy(
+                &target,
+                &format!("{}/mirror/bundles/hello:0.1.0", registry.host),
+            )
+            .unwrap();
+        let pulled = client
+            .pull("upstream.invalid/bundles/hello:0.1.0")
+            .expect("bundle pulled from the mirror");
+        assert_eq!(pulled.digest, pushed.digest);
+        let versions = client
+            .list_bundle_versions("upstream.invalid/bundles/hello")
+            .unwrap();
+        assert_eq!(versions[0].tag, "0.1.0");
+        assert!(Client::new()
+            .pull("upstream.invalid/bundles/hello:0.1.0")
+            .is_err());
+    }
+
+    #[test]
+    fn test_push() {
+        let registry = FakeRegistry::start();
+        let config = registry.put_blob("images/hello", b"{}");
+        let layer = registry.put_blob("images/hello", b"layer");
+        let image = serde_json::to_vec(&Manifest {
+            schema_version: 2,
+            media_type: Some(OCI_MANIFEST.to_string()),
+            config: descriptor("application/vnd.oci.image.config.v1+json", &config, 2),
+            layers: vec![descriptor(
+                "application/vnd.oci.image.layer.v1.tar",
+                &layer,
+                5,
+            )],
+            annotations: None,
+            artifact_type: None,
+            subject: None,
+        })
+        .unwrap();
+        let image_digest =
+            registry.put_manifest("images/hello", Some("0.1.0"), OCI_MANIFEST, &image);
+
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let original = format!("{}/images/hello:0.1.0", registry.host);
+        bundle.invocation_images[0].image = original.clone();
+        let component = bundle.images.as_mut().unwrap().get_mut("my-microservice");
+        component.unwrap().image = format!("{}/images/hello@{}", registry.host, image_digest);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let client = Client::new()
+            .progress(move |progress: &Progress| sink.lock().unwrap().push(progress.clone()));
+        let pushed = client
+            .push(&bundle, &format!("{}/bundles/hello:0.1.0", registry.host))
+            .expect("bundle pushed");
+
+        {
+            // Both images have the same two layers, mounted from the images' repository.
+            let events = events.lock().unwrap();
+            let phases: Vec<_> = events
+                .iter()
+                .filter_map(|e| match e {
+                    Progress::Phase(phase) => Some(*phase),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(phases, vec![Phase::Transferring, Phase::Publishing]);
+            assert_eq!(
+                events[events.len() - 2],
+                Progress::Layers { done: 4, total: 4 }
+            );
+            assert!(!events.iter().any(|e| matches!(e, Progress::Bytes { .. })));
+        }
+
+        {
+            let state = registry.state.lock().unwrap();
+            let blob = ("bundles/hello".to_string(), layer.clone());
+            assert_eq!(state.blobs[&blob], b"layer");
+            let manifest = ("bundles/hello".to_string(), image_digest.clone());
+            assert_eq!(state.manifests[&manifest].1, image);
+        }
+        let pulled = client
+            .pull(&format!("{}/bundles/hello:0.1.0", registry.host))
+            .expect("bundle pulled");
+        assert_eq!(pulled.digest, pushed.digest);
+        assert_eq!(pulled.relocation, pushed.relocation);
+        assert_eq!(pulled.bundle.invocation_images[0].image, original);
+        assert_eq!(
+            pulled.relocation.get(&original).unwrap(),
+            format!("{}/bundles/hello@{}", registry.host, image_digest)
+        );
+        assert_eq!(pulled.relocation.len(), 2);
+
+        // A mirror of the pushed bundle copies the images from where they were pushed.
+        registry
+            .state
+            .lock()
+            .unwrap()
+            .manifests
+            .retain(|(repository, _), _| repository != "images/hello");
+        let mirrored = client
+            .push_relocated(
+                &pulled.bundle,
+                &pulled.relocation,
+                &format!("{}/mirror/hello:0.1.0", registry.host),
+            )
+            .expect("bundle mirrored");
+        assert_eq!(
+            mirrored.relocation.get(&original).unwrap(),
+            format!("{}/mirror/hello@{}", registry.host, image_digest)
+        );
+
+        bundle.invocation_images[0].image_type = Some("wasm".to_string());
+        match client.push(&bundle, &format!("{}/bundles/hello:0.1.0", registry.host)) {
+            Err(RegistryError::UnsupportedImage(image)) => assert_eq!(image, original),
+            other => panic!("expected an unsupported image, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_copy() {
+        let source = FakeRegistry::start();
+        let config = source.put_blob("images/hello", b"{}");
+        let image = serde_json::to_vec(&Manifest {
+            schema_version: 2,
+            media_type: Some(OCI_MANIFEST.to_string()),
+            config: descriptor("application/vnd.oci.image.config.v1+json", &config, 2),
+            layers: Vec::new(),
+            annotations: None,
+            artifact_type: None,
+            subject: None,
+        })
+        .unwrap();
+        let image_digest = source.put_manifest("images/hello", Some("0.1.0"), OCI_MANIFEST, &image);
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let original = format!("{}/images/hello:0.1.0", source.host);
+        bundle.invocation_images[0].image = original.clone();
+        bundle.images = None;
+        let client = Client::new();
+        let pushed = client
+            .push(&bundle, &format!("{}/bundles/hello:0.1.0", source.host))
+            .unwrap();
+        // The copy must not need the images where the bundle first found them.
+        source
+            .state
+            .lock()
+            .unwrap()
+            .manifests
+            .retain(|(repository, _), _| repository != "images/hello");
+
+        let target = FakeRegistry::start();
+        let reference = format!("{}/mirror/hello:0.1.0", target.host);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let copied = Client::new()
+            .progress(move |progress: &Progress| sink.lock().unwrap().push(progress.clone()))
+            .copy(&format!("{}/bundles/hello:0.1.0", source.host), &reference)
+            .expect("bundle copied");
+        assert!(events.lock().unwrap().contains(&Progress::Bytes {
+            digest: config.clone(),
+            transferred: 2,
+            size: 2
+        }));
+        assert_eq!(copied.digest, pushed.digest);
+        assert_eq!(
+            copied.relocation.get(&original).unwrap(),
+            format!("{}/mirror/hello@{}", target.host, image_digest)
+        );
+        let pulled = client.pull(&reference).unwrap();
+        assert_eq!(pulled.relocation, copied.relocation);
+        let state = target.state.lock().unwrap();
+        assert_eq!(state.blobs[&("mirror/hello".to_string(), config)], b"{}");
+    }
+
+    #[test]
+    fn test_list_bundle_versions() {
+        let registry = FakeRegistry::start();
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.invocation_images[0].image = format!("{}/images/hello:0.1.0", registry.host);
+        bundle.images = None;
+        let config = registry.put_blob("images/hello", b"{}");
+        let image = serde_json::to_vec(&Manifest {
+            schema_version: 2,
+            media_type: Some(OCI_MANIFEST.to_string()),
+            config: descriptor("application/vnd.oci.image.config.v1+json", &config, 2),
+            layers: Vec::new(),
+            annotations: None,
+            artifact_type: None,
+            subject: None,
+        })
+        .unwrap();
+        registry.put_manifest("images/hello", Some("0.1.0"), OCI_MANIFEST, &image);
+        let client = Client::new();
+        let repository = format!("{}/bundles/hello", registry.host);
+        let first = client
+            .push(&bundle, &format!("{}:0.1.0", repository))
+            .unwrap();
+        bundle.version = semver::Version::parse("0.2.0").unwrap();
+        let second = client
+            .push(&bundle, &format!("{}:latest", repository))
+            .unwrap();
+        registry.put_manifest("bundles/hello", Some("image"), OCI_MANIFEST, b"{}");
+
+        assert_eq!(
+            client.list_tags(&repository).unwrap(),
+            vec!["0.1.0", "image", "latest"]
+        );
+        assert_eq!(
+            client.list_bundle_versions(&repository).unwrap(),
+            vec![
+                BundleVersion {
+                    tag: "0.1.0".to_string(),
+                    digest: first.digest,
+                    version: "0.1.2".to_string(),
+                },
+                BundleVersion {
+                    tag: "latest".to_string(),
+                    digest: second.digest.clone(),
+                    version: "0.2.0".to_string(),
+                },
+            ]
+        );
+
+        bundle.version = semver::Version::parse("0.3.0-beta.1").unwrap();
+        client
+            .push(&bundle, &format!("{}:beta", repository))
+            .unwrap();
+        let resolve = |requirement| {
+            client
+                .resolve_version(&repository, requirement)
+                .map(|v| (v.tag, v.version))
+        };
+        let version = |tag: &str, version: &str| (tag.to_string(), version.to_string());
+        assert_eq!(resolve(">=0.1, <0.2").unwrap(), version("0.1.0", "0.1.2"));
+        assert_eq!(resolve("*").unwrap(), version("latest", "0.2.0"));
+        assert_eq!(
+            resolve(">=0.3.0-beta.0").unwrap(),
+            version("beta", "0.3.0-beta.1")
+        );
+        assert_eq!(
+            client.resolve_version(&repository, "^0.2").unwrap().digest,
+            second.digest
+        );
+        match resolve("^1") {
+            Err(RegistryError::NoMatchingVersion { requirement, .. }) => {
+                assert_eq!(requirement, "^1")
+            }
+            other => panic!("expected no match, got {:?}", other),
+        }
+        match resolve("not a requirement") {
+            Err(RegistryError::InvalidVersionRequirement(_)) => {}
+            other => panic!("expected an invalid requirement, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cache() {
+        let registry = FakeRegistry::start();
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.invocation_images[0].image = format!("{}/images/hello:0.1.0", registry.host);
+        bundle.images = None;
+        let config = registry.put_blob("images/hello", b"{}");
+        let image = serde_json::to_vec(&Manifest {
+            schema_version: 2,
+            media_type: Some(OCI_MANIFEST.to_string()),
+            config: descriptor("application/vnd.oci.image.config.v1+json", &config, 2),
+            layers: Vec::new(),
+            annotations: None,
+            artifact_type: None,
+            subject: None,
+        })
+        .unwrap();
+        registry.put_manifest("images/hello", Some("0.1.0"), OCI_MANIFEST, &image);
+        let reference = format!("{}/bundles/hello:0.1.0", registry.host);
+        let pushed = Client::new().push(&bundle, &reference).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path()).ttl(std::time::Duration::from_secs(3600));
+        let metrics = Arc::new(Recorded::default());
+        let client = Client::new().cache(cache.clone()).metrics(metrics.clone());
+        assert_eq!(client.pull(&reference).unwrap().digest, pushed.digest);
+        // Everything the pull needs is in the cache now.
+        let emptied = {
+            let mut state = registry.state.lock().unwrap();
+            std::mem::take(&mut *state)
+        };
+        let pulled = client
+            .pull(&reference)
+            .expect("bundle pulled from the cache");
+        assert_eq!(pulled.digest, pushed.digest);
+        assert_eq!(pulled.bundle.name, "helloworld");
+        {
+            let counters = metrics.counters.lock().unwrap();
+            // The first pull looks up everything in vain, the second finds it all: a
+            // tag, the bundle's index and config manifest, and its bundle.json.
+            for (kind, count) in &[("tag", 1), ("manifest", 2), ("blob", 1)] {
+                assert_eq!(
+                    counters[&format!("{}{{kind={}}}", CACHE_HITS, kind)],
+                    *count
+                );
+                assert_eq!(
+                    counters[&format!("{}{{kind={}}}", CACHE_MISSES, kind)],
+                    *count
+                );
+            }
+            let timers = metrics.timers.lock().unwrap();
+            assert_eq!(*timers, vec![format!("{}{{result=ok}}", PULL_DURATION); 2]);
+        }
+
+        // An invalidated tag is resolved again.
+        cache.invalidate(&reference).unwrap();
+        match client.pull(&reference) {
+            Err(RegistryError::NotFound(_)) => {}
+            other => panic!("expected not found, got {:?}", other),
+        }
+        *registry.state.lock().unwrap() = emptied;
+        let uncached = Client::new().cache(Cache::new(dir.path()));
+        assert_eq!(uncached.pull(&reference).unwrap().digest, pushed.digest);
+        cache.clear().unwrap();
+        assert!(!dir.path().exists());
+    }
+
+    #[test]
+    fn test_credentials() {
+        let registry = FakeRegistry::start();
+        let credential = RegistryCredential::basic("me", "hunter2");
+        registry.state.lock().unwrap().authorization = credential.header();
+        let reference = format!("{}/bundles/hello:0.1.0", registry.host);
+
+        let anonymous = Client::new().docker_config(DockerConfig::default());
+        match anonymous.pull(&reference) {
+            Err(RegistryError::Status { status: 401, .. }) => {}
+            other => panic!("expected unauthorized, got {:?}", other),
+        }
+        let config: DockerConfig = serde_json::from_value(serde_json::json!({
+            "auths": { registry.host.clone(): { "auth": "bWU6aHVudGVyMg==" } }
+        }))
+        .unwrap();
+        for client in [
+            Client::new().docker_config(config),
+            anonymous.credential(&registry.host, credential.clone()),
+        ] {
+            match client.pull(&reference) {
+                Err(RegistryError::NotFound(_)) => {}
+                other => panic!("expected not found, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_token_auth() {
+        use super::testing::TokenAuth;
+
+        let registry = FakeRegistry::start();
+        let credential = RegistryCredential::basic("me", "hunter2");
+        registry.state.lock().unwrap().token_auth = Some(TokenAuth {
+            credential: credential.header(),
+            refresh_token: Some("refresh".to_string()),
+            ..TokenAuth::default()
+        });
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.invocation_images[0].image = format!("{}/images/hello:0.1.0", registry.host);
+        bundle.images = None;
+        let config = registry.put_blob("images/hello", b"{}");
+        let image = serde_json::to_vec(&Manifest {
+            schema_version: 2,
+            media_type: Some(OCI_MANIFEST.to_string()),
+            config: descriptor("application/vnd.oci.image.config.v1+json", &config, 2),
+            layers: Vec::new(),
+            annotations: None,
+            artifact_type: None,
+            subject: None,
+        })
+        .unwrap();
+        registry.put_manifest("images/hello", Some("0.1.0"), OCI_MANIFEST, &image);
+        let reference = format!("{}/bundles/hello:0.1.0", registry.host);
+        let token_requests = || {
+            let state = registry.state.lock().unwrap();
+            state.token_auth.as_ref().unwrap().requests.clone()
+        };
+
+        let anonymous = Client::new().docker_config(DockerConfig::default());
+        let client = Client::new()
+            .docker_config(DockerConfig::default())
+            .credential(&registry.host, credential);
+        let pushed = client.push(&bundle, &reference).expect("bundle pushed");
+        let requests = token_requests();
+        assert!(requests.iter().all(|(_, anonymous)| !anonymous));
+        let mount = vec![
+            "repository:bundles/hello:pull,push".to_string(),
+            "repository:images/hello:pull".to_string(),
+        ];
+        assert!(requests.iter().any(|(scopes, _)| *scopes == mount));
+
+        // Anyone can pull, with an anonymous token, but not push.
+        assert_eq!(anonymous.pull(&reference).unwrap().digest, pushed.digest);
+        assert!(token_requests().last().unwrap().1);
+        let before = token_requests().len();
+        match anonymous.push(&bundle, &reference) {
+            Err(RegistryError::Status { status: 401, .. }) => {}
+            other => panic!("expected unauthorized, got {:?}", other),
+        }
+        assert!(token_requests().len() - before <= AUTH_ATTEMPTS);
+
+        // Revoked tokens are replaced.
+        let before = token_requests().len();
+        registry
+            .state
+            .lock()
+            .unwrap()
+            .token_auth
+            .as_mut()
+            .unwrap()
+            .tokens
+            .clear();
+        assert_eq!(client.pull(&reference).unwrap().digest, pushed.digest);
+        assert!(token_requests().len() > before);
+
+        // Identity tokens are exchanged for tokens.
+        let config: DockerConfig = serde_json::from_value(serde_json::json!({
+            "auths": { registry.host.clone(): { "identitytoken": "refresh" } }
+        }))
+        .unwrap();
+        let client = Client::new().docker_config(config);
+        client.push(&bundle, &reference).expect("bundle pushed");
+        assert!(!token_requests().last().unwrap().1);
+    }
+
+    #[test]
+    fn test_verify_image_digests() {
+        let registry = FakeRegistry::start();
+        let digest = registry.put_manifest("images/hello", Some("0.1.0"), OCI_MANIFEST, b"{}");
+        let tagged = format!("{}/images/hello:0.1.0", registry.host);
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.invocation_images[0].image = tagged.clone();
+        bundle.invocation_images[0].content_digest = Some(digest.clone());
+        let images = bundle.images.as_mut().unwrap();
+        let component = images.get_mut("my-microservice").unwrap();
+        component.image = format!("{}/images/hello@{}", registry.host, digest);
+        component.content_digest = None;
+        let client = Client::new();
+
+        let report = bundle.verify_image_digests(&client).unwrap();
+        assert!(
+            report.iter().all(ImageVerification::is_verified),
+            "{:?}",
+            report
+        );
+        assert_eq!(report[1].component.as_deref(), Some("my-microservice"));
+
+        // The tag was moved to other content.
+        let replaced = registry.put_manifest("images/hello", Some("0.1.0"), OCI_MANIFEST, b"[]");
+        let report = bundle.verify_image_digests(&client).unwrap();
+        assert_eq!(
+            report[0].status,
+            ImageStatus::Mismatch {
+                expected: digest.clone(),
+                actual: replaced.clone(),
+            }
+        );
+        assert!(report[1].is_verified());
+
+        bundle.invocation_images[0].content_digest = None;
+        let images = bundle.images.as_mut().unwrap();
+        let component = images.get_mut("my-microservice").unwrap();
+        component.image = format!("{}/images/hello:missing", registry.host);
+        let report = bundle.verify_image_digests(&client).unwrap();
+        assert_eq!(report[0].status, ImageStatus::Unpinned { actual: replaced });
+        assert_eq!(report[1].status, ImageStatus::Missing);
+    }
+
+    #[test]
+    fn test_pin_images() {
+        let registry = FakeRegistry::start();
+        let digest = registry.put_manifest("images/hello", Some("0.1.0"), OCI_MANIFEST, b"{}");
+        let client = Client::new();
+        let tagged = format!("{}/images/hello:0.1.0", registry.host);
+        assert_eq!(client.resolve_digest(&tagged).unwrap(), digest);
+
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.invocation_images[0].image = tagged.clone();
+        bundle.images = None;
+        bundle
+            .pin_images(|image| client.resolve_digest(image))
+            .unwrap();
+        assert_eq!(
+            bundle.invocation_images[0].image,
+            format!("{}@{}", tagged, digest)
+        );
+        assert_eq!(bundle.invocation_images[0].content_digest, Some(digest));
+
+        bundle.invocation_images[0].image = format!("{}/images/hello:missing", registry.host);
+        bundle.invocation_images[0].content_digest = None;
+        match bundle.pin_images(|image| client.resolve_digest(image)) {
+            Err(RegistryError::NotFound(_)) => {}
+            other => panic!("expected not found, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use super::{BundleVersion, Client, PulledBundle, PushedBundle, RegistryError};
+use crate::asynchronous::blocking;
+use crate::cnab::Bundle;
+use crate::reference::AsReference;
+use std::sync::Arc;
+
+/// AsyncClient is a `Client` for async code: each request runs on tokio's blocking
+/// threads, so the runtime's own threads are never held up by the network.
+///
+/// ```no_run
+/// # async fn pull() -> Result<(), libcnab::registry::RegistryError> {
+/// use libcnab::registry::{AsyncClient, Client};
+///
+/// let client = AsyncClient::new(Client::new());
+/// let pulled = client.pull("example.com/hello:0.1.0").await?;
+/// println!("{} {}", pulled.bundle.name, pulled.digest);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AsyncClient {
+    client: Arc<Client>,
+}
+
+impl AsyncClient {
+    pub fn new(client: Client) -> Self {
+        AsyncClient {
+            client: Arc::new(client),
+        }
+    }
+
+    /// The wrapped client.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Pull the bundle stored at `reference`, see `Client::pull`.
+    pub async fn pull<R: AsReference + ?Sized>(
+        &self,
+        reference: &R,
+    ) -> Result<PulledBundle, RegistryError> {
+        let (client, reference) = (self.client.clone(), reference.to_reference()?);
+        blocking(move || client.pull(&reference)).await
+    }
+
+    /// The digest of the manifest `reference` points at, see `Client::resolve_digest`.
+    pub async fn resolve_digest<R: AsReference + ?Sized>(
+        &self,
+        reference: &R,
+    ) -> Result<String, RegistryError> {
+        let (client, reference) = (self.client.clone(), reference.to_reference()?);
+        blocking(move || client.resolve_digest(&reference)).await
+    }
+
+    /// The bundle versions in `repository`, see `Client::list_bundle_versions`.
+    pub async fn list_bundle_versions<R: AsReference + ?Sized>(
+        &self,
+        repository: &R,
+    ) -> Result<Vec<BundleVersion>, RegistryError> {
+        let (client, repository) = (self.client.clone(), repository.to_reference()?);
+        blocking(move || client.list_bundle_versions(&repository)).await
+    }
+
+    /// The newest bundle version in `repository` matching `requirement`, see
+    /// `Client::resolve_version`.
+    pub async fn resolve_version<R: AsReference + ?Sized>(
+        &self,
+        repository: &R,
+        requirement: &str,
+    ) -> Result<BundleVersion, RegistryError> {
+        let (client, repository) = (self.client.clone(), repository.to_reference()?);
+        let requirement = requirement.to_string();
+        blocking(move || client.resolve_version(&repository, &requirement)).await
+    }
+
+    /// Push `bundle` to `reference`, see `Client::push`.
+    pub async fn push<R: AsReference + ?Sized>(
+        &self,
+        bundle: &Bundle,
+        reference: &R,
+    ) -> Result<PushedBundle, RegistryError> {
+        let (client, reference) = (self.client.clone(), reference.to_reference()?);
+        let bundle = bundle.clone();
+        blocking(move || client.push(&bundle, &reference)).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::registry::testing::FakeRegistry;
+    use crate::registry::{Descriptor, Manifest, OCI_MANIFEST};
+
+    #[test]
+    fn test_async_client() {
+        let registry = FakeRegistry::start();
+        registry.put_blob("images/hello", b"{}");
+        let image = serde_json::to_vec(&Manifest {
+            schema_version: 2,
+            media_type: Some(OCI_MANIFEST.to_string()),
+            config: Descriptor::of("application/vnd.oci.image.config.v1+json", b"{}"),
+            layers: Vec::new(),
+            annotations: None,
+            artifact_type: None,
+            subject: None,
+        })
+        .unwrap();
+        registry.put_manifest("images/hello", Some("0.1.0"), OCI_MANIFEST, &image);
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.invocation_images[0].image = format!("{}/images/hello:0.1.0", registry.host);
+        bundle.images = None;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let client = AsyncClient::new(Client::new());
+        let reference = format!("{}/bundles/hello:0.1.2", registry.host);
+        let pushed = runtime.block_on(client.push(&bundle, &reference)).unwrap();
+        let pulled = runtime.block_on(client.pull(&reference)).unwrap();
+        assert_eq!(pulled.digest, pushed.digest);
+        assert_eq!(pulled.bundle.name, "helloworld");
+        assert_eq!(
+            runtime.block_on(client.resolve_digest(&reference)).unwrap(),
+            pushed.digest
+        );
+        assert!(matches!(
+            runtime.block_on(client.pull("not a reference")),
+            Err(RegistryError::InvalidReference(_))
+        ));
+    }
+}
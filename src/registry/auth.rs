@@ -0,0 +1,288 @@
+//! Registry credentials, including the ones `docker login` saved.
+use super::RegistryError;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The key Docker Hub credentials are saved under.
+const DOCKER_HUB_SERVER: &str = "https://index.docker.io/v1/";
+
+/// RegistryCredential is how the client authenticates to a registry.
+#[derive(Clone, PartialEq)]
+pub enum RegistryCredential {
+    Basic {
+        username: String,
+        password: String,
+    },
+    /// A token sent as is in the `Authorization: Bearer` header
+    Bearer(String),
+    /// A refresh token, such as the one `docker login` saves for Azure Container
+    /// Registry, exchanged with the registry's token service for bearer tokens
+    IdentityToken(String),
+}
+
+impl RegistryCredential {
+    pub fn basic(username: &str, password: &str) -> Self {
+        RegistryCredential::Basic {
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    /// The value of the `Authorization` header, for the credentials that can be sent
+    /// as is.
+    pub(crate) fn header(&self) -> Option<String> {
+        use base64::Engine;
+        match self {
+            RegistryCredential::Basic { username, password } => Some(format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD
+                    .encode(format!("{}:{}", username, password))
+            )),
+            RegistryCredential::Bearer(token) => Some(format!("Bearer {}", token)),
+            RegistryCredential::IdentityToken(_) => None,
+        }
+    }
+}
+
+/// Secrets are left out, like the rest of the crate does for sensitive values.
+impl std::fmt::Debug for RegistryCredential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegistryCredential::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .finish_non_exhaustive(),
+            RegistryCredential::Bearer(_) => f.write_str("Bearer(..)"),
+            RegistryCredential::IdentityToken(_) => f.write_str("IdentityToken(..)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct AuthEntry {
+    auth: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    registrytoken: Option<String>,
+    identitytoken: Option<String>,
+}
+
+/// DockerConfig is the part of a docker `config.json` that holds registry
+/// credentials.
+///
+/// Credentials are looked up the way the docker CLI does: a registry's entry in
+/// `credHelpers` wins, then its entry in `auths`, then the `credsStore` helper. A
+/// helper `<name>` is the `docker-credential-<name>` executable on `PATH`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DockerConfig {
+    #[serde(default)]
+    auths: BTreeMap<String, AuthEntry>,
+    #[serde(default)]
+    cred_helpers: BTreeMap<String, String>,
+    creds_store: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HelperResponse {
+    username: String,
+    secret: String,
+}
+
+impl DockerConfig {
+    /// The path of the docker configuration: `$DOCKER_CONFIG/config.json`, or
+    /// `~/.docker/config.json`.
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(dir) = std::env::var_os("DOCKER_CONFIG") {
+            return Some(PathBuf::from(dir).join("config.json"));
+        }
+        std::env::var_os("HOME")
+            .or_else(|| std::env::var_os("USERPROFILE"))
+            .map(|home| PathBuf::from(home).join(".docker").join("config.json"))
+    }
+
+    /// Load the docker configuration at its default path. A missing file is an empty
+    /// configuration.
+    pub fn from_env() -> Result<Self, RegistryError> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load(path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, RegistryError> {
+        let path = path.as_ref();
+        let contents = std::fs::read(path)
+            .map_err(|e| RegistryError::InvalidConfig(format!("{}: {}", path.display(), e)))?;
+        serde_json::from_slice(&contents)
+            .map_err(|e| RegistryError::InvalidConfig(format!("{}: {}", path.display(), e)))
+    }
+
+    /// The credential saved for `registry`, a host such as `ghcr.io` or `docker.io`.
+    pub fn credential(&self, registry: &str) -> Result<Option<RegistryCredential>, RegistryError> {
+        let registry = normalize(registry);
+        let server = if registry == "docker.io" {
+            DOCKER_HUB_SERVER
+        } else {
+            registry.as_str()
+        };
+        let helper = self
+            .cred_helpers
+            .iter()
+            .find(|(key, _)| normalize(key) == registry)
+            .map(|(_, helper)| helper);
+        if let Some(helper) = helper {
+            return run_helper(helper, server);
+        }
+        let entry = self
+            .auths
+            .iter()
+            .find(|(key, _)| normalize(key) == registry)
+            .map(|(_, entry)| entry);
+        if let Some(credential) = entry.and_then(decode_entry) {
+            return Ok(Some(credential));
+        }
+        match &self.creds_store {
+            Some(store) => run_helper(store, server),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Reduce a key of the docker configuration, which may be a URL, to a registry host.
+fn normalize(key: &str) -> String {
+    let host = key
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    match host {
+        "index.docker.io" | "registry-1.docker.io" => "docker.io".to_string(),
+        host => host.to_string(),
+    }
+}
+
+fn decode_entry(entry: &AuthEntry) -> Option<RegistryCredential> {
+    use base64::Engine;
+    if let Some(token) = &entry.registrytoken {
+        return Some(RegistryCredential::Bearer(token.clone()));
+    }
+    if let Some(token) = &entry.identitytoken {
+        return Some(RegistryCredential::IdentityToken(token.clone()));
+    }
+    if let (Some(username), Some(password)) = (&entry.username, &entry.password) {
+        return Some(RegistryCredential::basic(username, password));
+    }
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(entry.auth.as_ref()?.trim())
+        .ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some(RegistryCredential::basic(username, password))
+}
+
+/// Ask `docker-credential-<helper>` for the credential of `server`.
+fn run_helper(helper: &str, server: &str) -> Result<Option<RegistryCredential>, RegistryError> {
+    let program = format!("docker-credential-{}", helper);
+    let error = |e: &dyn std::fmt::Display| RegistryError::CredentialHelper {
+        helper: program.clone(),
+        message: e.to_string(),
+    };
+    let mut child = Command::new(&program)
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| error(&e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(server.as_bytes()).map_err(|e| error(&e))?;
+    }
+    let output = child.wait_with_output().map_err(|e| error(&e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        if stdout.contains("credentials not found") {
+            return Ok(None);
+        }
+        return Err(error(&stdout.trim()));
+    }
+    let response: HelperResponse = serde_json::from_str(&stdout).map_err(|e| error(&e))?;
+    // Helpers return identity tokens under this username.
+    if response.username == "<token>" {
+        return Ok(Some(RegistryCredential::IdentityToken(response.secret)));
+    }
+    Ok(Some(RegistryCredential::basic(
+        &response.username,
+        &response.secret,
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_docker_config() {
+        let config: DockerConfig = serde_json::from_str(
+            r#"{
+                "auths": {
+                    "https://index.docker.io/v1/": {"auth": "bWU6aHVudGVyMg=="},
+                    "ghcr.io": {"username": "octocat", "password": "ghp"},
+                    "example.com:5000": {"registrytoken": "abc"},
+                    "myregistry.azurecr.io": {"auth": "MDAwMDAwMDAtMDAwMC0wMDAwLTAwMDAtMDAwMDAwMDAwMDAwOg==", "identitytoken": "refresh"}
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.credential("docker.io").unwrap(),
+            Some(RegistryCredential::basic("me", "hunter2"))
+        );
+        assert_eq!(
+            config.credential("ghcr.io").unwrap(),
+            Some(RegistryCredential::basic("octocat", "ghp"))
+        );
+        assert_eq!(
+            config.credential("example.com:5000").unwrap(),
+            Some(RegistryCredential::Bearer("abc".to_string()))
+        );
+        assert_eq!(
+            config.credential("myregistry.azurecr.io").unwrap(),
+            Some(RegistryCredential::IdentityToken("refresh".to_string()))
+        );
+        assert_eq!(config.credential("quay.io").unwrap(), None);
+        assert_eq!(
+            RegistryCredential::basic("me", "hunter2").header().unwrap(),
+            "Basic bWU6aHVudGVyMg=="
+        );
+        assert!(!format!("{:?}", RegistryCredential::basic("me", "hunter2")).contains("hunter2"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_credential_helper() {
+        let dir = tempfile::tempdir().unwrap();
+        crate::fixture::script(
+            dir.path(),
+            "docker-credential-fake",
+            "#!/bin/sh\nread server\n[ \"$server\" = ghcr.io ] || { echo credentials not found; exit 1; }\necho '{\"ServerURL\":\"ghcr.io\",\"Username\":\"octocat\",\"Secret\":\"s3cret\"}'\n",
+        );
+        let path = std::env::var("PATH").unwrap_or_default();
+        std::env::set_var("PATH", format!("{}:{}", dir.path().display(), path));
+
+        let config: DockerConfig = serde_json::from_str(
+            r#"{"auths": {"ghcr.io": {"auth": "bWU6aHVudGVyMg=="}}, "credHelpers": {"ghcr.io": "fake"}, "credsStore": "fake"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.credential("ghcr.io").unwrap(),
+            Some(RegistryCredential::basic("octocat", "s3cret"))
+        );
+        assert_eq!(config.credential("quay.io").unwrap(), None);
+    }
+}
@@ -0,0 +1,210 @@
+//! An on-disk cache of what the registry client pulls.
+use super::{digest_like, sha256_digest};
+use crate::reference::{AsReference, BundleReference};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Cache keeps manifests and blobs on disk by digest, so a bundle pulled once is not
+/// downloaded again.
+///
+/// Content fetched by digest cannot change, so it is kept until the cache is
+/// cleared. It is checked against its digest whenever it is read back, and an
+/// entry that does not match, such as one changed on disk, is removed and fetched
+/// again. What a tag points at can: a tag is resolved again on every pull unless
+/// it was resolved less than `Cache::ttl` ago.
+///
+/// ```no_run
+/// use libcnab::registry::{Cache, Client};
+/// use std::time::Duration;
+///
+/// let cache = Cache::new(Cache::default_dir().unwrap()).ttl(Duration::from_secs(3600));
+/// let client = Client::new().cache(cache);
+/// let pulled = client.pull("example.com/bundles/helloworld:0.1.0").unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TagEntry {
+    digest: String,
+    resolved: DateTime<Utc>,
+}
+
+impl Cache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Cache {
+            dir: dir.into(),
+            ttl: Duration::from_secs(0),
+        }
+    }
+
+    /// The directory of the user's cache: `$XDG_CACHE_HOME/libcnab`, or
+    /// `~/.cache/libcnab`.
+    pub fn default_dir() -> Option<PathBuf> {
+        if let Some(dir) = std::env::var_os("XDG_CACHE_HOME") {
+            return Some(PathBuf::from(dir).join("libcnab"));
+        }
+        std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".cache"))
+            .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+            .map(|dir| dir.join("libcnab"))
+    }
+
+    /// Trust what a tag was resolved to for `ttl`, without asking the registry again.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Forget what the tag of `reference` was resolved to, so the next pull asks the
+    /// registry.
+    pub fn invalidate<R: AsReference + ?Sized>(&self, reference: &R) -> std::io::Result<()> {
+        let reference = reference
+            .to_reference()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        match std::fs::remove_file(self.tag_path(&reference, reference.reference())) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove everything in the cache.
+    pub fn clear(&self) -> std::io::Result<()> {
+        match std::fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn tag_path(&self, repository: &BundleReference, tag: &str) -> PathBuf {
+        let key = format!("{}:{}", repository.name(), tag);
+        let digest = sha256_digest(key.as_bytes());
+        self.dir
+            .join("tags")
+            .join(format!("{}.json", digest.trim_start_matches("sha256:")))
+    }
+
+    /// Where content with `digest` is kept, if it is a digest at all.
+    fn content_path(&self, kind: &str, digest: &str) -> Option<PathBuf> {
+        let (algorithm, hex) = digest.split_once(':')?;
+        let valid = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric());
+        if !valid(algorithm) || !valid(hex) {
+            return None;
+        }
+        Some(self.dir.join(kind).join(algorithm).join(hex))
+    }
+
+    /// The digest `tag` of `repository` was resolved to, unless that is too old.
+    pub(crate) fn tag(&self, repository: &BundleReference, tag: &str) -> Option<String> {
+        let contents = std::fs::read(self.tag_path(repository, tag)).ok()?;
+        let entry: TagEntry = serde_json::from_slice(&contents).ok()?;
+        let age = Utc::now()
+            .signed_duration_since(entry.resolved)
+            .to_std()
+            .ok()?;
+        if age < self.ttl {
+            Some(entry.digest)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn put_tag(&self, repository: &BundleReference, tag: &str, digest: &str) {
+        let entry = TagEntry {
+            digest: digest.to_string(),
+            resolved: Utc::now(),
+        };
+        let contents = serde_json::to_vec(&entry).expect("tags serialize");
+        write(&self.tag_path(repository, tag), &contents);
+    }
+
+    /// The media type and content of the manifest `digest`.
+    pub(crate) fn manifest(&self, digest: &str) -> Option<(String, Vec<u8>)> {
+        let path = self.content_path("manifests", digest)?;
+        let media_type = std::fs::read_to_string(path.with_extension("type")).ok()?;
+        let body = verified(&path, digest)?;
+        Some((media_type, body))
+    }
+
+    pub(crate) fn put_manifest(&self, digest: &str, media_type: &str, body: &[u8]) {
+        if let Some(path) = self.content_path("manifests", digest) {
+            // The media type goes first, so a manifest is never found without it.
+            write(&path.with_extension("type"), media_type.as_bytes());
+            write(&path, body);
+        }
+    }
+
+    pub(crate) fn blob(&self, digest: &str) -> Option<Vec<u8>> {
+        verified(&self.content_path("blobs", digest)?, digest)
+    }
+
+    pub(crate) fn put_blob(&self, digest: &str, body: &[u8]) {
+        if let Some(path) = self.content_path("blobs", digest) {
+            write(&path, body);
+        }
+    }
+}
+
+/// The content of the file at `path`, unless it does not have `digest`, in which
+/// case the file is removed.
+fn verified(path: &Path, digest: &str) -> Option<Vec<u8>> {
+    let body = std::fs::read(path).ok()?;
+    if digest_like(digest, &body).as_deref() == Some(digest) {
+        return Some(body);
+    }
+    #[cfg(feature = "tracing")]
+    tracing::warn!(path = %path.display(), digest, "removing a corrupt cache entry");
+    let _ = std::fs::remove_file(path);
+    None
+}
+
+/// Replace a file of the cache. The cache only saves work, so a failure to write it
+/// is not an error.
+fn write(path: &Path, contents: &[u8]) {
+    if let Err(_e) = try_write(path, contents) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(path = %path.display(), error = %_e, "could not write to the cache");
+    }
+}
+
+/// Write to a temporary file first, so that readers never see part of a file.
+fn try_write(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let dir = path.parent().expect("cache paths have a parent");
+    std::fs::create_dir_all(dir)?;
+    let temporary = dir.join(format!(".{}", ulid::Ulid::new()));
+    std::fs::write(&temporary, contents)?;
+    std::fs::rename(&temporary, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temporary);
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tampered() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path());
+        let digest = sha256_digest(b"{}");
+        cache.put_blob(&digest, b"{}");
+        cache.put_manifest(&digest, "application/json", b"{}");
+        assert_eq!(cache.blob(&digest).unwrap(), b"{}");
+        assert_eq!(cache.manifest(&digest).unwrap().1, b"{}");
+
+        for kind in ["blobs", "manifests"] {
+            let path = cache.content_path(kind, &digest).unwrap();
+            std::fs::write(&path, b"[]").unwrap();
+        }
+        assert_eq!(cache.blob(&digest), None);
+        assert_eq!(cache.manifest(&digest), None);
+        for kind in ["blobs", "manifests"] {
+            assert!(!cache.content_path(kind, &digest).unwrap().exists());
+        }
+    }
+}
@@ -0,0 +1,411 @@
+//! A store of image layers on disk, shared by the bundles that use them.
+use super::{
+    image_reference, in_parallel, parse, Cache, Client, Descriptor, Index, Manifest, Phase,
+    RegistryError, Tracker, Verified, DOCKER_MANIFEST_LIST, OCI_INDEX,
+};
+use crate::cnab::Bundle;
+use crate::reference::BundleReference;
+use crate::relocation::RelocationMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::PathBuf;
+
+/// LayerStore keeps the blobs of images, their layers and configs, on disk by
+/// digest, so that each is downloaded and stored once however many bundles use it.
+///
+/// An owner, such as an installation or a bundle's reference, retains the layers it
+/// uses until it releases them, and `gc` removes the layers no owner retains. The
+/// layers fetched for the exports of a client given the store with
+/// `Client::layer_store` are kept without an owner, until the next `gc`.
+///
+/// Several processes may use a store at once, since every file is written aside and
+/// renamed into place, but `gc` removes what a pull fetched and did not retain yet,
+/// so it is best run when nothing else uses the store.
+///
+/// ```no_run
+/// use libcnab::registry::{Client, LayerStore};
+///
+/// let store = LayerStore::new(LayerStore::default_dir().unwrap());
+/// let client = Client::new();
+/// let pulled = client.pull("example.com/bundles/helloworld:0.1.0").unwrap();
+/// store
+///     .pull(&client, "helloworld", &pulled.bundle, &pulled.relocation)
+///     .unwrap();
+/// // Once the layers are no longer needed:
+/// store.release("helloworld").unwrap();
+/// store.gc().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct LayerStore {
+    dir: PathBuf,
+}
+
+/// The layers an owner retains, as kept in the store
+#[derive(Serialize, Deserialize)]
+struct Owner {
+    owner: String,
+    layers: BTreeSet<String>,
+}
+
+/// Collected is what `LayerStore::gc` removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Collected {
+    pub layers: usize,
+    pub bytes: u64,
+}
+
+impl LayerStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        LayerStore { dir: dir.into() }
+    }
+
+    /// `layers` in the directory of the user's cache, `Cache::default_dir`.
+    pub fn default_dir() -> Option<PathBuf> {
+        Cache::default_dir().map(|dir| dir.join("layers"))
+    }
+
+    /// Where the layer `digest` is kept, if it is in the store.
+    pub fn path(&self, digest: &str) -> Option<PathBuf> {
+        self.layer_path(digest).filter(|path| path.is_file())
+    }
+
+    pub fn contains(&self, digest: &str) -> bool {
+        self.path(digest).is_some()
+    }
+
+    /// Add the layer `digest` of `size` bytes read from `content`, unless the store
+    /// has it already, checking it against its digest. Returns where it is kept.
+    pub fn insert<R: Read>(&self, digest: &str, size: u64, content: R) -> std::io::Result<PathBuf> {
+        let path = self.layer_path(digest).ok_or_else(|| {
+            std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid digest {}", digest),
+            )
+        })?;
+        if path.is_file() {
+            return Ok(path);
+        }
+        let dir = path.parent().expect("layer paths have a parent");
+        std::fs::create_dir_all(dir)?;
+        let temporary = dir.join(format!(".{}", ulid::Ulid::new()));
+        let written = File::create(&temporary).and_then(|mut file| {
+            std::io::copy(&mut Verified::new(content, digest, size), &mut file)?;
+            std::fs::rename(&temporary, &path)
+        });
+        if written.is_err() {
+            let _ = std::fs::remove_file(&temporary);
+        }
+        written.map(|_| path)
+    }
+
+    /// Record that `owner` uses `layers`, in place of the layers it used before.
+    pub fn retain(&self, owner: &str, layers: &[String]) -> std::io::Result<()> {
+        let entry = Owner {
+            owner: owner.to_string(),
+            layers: layers.iter().cloned().collect(),
+        };
+        let path = self.owner_path(owner);
+        let dir = path.parent().expect("owner paths have a parent");
+        std::fs::create_dir_all(dir)?;
+        let temporary = dir.join(format!(".{}", ulid::Ulid::new()));
+        std::fs::write(
+            &temporary,
+            serde_json::to_vec(&entry).expect("owners serialize"),
+        )?;
+        std::fs::rename(&temporary, &path).inspect_err(|_| {
+            let _ = std::fs::remove_file(&temporary);
+        })
+    }
+
+    /// Forget the layers `owner` uses, so that `gc` removes those no other owner
+    /// retains.
+    pub fn release(&self, owner: &str) -> std::io::Result<()> {
+        match std::fs::remove_file(self.owner_path(owner)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// The owners that retain layers, in order.
+    pub fn owners(&self) -> std::io::Result<Vec<String>> {
+        let mut owners: Vec<String> = self.entries()?.into_iter().map(|e| e.owner).collect();
+        owners.sort();
+        Ok(owners)
+    }
+
+    /// How many owners retain the layer `digest`.
+    pub fn references(&self, digest: &str) -> std::io::Result<usize> {
+        let entries = self.entries()?;
+        Ok(entries.iter().filter(|e| e.layers.contains(digest)).count())
+    }
+
+    /// Remove the layers no owner retains.
+    pub fn gc(&self) -> std::io::Result<Collected> {
+        let retained: BTreeSet<String> =
+            self.entries()?.into_iter().flat_map(|e| e.layers).collect();
+        let mut collected = Collected::default();
+        for algorithm in read_dir(self.dir.join("blobs"))? {
+            let name = algorithm.file_name().to_string_lossy().into_owned();
+            for layer in read_dir(algorithm.path())? {
+                let hex = layer.file_name().to_string_lossy().into_owned();
+                if hex.starts_with('.') || retained.contains(&format!("{}:{}", name, hex)) {
+                    continue;
+                }
+                let size = layer.metadata()?.len();
+                std::fs::remove_file(layer.path())?;
+                collected.layers += 1;
+                collected.bytes += size;
+            }
+        }
+        Ok(collected)
+    }
+
+    /// Fetch the layers and configs of every image of `bundle` with `client`, from
+    /// where `relocation` says the images are, and retain them for `owner`. Only the
+    /// layers the store does not have are downloaded, as many at once as the client's
+    /// `Client::parallelism`. Returns the digests of the layers, in order.
+    pub fn pull(
+        &self,
+        client: &Client,
+        owner: &str,
+        bundle: &Bundle,
+        relocation: &RelocationMap,
+    ) -> Result<Vec<String>, RegistryError> {
+        let tracker = client.tracker();
+        tracker.phase(Phase::Resolving);
+        let images = bundle
+            .invocation_images
+            .iter()
+            .map(|i| (&i.image, &i.image_type, &i.content_digest))
+            .chain(
+                bundle
+                    .images
+                    .iter()
+                    .flat_map(|images| images.values())
+                    .map(|i| (&i.image, &i.image_type, &i.content_digest)),
+            );
+        let mut blobs = Vec::new();
+        for (image, image_type, content_digest) in images {
+            let source = client.mirrored(image_reference(
+                relocation.relocate(image),
+                image_type.as_deref(),
+                content_digest.as_deref(),
+            )?);
+            image_blobs(client, &source, source.reference(), &mut blobs)?;
+        }
+
+        tracker.phase(Phase::Transferring);
+        tracker.layers(blobs.len());
+        in_parallel(client.parallel_transfers(), &blobs, |(source, blob)| {
+            self.fetch(client, source, blob, &tracker)?;
+            tracker.layer_done();
+            Ok::<_, RegistryError>(())
+        })?;
+        let mut layers: Vec<String> = blobs.into_iter().map(|(_, blob)| blob.digest).collect();
+        layers.sort();
+        self.retain(owner, &layers)?;
+        Ok(layers)
+    }
+
+    /// The layer `blob` of `source`, fetched unless the store has it.
+    pub(crate) fn fetch(
+        &self,
+        client: &Client,
+        source: &BundleReference,
+        blob: &Descriptor,
+        tracker: &Tracker<'_>,
+    ) -> Result<PathBuf, RegistryError> {
+        if let Some(path) = client.cached("layer", self.path(&blob.digest)) {
+            return Ok(path);
+        }
+        let content = client.blob_reader(source, &blob.digest)?;
+        let content = tracker.reader(&blob.digest, blob.size, content);
+        Ok(self.insert(&blob.digest, blob.size, content)?)
+    }
+
+    /// Where the layer `digest` is kept, if it is a digest at all.
+    fn layer_path(&self, digest: &str) -> Option<PathBuf> {
+        let (algorithm, hex) = digest.split_once(':')?;
+        let valid = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric());
+        if !valid(algorithm) || !valid(hex) {
+            return None;
+        }
+        Some(self.dir.join("blobs").join(algorithm).join(hex))
+    }
+
+    fn owner_path(&self, owner: &str) -> PathBuf {
+        let digest = super::sha256_digest(owner.as_bytes());
+        self.dir
+            .join("owners")
+            .join(format!("{}.json", digest.trim_start_matches("sha256:")))
+    }
+
+    /// What each owner retains. An entry that cannot be read retains nothing.
+    fn entries(&self) -> std::io::Result<Vec<Owner>> {
+        let mut entries = Vec::new();
+        for entry in read_dir(self.dir.join("owners"))? {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            if let Ok(owner) = serde_json::from_slice(&std::fs::read(entry.path())?) {
+                entries.push(owner);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// The entries of `dir`, none if it does not exist.
+fn read_dir(dir: PathBuf) -> std::io::Result<Vec<std::fs::DirEntry>> {
+    match std::fs::read_dir(dir) {
+        Ok(entries) => entries.collect(),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Add the blobs of the image `reference` of `source` to `blobs`, or of every image
+/// of the index it is, unless they are there already.
+fn image_blobs(
+    client: &Client,
+    source: &BundleReference,
+    reference: &str,
+    blobs: &mut Vec<(BundleReference, Descriptor)>,
+) -> Result<(), RegistryError> {
+    let manifest = client.manifest(source, reference)?;
+    match manifest.media_type.as_str() {
+        OCI_INDEX | DOCKER_MANIFEST_LIST => {
+            let index: Index = parse(source, &manifest.body)?;
+            for child in &index.manifests {
+                image_blobs(client, source, &child.digest, blobs)?;
+            }
+        }
+        _ => {
+            let image: Manifest = parse(source, &manifest.body)?;
+            for blob in Some(&image.config).into_iter().chain(&image.layers) {
+                if !blobs.iter().any(|(_, b)| b.digest == blob.digest) {
+                    blobs.push((source.clone(), blob.clone()));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::testing::FakeRegistry;
+    use super::super::OCI_MANIFEST;
+    use super::*;
+
+    fn descriptor(media_type: &str, digest: &str, size: usize) -> Descriptor {
+        Descriptor {
+            media_type: media_type.to_string(),
+            digest: digest.to_string(),
+            size: size as u64,
+            platform: None,
+            annotations: None,
+            artifact_type: None,
+        }
+    }
+
+    /// Push an image of `layers` on top of a shared base layer, and return a bundle
+    /// of it.
+    fn bundle(registry: &FakeRegistry, name: &str, layers: &[&[u8]]) -> Bundle {
+        let repository = format!("images/{}", name);
+        let config = registry.put_blob(&repository, b"{}");
+        let layers: Vec<Descriptor> = Some(&b"base"[..])
+            .into_iter()
+            .chain(layers.iter().copied())
+            .map(|content| {
+                let digest = registry.put_blob(&repository, content);
+                descriptor(
+                    "application/vnd.oci.image.layer.v1.tar",
+                    &digest,
+                    content.len(),
+                )
+            })
+            .collect();
+        let image = serde_json::to_vec(&Manifest {
+            schema_version: 2,
+            media_type: Some(OCI_MANIFEST.to_string()),
+            config: descriptor("application/vnd.oci.image.config.v1+json", &config, 2),
+            layers,
+            annotations: None,
+            artifact_type: None,
+            subject: None,
+        })
+        .unwrap();
+        registry.put_manifest(&repository, Some("0.1.0"), OCI_MANIFEST, &image);
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.invocation_images[0].image = format!("{}/{}:0.1.0", registry.host, repository);
+        bundle.images = None;
+        bundle
+    }
+
+    #[test]
+    fn test_layer_store() {
+        let registry = FakeRegistry::start();
+        let dir = tempfile::tempdir().unwrap();
+        let store = LayerStore::new(dir.path());
+        let client = Client::new();
+        let web = bundle(&registry, "web", &[b"web"]);
+        let api = bundle(&registry, "api", &[b"api"]);
+
+        let web_layers = store
+            .pull(&client, "web", &web, &RelocationMap::new())
+            .unwrap();
+        assert_eq!(web_layers.len(), 3);
+        registry.state.lock().unwrap().requests.clear();
+        let api_layers = store
+            .pull(&client, "api", &api, &RelocationMap::new())
+            .unwrap();
+        // The base layer and the config are downloaded once.
+        let base = super::super::sha256_digest(b"base");
+        let downloaded: Vec<String> = registry
+            .state
+            .lock()
+            .unwrap()
+            .requests
+            .iter()
+            .filter(|r| r.starts_with("GET") && r.contains("/blobs/"))
+            .cloned()
+            .collect();
+        assert_eq!(
+            downloaded,
+            vec![format!(
+                "GET /v2/images/api/blobs/{}",
+                super::super::sha256_digest(b"api")
+            )]
+        );
+        assert_eq!(store.references(&base).unwrap(), 2);
+        assert_eq!(
+            std::fs::read(store.path(&base).unwrap()).unwrap(),
+            b"base".to_vec()
+        );
+        assert_eq!(store.owners().unwrap(), vec!["api", "web"]);
+
+        // Only the layers no owner retains are collected.
+        store.release("web").unwrap();
+        assert_eq!(
+            store.gc().unwrap(),
+            Collected {
+                layers: 1,
+                bytes: 3
+            }
+        );
+        assert!(api_layers.iter().all(|layer| store.contains(layer)));
+        assert!(!store.contains(&super::super::sha256_digest(b"web")));
+        store.release("api").unwrap();
+        assert_eq!(store.gc().unwrap().layers, 3);
+        assert_eq!(store.owners().unwrap(), Vec::<String>::new());
+
+        // A layer that does not match its digest is not kept.
+        let digest = super::super::sha256_digest(b"layer");
+        let error = store.insert(&digest, 5, &b"other"[..]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(!store.contains(&digest));
+    }
+}
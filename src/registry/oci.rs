@@ -0,0 +1,219 @@
+//! The OCI documents a CNAB bundle is published as.
+//!
+//! These are what `Client::push` assembles, for use with other registry clients:
+//!
+//! ```
+//! use libcnab::registry::{config_blob, Descriptor, IndexBuilder, Manifest, OCI_MANIFEST};
+//! use libcnab::Bundle;
+//!
+//! let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+//! let config = config_blob(&bundle);
+//! let manifest = serde_json::to_vec(&Manifest::bundle_config(&config)).unwrap();
+//! let index = IndexBuilder::new(&bundle)
+//!     .config(Descriptor::of(OCI_MANIFEST, &manifest))
+//!     .invocation_image(Descriptor::of(OCI_MANIFEST, b"{}"))
+//!     .build();
+//! assert_eq!(index.manifests.len(), 2);
+//! assert_eq!(index.annotations.unwrap()["org.opencontainers.image.title"], "helloworld");
+//! ```
+use super::sha256_digest;
+use crate::cnab::Bundle;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const OCI_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+pub const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+pub const DOCKER_MANIFEST_LIST: &str = "application/vnd.docker.distribution.manifest.list.v2+json";
+pub const DOCKER_MANIFEST: &str = "application/vnd.docker.distribution.manifest.v2+json";
+/// The media type of the blob that holds a bundle's `bundle.json`
+pub const CNAB_CONFIG: &str = "application/vnd.cnab.config.v1+json";
+
+/// The annotation that says what an entry of a bundle's index is: `config`,
+/// `invocation` or `component`
+pub const MANIFEST_TYPE_ANNOTATION: &str = "io.cnab.manifest.type";
+/// The annotation that names the bundle image a `component` entry holds
+pub const COMPONENT_NAME_ANNOTATION: &str = "io.cnab.component.name";
+
+/// The `artifactType` of a bundle's index
+pub const CNAB_ARTIFACT_TYPE: &str = "application/vnd.cnab.manifest.v1";
+pub const ARTIFACT_TYPE_ANNOTATION: &str = "org.opencontainers.artifactType";
+/// The annotation that holds the CNAB specification version of a bundle
+pub const RUNTIME_VERSION_ANNOTATION: &str = "io.cnab.runtime_version";
+/// The annotation that holds a bundle's keywords as a JSON array
+pub const KEYWORDS_ANNOTATION: &str = "io.cnab.keywords";
+pub const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+pub const VERSION_ANNOTATION: &str = "org.opencontainers.image.version";
+pub const DESCRIPTION_ANNOTATION: &str = "org.opencontainers.image.description";
+/// The annotation that names an image of an OCI image layout
+pub const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+
+/// Every manifest media type the registry client accepts.
+pub(crate) const MANIFEST_TYPES: &[&str] = &[
+    OCI_INDEX,
+    DOCKER_MANIFEST_LIST,
+    OCI_MANIFEST,
+    DOCKER_MANIFEST,
+];
+
+/// Descriptor points at a piece of content in a repository.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+    pub media_type: String,
+    pub digest: String,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
+    /// What kind of artifact the content is, such as a signature or an SBOM
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+}
+
+impl Descriptor {
+    /// The descriptor of `content`, with its sha256 digest.
+    pub fn of(media_type: &str, content: &[u8]) -> Self {
+        Descriptor {
+            media_type: media_type.to_string(),
+            digest: sha256_digest(content),
+            size: content.len() as u64,
+            platform: None,
+            annotations: None,
+            artifact_type: None,
+        }
+    }
+
+    pub fn annotate(mut self, key: &str, value: &str) -> Self {
+        self.annotations
+            .get_or_insert_with(BTreeMap::new)
+            .insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn annotation(&self, key: &str) -> Option<&str> {
+        self.annotations
+            .as_ref()
+            .and_then(|a| a.get(key))
+            .map(String::as_str)
+    }
+}
+
+/// Manifest is an OCI image manifest, or a Docker v2 one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub schema_version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    pub config: Descriptor,
+    #[serde(default)]
+    pub layers: Vec<Descriptor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
+    /// What kind of artifact the manifest holds, when it is not an image
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    /// The manifest this one is attached to, as a signature or an SBOM is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<Descriptor>,
+}
+
+impl Manifest {
+    /// The manifest of a bundle's `config` entry, whose config blob is `config`.
+    pub fn bundle_config(config: &[u8]) -> Self {
+        Manifest {
+            schema_version: 2,
+            media_type: Some(OCI_MANIFEST.to_string()),
+            config: Descriptor::of(CNAB_CONFIG, config),
+            layers: Vec::new(),
+            annotations: None,
+            artifact_type: None,
+            subject: None,
+        }
+    }
+}
+
+/// The config blob of a bundle: its `bundle.json` as canonical JSON.
+pub fn config_blob(bundle: &Bundle) -> Vec<u8> {
+    bundle.to_canonical_json()
+}
+
+/// Index is an OCI image index, or a Docker manifest list.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Index {
+    pub schema_version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    pub manifests: Vec<Descriptor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
+}
+
+/// IndexBuilder assembles the index a bundle is stored as, annotated with the
+/// bundle's metadata. Entries are kept in the order they are added: the `config`
+/// entry first, then the invocation images in the order of the bundle's
+/// `invocationImages`, then the component images.
+#[derive(Debug, Clone)]
+pub struct IndexBuilder {
+    manifests: Vec<Descriptor>,
+    annotations: BTreeMap<String, String>,
+}
+
+impl IndexBuilder {
+    pub fn new(bundle: &Bundle) -> Self {
+        let mut annotations = BTreeMap::new();
+        let mut annotate = |key: &str, value: &str| {
+            annotations.insert(key.to_string(), value.to_string());
+        };
+        annotate(ARTIFACT_TYPE_ANNOTATION, CNAB_ARTIFACT_TYPE);
+        annotate(RUNTIME_VERSION_ANNOTATION, &bundle.schema_version);
+        annotate(
+            KEYWORDS_ANNOTATION,
+            &serde_json::to_string(bundle.keywords.as_deref().unwrap_or_default())
+                .expect("keywords serialize"),
+        );
+        annotate(TITLE_ANNOTATION, &bundle.name);
+        annotate(VERSION_ANNOTATION, &bundle.version.to_string());
+        if let Some(description) = &bundle.description {
+            annotate(DESCRIPTION_ANNOTATION, description);
+        }
+        IndexBuilder {
+            manifests: Vec::new(),
+            annotations,
+        }
+    }
+
+    /// Add the entry of the manifest that holds the `bundle.json`.
+    pub fn config(mut self, manifest: Descriptor) -> Self {
+        self.manifests
+            .push(manifest.annotate(MANIFEST_TYPE_ANNOTATION, "config"));
+        self
+    }
+
+    pub fn invocation_image(mut self, manifest: Descriptor) -> Self {
+        self.manifests
+            .push(manifest.annotate(MANIFEST_TYPE_ANNOTATION, "invocation"));
+        self
+    }
+
+    /// Add the entry of the bundle image `name`.
+    pub fn component(mut self, name: &str, manifest: Descriptor) -> Self {
+        self.manifests.push(
+            manifest
+                .annotate(MANIFEST_TYPE_ANNOTATION, "component")
+                .annotate(COMPONENT_NAME_ANNOTATION, name),
+        );
+        self
+    }
+
+    pub fn build(self) -> Index {
+        Index {
+            schema_version: 2,
+            media_type: Some(OCI_INDEX.to_string()),
+            manifests: self.manifests,
+            annotations: Some(self.annotations),
+        }
+    }
+}
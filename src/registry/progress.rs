@@ -0,0 +1,232 @@
+use std::fmt;
+use std::io::Read;
+use std::sync::Mutex;
+
+/// The fewest bytes transferred between two `Progress::Bytes` of a layer, besides
+/// the last
+pub const PROGRESS_INTERVAL: u64 = 1024 * 1024;
+
+/// Phase is the part of a long-running registry operation under way. The phases of
+/// an operation come in this order, although some may be skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// Fetching the manifests of the bundle and of its images
+    Resolving,
+    /// Moving the layers of the images
+    Transferring,
+    /// Pushing the manifests of the images and the bundle's index
+    Publishing,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Resolving => "resolving",
+            Phase::Transferring => "transferring",
+            Phase::Publishing => "publishing",
+        })
+    }
+}
+
+/// Progress is something that happened during a pull, push or copy, or an export or
+/// import of a thick bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Progress {
+    /// The operation moved on to another phase
+    Phase(Phase),
+    /// `done` of the `total` layers found so far were transferred, or found at their
+    /// target already. The total grows as the manifests of images are read.
+    Layers { done: usize, total: usize },
+    /// `transferred` of the `size` bytes of the layer `digest` were moved
+    Bytes {
+        digest: String,
+        transferred: u64,
+        size: u64,
+    },
+}
+
+/// ProgressSink receives the progress of the operations of a registry `Client`, such
+/// as to draw progress bars, and is given to it with `Client::progress`.
+///
+/// Layers are transferred from several threads at once, so `progress` may be called
+/// from several threads too. Any `Fn(&Progress) + Send + Sync` is a ProgressSink.
+pub trait ProgressSink: Send + Sync {
+    fn progress(&self, progress: &Progress);
+}
+
+impl<F> ProgressSink for F
+where
+    F: Fn(&Progress) + Send + Sync,
+{
+    fn progress(&self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+impl fmt::Debug for dyn ProgressSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressSink")
+    }
+}
+
+/// Tracker reports the progress of one operation to the sink of its client.
+pub(crate) struct Tracker<'a> {
+    sink: Option<&'a dyn ProgressSink>,
+    state: Mutex<TrackerState>,
+}
+
+#[derive(Default)]
+struct TrackerState {
+    phase: Option<Phase>,
+    done: usize,
+    total: usize,
+}
+
+impl<'a> Tracker<'a> {
+    pub(crate) fn new(sink: Option<&'a dyn ProgressSink>) -> Self {
+        Tracker {
+            sink,
+            state: Mutex::new(TrackerState::default()),
+        }
+    }
+
+    /// Move on to `phase`, unless the operation is past it already, such as when
+    /// an operation is part of another.
+    pub(crate) fn phase(&self, phase: Phase) {
+        let mut state = self.state.lock().expect("lock poisoned");
+        if let Some(sink) = self.sink.filter(|_| state.phase < Some(phase)) {
+            state.phase = Some(phase);
+            sink.progress(&Progress::Phase(phase));
+        }
+    }
+
+    /// Count `count` more layers to transfer.
+    pub(crate) fn layers(&self, count: usize) {
+        self.update(|state| state.total += count);
+    }
+
+    /// Count a layer as transferred.
+    pub(crate) fn layer_done(&self) {
+        self.update(|state| state.done += 1);
+    }
+
+    fn update(&self, f: impl FnOnce(&mut TrackerState)) {
+        if let Some(sink) = self.sink {
+            let mut state = self.state.lock().expect("lock poisoned");
+            f(&mut state);
+            sink.progress(&Progress::Layers {
+                done: state.done,
+                total: state.total,
+            });
+        }
+    }
+
+    /// Report the bytes read from `inner`, the content of the layer `digest`.
+    pub(crate) fn reader<R: Read>(&self, digest: &str, size: u64, inner: R) -> Counted<'_, R> {
+        self.reader_at(digest, size, 0, inner)
+    }
+
+    /// Report the bytes read from `inner`, the content of the layer `digest` from
+    /// `offset` on, counting the bytes before it as transferred already.
+    pub(crate) fn reader_at<R: Read>(
+        &self,
+        digest: &str,
+        size: u64,
+        offset: u64,
+        inner: R,
+    ) -> Counted<'_, R> {
+        Counted {
+            inner,
+            sink: self.sink,
+            digest: digest.to_string(),
+            size,
+            transferred: offset,
+            reported: offset,
+        }
+    }
+}
+
+/// Counted reads a layer, reporting how much of it was read every
+/// `PROGRESS_INTERVAL` bytes and at its end.
+pub(crate) struct Counted<'a, R> {
+    inner: R,
+    sink: Option<&'a dyn ProgressSink>,
+    digest: String,
+    size: u64,
+    transferred: u64,
+    reported: u64,
+}
+
+impl<R: Read> Read for Counted<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.transferred += n as u64;
+        let finished = n == 0 || self.transferred == self.size;
+        let due = self.transferred - self.reported >= PROGRESS_INTERVAL
+            || (finished && self.transferred > self.reported);
+        if let Some(sink) = self.sink.filter(|_| due) {
+            self.reported = self.transferred;
+            sink.progress(&Progress::Bytes {
+                digest: self.digest.clone(),
+                transferred: self.transferred,
+                size: self.size,
+            });
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tracker() {
+        let events = Mutex::new(Vec::new());
+        let sink = |progress: &Progress| events.lock().unwrap().push(progress.clone());
+        let tracker = Tracker::new(Some(&sink));
+        tracker.phase(Phase::Transferring);
+        tracker.phase(Phase::Resolving);
+        tracker.layers(2);
+        let content = vec![7; PROGRESS_INTERVAL as usize + 10];
+        let mut read = Vec::new();
+        tracker
+            .reader("sha256:abc", content.len() as u64, &content[..])
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!(read, content);
+        tracker.layer_done();
+        tracker.phase(Phase::Publishing);
+
+        let bytes = |transferred| Progress::Bytes {
+            digest: "sha256:abc".to_string(),
+            transferred,
+            size: content.len() as u64,
+        };
+        let events = events.into_inner().unwrap();
+        assert_eq!(events[0], Progress::Phase(Phase::Transferring));
+        assert_eq!(events[1], Progress::Layers { done: 0, total: 2 });
+        let partial = &events[2..events.len() - 3];
+        assert!(partial.iter().all(|e| match e {
+            Progress::Bytes { transferred, .. } => *transferred >= PROGRESS_INTERVAL,
+            _ => false,
+        }));
+        assert_eq!(
+            events[events.len() - 3..],
+            [
+                bytes(content.len() as u64),
+                Progress::Layers { done: 1, total: 2 },
+                Progress::Phase(Phase::Publishing)
+            ]
+        );
+
+        // Without a sink, reading only reads.
+        let quiet = Tracker::new(None);
+        let mut read = Vec::new();
+        quiet
+            .reader("sha256:abc", 3, &b"abc"[..])
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!(read, b"abc");
+    }
+}
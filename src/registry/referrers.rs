@@ -0,0 +1,408 @@
+//! Artifacts attached to bundles and images, such as signatures and SBOMs.
+use super::{
+    check, parse, Client, Descriptor, Fetched, Index, Manifest, RegistryError, CONFIG_LIMIT,
+    MANIFEST_LIMIT, OCI_INDEX, OCI_MANIFEST,
+};
+use crate::reference::{AsReference, BundleReference};
+
+/// The `artifactType` of a cosign signature
+pub const COSIGN_SIGNATURE_ARTIFACT_TYPE: &str = "application/vnd.dev.cosign.artifact.sig.v1+json";
+/// The `artifactType` of a Notary Project signature
+pub const NOTARY_SIGNATURE_ARTIFACT_TYPE: &str = "application/vnd.cncf.notary.signature";
+/// The `artifactType` of a Sigstore bundle, which holds a signature or an attestation
+pub const SIGSTORE_BUNDLE_ARTIFACT_TYPE: &str = "application/vnd.dev.sigstore.bundle.v0.3+json";
+/// The `artifactType` of an in-toto attestation
+pub const IN_TOTO_ARTIFACT_TYPE: &str = "application/vnd.in-toto+json";
+pub const SPDX_ARTIFACT_TYPE: &str = "application/spdx+json";
+pub const CYCLONEDX_ARTIFACT_TYPE: &str = "application/vnd.cyclonedx+json";
+/// The annotation of a Sigstore bundle that holds an attestation's predicate type
+pub const SIGSTORE_PREDICATE_TYPE_ANNOTATION: &str = "dev.sigstore.bundle.predicateType";
+/// The media type of the empty config blob of artifact manifests
+const EMPTY_CONFIG: &str = "application/vnd.oci.empty.v1+json";
+
+/// ReferrerKind is what an artifact attached to a manifest is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ReferrerKind {
+    Signature,
+    /// An in-toto attestation, such as SLSA provenance or a vulnerability scan
+    Attestation,
+    /// A software bill of materials
+    Sbom,
+    Other,
+}
+
+impl ReferrerKind {
+    /// The kind of the artifact `descriptor` points at, by its artifact type.
+    pub fn of(descriptor: &Descriptor) -> Self {
+        let artifact_type = descriptor
+            .artifact_type
+            .as_deref()
+            .unwrap_or(&descriptor.media_type);
+        match artifact_type {
+            COSIGN_SIGNATURE_ARTIFACT_TYPE | NOTARY_SIGNATURE_ARTIFACT_TYPE => {
+                ReferrerKind::Signature
+            }
+            t if t.starts_with("application/vnd.dev.sigstore.bundle") => {
+                if descriptor
+                    .annotation(SIGSTORE_PREDICATE_TYPE_ANNOTATION)
+                    .is_some()
+                {
+                    ReferrerKind::Attestation
+                } else {
+                    ReferrerKind::Signature
+                }
+            }
+            IN_TOTO_ARTIFACT_TYPE
+            | "application/vnd.dsse.envelope.v1+json"
+            | "application/vnd.dev.cosign.artifact.att.v1+json" => ReferrerKind::Attestation,
+            SPDX_ARTIFACT_TYPE
+            | CYCLONEDX_ARTIFACT_TYPE
+            | "text/spdx"
+            | "application/vnd.cyclonedx+xml"
+            | "application/vnd.syft+json"
+            | "application/vnd.dev.cosign.artifact.sbom.v1+json" => ReferrerKind::Sbom,
+            _ => ReferrerKind::Other,
+        }
+    }
+}
+
+/// Referrer is an artifact attached to a manifest, found by `Client::referrers`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Referrer {
+    /// The artifact's manifest
+    pub descriptor: Descriptor,
+    pub kind: ReferrerKind,
+    /// Where the artifact's manifest can be pulled from: `registry/repository@digest`
+    pub reference: String,
+}
+
+/// The tags cosign attaches artifacts to a manifest with, on registries without the
+/// referrers API, and what they hold.
+const COSIGN_TAGS: &[(&str, ReferrerKind)] = &[
+    ("sig", ReferrerKind::Signature),
+    ("att", ReferrerKind::Attestation),
+    ("sbom", ReferrerKind::Sbom),
+];
+
+impl Client {
+    /// The artifacts attached to the manifest `reference` points at, such as a
+    /// published bundle's signatures and SBOMs.
+    ///
+    /// They are listed with the referrers API of OCI distribution 1.1. On registries
+    /// without it, the index under the fallback tag `<algorithm>-<hex>` of the digest
+    /// is read instead. The tags cosign attaches artifacts with, such as
+    /// `sha256-<hex>.sig`, are read either way.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self), err))]
+    pub fn referrers<R: AsReference + ?Sized>(
+        &self,
+        reference: &R,
+    ) -> Result<Vec<Referrer>, RegistryError> {
+        let reference = reference.to_reference()?;
+        let digest = match &reference.digest {
+            Some(digest) => digest.clone(),
+            None => self.resolve(&reference, reference.reference())?,
+        };
+        let tag = digest.replace(':', "-");
+        let mut descriptors = match self.referrers_index(&reference, &digest)? {
+            Some(index) => index.manifests,
+            None => match self.tagged(&reference, &tag)? {
+                Some(fetched) => parse::<Index>(&reference, &fetched.body)?.manifests,
+                None => Vec::new(),
+            },
+        };
+        let mut kinds: Vec<_> = descriptors.iter().map(ReferrerKind::of).collect();
+        for (suffix, kind) in COSIGN_TAGS {
+            let fetched = match self.tagged(&reference, &format!("{}.{}", tag, suffix))? {
+                Some(fetched) => fetched,
+                None => continue,
+            };
+            if descriptors.iter().any(|d| d.digest == fetched.digest) {
+                continue;
+            }
+            descriptors.push(Descriptor::of(&fetched.media_type, &fetched.body));
+            kinds.push(*kind);
+        }
+        Ok(descriptors
+            .into_iter()
+            .zip(kinds)
+            .map(|(descriptor, kind)| Referrer {
+                reference: reference.with_digest(&descriptor.digest),
+                descriptor,
+                kind,
+            })
+            .collect())
+    }
+
+    /// The referrers of `digest` from the referrers API, or `None` when the
+    /// registry does not have it.
+    fn referrers_index(
+        &self,
+        repository: &BundleReference,
+        digest: &str,
+    ) -> Result<Option<Index>, RegistryError> {
+        let url = format!("{}/referrers/{}", self.url(repository), digest);
+        let mut response = self.send(repository, || {
+            Ok(self
+                .authorized(repository, self.agent(repository)?.get(&url))?
+                .header("Accept", OCI_INDEX)
+                .call()?)
+        })?;
+        match check(&url, &mut response) {
+            Err(RegistryError::NotFound(_)) => return Ok(None),
+            result => result?,
+        }
+        let body = response
+            .body_mut()
+            .with_config()
+            .limit(MANIFEST_LIMIT)
+            .read_to_vec()?;
+        Ok(Some(parse(repository, &body)?))
+    }
+
+    /// Attach `content` to the manifest `reference` points at, as an artifact of
+    /// `artifact_type` with one layer of `media_type`.
+    ///
+    /// Registries with the referrers API list the artifact among the manifest's
+    /// referrers. On others, it is added to the index under the fallback tag, where
+    /// `Client::referrers` finds it all the same.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, content), err))]
+    pub fn attach<R: AsReference + ?Sized>(
+        &self,
+        reference: &R,
+        artifact_type: &str,
+        media_type: &str,
+        content: &[u8],
+    ) -> Result<Referrer, RegistryError> {
+        let reference = reference.to_reference()?;
+        let subject = self.fetch_manifest(&reference, reference.reference())?;
+        let config = b"{}";
+        self.put_blob(&reference, config)?;
+        self.put_blob(&reference, content)?;
+        let manifest = Manifest {
+            schema_version: 2,
+            media_type: Some(OCI_MANIFEST.to_string()),
+            config: Descriptor::of(EMPTY_CONFIG, config),
+            layers: vec![Descriptor::of(media_type, content)],
+            annotations: None,
+            artifact_type: Some(artifact_type.to_string()),
+            subject: Some(Descriptor::of(&subject.media_type, &subject.body)),
+        };
+        let manifest = serde_json::to_vec(&manifest).expect("manifests serialize");
+        self.put_manifest(&reference, None, OCI_MANIFEST, &manifest)?;
+        let descriptor = Descriptor {
+            artifact_type: Some(artifact_type.to_string()),
+            ..Descriptor::of(OCI_MANIFEST, &manifest)
+        };
+
+        if self.referrers_index(&reference, &subject.digest)?.is_none() {
+            let tag = subject.digest.replace(':', "-");
+            let mut index = match self.tagged(&reference, &tag)? {
+                Some(fetched) => parse::<Index>(&reference, &fetched.body)?,
+                None => Index {
+                    schema_version: 2,
+                    media_type: Some(OCI_INDEX.to_string()),
+                    manifests: Vec::new(),
+                    annotations: None,
+                },
+            };
+            if !index
+                .manifests
+                .iter()
+                .any(|d| d.digest == descriptor.digest)
+            {
+                index.manifests.push(descriptor.clone());
+                let index = serde_json::to_vec(&index).expect("indexes serialize");
+                self.put_manifest(&reference, Some(&tag), OCI_INDEX, &index)?;
+            }
+        }
+        Ok(Referrer {
+            kind: ReferrerKind::of(&descriptor),
+            reference: reference.with_digest(&descriptor.digest),
+            descriptor,
+        })
+    }
+
+    /// The layers of the artifact `referrer` points at, with their content.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self), err))]
+    pub fn artifact_layers(
+        &self,
+        referrer: &Referrer,
+    ) -> Result<Vec<(Descriptor, Vec<u8>)>, RegistryError> {
+        let reference = referrer.reference.to_reference()?;
+        let fetched = self.manifest(&reference, &referrer.descriptor.digest)?;
+        let manifest: Manifest = parse(&reference, &fetched.body)?;
+        manifest
+            .layers
+            .into_iter()
+            .map(|layer| {
+                let content = self.blob(&reference, &layer.digest, CONFIG_LIMIT)?;
+                Ok((layer, content))
+            })
+            .collect()
+    }
+
+    /// The manifest tagged `tag`, if there is one.
+    fn tagged(
+        &self,
+        repository: &BundleReference,
+        tag: &str,
+    ) -> Result<Option<Fetched>, RegistryError> {
+        match self.fetch_manifest(repository, tag) {
+            Ok(fetched) => Ok(Some(fetched)),
+            Err(RegistryError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// The artifacts attached to the manifest `reference` points at, with a default
+/// `Client`.
+pub fn referrers<R: AsReference + ?Sized>(reference: &R) -> Result<Vec<Referrer>, RegistryError> {
+    Client::new().referrers(reference)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::registry::testing::FakeRegistry;
+    use crate::registry::OCI_MANIFEST;
+
+    /// An artifact manifest of `artifact_type` attached to `subject`.
+    fn artifact(artifact_type: &str, subject: &str) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": OCI_MANIFEST,
+            "artifactType": artifact_type,
+            "config": {
+                "mediaType": "application/vnd.oci.empty.v1+json",
+                "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a",
+                "size": 2
+            },
+            "layers": [],
+            "subject": { "mediaType": OCI_MANIFEST, "digest": subject, "size": 2 }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_referrers() {
+        let registry = FakeRegistry::start();
+        let subject = registry.put_manifest("bundles/hello", Some("0.1.0"), OCI_MANIFEST, b"{}");
+        let signature = registry.put_manifest(
+            "bundles/hello",
+            None,
+            OCI_MANIFEST,
+            &artifact(COSIGN_SIGNATURE_ARTIFACT_TYPE, &subject),
+        );
+        let sbom = registry.put_manifest(
+            "bundles/hello",
+            None,
+            OCI_MANIFEST,
+            &artifact(SPDX_ARTIFACT_TYPE, &subject),
+        );
+        let reference = format!("{}/bundles/hello:0.1.0", registry.host);
+        let kinds = |referrers: Vec<Referrer>| {
+            let mut kinds: Vec<_> = referrers
+                .into_iter()
+                .map(|r| (r.descriptor.digest, r.kind))
+                .collect();
+            kinds.sort();
+            kinds
+        };
+
+        registry.state.lock().unwrap().referrers_api = true;
+        let found = referrers(&reference).unwrap();
+        assert!(found
+            .iter()
+            .all(|r| r.reference
+                == format!("{}/bundles/hello@{}", registry.host, r.descriptor.digest)));
+        let mut expected = vec![
+            (signature.clone(), ReferrerKind::Signature),
+            (sbom.clone(), ReferrerKind::Sbom),
+        ];
+        expected.sort();
+        assert_eq!(kinds(found), expected);
+
+        // Without the API, the fallback tag and cosign's tags are read.
+        registry.state.lock().unwrap().referrers_api = false;
+        assert!(referrers(&reference).unwrap().is_empty());
+        let tag = subject.replace(':', "-");
+        let index = serde_json::to_vec(&Index {
+            schema_version: 2,
+            media_type: Some(OCI_INDEX.to_string()),
+            manifests: vec![Descriptor {
+                artifact_type: Some(SPDX_ARTIFACT_TYPE.to_string()),
+                ..Descriptor::of(OCI_MANIFEST, &artifact(SPDX_ARTIFACT_TYPE, &subject))
+            }],
+            annotations: None,
+        })
+        .unwrap();
+        registry.put_manifest("bundles/hello", Some(&tag), OCI_INDEX, &index);
+        let attestation = registry.put_manifest(
+            "bundles/hello",
+            Some(&format!("{}.att", tag)),
+            OCI_MANIFEST,
+            br#"{"schemaVersion":2}"#,
+        );
+        let mut expected = vec![
+            (sbom, ReferrerKind::Sbom),
+            (attestation, ReferrerKind::Attestation),
+        ];
+        expected.sort();
+        let pinned = format!("{}/bundles/hello@{}", registry.host, subject);
+        assert_eq!(kinds(referrers(&pinned).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_attach() {
+        let registry = FakeRegistry::start();
+        let subject = registry.put_manifest("bundles/hello", Some("0.1.0"), OCI_MANIFEST, b"{}");
+        let reference = format!("{}/bundles/hello:0.1.0", registry.host);
+        let client = Client::new();
+        let sbom = br#"{"spdxVersion":"SPDX-2.3"}"#;
+
+        for referrers_api in [true, false] {
+            registry.state.lock().unwrap().referrers_api = referrers_api;
+            let attached = client
+                .attach(&reference, SPDX_ARTIFACT_TYPE, SPDX_ARTIFACT_TYPE, sbom)
+                .unwrap();
+            assert_eq!(attached.kind, ReferrerKind::Sbom);
+            let found = client.referrers(&reference).unwrap();
+            assert_eq!(found.len(), 1);
+            assert_eq!(found[0].descriptor.digest, attached.descriptor.digest);
+            let layers = client.artifact_layers(&found[0]).unwrap();
+            assert_eq!(layers.len(), 1);
+            assert_eq!(layers[0].0.media_type, SPDX_ARTIFACT_TYPE);
+            assert_eq!(layers[0].1, sbom.to_vec());
+        }
+        // The fallback tag was only written without the API, and holds the artifact once.
+        let state = registry.state.lock().unwrap();
+        let key = ("bundles/hello".to_string(), subject.replace(':', "-"));
+        let index: Index = serde_json::from_slice(&state.manifests[&key].1).unwrap();
+        assert_eq!(index.manifests.len(), 1);
+    }
+
+    #[test]
+    fn test_referrer_kind() {
+        let of = |artifact_type: &str| {
+            ReferrerKind::of(&Descriptor {
+                artifact_type: Some(artifact_type.to_string()),
+                ..Descriptor::of(OCI_MANIFEST, b"{}")
+            })
+        };
+        assert_eq!(of(NOTARY_SIGNATURE_ARTIFACT_TYPE), ReferrerKind::Signature);
+        assert_eq!(of(IN_TOTO_ARTIFACT_TYPE), ReferrerKind::Attestation);
+        assert_eq!(of(CYCLONEDX_ARTIFACT_TYPE), ReferrerKind::Sbom);
+        assert_eq!(of(SIGSTORE_BUNDLE_ARTIFACT_TYPE), ReferrerKind::Signature);
+        let attestation = Descriptor {
+            artifact_type: Some(SIGSTORE_BUNDLE_ARTIFACT_TYPE.to_string()),
+            ..Descriptor::of(OCI_MANIFEST, b"{}")
+        }
+        .annotate(
+            SIGSTORE_PREDICATE_TYPE_ANNOTATION,
+            "https://slsa.dev/provenance/v1",
+        );
+        assert_eq!(ReferrerKind::of(&attestation), ReferrerKind::Attestation);
+        assert_eq!(of("application/octet-stream"), ReferrerKind::Other);
+    }
+}
@@ -0,0 +1,289 @@
+//! Software bills of materials attached to bundles.
+use super::{Client, Referrer, ReferrerKind, RegistryError};
+use super::{CYCLONEDX_ARTIFACT_TYPE, SPDX_ARTIFACT_TYPE};
+use crate::error_code::{Coded, ErrorCode};
+use crate::reference::AsReference;
+use serde_json::Value;
+use std::fmt;
+
+/// SbomFormat is the format of an SBOM document, in its JSON form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbomFormat {
+    /// SPDX 2
+    Spdx,
+    CycloneDx,
+}
+
+impl SbomFormat {
+    pub fn media_type(self) -> &'static str {
+        match self {
+            SbomFormat::Spdx => SPDX_ARTIFACT_TYPE,
+            SbomFormat::CycloneDx => CYCLONEDX_ARTIFACT_TYPE,
+        }
+    }
+}
+
+/// Sbom is an SBOM document, such as one a scanner or a build tool wrote.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sbom {
+    pub format: SbomFormat,
+    pub document: Value,
+}
+
+/// Package is a piece of software an SBOM lists.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Package {
+    pub name: String,
+    pub version: Option<String>,
+    /// The package URL, such as `pkg:deb/debian/openssl@3.0.11`
+    pub purl: Option<String>,
+    /// The SPDX license IDs or expressions that apply
+    pub licenses: Vec<String>,
+}
+
+impl Sbom {
+    /// Parse an SPDX or CycloneDX JSON document, telling the format by its content.
+    pub fn parse(content: &[u8]) -> Result<Self, SbomError> {
+        let document: Value =
+            serde_json::from_slice(content).map_err(|e| SbomError::Invalid(e.to_string()))?;
+        let format = if document.get("spdxVersion").is_some() {
+            SbomFormat::Spdx
+        } else if document.get("bomFormat").and_then(Value::as_str) == Some("CycloneDX") {
+            SbomFormat::CycloneDx
+        } else {
+            return Err(SbomError::UnknownFormat);
+        };
+        Ok(Sbom { format, document })
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        serde_json::to_vec(&self.document).expect("SBOMs serialize")
+    }
+
+    /// Every package the SBOM lists, with the components nested in CycloneDX ones.
+    pub fn packages(&self) -> Vec<Package> {
+        let mut packages = Vec::new();
+        match self.format {
+            SbomFormat::Spdx => {
+                for package in array(&self.document, "packages") {
+                    let purl = array(package, "externalRefs")
+                        .iter()
+                        .find(|r| r.get("referenceType").and_then(Value::as_str) == Some("purl"))
+                        .and_then(|r| string(r, "referenceLocator"));
+                    let licenses = ["licenseConcluded", "licenseDeclared"]
+                        .iter()
+                        .filter_map(|field| string(package, field))
+                        .find(|l| l != "NOASSERTION" && l != "NONE");
+                    packages.push(Package {
+                        name: string(package, "name").unwrap_or_default(),
+                        version: string(package, "versionInfo"),
+                        purl,
+                        licenses: licenses.into_iter().collect(),
+                    });
+                }
+            }
+            SbomFormat::CycloneDx => cyclonedx_components(&self.document, &mut packages),
+        }
+        packages
+    }
+}
+
+fn cyclonedx_components(parent: &Value, packages: &mut Vec<Package>) {
+    for component in array(parent, "components") {
+        let licenses = array(component, "licenses")
+            .iter()
+            .filter_map(|l| {
+                string(l, "expression")
+                    .or_else(|| l.get("license").and_then(|l| string(l, "id")))
+                    .or_else(|| l.get("license").and_then(|l| string(l, "name")))
+            })
+            .collect();
+        packages.push(Package {
+            name: string(component, "name").unwrap_or_default(),
+            version: string(component, "version"),
+            purl: string(component, "purl"),
+            licenses,
+        });
+        cyclonedx_components(component, packages);
+    }
+}
+
+fn array<'a>(value: &'a Value, field: &str) -> &'a [Value] {
+    value
+        .get(field)
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+fn string(value: &Value, field: &str) -> Option<String> {
+    value.get(field).and_then(Value::as_str).map(str::to_string)
+}
+
+impl Client {
+    /// Attach `sbom` to the manifest `reference` points at, such as a published
+    /// bundle, as a referrer.
+    pub fn attach_sbom<R: AsReference + ?Sized>(
+        &self,
+        reference: &R,
+        sbom: &Sbom,
+    ) -> Result<Referrer, RegistryError> {
+        let media_type = sbom.format.media_type();
+        self.attach(reference, media_type, media_type, &sbom.to_vec())
+    }
+
+    /// The SBOMs attached to the manifest `reference` points at, so they can be
+    /// scanned before the bundle is installed. SBOMs in formats other than SPDX and
+    /// CycloneDX JSON are passed over.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self), err))]
+    pub fn sboms<R: AsReference + ?Sized>(
+        &self,
+        reference: &R,
+    ) -> Result<Vec<Sbom>, RegistryError> {
+        let mut sboms = Vec::new();
+        for referrer in self.referrers(reference)? {
+            if referrer.kind != ReferrerKind::Sbom {
+                continue;
+            }
+            for (_, content) in self.artifact_layers(&referrer)? {
+                match Sbom::parse(&content) {
+                    Ok(sbom) => sboms.push(sbom),
+                    #[cfg(feature = "tracing")]
+                    Err(e) => {
+                        tracing::debug!(referrer = %referrer.reference, "skipping SBOM: {}", e)
+                    }
+                    #[cfg(not(feature = "tracing"))]
+                    Err(_) => {}
+                }
+            }
+        }
+        Ok(sboms)
+    }
+}
+
+/// The SBOMs attached to the manifest `reference` points at, with a default `Client`.
+pub fn sboms<R: AsReference + ?Sized>(reference: &R) -> Result<Vec<Sbom>, RegistryError> {
+    Client::new().sboms(reference)
+}
+
+/// SbomError describes a document that is not an SBOM this crate reads.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SbomError {
+    /// The document is not JSON
+    Invalid(String),
+    /// The document is neither SPDX nor CycloneDX
+    UnknownFormat,
+}
+
+impl fmt::Display for SbomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SbomError::Invalid(e) => write!(f, "invalid SBOM: {}", e),
+            SbomError::UnknownFormat => write!(f, "the SBOM is neither SPDX nor CycloneDX JSON"),
+        }
+    }
+}
+
+impl std::error::Error for SbomError {}
+
+impl Coded for SbomError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            SbomError::Invalid(_) => ErrorCode::SbomInvalid,
+            SbomError::UnknownFormat => ErrorCode::SbomUnknownFormat,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::registry::testing::FakeRegistry;
+    use crate::registry::OCI_MANIFEST;
+
+    fn spdx() -> Sbom {
+        Sbom::parse(
+            br#"{
+                "spdxVersion": "SPDX-2.3",
+                "name": "hello",
+                "packages": [
+                    {
+                        "name": "openssl",
+                        "versionInfo": "3.0.11",
+                        "licenseConcluded": "NOASSERTION",
+                        "licenseDeclared": "Apache-2.0",
+                        "externalRefs": [{
+                            "referenceCategory": "PACKAGE-MANAGER",
+                            "referenceType": "purl",
+                            "referenceLocator": "pkg:deb/debian/openssl@3.0.11"
+                        }]
+                    },
+                    { "name": "hello-binary" }
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_packages() {
+        let spdx = spdx();
+        assert_eq!(spdx.format, SbomFormat::Spdx);
+        assert_eq!(
+            spdx.packages(),
+            vec![
+                Package {
+                    name: "openssl".to_string(),
+                    version: Some("3.0.11".to_string()),
+                    purl: Some("pkg:deb/debian/openssl@3.0.11".to_string()),
+                    licenses: vec!["Apache-2.0".to_string()],
+                },
+                Package {
+                    name: "hello-binary".to_string(),
+                    ..Package::default()
+                },
+            ]
+        );
+
+        let cyclonedx = Sbom::parse(
+            br#"{
+                "bomFormat": "CycloneDX",
+                "specVersion": "1.5",
+                "components": [{
+                    "name": "serde",
+                    "version": "1.0.0",
+                    "purl": "pkg:cargo/serde@1.0.0",
+                    "licenses": [{ "expression": "MIT OR Apache-2.0" }],
+                    "components": [{ "name": "serde_derive", "licenses": [{ "license": { "id": "MIT" } }] }]
+                }]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(cyclonedx.format, SbomFormat::CycloneDx);
+        let packages = cyclonedx.packages();
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0].licenses, vec!["MIT OR Apache-2.0".to_string()]);
+        assert_eq!(packages[1].name, "serde_derive");
+        assert_eq!(packages[1].licenses, vec!["MIT".to_string()]);
+
+        assert_eq!(Sbom::parse(b"{}"), Err(SbomError::UnknownFormat));
+        assert!(matches!(Sbom::parse(b"<bom/>"), Err(SbomError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_attach_sbom() {
+        let registry = FakeRegistry::start();
+        registry.put_manifest("bundles/hello", Some("0.1.0"), OCI_MANIFEST, b"{}");
+        let reference = format!("{}/bundles/hello:0.1.0", registry.host);
+        let client = Client::new();
+        assert!(client.sboms(&reference).unwrap().is_empty());
+
+        let referrer = client.attach_sbom(&reference, &spdx()).unwrap();
+        assert_eq!(referrer.kind, ReferrerKind::Sbom);
+        assert_eq!(
+            referrer.descriptor.artifact_type.as_deref(),
+            Some(SPDX_ARTIFACT_TYPE)
+        );
+        assert_eq!(sboms(&reference).unwrap(), vec![spdx()]);
+    }
+}
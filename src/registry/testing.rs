@@ -0,0 +1,497 @@
+//! An in-memory OCI distribution registry that tests run the client against.
+use super::sha256_digest;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default)]
+pub(crate) struct State {
+    /// Manifests keyed by repository and tag or digest, with their media type
+    pub manifests: BTreeMap<(String, String), (String, Vec<u8>)>,
+    /// Blobs keyed by repository and digest
+    pub blobs: BTreeMap<(String, String), Vec<u8>>,
+    /// The repository of each upload in progress
+    uploads: BTreeMap<String, String>,
+    /// Every request served, as `METHOD /path`
+    pub requests: Vec<String>,
+    /// The `Authorization` header every request must carry, if any
+    pub authorization: Option<String>,
+    /// Ask for bearer tokens issued at `/token` instead, if set
+    pub token_auth: Option<TokenAuth>,
+    /// Serve the referrers API, as OCI distribution 1.1 registries do
+    pub referrers_api: bool,
+    /// The `Range` header of every request for a blob that had one
+    pub ranges: Vec<String>,
+    /// Serve whole blobs whatever range is asked for, as some registries do
+    pub ignore_ranges: bool,
+}
+
+/// TokenAuth is a token service. Credentials get tokens for every scope asked for,
+/// and anonymous requests get tokens to pull.
+#[derive(Debug, Default)]
+pub(crate) struct TokenAuth {
+    /// The `Authorization` header that gets tokens
+    pub credential: Option<String>,
+    /// The refresh token that gets tokens
+    pub refresh_token: Option<String>,
+    /// The scopes of each token that is still valid
+    pub tokens: BTreeMap<String, Vec<String>>,
+    /// The scopes of every token request, and whether it was anonymous
+    pub requests: Vec<(Vec<String>, bool)>,
+}
+
+pub(crate) struct FakeRegistry {
+    /// The `host:port` the registry listens on
+    pub host: String,
+    pub state: Arc<Mutex<State>>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: BTreeMap<String, String>,
+    /// Every query parameter, in order
+    pairs: Vec<(String, String)>,
+    headers: BTreeMap<String, String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn new(status: u16) -> Self {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn body(mut self, body: Vec<u8>) -> Self {
+        self.body = body;
+        self
+    }
+}
+
+impl FakeRegistry {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+        let state = Arc::new(Mutex::new(State::default()));
+        let shared = state.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = shared.clone();
+                std::thread::spawn(move || {
+                    let _ = serve(stream, &state);
+                });
+            }
+        });
+        FakeRegistry { host, state }
+    }
+
+    /// Store a manifest by digest, and under `tag` if one is given.
+    pub fn put_manifest(
+        &self,
+        repository: &str,
+        tag: Option<&str>,
+        media_type: &str,
+        body: &[u8],
+    ) -> String {
+        let digest = sha256_digest(body);
+        let mut state = self.state.lock().unwrap();
+        for reference in tag.into_iter().chain(Some(digest.as_str())) {
+            state.manifests.insert(
+                (repository.to_string(), reference.to_string()),
+                (media_type.to_string(), body.to_vec()),
+            );
+        }
+        digest
+    }
+
+    pub fn put_blob(&self, repository: &str, body: &[u8]) -> String {
+        let digest = sha256_digest(body);
+        self.state
+            .lock()
+            .unwrap()
+            .blobs
+            .insert((repository.to_string(), digest.clone()), body.to_vec());
+        digest
+    }
+}
+
+fn serve(stream: TcpStream, state: &Mutex<State>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+    let mut headers = BTreeMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    let length: usize = headers
+        .get("content-length")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let pairs: Vec<_> = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (decode(k), decode(v)))
+        .collect();
+    let request = Request {
+        method,
+        path: decode(path),
+        query: pairs.iter().cloned().collect(),
+        pairs,
+        headers,
+        body,
+    };
+    let response = handle(&request, &mut state.lock().unwrap());
+
+    let mut stream = stream;
+    write!(stream, "HTTP/1.1 {} Fake\r\n", response.status)?;
+    for (name, value) in &response.headers {
+        write!(stream, "{}: {}\r\n", name, value)?;
+    }
+    write!(
+        stream,
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        response.body.len()
+    )?;
+    if request.method != "HEAD" {
+        stream.write_all(&response.body)?;
+    }
+    stream.flush()
+}
+
+fn decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(b) => out.push(b),
+                    Err(_) => out.extend_from_slice(&bytes[i..i + 3]),
+                }
+                i += 3;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn handle(request: &Request, state: &mut State) -> Response {
+    state
+        .requests
+        .push(format!("{} {}", request.method, request.path));
+    if request.path == "/token" {
+        return match &mut state.token_auth {
+            Some(auth) => issue_token(request, auth),
+            None => Response::new(404),
+        };
+    }
+    let path = match request.path.strip_prefix("/v2/") {
+        Some("") => return Response::new(200).body(b"{}".to_vec()),
+        Some(path) => path,
+        None => return Response::new(404),
+    };
+    if let Some(expected) = &state.authorization {
+        if request.headers.get("authorization") != Some(expected) {
+            return Response::new(401)
+                .header("WWW-Authenticate", "Basic realm=\"fake\"")
+                .body(error("UNAUTHORIZED"));
+        }
+    }
+    let method = request.method.as_str();
+    if let Some(auth) = &state.token_auth {
+        let repository = ["/manifests/", "/blobs/", "/tags/list", "/referrers/"]
+            .iter()
+            .find_map(|s| path.split_once(s).map(|(r, _)| r))
+            .unwrap_or(path);
+        let action = if method == "GET" || method == "HEAD" {
+            "pull"
+        } else {
+            "push"
+        };
+        let granted = request
+            .headers
+            .get("authorization")
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .and_then(|token| auth.tokens.get(token))
+            .is_some_and(|scopes| {
+                scopes.iter().any(|scope| {
+                    scope
+                        .strip_prefix(&format!("repository:{}:", repository))
+                        .is_some_and(|actions| actions.split(',').any(|a| a == action))
+                })
+            });
+        if !granted {
+            let host = request.headers.get("host").cloned().unwrap_or_default();
+            let challenge = format!(
+                "Bearer realm=\"http://{}/token\",service=\"fake\",scope=\"repository:{}:{}\"",
+                host, repository, action
+            );
+            return Response::new(401)
+                .header("WWW-Authenticate", &challenge)
+                .body(error("UNAUTHORIZED"));
+        }
+    }
+
+    if let Some((repository, subject)) = path.split_once("/referrers/") {
+        if !state.referrers_api {
+            return Response::new(404);
+        }
+        let manifests: Vec<_> = state
+            .manifests
+            .iter()
+            .filter(|((r, reference), _)| r == repository && reference.starts_with("sha256:"))
+            .filter_map(|((_, digest), (media_type, body))| {
+                let manifest: serde_json::Value = serde_json::from_slice(body).ok()?;
+                if manifest.pointer("/subject/digest")?.as_str()? != subject {
+                    return None;
+                }
+                let artifact_type = manifest
+                    .get("artifactType")
+                    .or_else(|| manifest.pointer("/config/mediaType"))?;
+                Some(serde_json::json!({
+                    "mediaType": media_type,
+                    "digest": digest,
+                    "size": body.len(),
+                    "artifactType": artifact_type,
+                    "annotations": manifest.get("annotations"),
+                }))
+            })
+            .collect();
+        let index = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": manifests,
+        });
+        return Response::new(200)
+            .header("Content-Type", "application/vnd.oci.image.index.v1+json")
+            .body(index.to_string().into_bytes());
+    }
+
+    if let Some((repository, reference)) = path.split_once("/manifests/") {
+        let key = (repository.to_string(), reference.to_string());
+        return match method {
+            "GET" | "HEAD" => match state.manifests.get(&key) {
+                Some((media_type, body)) => Response::new(200)
+                    .header("Content-Type", media_type)
+                    .header("Docker-Content-Digest", &sha256_digest(body))
+                    .body(body.clone()),
+                None => Response::new(404).body(error("MANIFEST_UNKNOWN")),
+            },
+            "PUT" => {
+                let digest = sha256_digest(&request.body);
+                let media_type = request
+                    .headers
+                    .get("content-type")
+                    .cloned()
+                    .unwrap_or_default();
+                for reference in [reference, digest.as_str()] {
+                    state.manifests.insert(
+                        (repository.to_string(), reference.to_string()),
+                        (media_type.clone(), request.body.clone()),
+                    );
+                }
+                Response::new(201).header("Docker-Content-Digest", &digest)
+            }
+            _ => Response::new(405),
+        };
+    }
+
+    if let Some((repository, id)) = path.split_once("/blobs/uploads/") {
+        return match (method, id) {
+            ("POST", "") => {
+                if let (Some(digest), Some(from)) =
+                    (request.query.get("mount"), request.query.get("from"))
+                {
+                    if let Some(blob) = state.blobs.get(&(from.clone(), digest.clone())).cloned() {
+                        state
+                            .blobs
+                            .insert((repository.to_string(), digest.clone()), blob);
+                        return Response::new(201).header("Docker-Content-Digest", digest);
+                    }
+                }
+                let id = format!("{}", state.uploads.len() + 1);
+                state.uploads.insert(id.clone(), repository.to_string());
+                Response::new(202).header(
+                    "Location",
+                    &format!("/v2/{}/blobs/uploads/{}", repository, id),
+                )
+            }
+            ("PUT", id) => {
+                let digest = request.query.get("digest").cloned().unwrap_or_default();
+                if state.uploads.remove(id).as_deref() != Some(repository) {
+                    return Response::new(404).body(error("BLOB_UPLOAD_UNKNOWN"));
+                }
+                if sha256_digest(&request.body) != digest {
+                    return Response::new(400).body(error("DIGEST_INVALID"));
+                }
+                state.blobs.insert(
+                    (repository.to_string(), digest.clone()),
+                    request.body.clone(),
+                );
+                Response::new(201).header("Docker-Content-Digest", &digest)
+            }
+            _ => Response::new(405),
+        };
+    }
+
+    if let Some((repository, digest)) = path.split_once("/blobs/") {
+        return match state
+            .blobs
+            .get(&(repository.to_string(), digest.to_string()))
+        {
+            Some(blob) => {
+                let blob = blob.clone();
+                let range = request.headers.get("range").cloned();
+                let start = range
+                    .as_deref()
+                    .and_then(|r| r.strip_prefix("bytes="))
+                    .and_then(|r| r.strip_suffix('-'))
+                    .and_then(|r| r.parse::<usize>().ok())
+                    .filter(|start| *start < blob.len() && !state.ignore_ranges);
+                state.ranges.extend(range);
+                let response = Response::new(200)
+                    .header("Content-Type", "application/octet-stream")
+                    .header("Docker-Content-Digest", digest);
+                match start {
+                    Some(start) => {
+                        let range = format!("bytes {}-{}/{}", start, blob.len() - 1, blob.len());
+                        let response = Response {
+                            status: 206,
+                            ..response
+                        };
+                        response
+                            .header("Content-Range", &range)
+                            .body(blob[start..].to_vec())
+                    }
+                    None => response.body(blob),
+                }
+            }
+            None => Response::new(404).body(error("BLOB_UNKNOWN")),
+        };
+    }
+
+    // Tags are listed two at a time, to exercise pagination.
+    if let Some(repository) = path.strip_suffix("/tags/list") {
+        let last = request.query.get("last");
+        let tags: Vec<&str> = state
+            .manifests
+            .keys()
+            .filter(|(r, t)| r == repository && !t.starts_with("sha256:"))
+            .map(|(_, t)| t.as_str())
+            .filter(|t| last.is_none_or(|last| *t > last.as_str()))
+            .collect();
+        let page = &tags[..tags.len().min(2)];
+        let body = serde_json::json!({ "name": repository, "tags": page });
+        let mut response = Response::new(200).header("Content-Type", "application/json");
+        if tags.len() > page.len() {
+            let next = format!(
+                "</v2/{}/tags/list?n=2&last={}>; rel=\"next\"",
+                repository,
+                page[page.len() - 1]
+            );
+            response = response.header("Link", &next);
+        }
+        return response.body(body.to_string().into_bytes());
+    }
+
+    Response::new(404)
+}
+
+fn issue_token(request: &Request, auth: &mut TokenAuth) -> Response {
+    let (scopes, authenticated) = match request.method.as_str() {
+        "POST" => {
+            let form: Vec<(String, String)> = String::from_utf8_lossy(&request.body)
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(k, v)| (decode(k), decode(&v.replace('+', " "))))
+                .collect();
+            let field = |name: &str| form.iter().find(|(k, _)| k == name).map(|(_, v)| v);
+            let scopes: Vec<String> = field("scope")
+                .map(|s| s.split(' ').map(str::to_string).collect())
+                .unwrap_or_default();
+            let valid = field("grant_type").map(String::as_str) == Some("refresh_token")
+                && field("refresh_token").is_some()
+                && field("refresh_token") == auth.refresh_token.as_ref();
+            if !valid {
+                return Response::new(401).body(error("UNAUTHORIZED"));
+            }
+            (scopes, true)
+        }
+        _ => {
+            let scopes = request
+                .pairs
+                .iter()
+                .filter(|(k, _)| k == "scope")
+                .map(|(_, v)| v.clone())
+                .collect();
+            match request.headers.get("authorization") {
+                Some(header) if Some(header) == auth.credential.as_ref() => (scopes, true),
+                Some(_) => return Response::new(401).body(error("UNAUTHORIZED")),
+                None => (scopes, false),
+            }
+        }
+    };
+    auth.requests.push((scopes.clone(), !authenticated));
+    let granted = if authenticated {
+        scopes
+    } else {
+        scopes
+            .iter()
+            .filter_map(|scope| {
+                let (resource, actions) = scope.rsplit_once(':')?;
+                actions
+                    .split(',')
+                    .any(|a| a == "pull")
+                    .then(|| format!("{}:pull", resource))
+            })
+            .collect()
+    };
+    let token = format!("token-{}", auth.requests.len());
+    auth.tokens.insert(token.clone(), granted);
+    let body = serde_json::json!({ "token": token, "expires_in": 300 });
+    Response::new(200)
+        .header("Content-Type", "application/json")
+        .body(body.to_string().into_bytes())
+}
+
+fn error(code: &str) -> Vec<u8> {
+    serde_json::json!({ "errors": [{ "code": code, "message": code.to_lowercase() }] })
+        .to_string()
+        .into_bytes()
+}
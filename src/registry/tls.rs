@@ -0,0 +1,69 @@
+//! TLS settings for registries with their own PKI.
+use super::RegistryError;
+use ureq::tls::{Certificate, ClientCert, PemItem, PrivateKey, RootCerts, TlsConfig};
+
+/// How the client connects to one registry with TLS.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RegistryTls {
+    /// The CA certificates to trust instead of the public roots
+    pub roots: Vec<Certificate<'static>>,
+    pub client_certificate: Option<ClientCert>,
+}
+
+impl RegistryTls {
+    pub fn config(&self) -> TlsConfig {
+        let mut config = TlsConfig::builder().client_cert(self.client_certificate.clone());
+        if !self.roots.is_empty() {
+            config = config.root_certs(RootCerts::new_with_certs(&self.roots));
+        }
+        config.build()
+    }
+}
+
+/// The certificates of a PEM file. There must be at least one.
+pub(crate) fn certificates(pem: &[u8]) -> Result<Vec<Certificate<'static>>, RegistryError> {
+    let mut certificates = Vec::new();
+    for item in ureq::tls::parse_pem(pem) {
+        if let PemItem::Certificate(certificate) = item.map_err(invalid)? {
+            certificates.push(certificate);
+        }
+    }
+    if certificates.is_empty() {
+        return Err(RegistryError::InvalidCertificate(
+            "no certificate found".to_string(),
+        ));
+    }
+    Ok(certificates)
+}
+
+/// The private key of a PEM file.
+pub(crate) fn private_key(pem: &[u8]) -> Result<PrivateKey<'static>, RegistryError> {
+    for item in ureq::tls::parse_pem(pem) {
+        if let PemItem::PrivateKey(key) = item.map_err(invalid)? {
+            return Ok(key);
+        }
+    }
+    Err(RegistryError::InvalidCertificate(
+        "no private key found".to_string(),
+    ))
+}
+
+fn invalid(error: ureq::Error) -> RegistryError {
+    RegistryError::InvalidCertificate(error.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_certificates() {
+        let pem = std::fs::read("testdata/registry-ca.pem").unwrap();
+        assert_eq!(certificates(&pem).unwrap().len(), 1);
+        assert!(private_key(&pem).is_err());
+        match certificates(b"not a certificate") {
+            Err(RegistryError::InvalidCertificate(_)) => {}
+            other => panic!("expected an invalid certificate, got {:?}", other),
+        }
+    }
+}
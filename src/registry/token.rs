@@ -0,0 +1,176 @@
+//! The token authentication of the OCI distribution spec, which Docker Hub, GHCR, ACR
+//! and most other registries use: a request answered with a `Bearer` challenge is
+//! sent again with a token the challenge's realm issues for the repositories and
+//! actions it needs.
+use super::{RegistryCredential, RegistryError};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// How long a token lives when the token service does not say, per the spec.
+const DEFAULT_EXPIRY: Duration = Duration::from_secs(60);
+/// Tokens this close to expiring are refreshed before they are used.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(10);
+/// The `client_id` sent when an identity token is exchanged.
+const CLIENT_ID: &str = "libcnab";
+
+/// Challenge is the `WWW-Authenticate` header of a `401 Unauthorized` response.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Challenge {
+    /// The scheme, such as `Bearer` or `Basic`, in lowercase
+    pub scheme: String,
+    /// The parameters, such as `realm` and `service`
+    pub params: BTreeMap<String, String>,
+}
+
+impl Challenge {
+    /// Parse a header such as
+    /// `Bearer realm="https://auth.docker.io/token",service="registry.docker.io"`.
+    pub fn parse(header: &str) -> Option<Self> {
+        let header = header.trim();
+        let (scheme, mut rest) = header.split_once(' ').unwrap_or((header, ""));
+        if scheme.is_empty() {
+            return None;
+        }
+        let mut params = BTreeMap::new();
+        loop {
+            rest = rest.trim_start_matches([' ', ',']);
+            let (name, after) = match rest.split_once('=') {
+                Some(pair) => pair,
+                None => break,
+            };
+            let (value, after) = match after.strip_prefix('"') {
+                Some(quoted) => {
+                    let end = quoted.find('"')?;
+                    (&quoted[..end], &quoted[end + 1..])
+                }
+                None => after.split_once(',').unwrap_or((after, "")),
+            };
+            params.insert(name.trim().to_lowercase(), value.to_string());
+            rest = after;
+        }
+        Some(Challenge {
+            scheme: scheme.to_lowercase(),
+            params,
+        })
+    }
+
+    pub fn is_bearer(&self) -> bool {
+        self.scheme == "bearer" && self.params.contains_key("realm")
+    }
+}
+
+/// Token is a bearer token a token service issued.
+#[derive(Debug, Clone)]
+pub(crate) struct Token {
+    pub value: String,
+    expires: Instant,
+}
+
+impl Token {
+    pub fn is_fresh(&self) -> bool {
+        Instant::now() + EXPIRY_MARGIN < self.expires
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+    expires_in: Option<u64>,
+}
+
+/// Ask the realm of `challenge` for a token for `scopes`, with `credential` or
+/// anonymously.
+///
+/// A username and password is sent to the realm with basic authentication, and an
+/// identity token is exchanged with the OAuth2 `refresh_token` grant. A realm that
+/// rejects the credential is asked for an anonymous token, which is enough to pull
+/// public repositories.
+pub(crate) fn fetch(
+    agent: &ureq::Agent,
+    challenge: &Challenge,
+    scopes: &[String],
+    credential: Option<&RegistryCredential>,
+) -> Result<Token, RegistryError> {
+    let realm = &challenge.params["realm"];
+    let service = challenge.params.get("service");
+    let mut response = match credential {
+        Some(RegistryCredential::IdentityToken(token)) => {
+            let scope = scopes.join(" ");
+            let mut form = vec![
+                ("grant_type", "refresh_token"),
+                ("refresh_token", token.as_str()),
+                ("client_id", CLIENT_ID),
+                ("scope", scope.as_str()),
+            ];
+            if let Some(service) = service {
+                form.push(("service", service.as_str()));
+            }
+            agent.post(realm).send_form(form)?
+        }
+        _ => {
+            let mut request = agent.get(realm);
+            if let Some(service) = service {
+                request = request.query("service", service);
+            }
+            for scope in scopes {
+                request = request.query("scope", scope);
+            }
+            if let Some(header) = credential.and_then(RegistryCredential::header) {
+                request = request.header("Authorization", header);
+            }
+            request.call()?
+        }
+    };
+    let status = response.status().as_u16();
+    if credential.is_some() && (status == 401 || status == 403) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(realm = %realm, "credential rejected, asking for an anonymous token");
+        return fetch(agent, challenge, scopes, None);
+    }
+    super::check(realm, &mut response)?;
+    let body: TokenResponse = response
+        .body_mut()
+        .read_json()
+        .map_err(|e| RegistryError::InvalidToken(format!("{}: {}", realm, e)))?;
+    let value = body
+        .token
+        .or(body.access_token)
+        .filter(|t| !t.is_empty())
+        .ok_or_else(|| RegistryError::InvalidToken(format!("{}: no token returned", realm)))?;
+    let lifetime = body
+        .expires_in
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_EXPIRY);
+    Ok(Token {
+        value,
+        expires: Instant::now() + lifetime,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_challenge() {
+        let challenge = Challenge::parse(
+            r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/hello:pull repository:other:pull""#,
+        )
+        .unwrap();
+        assert!(challenge.is_bearer());
+        assert_eq!(challenge.params["realm"], "https://auth.docker.io/token");
+        assert_eq!(challenge.params["service"], "registry.docker.io");
+        assert_eq!(
+            challenge.params["scope"],
+            "repository:library/hello:pull repository:other:pull"
+        );
+
+        let basic = Challenge::parse("Basic realm=fake").unwrap();
+        assert_eq!(basic.scheme, "basic");
+        assert_eq!(basic.params["realm"], "fake");
+        assert!(!basic.is_bearer());
+        assert!(!Challenge::parse("Bearer").unwrap().is_bearer());
+        assert_eq!(Challenge::parse(""), None);
+    }
+}
@@ -0,0 +1,406 @@
+use crate::cnab::{is_oci_image, Bundle};
+use crate::reference::{BundleReference, InvalidReference};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::iter::FromIterator;
+use std::path::Path;
+
+/// RelocationMap records where the images of a bundle were copied to, in the format
+/// of `relocation-mapping.json`.
+///
+/// A relocated bundle keeps its original image references. Each one is mapped to the
+/// reference it can be pulled from now, so the runtime pulls the relocated invocation
+/// image and mounts the map at `/cnab/app/relocation-mapping.json` for the image to
+/// find the rest.
+///
+/// ```
+/// use libcnab::RelocationMap;
+///
+/// let map: RelocationMap = serde_json::from_str(
+///     r#"{"technosophos/helloworld:0.1.0": "example.com/hello@sha256:0123"}"#,
+/// )
+/// .unwrap();
+/// assert_eq!(map.relocate("technosophos/helloworld:0.1.0"), "example.com/hello@sha256:0123");
+/// assert_eq!(map.relocate("nginx:latest"), "nginx:latest");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(transparent)]
+pub struct RelocationMap(BTreeMap<String, String>);
+
+impl RelocationMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a `relocation-mapping.json` file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn insert(&mut self, original: &str, relocated: &str) {
+        self.0.insert(original.to_string(), relocated.to_string());
+    }
+
+    /// The reference `original` was relocated to, if it was.
+    pub fn get(&self, original: &str) -> Option<&str> {
+        self.0.get(original).map(String::as_str)
+    }
+
+    /// The reference to pull `image` from: its relocated reference, or `image` itself.
+    pub fn relocate<'a>(&'a self, image: &'a str) -> &'a str {
+        self.get(image).unwrap_or(image)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The original and relocated references, ordered by original reference.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+impl FromIterator<(String, String)> for RelocationMap {
+    fn from_iter<T: IntoIterator<Item = (String, String)>>(iter: T) -> Self {
+        RelocationMap(iter.into_iter().collect())
+    }
+}
+
+/// RelocationRules says where `Bundle::relocate` moves images to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RelocationRules {
+    /// Move every image under a registry, and optionally a namespace in it, keeping
+    /// its repository: under `mirror.example.com/team`, `technosophos/helloworld:0.1.0`
+    /// becomes `mirror.example.com/team/technosophos/helloworld:0.1.0`.
+    Prefix(String),
+    /// Replace the start of an image's `registry/repository` with the first rule that
+    /// matches it, such as `("docker.io/technosophos", "ghcr.io/acme")`. A rule
+    /// matches whole path components. Images that no rule matches are not moved.
+    Rewrite(Vec<(String, String)>),
+}
+
+impl RelocationRules {
+    /// The new `registry/repository` for an image, if it moves.
+    fn apply(&self, reference: &BundleReference) -> Option<String> {
+        match self {
+            RelocationRules::Prefix(prefix) => Some(format!(
+                "{}/{}",
+                prefix.trim_end_matches('/'),
+                reference.repository
+            )),
+            RelocationRules::Rewrite(rules) => rewrite(rules, &reference.name()),
+        }
+    }
+}
+
+/// `name` with its start replaced by the first of `rules` that matches whole path
+/// components of it, if one does.
+fn rewrite(rules: &[(String, String)], name: &str) -> Option<String> {
+    rules.iter().find_map(|(from, to)| {
+        let from = from.trim_end_matches('/');
+        match name.strip_prefix(from) {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+                Some(format!("{}{}", to.trim_end_matches('/'), rest))
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Mirrors says where to pull images and bundles from instead of their registries,
+/// for environments that block access to them, such as a mirror of Docker Hub.
+///
+/// Unlike `RelocationRules`, mirrors leave bundles as they are: the registry
+/// `Client` and the `Engine` given them pull from the mirrors, and the relocation
+/// map an invocation image is given sends the bundle's images to their mirrors.
+///
+/// ```
+/// use libcnab::{BundleReference, Mirrors};
+///
+/// let mirrors = Mirrors::parse(
+///     "# Docker Hub is blocked
+///      docker.io/* -> mirror.example.com/dockerhub/*",
+/// )
+/// .unwrap();
+/// let image: BundleReference = "technosophos/helloworld:0.1.0".parse().unwrap();
+/// assert_eq!(
+///     mirrors.mirror(&image).unwrap().to_string(),
+///     "mirror.example.com/dockerhub/technosophos/helloworld:0.1.0"
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Mirrors {
+    rules: Vec<(String, String)>,
+}
+
+impl Mirrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pull what is under `from`, a registry or the start of a `registry/repository`,
+    /// from under `to` instead, unless a rule added before matches. Rules match whole
+    /// path components, and may end with `/*`: `docker.io/*` is the same rule as
+    /// `docker.io`.
+    pub fn rule(mut self, from: &str, to: &str) -> Self {
+        let pattern = |s: &str| s.trim().trim_end_matches("/*").to_string();
+        self.rules.push((pattern(from), pattern(to)));
+        self
+    }
+
+    /// Read rules of the form `from -> to`, one per line, as `rule` takes them.
+    /// Blank lines, and lines starting with `#`, are skipped.
+    pub fn parse(rules: &str) -> Result<Self, InvalidMirrorRule> {
+        let mut mirrors = Mirrors::new();
+        for line in rules.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || InvalidMirrorRule(line.to_string());
+            let (from, to) = line.split_once("->").ok_or_else(invalid)?;
+            let valid = |s: &str| {
+                let s = s.trim().trim_end_matches("/*");
+                !s.is_empty() && !s.contains('*') && !s.contains(char::is_whitespace)
+            };
+            if !valid(from) || !valid(to) {
+                return Err(invalid());
+            }
+            mirrors = mirrors.rule(from, to);
+        }
+        Ok(mirrors)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Where to pull `reference` from instead, if a rule matches it. The tag and
+    /// digest stay as they are.
+    pub fn mirror(&self, reference: &BundleReference) -> Option<BundleReference> {
+        let name = rewrite(&self.rules, &reference.name())?;
+        let (registry, repository) = name.split_once('/')?;
+        Some(BundleReference {
+            registry: registry.to_string(),
+            repository: repository.to_string(),
+            ..reference.clone()
+        })
+    }
+
+    /// The relocation map to run `bundle` with: `relocation`, with every OCI image of
+    /// the bundle sent to its mirror from where `relocation` sends it. An image
+    /// referenced by tag alone is pinned to its `contentDigest`, as `Bundle::relocate`
+    /// pins them.
+    pub fn relocation(&self, bundle: &Bundle, relocation: &RelocationMap) -> RelocationMap {
+        let mut map = relocation.clone();
+        if self.is_empty() {
+            return map;
+        }
+        let images = bundle
+            .invocation_images
+            .iter()
+            .map(|i| (&i.image, &i.image_type, &i.content_digest))
+            .chain(
+                bundle
+                    .images
+                    .iter()
+                    .flat_map(|images| images.values())
+                    .map(|i| (&i.image, &i.image_type, &i.content_digest)),
+            );
+        for (image, image_type, content_digest) in images {
+            if !is_oci_image(image_type.as_deref()) {
+                continue;
+            }
+            let reference = BundleReference::parse(relocation.relocate(image)).ok();
+            if let Some(mut mirrored) = reference.and_then(|r| self.mirror(&r)) {
+                if mirrored.digest.is_none() {
+                    mirrored.digest = content_digest.clone();
+                }
+                map.insert(image, &mirrored.to_string());
+            }
+        }
+        map
+    }
+}
+
+/// InvalidMirrorRule is a line of mirror rules that could not be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidMirrorRule(pub String);
+
+impl fmt::Display for InvalidMirrorRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid mirror rule {:?}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidMirrorRule {}
+
+impl Bundle {
+    /// Rewrite the references of the bundle's OCI images to where `rules` move them,
+    /// returning the rewritten bundle and the relocation map from the original
+    /// references to the new ones.
+    ///
+    /// Moved images keep their tag and digest. An image referenced by tag alone is
+    /// pinned to its `contentDigest` when the bundle records one, as a copy of the
+    /// image is only known to be the same content by digest.
+    ///
+    /// ```
+    /// use libcnab::{Bundle, RelocationRules};
+    ///
+    /// let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+    /// let rules = RelocationRules::Prefix("mirror.example.com/team".to_string());
+    /// let (relocated, map) = bundle.relocate(&rules).unwrap();
+    /// assert_eq!(
+    ///     relocated.invocation_images[0].image,
+    ///     "mirror.example.com/team/technosophos/helloworld:0.1.0"
+    /// );
+    /// assert_eq!(
+    ///     map.relocate("technosophos/helloworld:0.1.0"),
+    ///     relocated.invocation_images[0].image
+    /// );
+    /// ```
+    pub fn relocate(
+        &self,
+        rules: &RelocationRules,
+    ) -> Result<(Bundle, RelocationMap), InvalidReference> {
+        let mut bundle = self.clone();
+        let mut map = RelocationMap::new();
+        let invocation_images = bundle
+            .invocation_images
+            .iter_mut()
+            .map(|i| (&mut i.image, &i.image_type, &i.content_digest));
+        let images = bundle
+            .images
+            .iter_mut()
+            .flat_map(|images| images.values_mut())
+            .map(|i| (&mut i.image, &i.image_type, &i.content_digest));
+        for (image, image_type, content_digest) in invocation_images.chain(images) {
+            if !is_oci_image(image_type.as_deref()) {
+                continue;
+            }
+            let reference = BundleReference::parse(image)?;
+            let name = match rules.apply(&reference) {
+                Some(name) => name,
+                None => continue,
+            };
+            let mut relocated = name;
+            if let Some(tag) = &reference.tag {
+                relocated = format!("{}:{}", relocated, tag);
+            }
+            if let Some(digest) = reference.digest.as_ref().or(content_digest.as_ref()) {
+                relocated = format!("{}@{}", relocated, digest);
+            }
+            map.insert(image, &relocated);
+            *image = relocated;
+        }
+        Ok((bundle, map))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_relocate() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.invocation_images[0].content_digest = Some("sha256:0123".to_string());
+
+        let rules = RelocationRules::Rewrite(vec![
+            (
+                "docker.io/technosophos/helloworld".to_string(),
+                "ghcr.io/acme/hello".to_string(),
+            ),
+            (
+                "docker.io/technosophos/micro".to_string(),
+                "ghcr.io/acme/micro".to_string(),
+            ),
+        ]);
+        let (relocated, map) = bundle.relocate(&rules).unwrap();
+        assert_eq!(
+            relocated.invocation_images[0].image,
+            "ghcr.io/acme/hello:0.1.0@sha256:0123"
+        );
+        assert_eq!(
+            map.get("technosophos/helloworld:0.1.0"),
+            Some("ghcr.io/acme/hello:0.1.0@sha256:0123")
+        );
+        // Rules only match whole components, so microservice stays where it is.
+        let images = relocated.images.as_ref().unwrap();
+        assert_eq!(
+            images["my-microservice"].image,
+            "technosophos/microservice:1.2.3"
+        );
+        assert_eq!(map.len(), 1);
+
+        bundle.invocation_images[0].image = "Not A Reference".to_string();
+        assert!(bundle
+            .relocate(&RelocationRules::Prefix("mirror.example.com".to_string()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_mirrors() {
+        let mirrors = Mirrors::parse(
+            "
+            # Docker Hub is blocked
+            docker.io/* -> mirror.example.com/dockerhub/*
+            ghcr.io/acme -> mirror.example.com/acme
+            ",
+        )
+        .unwrap();
+        let mirror = |image: &str| {
+            mirrors
+                .mirror(&BundleReference::parse(image).unwrap())
+                .map(|r| r.to_string())
+        };
+        assert_eq!(
+            mirror("nginx@sha256:0123").as_deref(),
+            Some("mirror.example.com/dockerhub/library/nginx@sha256:0123")
+        );
+        assert_eq!(
+            mirror("ghcr.io/acme/api:1.0").as_deref(),
+            Some("mirror.example.com/acme/api:1.0")
+        );
+        assert_eq!(mirror("ghcr.io/acmeco/api:1.0"), None);
+        assert_eq!(mirror("quay.io/acme/api:1.0"), None);
+
+        // The relocation map sends images to their mirrors from where they were
+        // relocated to.
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.invocation_images[0].content_digest = Some("sha256:0123".to_string());
+        let mut relocation = RelocationMap::new();
+        relocation.insert(
+            "technosophos/microservice:1.2.3",
+            "ghcr.io/acme/micro:1.2.3",
+        );
+        let map = mirrors.relocation(&bundle, &relocation);
+        assert_eq!(
+            map.get("technosophos/helloworld:0.1.0"),
+            Some("mirror.example.com/dockerhub/technosophos/helloworld:0.1.0@sha256:0123")
+        );
+        assert_eq!(
+            map.get("technosophos/microservice:1.2.3"),
+            Some("mirror.example.com/acme/micro:1.2.3")
+        );
+        assert_eq!(Mirrors::new().relocation(&bundle, &relocation), relocation);
+
+        for invalid in [
+            "docker.io",
+            "docker.io ->",
+            "docker.io/*/x -> mirror.example.com",
+        ] {
+            assert_eq!(
+                Mirrors::parse(invalid),
+                Err(InvalidMirrorRule(invalid.to_string()))
+            );
+        }
+    }
+}
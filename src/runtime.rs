@@ -0,0 +1,360 @@
+//! Support for invocation images written in Rust.
+//!
+//! The CNAB runtime starts an invocation image with a set of well-known environment
+//! variables and the bundle descriptor mounted at `/cnab/bundle.json`. The types here
+//! give the code running inside the image typed access to that environment.
+//!
+//! ```no_run
+//! use libcnab::runtime::CnabContext;
+//!
+//! let ctx = CnabContext::from_env().expect("running inside a CNAB invocation image");
+//! println!("{} {} ({})", ctx.action(), ctx.installation_name(), ctx.bundle_version());
+//! ```
+use crate::cancel::CancellationToken;
+use crate::cnab::{Bundle, BundleParseError};
+use crate::error_code::{Coded, ErrorCode};
+use crate::layout::{BUNDLE_PATH, RELOCATION_MAPPING_PATH};
+use crate::relocation::RelocationMap;
+use semver::Version;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+mod dispatch;
+pub use self::dispatch::*;
+mod events;
+pub use self::events::*;
+mod outputs;
+pub use self::outputs::*;
+mod preflight;
+mod signals;
+mod values;
+pub use self::signals::*;
+#[cfg(feature = "drivers")]
+pub(crate) use self::values::coerce;
+
+/// The name of the action being performed (e.g. `install`)
+pub const CNAB_ACTION: &str = "CNAB_ACTION";
+/// The name of the installation the action is performed against
+pub const CNAB_INSTALLATION_NAME: &str = "CNAB_INSTALLATION_NAME";
+/// The name of the bundle
+pub const CNAB_BUNDLE_NAME: &str = "CNAB_BUNDLE_NAME";
+/// The version of the bundle
+pub const CNAB_BUNDLE_VERSION: &str = "CNAB_BUNDLE_VERSION";
+/// The revision of the installation being created by this action
+pub const CNAB_REVISION: &str = "CNAB_REVISION";
+
+/// CnabContext describes the action an invocation image has been asked to perform.
+#[derive(Debug)]
+pub struct CnabContext {
+    action: String,
+    installation_name: String,
+    bundle_name: String,
+    bundle_version: Version,
+    revision: String,
+    bundle: Bundle,
+    /// Values of the env var destinations declared by the bundle
+    env: BTreeMap<String, String>,
+    cancellation: CancellationToken,
+    relocation: RelocationMap,
+}
+
+impl CnabContext {
+    /// Build the context from the process environment, the mounted bundle descriptor
+    /// and the relocation mapping, if one is mounted.
+    pub fn from_env() -> Result<Self, RuntimeError> {
+        let ctx = Self::from_lookup(|name| std::env::var(name).ok(), BUNDLE_PATH)?;
+        match RelocationMap::from_file(RELOCATION_MAPPING_PATH) {
+            Ok(map) => Ok(ctx.with_relocation(map)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ctx),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Build the context using `lookup` to read variables and the bundle descriptor at
+    /// `bundle_path`.
+    ///
+    /// This is useful for testing handlers outside of an invocation image.
+    pub fn from_lookup<F, P>(lookup: F, bundle_path: P) -> Result<Self, RuntimeError>
+    where
+        F: Fn(&str) -> Option<String>,
+        P: AsRef<Path>,
+    {
+        let var = |name: &str| {
+            lookup(name)
+                .filter(|v| !v.is_empty())
+                .ok_or_else(|| RuntimeError::MissingVariable(name.to_string()))
+        };
+        let version = var(CNAB_BUNDLE_VERSION)?;
+        let bundle_version =
+            Version::parse(&version).map_err(|e| RuntimeError::InvalidVariable {
+                name: CNAB_BUNDLE_VERSION.to_string(),
+                message: e.to_string(),
+            })?;
+        let bundle = Bundle::from_file(bundle_path)?;
+        let parameter_env = bundle
+            .parameters
+            .iter()
+            .flatten()
+            .filter_map(|(_, p)| p.destination.env.as_ref());
+        let credential_env = bundle
+            .credentials
+            .iter()
+            .flatten()
+            .filter_map(|(_, c)| c.env.as_ref());
+        let env = parameter_env
+            .chain(credential_env)
+            .filter_map(|name| lookup(name).map(|v| (name.to_string(), v)))
+            .collect();
+        Ok(CnabContext {
+            action: var(CNAB_ACTION)?,
+            installation_name: var(CNAB_INSTALLATION_NAME)?,
+            bundle_name: var(CNAB_BUNDLE_NAME)?,
+            bundle_version,
+            revision: var(CNAB_REVISION)?,
+            bundle,
+            env,
+            cancellation: CancellationToken::new(),
+            relocation: RelocationMap::new(),
+        })
+    }
+
+    /// The action being performed
+    pub fn action(&self) -> &str {
+        &self.action
+    }
+
+    /// The name of the installation
+    pub fn installation_name(&self) -> &str {
+        &self.installation_name
+    }
+
+    /// The name of the bundle, as passed by the runtime
+    pub fn bundle_name(&self) -> &str {
+        &self.bundle_name
+    }
+
+    /// The version of the bundle, as passed by the runtime
+    pub fn bundle_version(&self) -> &Version {
+        &self.bundle_version
+    }
+
+    /// The revision of the installation being created
+    pub fn revision(&self) -> &str {
+        &self.revision
+    }
+
+    /// The mounted bundle descriptor
+    pub fn bundle(&self) -> &Bundle {
+        &self.bundle
+    }
+
+    /// The token that is cancelled when the image is asked to stop.
+    ///
+    /// Long-running handlers should check it periodically and clean up when it fires.
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Where the bundle's images were relocated to. Empty unless the bundle was
+    /// relocated.
+    pub fn relocation(&self) -> &RelocationMap {
+        &self.relocation
+    }
+
+    /// The reference to pull the bundle's image `name` from, taking relocation into
+    /// account.
+    pub fn image(&self, name: &str) -> Option<&str> {
+        let image = self.bundle.images.as_ref()?.get(name)?;
+        Some(self.relocation.relocate(&image.image))
+    }
+
+    /// Replace the context's relocation mapping.
+    pub fn with_relocation(mut self, map: RelocationMap) -> Self {
+        self.relocation = map;
+        self
+    }
+
+    /// Replace the context's cancellation token.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+}
+
+/// Represents an error inside an invocation image
+#[derive(Debug)]
+pub enum RuntimeError {
+    /// A variable the runtime is required to set was missing or empty
+    MissingVariable(String),
+    /// A variable was set to a value that could not be parsed
+    InvalidVariable {
+        name: String,
+        message: String,
+    },
+    BundleParseError(BundleParseError),
+    /// No handler is registered for the requested action
+    UnknownAction(String),
+    /// The handler for an action returned an error
+    ActionFailed {
+        action: String,
+        source: ActionError,
+    },
+    /// An output was written that the bundle does not declare
+    UndeclaredOutput(String),
+    /// An output was written that does not satisfy its declaration
+    InvalidOutput {
+        name: String,
+        message: String,
+    },
+    /// A parameter was requested that the bundle does not declare
+    UndeclaredParameter(String),
+    /// A credential was requested that the bundle does not declare
+    UndeclaredCredential(String),
+    /// An injected parameter value does not match its definition
+    InvalidParameter {
+        name: String,
+        message: String,
+    },
+    /// Required parameters or credentials were not injected
+    PreflightFailed(Vec<String>),
+    IoError(std::io::Error),
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::MissingVariable(name) => {
+                write!(f, "environment variable {} is not set", name)
+            }
+            RuntimeError::InvalidVariable { name, message } => {
+                write!(f, "environment variable {} is invalid: {}", name, message)
+            }
+            RuntimeError::BundleParseError(e) => {
+                write!(f, "cannot load bundle descriptor: {}", e)
+            }
+            RuntimeError::UnknownAction(action) => {
+                write!(f, "action {} is not supported by this bundle", action)
+            }
+            RuntimeError::ActionFailed { action, source } => {
+                write!(f, "action {} failed: {}", action, source)
+            }
+            RuntimeError::UndeclaredOutput(name) => {
+                write!(f, "output {} is not declared by the bundle", name)
+            }
+            RuntimeError::InvalidOutput { name, message } => {
+                write!(f, "output {} is invalid: {}", name, message)
+            }
+            RuntimeError::UndeclaredParameter(name) => {
+                write!(f, "parameter {} is not declared by the bundle", name)
+            }
+            RuntimeError::UndeclaredCredential(name) => {
+                write!(f, "credential {} is not declared by the bundle", name)
+            }
+            RuntimeError::InvalidParameter { name, message } => {
+                write!(f, "parameter {} is invalid: {}", name, message)
+            }
+            RuntimeError::PreflightFailed(problems) => {
+                write!(f, "preflight check failed: {}", problems.join("; "))
+            }
+            RuntimeError::IoError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl Coded for RuntimeError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            RuntimeError::MissingVariable(_) => ErrorCode::RuntimeMissingVariable,
+            RuntimeError::InvalidVariable { .. } => ErrorCode::RuntimeInvalidVariable,
+            RuntimeError::BundleParseError(e) => e.code(),
+            RuntimeError::UnknownAction(_) => ErrorCode::RuntimeUnknownAction,
+            RuntimeError::ActionFailed { .. } => ErrorCode::RuntimeActionFailed,
+            RuntimeError::UndeclaredOutput(_) => ErrorCode::RuntimeUndeclaredOutput,
+            RuntimeError::InvalidOutput { .. } => ErrorCode::RuntimeInvalidOutput,
+            RuntimeError::UndeclaredParameter(_) => ErrorCode::RuntimeUndeclaredParameter,
+            RuntimeError::UndeclaredCredential(_) => ErrorCode::RuntimeUndeclaredCredential,
+            RuntimeError::InvalidParameter { .. } => ErrorCode::RuntimeInvalidParameter,
+            RuntimeError::PreflightFailed(_) => ErrorCode::RuntimePreflightFailed,
+            RuntimeError::IoError(_) => ErrorCode::Io,
+        }
+    }
+}
+
+impl From<std::io::Error> for RuntimeError {
+    fn from(error: std::io::Error) -> Self {
+        RuntimeError::IoError(error)
+    }
+}
+
+impl From<BundleParseError> for RuntimeError {
+    fn from(error: BundleParseError) -> Self {
+        RuntimeError::BundleParseError(error)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    /// A context for the testdata bundle performing `action`.
+    pub(crate) fn context(action: &str) -> CnabContext {
+        let mut vars = vars();
+        vars.insert(CNAB_ACTION, action.to_string());
+        CnabContext::from_lookup(|k| vars.get(k).cloned(), "testdata/bundle.json").unwrap()
+    }
+
+    pub(crate) fn vars() -> BTreeMap<&'static str, String> {
+        vec![
+            (CNAB_ACTION, "install"),
+            (CNAB_INSTALLATION_NAME, "my-install"),
+            (CNAB_BUNDLE_NAME, "helloworld"),
+            (CNAB_BUNDLE_VERSION, "0.1.2"),
+            (CNAB_REVISION, "01CP6XM0KVB9V1BQDZ9NK8VP29"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k, v.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn test_context_from_lookup() {
+        let vars = vars();
+        let ctx = CnabContext::from_lookup(|k| vars.get(k).cloned(), "testdata/bundle.json")
+            .expect("context built");
+        assert_eq!(ctx.action(), "install");
+        assert_eq!(ctx.installation_name(), "my-install");
+        assert_eq!(ctx.bundle_version(), &Version::new(0, 1, 2));
+        assert_eq!(ctx.bundle().name, "helloworld");
+        assert_eq!(
+            ctx.image("my-microservice"),
+            Some("technosophos/microservice:1.2.3")
+        );
+
+        let map = vec![(
+            "technosophos/microservice:1.2.3".to_string(),
+            "mirror.example.com/microservice@sha256:0123".to_string(),
+        )]
+        .into_iter()
+        .collect();
+        let ctx = ctx.with_relocation(map);
+        assert_eq!(
+            ctx.image("my-microservice"),
+            Some("mirror.example.com/microservice@sha256:0123")
+        );
+        assert_eq!(ctx.image("missing"), None);
+    }
+
+    #[test]
+    fn test_context_missing_variable() {
+        let mut vars = vars();
+        vars.remove(CNAB_REVISION);
+        match CnabContext::from_lookup(|k| vars.get(k).cloned(), "testdata/bundle.json") {
+            Err(RuntimeError::MissingVariable(name)) => assert_eq!(name, CNAB_REVISION),
+            other => panic!("expected missing variable, got {:?}", other),
+        }
+    }
+}
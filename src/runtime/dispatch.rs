@@ -0,0 +1,147 @@
+use super::{install_signal_handlers, CnabContext, RuntimeError};
+use std::collections::BTreeMap;
+
+/// The error type returned by action handlers.
+pub type ActionError = Box<dyn std::error::Error + Send + Sync>;
+
+/// ActionHandler implements one action of an invocation image.
+///
+/// Any `Fn(&CnabContext) -> Result<(), ActionError>` is an ActionHandler.
+pub trait ActionHandler {
+    fn handle(&self, ctx: &CnabContext) -> Result<(), ActionError>;
+}
+
+impl<F> ActionHandler for F
+where
+    F: Fn(&CnabContext) -> Result<(), ActionError>,
+{
+    fn handle(&self, ctx: &CnabContext) -> Result<(), ActionError> {
+        self(ctx)
+    }
+}
+
+/// Runtime routes the action requested by `CNAB_ACTION` to a registered handler.
+///
+/// ```no_run
+/// use libcnab::runtime::{ActionError, CnabContext, Runtime};
+///
+/// fn install(ctx: &CnabContext) -> Result<(), ActionError> {
+///     println!("installing {}", ctx.installation_name());
+///     Ok(())
+/// }
+///
+/// fn main() {
+///     let runtime = Runtime::new()
+///         .action("install", install)
+///         .action("uninstall", |_: &CnabContext| Ok(()));
+///     std::process::exit(runtime.run());
+/// }
+/// ```
+#[derive(Default)]
+pub struct Runtime {
+    handlers: BTreeMap<String, Box<dyn ActionHandler>>,
+    fallback: Option<Box<dyn ActionHandler>>,
+}
+
+impl Runtime {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the handler for an action, replacing any previous handler.
+    pub fn action<H: ActionHandler + 'static>(mut self, name: &str, handler: H) -> Self {
+        self.handlers.insert(name.to_string(), Box::new(handler));
+        self
+    }
+
+    /// Register a handler for actions that have no handler of their own.
+    ///
+    /// Without a fallback, unknown actions fail with `RuntimeError::UnknownAction`.
+    pub fn fallback<H: ActionHandler + 'static>(mut self, handler: H) -> Self {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// The names of the actions with registered handlers.
+    pub fn actions(&self) -> impl Iterator<Item = &str> {
+        self.handlers.keys().map(String::as_str)
+    }
+
+    /// Run the handler for the context's action.
+    pub fn dispatch(&self, ctx: &CnabContext) -> Result<(), RuntimeError> {
+        let handler = self
+            .handlers
+            .get(ctx.action())
+            .or(self.fallback.as_ref())
+            .ok_or_else(|| RuntimeError::UnknownAction(ctx.action().to_string()))?;
+        handler
+            .handle(ctx)
+            .map_err(|source| RuntimeError::ActionFailed {
+                action: ctx.action().to_string(),
+                source,
+            })
+    }
+
+    /// Load the context from the environment and dispatch it, reporting any error on
+    /// stderr. Returns the process exit code.
+    ///
+    /// SIGTERM and SIGINT handlers are installed first, so handlers can watch
+    /// `CnabContext::cancellation` to clean up before the container is killed. The
+    /// context's `preflight` check must pass before the handler is called.
+    pub fn run(&self) -> i32 {
+        let result = install_signal_handlers()
+            .map_err(RuntimeError::from)
+            .and_then(|token| Ok(CnabContext::from_env()?.with_cancellation(token)))
+            .and_then(|ctx| {
+                ctx.preflight()?;
+                self.dispatch(&ctx)
+            });
+        match result {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::runtime::test::context;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_dispatch() {
+        let called = Rc::new(Cell::new(false));
+        let flag = called.clone();
+        let runtime = Runtime::new()
+            .action("install", move |_: &CnabContext| {
+                flag.set(true);
+                Ok(())
+            })
+            .action("upgrade", |_: &CnabContext| Err("boom".into()));
+
+        runtime
+            .dispatch(&context("install"))
+            .expect("install handled");
+        assert!(called.get());
+
+        match runtime.dispatch(&context("upgrade")) {
+            Err(RuntimeError::ActionFailed { action, source }) => {
+                assert_eq!(action, "upgrade");
+                assert_eq!(source.to_string(), "boom");
+            }
+            other => panic!("expected failed action, got {:?}", other),
+        }
+        match runtime.dispatch(&context("status")) {
+            Err(RuntimeError::UnknownAction(action)) => assert_eq!(action, "status"),
+            other => panic!("expected unknown action, got {:?}", other),
+        }
+
+        let runtime = runtime.fallback(|_: &CnabContext| Ok(()));
+        runtime.dispatch(&context("status")).expect("fallback used");
+    }
+}
@@ -0,0 +1,80 @@
+use crate::events::Event;
+use std::io::{Stdout, Write};
+
+/// EventWriter reports structured events from an invocation image to the host.
+///
+/// Each event is written as one line and flushed immediately, so it can be
+/// interleaved with ordinary output on the same stream.
+pub struct EventWriter<W: Write> {
+    out: W,
+}
+
+impl EventWriter<Stdout> {
+    /// Write events to stdout, where the host reads them.
+    pub fn stdout() -> Self {
+        EventWriter::new(std::io::stdout())
+    }
+}
+
+impl<W: Write> EventWriter<W> {
+    pub fn new(out: W) -> Self {
+        EventWriter { out }
+    }
+
+    pub fn emit(&mut self, event: &Event) -> std::io::Result<()> {
+        writeln!(self.out, "{}", event.to_line())?;
+        self.out.flush()
+    }
+
+    pub fn progress(&mut self, message: &str) -> std::io::Result<()> {
+        self.emit(&Event::progress(message))
+    }
+
+    /// Report progress as step `current` of `total`.
+    pub fn step(&mut self, message: &str, current: u64, total: u64) -> std::io::Result<()> {
+        self.emit(&Event::step(message, current, total))
+    }
+
+    pub fn warning(&mut self, message: &str) -> std::io::Result<()> {
+        self.emit(&Event::warning(message))
+    }
+
+    /// Announce that an output has been written.
+    pub fn output(&mut self, name: &str) -> std::io::Result<()> {
+        self.emit(&Event::output(name))
+    }
+
+    /// Send more of the output `name` to the host while the action runs. The host
+    /// still collects the output once the action exits, so the chunks are usually
+    /// appended to it as well.
+    pub fn chunk(&mut self, name: &str, data: &str) -> std::io::Result<()> {
+        self.emit(&Event::chunk(name, data))
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_event_writer() {
+        let mut events = EventWriter::new(Vec::new());
+        events.step("pulling images", 1, 2).unwrap();
+        events.output("port").unwrap();
+        events.chunk("log", "started").unwrap();
+        let written = String::from_utf8(events.into_inner()).unwrap();
+        let parsed: Vec<_> = written.lines().filter_map(Event::parse).collect();
+        assert_eq!(
+            parsed,
+            vec![
+                Event::step("pulling images", 1, 2),
+                Event::output("port"),
+                Event::chunk("log", "started")
+            ]
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use super::{CnabContext, RuntimeError};
+use crate::cnab::Output;
+use crate::layout::OUTPUTS_DIR;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Outputs writes the outputs of the current action where the runtime collects them.
+///
+/// Each output is written to `<dir>/<name>`. Writes are checked against the output
+/// declarations in the bundle: the output must be declared and must apply to the
+/// current action. Outputs whose definition is `writeOnly` are treated as sensitive
+/// and, on Unix, are created readable only by the owner.
+pub struct Outputs<'a> {
+    ctx: &'a CnabContext,
+    dir: PathBuf,
+}
+
+impl<'a> Outputs<'a> {
+    /// Write outputs to the standard outputs directory.
+    pub fn new(ctx: &'a CnabContext) -> Self {
+        Self::in_dir(ctx, OUTPUTS_DIR)
+    }
+
+    /// Write outputs to a different directory (for example, in tests).
+    pub fn in_dir<P: AsRef<Path>>(ctx: &'a CnabContext, dir: P) -> Self {
+        Outputs {
+            ctx,
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Write the complete contents of an output.
+    ///
+    /// For scalar definitions (`integer`, `number`, `boolean`) the contents are checked
+    /// to parse as that type.
+    pub fn write<C: AsRef<[u8]>>(&self, name: &str, contents: C) -> Result<(), RuntimeError> {
+        let contents = contents.as_ref();
+        self.check_scalar(name, contents)?;
+        self.writer(name)?.write_all(contents)?;
+        Ok(())
+    }
+
+    /// Open an output for streaming. The caller writes the contents to the returned file.
+    pub fn writer(&self, name: &str) -> Result<File, RuntimeError> {
+        self.declaration(name)?;
+        std::fs::create_dir_all(&self.dir)?;
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            if self.is_sensitive(name) {
+                options.mode(0o600);
+            }
+        }
+        Ok(options.open(self.dir.join(name))?)
+    }
+
+    /// Stream an output from a reader, returning the number of bytes written.
+    pub fn copy_from<R: Read>(&self, name: &str, mut reader: R) -> Result<u64, RuntimeError> {
+        let mut file = self.writer(name)?;
+        Ok(std::io::copy(&mut reader, &mut file)?)
+    }
+
+    /// Whether the output's definition marks it as sensitive (`writeOnly: true`).
+    pub fn is_sensitive(&self, name: &str) -> bool {
+        self.ctx
+            .bundle()
+            .outputs
+            .as_ref()
+            .and_then(|o| o.get(name))
+            .map(|o| self.ctx.bundle().is_sensitive(&o.definition))
+            .unwrap_or(false)
+    }
+
+    fn declaration(&self, name: &str) -> Result<&Output, RuntimeError> {
+        let output = self
+            .ctx
+            .bundle()
+            .outputs
+            .as_ref()
+            .and_then(|o| o.get(name))
+            .ok_or_else(|| RuntimeError::UndeclaredOutput(name.to_string()))?;
+        if !output.applies_to(self.ctx.action()) {
+            return Err(RuntimeError::InvalidOutput {
+                name: name.to_string(),
+                message: format!("output does not apply to action {}", self.ctx.action()),
+            });
+        }
+        Ok(output)
+    }
+
+    fn definition(&self, name: &str) -> Option<&serde_json::Value> {
+        let output = self.ctx.bundle().outputs.as_ref()?.get(name)?;
+        self.ctx.bundle().definition(&output.definition)
+    }
+
+    fn check_scalar(&self, name: &str, contents: &[u8]) -> Result<(), RuntimeError> {
+        check_scalar(self.definition(name), contents).map_err(|message| {
+            RuntimeError::InvalidOutput {
+                name: name.to_string(),
+                message,
+            }
+        })
+    }
+}
+
+/// Check that output contents parse as the scalar `type` (`integer`, `number` or
+/// `boolean`) of their definition. Other types are not checked.
+pub(crate) fn check_scalar(
+    definition: Option<&serde_json::Value>,
+    contents: &[u8],
+) -> Result<(), String> {
+    let kind = definition
+        .and_then(|d| d.get("type"))
+        .and_then(|t| t.as_str());
+    let text = || String::from_utf8_lossy(contents).trim().to_string();
+    let ok = match kind {
+        Some("integer") => text().parse::<i64>().is_ok(),
+        Some("number") => text().parse::<f64>().is_ok(),
+        Some("boolean") => text().parse::<bool>().is_ok(),
+        _ => true,
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(format!("value is not a valid {}", kind.unwrap_or_default()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::runtime::CnabContext;
+
+    fn context(dir: &Path) -> CnabContext {
+        let bundle = dir.join("bundle.json");
+        std::fs::write(
+            &bundle,
+            r#"{
+                "name": "outputs",
+                "invocationImages": [],
+                "schemaVersion": "1.0.0",
+                "version": "1.0.0",
+                "definitions": {
+                    "port": { "type": "integer" },
+                    "secret": { "type": "string", "writeOnly": true }
+                },
+                "outputs": {
+                    "port": { "definition": "port" },
+                    "password": { "definition": "secret" },
+                    "backup": { "definition": "secret", "applyTo": ["backup"] }
+                }
+            }"#,
+        )
+        .unwrap();
+        let vars = crate::runtime::test::vars();
+        CnabContext::from_lookup(|k| vars.get(k).cloned(), bundle).unwrap()
+    }
+
+    #[test]
+    fn test_outputs_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = context(dir.path());
+        let outputs = Outputs::in_dir(&ctx, dir.path().join("outputs"));
+
+        outputs.write("port", "8080").expect("port written");
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("outputs/port")).unwrap(),
+            "8080"
+        );
+        assert!(outputs.write("port", "eighty").is_err());
+
+        outputs
+            .copy_from("password", "hunter2".as_bytes())
+            .expect("password streamed");
+        assert!(outputs.is_sensitive("password"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.path().join("outputs/password"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        assert!(outputs.write("undeclared", "x").is_err());
+        assert!(outputs.write("backup", "x").is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use super::{CnabContext, RuntimeError};
+use std::path::Path;
+
+impl CnabContext {
+    /// Check that everything the action depends on was injected before running it.
+    ///
+    /// Every required parameter and credential that applies to the current action must
+    /// be present at each destination it declares: the env var must be set and the file
+    /// must exist. All problems are collected into a single
+    /// `RuntimeError::PreflightFailed` so they can be fixed in one pass.
+    pub fn preflight(&self) -> Result<(), RuntimeError> {
+        let mut problems = Vec::new();
+        if let Some(parameters) = &self.bundle().parameters {
+            for (name, parameter) in parameters {
+                if parameter.required.unwrap_or(false) && parameter.applies_to(self.action()) {
+                    self.check_destination(
+                        &format!("parameter {}", name),
+                        parameter.destination.env.as_deref(),
+                        parameter.destination.path.as_deref(),
+                        &mut problems,
+                    );
+                }
+            }
+        }
+        if let Some(credentials) = &self.bundle().credentials {
+            for (name, credential) in credentials {
+                if credential.required.unwrap_or(false) && credential.applies_to(self.action()) {
+                    self.check_destination(
+                        &format!("credential {}", name),
+                        credential.env.as_deref(),
+                        credential.path.as_deref(),
+                        &mut problems,
+                    );
+                }
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(RuntimeError::PreflightFailed(problems))
+        }
+    }
+
+    fn check_destination(
+        &self,
+        what: &str,
+        env: Option<&str>,
+        path: Option<&Path>,
+        problems: &mut Vec<String>,
+    ) {
+        if let Some(env) = env {
+            if !self.env.contains_key(env) {
+                problems.push(format!("{} requires environment variable {}", what, env));
+            }
+        }
+        if let Some(path) = path {
+            if !path.exists() {
+                problems.push(format!("{} requires file {}", what, path.display()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::runtime::test::vars;
+
+    #[test]
+    fn test_preflight() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("bundle.json");
+        std::fs::write(
+            &bundle,
+            format!(
+                r#"{{
+                    "name": "preflight",
+                    "invocationImages": [],
+                    "schemaVersion": "1.0.0",
+                    "version": "1.0.0",
+                    "parameters": {{
+                        "replicas": {{ "required": true, "destination": {{ "env": "REPLICAS" }} }},
+                        "config": {{ "required": true, "destination": {{ "path": "{dir}/config" }} }},
+                        "backup": {{ "required": true, "applyTo": ["backup"], "destination": {{ "env": "BACKUP" }} }},
+                        "debug": {{ "destination": {{ "env": "DEBUG" }} }}
+                    }},
+                    "credentials": {{
+                        "kubeconfig": {{ "required": true, "path": "{dir}/kubeconfig" }}
+                    }}
+                }}"#,
+                dir = dir.path().display()
+            ),
+        )
+        .unwrap();
+
+        let mut vars = vars();
+        let ctx = CnabContext::from_lookup(|k| vars.get(k).cloned(), &bundle).unwrap();
+        match ctx.preflight() {
+            Err(RuntimeError::PreflightFailed(problems)) => assert_eq!(
+                problems,
+                vec![
+                    format!(
+                        "parameter config requires file {}/config",
+                        dir.path().display()
+                    ),
+                    "parameter replicas requires environment variable REPLICAS".to_string(),
+                    format!(
+                        "credential kubeconfig requires file {}/kubeconfig",
+                        dir.path().display()
+                    ),
+                ]
+            ),
+            other => panic!("expected preflight failure, got {:?}", other),
+        }
+
+        vars.insert("REPLICAS", "3".to_string());
+        std::fs::write(dir.path().join("config"), "{}").unwrap();
+        std::fs::write(dir.path().join("kubeconfig"), "").unwrap();
+        let ctx = CnabContext::from_lookup(|k| vars.get(k).cloned(), &bundle).unwrap();
+        ctx.preflight().expect("everything injected");
+    }
+}
@@ -0,0 +1,41 @@
+use crate::cancel::CancellationToken;
+#[cfg(not(target_arch = "wasm32"))]
+use signal_hook::consts::{SIGINT, SIGTERM};
+
+/// The exit code used when a second signal forces the process to stop
+pub const FORCED_EXIT_CODE: i32 = 130;
+
+/// Install SIGTERM and SIGINT handlers that cancel the returned token.
+///
+/// The first signal only sets the token, giving the action a chance to clean up
+/// partially-created resources. A second signal exits immediately with
+/// `FORCED_EXIT_CODE`.
+///
+/// On wasm32 there are no signals to handle, and the token is only cancelled by hand.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn install_signal_handlers() -> std::io::Result<CancellationToken> {
+    let token = CancellationToken::new();
+    for signal in &[SIGTERM, SIGINT] {
+        signal_hook::flag::register_conditional_shutdown(*signal, FORCED_EXIT_CODE, token.flag())?;
+        signal_hook::flag::register(*signal, token.flag())?;
+    }
+    Ok(token)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn install_signal_handlers() -> std::io::Result<CancellationToken> {
+    Ok(CancellationToken::new())
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sigterm_cancels() {
+        let token = install_signal_handlers().expect("handlers installed");
+        assert!(!token.is_cancelled());
+        signal_hook::low_level::raise(SIGTERM).unwrap();
+        assert!(token.is_cancelled());
+    }
+}
@@ -0,0 +1,178 @@
+use super::{CnabContext, RuntimeError};
+use crate::encoding::ContentEncoding;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::path::Path;
+
+impl CnabContext {
+    /// Read the value of a parameter from its declared destination.
+    ///
+    /// The environment variable is preferred when it is set; otherwise the file at the
+    /// destination path is read. The raw value is coerced to the `type` declared by the
+    /// parameter's definition, so an `integer` parameter yields a JSON number. Returns
+    /// `None` when the parameter was not injected at all.
+    pub fn parameter(&self, name: &str) -> Result<Option<Value>, RuntimeError> {
+        let parameter = self
+            .bundle()
+            .parameters
+            .as_ref()
+            .and_then(|p| p.get(name))
+            .ok_or_else(|| RuntimeError::UndeclaredParameter(name.to_string()))?;
+        let definition = parameter
+            .definition
+            .as_ref()
+            .and_then(|d| self.bundle().definition(d));
+        let raw = read_destination(
+            self.env(parameter.destination.env.as_deref()),
+            parameter.destination.path.as_deref(),
+            definition,
+        )?;
+        raw.map(|raw| coerce(definition, raw))
+            .transpose()
+            .map_err(|message| RuntimeError::InvalidParameter {
+                name: name.to_string(),
+                message,
+            })
+    }
+
+    /// Read a parameter and deserialize it into `T`.
+    pub fn parameter_as<T: DeserializeOwned>(&self, name: &str) -> Result<Option<T>, RuntimeError> {
+        self.parameter(name)?
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(|e| RuntimeError::InvalidParameter {
+                name: name.to_string(),
+                message: e.to_string(),
+            })
+    }
+
+    /// Read the value of a credential from its declared env var or path, preferring the
+    /// environment variable when it is set. Returns `None` when it was not injected.
+    pub fn credential(&self, name: &str) -> Result<Option<String>, RuntimeError> {
+        let credential = self
+            .bundle()
+            .credentials
+            .as_ref()
+            .and_then(|c| c.get(name))
+            .ok_or_else(|| RuntimeError::UndeclaredCredential(name.to_string()))?;
+        read_destination(
+            self.env(credential.env.as_deref()),
+            credential.path.as_deref(),
+            None,
+        )
+    }
+
+    fn env(&self, name: Option<&str>) -> Option<String> {
+        name.and_then(|n| self.env.get(n)).cloned()
+    }
+}
+
+fn read_destination(
+    env: Option<String>,
+    path: Option<&Path>,
+    definition: Option<&Value>,
+) -> Result<Option<String>, RuntimeError> {
+    if env.is_some() {
+        return Ok(env);
+    }
+    match path {
+        Some(path) if path.exists() => {
+            let contents = std::fs::read(path)?;
+            let encoding = definition
+                .map(ContentEncoding::from_definition)
+                .transpose()
+                .map_err(|e| RuntimeError::InvalidVariable {
+                    name: path.display().to_string(),
+                    message: e.to_string(),
+                })?
+                .flatten();
+            match encoding {
+                Some(encoding) => Ok(Some(encoding.encode(&contents))),
+                None => Ok(Some(String::from_utf8_lossy(&contents).into_owned())),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Convert a raw injected value into the JSON type named by the definition.
+pub(crate) fn coerce(definition: Option<&Value>, raw: String) -> Result<Value, String> {
+    let types: Vec<&str> = match definition.and_then(|d| d.get("type")) {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(ts)) => ts.iter().filter_map(Value::as_str).collect(),
+        _ => vec![],
+    };
+    if types.is_empty() || types.contains(&"string") {
+        return Ok(Value::String(raw));
+    }
+    let text = raw.trim();
+    types
+        .iter()
+        .find_map(|t| match *t {
+            "integer" => text.parse::<i64>().ok().map(Value::from),
+            "number" => text.parse::<f64>().ok().map(Value::from),
+            "boolean" => text.parse::<bool>().ok().map(Value::from),
+            "null" if text.is_empty() || text == "null" => Some(Value::Null),
+            "object" | "array" => serde_json::from_str(text)
+                .ok()
+                .filter(|v: &Value| v.is_object() == (*t == "object")),
+            _ => None,
+        })
+        .ok_or_else(|| format!("{:?} is not a valid {}", raw, types.join(" or ")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::runtime::test::vars;
+
+    #[test]
+    fn test_parameter_and_credential_values() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("replicas"), "3\n").unwrap();
+        std::fs::write(dir.path().join("token"), "s3cr3t").unwrap();
+        let bundle = dir.path().join("bundle.json");
+        std::fs::write(
+            &bundle,
+            format!(
+                r#"{{
+                    "name": "values",
+                    "invocationImages": [],
+                    "schemaVersion": "1.0.0",
+                    "version": "1.0.0",
+                    "definitions": {{
+                        "int": {{ "type": "integer" }},
+                        "bool": {{ "type": "boolean" }},
+                        "tags": {{ "type": "array" }}
+                    }},
+                    "parameters": {{
+                        "replicas": {{ "definition": "int", "destination": {{ "path": "{dir}/replicas" }} }},
+                        "debug": {{ "definition": "bool", "destination": {{ "env": "DEBUG" }} }},
+                        "tags": {{ "definition": "tags", "destination": {{ "env": "TAGS" }} }},
+                        "unset": {{ "destination": {{ "env": "UNSET" }} }}
+                    }},
+                    "credentials": {{
+                        "token": {{ "env": "TOKEN", "path": "{dir}/token" }}
+                    }}
+                }}"#,
+                dir = dir.path().display()
+            ),
+        )
+        .unwrap();
+
+        let mut vars = vars();
+        vars.insert("DEBUG", "true".to_string());
+        vars.insert("TAGS", r#"["a", "b"]"#.to_string());
+        let ctx = CnabContext::from_lookup(|k| vars.get(k).cloned(), &bundle).unwrap();
+
+        assert_eq!(ctx.parameter("replicas").unwrap(), Some(Value::from(3)));
+        assert_eq!(ctx.parameter_as::<bool>("debug").unwrap(), Some(true));
+        assert_eq!(
+            ctx.parameter_as::<Vec<String>>("tags").unwrap(),
+            Some(vec!["a".to_string(), "b".to_string()])
+        );
+        assert_eq!(ctx.parameter("unset").unwrap(), None);
+        assert!(ctx.parameter("nope").is_err());
+        assert_eq!(ctx.credential("token").unwrap(), Some("s3cr3t".to_string()));
+    }
+}
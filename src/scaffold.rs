@@ -0,0 +1,355 @@
+//! Generate a starter project for an invocation image written in Rust.
+//!
+//! The generated project builds a `run` binary with the `runtime` module, a Dockerfile
+//! that installs it at `/cnab/app/run`, and a `bundle.json` stub that declares the
+//! bundle's custom actions.
+//!
+//! ```no_run
+//! use libcnab::scaffold::Scaffold;
+//!
+//! Scaffold::new("helloworld", &["install", "upgrade", "uninstall", "io.cnab.status"])
+//!     .image("example.com/helloworld-installer")
+//!     .write("helloworld")
+//!     .expect("project written");
+//! ```
+//!
+//! Tooling that makes bundles itself can start from `Bundle::skeleton` or
+//! `bundle_with_actions` instead.
+use crate::cnab::{Action, Bundle, InvocationImage, BUILTIN_ACTIONS};
+use crate::layout::RUN_PATH;
+use semver::Version;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Scaffold describes the project to generate.
+#[derive(Debug, Clone)]
+pub struct Scaffold {
+    name: String,
+    version: String,
+    image: Option<String>,
+    actions: Vec<String>,
+}
+
+/// A file of a generated project, with its path relative to the project root
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScaffoldFile {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+impl Scaffold {
+    pub fn new<S: AsRef<str>>(name: &str, actions: &[S]) -> Self {
+        Scaffold {
+            name: name.to_string(),
+            version: "0.1.0".to_string(),
+            image: None,
+            actions: actions.iter().map(|a| a.as_ref().to_string()).collect(),
+        }
+    }
+
+    /// The version for the bundle and the crate. Defaults to `0.1.0`.
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = version.to_string();
+        self
+    }
+
+    /// The repository of the invocation image. Defaults to `<name>-installer`.
+    pub fn image(mut self, image: &str) -> Self {
+        self.image = Some(image.to_string());
+        self
+    }
+
+    /// The files of the project.
+    pub fn files(&self) -> Vec<ScaffoldFile> {
+        vec![
+            ScaffoldFile {
+                path: PathBuf::from("Cargo.toml"),
+                contents: self.cargo_toml(),
+            },
+            ScaffoldFile {
+                path: PathBuf::from("src/main.rs"),
+                contents: self.main_rs(),
+            },
+            ScaffoldFile {
+                path: PathBuf::from("Dockerfile"),
+                contents: self.dockerfile(),
+            },
+            ScaffoldFile {
+                path: PathBuf::from("bundle.json"),
+                contents: self.bundle_json(),
+            },
+        ]
+    }
+
+    /// Write the project under `dir`, creating it if needed.
+    ///
+    /// Existing files are never overwritten; the write fails with
+    /// `std::io::ErrorKind::AlreadyExists` instead.
+    pub fn write<P: AsRef<Path>>(&self, dir: P) -> std::io::Result<()> {
+        for file in self.files() {
+            let path = dir.as_ref().join(&file.path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)?
+                .write_all(file.contents.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn crate_name(&self) -> String {
+        crate_name(&self.name)
+    }
+
+    fn cargo_toml(&self) -> String {
+        format!(
+            r#"[package]
+name = "{name}"
+version = "{version}"
+edition = "2018"
+publish = false
+
+[[bin]]
+name = "run"
+path = "src/main.rs"
+
+[dependencies]
+libcnab = "{libcnab}"
+"#,
+            name = self.crate_name(),
+            version = self.version,
+            libcnab = env!("CARGO_PKG_VERSION"),
+        )
+    }
+
+    fn main_rs(&self) -> String {
+        let mut source =
+            String::from("use libcnab::runtime::{ActionError, CnabContext, Runtime};\n");
+        for action in &self.actions {
+            source.push_str(&format!(
+                r#"
+fn {handler}(ctx: &CnabContext) -> Result<(), ActionError> {{
+    println!("{action} {{}}", ctx.installation_name());
+    Ok(())
+}}
+"#,
+                handler = identifier(action),
+                action = action,
+            ));
+        }
+        source.push_str("\nfn main() {\n    let runtime = Runtime::new()");
+        for action in &self.actions {
+            source.push_str(&format!(
+                "\n        .action({:?}, {})",
+                action,
+                identifier(action)
+            ));
+        }
+        source.push_str(";\n    std::process::exit(runtime.run());\n}\n");
+        source
+    }
+
+    fn dockerfile(&self) -> String {
+        format!(
+            r#"FROM rust:1 AS build
+WORKDIR /src
+COPY . .
+RUN cargo build --release
+
+FROM debian:bookworm-slim
+COPY --from=build /src/target/release/run {run}
+CMD ["{run}"]
+"#,
+            run = RUN_PATH,
+        )
+    }
+
+    fn bundle_json(&self) -> String {
+        let image = self
+            .image
+            .clone()
+            .unwrap_or_else(|| format!("{}-installer", self.crate_name()));
+        let mut bundle = json!({
+            "schemaVersion": "v1.0.0",
+            "name": self.name,
+            "version": self.version,
+            "invocationImages": [{
+                "imageType": "docker",
+                "image": format!("{}:{}", image, self.version),
+            }],
+        });
+        let custom: serde_json::Map<String, serde_json::Value> = self
+            .actions
+            .iter()
+            .filter(|a| !BUILTIN_ACTIONS.contains(&a.as_str()))
+            .map(|a| (a.clone(), json!({ "modifies": false })))
+            .collect();
+        if !custom.is_empty() {
+            bundle["actions"] = serde_json::Value::Object(custom);
+        }
+        let mut out = serde_json::to_string_pretty(&bundle).expect("json values serialize");
+        out.push('\n');
+        out
+    }
+}
+
+impl Bundle {
+    /// A minimal valid bundle named `name`, to fill in: with the current schema
+    /// version, a placeholder `<name>-installer:<version>` Docker invocation image,
+    /// and empty actions, credentials, definitions, outputs and parameters.
+    ///
+    /// ```
+    /// use libcnab::Bundle;
+    ///
+    /// let bundle = Bundle::skeleton("Hello World", semver::Version::new(0, 1, 0));
+    /// assert_eq!(bundle.invocation_images[0].image, "hello-world-installer:0.1.0");
+    /// assert!(bundle.parameters.unwrap().is_empty());
+    /// ```
+    pub fn skeleton(name: &str, version: Version) -> Bundle {
+        Bundle {
+            actions: Some(BTreeMap::new()),
+            credentials: Some(BTreeMap::new()),
+            custom: None,
+            definitions: Some(BTreeMap::new()),
+            description: None,
+            images: None,
+            invocation_images: vec![InvocationImage {
+                content_digest: None,
+                image: format!("{}-installer:{}", crate_name(name), version),
+                image_type: Some("docker".to_string()),
+                media_type: None,
+                size: None,
+                labels: None,
+            }],
+            keywords: None,
+            license: None,
+            maintainers: None,
+            name: name.to_string(),
+            outputs: Some(BTreeMap::new()),
+            parameters: Some(BTreeMap::new()),
+            required_extensions: None,
+            schema_version: "v1.0.0".to_string(),
+            version,
+        }
+    }
+}
+
+/// A skeleton bundle, as `Bundle::skeleton` makes, that declares `actions` as
+/// custom actions which do not modify the installation. Built-in actions among
+/// them are left out, as every bundle has those.
+pub fn bundle_with_actions<S: AsRef<str>>(name: &str, version: Version, actions: &[S]) -> Bundle {
+    let mut bundle = Bundle::skeleton(name, version);
+    bundle.actions = Some(
+        actions
+            .iter()
+            .map(AsRef::as_ref)
+            .filter(|a| !BUILTIN_ACTIONS.contains(a))
+            .map(|a| {
+                (
+                    a.to_string(),
+                    Action {
+                        description: None,
+                        modifies: false,
+                        stateless: false,
+                    },
+                )
+            })
+            .collect(),
+    );
+    bundle
+}
+
+/// Turn a bundle name into a crate or image name.
+fn crate_name(name: &str) -> String {
+    identifier(name).replace('_', "-")
+}
+
+/// Turn an action or bundle name into a Rust identifier.
+fn identifier(name: &str) -> String {
+    let mut ident: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    ident
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ExtensionRegistry;
+
+    #[test]
+    fn test_scaffold() {
+        let scaffold = Scaffold::new("Hello World", &["install", "uninstall", "io.cnab.status"]);
+        let files = scaffold.files();
+        let file = |name: &str| {
+            files
+                .iter()
+                .find(|f| f.path == Path::new(name))
+                .map(|f| f.contents.clone())
+                .unwrap()
+        };
+
+        assert!(file("Cargo.toml").contains("name = \"hello-world\""));
+        let main = file("src/main.rs");
+        assert!(main.contains("fn io_cnab_status(ctx: &CnabContext)"));
+        assert!(main.contains(".action(\"io.cnab.status\", io_cnab_status)"));
+        assert!(file("Dockerfile").contains("/cnab/app/run"));
+
+        let bundle = Bundle::from_json(file("bundle.json").as_bytes()).expect("stub parses");
+        assert_eq!(bundle.name, "Hello World");
+        assert_eq!(
+            bundle.invocation_images[0].image,
+            "hello-world-installer:0.1.0"
+        );
+        let actions = bundle.actions.expect("custom actions declared");
+        assert_eq!(actions.keys().collect::<Vec<_>>(), vec!["io.cnab.status"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        scaffold.write(dir.path()).expect("project written");
+        assert!(dir.path().join("src/main.rs").is_file());
+        let err = scaffold
+            .write(dir.path())
+            .expect_err("files are not overwritten");
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+    }
+
+    #[test]
+    fn test_skeleton() {
+        let bundle = bundle_with_actions(
+            "helloworld",
+            Version::new(1, 2, 3),
+            &["install", "io.cnab.status", "logs"],
+        );
+        let actions = bundle.actions.as_ref().unwrap();
+        assert_eq!(
+            actions.keys().collect::<Vec<_>>(),
+            vec!["io.cnab.status", "logs"]
+        );
+        assert!(!actions["logs"].modifies);
+        assert_eq!(
+            bundle.invocation_images[0].image,
+            "helloworld-installer:1.2.3"
+        );
+
+        let json = serde_json::to_string(&bundle).unwrap();
+        let parsed = Bundle::from_json(json.as_bytes()).expect("skeletons parse");
+        assert_eq!(parsed.schema_version, "v1.0.0");
+        parsed.validate(&ExtensionRegistry::default()).unwrap();
+    }
+}
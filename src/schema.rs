@@ -0,0 +1,1103 @@
+//! A JSON Schema draft-07 validator for the values of parameters, credentials and
+//! outputs, checked against the definitions of their bundle.
+//!
+//! It follows the draft and its test suite, except where bundles written by other
+//! tools expect otherwise:
+//!
+//! - The keywords beside a `$ref` are applied as well as what it refers to, where
+//!   the draft ignores them. `{ "$ref": "#/definitions/port", "minimum": 1024 }`
+//!   refuses 80 even if `port` allows it.
+//! - A `$ref` may be the bare name of one of the bundle's definitions, such as
+//!   `"port"` for `"#/definitions/port"`. Other `$ref`s, to other documents or
+//!   by `$id`, do not resolve.
+//! - The `uri`, `iri`, `uri-reference`, `email` and `hostname` formats are checked
+//!   loosely: a URI needs only a scheme and no whitespace, an email only something
+//!   on either side of its last `@`, and a hostname's labels may hold any
+//!   alphanumeric characters, not only ASCII ones.
+//!
+//! The regexes of `pattern` and `patternProperties` are compiled once and shared by
+//! every later check.
+use crate::encoding::ContentEncoding;
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, OnceLock};
+
+/// The definitions of a bundle, which a definition's `$ref` refers to
+pub(crate) type Definitions = BTreeMap<String, Value>;
+
+/// How many `$ref`s deep a definition may go before it is taken to be circular
+const MAX_REF_DEPTH: usize = 32;
+
+/// How many compiled patterns are kept before the cache starts over
+const MAX_CACHED_PATTERNS: usize = 256;
+
+/// Check `value` against the JSON Schema draft-07 `definition`, returning what is
+/// wrong with it, if anything.
+///
+/// All of the draft's validation keywords are checked, including `allOf`, `anyOf`,
+/// `oneOf`, `not`, `if`, `items`, `properties` and `dependencies`, as well as
+/// `contentEncoding`. The `format`s of the draft are checked, and others ignored.
+/// What is wrong below the top of the value is prefixed with its JSON pointer, as
+/// in `/servers/0/port: 70000 is greater than the maximum 65535`.
+///
+/// A `$ref` is to another of the `definitions`, as `#/definitions/<name>` or a
+/// JSON pointer into one, such as `#/definitions/<name>/properties/port`, or by
+/// its bare name. Where this departs from the draft is listed in the module's
+/// documentation.
+pub(crate) fn check_value(
+    definitions: Option<&Definitions>,
+    definition: &Value,
+    value: &Value,
+) -> Vec<String> {
+    let mut violations = Vec::new();
+    Checker { definitions }.check(definition, value, "", 0, &mut violations);
+    violations
+}
+
+/// The definition a `$ref` refers to, if it is one of `definitions`.
+pub(crate) fn resolve_ref<'d>(
+    definitions: Option<&'d Definitions>,
+    reference: &str,
+) -> Option<&'d Value> {
+    let definitions = definitions?;
+    let pointer = match reference.strip_prefix("#/definitions/") {
+        Some(pointer) => pointer,
+        None if !reference.contains(['#', '/']) => reference,
+        None => return None,
+    };
+    let (name, rest) = match pointer.find('/') {
+        Some(slash) => pointer.split_at(slash),
+        None => (pointer, ""),
+    };
+    let name = name.replace("~1", "/").replace("~0", "~");
+    definitions.get(&name)?.pointer(rest)
+}
+
+/// `definition`, or what its `$ref`s lead to if it does not say its `type` itself.
+pub(crate) fn resolve<'d>(
+    definitions: Option<&'d Definitions>,
+    definition: &'d Value,
+) -> &'d Value {
+    let mut resolved = definition;
+    for _ in 0..MAX_REF_DEPTH {
+        if resolved.get("type").is_some() {
+            break;
+        }
+        match resolved
+            .get("$ref")
+            .and_then(Value::as_str)
+            .and_then(|r| resolve_ref(definitions, r))
+        {
+            Some(target) => resolved = target,
+            None => break,
+        }
+    }
+    resolved
+}
+
+struct Checker<'d> {
+    definitions: Option<&'d Definitions>,
+}
+
+impl Checker<'_> {
+    fn check(
+        &self,
+        schema: &Value,
+        value: &Value,
+        path: &str,
+        depth: usize,
+        violations: &mut Vec<String>,
+    ) {
+        let object = match schema {
+            Value::Object(object) => object,
+            Value::Bool(false) => {
+                return violations.push(at(path, format!("{} is not allowed", value)))
+            }
+            _ => return,
+        };
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            match resolve_ref(self.definitions, reference) {
+                _ if depth >= MAX_REF_DEPTH => {
+                    let circular = format!("$ref {} is circular", reference);
+                    return violations.push(at(path, circular));
+                }
+                Some(target) => self.check(target, value, path, depth + 1, violations),
+                None => {
+                    let unresolved = format!("$ref {} does not resolve", reference);
+                    violations.push(at(path, unresolved));
+                }
+            }
+        }
+        let mut violation = |message: String| violations.push(at(path, message));
+        let shown = || value.to_string();
+
+        let types: Vec<&str> = match schema.get("type") {
+            Some(Value::String(t)) => vec![t.as_str()],
+            Some(Value::Array(ts)) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        if !types.is_empty() && !types.iter().any(|t| is_of_type(value, t)) {
+            violation(format!("{} is not of type {}", shown(), types.join(" or ")));
+        }
+        if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+            if !allowed.iter().any(|a| json_eq(a, value)) {
+                violation(format!("{} is not one of the allowed values", shown()));
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if !json_eq(expected, value) {
+                violation(format!("{} is not {}", shown(), expected));
+            }
+        }
+
+        match value {
+            Value::String(s) => check_string(schema, s, &mut violation),
+            Value::Number(_) => check_number(schema, value, &mut violation),
+            _ => {}
+        }
+        match value {
+            Value::Array(items) => self.check_array(object, items, path, depth, violations),
+            Value::Object(fields) => self.check_object(object, fields, path, depth, violations),
+            _ => {}
+        }
+        self.check_combinators(object, value, path, depth, violations);
+    }
+
+    fn is_valid(&self, schema: &Value, value: &Value, depth: usize) -> bool {
+        let mut violations = Vec::new();
+        self.check(schema, value, "", depth, &mut violations);
+        violations.is_empty()
+    }
+
+    fn check_array(
+        &self,
+        schema: &Map<String, Value>,
+        items: &[Value],
+        path: &str,
+        depth: usize,
+        violations: &mut Vec<String>,
+    ) {
+        let item_path = |i: usize| format!("{}/{}", path, i);
+        match schema.get("items") {
+            Some(Value::Array(tuple)) => {
+                for (i, (item, schema)) in items.iter().zip(tuple).enumerate() {
+                    self.check(schema, item, &item_path(i), depth, violations);
+                }
+                if let Some(additional) = schema.get("additionalItems") {
+                    for (i, item) in items.iter().enumerate().skip(tuple.len()) {
+                        self.check(additional, item, &item_path(i), depth, violations);
+                    }
+                }
+            }
+            Some(each) => {
+                for (i, item) in items.iter().enumerate() {
+                    self.check(each, item, &item_path(i), depth, violations);
+                }
+            }
+            None => {}
+        }
+
+        let len = items.len() as u64;
+        let shown = || Value::from(items.to_vec()).to_string();
+        if let Some(min) = schema
+            .get("minItems")
+            .and_then(Value::as_u64)
+            .filter(|m| len < *m)
+        {
+            violations.push(at(
+                path,
+                format!("{} has fewer than {} items", shown(), min),
+            ));
+        }
+        if let Some(max) = schema
+            .get("maxItems")
+            .and_then(Value::as_u64)
+            .filter(|m| len > *m)
+        {
+            violations.push(at(path, format!("{} has more than {} items", shown(), max)));
+        }
+        if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+            let repeated = items
+                .iter()
+                .enumerate()
+                .any(|(i, item)| items[..i].iter().any(|other| json_eq(other, item)));
+            if repeated {
+                violations.push(at(path, format!("{} has repeated items", shown())));
+            }
+        }
+        if let Some(contains) = schema.get("contains") {
+            if !items
+                .iter()
+                .any(|item| self.is_valid(contains, item, depth))
+            {
+                violations.push(at(
+                    path,
+                    format!("{} has no item that matches contains", shown()),
+                ));
+            }
+        }
+    }
+
+    fn check_object(
+        &self,
+        schema: &Map<String, Value>,
+        fields: &Map<String, Value>,
+        path: &str,
+        depth: usize,
+        violations: &mut Vec<String>,
+    ) {
+        let field_path =
+            |name: &str| format!("{}/{}", path, name.replace('~', "~0").replace('/', "~1"));
+        let properties = schema.get("properties").and_then(Value::as_object);
+        let patterns: Vec<(Regex, &Value)> = schema
+            .get("patternProperties")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+            .filter_map(|(pattern, schema)| Some((compiled(pattern)?, schema)))
+            .collect();
+        for (name, field) in fields {
+            let mut matched = false;
+            if let Some(schema) = properties.and_then(|p| p.get(name)) {
+                matched = true;
+                self.check(schema, field, &field_path(name), depth, violations);
+            }
+            for (pattern, schema) in &patterns {
+                if pattern.is_match(name) {
+                    matched = true;
+                    self.check(schema, field, &field_path(name), depth, violations);
+                }
+            }
+            if !matched {
+                if let Some(additional) = schema.get("additionalProperties") {
+                    if additional == &Value::Bool(false) {
+                        violations.push(at(path, format!("property {} is not allowed", name)));
+                    } else {
+                        self.check(additional, field, &field_path(name), depth, violations);
+                    }
+                }
+            }
+            if let Some(names) = schema.get("propertyNames") {
+                if !self.is_valid(names, &Value::from(name.as_str()), depth) {
+                    violations.push(at(path, format!("property name {} is not allowed", name)));
+                }
+            }
+        }
+
+        for name in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            if let Some(name) = name.as_str().filter(|n| !fields.contains_key(*n)) {
+                violations.push(at(path, format!("property {} is required", name)));
+            }
+        }
+        for (name, dependency) in schema
+            .get("dependencies")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+        {
+            if !fields.contains_key(name) {
+                continue;
+            }
+            match dependency {
+                Value::Array(required) => {
+                    for other in required.iter().filter_map(Value::as_str) {
+                        if !fields.contains_key(other) {
+                            violations.push(at(
+                                path,
+                                format!("property {} is required by {}", other, name),
+                            ));
+                        }
+                    }
+                }
+                schema => self.check(
+                    schema,
+                    &Value::Object(fields.clone()),
+                    path,
+                    depth,
+                    violations,
+                ),
+            }
+        }
+
+        let len = fields.len() as u64;
+        if let Some(min) = schema
+            .get("minProperties")
+            .and_then(Value::as_u64)
+            .filter(|m| len < *m)
+        {
+            violations.push(at(
+                path,
+                format!("object has fewer than {} properties", min),
+            ));
+        }
+        if let Some(max) = schema
+            .get("maxProperties")
+            .and_then(Value::as_u64)
+            .filter(|m| len > *m)
+        {
+            violations.push(at(path, format!("object has more than {} properties", max)));
+        }
+    }
+
+    fn check_combinators(
+        &self,
+        schema: &Map<String, Value>,
+        value: &Value,
+        path: &str,
+        depth: usize,
+        violations: &mut Vec<String>,
+    ) {
+        let shown = || value.to_string();
+        let schemas = |keyword: &str| schema.get(keyword).and_then(Value::as_array);
+        if let Some(all) = schemas("allOf") {
+            for schema in all {
+                self.check(schema, value, path, depth, violations);
+            }
+        }
+        if let Some(any) = schemas("anyOf") {
+            if !any.iter().any(|schema| self.is_valid(schema, value, depth)) {
+                violations.push(at(path, format!("{} matches none of anyOf", shown())));
+            }
+        }
+        if let Some(one) = schemas("oneOf") {
+            let matched = one
+                .iter()
+                .filter(|schema| self.is_valid(schema, value, depth))
+                .count();
+            if matched != 1 {
+                violations.push(at(
+                    path,
+                    format!("{} matches {} of oneOf, not exactly one", shown(), matched),
+                ));
+            }
+        }
+        if let Some(not) = schema.get("not") {
+            if self.is_valid(not, value, depth) {
+                violations.push(at(path, format!("{} matches not", shown())));
+            }
+        }
+        if let Some(condition) = schema.get("if") {
+            let branch = if self.is_valid(condition, value, depth) {
+                schema.get("then")
+            } else {
+                schema.get("else")
+            };
+            if let Some(branch) = branch {
+                self.check(branch, value, path, depth, violations);
+            }
+        }
+    }
+}
+
+/// The regex `pattern`, compiled the first time it is asked for, or `None` if it
+/// is not a valid one.
+fn compiled(pattern: &str) -> Option<Regex> {
+    static PATTERNS: OnceLock<Mutex<HashMap<String, Option<Regex>>>> = OnceLock::new();
+    let mut patterns = PATTERNS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if let Some(re) = patterns.get(pattern) {
+        return re.clone();
+    }
+    if patterns.len() >= MAX_CACHED_PATTERNS {
+        patterns.clear();
+    }
+    let re = Regex::new(pattern).ok();
+    patterns.insert(pattern.to_string(), re.clone());
+    re
+}
+
+/// Whether `a` and `b` are equal as JSON Schema compares them, where numbers are
+/// equal if their values are, so that `1` is `1.0`.
+fn json_eq(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => {
+            x == y || ((x.is_f64() || y.is_f64()) && x.as_f64() == y.as_f64())
+        }
+        (Value::Array(xs), Value::Array(ys)) => {
+            xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| json_eq(x, y))
+        }
+        (Value::Object(xs), Value::Object(ys)) => {
+            xs.len() == ys.len()
+                && xs
+                    .iter()
+                    .all(|(name, x)| ys.get(name).is_some_and(|y| json_eq(x, y)))
+        }
+        _ => a == b,
+    }
+}
+
+/// `message`, prefixed with where in the value it is about unless that is the top.
+fn at(path: &str, message: String) -> String {
+    if path.is_empty() {
+        message
+    } else {
+        format!("{}: {}", path, message)
+    }
+}
+
+fn check_string(schema: &Value, s: &str, violation: &mut impl FnMut(String)) {
+    let shown = || Value::from(s).to_string();
+    if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+        match compiled(pattern) {
+            Some(re) if re.is_match(s) => {}
+            Some(_) => violation(format!("{} does not match {}", shown(), pattern)),
+            None => violation(format!("pattern {} is not a valid regex", pattern)),
+        }
+    }
+    let length = s.chars().count() as u64;
+    if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+        if length < min {
+            violation(format!("{} is shorter than {} characters", shown(), min));
+        }
+    }
+    if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+        if length > max {
+            violation(format!("{} is longer than {} characters", shown(), max));
+        }
+    }
+    if let Some(format) = schema.get("format").and_then(Value::as_str) {
+        if !is_of_format(s, format) {
+            violation(format!("{} is not a valid {}", shown(), format));
+        }
+    }
+    match ContentEncoding::from_definition(schema) {
+        Ok(Some(encoding)) => {
+            if encoding.decode(s).is_err() {
+                let name = match encoding {
+                    ContentEncoding::Base64 => "base64",
+                };
+                violation(format!("{} is not valid {}", shown(), name));
+            }
+        }
+        Ok(None) => {}
+        Err(e) => violation(e.to_string()),
+    }
+}
+
+fn check_number(schema: &Value, value: &Value, violation: &mut impl FnMut(String)) {
+    let n = match value.as_f64() {
+        Some(n) => n,
+        None => return,
+    };
+    let shown = || value.to_string();
+    let bound = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+    if let Some(min) = bound("minimum").filter(|min| n < *min) {
+        violation(format!("{} is less than the minimum {}", shown(), min));
+    }
+    if let Some(max) = bound("maximum").filter(|max| n > *max) {
+        violation(format!("{} is greater than the maximum {}", shown(), max));
+    }
+    if let Some(min) = bound("exclusiveMinimum").filter(|min| n <= *min) {
+        violation(format!("{} is not greater than {}", shown(), min));
+    }
+    if let Some(max) = bound("exclusiveMaximum").filter(|max| n >= *max) {
+        violation(format!("{} is not less than {}", shown(), max));
+    }
+    if let Some(divisor) = bound("multipleOf").filter(|d| *d > 0.0) {
+        let quotient = n / divisor;
+        if (quotient - quotient.round()).abs() > 1e-9 {
+            violation(format!("{} is not a multiple of {}", shown(), divisor));
+        }
+    }
+}
+
+/// Whether `s` is of the draft-07 `format`. Formats the draft does not define are
+/// not checked.
+fn is_of_format(s: &str, format: &str) -> bool {
+    use chrono::{DateTime, NaiveDate};
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    match format {
+        "date-time" => DateTime::parse_from_rfc3339(s).is_ok(),
+        "date" => NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok(),
+        "time" => DateTime::parse_from_rfc3339(&format!("1970-01-01T{}", s)).is_ok(),
+        "email" | "idn-email" => match s.rsplit_once('@') {
+            Some((local, domain)) => {
+                !local.is_empty() && !domain.is_empty() && !s.contains(char::is_whitespace)
+            }
+            None => false,
+        },
+        "hostname" | "idn-hostname" => {
+            s.len() <= 253
+                && s.split('.').all(|label| {
+                    (1..=63).contains(&label.len())
+                        && !label.starts_with('-')
+                        && !label.ends_with('-')
+                        && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+                })
+        }
+        "ipv4" => s.parse::<Ipv4Addr>().is_ok(),
+        "ipv6" => s.parse::<Ipv6Addr>().is_ok(),
+        "uri" | "iri" => {
+            let scheme = s.split(':').next().unwrap_or_default();
+            s.contains(':')
+                && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+                && !s.contains(char::is_whitespace)
+        }
+        "uri-reference" | "iri-reference" | "uri-template" => !s.contains(char::is_whitespace),
+        "json-pointer" => is_json_pointer(s),
+        "relative-json-pointer" => {
+            let digits = s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let rest = &s[digits..];
+            digits > 0 && (rest == "#" || is_json_pointer(rest))
+        }
+        "regex" => Regex::new(s).is_ok(),
+        _ => true,
+    }
+}
+
+fn is_json_pointer(s: &str) -> bool {
+    (s.is_empty() || s.starts_with('/'))
+        && s.split('~')
+            .skip(1)
+            .all(|escape| escape.starts_with(['0', '1']))
+}
+
+/// Whether `value` is of the JSON Schema type `kind`.
+fn is_of_type(value: &Value, kind: &str) -> bool {
+    match kind {
+        "string" => value.is_string(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_check_value() {
+        let port = json!({ "type": "integer", "minimum": 1, "exclusiveMaximum": 65536 });
+        assert!(check_value(None, &port, &json!(8080)).is_empty());
+        assert!(check_value(None, &port, &json!(8080.0)).is_empty());
+        assert_eq!(
+            check_value(None, &port, &json!(65536)),
+            vec!["65536 is not less than 65536"]
+        );
+        assert_eq!(
+            check_value(None, &port, &json!("80")),
+            vec!["\"80\" is not of type integer"]
+        );
+
+        let level = json!({ "type": ["string", "null"], "enum": ["debug", "info", null] });
+        assert!(check_value(None, &level, &Value::Null).is_empty());
+        assert_eq!(
+            check_value(None, &level, &json!("trace")),
+            vec!["\"trace\" is not one of the allowed values"]
+        );
+
+        let name = json!({ "type": "string", "pattern": "^[a-z]+$", "maxLength": 4 });
+        assert_eq!(
+            check_value(None, &name, &json!("Hello")),
+            vec![
+                "\"Hello\" does not match ^[a-z]+$",
+                "\"Hello\" is longer than 4 characters"
+            ]
+        );
+
+        let binary = json!({ "type": "string", "contentEncoding": "base64" });
+        assert!(check_value(None, &binary, &json!("aGVsbG8=")).is_empty());
+        assert_eq!(
+            check_value(None, &binary, &json!("not base64!")),
+            vec!["\"not base64!\" is not valid base64"]
+        );
+        let unknown = json!({ "contentEncoding": "quoted-printable" });
+        assert_eq!(
+            check_value(None, &unknown, &json!("x")),
+            vec!["unsupported contentEncoding quoted-printable"]
+        );
+    }
+
+    #[test]
+    fn test_check_value_draft07() {
+        let servers = json!({
+            "type": "array",
+            "minItems": 1,
+            "uniqueItems": true,
+            "items": {
+                "type": "object",
+                "required": ["host"],
+                "additionalProperties": false,
+                "properties": {
+                    "host": { "type": "string", "format": "hostname" },
+                    "port": { "type": "integer", "maximum": 65535 },
+                    "tls": { "type": "boolean" }
+                },
+                "dependencies": { "tls": ["port"] }
+            }
+        });
+        let valid = json!([{ "host": "db.example.com", "port": 5432, "tls": true }]);
+        assert!(check_value(None, &servers, &valid).is_empty());
+        assert_eq!(
+            check_value(
+                None,
+                &servers,
+                &json!([
+                    { "host": "-db-", "port": 70000 },
+                    { "tls": true, "user": "admin" }
+                ])
+            ),
+            vec![
+                "/0/host: \"-db-\" is not a valid hostname",
+                "/0/port: 70000 is greater than the maximum 65535",
+                "/1: property user is not allowed",
+                "/1: property host is required",
+                "/1: property port is required by tls",
+            ]
+        );
+        assert_eq!(
+            check_value(None, &servers, &json!([])),
+            vec!["[] has fewer than 1 items"]
+        );
+
+        let endpoint = json!({
+            "oneOf": [
+                { "type": "string", "format": "ipv4" },
+                { "type": "string", "format": "uri" }
+            ]
+        });
+        assert!(check_value(None, &endpoint, &json!("10.0.0.1")).is_empty());
+        assert!(check_value(None, &endpoint, &json!("https://example.com")).is_empty());
+        assert_eq!(
+            check_value(None, &endpoint, &json!("not an endpoint")),
+            vec!["\"not an endpoint\" matches 0 of oneOf, not exactly one"]
+        );
+
+        let replicas = json!({
+            "anyOf": [{ "type": "null" }, { "type": "integer", "multipleOf": 2 }],
+            "not": { "const": 0 }
+        });
+        assert!(check_value(None, &replicas, &json!(4)).is_empty());
+        assert_eq!(
+            check_value(None, &replicas, &json!(3)),
+            vec!["3 matches none of anyOf"]
+        );
+        assert_eq!(
+            check_value(None, &replicas, &json!(0)),
+            vec!["0 matches not"]
+        );
+
+        let conditional = json!({
+            "if": { "properties": { "kind": { "const": "cron" } } },
+            "then": { "required": ["schedule"] },
+            "else": { "maxProperties": 1 }
+        });
+        assert!(check_value(None, &conditional, &json!({ "kind": "once" })).is_empty());
+        assert_eq!(
+            check_value(None, &conditional, &json!({ "kind": "cron" })),
+            vec!["property schedule is required"]
+        );
+
+        for (format, valid, invalid) in [
+            ("date-time", "2024-01-02T03:04:05Z", "2024-01-02"),
+            ("date", "2024-01-02", "01/02/2024"),
+            ("email", "ops@example.com", "ops"),
+            ("ipv6", "::1", "10.0.0.1"),
+            ("json-pointer", "/a~1b/0", "a/b"),
+            ("regex", "^[a-z]+$", "(unclosed"),
+        ] {
+            let schema = json!({ "format": format });
+            assert!(
+                check_value(None, &schema, &json!(valid)).is_empty(),
+                "{}",
+                format
+            );
+            assert_eq!(
+                check_value(None, &schema, &json!(invalid)).len(),
+                1,
+                "{}",
+                format
+            );
+        }
+        assert!(check_value(None, &json!({ "format": "color" }), &json!("teal")).is_empty());
+        assert_eq!(
+            check_value(None, &json!(false), &json!(1)),
+            vec!["1 is not allowed"]
+        );
+    }
+
+    #[test]
+    fn test_check_value_refs() {
+        let definitions: Definitions = serde_json::from_value(json!({
+            "port": { "type": "integer", "maximum": 65535 },
+            "http-port": { "$ref": "#/definitions/port", "minimum": 1024 },
+            "server": {
+                "type": "object",
+                "properties": { "port": { "$ref": "port" } }
+            },
+            "a/b": { "enum": ["a"] },
+            "loop": { "$ref": "#/definitions/loop" }
+        }))
+        .unwrap();
+        let check =
+            |definition: Value, value: Value| check_value(Some(&definitions), &definition, &value);
+
+        assert!(check(json!({ "$ref": "#/definitions/http-port" }), json!(8080)).is_empty());
+        assert_eq!(
+            check(json!({ "$ref": "#/definitions/http-port" }), json!(80)),
+            vec!["80 is less than the minimum 1024"]
+        );
+        assert_eq!(
+            check(json!({ "$ref": "#/definitions/http-port" }), json!("80")),
+            vec!["\"80\" is not of type integer"]
+        );
+        assert_eq!(
+            check(
+                json!({ "$ref": "#/definitions/server/properties/port" }),
+                json!(70000)
+            ),
+            vec!["70000 is greater than the maximum 65535"]
+        );
+        assert!(check(json!({ "$ref": "#/definitions/a~1b" }), json!("a")).is_empty());
+        assert_eq!(
+            check(json!({ "$ref": "#/definitions/missing" }), json!(1)),
+            vec!["$ref #/definitions/missing does not resolve"]
+        );
+        assert_eq!(
+            check(json!({ "$ref": "https://example.com/port.json" }), json!(1)),
+            vec!["$ref https://example.com/port.json does not resolve"]
+        );
+        assert_eq!(
+            check(json!({ "$ref": "loop" }), json!(1)),
+            vec!["$ref #/definitions/loop is circular"]
+        );
+
+        let http_port = json!({ "$ref": "http-port" });
+        assert_eq!(
+            resolve(Some(&definitions), &http_port),
+            &definitions["port"]
+        );
+    }
+
+    /// Cases from the draft-07 tests of the JSON Schema test suite.
+    #[test]
+    fn test_check_value_conformance() {
+        let cases = [
+            // type
+            (json!({ "type": "integer" }), json!(1.0), true),
+            (json!({ "type": "integer" }), json!(1.1), false),
+            (json!({ "type": "integer" }), json!("1"), false),
+            (json!({ "type": "number" }), json!(1), true),
+            (json!({ "type": "string" }), json!(""), true),
+            (json!({ "type": "null" }), json!(0), false),
+            (json!({ "type": "boolean" }), json!(0), false),
+            (json!({ "type": ["integer", "string"] }), json!(1.5), false),
+            // enum and const
+            (json!({ "enum": [1, 2, 3] }), json!(4), false),
+            (json!({ "enum": [false] }), json!(0), false),
+            (json!({ "enum": [[1]] }), json!([1.0]), true),
+            (json!({ "const": 1 }), json!(1.0), true),
+            (json!({ "const": { "a": false } }), json!({ "a": 0 }), false),
+            (json!({ "const": null }), json!(0), false),
+            // numbers
+            (json!({ "minimum": 1.1 }), json!(1.1), true),
+            (json!({ "minimum": -2 }), json!(-2.0001), false),
+            (json!({ "minimum": 1.1 }), json!("x"), true),
+            (json!({ "exclusiveMinimum": 1.1 }), json!(1.1), false),
+            (json!({ "maximum": 3.0 }), json!(3), true),
+            (json!({ "exclusiveMaximum": 3.0 }), json!(3.0), false),
+            (json!({ "multipleOf": 2 }), json!(10), true),
+            (json!({ "multipleOf": 2 }), json!(7), false),
+            (json!({ "multipleOf": 0.0001 }), json!(0.0075), true),
+            (json!({ "multipleOf": 0.0001 }), json!(0.00751), false),
+            // strings
+            (json!({ "minLength": 2 }), json!("\u{1f4a9}"), false),
+            (json!({ "maxLength": 2 }), json!("\u{1f4a9}\u{1f4a9}"), true),
+            (json!({ "maxLength": 2 }), json!(100), true),
+            (json!({ "pattern": "a+" }), json!("xxaayy"), true),
+            (json!({ "pattern": "^a*$" }), json!("abc"), false),
+            (json!({ "pattern": "^a*$" }), json!(true), true),
+            // arrays
+            (
+                json!({ "items": { "type": "integer" } }),
+                json!([1, "x"]),
+                false,
+            ),
+            (
+                json!({ "items": { "type": "integer" } }),
+                json!({ "0": "x" }),
+                true,
+            ),
+            (json!({ "items": false }), json!([1]), false),
+            (json!({ "items": false }), json!([]), true),
+            (
+                json!({ "items": [{}, {}], "additionalItems": false }),
+                json!([1, 2, 3]),
+                false,
+            ),
+            (
+                json!({ "items": {}, "additionalItems": false }),
+                json!([1, 2, 3]),
+                true,
+            ),
+            (json!({ "additionalItems": false }), json!([1, 2, 3]), true),
+            (json!({ "minItems": 1 }), json!([]), false),
+            (json!({ "maxItems": 2 }), json!([1, 2, 3]), false),
+            (json!({ "uniqueItems": true }), json!([1, 1.0]), false),
+            (
+                json!({ "uniqueItems": true }),
+                json!([{ "a": 1 }, { "a": 2 }]),
+                true,
+            ),
+            (json!({ "uniqueItems": true }), json!([[1], [true]]), true),
+            (json!({ "uniqueItems": false }), json!([1, 1]), true),
+            (
+                json!({ "contains": { "minimum": 5 } }),
+                json!([2, 3]),
+                false,
+            ),
+            (json!({ "contains": { "minimum": 5 } }), json!([2, 5]), true),
+            (json!({ "contains": { "minimum": 5 } }), json!([]), false),
+            (json!({ "contains": false }), json!("x"), true),
+            // objects
+            (
+                json!({ "properties": { "a": { "type": "integer" } } }),
+                json!({ "a": "1" }),
+                false,
+            ),
+            (json!({ "properties": { "a": false } }), json!({}), true),
+            (
+                json!({ "properties": { "a": false } }),
+                json!({ "a": 1 }),
+                false,
+            ),
+            (
+                json!({ "patternProperties": { "f.*o": { "type": "integer" } } }),
+                json!({ "foo": 1, "foooooo": "x" }),
+                false,
+            ),
+            (
+                json!({
+                    "properties": { "foo": {} },
+                    "patternProperties": { "^v": {} },
+                    "additionalProperties": false
+                }),
+                json!({ "foo": 1, "vroom": 2 }),
+                true,
+            ),
+            (
+                json!({ "properties": { "foo": {} }, "additionalProperties": false }),
+                json!({ "foo": 1, "quux": 2 }),
+                false,
+            ),
+            (
+                json!({ "additionalProperties": { "type": "boolean" } }),
+                json!({ "foo": 1 }),
+                false,
+            ),
+            (json!({ "required": ["foo"] }), json!({ "bar": 1 }), false),
+            (json!({ "required": ["foo"] }), json!([]), true),
+            (json!({ "minProperties": 1 }), json!({}), false),
+            (
+                json!({ "maxProperties": 1 }),
+                json!({ "a": 1, "b": 2 }),
+                false,
+            ),
+            (
+                json!({ "propertyNames": { "maxLength": 3 } }),
+                json!({ "abcd": 1 }),
+                false,
+            ),
+            (json!({ "propertyNames": false }), json!({}), true),
+            (
+                json!({ "dependencies": { "bar": ["foo"] } }),
+                json!({ "bar": 2 }),
+                false,
+            ),
+            (
+                json!({ "dependencies": { "bar": ["foo"] } }),
+                json!({ "foo": 1 }),
+                true,
+            ),
+            (
+                json!({ "dependencies": { "bar": false } }),
+                json!({ "bar": 2 }),
+                false,
+            ),
+            (
+                json!({ "dependencies": { "bar": { "required": ["foo"] } } }),
+                json!({ "bar": 2 }),
+                false,
+            ),
+            // combinators
+            (
+                json!({ "allOf": [{ "type": "integer" }, { "minimum": 2 }] }),
+                json!(1),
+                false,
+            ),
+            (json!({ "allOf": [true, false] }), json!(1), false),
+            (
+                json!({ "anyOf": [{ "type": "integer" }, { "minimum": 2 }] }),
+                json!(1.5),
+                false,
+            ),
+            (
+                json!({ "anyOf": [{ "type": "integer" }, { "minimum": 2 }] }),
+                json!(2.5),
+                true,
+            ),
+            (
+                json!({ "oneOf": [{ "type": "integer" }, { "minimum": 2 }] }),
+                json!(3),
+                false,
+            ),
+            (json!({ "oneOf": [true, false] }), json!(1), true),
+            (json!({ "oneOf": [true, true] }), json!(1), false),
+            (json!({ "not": { "type": "integer" } }), json!(1), false),
+            (json!({ "not": false }), json!(1), true),
+            (json!({ "if": { "const": 0 } }), json!(1), true),
+            (json!({ "then": { "const": 0 } }), json!(1), true),
+            (
+                json!({ "if": { "exclusiveMaximum": 0 }, "then": { "minimum": -10 } }),
+                json!(-100),
+                false,
+            ),
+            (
+                json!({ "if": { "exclusiveMaximum": 0 }, "else": { "multipleOf": 2 } }),
+                json!(3),
+                false,
+            ),
+            // boolean schemas
+            (json!(true), json!("anything"), true),
+            (json!(false), json!(null), false),
+            // formats
+            (
+                json!({ "format": "date-time" }),
+                json!("1963-06-19T08:30:06.283185Z"),
+                true,
+            ),
+            (
+                json!({ "format": "date-time" }),
+                json!("06/19/1963 08:30:06 PST"),
+                false,
+            ),
+            (json!({ "format": "date" }), json!("2020-02-30"), false),
+            (json!({ "format": "time" }), json!("08:30:06Z"), true),
+            (json!({ "format": "ipv4" }), json!("127.0.0.0.1"), false),
+            (json!({ "format": "ipv4" }), json!("256.256.256.256"), false),
+            (json!({ "format": "ipv6" }), json!("12345::"), false),
+            (
+                json!({ "format": "hostname" }),
+                json!("www.example.com"),
+                true,
+            ),
+            (
+                json!({ "format": "hostname" }),
+                json!("-a-host-name"),
+                false,
+            ),
+            (
+                json!({ "format": "hostname" }),
+                json!("not_a_valid_host_name"),
+                false,
+            ),
+            (
+                json!({ "format": "uri" }),
+                json!("http://foo.bar/?baz=qux#quux"),
+                true,
+            ),
+            (
+                json!({ "format": "uri" }),
+                json!("//foo.bar/?baz=qux#quux"),
+                false,
+            ),
+            (
+                json!({ "format": "uri" }),
+                json!("http:// shouldfail.com"),
+                false,
+            ),
+            (
+                json!({ "format": "email" }),
+                json!("joe.bloggs@example.com"),
+                true,
+            ),
+            (json!({ "format": "email" }), json!("2962"), false),
+            (
+                json!({ "format": "json-pointer" }),
+                json!("/foo/bar~0/baz~1/%a"),
+                true,
+            ),
+            (
+                json!({ "format": "json-pointer" }),
+                json!("/foo/bar~"),
+                false,
+            ),
+            (
+                json!({ "format": "relative-json-pointer" }),
+                json!("0#"),
+                true,
+            ),
+            (
+                json!({ "format": "relative-json-pointer" }),
+                json!("/foo/bar"),
+                false,
+            ),
+            (json!({ "format": "regex" }), json!("^(abc]"), false),
+            (json!({ "format": "ipv4" }), json!(12), true),
+        ];
+        for (schema, value, valid) in cases {
+            let violations = check_value(None, &schema, &value);
+            assert_eq!(
+                violations.is_empty(),
+                valid,
+                "{} against {}: {:?}",
+                value,
+                schema,
+                violations
+            );
+        }
+    }
+
+    /// Where the checks depart from the draft, as the module documents.
+    #[test]
+    fn test_check_value_deviations() {
+        let definitions: Definitions = serde_json::from_value(json!({
+            "reallyMax": { "maxItems": 2 }
+        }))
+        .unwrap();
+        let beside = json!({ "$ref": "#/definitions/reallyMax", "maxItems": 1 });
+        assert_eq!(
+            check_value(Some(&definitions), &beside, &json!([1, 2])),
+            vec!["[1,2] has more than 1 items"]
+        );
+        let bare = json!({ "$ref": "reallyMax" });
+        assert_eq!(
+            check_value(Some(&definitions), &bare, &json!([1, 2, 3])),
+            vec!["[1,2,3] has more than 2 items"]
+        );
+
+        for (format, loose) in [
+            ("uri", "http://exa%mple.com"),
+            ("uri-reference", r"\\WINDOWS\fileshare"),
+            ("email", "a@b@example.com"),
+            ("hostname", "b\u{fc}cher.example"),
+        ] {
+            let schema = json!({ "format": format });
+            assert!(
+                check_value(None, &schema, &json!(loose)).is_empty(),
+                "{}",
+                format
+            );
+        }
+    }
+}
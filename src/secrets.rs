@@ -0,0 +1,133 @@
+//! Pluggable secret stores used when resolving credential and parameter sources.
+//!
+//! A `SecretResolver` holds a set of named `SecretSource`s. Credential and parameter
+//! sources refer to a secret by source name and key, and the resolver dispatches the
+//! lookup to the matching store. The `keychain` and `vault` sources are registered by
+//! default when their features are enabled, and external plugins can be added with
+//! `SecretResolver::with_plugins`.
+use crate::credentialset::ResolveError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[cfg(feature = "aws")]
+pub mod aws;
+#[cfg(feature = "azure")]
+pub mod azure;
+pub mod plugin;
+
+/// SecretSource looks up secret values from an external secret store.
+pub trait SecretSource {
+    /// Fetch the secret identified by `key`.
+    ///
+    /// The format of the key is defined by the implementation.
+    fn get(&self, key: &str) -> Result<String, ResolveError>;
+}
+
+/// SecretRef names a secret held by one of the sources registered on a `SecretResolver`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct SecretRef {
+    /// The name the source was registered under (e.g. `aws`, `azure`)
+    pub source: String,
+    /// The key passed to the source
+    pub key: String,
+}
+
+/// SecretResolver dispatches secret lookups to registered sources by name.
+pub struct SecretResolver {
+    sources: BTreeMap<String, Box<dyn SecretSource>>,
+}
+
+impl SecretResolver {
+    /// Create a resolver with no sources registered.
+    pub fn empty() -> Self {
+        SecretResolver {
+            sources: BTreeMap::new(),
+        }
+    }
+
+    /// Register a source under the given name, replacing any previous source with that name.
+    pub fn with_source<S: SecretSource + 'static>(mut self, name: &str, source: S) -> Self {
+        self.sources.insert(name.to_string(), Box::new(source));
+        self
+    }
+
+    /// Look up `key` in the source registered as `source`.
+    pub fn get(&self, source: &str, key: &str) -> Result<String, ResolveError> {
+        self.sources
+            .get(source)
+            .ok_or_else(|| ResolveError::UnknownSecretSource(source.to_string()))?
+            .get(key)
+    }
+}
+
+impl Default for SecretResolver {
+    /// A resolver with the built-in sources enabled by crate features.
+    fn default() -> Self {
+        #[allow(unused_mut)]
+        let mut resolver = SecretResolver::empty();
+        #[cfg(feature = "keychain")]
+        {
+            resolver = resolver.with_source("keychain", Keychain::default());
+        }
+        #[cfg(feature = "vault")]
+        {
+            resolver = resolver.with_source("vault", crate::vault::VaultFromEnv);
+        }
+        resolver
+    }
+}
+
+/// Keychain reads secrets from the OS secret store (macOS Keychain, Windows Credential
+/// Manager, or Secret Service on Linux).
+///
+/// The key is the account name of the entry under `service`.
+#[cfg(feature = "keychain")]
+pub struct Keychain {
+    pub service: String,
+}
+
+#[cfg(feature = "keychain")]
+impl Default for Keychain {
+    fn default() -> Self {
+        Keychain {
+            service: crate::credentialset::KEYCHAIN_SERVICE.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "keychain")]
+impl SecretSource for Keychain {
+    fn get(&self, key: &str) -> Result<String, ResolveError> {
+        keyring::Entry::new(&self.service, key)
+            .and_then(|e| e.get_password())
+            .map_err(|e| ResolveError::secret("keychain", key, e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Fixed(&'static str);
+
+    impl SecretSource for Fixed {
+        fn get(&self, key: &str) -> Result<String, ResolveError> {
+            Ok(format!("{}:{}", self.0, key))
+        }
+    }
+
+    #[test]
+    fn test_resolver_dispatch() {
+        let resolver = SecretResolver::empty()
+            .with_source("one", Fixed("first"))
+            .with_source("two", Fixed("second"));
+
+        assert_eq!(resolver.get("two", "db").unwrap(), "second:db");
+        match resolver.get("three", "db") {
+            Err(ResolveError::UnknownSecretSource(name)) => assert_eq!(name, "three"),
+            other => panic!("expected unknown source, got {:?}", other),
+        }
+    }
+}
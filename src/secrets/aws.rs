@@ -0,0 +1,171 @@
+//! AWS Secrets Manager secret source.
+use super::SecretSource;
+use crate::credentialset::ResolveError;
+use crate::proxy::ProxyConfig;
+use chrono::prelude::Utc;
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+const SERVICE: &str = "secretsmanager";
+
+/// AwsSecretsManager reads secrets with the Secrets Manager `GetSecretValue` API.
+///
+/// Keys are secret IDs (names or ARNs). A key of the form `<secret-id>#<field>` reads
+/// a single field out of a secret whose value is a JSON object.
+#[derive(Debug, Clone)]
+pub struct AwsSecretsManager {
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+    pub proxy: ProxyConfig,
+}
+
+impl AwsSecretsManager {
+    /// Build a client from `AWS_REGION` (or `AWS_DEFAULT_REGION`), `AWS_ACCESS_KEY_ID`,
+    /// `AWS_SECRET_ACCESS_KEY` and the optional `AWS_SESSION_TOKEN`, with the proxies
+    /// of `ProxyConfig::from_env`.
+    pub fn from_env() -> Result<Self, ResolveError> {
+        let var = |name: &str| {
+            std::env::var(name).map_err(|_| ResolveError::secret("aws", name, "not set"))
+        };
+        Ok(AwsSecretsManager {
+            region: var("AWS_REGION").or_else(|_| var("AWS_DEFAULT_REGION"))?,
+            access_key_id: var("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+            proxy: ProxyConfig::from_env(),
+        })
+    }
+
+    fn get_secret_value(&self, secret_id: &str) -> Result<Value, ureq::Error> {
+        let host = format!("{}.{}.amazonaws.com", SERVICE, self.region);
+        let body = serde_json::json!({ "SecretId": secret_id }).to_string();
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+        let mut headers = vec![
+            ("content-type", "application/x-amz-json-1.1".to_string()),
+            ("host", host.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        headers.push(("x-amz-target", "secretsmanager.GetSecretValue".to_string()));
+        let authorization = self.authorization(&amz_date, &headers, &body);
+
+        let url = format!("https://{}/", host);
+        let mut request = self.proxy.agent(&url)?.post(&url);
+        for (name, value) in headers.iter().filter(|(n, _)| *n != "host") {
+            request = request.header(*name, value);
+        }
+        request
+            .header("authorization", &authorization)
+            .send(body.as_bytes())?
+            .body_mut()
+            .read_json()
+    }
+
+    /// Compute the SigV4 `Authorization` header for a POST to `/`.
+    ///
+    /// `headers` must be lowercase and sorted by name.
+    fn authorization(&self, amz_date: &str, headers: &[(&str, String)], body: &str) -> String {
+        let date = &amz_date[..8];
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, SERVICE);
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(n, v)| format!("{}:{}\n", n, v.trim()))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(n, _)| *n)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "POST\n/\n\n{}\n{}\n{}",
+            canonical_headers,
+            signed_headers,
+            hex::encode(Sha256::digest(body.as_bytes()))
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key = signing_key(&self.secret_access_key, date, &self.region, SERVICE);
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id,
+            scope,
+            signed_headers,
+            hex::encode(hmac(&key, &string_to_sign))
+        )
+    }
+}
+
+impl SecretSource for AwsSecretsManager {
+    fn get(&self, key: &str) -> Result<String, ResolveError> {
+        let (secret_id, field) = match key.rfind('#') {
+            Some(i) => (&key[..i], Some(&key[i + 1..])),
+            None => (key, None),
+        };
+        let response = self
+            .get_secret_value(secret_id)
+            .map_err(|e| ResolveError::secret("aws", secret_id, e))?;
+        let secret = response
+            .get("SecretString")
+            .and_then(Value::as_str)
+            .ok_or_else(|| ResolveError::secret("aws", secret_id, "secret has no SecretString"))?;
+        match field {
+            None => Ok(secret.to_string()),
+            Some(field) => {
+                let object: Value = serde_json::from_str(secret)
+                    .map_err(|e| ResolveError::secret("aws", secret_id, e))?;
+                match object.get(field) {
+                    Some(Value::String(s)) => Ok(s.clone()),
+                    Some(other) => Ok(other.to_string()),
+                    None => Err(ResolveError::secret(
+                        "aws",
+                        secret_id,
+                        format!("no field {} in secret", field),
+                    )),
+                }
+            }
+        }
+    }
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let k_date = hmac(format!("AWS4{}", secret).as_bytes(), date);
+    let k_region = hmac(&k_date, region);
+    let k_service = hmac(&k_region, service);
+    hmac(&k_service, "aws4_request")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_signing_key() {
+        // Example from the AWS Signature Version 4 documentation.
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex::encode(key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Azure Key Vault secret source.
+use super::SecretSource;
+use crate::credentialset::ResolveError;
+use crate::proxy::ProxyConfig;
+use serde_json::Value;
+
+const API_VERSION: &str = "7.4";
+
+/// AzureAuth describes how the client obtains an access token for Key Vault.
+#[derive(Debug, Clone)]
+pub enum AzureAuth {
+    /// A pre-issued bearer token with the `https://vault.azure.net` audience
+    Token(String),
+    /// A service principal, exchanged for a token on each lookup
+    ClientSecret {
+        tenant_id: String,
+        client_id: String,
+        client_secret: String,
+    },
+}
+
+/// AzureKeyVault reads secrets from an Azure Key Vault.
+///
+/// Keys are secret names, optionally followed by `/<version>`.
+#[derive(Debug, Clone)]
+pub struct AzureKeyVault {
+    /// The vault URL, e.g. `https://myvault.vault.azure.net`
+    pub vault_url: String,
+    pub auth: AzureAuth,
+    pub proxy: ProxyConfig,
+}
+
+impl AzureKeyVault {
+    /// Build a client for `vault_url` authenticating with the service principal in
+    /// `AZURE_TENANT_ID`, `AZURE_CLIENT_ID` and `AZURE_CLIENT_SECRET`, with the proxies
+    /// of `ProxyConfig::from_env`.
+    pub fn from_env(vault_url: &str) -> Result<Self, ResolveError> {
+        let var = |name: &str| {
+            std::env::var(name).map_err(|_| ResolveError::secret("azure", name, "not set"))
+        };
+        Ok(AzureKeyVault {
+            vault_url: vault_url.to_string(),
+            auth: AzureAuth::ClientSecret {
+                tenant_id: var("AZURE_TENANT_ID")?,
+                client_id: var("AZURE_CLIENT_ID")?,
+                client_secret: var("AZURE_CLIENT_SECRET")?,
+            },
+            proxy: ProxyConfig::from_env(),
+        })
+    }
+
+    /// The access token to read `key` with. A token endpoint that issues no token
+    /// fails with the `error` and `error_description` it answered with.
+    fn token(&self, key: &str) -> Result<String, ResolveError> {
+        match &self.auth {
+            AzureAuth::Token(token) => Ok(token.clone()),
+            AzureAuth::ClientSecret {
+                tenant_id,
+                client_id,
+                client_secret,
+            } => {
+                let url = format!(
+                    "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
+                    tenant_id
+                );
+                let body: Value = self
+                    .proxy
+                    .agent(&url)
+                    .and_then(|agent| {
+                        agent
+                            .post(&url)
+                            .config()
+                            .http_status_as_error(false)
+                            .build()
+                            .send_form([
+                                ("grant_type", "client_credentials"),
+                                ("client_id", client_id.as_str()),
+                                ("client_secret", client_secret.as_str()),
+                                ("scope", "https://vault.azure.net/.default"),
+                            ])?
+                            .body_mut()
+                            .read_json()
+                    })
+                    .map_err(|e| ResolveError::secret("azure", key, e))?;
+                access_token(&body).map_err(|e| ResolveError::secret("azure", key, e))
+            }
+        }
+    }
+}
+
+/// The access token of a token endpoint's response, or why it issued none.
+fn access_token(body: &Value) -> Result<String, String> {
+    if let Some(token) = body.get("access_token").and_then(Value::as_str) {
+        return Ok(token.to_string());
+    }
+    let field = |name: &str| body.get(name).and_then(Value::as_str);
+    Err(match (field("error"), field("error_description")) {
+        (Some(error), Some(description)) => format!(
+            "token endpoint issued no access token: {}: {}",
+            error, description
+        ),
+        (Some(error), None) => format!("token endpoint issued no access token: {}", error),
+        (None, _) => "token endpoint issued no access token".to_string(),
+    })
+}
+
+impl SecretSource for AzureKeyVault {
+    fn get(&self, key: &str) -> Result<String, ResolveError> {
+        let url = format!(
+            "{}/secrets/{}?api-version={}",
+            self.vault_url.trim_end_matches('/'),
+            key.trim_start_matches('/'),
+            API_VERSION
+        );
+        let token = self.token(key)?;
+        let body: Value = self
+            .proxy
+            .agent(&url)
+            .and_then(|agent| {
+                agent
+                    .get(&url)
+                    .header("Authorization", &format!("Bearer {}", token))
+                    .call()?
+                    .body_mut()
+                    .read_json()
+            })
+            .map_err(|e| ResolveError::secret("azure", key, e))?;
+        body.get("value")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| ResolveError::secret("azure", key, "response has no value"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_access_token() {
+        let body = serde_json::json!({ "token_type": "Bearer", "access_token": "abc" });
+        assert_eq!(access_token(&body).unwrap(), "abc");
+
+        let body = serde_json::json!({
+            "error": "invalid_client",
+            "error_description": "AADSTS7000215: Invalid client secret provided."
+        });
+        assert_eq!(
+            access_token(&body).unwrap_err(),
+            "token endpoint issued no access token: invalid_client: AADSTS7000215: Invalid client secret provided."
+        );
+        assert_eq!(
+            access_token(&serde_json::json!({})).unwrap_err(),
+            "token endpoint issued no access token"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! External secret source plugins.
+//!
+//! A plugin is any executable on `PATH` named `cnab-secrets-<name>`. For each lookup the
+//! plugin is started, sent a single JSON request on stdin, and expected to write a single
+//! JSON response to stdout before exiting:
+//!
+//! ```text
+//! request:  {"protocolVersion": 1, "operation": "get", "key": "prod/db"}
+//! response: {"value": "hunter2"}
+//!       or: {"error": "no such secret"}
+//! ```
+//!
+//! Plugins are registered on a `SecretResolver` under `<name>`, so a credential source of
+//! `secret: {source: "onepassword", key: "..."}` is served by `cnab-secrets-onepassword`.
+use super::{SecretResolver, SecretSource};
+use crate::credentialset::ResolveError;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The filename prefix that identifies secret source plugins.
+pub const PLUGIN_PREFIX: &str = "cnab-secrets-";
+
+/// The version of the plugin protocol spoken by this crate.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginRequest {
+    pub protocol_version: u32,
+    pub operation: String,
+    pub key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginResponse {
+    pub value: Option<String>,
+    pub error: Option<String>,
+}
+
+/// PluginSource is a `SecretSource` backed by an external plugin executable.
+#[derive(Debug, Clone)]
+pub struct PluginSource {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl PluginSource {
+    pub fn new<P: AsRef<Path>>(name: &str, path: P) -> Self {
+        PluginSource {
+            name: name.to_string(),
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl SecretSource for PluginSource {
+    fn get(&self, key: &str) -> Result<String, ResolveError> {
+        let error = |e: &dyn std::fmt::Display| ResolveError::secret(&self.name, key, e);
+        let request = serde_json::to_vec(&PluginRequest {
+            protocol_version: PROTOCOL_VERSION,
+            operation: "get".to_string(),
+            key: key.to_string(),
+        })
+        .map_err(|e| error(&e))?;
+
+        let mut child = Command::new(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| error(&e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&request).map_err(|e| error(&e))?;
+        }
+        let output = child.wait_with_output().map_err(|e| error(&e))?;
+
+        let response: PluginResponse = serde_json::from_slice(&output.stdout)
+            .map_err(|e| error(&format!("invalid plugin response: {}", e)))?;
+        match (response.value, response.error) {
+            (_, Some(message)) => Err(error(&message)),
+            (Some(value), None) if output.status.success() => Ok(value),
+            _ => Err(error(&format!("plugin exited with {}", output.status))),
+        }
+    }
+}
+
+/// Find the plugins on `PATH`.
+pub fn discover() -> Vec<PluginSource> {
+    match std::env::var_os("PATH") {
+        Some(path) => discover_in(std::env::split_paths(&path)),
+        None => Vec::new(),
+    }
+}
+
+/// Find the plugins in the given directories.
+///
+/// If two directories contain a plugin with the same name, the first one wins.
+pub fn discover_in<I, P>(dirs: I) -> Vec<PluginSource>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let mut plugins: Vec<PluginSource> = Vec::new();
+    for dir in dirs {
+        let entries = match std::fs::read_dir(dir.as_ref()) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        let mut found: Vec<PluginSource> = entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().map(|t| !t.is_dir()).unwrap_or(false))
+            .filter_map(|e| {
+                let file_name = e.file_name().into_string().ok()?;
+                let name = file_name.strip_prefix(PLUGIN_PREFIX)?;
+                let name = name.strip_suffix(".exe").unwrap_or(name);
+                Some(PluginSource::new(name, e.path()))
+            })
+            .filter(|p| !plugins.iter().any(|q| q.name == p.name))
+            .collect();
+        found.sort_by(|a, b| a.name.cmp(&b.name));
+        plugins.append(&mut found);
+    }
+    plugins
+}
+
+impl SecretResolver {
+    /// Register every plugin found on `PATH`. Built-in or previously registered sources
+    /// with the same name are replaced.
+    pub fn with_plugins(self) -> Self {
+        discover()
+            .into_iter()
+            .fold(self, |r, p| r.with_source(&p.name.clone(), p))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_plugin_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        crate::fixture::script(
+            dir.path(),
+            "cnab-secrets-echo",
+            "#!/bin/sh\nread req\ncase \"$req\" in\n  *missing*) echo '{\"error\":\"no such secret\"}' ;;\n  *) echo '{\"value\":\"s3cr3t\"}' ;;\nesac\n",
+        );
+
+        let plugins = discover_in(vec![dir.path()]);
+        assert_eq!(plugins.len(), 1);
+        assert_eq!(plugins[0].name, "echo");
+
+        let resolver = SecretResolver::empty().with_source("echo", plugins[0].clone());
+        assert_eq!(resolver.get("echo", "prod/db").unwrap(), "s3cr3t");
+        assert!(resolver.get("echo", "missing").is_err());
+    }
+}
@@ -0,0 +1,358 @@
+//! Detached Ed25519 signatures over bundles.
+//!
+//! A signature covers the canonical JSON of a bundle (`Bundle::to_canonical_json`), so
+//! it stays valid however the `bundle.json` it travels with is formatted. Keys and
+//! signatures are written in a minisign-style text format: a public key is the
+//! base64 of `Ed`, its 8-byte key ID and the 32-byte key, and a signature is an
+//! `untrusted comment:` line followed by the base64 of `Ed`, the key ID of the key
+//! that made it and the 64-byte signature.
+//!
+//! ```
+//! use libcnab::signing::{self, SigningKey};
+//! use libcnab::Bundle;
+//!
+//! let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+//! let key = SigningKey::generate();
+//! let signature = signing::sign(&bundle, &key);
+//!
+//! let trusted = [key.public_key()];
+//! let signer = signing::verify(&bundle, &signature.to_string().parse().unwrap(), &trusted);
+//! assert_eq!(signer.unwrap(), &trusted[0]);
+//! ```
+//!
+//! Signing keys can be kept in a `Keyring`, sealed with a passphrase.
+use crate::cnab::Bundle;
+use crate::error_code::{Coded, ErrorCode};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::{Signer, Verifier};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+mod keyring;
+pub use self::keyring::*;
+
+/// The algorithm tag that starts every encoded key and signature
+const ALGORITHM: &[u8; 2] = b"Ed";
+const KEY_ID_LENGTH: usize = 8;
+const UNTRUSTED_COMMENT: &str = "untrusted comment: ";
+
+/// SigningKey is the secret half of an Ed25519 key pair.
+#[derive(Clone)]
+pub struct SigningKey {
+    key: ed25519_dalek::SigningKey,
+}
+
+impl SigningKey {
+    /// A new key from the operating system's random number generator.
+    pub fn generate() -> Self {
+        let mut seed = [0; 32];
+        getrandom::getrandom(&mut seed).expect("the operating system has randomness");
+        Self::from_bytes(&seed)
+    }
+
+    /// The key with the 32-byte seed `seed`.
+    pub fn from_bytes(seed: &[u8; 32]) -> Self {
+        SigningKey {
+            key: ed25519_dalek::SigningKey::from_bytes(seed),
+        }
+    }
+
+    /// The 32-byte seed of the key, which must be kept secret.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.key.to_bytes()
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::new(self.key.verifying_key())
+    }
+
+    /// Sign `message`.
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        Signature {
+            key_id: self.public_key().key_id,
+            signature: self.key.sign(message),
+            comment: String::new(),
+        }
+    }
+}
+
+/// The secret is left out.
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningKey")
+            .field("key_id", &self.public_key().key_id())
+            .finish_non_exhaustive()
+    }
+}
+
+/// PublicKey verifies the signatures of a `SigningKey`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKey {
+    key: ed25519_dalek::VerifyingKey,
+    key_id: [u8; KEY_ID_LENGTH],
+}
+
+impl PublicKey {
+    fn new(key: ed25519_dalek::VerifyingKey) -> Self {
+        let digest = Sha256::digest(key.as_bytes());
+        let mut key_id = [0; KEY_ID_LENGTH];
+        key_id.copy_from_slice(&digest[..KEY_ID_LENGTH]);
+        PublicKey { key, key_id }
+    }
+
+    /// The key with the 32 bytes `key`.
+    pub fn from_bytes(key: &[u8; 32]) -> Result<Self, SignatureError> {
+        ed25519_dalek::VerifyingKey::from_bytes(key)
+            .map(Self::new)
+            .map_err(|e| SignatureError::InvalidKey(e.to_string()))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.key.to_bytes()
+    }
+
+    /// The ID signatures name the key by: the hex of the first 8 bytes of the key's
+    /// sha256 digest.
+    pub fn key_id(&self) -> String {
+        hex::encode(self.key_id)
+    }
+
+    /// Check that `signature` is this key's signature of `message`.
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> Result<(), SignatureError> {
+        if signature.key_id != self.key_id {
+            return Err(SignatureError::UntrustedKey(signature.key_id()));
+        }
+        self.key
+            .verify(message, &signature.signature)
+            .map_err(|_| SignatureError::BadSignature(signature.key_id()))
+    }
+}
+
+impl fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bytes = ALGORITHM.to_vec();
+        bytes.extend_from_slice(&self.key_id);
+        bytes.extend_from_slice(self.key.as_bytes());
+        f.write_str(&STANDARD.encode(bytes))
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = SignatureError;
+
+    /// Parse an encoded key. An `untrusted comment:` line before it is skipped, as
+    /// in minisign's `.pub` files.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SignatureError::InvalidKey(s.trim().to_string());
+        let bytes = decode(s, 32).ok_or_else(invalid)?;
+        let mut key = [0; 32];
+        key.copy_from_slice(&bytes[KEY_ID_LENGTH..]);
+        let key = PublicKey::from_bytes(&key)?;
+        if key.key_id[..] != bytes[..KEY_ID_LENGTH] {
+            return Err(invalid());
+        }
+        Ok(key)
+    }
+}
+
+/// Signature is a detached signature, made by the key with its key ID.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    key_id: [u8; KEY_ID_LENGTH],
+    signature: ed25519_dalek::Signature,
+    /// The comment written above the signature. It is not signed.
+    pub comment: String,
+}
+
+impl Signature {
+    /// The signature `bytes` by the key with the hex ID `key_id`.
+    pub fn from_bytes(key_id: &str, bytes: &[u8; 64]) -> Result<Self, SignatureError> {
+        let invalid = || SignatureError::InvalidSignature(key_id.to_string());
+        let id = hex::decode(key_id).map_err(|_| invalid())?;
+        if id.len() != KEY_ID_LENGTH {
+            return Err(invalid());
+        }
+        let mut key_id = [0; KEY_ID_LENGTH];
+        key_id.copy_from_slice(&id);
+        Ok(Signature {
+            key_id,
+            signature: ed25519_dalek::Signature::from_bytes(bytes),
+            comment: String::new(),
+        })
+    }
+
+    /// The ID of the key that made the signature.
+    pub fn key_id(&self) -> String {
+        hex::encode(self.key_id)
+    }
+
+    /// The 64 bytes of the signature, without its key ID.
+    pub fn to_bytes(&self) -> [u8; 64] {
+        self.signature.to_bytes()
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bytes = ALGORITHM.to_vec();
+        bytes.extend_from_slice(&self.key_id);
+        bytes.extend_from_slice(&self.signature.to_bytes());
+        let comment = if self.comment.is_empty() {
+            format!("signature from key {}", self.key_id())
+        } else {
+            self.comment.replace('\n', " ")
+        };
+        writeln!(f, "{}{}", UNTRUSTED_COMMENT, comment)?;
+        writeln!(f, "{}", STANDARD.encode(bytes))
+    }
+}
+
+impl FromStr for Signature {
+    type Err = SignatureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SignatureError::InvalidSignature(s.trim().to_string());
+        let bytes = decode(s, 64).ok_or_else(invalid)?;
+        let mut key_id = [0; KEY_ID_LENGTH];
+        key_id.copy_from_slice(&bytes[..KEY_ID_LENGTH]);
+        let signature =
+            ed25519_dalek::Signature::from_slice(&bytes[KEY_ID_LENGTH..]).map_err(|_| invalid())?;
+        let comment = s
+            .lines()
+            .find_map(|l| l.trim().strip_prefix(UNTRUSTED_COMMENT))
+            .unwrap_or_default()
+            .to_string();
+        Ok(Signature {
+            key_id,
+            signature,
+            comment,
+        })
+    }
+}
+
+/// The key ID and the `length` bytes after the algorithm tag of an encoded key or
+/// signature, which is the last line of `s` that is not a comment.
+fn decode(s: &str, length: usize) -> Option<Vec<u8>> {
+    let line = s
+        .lines()
+        .map(str::trim)
+        .rfind(|l| !l.is_empty() && !l.starts_with(UNTRUSTED_COMMENT))?;
+    let bytes = STANDARD.decode(line).ok()?;
+    let rest = bytes.strip_prefix(&ALGORITHM[..])?;
+    if rest.len() != KEY_ID_LENGTH + length {
+        return None;
+    }
+    Some(rest.to_vec())
+}
+
+/// Sign the canonical JSON of `bundle` with `key`.
+pub fn sign(bundle: &Bundle, key: &SigningKey) -> Signature {
+    let mut signature = key.sign(&bundle.to_canonical_json());
+    signature.comment = format!(
+        "signature of bundle {} {} from key {}",
+        bundle.name,
+        bundle.version,
+        signature.key_id()
+    );
+    signature
+}
+
+/// Check that `signature` is a signature of `bundle` by one of `trusted_keys`,
+/// returning the key that made it.
+pub fn verify<'a>(
+    bundle: &Bundle,
+    signature: &Signature,
+    trusted_keys: &'a [PublicKey],
+) -> Result<&'a PublicKey, SignatureError> {
+    let key = trusted_keys
+        .iter()
+        .find(|k| k.key_id == signature.key_id)
+        .ok_or_else(|| SignatureError::UntrustedKey(signature.key_id()))?;
+    key.verify(&bundle.to_canonical_json(), signature)?;
+    Ok(key)
+}
+
+/// SignatureError describes a failure to read or verify a signature.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignatureError {
+    /// A public key could not be parsed
+    InvalidKey(String),
+    /// A signature could not be parsed
+    InvalidSignature(String),
+    /// A signature was made by a key that is not trusted, named by its key ID
+    UntrustedKey(String),
+    /// A signature does not match what it was said to sign: the content was changed
+    /// since it was signed
+    BadSignature(String),
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::InvalidKey(k) => write!(f, "invalid public key {}", k),
+            SignatureError::InvalidSignature(s) => write!(f, "invalid signature {}", s),
+            SignatureError::UntrustedKey(id) => write!(f, "key {} is not trusted", id),
+            SignatureError::BadSignature(id) => {
+                write!(f, "signature from key {} does not verify", id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+impl Coded for SignatureError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            SignatureError::InvalidKey(_) => ErrorCode::SignatureInvalidKey,
+            SignatureError::InvalidSignature(_) => ErrorCode::SignatureInvalidSignature,
+            SignatureError::UntrustedKey(_) => ErrorCode::SignatureUntrustedKey,
+            SignatureError::BadSignature(_) => ErrorCode::SignatureBadSignature,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let public = key.public_key();
+        let signature = sign(&bundle, &key);
+        assert_eq!(signature.key_id(), public.key_id());
+        assert!(!format!("{:?}", key).contains("[7"));
+
+        let text = signature.to_string();
+        assert!(text.starts_with("untrusted comment: signature of bundle helloworld 0.1.2"));
+        let parsed: Signature = text.parse().unwrap();
+        assert_eq!(parsed, signature);
+        let key_text = public.to_string();
+        assert_eq!(key_text.parse::<PublicKey>().unwrap(), public);
+        let commented = format!("untrusted comment: a key\n{}\n", key_text);
+        assert_eq!(commented.parse::<PublicKey>().unwrap(), public);
+
+        let trusted = vec![SigningKey::generate().public_key(), public.clone()];
+        assert_eq!(verify(&bundle, &parsed, &trusted), Ok(&public));
+        match verify(&bundle, &parsed, &trusted[..1]) {
+            Err(SignatureError::UntrustedKey(id)) => assert_eq!(id, public.key_id()),
+            other => panic!("expected an untrusted key, got {:?}", other),
+        }
+        bundle.description = Some("changed".to_string());
+        match verify(&bundle, &parsed, &trusted) {
+            Err(SignatureError::BadSignature(_)) => {}
+            other => panic!("expected a bad signature, got {:?}", other),
+        }
+
+        let raw = Signature::from_bytes(&parsed.key_id(), &parsed.to_bytes()).unwrap();
+        assert_eq!(raw.signature, parsed.signature);
+        assert!(Signature::from_bytes("abcd", &parsed.to_bytes()).is_err());
+        assert!("not a signature".parse::<Signature>().is_err());
+        assert!(key_text.parse::<Signature>().is_err());
+        assert!(text.parse::<PublicKey>().is_err());
+    }
+}
@@ -0,0 +1,603 @@
+//! Storage for signing keys.
+//!
+//! A `Keyring` keeps signing keys by name, each one encrypted with a passphrase:
+//! the key's seed is sealed with XChaCha20-Poly1305 under a key derived from the
+//! passphrase with scrypt. The public key is stored in the clear next to it, so a
+//! keyring can be listed, and its keys trusted, without any passphrase.
+//! `FileKeyring` keeps one JSON document per key in a directory; `MemoryKeyring` is
+//! useful for tests and short-lived tools.
+//!
+//! ```
+//! use libcnab::signing::{self, FileKeyring, Keyring};
+//! use libcnab::Bundle;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let keyring = FileKeyring::new(dir.path());
+//! keyring.generate("release", "correct horse").unwrap();
+//!
+//! let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+//! let signature = keyring.sign(&bundle, "release", "correct horse").unwrap();
+//! let trusted = keyring.public_keys().unwrap();
+//! signing::verify(&bundle, &signature, &trusted).unwrap();
+//! ```
+use super::{PublicKey, Signature, SigningKey};
+use crate::cnab::Bundle;
+use crate::error_code::{Coded, ErrorCode};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// The scrypt cost that new keys are sealed with, as log2 of N.
+#[cfg(not(test))]
+const SCRYPT_LOG_N: u8 = 15;
+#[cfg(test)]
+const SCRYPT_LOG_N: u8 = 4;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+/// The highest scrypt costs a key may be sealed with. Keys are imported from
+/// documents that anyone can write, and unbounded costs would let one make opening
+/// it take any amount of memory and time. scrypt needs 128·r·N bytes, which is
+/// bounded as a whole, and runs it p times. New keys need 32 MiB and one pass.
+const MAX_SCRYPT_MEMORY: u64 = 256 * 1024 * 1024;
+const MAX_SCRYPT_P: u32 = 4;
+
+/// Whether opening a key sealed with these scrypt costs stays under the ceiling.
+fn scrypt_within_limits(log_n: u8, r: u32, p: u32) -> bool {
+    let memory = 1u64
+        .checked_shl(log_n.into())
+        .and_then(|n| n.checked_mul(128 * u64::from(r)));
+    matches!(memory, Some(m) if m <= MAX_SCRYPT_MEMORY) && p <= MAX_SCRYPT_P
+}
+
+/// EncryptedKey is a signing key sealed with a passphrase, with its public key.
+///
+/// It is written as a JSON document, which is also how keys are exported from one
+/// keyring and imported into another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncryptedKey {
+    public_key: PublicKey,
+    salt: Vec<u8>,
+    log_n: u8,
+    r: u32,
+    p: u32,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EncryptedKeyDocument {
+    public_key: String,
+    kdf: Kdf,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Kdf {
+    algorithm: String,
+    salt: String,
+    log_n: u8,
+    r: u32,
+    p: u32,
+}
+
+impl EncryptedKey {
+    /// Seal `key` with `passphrase`.
+    pub fn seal(key: &SigningKey, passphrase: &str) -> Self {
+        let mut salt = vec![0; 32];
+        let mut nonce = vec![0; 24];
+        getrandom::getrandom(&mut salt).expect("the operating system has randomness");
+        getrandom::getrandom(&mut nonce).expect("the operating system has randomness");
+        let public_key = key.public_key();
+        let cipher = cipher(passphrase, &salt, SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)
+            .expect("the scrypt parameters are valid");
+        let ciphertext = cipher
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &key.to_bytes(),
+                    aad: &public_key.to_bytes(),
+                },
+            )
+            .expect("the seed fits in a message");
+        EncryptedKey {
+            public_key,
+            salt,
+            log_n: SCRYPT_LOG_N,
+            r: SCRYPT_R,
+            p: SCRYPT_P,
+            nonce,
+            ciphertext,
+        }
+    }
+
+    /// The signing key, if `passphrase` is the passphrase it was sealed with.
+    pub fn open(&self, passphrase: &str) -> Result<SigningKey, KeyringError> {
+        let invalid = |m: &str| KeyringError::InvalidKey(m.to_string());
+        let cipher = cipher(passphrase, &self.salt, self.log_n, self.r, self.p)
+            .ok_or_else(|| invalid("invalid scrypt parameters"))?;
+        if self.nonce.len() != 24 {
+            return Err(invalid("invalid nonce"));
+        }
+        let seed = cipher
+            .decrypt(
+                XNonce::from_slice(&self.nonce),
+                Payload {
+                    msg: &self.ciphertext,
+                    aad: &self.public_key.to_bytes(),
+                },
+            )
+            .map_err(|_| KeyringError::WrongPassphrase)?;
+        let mut bytes = [0; 32];
+        if seed.len() != bytes.len() {
+            return Err(invalid("invalid seed"));
+        }
+        bytes.copy_from_slice(&seed);
+        let key = SigningKey::from_bytes(&bytes);
+        if key.public_key() != self.public_key {
+            return Err(invalid("the seed does not match the public key"));
+        }
+        Ok(key)
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+}
+
+/// The cipher keyed by `passphrase`, or `None` when the parameters are out of range.
+fn cipher(passphrase: &str, salt: &[u8], log_n: u8, r: u32, p: u32) -> Option<XChaCha20Poly1305> {
+    if !scrypt_within_limits(log_n, r, p) {
+        return None;
+    }
+    let params = scrypt::Params::new(log_n, r, p, 32).ok()?;
+    let mut key = [0; 32];
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key).ok()?;
+    Some(XChaCha20Poly1305::new(&key.into()))
+}
+
+impl fmt::Display for EncryptedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let document = EncryptedKeyDocument {
+            public_key: self.public_key.to_string(),
+            kdf: Kdf {
+                algorithm: "scrypt".to_string(),
+                salt: STANDARD.encode(&self.salt),
+                log_n: self.log_n,
+                r: self.r,
+                p: self.p,
+            },
+            nonce: STANDARD.encode(&self.nonce),
+            ciphertext: STANDARD.encode(&self.ciphertext),
+        };
+        let json = serde_json::to_string_pretty(&document).expect("keys serialize");
+        f.write_str(&json)
+    }
+}
+
+impl FromStr for EncryptedKey {
+    type Err = KeyringError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |m: String| KeyringError::InvalidKey(m);
+        let document: EncryptedKeyDocument =
+            serde_json::from_str(s).map_err(|e| invalid(e.to_string()))?;
+        if document.kdf.algorithm != "scrypt" {
+            return Err(invalid(format!(
+                "unsupported key derivation {}",
+                document.kdf.algorithm
+            )));
+        }
+        let kdf = &document.kdf;
+        if !scrypt_within_limits(kdf.log_n, kdf.r, kdf.p) {
+            return Err(invalid(format!(
+                "scrypt parameters logN {}, r {}, p {} are over the limits of {} MiB and {} passes",
+                kdf.log_n,
+                kdf.r,
+                kdf.p,
+                MAX_SCRYPT_MEMORY >> 20,
+                MAX_SCRYPT_P
+            )));
+        }
+        let decode = |field: &str, value: &str| {
+            STANDARD
+                .decode(value)
+                .map_err(|e| invalid(format!("{}: {}", field, e)))
+        };
+        Ok(EncryptedKey {
+            public_key: document
+                .public_key
+                .parse()
+                .map_err(|e: super::SignatureError| invalid(e.to_string()))?,
+            salt: decode("salt", &document.kdf.salt)?,
+            log_n: document.kdf.log_n,
+            r: document.kdf.r,
+            p: document.kdf.p,
+            nonce: decode("nonce", &document.nonce)?,
+            ciphertext: decode("ciphertext", &document.ciphertext)?,
+        })
+    }
+}
+
+/// Keyring saves and loads encrypted signing keys by name.
+pub trait Keyring {
+    /// The key named `name`, or `None` if there is none.
+    fn get(&self, name: &str) -> Result<Option<EncryptedKey>, KeyringError>;
+
+    /// Save `key` as `name`, replacing any key of that name.
+    fn insert(&self, name: &str, key: &EncryptedKey) -> Result<(), KeyringError>;
+
+    /// Remove the key named `name`. Removing a missing key is not an error.
+    fn remove(&self, name: &str) -> Result<(), KeyringError>;
+
+    /// The names of the keys, in sorted order.
+    fn names(&self) -> Result<Vec<String>, KeyringError>;
+
+    /// Generate a key named `name`, sealed with `passphrase`, and return its public
+    /// key. A key of that name is never replaced.
+    fn generate(&self, name: &str, passphrase: &str) -> Result<PublicKey, KeyringError> {
+        self.import(
+            name,
+            &EncryptedKey::seal(&SigningKey::generate(), passphrase),
+        )
+    }
+
+    /// Save `key`, such as one exported from another keyring, as `name` and return
+    /// its public key. A key of that name is never replaced.
+    fn import(&self, name: &str, key: &EncryptedKey) -> Result<PublicKey, KeyringError> {
+        if self.get(name)?.is_some() {
+            return Err(KeyringError::Exists(name.to_string()));
+        }
+        self.insert(name, key)?;
+        Ok(key.public_key().clone())
+    }
+
+    /// The key named `name`, still sealed, to import into another keyring.
+    fn export(&self, name: &str) -> Result<EncryptedKey, KeyringError> {
+        self.get(name)?
+            .ok_or_else(|| KeyringError::NotFound(name.to_string()))
+    }
+
+    /// The key named `name`, unsealed with `passphrase`.
+    fn signing_key(&self, name: &str, passphrase: &str) -> Result<SigningKey, KeyringError> {
+        self.export(name)?.open(passphrase)
+    }
+
+    /// The names and public keys of the keys, in the order of their names.
+    fn list(&self) -> Result<Vec<(String, PublicKey)>, KeyringError> {
+        let mut keys = Vec::new();
+        for name in self.names()? {
+            if let Some(key) = self.get(&name)? {
+                keys.push((name, key.public_key().clone()));
+            }
+        }
+        Ok(keys)
+    }
+
+    /// The public keys of the keys, for `signing::verify` to trust.
+    fn public_keys(&self) -> Result<Vec<PublicKey>, KeyringError> {
+        Ok(self.list()?.into_iter().map(|(_, key)| key).collect())
+    }
+
+    /// Sign `bundle` with the key named `name`, unsealed with `passphrase`.
+    fn sign(
+        &self,
+        bundle: &Bundle,
+        name: &str,
+        passphrase: &str,
+    ) -> Result<Signature, KeyringError> {
+        Ok(super::sign(bundle, &self.signing_key(name, passphrase)?))
+    }
+}
+
+/// FileKeyring stores each key as `<dir>/<name>.key`.
+#[derive(Debug, Clone)]
+pub struct FileKeyring {
+    dir: PathBuf,
+}
+
+impl FileKeyring {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        FileKeyring {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, KeyringError> {
+        let valid = !name.is_empty() && !name.starts_with('.') && !name.contains(&['/', '\\'][..]);
+        if !valid {
+            return Err(KeyringError::InvalidName(name.to_string()));
+        }
+        Ok(self.dir.join(format!("{}.key", name)))
+    }
+}
+
+impl Keyring for FileKeyring {
+    fn get(&self, name: &str) -> Result<Option<EncryptedKey>, KeyringError> {
+        match std::fs::read_to_string(self.path(name)?) {
+            Ok(text) => text.parse().map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn insert(&self, name: &str, key: &EncryptedKey) -> Result<(), KeyringError> {
+        let path = self.path(name)?;
+        create_private_dir(&self.dir)?;
+        // Write to a temporary file and rename it so a crash never leaves a partial key.
+        let partial = path.with_extension("key.tmp");
+        write_private(&partial, key.to_string().as_bytes())?;
+        std::fs::rename(&partial, &path)?;
+        Ok(())
+    }
+
+    fn remove(&self, name: &str) -> Result<(), KeyringError> {
+        match std::fs::remove_file(self.path(name)?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn names(&self) -> Result<Vec<String>, KeyringError> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            let name = entry?.file_name();
+            if let Some(name) = name.to_str().and_then(|n| n.strip_suffix(".key")) {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)
+}
+
+/// Write a file only its owner can read.
+fn write_private(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(content)
+}
+
+/// MemoryKeyring keeps keys in memory.
+#[derive(Debug, Default)]
+pub struct MemoryKeyring {
+    keys: Mutex<BTreeMap<String, EncryptedKey>>,
+}
+
+impl MemoryKeyring {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Keyring for MemoryKeyring {
+    fn get(&self, name: &str) -> Result<Option<EncryptedKey>, KeyringError> {
+        Ok(self.keys.lock().expect("lock poisoned").get(name).cloned())
+    }
+
+    fn insert(&self, name: &str, key: &EncryptedKey) -> Result<(), KeyringError> {
+        self.keys
+            .lock()
+            .expect("lock poisoned")
+            .insert(name.to_string(), key.clone());
+        Ok(())
+    }
+
+    fn remove(&self, name: &str) -> Result<(), KeyringError> {
+        self.keys.lock().expect("lock poisoned").remove(name);
+        Ok(())
+    }
+
+    fn names(&self) -> Result<Vec<String>, KeyringError> {
+        Ok(self
+            .keys
+            .lock()
+            .expect("lock poisoned")
+            .keys()
+            .cloned()
+            .collect())
+    }
+}
+
+/// KeyringError describes a failure to store or unseal a key.
+#[derive(Debug)]
+pub enum KeyringError {
+    /// The key name cannot be used as a key in this keyring
+    InvalidName(String),
+    /// There is no key of the name
+    NotFound(String),
+    /// There already is a key of the name
+    Exists(String),
+    /// A stored or imported key could not be parsed
+    InvalidKey(String),
+    /// The passphrase does not unseal the key
+    WrongPassphrase,
+    IoError(std::io::Error),
+}
+
+impl fmt::Display for KeyringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyringError::InvalidName(name) => write!(f, "{:?} is not a valid key name", name),
+            KeyringError::NotFound(name) => write!(f, "no key named {}", name),
+            KeyringError::Exists(name) => write!(f, "a key named {} already exists", name),
+            KeyringError::InvalidKey(e) => write!(f, "invalid key: {}", e),
+            KeyringError::WrongPassphrase => write!(f, "the passphrase does not unseal the key"),
+            KeyringError::IoError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for KeyringError {}
+
+impl Coded for KeyringError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            KeyringError::InvalidName(_) => ErrorCode::KeyringInvalidName,
+            KeyringError::NotFound(_) => ErrorCode::KeyringNotFound,
+            KeyringError::Exists(_) => ErrorCode::KeyringExists,
+            KeyringError::InvalidKey(_) => ErrorCode::KeyringInvalidKey,
+            KeyringError::WrongPassphrase => ErrorCode::KeyringWrongPassphrase,
+            KeyringError::IoError(_) => ErrorCode::Io,
+        }
+    }
+}
+
+impl From<std::io::Error> for KeyringError {
+    fn from(error: std::io::Error) -> Self {
+        KeyringError::IoError(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn exercise(keyring: &dyn Keyring) {
+        assert!(keyring.list().unwrap().is_empty());
+        let release = keyring.generate("release", "secret").unwrap();
+        match keyring.generate("release", "other") {
+            Err(KeyringError::Exists(name)) => assert_eq!(name, "release"),
+            other => panic!("expected an existing key, got {:?}", other),
+        }
+        let imported = SigningKey::from_bytes(&[3; 32]);
+        keyring
+            .import("ci", &EncryptedKey::seal(&imported, "ci-secret"))
+            .unwrap();
+        assert_eq!(
+            keyring.list().unwrap(),
+            vec![
+                ("ci".to_string(), imported.public_key()),
+                ("release".to_string(), release.clone())
+            ]
+        );
+
+        let key = keyring.signing_key("release", "secret").unwrap();
+        assert_eq!(key.public_key(), release);
+        assert!(matches!(
+            keyring.signing_key("release", "wrong"),
+            Err(KeyringError::WrongPassphrase)
+        ));
+        assert!(matches!(
+            keyring.signing_key("missing", "secret"),
+            Err(KeyringError::NotFound(_))
+        ));
+
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let signature = keyring.sign(&bundle, "ci", "ci-secret").unwrap();
+        let trusted = keyring.public_keys().unwrap();
+        assert_eq!(
+            super::super::verify(&bundle, &signature, &trusted),
+            Ok(&imported.public_key())
+        );
+
+        // Exported keys stay sealed.
+        let exported = keyring.export("ci").unwrap().to_string();
+        assert!(!exported.contains(&STANDARD.encode([3; 32])));
+        let other = MemoryKeyring::new();
+        other.import("ci", &exported.parse().unwrap()).unwrap();
+        assert_eq!(
+            other.signing_key("ci", "ci-secret").unwrap().to_bytes(),
+            [3; 32]
+        );
+
+        keyring.remove("ci").unwrap();
+        keyring.remove("ci").unwrap();
+        assert_eq!(keyring.names().unwrap(), vec!["release"]);
+    }
+
+    #[test]
+    fn test_keyrings() {
+        exercise(&MemoryKeyring::new());
+
+        let dir = tempfile::tempdir().unwrap();
+        let keyring = FileKeyring::new(dir.path());
+        exercise(&keyring);
+        assert!(matches!(
+            keyring.get("../etc"),
+            Err(KeyringError::InvalidName(_))
+        ));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.path().join("release.key"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // A tampered public key no longer unseals.
+        let mut sealed = EncryptedKey::seal(&SigningKey::from_bytes(&[3; 32]), "secret");
+        sealed.public_key = SigningKey::from_bytes(&[4; 32]).public_key();
+        assert!(matches!(
+            sealed.open("secret"),
+            Err(KeyringError::WrongPassphrase)
+        ));
+    }
+
+    #[test]
+    fn test_scrypt_limits() {
+        let sealed = EncryptedKey::seal(&SigningKey::from_bytes(&[3; 32]), "secret");
+        let document: serde_json::Value = serde_json::from_str(&sealed.to_string()).unwrap();
+        let with = |log_n: u8, r: u32, p: u32| {
+            let mut document = document.clone();
+            document["kdf"]["logN"] = log_n.into();
+            document["kdf"]["r"] = r.into();
+            document["kdf"]["p"] = p.into();
+            document.to_string()
+        };
+        // 128·8·2¹⁸ is the 256 MiB ceiling itself.
+        with(18, 8, 4).parse::<EncryptedKey>().unwrap();
+        for (log_n, r, p) in [(19, 8, 1), (18, 9, 1), (18, 8, 5), (15, 32, 16), (64, 1, 1)] {
+            match with(log_n, r, p).parse::<EncryptedKey>() {
+                Err(KeyringError::InvalidKey(message)) => {
+                    assert!(message.contains("over the limits"), "{}", message)
+                }
+                other => panic!(
+                    "expected logN {}, r {}, p {} to be refused, got {:?}",
+                    log_n, r, p, other
+                ),
+            }
+        }
+
+        let mut costly = sealed.clone();
+        costly.log_n = 40;
+        assert!(matches!(
+            costly.open("secret"),
+            Err(KeyringError::InvalidKey(_))
+        ));
+    }
+}
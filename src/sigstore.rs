@@ -0,0 +1,1160 @@
+//! Keyless signing with Sigstore.
+//!
+//! `Signer` signs the canonical JSON of a bundle, or any other artifact, with a
+//! short-lived key. Fulcio certifies the key for the identity in an OIDC token, and
+//! the signature is recorded in the Rekor transparency log. The result is a
+//! `SigstoreBundle`, the same file cosign writes with `--bundle`.
+//!
+//! `Verifier` checks those signatures against a `TrustRoot` and the identities
+//! allowed to sign. With the `drivers` feature it is a `SignatureVerifier`, so the
+//! engine can use it to run verified bundles only:
+//!
+//! ```no_run
+//! # #[cfg(feature = "drivers")]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use libcnab::claimstore::MemoryClaimStore;
+//! use libcnab::driver::DebugDriver;
+//! use libcnab::engine::{verified_only, Engine};
+//! use libcnab::sigstore::{SigstoreBundle, TrustRoot, Verifier};
+//!
+//! let trust_root = TrustRoot::from_pem(
+//!     &std::fs::read("fulcio-chain.pem")?,
+//!     &std::fs::read("rekor.pub")?,
+//! )?;
+//! let signature: SigstoreBundle =
+//!     serde_json::from_slice(&std::fs::read("bundle.sigstore.json")?)?;
+//! let verifier = Verifier::new(trust_root)
+//!     .identity("https://accounts.google.com", "release@example.com")
+//!     .signature(signature);
+//!
+//! let (driver, claims) = (DebugDriver::new(), MemoryClaimStore::new());
+//! let engine = Engine::new(&driver, &claims)
+//!     .signatures(&verifier)
+//!     .policy(&verified_only);
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "drivers"))]
+//! # fn main() {}
+//! ```
+use crate::cnab::Bundle;
+#[cfg(feature = "drivers")]
+use crate::engine::{SignatureStatus, SignatureVerifier};
+use crate::error_code::{Coded, ErrorCode};
+use crate::proxy::ProxyConfig;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use p256::ecdsa::signature::hazmat::PrehashVerifier;
+use p256::ecdsa::signature::Signer as _;
+use p256::pkcs8::{DecodePublicKey, EncodePublicKey, LineEnding};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256, Sha384};
+use std::fmt;
+use x509_cert::der::asn1::{ObjectIdentifier, Utf8StringRef};
+use x509_cert::der::oid::AssociatedOid;
+use x509_cert::der::{Decode, DecodePem, Encode, EncodePem};
+use x509_cert::ext::pkix::name::GeneralName;
+use x509_cert::ext::pkix::SubjectAltName;
+use x509_cert::Certificate;
+
+/// The public-good Fulcio instance
+pub const FULCIO_URL: &str = "https://fulcio.sigstore.dev";
+/// The public-good Rekor instance
+pub const REKOR_URL: &str = "https://rekor.sigstore.dev";
+/// The media type of the Sigstore bundles this module writes
+pub const SIGSTORE_BUNDLE_MEDIA_TYPE: &str = "application/vnd.dev.sigstore.bundle.v0.3+json";
+
+/// The extension Fulcio records the token's issuer in, as a UTF8String
+const OIDC_ISSUER: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.57264.1.8");
+/// The deprecated form of `OIDC_ISSUER`, holding the bare issuer
+const OIDC_ISSUER_V1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.57264.1.1");
+const ECDSA_WITH_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+const ECDSA_WITH_SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3");
+
+/// SigstoreBundle is a signature with what verifies it: the signing certificate and
+/// the transparency log entry.
+///
+/// It is read and written as the JSON of the Sigstore bundle format.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SigstoreBundle {
+    media_type: String,
+    verification_material: VerificationMaterial,
+    message_signature: MessageSignature,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VerificationMaterial {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    certificate: Option<RawBytes>,
+    /// The certificate of bundles before v0.3
+    #[serde(skip_serializing_if = "Option::is_none")]
+    x509_certificate_chain: Option<CertificateChain>,
+    #[serde(default)]
+    tlog_entries: Vec<TlogEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawBytes {
+    raw_bytes: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CertificateChain {
+    certificates: Vec<RawBytes>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TlogEntry {
+    log_index: String,
+    log_id: LogId,
+    kind_version: KindVersion,
+    integrated_time: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inclusion_promise: Option<InclusionPromise>,
+    canonicalized_body: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LogId {
+    key_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct KindVersion {
+    kind: String,
+    version: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InclusionPromise {
+    signed_entry_timestamp: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MessageSignature {
+    message_digest: MessageDigest,
+    signature: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct MessageDigest {
+    algorithm: String,
+    digest: String,
+}
+
+impl SigstoreBundle {
+    /// The `sha256:<hex>` digest of what was signed.
+    pub fn digest(&self) -> String {
+        let digest = STANDARD
+            .decode(&self.message_signature.message_digest.digest)
+            .unwrap_or_default();
+        format!("sha256:{}", hex::encode(digest))
+    }
+
+    fn signs(&self, digest: &[u8]) -> bool {
+        self.message_signature.message_digest.algorithm == "SHA2_256"
+            && STANDARD
+                .decode(&self.message_signature.message_digest.digest)
+                .is_ok_and(|d| d == digest)
+    }
+
+    /// The signing certificate, parsed and as DER.
+    fn certificate(&self) -> Result<(Certificate, Vec<u8>), SigstoreError> {
+        let material = &self.verification_material;
+        let raw = material
+            .certificate
+            .as_ref()
+            .or_else(|| {
+                material
+                    .x509_certificate_chain
+                    .as_ref()
+                    .and_then(|chain| chain.certificates.first())
+            })
+            .ok_or_else(|| SigstoreError::Invalid("no signing certificate".to_string()))?;
+        let der = STANDARD.decode(&raw.raw_bytes).map_err(invalid)?;
+        Ok((Certificate::from_der(&der).map_err(invalid)?, der))
+    }
+}
+
+/// Identity is who a Fulcio certificate was issued to: the subject of an OIDC token,
+/// such as an email address or a CI workflow's URI, and the token's issuer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub issuer: String,
+    pub subject: String,
+}
+
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (issued by {})", self.subject, self.issuer)
+    }
+}
+
+/// Signer signs artifacts keylessly with a Fulcio and a Rekor instance.
+#[derive(Debug, Clone)]
+pub struct Signer {
+    fulcio: String,
+    rekor: String,
+    proxy: ProxyConfig,
+}
+
+impl Default for Signer {
+    fn default() -> Self {
+        Signer {
+            fulcio: FULCIO_URL.to_string(),
+            rekor: REKOR_URL.to_string(),
+            proxy: ProxyConfig::from_env(),
+        }
+    }
+}
+
+impl Signer {
+    /// A signer for the public-good instances, reached through the proxies of
+    /// `ProxyConfig::from_env`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get certificates from the Fulcio instance at `url`.
+    pub fn fulcio(mut self, url: &str) -> Self {
+        self.fulcio = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Record signatures in the Rekor instance at `url`.
+    pub fn rekor(mut self, url: &str) -> Self {
+        self.rekor = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Sign the canonical JSON of `bundle` as the identity of the OIDC token
+    /// `identity_token`.
+    pub fn sign(
+        &self,
+        bundle: &Bundle,
+        identity_token: &str,
+    ) -> Result<SigstoreBundle, SigstoreError> {
+        self.sign_bytes(&bundle.to_canonical_json(), identity_token)
+    }
+
+    /// Sign `payload` as the identity of the OIDC token `identity_token`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, err))]
+    pub fn sign_bytes(
+        &self,
+        payload: &[u8],
+        identity_token: &str,
+    ) -> Result<SigstoreBundle, SigstoreError> {
+        let key = ephemeral_key();
+        let certificate = self.certificate(&key, identity_token)?;
+        let signature: p256::ecdsa::Signature = key.sign(payload);
+        let signature = signature.to_der().as_bytes().to_vec();
+        let digest = Sha256::digest(payload);
+        let entry = self.record(&certificate, &digest, &signature)?;
+        Ok(SigstoreBundle {
+            media_type: SIGSTORE_BUNDLE_MEDIA_TYPE.to_string(),
+            verification_material: VerificationMaterial {
+                certificate: Some(RawBytes {
+                    raw_bytes: STANDARD.encode(certificate.to_der().map_err(invalid)?),
+                }),
+                x509_certificate_chain: None,
+                tlog_entries: vec![entry],
+            },
+            message_signature: MessageSignature {
+                message_digest: MessageDigest {
+                    algorithm: "SHA2_256".to_string(),
+                    digest: STANDARD.encode(digest),
+                },
+                signature: STANDARD.encode(signature),
+            },
+        })
+    }
+
+    /// Have Fulcio certify `key` for the identity of `identity_token`.
+    fn certificate(
+        &self,
+        key: &p256::ecdsa::SigningKey,
+        identity_token: &str,
+    ) -> Result<Certificate, SigstoreError> {
+        // Fulcio asks for a signature of the token's subject, proving the key is held.
+        let proof: p256::ecdsa::Signature = key.sign(token_subject(identity_token)?.as_bytes());
+        let public_key = key
+            .verifying_key()
+            .to_public_key_pem(LineEnding::LF)
+            .map_err(invalid)?;
+        let url = format!("{}/api/v2/signingCert", self.fulcio);
+        let response = self.post(
+            &url,
+            serde_json::json!({
+                "credentials": { "oidcIdentityToken": identity_token },
+                "publicKeyRequest": {
+                    "publicKey": { "algorithm": "ECDSA", "content": public_key },
+                    "proofOfPossession": STANDARD.encode(proof.to_der().as_bytes()),
+                },
+            }),
+        )?;
+        let leaf = [
+            "signedCertificateEmbeddedSct",
+            "signedCertificateDetachedSct",
+        ]
+        .iter()
+        .find_map(|field| response.get(field))
+        .and_then(|signed| signed.pointer("/chain/certificates/0"))
+        .and_then(Value::as_str)
+        .ok_or_else(|| service_error(&url, "no certificate returned"))?;
+        Certificate::from_pem(leaf).map_err(invalid)
+    }
+
+    /// Record a `hashedrekord` entry of `signature` in Rekor.
+    fn record(
+        &self,
+        certificate: &Certificate,
+        digest: &[u8],
+        signature: &[u8],
+    ) -> Result<TlogEntry, SigstoreError> {
+        let pem = certificate.to_pem(LineEnding::LF).map_err(invalid)?;
+        let url = format!("{}/api/v1/log/entries", self.rekor);
+        let response = self.post(&url, hashed_rekord(digest, signature, &pem))?;
+        let missing = |field: &str| service_error(&url, format!("no {} returned", field));
+        let entry = response
+            .as_object()
+            .and_then(|entries| entries.values().next())
+            .ok_or_else(|| missing("log entry"))?;
+        let field = |name: &str| entry.get(name).ok_or_else(|| missing(name));
+        let log_id = hex::decode(field("logID")?.as_str().unwrap_or_default())
+            .map_err(|_| missing("logID"))?;
+        let timestamp = entry
+            .pointer("/verification/signedEntryTimestamp")
+            .and_then(Value::as_str)
+            .ok_or_else(|| missing("signedEntryTimestamp"))?;
+        Ok(TlogEntry {
+            log_index: field("logIndex")?.to_string(),
+            log_id: LogId {
+                key_id: STANDARD.encode(log_id),
+            },
+            kind_version: KindVersion {
+                kind: "hashedrekord".to_string(),
+                version: "0.0.1".to_string(),
+            },
+            integrated_time: field("integratedTime")?.to_string(),
+            inclusion_promise: Some(InclusionPromise {
+                signed_entry_timestamp: timestamp.to_string(),
+            }),
+            canonicalized_body: field("body")?.as_str().unwrap_or_default().to_string(),
+        })
+    }
+
+    fn post(&self, url: &str, body: Value) -> Result<Value, SigstoreError> {
+        let agent = self.proxy.agent(url).map_err(|e| service_error(url, e))?;
+        agent
+            .post(url)
+            .send_json(body)
+            .and_then(|mut r| r.body_mut().read_json())
+            .map_err(|e| service_error(url, e))
+    }
+}
+
+/// The `hashedrekord` entry Rekor records for a signature.
+fn hashed_rekord(digest: &[u8], signature: &[u8], certificate_pem: &str) -> Value {
+    serde_json::json!({
+        "apiVersion": "0.0.1",
+        "kind": "hashedrekord",
+        "spec": {
+            "data": { "hash": { "algorithm": "sha256", "value": hex::encode(digest) } },
+            "signature": {
+                "content": STANDARD.encode(signature),
+                "publicKey": { "content": STANDARD.encode(certificate_pem) },
+            },
+        },
+    })
+}
+
+/// A new P-256 key for one signature.
+fn ephemeral_key() -> p256::ecdsa::SigningKey {
+    loop {
+        let mut bytes = [0; 32];
+        getrandom::getrandom(&mut bytes).expect("the operating system has randomness");
+        if let Ok(key) = p256::ecdsa::SigningKey::from_slice(&bytes) {
+            return key;
+        }
+    }
+}
+
+/// The subject Fulcio certifies for a token: its `email` claim, or else its `sub`.
+fn token_subject(token: &str) -> Result<String, SigstoreError> {
+    let claims: Value = token
+        .split('.')
+        .nth(1)
+        .and_then(|claims| URL_SAFE_NO_PAD.decode(claims.trim_end_matches('=')).ok())
+        .and_then(|claims| serde_json::from_slice(&claims).ok())
+        .ok_or_else(|| SigstoreError::InvalidToken("not a JWT".to_string()))?;
+    claims
+        .get("email")
+        .or_else(|| claims.get("sub"))
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| SigstoreError::InvalidToken("no email or sub claim".to_string()))
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Hash {
+    Sha256,
+    Sha384,
+}
+
+/// An ECDSA public key, of the curves Fulcio and Rekor use.
+#[derive(Debug, Clone)]
+enum PublicKey {
+    P256(p256::ecdsa::VerifyingKey),
+    P384(p384::ecdsa::VerifyingKey),
+}
+
+impl PublicKey {
+    fn from_der(spki: &[u8]) -> Option<Self> {
+        p256::ecdsa::VerifyingKey::from_public_key_der(spki)
+            .map(PublicKey::P256)
+            .or_else(|_| p384::ecdsa::VerifyingKey::from_public_key_der(spki).map(PublicKey::P384))
+            .ok()
+    }
+
+    fn of(certificate: &Certificate) -> Option<Self> {
+        Self::from_der(
+            &certificate
+                .tbs_certificate
+                .subject_public_key_info
+                .to_der()
+                .ok()?,
+        )
+    }
+
+    /// The hash the key signs messages with.
+    fn hash(&self) -> Hash {
+        match self {
+            PublicKey::P256(_) => Hash::Sha256,
+            PublicKey::P384(_) => Hash::Sha384,
+        }
+    }
+
+    /// Check the DER `signature` of `message` hashed with `hash`.
+    fn verify(&self, message: &[u8], signature: &[u8], hash: Hash) -> bool {
+        let digest = match hash {
+            Hash::Sha256 => Sha256::digest(message).to_vec(),
+            Hash::Sha384 => Sha384::digest(message).to_vec(),
+        };
+        match self {
+            PublicKey::P256(key) => p256::ecdsa::Signature::from_der(signature)
+                .is_ok_and(|s| key.verify_prehash(&digest, &s).is_ok()),
+            PublicKey::P384(key) => p384::ecdsa::Signature::from_der(signature)
+                .is_ok_and(|s| key.verify_prehash(&digest, &s).is_ok()),
+        }
+    }
+}
+
+/// TrustRoot is what Sigstore signatures are checked against: the CA certificates of
+/// Fulcio and the public keys of Rekor.
+///
+/// For the public-good instances they are published with TUF by the Sigstore
+/// project, and can be read from `https://fulcio.sigstore.dev/api/v2/trustBundle`
+/// and `https://rekor.sigstore.dev/api/v1/log/publicKey`.
+#[derive(Debug, Clone)]
+pub struct TrustRoot {
+    certificates: Vec<Certificate>,
+    /// The keys of each log, by log ID: the sha256 digest of the key's DER
+    logs: Vec<(Vec<u8>, PublicKey)>,
+}
+
+impl TrustRoot {
+    /// A root of the PEM certificates `fulcio`, root and intermediates alike, and
+    /// the PEM public keys `rekor`.
+    pub fn from_pem(fulcio: &[u8], rekor: &[u8]) -> Result<Self, SigstoreError> {
+        let certificates = Certificate::load_pem_chain(fulcio).map_err(invalid)?;
+        if certificates.is_empty() {
+            return Err(SigstoreError::Invalid("no Fulcio certificate".to_string()));
+        }
+        let rekor = std::str::from_utf8(rekor).map_err(invalid)?;
+        let mut logs = Vec::new();
+        for block in rekor.split_inclusive("-----END PUBLIC KEY-----") {
+            if !block.contains("-----BEGIN PUBLIC KEY-----") {
+                continue;
+            }
+            let der = p256::ecdsa::VerifyingKey::from_public_key_pem(block.trim())
+                .map(|k| k.to_public_key_der())
+                .or_else(|_| {
+                    p384::ecdsa::VerifyingKey::from_public_key_pem(block.trim())
+                        .map(|k| k.to_public_key_der())
+                })
+                .map_err(invalid)?
+                .map_err(invalid)?;
+            let key = PublicKey::from_der(der.as_bytes()).expect("the key was just parsed");
+            logs.push((Sha256::digest(der.as_bytes()).to_vec(), key));
+        }
+        if logs.is_empty() {
+            return Err(SigstoreError::Invalid("no Rekor public key".to_string()));
+        }
+        Ok(TrustRoot { certificates, logs })
+    }
+
+    /// Check that `certificate` was issued by a trusted CA, and that both were valid
+    /// at `time`.
+    fn check_certificate(&self, certificate: &Certificate, time: u64) -> Result<(), SigstoreError> {
+        let untrusted = |reason: &str| SigstoreError::UntrustedCertificate(reason.to_string());
+        if !valid_at(certificate, time) {
+            return Err(untrusted(
+                "the certificate was not valid when the signature was logged",
+            ));
+        }
+        let hash = match certificate.signature_algorithm.oid {
+            ECDSA_WITH_SHA256 => Hash::Sha256,
+            ECDSA_WITH_SHA384 => Hash::Sha384,
+            other => {
+                return Err(SigstoreError::UntrustedCertificate(format!(
+                    "unsupported signature algorithm {}",
+                    other
+                )))
+            }
+        };
+        let tbs = certificate.tbs_certificate.to_der().map_err(invalid)?;
+        let signature = certificate.signature.raw_bytes();
+        let issued = self.certificates.iter().any(|ca| {
+            ca.tbs_certificate.subject == certificate.tbs_certificate.issuer
+                && valid_at(ca, time)
+                && PublicKey::of(ca).is_some_and(|key| key.verify(&tbs, signature, hash))
+        });
+        if !issued {
+            return Err(untrusted("the certificate was not issued by a trusted CA"));
+        }
+        Ok(())
+    }
+}
+
+fn valid_at(certificate: &Certificate, time: u64) -> bool {
+    let validity = &certificate.tbs_certificate.validity;
+    validity.not_before.to_unix_duration().as_secs() <= time
+        && time <= validity.not_after.to_unix_duration().as_secs()
+}
+
+/// The identity a Fulcio certificate was issued to.
+fn identity_of(certificate: &Certificate) -> Result<Identity, SigstoreError> {
+    let (mut issuer, mut issuer_v1, mut subject) = (None, None, None);
+    let extensions = certificate.tbs_certificate.extensions.as_deref();
+    for extension in extensions.unwrap_or_default() {
+        let value = extension.extn_value.as_bytes();
+        if extension.extn_id == OIDC_ISSUER {
+            issuer = Utf8StringRef::from_der(value)
+                .ok()
+                .map(|s| s.as_str().to_string());
+        } else if extension.extn_id == OIDC_ISSUER_V1 {
+            issuer_v1 = std::str::from_utf8(value).ok().map(str::to_string);
+        } else if extension.extn_id == SubjectAltName::OID {
+            let names = SubjectAltName::from_der(value).map_err(invalid)?;
+            subject = names.0.into_iter().find_map(|name| match name {
+                GeneralName::Rfc822Name(s) | GeneralName::UniformResourceIdentifier(s) => {
+                    Some(s.to_string())
+                }
+                _ => None,
+            });
+        }
+    }
+    match (issuer.or(issuer_v1), subject) {
+        (Some(issuer), Some(subject)) => Ok(Identity { issuer, subject }),
+        _ => Err(SigstoreError::UntrustedCertificate(
+            "the certificate names no OIDC identity".to_string(),
+        )),
+    }
+}
+
+/// Verifier checks Sigstore signatures, and that they were made by one of the
+/// identities it allows.
+#[derive(Debug, Clone)]
+pub struct Verifier {
+    trust_root: TrustRoot,
+    identities: Vec<Identity>,
+    signatures: Vec<SigstoreBundle>,
+}
+
+impl Verifier {
+    /// A verifier that allows no identity yet.
+    pub fn new(trust_root: TrustRoot) -> Self {
+        Verifier {
+            trust_root,
+            identities: Vec::new(),
+            signatures: Vec::new(),
+        }
+    }
+
+    /// Allow signatures by `subject`, as the OIDC issuer `issuer` vouches for it.
+    pub fn identity(mut self, issuer: &str, subject: &str) -> Self {
+        self.identities.push(Identity {
+            issuer: issuer.to_string(),
+            subject: subject.to_string(),
+        });
+        self
+    }
+
+    /// Add a signature to check bundles against as a `SignatureVerifier`.
+    pub fn signature(mut self, signature: SigstoreBundle) -> Self {
+        self.signatures.push(signature);
+        self
+    }
+
+    /// Check that `signature` is a valid signature of the canonical JSON of `bundle`,
+    /// returning who made it.
+    pub fn verify_bundle(
+        &self,
+        bundle: &Bundle,
+        signature: &SigstoreBundle,
+    ) -> Result<Identity, SigstoreError> {
+        self.verify_bytes(&bundle.to_canonical_json(), signature)
+    }
+
+    /// Check that `signature` is a valid signature of `payload`, returning who made
+    /// it.
+    ///
+    /// The signature must match the key of its certificate, the certificate must be
+    /// issued by the trust root's Fulcio to an allowed identity, and the signature
+    /// must be in a trusted Rekor log while the certificate was valid. The log entry
+    /// is checked with its signed entry timestamp, without contacting the log.
+    pub fn verify_bytes(
+        &self,
+        payload: &[u8],
+        signature: &SigstoreBundle,
+    ) -> Result<Identity, SigstoreError> {
+        let digest = Sha256::digest(payload);
+        if !signature.signs(&digest) {
+            return Err(SigstoreError::BadSignature);
+        }
+        let (certificate, der) = signature.certificate()?;
+        let signed = STANDARD
+            .decode(&signature.message_signature.signature)
+            .map_err(invalid)?;
+        let key = PublicKey::of(&certificate).ok_or_else(|| {
+            SigstoreError::UntrustedCertificate("unsupported public key".to_string())
+        })?;
+        if !key.verify(payload, &signed, key.hash()) {
+            return Err(SigstoreError::BadSignature);
+        }
+        let time = self.check_log(signature, &der, &digest, &signed)?;
+        self.trust_root.check_certificate(&certificate, time)?;
+        let identity = identity_of(&certificate)?;
+        if !self.identities.contains(&identity) {
+            return Err(SigstoreError::UntrustedIdentity(identity));
+        }
+        Ok(identity)
+    }
+
+    /// Check the log entry of `signature`, returning when it was logged.
+    fn check_log(
+        &self,
+        signature: &SigstoreBundle,
+        certificate: &[u8],
+        digest: &[u8],
+        signed: &[u8],
+    ) -> Result<u64, SigstoreError> {
+        let log_error = |reason: &str| SigstoreError::TransparencyLog(reason.to_string());
+        let entry = signature
+            .verification_material
+            .tlog_entries
+            .first()
+            .ok_or_else(|| log_error("no log entry"))?;
+        let log_id = STANDARD.decode(&entry.log_id.key_id).map_err(invalid)?;
+        let key = self
+            .trust_root
+            .logs
+            .iter()
+            .find(|(id, _)| *id == log_id)
+            .map(|(_, key)| key)
+            .ok_or_else(|| {
+                SigstoreError::TransparencyLog(format!(
+                    "log {} is not trusted",
+                    hex::encode(&log_id)
+                ))
+            })?;
+        let promise = entry
+            .inclusion_promise
+            .as_ref()
+            .ok_or_else(|| log_error("no signed entry timestamp"))?;
+        let integrated_time: u64 = entry.integrated_time.parse().map_err(invalid)?;
+        let log_index: u64 = entry.log_index.parse().map_err(invalid)?;
+        // The timestamp signs the entry's canonical JSON, whose keys are sorted.
+        let payload = serde_json::to_vec(&serde_json::json!({
+            "body": entry.canonicalized_body,
+            "integratedTime": integrated_time,
+            "logID": hex::encode(&log_id),
+            "logIndex": log_index,
+        }))
+        .expect("log entries serialize");
+        let timestamp = STANDARD
+            .decode(&promise.signed_entry_timestamp)
+            .map_err(invalid)?;
+        if !key.verify(&payload, &timestamp, Hash::Sha256) {
+            return Err(log_error("the signed entry timestamp does not verify"));
+        }
+
+        let body: Value = STANDARD
+            .decode(&entry.canonicalized_body)
+            .ok()
+            .and_then(|body| serde_json::from_slice(&body).ok())
+            .ok_or_else(|| log_error("the log entry is not JSON"))?;
+        let field = |pointer: &str| body.pointer(pointer).and_then(Value::as_str);
+        let logged_certificate = field("/spec/signature/publicKey/content")
+            .and_then(|pem| STANDARD.decode(pem).ok())
+            .and_then(|pem| Certificate::from_pem(pem).ok())
+            .and_then(|c| c.to_der().ok());
+        let logged_signature =
+            field("/spec/signature/content").and_then(|s| STANDARD.decode(s).ok());
+        if field("/kind") != Some("hashedrekord")
+            || field("/spec/data/hash/value") != Some(hex::encode(digest).as_str())
+            || logged_signature.as_deref() != Some(signed)
+            || logged_certificate.as_deref() != Some(certificate)
+        {
+            return Err(log_error("the log entry is of another signature"));
+        }
+        Ok(integrated_time)
+    }
+}
+
+/// Bundles are checked against the verifier's signatures. Those that sign other
+/// content are passed over, so a bundle none of them signs is unsigned.
+#[cfg(feature = "drivers")]
+impl SignatureVerifier for Verifier {
+    fn verify(&self, bundle: &Bundle, _digest: &str) -> SignatureStatus {
+        let payload = bundle.to_canonical_json();
+        let digest = Sha256::digest(&payload);
+        let mut status = SignatureStatus::Unsigned;
+        for signature in self.signatures.iter().filter(|s| s.signs(&digest)) {
+            match self.verify_bytes(&payload, signature) {
+                Ok(identity) => {
+                    return SignatureStatus::Verified {
+                        signer: identity.subject,
+                    }
+                }
+                Err(e) => status = SignatureStatus::Invalid(e.to_string()),
+            }
+        }
+        status
+    }
+}
+
+/// SigstoreError describes a failure to sign with Sigstore, or to verify a signature.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SigstoreError {
+    /// Fulcio or Rekor could not be reached, or refused a request
+    Service { url: String, message: String },
+    /// An OIDC identity token could not be read
+    InvalidToken(String),
+    /// A certificate, key or Sigstore bundle could not be parsed
+    Invalid(String),
+    /// The signing certificate was not issued by a trusted Fulcio
+    UntrustedCertificate(String),
+    /// The signing certificate was issued to an identity that is not allowed
+    UntrustedIdentity(Identity),
+    /// The signature's transparency log entry is missing, or does not verify
+    TransparencyLog(String),
+    /// The signature does not match the signed content
+    BadSignature,
+}
+
+impl fmt::Display for SigstoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SigstoreError::Service { url, message } => write!(f, "{}: {}", url, message),
+            SigstoreError::InvalidToken(e) => write!(f, "invalid identity token: {}", e),
+            SigstoreError::Invalid(e) => write!(f, "invalid signature material: {}", e),
+            SigstoreError::UntrustedCertificate(e) => write!(f, "untrusted certificate: {}", e),
+            SigstoreError::UntrustedIdentity(identity) => {
+                write!(f, "{} is not allowed to sign", identity)
+            }
+            SigstoreError::TransparencyLog(e) => write!(f, "transparency log: {}", e),
+            SigstoreError::BadSignature => {
+                write!(f, "the signature does not match the signed content")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SigstoreError {}
+
+impl Coded for SigstoreError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            SigstoreError::Service { .. } => ErrorCode::SigstoreService,
+            SigstoreError::InvalidToken(_) => ErrorCode::SigstoreInvalidToken,
+            SigstoreError::Invalid(_) => ErrorCode::SigstoreInvalid,
+            SigstoreError::UntrustedCertificate(_) => ErrorCode::SigstoreUntrustedCertificate,
+            SigstoreError::UntrustedIdentity(_) => ErrorCode::SigstoreUntrustedIdentity,
+            SigstoreError::TransparencyLog(_) => ErrorCode::SigstoreTransparencyLog,
+            SigstoreError::BadSignature => ErrorCode::SigstoreBadSignature,
+        }
+    }
+}
+
+fn invalid<E: fmt::Display>(error: E) -> SigstoreError {
+    SigstoreError::Invalid(error.to_string())
+}
+
+fn service_error<E: fmt::Display>(url: &str, error: E) -> SigstoreError {
+    SigstoreError::Service {
+        url: url.to_string(),
+        message: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p256::ecdsa::signature::Verifier as _;
+    use std::convert::TryFrom;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+    use x509_cert::builder::{Builder, CertificateBuilder, Profile};
+    use x509_cert::der::{FixedTag, Length, Tag, Writer};
+    use x509_cert::ext::pkix::name::GeneralNames;
+    use x509_cert::name::Name;
+    use x509_cert::serial_number::SerialNumber;
+    use x509_cert::spki::SubjectPublicKeyInfoOwned;
+    use x509_cert::time::Validity;
+
+    const ISSUER: &str = "https://issuer.example.com";
+    const SUBJECT: &str = "release@example.com";
+
+    /// The issuer extension of the certificates the fake Fulcio issues
+    struct OidcIssuer(&'static str);
+
+    impl AssociatedOid for OidcIssuer {
+        const OID: ObjectIdentifier = OIDC_ISSUER;
+    }
+
+    impl FixedTag for OidcIssuer {
+        const TAG: Tag = Tag::Utf8String;
+    }
+
+    impl x509_cert::der::EncodeValue for OidcIssuer {
+        fn value_len(&self) -> x509_cert::der::Result<Length> {
+            Length::try_from(self.0.len())
+        }
+
+        fn encode_value(&self, writer: &mut impl Writer) -> x509_cert::der::Result<()> {
+            writer.write(self.0.as_bytes())
+        }
+    }
+
+    impl x509_cert::ext::AsExtension for OidcIssuer {
+        fn critical(&self, _: &Name, _: &[x509_cert::ext::Extension]) -> bool {
+            false
+        }
+    }
+
+    /// A Fulcio with a P-384 root, and a Rekor with a P-256 key.
+    struct FakeSigstore {
+        url: String,
+        ca_key: p384::ecdsa::SigningKey,
+        ca: Certificate,
+        log_key: p256::ecdsa::SigningKey,
+        serial: AtomicU32,
+    }
+
+    impl FakeSigstore {
+        fn start() -> Arc<Self> {
+            Self::with_root(3)
+        }
+
+        /// A Fulcio whose root key is made of the byte `seed`.
+        fn with_root(seed: u8) -> Arc<Self> {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let ca_key = p384::ecdsa::SigningKey::from_slice(&[seed; 48]).unwrap();
+            let subject = Name::from_str("CN=fake-fulcio,O=libcnab").unwrap();
+            let spki = SubjectPublicKeyInfoOwned::from_key(*ca_key.verifying_key()).unwrap();
+            let ca = CertificateBuilder::new(
+                Profile::Root,
+                SerialNumber::from(1u32),
+                Validity::from_now(Duration::from_secs(3600)).unwrap(),
+                subject,
+                spki,
+                &ca_key,
+            )
+            .unwrap()
+            .build::<p384::ecdsa::DerSignature>()
+            .unwrap();
+            let sigstore = Arc::new(FakeSigstore {
+                url: format!("http://{}", listener.local_addr().unwrap()),
+                ca_key,
+                ca,
+                log_key: p256::ecdsa::SigningKey::from_slice(&[5; 32]).unwrap(),
+                serial: AtomicU32::new(2),
+            });
+            let shared = sigstore.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let _ = shared.serve(stream);
+                }
+            });
+            sigstore
+        }
+
+        fn trust_root(&self) -> TrustRoot {
+            let log_key = self
+                .log_key
+                .verifying_key()
+                .to_public_key_pem(LineEnding::LF)
+                .unwrap();
+            TrustRoot::from_pem(
+                self.ca.to_pem(LineEnding::LF).unwrap().as_bytes(),
+                log_key.as_bytes(),
+            )
+            .unwrap()
+        }
+
+        fn serve(&self, mut stream: TcpStream) -> std::io::Result<()> {
+            let mut reader = BufReader::new(stream.try_clone()?);
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            let path = line
+                .split_whitespace()
+                .nth(1)
+                .unwrap_or_default()
+                .to_string();
+            let mut length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header)?;
+                let header = header.trim_end();
+                if header.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap_or_default();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            let request: Value = serde_json::from_slice(&body).unwrap_or_default();
+            let (status, response) = match path.as_str() {
+                "/api/v2/signingCert" => self.certify(&request),
+                "/api/v1/log/entries" => self.log(&request),
+                _ => (404, Value::Null),
+            };
+            let response = serde_json::to_vec(&response).unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 {} Fake\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                response.len()
+            )?;
+            stream.write_all(&response)
+        }
+
+        /// Issue a certificate to the token's subject, if the proof of possession
+        /// verifies.
+        fn certify(&self, request: &Value) -> (u16, Value) {
+            let field = |pointer: &str| request.pointer(pointer).and_then(Value::as_str);
+            let token = field("/credentials/oidcIdentityToken").unwrap_or_default();
+            let subject = token_subject(token).unwrap();
+            let key = p256::ecdsa::VerifyingKey::from_public_key_pem(
+                field("/publicKeyRequest/publicKey/content").unwrap_or_default(),
+            )
+            .unwrap();
+            let proof = STANDARD
+                .decode(field("/publicKeyRequest/proofOfPossession").unwrap_or_default())
+                .unwrap();
+            let proof = p256::ecdsa::Signature::from_der(&proof).unwrap();
+            if key.verify(subject.as_bytes(), &proof).is_err() {
+                return (400, Value::Null);
+            }
+            let pem = self.issue(&key, &subject).to_pem(LineEnding::LF).unwrap();
+            (
+                201,
+                serde_json::json!({
+                    "signedCertificateEmbeddedSct": { "chain": { "certificates": [pem] } }
+                }),
+            )
+        }
+
+        fn issue(&self, key: &p256::ecdsa::VerifyingKey, subject: &str) -> Certificate {
+            let profile = Profile::Leaf {
+                issuer: self.ca.tbs_certificate.subject.clone(),
+                enable_key_agreement: false,
+                enable_key_encipherment: false,
+            };
+            let serial = self.serial.fetch_add(1, Ordering::SeqCst);
+            let mut builder = CertificateBuilder::new(
+                profile,
+                SerialNumber::from(serial),
+                Validity::from_now(Duration::from_secs(600)).unwrap(),
+                Name::default(),
+                SubjectPublicKeyInfoOwned::from_key(*key).unwrap(),
+                &self.ca_key,
+            )
+            .unwrap();
+            let email = x509_cert::der::asn1::Ia5String::new(subject).unwrap();
+            let names: GeneralNames = vec![GeneralName::Rfc822Name(email)];
+            builder.add_extension(&SubjectAltName(names)).unwrap();
+            builder.add_extension(&OidcIssuer(ISSUER)).unwrap();
+            builder.build::<p384::ecdsa::DerSignature>().unwrap()
+        }
+
+        /// Log the entry, with a signed entry timestamp.
+        fn log(&self, request: &Value) -> (u16, Value) {
+            let body = STANDARD.encode(serde_json::to_vec(request).unwrap());
+            let log_id = hex::encode(Sha256::digest(
+                self.log_key
+                    .verifying_key()
+                    .to_public_key_der()
+                    .unwrap()
+                    .as_bytes(),
+            ));
+            let integrated_time = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let entry = serde_json::json!({
+                "body": body,
+                "integratedTime": integrated_time,
+                "logID": log_id,
+                "logIndex": 42,
+            });
+            let timestamp: p256::ecdsa::Signature =
+                self.log_key.sign(&serde_json::to_vec(&entry).unwrap());
+            let mut entry = entry;
+            entry["verification"] = serde_json::json!({
+                "signedEntryTimestamp": STANDARD.encode(timestamp.to_der().as_bytes())
+            });
+            (201, serde_json::json!({ "24296fb24b8ad77a": entry }))
+        }
+    }
+
+    fn token(subject: &str) -> String {
+        let claims = serde_json::json!({ "iss": ISSUER, "sub": "1234", "email": subject });
+        format!(
+            "eyJhbGciOiJub25lIn0.{}.",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).unwrap())
+        )
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let sigstore = FakeSigstore::start();
+        let signer = Signer::new()
+            .fulcio(&sigstore.url)
+            .rekor(&sigstore.url)
+            .proxy(ProxyConfig::none());
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let signature = signer.sign(&bundle, &token(SUBJECT)).unwrap();
+        let json = serde_json::to_string(&signature).unwrap();
+        let signature: SigstoreBundle = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            signature.digest(),
+            format!(
+                "sha256:{}",
+                hex::encode(Sha256::digest(bundle.to_canonical_json()))
+            )
+        );
+
+        let verifier = Verifier::new(sigstore.trust_root()).identity(ISSUER, SUBJECT);
+        let identity = verifier.verify_bundle(&bundle, &signature).unwrap();
+        assert_eq!(identity.subject, SUBJECT);
+        assert_eq!(identity.issuer, ISSUER);
+
+        let stranger = Verifier::new(sigstore.trust_root()).identity(ISSUER, "someone@else.com");
+        assert_eq!(
+            stranger.verify_bundle(&bundle, &signature),
+            Err(SigstoreError::UntrustedIdentity(identity))
+        );
+
+        let mut forged = signature.clone();
+        forged.verification_material.tlog_entries[0].integrated_time = "1".to_string();
+        match verifier.verify_bundle(&bundle, &forged) {
+            Err(SigstoreError::TransparencyLog(_)) => {}
+            other => panic!("expected a log failure, got {:?}", other),
+        }
+
+        let other = FakeSigstore::with_root(4);
+        let untrusted = Verifier::new(TrustRoot {
+            certificates: vec![other.ca.clone()],
+            ..sigstore.trust_root()
+        })
+        .identity(ISSUER, SUBJECT);
+        match untrusted.verify_bundle(&bundle, &signature) {
+            Err(SigstoreError::UntrustedCertificate(_)) => {}
+            other => panic!("expected an untrusted certificate, got {:?}", other),
+        }
+
+        bundle.description = Some("changed".to_string());
+        assert_eq!(
+            verifier.verify_bundle(&bundle, &signature),
+            Err(SigstoreError::BadSignature)
+        );
+        match signer.sign(&bundle, "not a token") {
+            Err(SigstoreError::InvalidToken(_)) => {}
+            other => panic!("expected an invalid token, got {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "drivers")]
+    #[test]
+    fn test_verified_only() {
+        use crate::claimstore::MemoryClaimStore;
+        use crate::driver::DebugDriver;
+        use crate::engine::{verified_only, Engine, EngineError};
+        use crate::secrets::SecretResolver;
+        use crate::CredentialSet;
+
+        let sigstore = FakeSigstore::start();
+        let signer = Signer::new()
+            .fulcio(&sigstore.url)
+            .rekor(&sigstore.url)
+            .proxy(ProxyConfig::none());
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let mut other = bundle.clone();
+        other.version = semver::Version::parse("0.2.0").unwrap();
+        let verifier = Verifier::new(sigstore.trust_root())
+            .identity(ISSUER, SUBJECT)
+            .signature(signer.sign(&other, &token(SUBJECT)).unwrap());
+        assert_eq!(
+            verifier.verify(&bundle, "sha256:any"),
+            SignatureStatus::Unsigned
+        );
+
+        let verifier = verifier.signature(signer.sign(&bundle, &token(SUBJECT)).unwrap());
+        let credentials: Vec<CredentialSet> = vec![serde_json::from_str(
+            r#"{"name": "dev", "credentials": [{"name": "hostkey", "source": {"value": "key"}}]}"#,
+        )
+        .unwrap()];
+        let (driver, claims) = (DebugDriver::new(), MemoryClaimStore::new());
+        let engine = Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .policy(&verified_only);
+        match engine.install("hello", &bundle, &[], &credentials) {
+            Err(EngineError::Denied(_)) => {}
+            other => panic!("expected a denial, got {:?}", other),
+        }
+        engine
+            .signatures(&verifier)
+            .install("hello", &bundle, &[], &credentials)
+            .expect("bundles signed by the release identity run");
+
+        let stranger = Verifier::new(sigstore.trust_root())
+            .identity(ISSUER, SUBJECT)
+            .signature(signer.sign(&bundle, &token("someone@else.com")).unwrap());
+        match stranger.verify(&bundle, "sha256:any") {
+            SignatureStatus::Invalid(reason) => assert!(reason.contains("someone@else.com")),
+            other => panic!("expected an invalid signature, got {:?}", other),
+        }
+    }
+}
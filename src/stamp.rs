@@ -0,0 +1,250 @@
+use crate::cnab::Bundle;
+use crate::custom::{deserialize, CustomExtensionError};
+use crate::error_code::{Coded, ErrorCode};
+use chrono::prelude::{DateTime, Utc};
+use chrono::{SubsecRound, TimeZone};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// The custom key under which the build stamp of a bundle is stored
+pub const STAMP_KEY: &str = "libcnab.stamp";
+
+/// BuildInfo is what a build records about how it made a bundle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildInfo {
+    /// The source commit the bundle was built from, in lowercase
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// When the bundle was built, to the second
+    pub timestamp: DateTime<Utc>,
+    /// The tool that built the bundle, such as `porter v1.0.0`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<String>,
+}
+
+impl BuildInfo {
+    pub fn new(timestamp: DateTime<Utc>) -> Self {
+        BuildInfo {
+            commit: None,
+            timestamp,
+            toolchain: None,
+        }
+    }
+
+    /// Build info for a build happening now, or at `SOURCE_DATE_EPOCH` when that is
+    /// set, so that reproducible builds stamp reproducibly.
+    pub fn now() -> Self {
+        let epoch = std::env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|e| e.trim().parse::<i64>().ok())
+            .and_then(|e| Utc.timestamp_opt(e, 0).single());
+        BuildInfo::new(epoch.unwrap_or_else(Utc::now))
+    }
+
+    pub fn commit(mut self, commit: &str) -> Self {
+        self.commit = Some(commit.to_string());
+        self
+    }
+
+    pub fn toolchain(mut self, toolchain: &str) -> Self {
+        self.toolchain = Some(toolchain.to_string());
+        self
+    }
+
+    /// The info in the form it is stamped in: without surrounding whitespace or
+    /// empty values, the commit in lowercase and the timestamp to the second.
+    fn normalized(self) -> Self {
+        let trimmed = |s: Option<String>| s.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+        BuildInfo {
+            commit: trimmed(self.commit).map(|c| c.to_lowercase()),
+            timestamp: self.timestamp.trunc_subsecs(0),
+            toolchain: trimmed(self.toolchain),
+        }
+    }
+}
+
+/// Stamp is the build info stamped into a bundle, with the digest that ties it to
+/// the bundle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stamp {
+    #[serde(flatten)]
+    pub build: BuildInfo,
+    /// The `sha256:<hex>` digest of the canonical JSON of the bundle, with the
+    /// build info but without this digest under `STAMP_KEY`
+    pub digest: String,
+}
+
+impl Bundle {
+    /// Record `build` under `STAMP_KEY` in `custom`, with a digest of the bundle, so
+    /// that the provenance of the bundle goes wherever it is published.
+    ///
+    /// A stamp already there is replaced. Anything changed in the bundle afterwards,
+    /// other than its stamp, makes the stamp fail to verify.
+    ///
+    /// ```
+    /// use libcnab::{BuildInfo, Bundle};
+    ///
+    /// let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+    /// let stamp = bundle.stamp(BuildInfo::now().commit("3A6F2C1"));
+    /// assert_eq!(stamp.build.commit.as_deref(), Some("3a6f2c1"));
+    /// assert_eq!(bundle.verify_stamp().unwrap(), stamp);
+    ///
+    /// bundle.description = Some("changed after the build".to_string());
+    /// assert!(bundle.verify_stamp().is_err());
+    /// ```
+    pub fn stamp(&mut self, build: BuildInfo) -> Stamp {
+        let build = build.normalized();
+        let stamp = Stamp {
+            digest: self.stamp_digest(&build),
+            build,
+        };
+        self.custom.get_or_insert_with(Default::default).insert(
+            STAMP_KEY.to_string(),
+            serde_json::to_value(&stamp).expect("stamps serialize"),
+        );
+        stamp
+    }
+
+    /// The stamp of the bundle, if its digest is that of the bundle as it is.
+    pub fn verify_stamp(&self) -> Result<Stamp, StampError> {
+        let value = self
+            .custom
+            .as_ref()
+            .and_then(|c| c.get(STAMP_KEY))
+            .ok_or(StampError::Missing)?;
+        let stamp: Stamp = deserialize(STAMP_KEY, value)?;
+        let actual = self.stamp_digest(&stamp.build);
+        if actual != stamp.digest {
+            return Err(StampError::Mismatch {
+                expected: stamp.digest,
+                actual,
+            });
+        }
+        Ok(stamp)
+    }
+
+    /// The digest of the bundle with `build`, and no digest, as its stamp.
+    fn stamp_digest(&self, build: &BuildInfo) -> String {
+        let mut bundle = self.clone();
+        bundle.custom.get_or_insert_with(Default::default).insert(
+            STAMP_KEY.to_string(),
+            serde_json::to_value(build).expect("build info serializes"),
+        );
+        format!(
+            "sha256:{}",
+            hex::encode(Sha256::digest(bundle.to_canonical_json()))
+        )
+    }
+}
+
+/// StampError describes why the stamp of a bundle does not verify.
+#[derive(Debug)]
+pub enum StampError {
+    /// The bundle has no stamp
+    Missing,
+    /// The stamp is not a stamp
+    Invalid(CustomExtensionError),
+    /// The bundle or its build info changed since it was stamped
+    Mismatch { expected: String, actual: String },
+}
+
+impl fmt::Display for StampError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StampError::Missing => write!(f, "bundle has no {} stamp", STAMP_KEY),
+            StampError::Invalid(e) => e.fmt(f),
+            StampError::Mismatch { expected, actual } => write!(
+                f,
+                "bundle changed since it was stamped: stamped with {}, now {}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StampError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StampError::Invalid(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Coded for StampError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            StampError::Missing => ErrorCode::StampMissing,
+            StampError::Invalid(e) => e.code(),
+            StampError::Mismatch { .. } => ErrorCode::StampMismatch,
+        }
+    }
+}
+
+impl From<CustomExtensionError> for StampError {
+    fn from(error: CustomExtensionError) -> Self {
+        StampError::Invalid(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stamp() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        assert!(matches!(bundle.verify_stamp(), Err(StampError::Missing)));
+
+        let timestamp = Utc.timestamp_opt(1_700_000_000, 123_456_789).unwrap();
+        let stamp = bundle.stamp(
+            BuildInfo::new(timestamp)
+                .commit(" 3A6F2C1E ")
+                .toolchain("  "),
+        );
+        assert_eq!(
+            stamp.build,
+            BuildInfo {
+                commit: Some("3a6f2c1e".to_string()),
+                timestamp: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+                toolchain: None,
+            }
+        );
+        assert_eq!(
+            bundle.custom.as_ref().unwrap()[STAMP_KEY],
+            serde_json::json!({
+                "commit": "3a6f2c1e",
+                "timestamp": "2023-11-14T22:13:20Z",
+                "digest": stamp.digest,
+            })
+        );
+
+        // Provenance survives a round trip through the published form.
+        let republished: Bundle = serde_json::from_slice(&bundle.to_canonical_json()).unwrap();
+        assert_eq!(republished.verify_stamp().unwrap(), stamp);
+
+        // Restamping replaces the stamp rather than digesting the old one.
+        let mut restamped = bundle.clone();
+        assert_eq!(restamped.stamp(stamp.build.clone()), stamp);
+
+        let mut tampered = bundle.clone();
+        tampered
+            .custom
+            .as_mut()
+            .unwrap()
+            .get_mut(STAMP_KEY)
+            .unwrap()["commit"] = serde_json::json!("0000000");
+        assert!(matches!(
+            tampered.verify_stamp(),
+            Err(StampError::Mismatch { .. })
+        ));
+
+        bundle.custom.as_mut().unwrap().insert(
+            STAMP_KEY.to_string(),
+            serde_json::json!({ "commit": "3a6f2c1e" }),
+        );
+        assert!(matches!(bundle.verify_stamp(), Err(StampError::Invalid(_))));
+    }
+}
@@ -0,0 +1,421 @@
+//! Property-testing support: proptest strategies that generate bundles valid
+//! against the CNAB Core specification, and `Arbitrary` impls built on them.
+//!
+//! Generated bundles have every required field, at least one invocation image,
+//! names made of `[a-z0-9._-]`, environment variables made of `[A-Z0-9_]`, and
+//! parameters and outputs whose `definition` names one of the bundle's definitions.
+//!
+//! ```
+//! use libcnab::testing;
+//! use proptest::prelude::*;
+//! use proptest::test_runner::TestRunner;
+//!
+//! TestRunner::default()
+//!     .run(&testing::bundle(), |bundle| {
+//!         let json = serde_json::to_string(&bundle).unwrap();
+//!         let parsed: libcnab::Bundle = json.parse().unwrap();
+//!         prop_assert_eq!(parsed.to_canonical_json(), bundle.to_canonical_json());
+//!         Ok(())
+//!     })
+//!     .unwrap();
+//! ```
+use crate::claim::{Response, Status};
+use crate::cnab::{
+    Action, Bundle, Credential, Destination, Image, InvocationImage, Maintainer, Output, Parameter,
+};
+use crate::env_var::EnvVarName;
+use proptest::arbitrary::Arbitrary;
+use proptest::collection::{btree_map, vec};
+use proptest::option;
+use proptest::prelude::*;
+use proptest::sample::select;
+use semver::Version;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// The schema version of generated bundles
+pub const SCHEMA_VERSION: &str = "v1.0.0";
+
+/// The actions every runtime provides, which a bundle's custom actions may not reuse
+const BUILT_IN_ACTIONS: &[&str] = &["install", "upgrade", "uninstall"];
+
+/// A name for a bundle, or for one of its parameters, credentials, outputs, images,
+/// actions or definitions.
+pub fn name() -> impl Strategy<Value = String> {
+    "[a-z0-9]([a-z0-9._-]{0,14}[a-z0-9])?"
+}
+
+/// The name of an environment variable.
+pub fn env_var() -> impl Strategy<Value = EnvVarName> {
+    "[A-Z_][A-Z0-9_]{0,15}".prop_map(|name| EnvVarName::new(name).unwrap())
+}
+
+/// A semantic version, sometimes a pre-release.
+pub fn version() -> impl Strategy<Value = Version> {
+    (
+        0..20u64,
+        0..20u64,
+        0..20u64,
+        option::of("(alpha|beta|rc)\\.[0-9]"),
+    )
+        .prop_map(|(major, minor, patch, pre)| {
+            let version = match pre {
+                Some(pre) => format!("{}.{}.{}-{}", major, minor, patch, pre),
+                None => format!("{}.{}.{}", major, minor, patch),
+            };
+            Version::parse(&version).expect("generated versions parse")
+        })
+}
+
+/// A reference to an OCI image, by tag.
+pub fn image_reference() -> impl Strategy<Value = String> {
+    "([a-z0-9]{1,8}\\.(com|io)/)?[a-z0-9]{1,8}(/[a-z0-9]{1,8})?:[a-z0-9][a-z0-9.]{0,7}"
+}
+
+/// A `sha256` content digest.
+pub fn digest() -> impl Strategy<Value = String> {
+    "[0-9a-f]{64}".prop_map(|hex| format!("sha256:{}", hex))
+}
+
+/// An absolute path inside an invocation image.
+pub fn path() -> impl Strategy<Value = PathBuf> {
+    vec("[a-z0-9_-]{1,8}", 1..4)
+        .prop_map(|segments| PathBuf::from(format!("/{}", segments.join("/"))))
+}
+
+/// An `applyTo` list: unset, or some of the built-in actions.
+pub fn apply_to() -> impl Strategy<Value = Option<Vec<String>>> {
+    option::of(
+        proptest::sample::subsequence(BUILT_IN_ACTIONS, 1..=BUILT_IN_ACTIONS.len())
+            .prop_map(|actions| actions.into_iter().map(String::from).collect()),
+    )
+}
+
+fn description() -> impl Strategy<Value = Option<String>> {
+    option::of("[A-Za-z0-9 ,.'-]{0,40}")
+}
+
+/// A JSON Schema definition for parameter and output values.
+pub fn definition() -> impl Strategy<Value = serde_json::Value> {
+    prop_oneof![
+        Just(serde_json::json!({ "type": "string" })),
+        Just(serde_json::json!({ "type": "string", "writeOnly": true })),
+        any::<i64>().prop_map(|d| serde_json::json!({ "type": "integer", "default": d })),
+        any::<bool>().prop_map(|d| serde_json::json!({ "type": "boolean", "default": d })),
+        Just(serde_json::json!({ "type": "string", "contentEncoding": "base64" })),
+    ]
+}
+
+/// Where a parameter is placed: an environment variable, a file, or both.
+pub fn destination() -> impl Strategy<Value = Destination> {
+    prop_oneof![
+        env_var().prop_map(|env| Destination {
+            env: Some(env),
+            path: None,
+        }),
+        path().prop_map(|path| Destination {
+            env: None,
+            path: Some(path),
+        }),
+        (env_var(), path()).prop_map(|(env, path)| Destination {
+            env: Some(env),
+            path: Some(path),
+        }),
+    ]
+}
+
+/// A parameter described by one of `definitions`, which must not be empty.
+pub fn parameter(definitions: Vec<String>) -> impl Strategy<Value = Parameter> {
+    (
+        apply_to(),
+        select(definitions),
+        description(),
+        destination(),
+        option::of(any::<bool>()),
+    )
+        .prop_map(
+            |(apply_to, definition, description, destination, required)| Parameter {
+                apply_to,
+                definition: Some(definition),
+                description,
+                destination,
+                required,
+            },
+        )
+}
+
+/// An output described by one of `definitions`, which must not be empty, written to
+/// `/cnab/app/outputs/<name>`.
+pub fn output(name: &str, definitions: Vec<String>) -> impl Strategy<Value = Output> {
+    let path = PathBuf::from("/cnab/app/outputs").join(name);
+    (apply_to(), select(definitions), description()).prop_map(
+        move |(apply_to, definition, description)| Output {
+            apply_to,
+            definition,
+            description,
+            path: Some(path.clone()),
+        },
+    )
+}
+
+/// The parameters and outputs of a bundle
+type Described = (
+    Option<BTreeMap<String, Parameter>>,
+    Option<BTreeMap<String, Output>>,
+);
+
+/// The parameters and outputs of a bundle with `definitions`.
+fn described_by(definitions: &BTreeMap<String, serde_json::Value>) -> BoxedStrategy<Described> {
+    let names: Vec<String> = definitions.keys().cloned().collect();
+    if names.is_empty() {
+        return Just((None, None)).boxed();
+    }
+    let parameters = option::of(btree_map(name(), parameter(names.clone()), 1..4));
+    let outputs = option::of(vec(name(), 1..3).prop_flat_map(move |output_names| {
+        output_names
+            .into_iter()
+            .map(|name| output(&name, names.clone()).prop_map(move |o| (name.clone(), o)))
+            .collect::<Vec<_>>()
+            .prop_map(|outputs| outputs.into_iter().collect::<BTreeMap<_, _>>())
+    }));
+    (parameters, outputs).boxed()
+}
+
+/// A bundle valid against the CNAB Core specification.
+pub fn bundle() -> impl Strategy<Value = Bundle> {
+    let definitions = btree_map(name(), definition(), 0..4);
+    let described = definitions.prop_flat_map(|definitions| {
+        let described = described_by(&definitions);
+        (Just(definitions), described)
+    });
+    let actions = option::of(btree_map(
+        name().prop_filter("custom actions are not built in", |n| {
+            !BUILT_IN_ACTIONS.contains(&n.as_str())
+        }),
+        any::<Action>(),
+        1..3,
+    ));
+    (
+        (
+            actions,
+            option::of(btree_map(name(), any::<Credential>(), 1..4)),
+            described,
+            description(),
+            option::of(btree_map(name(), any::<Image>(), 1..3)),
+            vec(any::<InvocationImage>(), 1..3),
+        ),
+        (
+            option::of(vec("[a-z0-9-]{1,10}", 1..4)),
+            option::of(Just("MIT".to_string())),
+            option::of(vec(any::<Maintainer>(), 1..3)),
+            name(),
+            version(),
+        ),
+    )
+        .prop_map(
+            |(
+                (
+                    actions,
+                    credentials,
+                    (definitions, (parameters, outputs)),
+                    description,
+                    images,
+                    invocation_images,
+                ),
+                (keywords, license, maintainers, name, version),
+            )| Bundle {
+                actions,
+                credentials,
+                custom: None,
+                definitions: if definitions.is_empty() {
+                    None
+                } else {
+                    Some(definitions)
+                },
+                description,
+                images,
+                invocation_images,
+                keywords,
+                license,
+                maintainers,
+                name,
+                outputs,
+                parameters,
+                required_extensions: None,
+                schema_version: SCHEMA_VERSION.to_string(),
+                version,
+            },
+        )
+}
+
+impl Arbitrary for Bundle {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        bundle().boxed()
+    }
+}
+
+impl Arbitrary for InvocationImage {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            option::of(digest()),
+            image_reference(),
+            option::of(select(vec!["oci".to_string(), "docker".to_string()])),
+            option::of(0..1_000_000_000i64),
+        )
+            .prop_map(
+                |(content_digest, image, image_type, size)| InvocationImage {
+                    content_digest,
+                    image,
+                    image_type,
+                    media_type: None,
+                    size,
+                    labels: None,
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for Image {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            description(),
+            option::of(digest()),
+            image_reference(),
+            option::of(btree_map("[a-z]{1,8}", "[a-z0-9]{0,8}", 1..3)),
+        )
+            .prop_map(|(description, content_digest, image, labels)| Image {
+                description,
+                content_digest,
+                image,
+                image_type: Some("oci".to_string()),
+                media_type: None,
+                platform: None,
+                size: None,
+                labels,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Maintainer {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            "[A-Z][a-z]{1,8} [A-Z][a-z]{1,10}",
+            option::of("[a-z]{1,8}@example\\.com"),
+            option::of("https://example\\.com/[a-z]{1,8}"),
+        )
+            .prop_map(|(name, email, url)| Maintainer { email, name, url })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Credential {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    /// A credential placed in an environment variable, a file, or both.
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            apply_to(),
+            description(),
+            destination(),
+            option::of(any::<bool>()),
+        )
+            .prop_map(
+                |(apply_to, description, destination, required)| Credential {
+                    apply_to,
+                    description,
+                    env: destination.env,
+                    path: destination.path,
+                    required,
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for Action {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (description(), any::<bool>(), any::<bool>())
+            .prop_map(|(description, modifies, stateless)| Action {
+                description,
+                modifies,
+                stateless,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Status {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        select(vec![
+            Status::Success,
+            Status::Failure,
+            Status::Pending,
+            Status::Canceled,
+        ])
+        .boxed()
+    }
+}
+
+impl Arbitrary for Response {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (select(BUILT_IN_ACTIONS), any::<Status>(), description())
+            .prop_map(|(action, status, message)| Response::new(action, status, message))
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::extensions::ExtensionRegistry;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_bundles_are_valid(bundle in any::<Bundle>()) {
+            prop_assert!(!bundle.invocation_images.is_empty());
+            let definitions = bundle.definitions.clone().unwrap_or_default();
+            for parameter in bundle.parameters.iter().flat_map(|p| p.values()) {
+                let definition = parameter.definition.as_ref().unwrap();
+                prop_assert!(definitions.contains_key(definition));
+                prop_assert!(parameter.destination.env.is_some() || parameter.destination.path.is_some());
+            }
+            for output in bundle.outputs.iter().flat_map(|o| o.values()) {
+                prop_assert!(definitions.contains_key(&output.definition));
+            }
+            for action in bundle.actions.iter().flat_map(|a| a.keys()) {
+                prop_assert!(!BUILT_IN_ACTIONS.contains(&action.as_str()));
+            }
+            prop_assert!(bundle.validate(&ExtensionRegistry::default()).is_ok());
+
+            let json = String::from_utf8(bundle.to_canonical_json()).unwrap();
+            let parsed: Bundle = json.parse().unwrap();
+            prop_assert_eq!(parsed.to_canonical_json(), json.into_bytes());
+        }
+    }
+}
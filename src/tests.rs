@@ -1,3 +1,8 @@
+#![allow(
+    clippy::needless_borrows_for_generic_args,
+    clippy::unnecessary_to_owned
+)]
+
 use crate::cnab::*;
 use semver::Version;
 use spectral::prelude::*;
@@ -46,6 +51,36 @@ fn test_bundle_keywords() {
     assert_that(&kw[2]).is_equal_to("c".to_string());
 }
 
+// Test tidying keywords for indexing
+#[test]
+fn test_bundle_normalize_keywords() {
+    let mut bun: Bundle = r#"{
+        "name": "aristotle",
+        "invocationImages": [],
+        "schemaVersion": "1.0",
+        "version": "1.0.0",
+        "keywords": [" Database", "mysql", "", "DATABASE", "CNAB", "bundle "]
+    }"#
+    .parse()
+    .unwrap();
+
+    bun.normalize_keywords();
+    assert_that(&bun.keywords.clone().unwrap()).is_equal_to(
+        ["database", "mysql", "cnab", "bundle"]
+            .iter()
+            .map(|k| k.to_string())
+            .collect::<Vec<_>>(),
+    );
+
+    bun.normalize_keywords_with(&["cnab", "Bundle"]);
+    assert_that(&bun.keywords.clone().unwrap())
+        .is_equal_to(vec!["database".to_string(), "mysql".to_string()]);
+
+    bun.keywords = None;
+    bun.normalize_keywords();
+    assert_that(&bun.keywords).is_none();
+}
+
 #[test]
 fn test_bundle_actions() {
     let bun: Bundle = r#"{
@@ -140,9 +175,9 @@ fn test_bundle_parameters() {
         assert!(&arg1.required.is_none());
 
         // Destination should have just env
-        assert_that(&arg1.destination.env.as_ref())
+        assert_that(&arg1.destination.env.as_deref())
             .is_some()
-            .is_equal_to(&"FIRST".to_string());
+            .is_equal_to("FIRST");
         assert_that(&arg1.destination.path).is_none();
         assert_that(&arg1.description).is_equal_to(Some("this is a description".into()));
         assert_that(&arg1.definition).is_equal_to(Some("somedef".into()));
@@ -173,8 +208,8 @@ fn test_bundle_parameters() {
         assert!(apply.is_some());
 
         let dest = &arg3.unwrap().destination;
-        let env = &dest.env;
-        assert_that(&env).is_equal_to(&Some("LETTERS".to_string()));
+        let env = dest.env.as_deref();
+        assert_that(&env).is_equal_to(Some("LETTERS"));
 
         let path = &dest.path;
         assert_that(path)
@@ -290,9 +325,9 @@ fn test_bundle_credentials() {
     assert_that(&first.description)
         .is_some()
         .is_equal_to("token".to_string());
-    assert_that(&first.env)
+    assert_that(&first.env.as_deref())
         .is_some()
-        .is_equal_to("TOKEN".to_string());
+        .is_equal_to("TOKEN");
     assert_that(&first.path).is_none();
 
     let second = &creds.get(&"myconfig".to_string()).unwrap();
@@ -310,6 +345,44 @@ fn test_bundle_credentials() {
     assert_that(&third.path).is_some();
 }
 
+// Test which required credentials are missing for an action
+#[test]
+fn test_bundle_missing_credentials() {
+    let bun: Bundle = r#"{
+        "name": "aristotle",
+        "invocationImages": [],
+        "schemaVersion": "1.0",
+        "version": "1.0.0",
+        "credentials": {
+            "kubeconfig": {
+                "path": "/root/.kube/config",
+                "required": true
+            },
+            "backup_token": {
+                "applyTo": ["backup"],
+                "env": "BACKUP_TOKEN",
+                "required": true
+            },
+            "optional": {
+                "env": "OPTIONAL"
+            }
+        }
+    }"#
+    .parse()
+    .unwrap();
+
+    let none: &[&str] = &[];
+    assert_that(&bun.missing_credentials("install", none))
+        .is_equal_to(vec!["kubeconfig".to_string()]);
+    assert_that(&bun.missing_credentials("install", &["kubeconfig"])).is_equal_to(vec![]);
+    assert_that(&bun.missing_credentials("backup", &["kubeconfig"]))
+        .is_equal_to(vec!["backup_token".to_string()]);
+
+    let creds = bun.credentials.as_ref().unwrap();
+    assert_that(&creds["backup_token"].applies_to("install")).is_false();
+    assert_that(&creds["optional"].applies_to("install")).is_true();
+}
+
 // Test invocation images and regular images
 #[test]
 fn test_bundle_images() {
@@ -402,3 +475,78 @@ fn test_bundle_from_file_not_found() {
     let bun = Bundle::from_file("no/such/file.json");
     assert_that(&bun.is_err()).is_true();
 }
+
+#[test]
+fn test_bundle_unsupported_schema_version() {
+    let json = |schema_version: &str| {
+        format!(
+            r#"{{
+                "name": "aristotle",
+                "invocationImages": [],
+                "schemaVersion": "{}",
+                "version": "1.0.0"
+            }}"#,
+            schema_version
+        )
+    };
+    for supported in &[
+        "v1.0.0",
+        "1.0",
+        "v1.0.1",
+        "v1.0.0-WD",
+        "v0.2",
+        "not a version",
+    ] {
+        assert_that(&Bundle::from_json(json(supported).as_bytes()).is_ok()).is_true();
+    }
+
+    let newer = Bundle::from_json(json("v1.3.0").as_bytes());
+    match newer {
+        Err(BundleParseError::UnsupportedSchemaVersion { found, supported }) => {
+            assert_that(&found).is_equal_to("v1.3.0".to_string());
+            assert_that(&supported).is_equal_to(SUPPORTED_SCHEMA_VERSIONS);
+        }
+        other => panic!("expected an unsupported schemaVersion, got {:?}", other),
+    }
+
+    // A later version is reported rather than the fields it changed.
+    let changed = r#"{
+        "name": "aristotle",
+        "invocationImages": {"default": {"image": "technosophos/aristotle:2.0.0"}},
+        "schemaVersion": "v2.0.0",
+        "version": "1.0.0"
+    }"#;
+    let err = Bundle::from_json(changed.as_bytes()).unwrap_err();
+    assert_that(&err.to_string()).is_equal_to(
+        "bundle is of schemaVersion v2.0.0, newer than the supported v1.0.0".to_string(),
+    );
+
+    // Parsing from a string checks the version as well.
+    match changed.parse::<Bundle>() {
+        Err(BundleParseError::UnsupportedSchemaVersion { found, .. }) => {
+            assert_that(&found).is_equal_to("v2.0.0".to_string())
+        }
+        other => panic!("expected an unsupported schemaVersion, got {:?}", other),
+    }
+    assert_that(&json("v1.3.0").parse::<Bundle>().is_err()).is_true();
+}
+
+#[cfg(feature = "schemars")]
+#[test]
+fn test_json_schema() {
+    let schema = serde_json::to_value(schemars::schema_for!(Bundle)).unwrap();
+    let required = schema["required"].as_array().unwrap();
+    for field in &["name", "version", "schemaVersion", "invocationImages"] {
+        assert!(required.contains(&serde_json::json!(field)), "{}", field);
+    }
+    assert_eq!(schema["properties"]["version"]["type"], "string");
+    assert!(schema["definitions"]["InvocationImage"].is_object());
+
+    let schema = serde_json::to_value(schemars::schema_for!(crate::Claim)).unwrap();
+    assert_eq!(schema["properties"]["created"]["format"], "date-time");
+    let status = schema["definitions"]["Status"].to_string();
+    assert!(status.contains(r#""canceled""#), "{}", status);
+
+    let schema = serde_json::to_value(schemars::schema_for!(crate::CredentialSet)).unwrap();
+    assert!(schema["definitions"]["CredentialSource"]["properties"]["vault"].is_object());
+}
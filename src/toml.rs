@@ -0,0 +1,143 @@
+use crate::cnab::{check_schema_version, Bundle, BundleParseError};
+use crate::profile::ValidationProfile;
+use std::path::Path;
+
+/// The line that opens and closes TOML front matter
+const FRONT_MATTER_DELIMITER: &str = "+++";
+
+/// Bundle descriptors may be authored in TOML, alongside the rest of a project's
+/// metadata. TOML is only read: the bundle is written out, digested and signed as JSON,
+/// see `Bundle::to_canonical_json`. TOML has no null, so optional fields are left out
+/// rather than set to null.
+///
+/// ```
+/// use libcnab::Bundle;
+///
+/// let authored = Bundle::from_toml_file("testdata/bundle.toml").unwrap();
+/// let shipped = Bundle::from_file("testdata/bundle.json").unwrap();
+/// assert_eq!(authored.to_canonical_json(), shipped.to_canonical_json());
+/// ```
+impl Bundle {
+    /// Deserialize a `Bundle` from a TOML file.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(path = %path.as_ref().display()), err)
+    )]
+    pub fn from_toml_file<P: AsRef<Path>>(path: P) -> Result<Self, BundleParseError> {
+        let toml = std::fs::read_to_string(path)?;
+        Self::from_toml_str(&toml)
+    }
+
+    /// Deserialize a `Bundle` from a TOML document.
+    pub fn from_toml_str(toml: &str) -> Result<Self, BundleParseError> {
+        Self::from_toml_str_with(toml, &ValidationProfile::Strict1_0)
+    }
+
+    /// Deserialize a `Bundle` from a TOML document, enforcing only the parsing rules
+    /// of `profile`.
+    pub fn from_toml_str_with(
+        toml: &str,
+        profile: &ValidationProfile,
+    ) -> Result<Self, BundleParseError> {
+        let bundle: Bundle = ::toml::from_str(toml)?;
+        check_schema_version(&bundle.schema_version, profile)?;
+        Ok(bundle)
+    }
+
+    /// Deserialize a `Bundle` from the TOML front matter of a document, such as a
+    /// README: the lines between a first line of `+++` and the next line of `+++`.
+    ///
+    /// Returns `None` if the document does not start with front matter.
+    pub fn from_toml_front_matter(document: &str) -> Result<Option<Self>, BundleParseError> {
+        let mut lines = document.lines();
+        if lines.next().map(str::trim_end) != Some(FRONT_MATTER_DELIMITER) {
+            return Ok(None);
+        }
+        let mut toml = String::new();
+        for line in lines {
+            if line.trim_end() == FRONT_MATTER_DELIMITER {
+                return Self::from_toml_str(&toml).map(Some);
+            }
+            toml.push_str(line);
+            toml.push('\n');
+        }
+        Ok(None)
+    }
+}
+
+impl From<::toml::de::Error> for BundleParseError {
+    fn from(error: ::toml::de::Error) -> Self {
+        BundleParseError::TomlError(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_toml_fields() {
+        let golden = Bundle::from_file("testdata/bundle.json").unwrap();
+        let bundle = Bundle::from_toml_file("testdata/bundle.toml").unwrap();
+        assert_eq!(bundle.to_canonical_json(), golden.to_canonical_json());
+
+        assert_eq!(bundle.schema_version, "v1.0.0");
+        assert_eq!(bundle.maintainers.as_ref().unwrap()[0].name, "Matt Butcher");
+        let invocation = &bundle.invocation_images[0];
+        assert_eq!(invocation.image, "technosophos/helloworld:0.1.0");
+        assert_eq!(invocation.image_type.as_deref(), Some("docker"));
+        let images = bundle.images.as_ref().unwrap();
+        assert_eq!(
+            images["my-microservice"].image,
+            "technosophos/microservice:1.2.3"
+        );
+        let parameter = &bundle.parameters.as_ref().unwrap()["backend_port"];
+        assert_eq!(parameter.destination.env.as_deref(), Some("BACKEND_PORT"));
+        let credential = &bundle.credentials.as_ref().unwrap()["hostkey"];
+        assert_eq!(
+            credential.path.as_deref(),
+            Some(Path::new("/etc/hostkey.txt"))
+        );
+        assert_eq!(
+            bundle.custom.as_ref().unwrap()["com.example.backup-preferences"]["frequency"],
+            "daily"
+        );
+    }
+
+    #[test]
+    fn test_toml_front_matter() {
+        let toml = std::fs::read_to_string("testdata/bundle.toml").unwrap();
+        let document = format!("+++\n{}+++\n\n# helloworld\n", toml);
+        let bundle = Bundle::from_toml_front_matter(&document).unwrap().unwrap();
+        assert_eq!(bundle.name, "helloworld");
+
+        assert!(Bundle::from_toml_front_matter("# helloworld\n")
+            .unwrap()
+            .is_none());
+        assert!(Bundle::from_toml_front_matter("+++\nname = \"unclosed\"\n")
+            .unwrap()
+            .is_none());
+        match Bundle::from_toml_front_matter("+++\nname = [\n+++\n") {
+            Err(e @ BundleParseError::TomlError(_)) => {
+                assert!(e.to_string().starts_with("invalid bundle TOML: "))
+            }
+            other => panic!(
+                "expected a TOML error, got {:?}",
+                other.map(|b| b.is_some())
+            ),
+        }
+    }
+
+    #[test]
+    fn test_toml_profiles() {
+        let toml = std::fs::read_to_string("testdata/bundle.toml")
+            .unwrap()
+            .replace("\"v1.0.0\"", "\"v1.1.0\"");
+        assert!(matches!(
+            Bundle::from_toml_str(&toml),
+            Err(BundleParseError::UnsupportedSchemaVersion { .. })
+        ));
+        let bundle = Bundle::from_toml_str_with(&toml, &ValidationProfile::Permissive).unwrap();
+        assert_eq!(bundle.schema_version, "v1.1.0");
+    }
+}
@@ -0,0 +1,580 @@
+//! Trust policies: which signatures a bundle needs before it is trusted, kept as
+//! configuration rather than code.
+//!
+//! A `TrustPolicy` is a JSON document of rules. A rule applies to the bundles whose
+//! name, or the repository of the reference they come from, matches its `scope`,
+//! where `*` matches any characters. The first rule that applies to a bundle
+//! decides: it names the Ed25519 keys and Sigstore identities trusted to sign it,
+//! each until it `expires`, and the types of signature the bundle must carry. A
+//! bundle no rule applies to is not trusted.
+//!
+//! `TrustPolicy::evaluate` checks a bundle's signatures with `signing::verify`, a
+//! Sigstore `Verifier` and `Bundle::provenance`, and with the `drivers` feature
+//! `TrustPolicy::check` makes the policy an `ExecutionPolicy` for the engine.
+//!
+//! ```
+//! use libcnab::signing::{self, SigningKey};
+//! use libcnab::trust::{Signatures, TrustPolicy};
+//! use libcnab::Bundle;
+//!
+//! let key = SigningKey::generate();
+//! let policy = TrustPolicy::from_json(&serde_json::to_vec(&serde_json::json!({
+//!     "rules": [
+//!         {
+//!             "scope": "registry.example.com/bundles/*",
+//!             "keys": [{ "key": key.public_key().to_string(), "expires": "2099-01-01T00:00:00Z" }],
+//!             "require": ["key"]
+//!         },
+//!         { "scope": "*", "allowUnsigned": true }
+//!     ]
+//! })).unwrap())
+//! .unwrap();
+//!
+//! let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+//! let signatures = Signatures::new().key(signing::sign(&bundle, &key));
+//! let now = chrono::Utc::now();
+//! let trusted = policy
+//!     .evaluate("registry.example.com/bundles/hello:0.1.2", &bundle, &signatures, now)
+//!     .unwrap();
+//! assert_eq!(trusted.scope, "registry.example.com/bundles/*");
+//! assert!(policy
+//!     .evaluate("registry.example.com/bundles/hello:0.1.2", &bundle, &Signatures::new(), now)
+//!     .is_err());
+//! assert!(policy.evaluate("example.com/other", &bundle, &Signatures::new(), now).is_ok());
+//! ```
+use crate::cnab::Bundle;
+#[cfg(feature = "drivers")]
+use crate::engine::{ExecutionPolicy, PolicyInput};
+use crate::error_code::{Coded, ErrorCode};
+use crate::reference::BundleReference;
+use crate::signing::{self, PublicKey, Signature, SignatureError};
+#[cfg(feature = "sigstore")]
+use crate::sigstore::{SigstoreBundle, TrustRoot, Verifier};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+/// TrustPolicy is the rules that decide which bundles are trusted, in the order they
+/// are matched.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustPolicy {
+    pub rules: Vec<TrustRule>,
+    /// The Fulcio and Rekor instances Sigstore signatures are checked against
+    #[cfg(feature = "sigstore")]
+    #[serde(skip)]
+    trust_root: Option<TrustRoot>,
+}
+
+/// TrustRule is what a bundle in its scope must be signed by.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustRule {
+    /// The bundle names or repositories the rule applies to, such as
+    /// `registry.example.com/bundles/*`
+    pub scope: String,
+    /// The Ed25519 keys trusted to sign the bundles and their provenance
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keys: Vec<TrustedKey>,
+    /// The Sigstore identities trusted to sign the bundles
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub identities: Vec<TrustedIdentity>,
+    /// The types of signature the bundles must all carry. When none is given, any
+    /// one trusted signature is enough.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub require: Vec<SignatureType>,
+    /// Trust the bundles without any signature, unless `require` names some
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_unsigned: bool,
+}
+
+/// TrustedKey is an Ed25519 public key, as `PublicKey` writes them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrustedKey {
+    pub key: String,
+    /// When the key stops being trusted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires: Option<DateTime<Utc>>,
+}
+
+/// TrustedIdentity is an OIDC identity Fulcio certifies keys for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrustedIdentity {
+    pub issuer: String,
+    pub subject: String,
+    /// When the identity stops being trusted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires: Option<DateTime<Utc>>,
+}
+
+/// SignatureType is a kind of signature a `TrustRule` can require.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureType {
+    /// A detached Ed25519 signature of the bundle, by one of the rule's keys
+    Key,
+    /// A Sigstore signature of the bundle, by one of the rule's identities. Only
+    /// checked with the `sigstore` feature, and a trust root set with
+    /// `TrustPolicy::trust_root`.
+    Sigstore,
+    /// Provenance the bundle carries, signed by one of the rule's keys
+    Provenance,
+}
+
+impl fmt::Display for SignatureType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SignatureType::Key => "key",
+            SignatureType::Sigstore => "sigstore",
+            SignatureType::Provenance => "provenance",
+        })
+    }
+}
+
+/// Signatures are the detached signatures of a bundle to check against a policy.
+#[derive(Debug, Clone, Default)]
+pub struct Signatures {
+    keyed: Vec<Signature>,
+    #[cfg(feature = "sigstore")]
+    sigstore: Vec<SigstoreBundle>,
+}
+
+impl Signatures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an Ed25519 signature.
+    pub fn key(mut self, signature: Signature) -> Self {
+        self.keyed.push(signature);
+        self
+    }
+
+    /// Add a Sigstore signature.
+    #[cfg(feature = "sigstore")]
+    pub fn sigstore(mut self, signature: SigstoreBundle) -> Self {
+        self.sigstore.push(signature);
+        self
+    }
+}
+
+/// Verified is a signature of a bundle the policy trusts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verified {
+    pub signature_type: SignatureType,
+    /// The key ID of the key, the subject of the identity, or the builder of the
+    /// provenance
+    pub signer: String,
+}
+
+/// Trusted is how a bundle met a policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trusted {
+    /// The scope of the rule that applied
+    pub scope: String,
+    /// The trusted signatures, at most one of each type
+    pub verified: Vec<Verified>,
+}
+
+impl TrustPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a policy document, checking that its keys parse.
+    pub fn from_json(json: &[u8]) -> Result<Self, TrustError> {
+        let policy: TrustPolicy =
+            serde_json::from_slice(json).map_err(|e| TrustError::Invalid(e.to_string()))?;
+        for key in policy.rules.iter().flat_map(|r| &r.keys) {
+            key.key
+                .parse::<PublicKey>()
+                .map_err(TrustError::InvalidKey)?;
+        }
+        Ok(policy)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, TrustError> {
+        let json = std::fs::read(path).map_err(|e| TrustError::Invalid(e.to_string()))?;
+        Self::from_json(&json)
+    }
+
+    /// Add a rule, matched after those the policy has.
+    pub fn rule(mut self, rule: TrustRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Check Sigstore signatures against `trust_root`.
+    #[cfg(feature = "sigstore")]
+    pub fn trust_root(mut self, trust_root: TrustRoot) -> Self {
+        self.trust_root = Some(trust_root);
+        self
+    }
+
+    /// The rule that applies to `subject`, a bundle name or a reference: the first
+    /// whose scope matches it, or the repository of the reference.
+    pub fn rule_for(&self, subject: &str) -> Option<&TrustRule> {
+        let repository = BundleReference::parse(subject).ok().map(|r| r.name());
+        self.rules.iter().find(|rule| {
+            matches(&rule.scope, subject)
+                || repository
+                    .as_deref()
+                    .is_some_and(|repository| matches(&rule.scope, repository))
+        })
+    }
+
+    /// The keys the rule for `subject` trusts at `now`, to give `signing::verify` or
+    /// `Bundle::provenance`.
+    pub fn trusted_keys(&self, subject: &str, now: DateTime<Utc>) -> Vec<PublicKey> {
+        self.rule_for(subject)
+            .map(|rule| rule.trusted_keys(now))
+            .unwrap_or_default()
+    }
+
+    /// A Sigstore verifier that allows the identities the rule for `subject` trusts
+    /// at `now`, if the policy has a trust root.
+    #[cfg(feature = "sigstore")]
+    pub fn sigstore_verifier(&self, subject: &str, now: DateTime<Utc>) -> Option<Verifier> {
+        let rule = self.rule_for(subject)?;
+        let trust_root = self.trust_root.clone()?;
+        let verifier = rule
+            .identities
+            .iter()
+            .filter(|identity| unexpired(identity.expires, now))
+            .fold(Verifier::new(trust_root), |verifier, identity| {
+                verifier.identity(&identity.issuer, &identity.subject)
+            });
+        Some(verifier)
+    }
+
+    /// Decide whether `bundle`, pulled from or named `subject`, is trusted at `now`
+    /// with `signatures`.
+    pub fn evaluate(
+        &self,
+        subject: &str,
+        bundle: &Bundle,
+        signatures: &Signatures,
+        now: DateTime<Utc>,
+    ) -> Result<Trusted, TrustError> {
+        let rule = self
+            .rule_for(subject)
+            .ok_or_else(|| TrustError::NoRule(subject.to_string()))?;
+        let keys = rule.trusted_keys(now);
+        let mut verified = Vec::new();
+        let signer = signatures
+            .keyed
+            .iter()
+            .find_map(|signature| signing::verify(bundle, signature, &keys).ok());
+        if let Some(key) = signer {
+            verified.push(Verified {
+                signature_type: SignatureType::Key,
+                signer: key.key_id(),
+            });
+        }
+        #[cfg(feature = "sigstore")]
+        if let Some(verifier) = self.sigstore_verifier(subject, now) {
+            let identity = signatures
+                .sigstore
+                .iter()
+                .find_map(|signature| verifier.verify_bundle(bundle, signature).ok());
+            if let Some(identity) = identity {
+                verified.push(Verified {
+                    signature_type: SignatureType::Sigstore,
+                    signer: identity.subject,
+                });
+            }
+        }
+        if let Ok(Some(provenance)) = bundle.provenance(&keys) {
+            verified.push(Verified {
+                signature_type: SignatureType::Provenance,
+                signer: provenance.builder_id().to_string(),
+            });
+        }
+
+        let missing: Vec<SignatureType> = rule
+            .require
+            .iter()
+            .filter(|t| !verified.iter().any(|v| v.signature_type == **t))
+            .copied()
+            .collect();
+        if !missing.is_empty() {
+            return Err(TrustError::Missing {
+                subject: subject.to_string(),
+                missing,
+            });
+        }
+        if verified.is_empty() && !rule.allow_unsigned {
+            return Err(TrustError::Unsigned(subject.to_string()));
+        }
+        Ok(Trusted {
+            scope: rule.scope.clone(),
+            verified,
+        })
+    }
+
+    /// An `ExecutionPolicy` that runs the bundles this policy trusts with
+    /// `signatures`.
+    #[cfg(feature = "drivers")]
+    pub fn check(&self, signatures: Signatures) -> TrustCheck<'_> {
+        TrustCheck {
+            policy: self,
+            signatures,
+        }
+    }
+}
+
+impl TrustRule {
+    fn trusted_keys(&self, now: DateTime<Utc>) -> Vec<PublicKey> {
+        self.keys
+            .iter()
+            .filter(|key| unexpired(key.expires, now))
+            .filter_map(|key| key.key.parse().ok())
+            .collect()
+    }
+}
+
+fn unexpired(expires: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    expires.is_none_or(|expires| now < expires)
+}
+
+/// Whether `pattern` matches all of `s`, where `*` in it matches any characters.
+fn matches(pattern: &str, s: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == s,
+        Some((prefix, rest)) => {
+            let s = match s.strip_prefix(prefix) {
+                Some(s) => s,
+                None => return false,
+            };
+            (0..=s.len())
+                .filter(|i| s.is_char_boundary(*i))
+                .any(|i| matches(rest, &s[i..]))
+        }
+    }
+}
+
+/// TrustCheck runs the bundles a `TrustPolicy` trusts, given their signatures. Rules
+/// are matched by bundle name, since the engine is given bundles rather than
+/// references, and expiry is checked as of when the action runs.
+#[cfg(feature = "drivers")]
+#[derive(Debug)]
+pub struct TrustCheck<'a> {
+    policy: &'a TrustPolicy,
+    signatures: Signatures,
+}
+
+#[cfg(feature = "drivers")]
+impl ExecutionPolicy for TrustCheck<'_> {
+    fn evaluate(&self, input: &PolicyInput<'_>) -> Result<(), String> {
+        self.policy
+            .evaluate(
+                &input.bundle.name,
+                input.bundle,
+                &self.signatures,
+                Utc::now(),
+            )
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// TrustError is why a policy could not be read, or does not trust a bundle.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrustError {
+    /// The policy document could not be read or parsed
+    Invalid(String),
+    /// A key of the policy could not be parsed
+    InvalidKey(SignatureError),
+    /// No rule of the policy applies to the bundle
+    NoRule(String),
+    /// The bundle has no signature the policy trusts
+    Unsigned(String),
+    /// The bundle lacks trusted signatures of types its rule requires
+    Missing {
+        subject: String,
+        missing: Vec<SignatureType>,
+    },
+}
+
+impl fmt::Display for TrustError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrustError::Invalid(e) => write!(f, "invalid trust policy: {}", e),
+            TrustError::InvalidKey(e) => write!(f, "invalid trust policy: {}", e),
+            TrustError::NoRule(subject) => {
+                write!(f, "no trust policy rule applies to {}", subject)
+            }
+            TrustError::Unsigned(subject) => {
+                write!(f, "{} has no trusted signature", subject)
+            }
+            TrustError::Missing { subject, missing } => {
+                let missing: Vec<String> = missing.iter().map(ToString::to_string).collect();
+                write!(
+                    f,
+                    "{} has no trusted signature of type {}",
+                    subject,
+                    missing.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TrustError {}
+
+impl Coded for TrustError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            TrustError::Invalid(_) => ErrorCode::TrustInvalid,
+            TrustError::InvalidKey(e) => e.code(),
+            TrustError::NoRule(_) => ErrorCode::TrustNoRule,
+            TrustError::Unsigned(_) => ErrorCode::TrustUnsigned,
+            TrustError::Missing { .. } => ErrorCode::TrustMissingSignatures,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::attestation::Provenance;
+    use crate::signing::SigningKey;
+    use chrono::TimeZone;
+
+    fn rule(scope: &str, keys: &[(&SigningKey, Option<DateTime<Utc>>)]) -> TrustRule {
+        TrustRule {
+            scope: scope.to_string(),
+            keys: keys
+                .iter()
+                .map(|(key, expires)| TrustedKey {
+                    key: key.public_key().to_string(),
+                    expires: *expires,
+                })
+                .collect(),
+            ..TrustRule::default()
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("*", "anything"));
+        assert!(matches("example.com/*", "example.com/a/b"));
+        assert!(matches("example.com/*/web", "example.com/team/web"));
+        assert!(!matches("example.com/*/web", "example.com/team/api"));
+        assert!(matches("hello", "hello"));
+        assert!(!matches("hello", "hello-world"));
+    }
+
+    #[test]
+    fn test_trust_policy() {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let (release, builder, retired) = (
+            SigningKey::generate(),
+            SigningKey::generate(),
+            SigningKey::generate(),
+        );
+        let retirement = Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap();
+        let policy = TrustPolicy::new()
+            .rule(TrustRule {
+                require: vec![SignatureType::Key, SignatureType::Provenance],
+                ..rule(
+                    "example.com/release/*",
+                    &[(&release, None), (&builder, None)],
+                )
+            })
+            .rule(rule(
+                "example.com/*",
+                &[(&release, None), (&retired, Some(retirement))],
+            ));
+        let json = serde_json::to_vec(&policy).unwrap();
+        let policy = TrustPolicy::from_json(&json).unwrap();
+        let now = Utc::now();
+
+        let signatures = Signatures::new().key(signing::sign(&bundle, &release));
+        let trusted = policy
+            .evaluate("example.com/dev/hello:0.1.2", &bundle, &signatures, now)
+            .unwrap();
+        assert_eq!(trusted.scope, "example.com/*");
+        assert_eq!(
+            trusted.verified,
+            vec![Verified {
+                signature_type: SignatureType::Key,
+                signer: release.public_key().key_id(),
+            }]
+        );
+
+        // Release bundles need provenance as well.
+        assert_eq!(
+            policy.evaluate("example.com/release/hello", &bundle, &signatures, now),
+            Err(TrustError::Missing {
+                subject: "example.com/release/hello".to_string(),
+                missing: vec![SignatureType::Provenance],
+            })
+        );
+        let provenance = Provenance::new("https://ci.example.com/builders/release");
+        bundle.add_provenance(&provenance, &builder);
+        let signatures = Signatures::new().key(signing::sign(&bundle, &release));
+        let trusted = policy
+            .evaluate("example.com/release/hello", &bundle, &signatures, now)
+            .unwrap();
+        assert_eq!(trusted.verified.len(), 2);
+
+        // A key is not trusted once it expired, and other bundles not at all.
+        let signatures = Signatures::new().key(signing::sign(&bundle, &retired));
+        assert_eq!(
+            policy.evaluate("example.com/dev/hello", &bundle, &signatures, now),
+            Err(TrustError::Unsigned("example.com/dev/hello".to_string()))
+        );
+        let before = Utc.with_ymd_and_hms(2019, 6, 1, 0, 0, 0).unwrap();
+        assert!(policy
+            .evaluate("example.com/dev/hello", &bundle, &signatures, before)
+            .is_ok());
+        assert_eq!(
+            policy.evaluate("other.com/hello", &bundle, &signatures, now),
+            Err(TrustError::NoRule("other.com/hello".to_string()))
+        );
+        assert_eq!(policy.trusted_keys("example.com/dev/hello", now).len(), 1);
+
+        assert!(matches!(
+            TrustPolicy::from_json(br#"{"rules": [{"scope": "*", "keys": [{"key": "nope"}]}]}"#),
+            Err(TrustError::InvalidKey(_))
+        ));
+    }
+
+    #[cfg(feature = "drivers")]
+    #[test]
+    fn test_trust_check() {
+        use crate::claimstore::MemoryClaimStore;
+        use crate::driver::DebugDriver;
+        use crate::engine::{Engine, EngineError};
+        use crate::secrets::SecretResolver;
+
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let key = SigningKey::generate();
+        let policy = TrustPolicy::new().rule(rule("helloworld", &[(&key, None)]));
+        let credentials: Vec<crate::CredentialSet> = vec![serde_json::from_str(
+            r#"{"name": "dev", "credentials": [{"name": "hostkey", "source": {"value": "key"}}]}"#,
+        )
+        .unwrap()];
+        let (driver, claims) = (DebugDriver::new(), MemoryClaimStore::new());
+
+        let unsigned = policy.check(Signatures::new());
+        let engine = Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .policy(&unsigned);
+        match engine.install("hello", &bundle, &[], &credentials) {
+            Err(EngineError::Denied(reason)) => {
+                assert_eq!(reason, "helloworld has no trusted signature")
+            }
+            other => panic!("expected a denial, got {:?}", other),
+        }
+
+        let signed = policy.check(Signatures::new().key(signing::sign(&bundle, &key)));
+        Engine::new(&driver, &claims)
+            .secrets(SecretResolver::empty())
+            .policy(&signed)
+            .install("hello", &bundle, &[], &credentials)
+            .expect("trusted bundles run");
+    }
+}
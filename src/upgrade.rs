@@ -0,0 +1,266 @@
+//! Plan upgrades from one version of a bundle to another.
+use crate::cnab::{Bundle, Parameter};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// ParameterDelta is how the parameters change from one version of a bundle to
+/// another, so that an installer can ask only for what the upgrade needs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParameterDelta {
+    /// The parameters that need a value given to upgrade: these the new version
+    /// requires without a default, and the old version did not, or did not have
+    pub required: Vec<String>,
+    /// The parameters new to the new version that need no value given
+    pub added: Vec<String>,
+    /// The parameters of the old version the new version does not have
+    pub removed: Vec<String>,
+    /// The parameters of both versions whose default changed
+    pub defaults: Vec<DefaultChange>,
+    /// The parameters of both versions whose schema, other than the default, changed
+    pub constraints: Vec<ConstraintChange>,
+}
+
+/// DefaultChange is how the default of a parameter changed, `None` being none.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DefaultChange {
+    pub name: String,
+    pub from: Option<Value>,
+    pub to: Option<Value>,
+}
+
+/// ConstraintChange is how the schema of a parameter changed, without its default.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConstraintChange {
+    pub name: String,
+    pub from: Value,
+    pub to: Value,
+}
+
+/// How the parameters change from `old` to `new`.
+///
+/// ```
+/// use libcnab::upgrade::parameter_delta;
+/// use libcnab::{Bundle, Destination, Parameter};
+///
+/// let old = Bundle::from_file("testdata/bundle.json").unwrap();
+/// let mut new = old.clone();
+/// new.parameters.as_mut().unwrap().insert(
+///     "region".to_string(),
+///     Parameter {
+///         apply_to: None,
+///         definition: None,
+///         description: None,
+///         destination: Destination { env: "REGION".parse().ok(), path: None },
+///         required: Some(true),
+///     },
+/// );
+/// assert_eq!(parameter_delta(&old, &new).required, vec!["region"]);
+/// ```
+pub fn parameter_delta(old: &Bundle, new: &Bundle) -> ParameterDelta {
+    let mut delta = ParameterDelta::default();
+    let (olds, news) = (parameters(old), parameters(new));
+    for (name, _) in &olds {
+        if !news.iter().any(|(n, _)| n == name) {
+            delta.removed.push(name.to_string());
+        }
+    }
+    for (name, parameter) in &news {
+        let schema = schema(new, parameter);
+        let previous = olds.iter().find(|(n, _)| n == name);
+        if needs_value(parameter, &schema)
+            && !previous.is_some_and(|(_, p)| needs_value(p, &self::schema(old, p)))
+        {
+            delta.required.push(name.to_string());
+        } else if previous.is_none() {
+            delta.added.push(name.to_string());
+        }
+        let (_, previous) = match previous {
+            Some(previous) => previous,
+            None => continue,
+        };
+        let (mut from, mut to) = (self::schema(old, previous), schema);
+        let (from_default, to_default) = (from.remove("default"), to.remove("default"));
+        if from_default != to_default {
+            delta.defaults.push(DefaultChange {
+                name: name.to_string(),
+                from: from_default,
+                to: to_default,
+            });
+        }
+        if from != to {
+            delta.constraints.push(ConstraintChange {
+                name: name.to_string(),
+                from: Value::Object(from),
+                to: Value::Object(to),
+            });
+        }
+    }
+    delta
+}
+
+impl ParameterDelta {
+    /// Whether the parameters do not change.
+    pub fn is_empty(&self) -> bool {
+        self == &ParameterDelta::default()
+    }
+}
+
+impl fmt::Display for ParameterDelta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in &self.required {
+            writeln!(f, "requires parameter {}", name)?;
+        }
+        for name in &self.added {
+            writeln!(f, "added parameter {}", name)?;
+        }
+        for name in &self.removed {
+            writeln!(f, "removed parameter {}", name)?;
+        }
+        for change in &self.defaults {
+            let show = |v: &Option<Value>| v.as_ref().map_or("none".to_string(), Value::to_string);
+            writeln!(
+                f,
+                "changed default of parameter {}: {} -> {}",
+                change.name,
+                show(&change.from),
+                show(&change.to)
+            )?;
+        }
+        for change in &self.constraints {
+            writeln!(f, "changed constraints of parameter {}", change.name)?;
+        }
+        Ok(())
+    }
+}
+
+/// The parameters of `bundle`, in order of their names.
+fn parameters(bundle: &Bundle) -> Vec<(&str, &Parameter)> {
+    bundle
+        .parameters
+        .iter()
+        .flatten()
+        .map(|(name, p)| (name.as_str(), p))
+        .collect()
+}
+
+/// The schema of `parameter`, from its definition, or an empty one.
+fn schema(bundle: &Bundle, parameter: &Parameter) -> Map<String, Value> {
+    parameter
+        .definition
+        .as_deref()
+        .and_then(|d| bundle.definition(d))
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Whether `parameter` needs a value given, being required without a default.
+fn needs_value(parameter: &Parameter, schema: &Map<String, Value>) -> bool {
+    parameter.required.unwrap_or(false) && !schema.contains_key("default")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cnab::Destination;
+    use serde_json::json;
+
+    fn parameter(definition: &str, required: bool) -> Parameter {
+        Parameter {
+            apply_to: None,
+            definition: Some(definition.to_string()),
+            description: None,
+            destination: Destination {
+                env: definition.to_uppercase().parse().ok(),
+                path: None,
+            },
+            required: Some(required),
+        }
+    }
+
+    fn bundle(parameters: Vec<(&str, Parameter)>, definitions: Value) -> Bundle {
+        let mut bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        bundle.parameters = Some(
+            parameters
+                .into_iter()
+                .map(|(n, p)| (n.to_string(), p))
+                .collect(),
+        );
+        bundle.definitions = serde_json::from_value(definitions).unwrap();
+        bundle
+    }
+
+    #[test]
+    fn test_parameter_delta() {
+        let old = bundle(
+            vec![
+                ("port", parameter("port", false)),
+                ("host", parameter("host", true)),
+                ("debug", parameter("debug", false)),
+                ("token", parameter("token", false)),
+            ],
+            json!({
+                "port": { "type": "integer", "default": 80, "maximum": 10240 },
+                "host": { "type": "string" },
+                "debug": { "type": "boolean", "default": false },
+                "token": { "type": "string", "default": "" },
+            }),
+        );
+        let new = bundle(
+            vec![
+                ("port", parameter("port", false)),
+                ("host", parameter("host", true)),
+                ("token", parameter("token", true)),
+                ("region", parameter("region", true)),
+                ("zone", parameter("zone", true)),
+            ],
+            json!({
+                "port": { "type": "integer", "default": 8080, "maximum": 65535 },
+                "host": { "type": "string" },
+                "token": { "type": "string" },
+                "region": { "type": "string" },
+                "zone": { "type": "string", "default": "a" },
+            }),
+        );
+
+        let delta = parameter_delta(&old, &new);
+        assert_eq!(delta.required, vec!["region", "token"]);
+        assert_eq!(delta.added, vec!["zone"]);
+        assert_eq!(delta.removed, vec!["debug"]);
+        assert_eq!(
+            delta.defaults,
+            vec![
+                DefaultChange {
+                    name: "port".to_string(),
+                    from: Some(json!(80)),
+                    to: Some(json!(8080)),
+                },
+                DefaultChange {
+                    name: "token".to_string(),
+                    from: Some(json!("")),
+                    to: None,
+                },
+            ]
+        );
+        assert_eq!(delta.constraints.len(), 1);
+        assert_eq!(delta.constraints[0].name, "port");
+        assert_eq!(
+            delta.constraints[0].to,
+            json!({ "type": "integer", "maximum": 65535 })
+        );
+        assert_eq!(
+            delta.to_string(),
+            "requires parameter region\n\
+             requires parameter token\n\
+             added parameter zone\n\
+             removed parameter debug\n\
+             changed default of parameter port: 80 -> 8080\n\
+             changed default of parameter token: \"\" -> none\n\
+             changed constraints of parameter port\n"
+        );
+
+        assert!(parameter_delta(&new, &new).is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+//! A minimal HashiCorp Vault client for resolving `vault` sources.
+use crate::credentialset::{ResolveError, VaultSource};
+use crate::proxy::ProxyConfig;
+use crate::secrets::SecretSource;
+use serde_json::Value;
+
+/// VaultAuth describes how the client authenticates to Vault.
+#[derive(Debug, Clone)]
+pub enum VaultAuth {
+    /// A pre-issued client token
+    Token(String),
+    /// AppRole login, exchanged for a client token before each read
+    AppRole { role_id: String, secret_id: String },
+}
+
+/// VaultConfig holds the address and credentials used to talk to a Vault server.
+#[derive(Debug, Clone)]
+pub struct VaultConfig {
+    /// The base URL of the server, e.g. `https://vault.example.com:8200`
+    pub address: String,
+    pub auth: VaultAuth,
+    /// The Vault Enterprise namespace to send requests to
+    pub namespace: Option<String>,
+    pub proxy: ProxyConfig,
+}
+
+impl VaultConfig {
+    /// Build a configuration from the standard Vault environment variables.
+    ///
+    /// `VAULT_ADDR` is required. `VAULT_TOKEN` is used when set, otherwise
+    /// `VAULT_ROLE_ID` and `VAULT_SECRET_ID` are used for an AppRole login.
+    /// `VAULT_NAMESPACE` is honored if present, and proxies are read with
+    /// `ProxyConfig::from_env`.
+    pub fn from_env() -> Result<Self, ResolveError> {
+        let address =
+            std::env::var("VAULT_ADDR").map_err(|_| vault_error("VAULT_ADDR", "not set"))?;
+        let auth = match std::env::var("VAULT_TOKEN") {
+            Ok(token) => VaultAuth::Token(token),
+            Err(_) => match (
+                std::env::var("VAULT_ROLE_ID"),
+                std::env::var("VAULT_SECRET_ID"),
+            ) {
+                (Ok(role_id), Ok(secret_id)) => VaultAuth::AppRole { role_id, secret_id },
+                _ => {
+                    return Err(vault_error(
+                        "VAULT_TOKEN",
+                        "set VAULT_TOKEN, or VAULT_ROLE_ID and VAULT_SECRET_ID",
+                    ))
+                }
+            },
+        };
+        Ok(VaultConfig {
+            address,
+            auth,
+            namespace: std::env::var("VAULT_NAMESPACE").ok(),
+            proxy: ProxyConfig::from_env(),
+        })
+    }
+
+    /// Read a single key of a KV secret.
+    pub fn read(&self, source: &VaultSource) -> Result<String, ResolveError> {
+        let token = match &self.auth {
+            VaultAuth::Token(token) => token.clone(),
+            VaultAuth::AppRole { role_id, secret_id } => self.approle_login(role_id, secret_id)?,
+        };
+        let url = self.url(&source.path);
+        let agent = self
+            .proxy
+            .agent(&url)
+            .map_err(|e| vault_error(&source.path, e))?;
+        let mut request = agent.get(&url).header("X-Vault-Token", &token);
+        if let Some(ns) = &self.namespace {
+            request = request.header("X-Vault-Namespace", ns);
+        }
+        let body: Value = request
+            .call()
+            .and_then(|mut r| r.body_mut().read_json())
+            .map_err(|e| vault_error(&source.path, e))?;
+        secret_value(&body, &source.key)
+            .ok_or_else(|| vault_error(&source.path, format!("no key {} in secret", source.key)))
+    }
+
+    fn approle_login(&self, role_id: &str, secret_id: &str) -> Result<String, ResolveError> {
+        let url = self.url("auth/approle/login");
+        let agent = self
+            .proxy
+            .agent(&url)
+            .map_err(|e| vault_error("auth/approle/login", e))?;
+        let mut request = agent.post(&url);
+        if let Some(ns) = &self.namespace {
+            request = request.header("X-Vault-Namespace", ns);
+        }
+        let body: Value = request
+            .send_json(serde_json::json!({ "role_id": role_id, "secret_id": secret_id }))
+            .and_then(|mut r| r.body_mut().read_json())
+            .map_err(|e| vault_error("auth/approle/login", e))?;
+        body.pointer("/auth/client_token")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| vault_error("auth/approle/login", "no client token returned"))
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}/v1/{}",
+            self.address.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
+    }
+}
+
+/// Keys take the form `<path>#<key>`, for example `secret/data/myapp#password`.
+impl SecretSource for VaultConfig {
+    fn get(&self, key: &str) -> Result<String, ResolveError> {
+        self.read(&parse_key(key)?)
+    }
+}
+
+/// VaultFromEnv is a `SecretSource` that builds its `VaultConfig` from the environment
+/// at lookup time. It is the `vault` source registered on the default `SecretResolver`.
+pub struct VaultFromEnv;
+
+impl SecretSource for VaultFromEnv {
+    fn get(&self, key: &str) -> Result<String, ResolveError> {
+        VaultConfig::from_env()?.get(key)
+    }
+}
+
+fn parse_key(key: &str) -> Result<VaultSource, ResolveError> {
+    match key.rfind('#') {
+        Some(i) => Ok(VaultSource {
+            path: key[..i].to_string(),
+            key: key[i + 1..].to_string(),
+        }),
+        None => Err(vault_error(key, "expected a key of the form <path>#<key>")),
+    }
+}
+
+fn vault_error<E: std::fmt::Display>(path: &str, error: E) -> ResolveError {
+    ResolveError::secret("vault", path, error)
+}
+
+/// Extract a key from a KV read response, handling both KV v1 and v2 response shapes.
+fn secret_value(body: &Value, key: &str) -> Option<String> {
+    let data = body.get("data")?;
+    let data = match (data.get("data"), data.get("metadata")) {
+        (Some(inner), Some(_)) => inner,
+        _ => data,
+    };
+    match data.get(key)? {
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_secret_value() {
+        let v1 = serde_json::json!({ "data": { "password": "hunter2" } });
+        assert_eq!(secret_value(&v1, "password"), Some("hunter2".to_string()));
+
+        let v2 = serde_json::json!({
+            "data": {
+                "data": { "password": "hunter2", "port": 5432 },
+                "metadata": { "version": 3 }
+            }
+        });
+        assert_eq!(secret_value(&v2, "password"), Some("hunter2".to_string()));
+        assert_eq!(secret_value(&v2, "port"), Some("5432".to_string()));
+        assert_eq!(secret_value(&v2, "missing"), None);
+    }
+}
@@ -0,0 +1,93 @@
+//! wasm-bindgen wrappers for parsing and validating bundles in the browser.
+//!
+//! Build for `wasm32-unknown-unknown` with the `web` feature, and generate the
+//! JavaScript glue with `wasm-bindgen`:
+//!
+//! ```js
+//! import { Bundle, validateBundle } from "./libcnab.js";
+//!
+//! const bundle = new Bundle(json); // throws if the JSON is not a bundle
+//! console.log(bundle.name, bundle.version, bundle.validate());
+//! ```
+use crate::cnab::Bundle;
+use crate::extensions::ExtensionRegistry;
+use wasm_bindgen::prelude::*;
+
+/// A parsed bundle descriptor, exported to JavaScript as `Bundle`.
+#[wasm_bindgen(js_name = Bundle)]
+#[derive(Debug)]
+pub struct WebBundle {
+    bundle: Bundle,
+}
+
+#[wasm_bindgen(js_class = Bundle)]
+impl WebBundle {
+    /// Parse a bundle descriptor, throwing an `Error` if it is not one.
+    #[wasm_bindgen(constructor)]
+    pub fn parse(json: &str) -> Result<WebBundle, JsError> {
+        Ok(WebBundle {
+            bundle: json.parse()?,
+        })
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.bundle.name.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn version(&self) -> String {
+        self.bundle.version.to_string()
+    }
+
+    /// The problems with the bundle and its extensions that `Bundle::validate` finds
+    /// with the default registry, empty when there are none.
+    pub fn validate(&self) -> Vec<String> {
+        match self.bundle.validate(&ExtensionRegistry::default()) {
+            Ok(()) => Vec::new(),
+            Err(e) => e.problems.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    /// The bundle as canonical JSON, see `Bundle::to_canonical_json`.
+    #[wasm_bindgen(js_name = toCanonicalJson)]
+    pub fn to_canonical_json(&self) -> String {
+        String::from_utf8(self.bundle.to_canonical_json()).expect("JSON is UTF-8")
+    }
+}
+
+impl WebBundle {
+    pub fn bundle(&self) -> &Bundle {
+        &self.bundle
+    }
+}
+
+/// Parse and validate a bundle descriptor, returning its problems, and throwing an
+/// `Error` if it is not a bundle at all.
+#[wasm_bindgen(js_name = validateBundle)]
+pub fn validate_bundle(json: &str) -> Result<Vec<String>, JsError> {
+    Ok(WebBundle::parse(json)?.validate())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_web_bundle() {
+        let json = std::fs::read_to_string("testdata/bundle.json").unwrap();
+        let bundle = WebBundle::parse(&json).unwrap();
+        assert_eq!(bundle.name(), "helloworld");
+        assert_eq!(bundle.version(), "0.1.2");
+        assert!(bundle.validate().is_empty());
+        assert!(bundle.to_canonical_json().starts_with(r#"{"actions":null"#));
+
+        let mut required = bundle.bundle().clone();
+        required.required_extensions = Some(vec!["com.example.unknown".to_string()]);
+        let json = serde_json::to_string(&required).unwrap();
+        assert_eq!(
+            validate_bundle(&json).unwrap(),
+            vec!["the bundle requires unknown extension com.example.unknown"]
+        );
+    }
+}
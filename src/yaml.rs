@@ -0,0 +1,111 @@
+use crate::cnab::{check_schema_version, Bundle, BundleParseError};
+use crate::profile::ValidationProfile;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Bundle descriptors may be authored in YAML, which is easier to write and review by
+/// hand, but what is digested, signed and shipped is still the JSON: read the YAML,
+/// then write the bundle out with `Bundle::to_canonical_json`.
+///
+/// ```
+/// use libcnab::Bundle;
+///
+/// let authored = Bundle::from_yaml_file("testdata/bundle.yaml").unwrap();
+/// let shipped = Bundle::from_file("testdata/bundle.json").unwrap();
+/// assert_eq!(authored.to_canonical_json(), shipped.to_canonical_json());
+/// ```
+impl Bundle {
+    /// Deserialize a `Bundle` from a YAML file.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(path = %path.as_ref().display()), err)
+    )]
+    pub fn from_yaml_file<P: AsRef<Path>>(path: P) -> Result<Self, BundleParseError> {
+        let file = File::open(path)?;
+        Self::from_yaml_reader(file)
+    }
+
+    /// Deserialize a `Bundle` from YAML read from any type implementing `Read`.
+    pub fn from_yaml_reader<R: Read>(reader: R) -> Result<Self, BundleParseError> {
+        Self::from_yaml_reader_with(reader, &ValidationProfile::Strict1_0)
+    }
+
+    /// Deserialize a `Bundle` from YAML as `from_yaml_reader` does, enforcing only
+    /// the parsing rules of `profile`.
+    pub fn from_yaml_reader_with<R: Read>(
+        reader: R,
+        profile: &ValidationProfile,
+    ) -> Result<Self, BundleParseError> {
+        let bundle: Bundle = serde_yaml::from_reader(reader)?;
+        check_schema_version(&bundle.schema_version, profile)?;
+        Ok(bundle)
+    }
+
+    /// Deserialize a `Bundle` from a YAML document.
+    pub fn from_yaml_str(yaml: &str) -> Result<Self, BundleParseError> {
+        Self::from_yaml_str_with(yaml, &ValidationProfile::Strict1_0)
+    }
+
+    /// Deserialize a `Bundle` from a YAML document, enforcing only the parsing rules
+    /// of `profile`.
+    pub fn from_yaml_str_with(
+        yaml: &str,
+        profile: &ValidationProfile,
+    ) -> Result<Self, BundleParseError> {
+        let bundle: Bundle = serde_yaml::from_str(yaml)?;
+        check_schema_version(&bundle.schema_version, profile)?;
+        Ok(bundle)
+    }
+
+    /// Serialize the bundle as YAML.
+    pub fn to_yaml_string(&self) -> Result<String, serde_yaml::Error> {
+        serde_yaml::to_string(self)
+    }
+}
+
+impl From<serde_yaml::Error> for BundleParseError {
+    fn from(error: serde_yaml::Error) -> Self {
+        BundleParseError::YamlError(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_yaml_round_trip() {
+        let bundle = Bundle::from_file("testdata/bundle.json").unwrap();
+        let yaml = bundle.to_yaml_string().unwrap();
+        assert!(yaml.contains("schemaVersion: v1.0.0"), "{}", yaml);
+        let parsed = Bundle::from_yaml_str(&yaml).unwrap();
+        assert_eq!(parsed.to_canonical_json(), bundle.to_canonical_json());
+
+        let authored = Bundle::from_yaml_file("testdata/bundle.yaml").unwrap();
+        assert_eq!(authored.to_canonical_json(), bundle.to_canonical_json());
+
+        match Bundle::from_yaml_str("name: [") {
+            Err(e @ BundleParseError::YamlError(_)) => {
+                assert!(e.to_string().starts_with("invalid bundle YAML: "))
+            }
+            other => panic!("expected a YAML error, got {:?}", other.map(|b| b.name)),
+        }
+    }
+
+    #[test]
+    fn test_yaml_profiles() {
+        let yaml = std::fs::read_to_string("testdata/bundle.yaml")
+            .unwrap()
+            .replace("v1.0.0", "v1.1.0");
+        assert!(matches!(
+            Bundle::from_yaml_str(&yaml),
+            Err(BundleParseError::UnsupportedSchemaVersion { .. })
+        ));
+        let permissive = ValidationProfile::Permissive;
+        let bundle = Bundle::from_yaml_str_with(&yaml, &permissive).unwrap();
+        assert_eq!(bundle.schema_version, "v1.1.0");
+        let bundle = Bundle::from_yaml_reader_with(yaml.as_bytes(), &permissive).unwrap();
+        assert_eq!(bundle.schema_version, "v1.1.0");
+    }
+}